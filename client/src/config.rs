@@ -0,0 +1,407 @@
+//! Settings that persist between runs of the client.
+
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use logic::components::Direction;
+use logic::{CatchUp, CatchUpPolicy};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+
+use crate::options::Options;
+use crate::renderer::FrameLimit;
+
+/// The name of the file, within the config directory, that stores the settings.
+const CONFIG_FILE: &str = "config.json";
+
+/// All settings stored in the config file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The name other players see.
+    pub nickname: String,
+    /// The server that was last connected to.
+    pub server: ServerAddress,
+    /// The language of all text, such as `en`. Defaults to the language of the system.
+    pub language: Option<String>,
+    pub keybindings: KeyBindings,
+    pub graphics: Graphics,
+    pub audio: Audio,
+    pub feedback: Feedback,
+    pub split_screen: SplitScreen,
+    pub simulation: Simulation,
+}
+
+/// The address of a game server.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerAddress {
+    pub addr: IpAddr,
+    pub port: u16,
+}
+
+/// Physical keys (scancodes) used to control the player.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub north: u32,
+    pub west: u32,
+    pub south: u32,
+    pub east: u32,
+    pub rotate_left: u32,
+    pub rotate_right: u32,
+    /// Block while held.
+    pub block: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Graphics {
+    /// Number of samples used for multisampling.
+    pub samples: u32,
+    /// Draw the bounding boxes of entities.
+    pub render_bounds: bool,
+    /// Draw outlines around geometry.
+    pub outlines: bool,
+    /// Fade distant geometry into the sky.
+    pub fog: bool,
+    /// How often frames are rendered.
+    pub frame_limit: FrameLimit,
+    /// Animate trees and mushrooms, and draw drifting snow.
+    pub ambient: bool,
+    /// Darken the window while it is out of focus, as a reminder that the players stand still.
+    pub away_overlay: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Audio {
+    /// Master volume in the range 0 to 1.
+    pub volume: f32,
+}
+
+/// Visual feedback when hitting, or getting hit by, projectiles.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Feedback {
+    /// Enable all feedback effects.
+    pub enabled: bool,
+    /// How strongly the camera shakes when getting hit. 0 disables shaking.
+    pub screen_shake: f32,
+    /// Flash a marker on targets hit by our projectiles.
+    pub hit_markers: bool,
+}
+
+/// How the local simulation catches up after falling behind, for example after a long frame.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Simulation {
+    /// The most simulation steps run during a single frame.
+    pub max_catch_up_ticks: u32,
+    /// What to do with time that could not be simulated: `drop`, `slow-motion` or
+    /// `limited:<seconds>`.
+    #[serde(with = "policy")]
+    pub catch_up: CatchUpPolicy,
+}
+
+/// Settings for a second player sharing the same machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SplitScreen {
+    /// Split the window in two and connect a second player.
+    pub enabled: bool,
+    /// The name other players see for the second player.
+    pub nickname: String,
+    /// The second player has no mouse, so every action is bound to a key.
+    pub keybindings: KeyBindings,
+    pub throw: u32,
+    pub interact: u32,
+}
+
+/// The settings in effect: the config file with command line options applied on top.
+///
+/// The two are kept apart so that one-off command line options are never written back to the
+/// config file. Settings are changed through [`Settings::change`], which saves only the changed
+/// values.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// The config in effect, including command line options.
+    current: Config,
+    /// The config as stored in the config file.
+    stored: Config,
+    /// The config file exists but could not be read. It is backed up before it is overwritten.
+    unreadable: bool,
+}
+
+impl Settings {
+    /// Load the config from the config directory, falling back to the default settings if no
+    /// config exists yet or it could not be read. Command line options take precedence.
+    pub fn load(options: &Options) -> Settings {
+        let (stored, unreadable) = match Config::read() {
+            Ok(config) => (config, false),
+            Err(e) => {
+                log::warn!("failed to load config, using defaults: {:#}", e);
+                (Config::default(), true)
+            }
+        };
+
+        let mut current = stored.clone();
+        current.apply_overrides(options);
+
+        Settings {
+            current,
+            stored,
+            unreadable,
+        }
+    }
+
+    /// Change some settings, both in effect and in the config file, and save the config file.
+    pub fn change(&mut self, mut change: impl FnMut(&mut Config)) {
+        change(&mut self.current);
+        change(&mut self.stored);
+
+        if let Err(e) = self.save() {
+            log::error!("failed to save config: {:#}", e);
+        }
+    }
+
+    /// Write the stored config to the config directory, backing up a config file that could not
+    /// be read instead of overwriting it.
+    fn save(&mut self) -> Result<()> {
+        let path = Config::path().ok_or_else(|| anyhow!("no config directory available"))?;
+
+        if self.unreadable && path.exists() {
+            let backup = path.with_extension("json.bak");
+            fs::rename(&path, &backup).with_context(|| {
+                format!(
+                    "failed to back up {} to {}",
+                    path.display(),
+                    backup.display()
+                )
+            })?;
+            log::warn!("backed up unreadable config to {}", backup.display());
+        }
+        self.unreadable = false;
+
+        self.stored.save(&path)
+    }
+}
+
+impl std::ops::Deref for Settings {
+    type Target = Config;
+
+    fn deref(&self) -> &Config {
+        &self.current
+    }
+}
+
+impl Config {
+    /// Write the config to the given path.
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create directory {}", dir.display()))?;
+        }
+
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text).with_context(|| format!("failed to write {}", path.display()))?;
+
+        log::debug!("saved config to {}", path.display());
+        Ok(())
+    }
+
+    /// Get the location of the config file.
+    pub fn path() -> Option<PathBuf> {
+        let dirs = ProjectDirs::from("se", "nolanderc", "snow-fight")?;
+        Some(dirs.config_dir().join(CONFIG_FILE))
+    }
+
+    fn read() -> Result<Config> {
+        let path = Self::path().ok_or_else(|| anyhow!("no config directory available"))?;
+
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let config = serde_json::from_str(&text)
+            .with_context(|| format!("malformed config in {}", path.display()))?;
+
+        Ok(config)
+    }
+
+    fn apply_overrides(&mut self, options: &Options) {
+        if let Some(addr) = options.addr {
+            self.server.addr = addr;
+        }
+        if let Some(port) = options.port {
+            self.server.port = port;
+        }
+        if let Some(nickname) = &options.nickname {
+            self.nickname = nickname.clone();
+        }
+        if let Some(language) = &options.language {
+            self.language = Some(language.clone());
+        }
+        if let Some(samples) = options.samples {
+            self.graphics.samples = samples;
+        }
+        if let Some(frame_limit) = options.frame_limit {
+            self.graphics.frame_limit = frame_limit;
+        }
+        if options.split_screen {
+            self.split_screen.enabled = true;
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Get the direction of movement bound to a key, if any.
+    pub fn direction(&self, scancode: u32) -> Option<Direction> {
+        if scancode == self.north {
+            Some(Direction::NORTH)
+        } else if scancode == self.west {
+            Some(Direction::WEST)
+        } else if scancode == self.south {
+            Some(Direction::SOUTH)
+        } else if scancode == self.east {
+            Some(Direction::EAST)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            nickname: String::from("Snowman"),
+            server: ServerAddress::default(),
+            language: None,
+            keybindings: KeyBindings::default(),
+            graphics: Graphics::default(),
+            audio: Audio::default(),
+            feedback: Feedback::default(),
+            split_screen: SplitScreen::default(),
+            simulation: Simulation::default(),
+        }
+    }
+}
+
+impl Default for ServerAddress {
+    fn default() -> Self {
+        ServerAddress {
+            addr: Ipv4Addr::new(0, 0, 0, 0).into(),
+            port: 8999,
+        }
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        use crate::game::qwerty;
+
+        KeyBindings {
+            north: qwerty::W,
+            west: qwerty::A,
+            south: qwerty::S,
+            east: qwerty::D,
+            rotate_left: qwerty::Q,
+            rotate_right: qwerty::E,
+            block: qwerty::F,
+        }
+    }
+}
+
+impl Default for Graphics {
+    fn default() -> Self {
+        Graphics {
+            samples: 1,
+            render_bounds: false,
+            outlines: true,
+            fog: true,
+            frame_limit: FrameLimit::default(),
+            ambient: true,
+            away_overlay: true,
+        }
+    }
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Audio { volume: 1.0 }
+    }
+}
+
+impl Default for Feedback {
+    fn default() -> Self {
+        Feedback {
+            enabled: true,
+            screen_shake: 1.0,
+            hit_markers: true,
+        }
+    }
+}
+
+impl Default for SplitScreen {
+    fn default() -> Self {
+        use crate::game::qwerty;
+
+        SplitScreen {
+            enabled: false,
+            nickname: String::from("Snowball"),
+            keybindings: KeyBindings {
+                north: qwerty::I,
+                west: qwerty::J,
+                south: qwerty::K,
+                east: qwerty::L,
+                rotate_left: qwerty::U,
+                rotate_right: qwerty::O,
+                block: qwerty::H,
+            },
+            throw: qwerty::N,
+            interact: qwerty::M,
+        }
+    }
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        let catch_up = CatchUp::default();
+        Simulation {
+            max_catch_up_ticks: catch_up.max_ticks,
+            catch_up: catch_up.policy,
+        }
+    }
+}
+
+impl Simulation {
+    pub fn catch_up(&self) -> CatchUp {
+        CatchUp {
+            max_ticks: self.max_catch_up_ticks,
+            policy: self.catch_up,
+        }
+    }
+}
+
+/// Store a `CatchUpPolicy` as a string.
+mod policy {
+    use logic::CatchUpPolicy;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(policy: &CatchUpPolicy, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(policy)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<CatchUpPolicy, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(D::Error::custom)
+    }
+}
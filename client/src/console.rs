@@ -0,0 +1,125 @@
+//! A developer console that reads commands from stdin.
+//!
+//! Supported commands:
+//!
+//! - `net.sim`: show the simulated network conditions.
+//! - `net.sim off`: stop simulating network conditions.
+//! - `net.sim [loss <percent>] [latency <ms>] [jitter <ms>]`: change the simulated network
+//!   conditions, eg. `net.sim latency 150 jitter 30`. Settings that are not given are kept.
+//! - `gfx.fps`: show the current frame limit.
+//! - `gfx.fps <vsync|mailbox|fps>`: change the frame limit, eg. `gfx.fps 30` to save battery.
+//! - `say <message>`: send a message to the chat of the running game.
+
+use anyhow::{Context, Result};
+
+use socket::simulation::{self, Conditions};
+
+use crate::game;
+use crate::renderer::pacing::{self, FrameLimit};
+
+use std::io::{self, BufRead};
+use std::thread;
+use std::time::Duration;
+
+/// Start reading commands from stdin in the background.
+pub fn spawn() {
+    thread::spawn(|| {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    log::error!("failed to read from console: {}", e);
+                    break;
+                }
+            };
+
+            if let Err(e) = execute(&line) {
+                println!("error: {:#}", e);
+            }
+        }
+    });
+}
+
+/// Execute a single command.
+fn execute(line: &str) -> Result<()> {
+    let mut words = line.split_whitespace();
+
+    match words.next() {
+        None => Ok(()),
+        Some("help") => {
+            println!(
+                "net.sim [off] [loss <percent>] [latency <ms>] [jitter <ms>] \
+                 [duplicate <percent>] [reorder <percent>]"
+            );
+            println!("gfx.fps [vsync | mailbox | <fps>]");
+            println!("say <message>");
+            Ok(())
+        }
+        Some("net.sim") => network_simulation(words.collect()),
+        Some("gfx.fps") => frame_limit(words.collect()),
+        Some("say") => say(line),
+        Some(command) => Err(anyhow!("unknown command `{}`, try `help`", command)),
+    }
+}
+
+fn network_simulation(args: Vec<&str>) -> Result<()> {
+    let mut conditions = simulation::conditions();
+
+    match args.as_slice() {
+        [] => {}
+        ["off"] => conditions = Conditions::IDEAL,
+        _ => {
+            for pair in args.chunks(2) {
+                let (setting, value) = match *pair {
+                    [setting, value] => (setting, value),
+                    _ => return Err(anyhow!("missing value for `{}`", pair[0])),
+                };
+
+                let value: f64 = value
+                    .parse()
+                    .with_context(|| format!("invalid value for `{}`", setting))?;
+                if value < 0.0 {
+                    return Err(anyhow!("`{}` can not be negative", setting));
+                }
+
+                match setting {
+                    "loss" => conditions.loss = (value / 100.0).min(1.0),
+                    "latency" => conditions.latency = Duration::from_secs_f64(value / 1000.0),
+                    "jitter" => conditions.jitter = Duration::from_secs_f64(value / 1000.0),
+                    "duplicate" => conditions.duplicate = (value / 100.0).min(1.0),
+                    "reorder" => conditions.reorder = (value / 100.0).min(1.0),
+                    _ => return Err(anyhow!("unknown setting `{}`", setting)),
+                }
+            }
+        }
+    }
+
+    simulation::set_conditions(conditions);
+    println!("net.sim: {}", conditions);
+
+    Ok(())
+}
+
+fn say(line: &str) -> Result<()> {
+    let text = line.trim_start().trim_start_matches("say").trim();
+    if text.is_empty() {
+        return Err(anyhow!("expected a message"));
+    }
+
+    game::say(text.to_owned());
+
+    Ok(())
+}
+
+fn frame_limit(args: Vec<&str>) -> Result<()> {
+    match args.as_slice() {
+        [] => {}
+        [limit] => pacing::set_frame_limit(limit.parse::<FrameLimit>()?),
+        _ => return Err(anyhow!("expected at most one argument")),
+    }
+
+    println!("gfx.fps: {}", pacing::frame_limit());
+
+    Ok(())
+}
@@ -1,13 +1,37 @@
+mod ambient;
 mod camera;
+mod chat;
+mod feedback;
+mod graphs;
+mod latency;
+mod loading;
+mod menu;
 mod network;
+mod ownership;
+mod particles;
 mod render;
+mod smoothing;
+mod split;
+mod summary;
 
-use crate::renderer::{Camera, Renderer, RendererConfig, Size};
+pub use chat::say;
+pub use menu::Menu;
+
+use crate::config::{Config, Settings};
+use crate::renderer::{Camera, Renderer, RendererConfig, ShaderOptions, Size};
 
 use crate::message::Connection;
 
+use ambient::Ambient;
 use camera::Controller;
+use chat::Chat;
+use feedback::Feedback;
+use graphs::Graphs;
+use loading::InitialWorld;
+use particles::Particles;
 use render::RenderOptions;
+use smoothing::Smoothing;
+use split::SecondPlayer;
 
 use anyhow::Result;
 
@@ -16,16 +40,19 @@ use cgmath::{Point2, Point3, Vector3};
 
 use logic::components::*;
 use logic::legion::prelude::*;
+use logic::resources::EntityEffects;
 use logic::snapshot::{RestoreConfig, SnapshotEncoder};
+use protocol::bandwidth::Sample;
 
-use protocol::{Action, ActionKind, Break, EntityId, GameOver, Init, Move, PlayerId, Throw};
+use protocol::{
+    Action, ActionKind, Block, Break, EntityId, GameOver, MatchSummary, Move, Ping, PlayerId,
+    ProjectileKind, Throw, TraceId,
+};
 
 use std::f32::consts::PI;
 use std::sync::Arc;
 use std::time::Instant;
 
-const TITLE: &str = "Snow Fight";
-
 use winit::{
     dpi::PhysicalSize,
     event::{MouseButton, ScanCode, VirtualKeyCode},
@@ -38,28 +65,49 @@ pub struct Game {
 
     connection: Connection,
     snapshots: SnapshotEncoder,
+    smoothing: Smoothing,
 
     fps_meter: FpsMeter,
+    /// The bandwidth counters of the connection when the title was last updated.
+    bandwidth: Sample,
+    graphs: Graphs,
+    chat: Chat,
 
     renderer: Renderer,
     render_options: RenderOptions,
     camera: Camera,
     controller: Controller,
+    feedback: Feedback,
+    particles: Particles,
+    ambient: Ambient,
 
     window: WindowState,
+    /// Whether the window has focus. Keys and buttons are released when it loses focus.
+    focused: bool,
 
     should_exit: bool,
 
     player: LocalPlayer,
     selected: Option<Entity>,
 
+    /// The second player when playing split-screen.
+    second: Option<SecondPlayer>,
+
     game_over: Option<GameOver>,
+    summary: Option<MatchSummary>,
+    return_to_menu: bool,
+    /// When the world starts moving after the server restarted the game.
+    countdown: Option<Instant>,
+
+    config: Settings,
 }
 
 struct LocalPlayer {
+    /// The entity controlled by the player, which changes when the player respawns.
     entity: Entity,
-    #[allow(dead_code)]
     id: PlayerId,
+    /// The kind of projectile to throw.
+    projectile: ProjectileKind,
 }
 
 struct FpsMeter {
@@ -105,33 +153,94 @@ pub enum Event {
         delta_x: f32,
         delta_y: f32,
     },
+    Character(char),
+    /// The window gained or lost focus.
+    Focused(bool),
 }
 
-mod qwerty {
-    #![cfg(target_os = "macos")]
+/// Scancodes of keys on a QWERTY keyboard.
+pub(crate) mod qwerty {
+    #[cfg(target_os = "macos")]
+    mod codes {
+        pub const Q: u32 = 12;
+        pub const W: u32 = 13;
+        pub const E: u32 = 14;
+
+        pub const A: u32 = 0;
+        pub const S: u32 = 1;
+        pub const D: u32 = 2;
+        pub const F: u32 = 3;
+        pub const H: u32 = 4;
+
+        pub const U: u32 = 32;
+        pub const I: u32 = 34;
+        pub const O: u32 = 31;
+
+        pub const J: u32 = 38;
+        pub const K: u32 = 40;
+        pub const L: u32 = 37;
+
+        pub const N: u32 = 45;
+        pub const M: u32 = 46;
+    }
 
-    pub const Q: u32 = 12;
-    pub const W: u32 = 13;
-    pub const E: u32 = 14;
+    #[cfg(not(target_os = "macos"))]
+    mod codes {
+        pub const Q: u32 = 16;
+        pub const W: u32 = 17;
+        pub const E: u32 = 18;
 
-    pub const A: u32 = 0;
-    pub const S: u32 = 1;
-    pub const D: u32 = 2;
-}
+        pub const A: u32 = 30;
+        pub const S: u32 = 31;
+        pub const D: u32 = 32;
+        pub const F: u32 = 33;
+        pub const H: u32 = 35;
 
-impl Game {
-    pub async fn new(window: Window, mut connection: Connection) -> Result<Game> {
-        let window = Arc::new(window);
+        pub const U: u32 = 22;
+        pub const I: u32 = 23;
+        pub const O: u32 = 24;
 
-        let renderer = Self::create_renderer(&window).await?;
+        pub const J: u32 = 36;
+        pub const K: u32 = 37;
+        pub const L: u32 = 38;
 
+        pub const N: u32 = 49;
+        pub const M: u32 = 50;
+    }
+
+    pub use codes::*;
+}
+
+impl Game {
+    pub fn new(
+        window: Arc<Window>,
+        mut renderer: Renderer,
+        mut connection: Connection,
+        initial: InitialWorld,
+        second: Option<(Connection, InitialWorld)>,
+        config: Settings,
+    ) -> Result<Game> {
         let mut world = logic::create_world(logic::WorldKind::Plain);
 
         let schedule = logic::add_systems(Default::default(), logic::SystemSet::NonDestructive);
-        let executor = logic::Executor::new(schedule);
+        let mut executor = logic::Executor::new(schedule);
+        executor.set_catch_up(config.simulation.catch_up());
 
         let mut snapshots = SnapshotEncoder::new();
-        let player = Self::init(&mut world, &mut connection, &mut snapshots)?;
+        snapshots.replicate_debug_names();
+        let player = Self::init(&mut world, &mut snapshots, &initial)?;
+
+        let mut second_backlog = Vec::new();
+        let second = match second {
+            None => None,
+            Some((connection, initial)) => {
+                let player = Self::init(&mut world, &mut snapshots, &initial)?;
+                second_backlog = initial.backlog;
+                Some(SecondPlayer::new(player, connection))
+            }
+        };
+
+        renderer.set_view_count(if second.is_some() { 2 } else { 1 });
 
         let mut controller = Controller::new();
         controller.target = Some(player.entity);
@@ -142,40 +251,69 @@ impl Game {
             fov: 70.0,
         };
 
-        Ok(Game {
+        let bandwidth = connection.bandwidth().sample();
+        let chat = Chat::new(&mut connection);
+
+        let mut game = Game {
             world,
             executor,
 
             connection,
             snapshots,
+            smoothing: Smoothing::default(),
 
             fps_meter: FpsMeter::new(),
+            bandwidth,
+            graphs: Graphs::default(),
+            chat,
 
             window: WindowState::new(window),
+            focused: true,
 
             renderer,
-            render_options: Default::default(),
+            render_options: RenderOptions {
+                render_bounds: config.graphics.render_bounds,
+                ambient: config.graphics.ambient,
+                shader_options: shader_options(&config),
+            },
             camera,
             controller,
+            feedback: Feedback::default(),
+            particles: Particles::default(),
+            ambient: Ambient::default(),
 
             should_exit: false,
 
             player,
             selected: None,
 
+            second,
+
             game_over: None,
-        })
+            countdown: None,
+            summary: None,
+            return_to_menu: false,
+
+            config,
+        };
+
+        for event in initial.backlog {
+            game.handle_event(event);
+        }
+        for event in second_backlog {
+            game.handle_second_event(event);
+        }
+
+        Ok(game)
     }
 
     fn init(
         world: &mut World,
-        connection: &mut Connection,
         snapshots: &mut SnapshotEncoder,
+        init: &InitialWorld,
     ) -> Result<LocalPlayer> {
-        let init = connection.request(Init).wait()?;
-
         let config = RestoreConfig {
-            active_player: None,
+            active_players: Vec::new(),
         };
         snapshots.restore_snapshot(world, &init.snapshot, &config);
 
@@ -187,17 +325,19 @@ impl Game {
         Ok(LocalPlayer {
             entity,
             id: init.player_id,
+            projectile: ProjectileKind::Snowball,
         })
     }
 
-    async fn create_renderer(window: &Window) -> Result<Renderer> {
+    async fn create_renderer(window: &Window, config: &Config) -> Result<Renderer> {
         let size = window.inner_size();
         Renderer::new(
             &window,
             RendererConfig {
                 width: size.width,
                 height: size.height,
-                samples: 1,
+                samples: config.graphics.samples,
+                shader_options: shader_options(config),
             },
         )
         .await
@@ -207,6 +347,33 @@ impl Game {
         !self.should_exit
     }
 
+    /// Close the connection to the server and, if the player asked for it, go back to the main
+    /// menu.
+    pub fn into_menu(self) -> Option<Menu> {
+        let Game {
+            connection,
+            second,
+            window,
+            mut renderer,
+            config,
+            return_to_menu,
+            ..
+        } = self;
+
+        connection.close();
+        if let Some(second) = second {
+            second.close();
+        }
+
+        renderer.set_view_count(1);
+
+        if return_to_menu {
+            Some(Menu::with_renderer(window.handle, renderer, config))
+        } else {
+            None
+        }
+    }
+
     pub fn handle_event(&mut self, event: Event) {
         match event {
             Event::Resized(PhysicalSize { width, height }) => self.resize(Size { width, height }),
@@ -238,11 +405,25 @@ impl Game {
                     self.controller.distance_impulse(-0.01 * delta_y)
                 }
             }
+            Event::Focused(true) => self.focused = true,
+            Event::Focused(false) => self.focus_lost(),
 
             _ => {}
         }
     }
 
+    /// Release every key and button, since their releases are not seen while the window is out of
+    /// focus, and stop the players in their tracks.
+    fn focus_lost(&mut self) {
+        self.focused = false;
+        self.window.release_all();
+
+        if self.game_over.is_none() {
+            stop_player(&mut self.world, self.player.entity, &mut self.connection);
+        }
+        self.second_focus_lost();
+    }
+
     fn resize(&mut self, size: Size) {
         self.window.size = size;
         self.renderer.set_size(size.width, size.height);
@@ -253,38 +434,74 @@ impl Game {
             VirtualKeyCode::Tab => self.switch_closest(),
             VirtualKeyCode::F1 => {
                 self.render_options.render_bounds ^= true;
+                let render_bounds = self.render_options.render_bounds;
+                self.config
+                    .change(|config| config.graphics.render_bounds = render_bounds);
+            }
+            VirtualKeyCode::F2 => {
+                let enabled = !self.config.feedback.enabled;
+                self.config
+                    .change(|config| config.feedback.enabled = enabled);
+            }
+            VirtualKeyCode::F3 => {
+                let mut options = self.render_options.shader_options;
+                options.outlines ^= true;
+                self.set_shader_options(options);
+            }
+            VirtualKeyCode::F4 => {
+                let mut options = self.render_options.shader_options;
+                options.fog ^= true;
+                self.set_shader_options(options);
             }
+            VirtualKeyCode::Key1 => self.player.projectile = ProjectileKind::Snowball,
+            VirtualKeyCode::Key2 => self.player.projectile = ProjectileKind::Iceball,
+            VirtualKeyCode::Key3 => self.player.projectile = ProjectileKind::Slushball,
+            VirtualKeyCode::Key4 => self.player.projectile = ProjectileKind::Snowbomb,
             VirtualKeyCode::F5 => {
-                match futures::executor::block_on(Self::create_renderer(&self.window.handle)) {
+                let renderer = Self::create_renderer(&self.window.handle, &self.config);
+                match futures::executor::block_on(renderer) {
                     Ok(renderer) => self.renderer = renderer,
                     Err(e) => eprintln!("failed to reload renderer: {:#}", e),
                 }
             }
+            VirtualKeyCode::F6 => self.graphs.visible ^= true,
+            VirtualKeyCode::F7 => self.graphs.paused ^= true,
+            VirtualKeyCode::PageUp => self.graphs.zoom_in(),
+            VirtualKeyCode::PageDown => self.graphs.zoom_out(),
+            VirtualKeyCode::Return if self.game_over.is_some() => {
+                self.return_to_menu = true;
+                self.should_exit = true;
+            }
             _ => {}
         }
 
         let set_direction = |game: &mut Game, direction| {
-            game.world
-                .get_component_mut::<Movement>(game.player.entity)
-                .unwrap()
-                .direction
-                .insert(direction)
+            if let Some(mut movement) = game.world.get_component_mut::<Movement>(game.player.entity)
+            {
+                movement.direction.insert(direction);
+            }
         };
 
-        match scancode {
-            qwerty::W => set_direction(self, Direction::NORTH),
-            qwerty::A => set_direction(self, Direction::WEST),
-            qwerty::S => set_direction(self, Direction::SOUTH),
-            qwerty::D => set_direction(self, Direction::EAST),
+        self.second_key_down(scancode);
 
-            qwerty::Q => {
-                self.controller.rotation_impulse(PI / 2.0);
-            }
-            qwerty::E => {
-                self.controller.rotation_impulse(-PI / 2.0);
-            }
+        if self.game_over.is_some() {
+            return;
+        }
 
-            _ => {}
+        let bindings = self.config.keybindings;
+        if let Some(direction) = bindings.direction(scancode) {
+            self.graphs.action_input();
+            set_direction(self, direction);
+        } else if scancode == bindings.rotate_left {
+            self.controller.rotation_impulse(PI / 2.0);
+        } else if scancode == bindings.rotate_right {
+            self.controller.rotation_impulse(-PI / 2.0);
+        } else if scancode == bindings.block {
+            self.graphs.action_input();
+            let stance = Stance::Blocking {
+                facing: self.cursor_facing().unwrap_or(0.0),
+            };
+            set_stance(&mut self.world, self.player.entity, stance);
         }
     }
 
@@ -294,25 +511,33 @@ impl Game {
             _ => {}
         }
 
+        self.second_key_up(scancode);
+
+        if self.game_over.is_some() {
+            return;
+        }
+
         let reset_direction = |game: &mut Game, direction| {
-            game.world
-                .get_component_mut::<Movement>(game.player.entity)
-                .unwrap()
-                .direction
-                .remove(direction)
+            if let Some(mut movement) = game.world.get_component_mut::<Movement>(game.player.entity)
+            {
+                movement.direction.remove(direction);
+            }
         };
 
-        match scancode {
-            qwerty::W => reset_direction(self, Direction::NORTH),
-            qwerty::A => reset_direction(self, Direction::WEST),
-            qwerty::S => reset_direction(self, Direction::SOUTH),
-            qwerty::D => reset_direction(self, Direction::EAST),
-
-            _ => {}
+        if let Some(direction) = self.config.keybindings.direction(scancode) {
+            self.graphs.action_input();
+            reset_direction(self, direction);
+        } else if scancode == self.config.keybindings.block {
+            self.graphs.action_input();
+            set_stance(&mut self.world, self.player.entity, Stance::Standing);
         }
     }
 
     fn button_down(&mut self, button: MouseButton) {
+        if self.game_over.is_some() {
+            return;
+        }
+
         match button {
             MouseButton::Right => {
                 let (origin, direction) = self.mouse_ray();
@@ -324,9 +549,15 @@ impl Game {
                     Some((_, position)) => position,
                 };
 
-                logic::events::throw(&mut self.world, self.player.entity, target);
+                let projectile = self.player.projectile;
+
+                // the server has the final say in whether the throw succeeded
+                let _ =
+                    logic::events::throw(&mut self.world, self.player.entity, target, projectile);
+                self.graphs.action_input();
                 self.connection.send_action(Action {
-                    kind: ActionKind::Throw(Throw { target }),
+                    kind: ActionKind::Throw(Throw { target, projectile }),
+                    trace: self.graphs.trace_action(),
                 });
             }
 
@@ -338,34 +569,132 @@ impl Game {
 
     fn cursor_moved(&mut self, _position: Point2<f32>) {}
 
-    pub fn tick(&mut self) -> Result<Option<GameOver>> {
-        if let Some(game_over) = self.poll_connection()? {
-            return Ok(Some(game_over));
-        }
+    pub fn tick(&mut self) -> Result<()> {
+        self.poll_connection()?;
+        self.poll_second_connection()?;
+        self.chat.poll_history();
+        chat::send_typed(&mut self.connection);
 
         if self.game_over.is_none() {
             self.update_selected();
             self.update_breaking();
+            self.update_blocking();
+
+            let trace = self.graphs.trace_action();
+            send_actions(&self.world, self.player.entity, &mut self.connection, trace);
+        }
+
+        if self.is_playing() {
+            self.send_second_actions();
+
+            let start = Instant::now();
+            if self.countdown.map_or(false, |end| start < end) {
+                self.executor.pause();
+            } else {
+                self.executor.tick(&mut self.world);
+            }
+            self.graphs.record_tick(start.elapsed());
 
-            self.send_actions();
+            // effects are triggered by the server, not by the local prediction
+            self.world
+                .resources
+                .get_mut::<EntityEffects>()
+                .unwrap()
+                .effects
+                .clear();
 
-            self.executor.tick(&mut self.world);
+            self.smoothing.decay(&self.world);
+            self.update_feedback();
+            self.update_particles();
+            self.update_ambient();
             self.update_camera();
         }
 
+        self.update_graphs();
         self.render();
-        self.update_fps();
 
-        Ok(None)
+        if self.game_over.is_none() {
+            self.update_fps();
+        }
+
+        Ok(())
+    }
+
+    /// Is any of the local players still in the game?
+    fn is_playing(&self) -> bool {
+        let second_playing = self.second.as_ref().map(SecondPlayer::is_playing);
+        self.game_over.is_none() || second_playing.unwrap_or(false)
+    }
+
+    /// The entities of all local players still in the game.
+    fn active_players(&self) -> Vec<Entity> {
+        let mut players = Vec::new();
+        if self.game_over.is_none() {
+            players.push(self.player.entity);
+        }
+        if let Some(second) = &self.second {
+            if second.is_playing() {
+                players.push(second.player.entity);
+            }
+        }
+        players
     }
 
     fn update_fps(&mut self) {
         if let Some(fps) = self.fps_meter.tick() {
-            let new_title = format!("{} @ {} fps", TITLE, fps.round());
+            let mut new_title = format!(
+                "{} @ {} | {}",
+                tr!("title"),
+                tr!("hud.fps", fps = fps.round()),
+                projectile_name(self.player.projectile),
+            );
+
+            if !self.focused {
+                new_title += " | ";
+                new_title += &tr!("hud.away");
+            }
+
+            let sample = self.connection.bandwidth().sample();
+            let report = sample.report_since(&self.bandwidth);
+            self.bandwidth = sample;
+
+            let received = report.per_second(report.total.received_bytes) / 1000.0;
+            let sent = report.per_second(report.total.sent_bytes) / 1000.0;
+            new_title += " | ";
+            new_title += &tr!(
+                "hud.network",
+                received = format!("{:.1}", received),
+                sent = format!("{:.1}", sent),
+            );
+            if let Some((name, _)) = report.traffic.first() {
+                new_title += &format!(" ({})", name);
+            }
+
+            let conditions = socket::simulation::conditions();
+            if !conditions.is_ideal() {
+                new_title += " | ";
+                new_title += &tr!("hud.network_simulation", conditions = conditions);
+            }
+
             self.window.handle.set_title(&new_title);
         }
     }
 
+    fn update_graphs(&mut self) {
+        self.graphs.record_frame();
+        self.graphs.poll_pings();
+
+        if self.graphs.visible {
+            self.graphs
+                .record_snapshots(self.connection.bandwidth().sample());
+        }
+
+        if self.graphs.should_ping() {
+            let response = self.connection.request(Ping);
+            self.graphs.ping_sent(response);
+        }
+    }
+
     fn rotate_camera(&mut self, dx: f32, dy: f32) {
         if self.window.key_down(VirtualKeyCode::Space) {
             if self.window.button_down(MouseButton::Left) {
@@ -441,43 +770,133 @@ impl Game {
     fn update_breaking(&mut self) {
         let is_breaking = self.window.button_down(MouseButton::Left);
 
-        self.world
-            .get_component_mut::<WorldInteraction>(self.player.entity)
-            .unwrap()
-            .breaking = if is_breaking { self.selected } else { None };
+        let interaction = self
+            .world
+            .get_component_mut::<WorldInteraction>(self.player.entity);
+        if let Some(mut interaction) = interaction {
+            interaction.breaking = if is_breaking { self.selected } else { None };
+        }
     }
 
-    fn send_actions(&mut self) {
-        let direction = self
-            .world
-            .get_component::<Movement>(self.player.entity)
-            .unwrap()
-            .direction;
-        self.connection.send_action(Action {
-            kind: Move { direction }.into(),
-        });
+    /// Keep a blocking player facing the cursor.
+    fn update_blocking(&mut self) {
+        if let Some(facing) = self.cursor_facing() {
+            turn_blocking(&mut self.world, self.player.entity, facing);
+        }
+    }
 
-        let interaction = self
-            .world
-            .get_component::<WorldInteraction>(self.player.entity)
-            .unwrap();
-        let breaking = interaction
-            .breaking
-            .and_then(|target| self.world.get_component::<EntityId>(target))
-            .map(|breaking| *breaking);
-        self.connection.send_action(Action {
-            kind: Break { entity: breaking }.into(),
-        });
+    /// The direction from the player to the point on the ground beneath the cursor, in radians
+    /// counter-clockwise from east.
+    fn cursor_facing(&self) -> Option<f32> {
+        let (origin, direction) = self.mouse_ray();
+        if direction.z >= 0.0 {
+            return None;
+        }
+
+        let target = origin - origin.z / direction.z * direction;
+        let position = self.world.get_component::<Position>(self.player.entity)?;
+        let offset = target - position.0;
+        Some(offset.y.atan2(offset.x))
     }
 
     fn mouse_ray(&self) -> (Point3<f32>, Vector3<f32>) {
-        let direction = self
-            .camera
-            .cast_ray(self.window.size, self.window.mouse_screen());
+        let size = self.renderer.view_size();
+        let direction = self.camera.cast_ray(size, self.window.mouse_screen(size));
         (self.camera.position, direction)
     }
 }
 
+/// The shader features enabled in the config.
+fn shader_options(config: &Config) -> ShaderOptions {
+    ShaderOptions {
+        outlines: config.graphics.outlines,
+        fog: config.graphics.fog,
+    }
+}
+
+/// The name of a kind of projectile, shown in the title.
+fn projectile_name(projectile: ProjectileKind) -> String {
+    match projectile {
+        ProjectileKind::Snowball => tr!("hud.projectile.snowball"),
+        ProjectileKind::Iceball => tr!("hud.projectile.iceball"),
+        ProjectileKind::Slushball => tr!("hud.projectile.slushball"),
+        ProjectileKind::Snowbomb => tr!("hud.projectile.snowbomb"),
+    }
+}
+
+/// Stop a player from moving, without waiting for the next tick to tell the server.
+fn stop_player(world: &mut World, entity: Entity, connection: &mut Connection) {
+    if let Some(mut movement) = world.get_component_mut::<Movement>(entity) {
+        movement.direction = Direction::empty();
+        movement.stance = Stance::Standing;
+    }
+
+    connection.send_action(Action {
+        kind: Move {
+            direction: Direction::empty(),
+        }
+        .into(),
+        trace: None,
+    });
+    connection.send_action(Action {
+        kind: Block { facing: None }.into(),
+        trace: None,
+    });
+}
+
+/// Start or stop blocking with a player.
+fn set_stance(world: &mut World, entity: Entity, stance: Stance) {
+    if let Some(mut movement) = world.get_component_mut::<Movement>(entity) {
+        movement.stance = stance;
+    }
+}
+
+/// Turn a player to face a direction if they are blocking.
+fn turn_blocking(world: &mut World, entity: Entity, facing: f32) {
+    if let Some(mut movement) = world.get_component_mut::<Movement>(entity) {
+        if let Stance::Blocking { .. } = movement.stance {
+            movement.stance = Stance::Blocking { facing };
+        }
+    }
+}
+
+/// Tell the server how a local player is moving and interacting with the world, tracing the
+/// movement with the given id.
+fn send_actions(
+    world: &World,
+    entity: Entity,
+    connection: &mut Connection,
+    trace: Option<TraceId>,
+) {
+    let movement = world.get_component::<Movement>(entity).unwrap();
+    connection.send_action(Action {
+        kind: Move {
+            direction: movement.direction,
+        }
+        .into(),
+        trace,
+    });
+
+    let interaction = world.get_component::<WorldInteraction>(entity).unwrap();
+    let breaking = interaction
+        .breaking
+        .and_then(|target| world.get_component::<EntityId>(target))
+        .map(|breaking| *breaking);
+    connection.send_action(Action {
+        kind: Break { entity: breaking }.into(),
+        trace: None,
+    });
+
+    let facing = match movement.stance {
+        Stance::Blocking { facing } => Some(facing),
+        Stance::Standing => None,
+    };
+    connection.send_action(Action {
+        kind: Block { facing }.into(),
+        trace: None,
+    });
+}
+
 impl FpsMeter {
     pub fn new() -> Self {
         FpsMeter {
@@ -534,6 +953,11 @@ impl WindowState {
         self.mouse_buttons.retain(|pressed| *pressed != button);
     }
 
+    pub fn release_all(&mut self) {
+        self.pressed_keys.clear();
+        self.mouse_buttons.clear();
+    }
+
     pub fn key_down(&self, key: VirtualKeyCode) -> bool {
         self.pressed_keys.contains(&key)
     }
@@ -542,11 +966,13 @@ impl WindowState {
         self.mouse_buttons.contains(&button)
     }
 
-    pub fn mouse_screen(&self) -> Point2<f32> {
+    /// The position of the mouse relative to a view of the given size in the top-left corner of
+    /// the window.
+    pub fn mouse_screen(&self, size: Size) -> Point2<f32> {
         let mut screen = 2.0 * self.mouse_position;
-        screen.x /= self.size.width as f32;
+        screen.x /= size.width as f32;
         screen.x -= 1.0;
-        screen.y /= self.size.height as f32;
+        screen.y /= size.height as f32;
         screen.y -= 1.0;
         screen
     }
@@ -0,0 +1,133 @@
+//! Motion that keeps the world alive between player actions: trees sway, mushrooms bob and
+//! sparkles of snow drift through the air around the cameras.
+//!
+//! Everything here is local to the client. Trees and mushrooms are animated in the vertex shader,
+//! with a phase derived from their position so that neighbours move out of step.
+
+use cgmath::{Point3, Vector3};
+use rand::Rng;
+
+use logic::components::Model;
+use logic::legion::prelude::*;
+use logic::resources::TimeStep;
+
+use std::f32::consts::PI;
+
+use crate::renderer::{Frame, Instance};
+
+/// For how many seconds a sparkle drifts before it melts.
+const SPARKLE_LIFETIME: f32 = 4.0;
+
+/// The number of sparkles spawned around each camera every second.
+const SPARKLE_RATE: f32 = 12.0;
+
+/// How far from the focus of a camera sparkles are spawned.
+const SPARKLE_RADIUS: f32 = 8.0;
+
+/// The largest size of a sparkle.
+const SPARKLE_SIZE: f32 = 0.05;
+
+/// The velocity every sparkle drifts with.
+const WIND: Vector3<f32> = Vector3 {
+    x: 0.4,
+    y: 0.15,
+    z: -0.1,
+};
+
+#[derive(Default)]
+pub struct Ambient {
+    sparkles: Vec<Sparkle>,
+    /// The fraction of a sparkle that was due to spawn during previous frames.
+    pending: f32,
+}
+
+struct Sparkle {
+    position: Point3<f32>,
+    /// Offsets the fluttering of the sparkle.
+    phase: f32,
+    /// Seconds since the sparkle was spawned.
+    age: f32,
+}
+
+/// Add the ambient motion of a model to its instance.
+pub fn animate(instance: Instance, model: Model, position: Point3<f32>) -> Instance {
+    let phase = phase(position);
+    match model {
+        Model::Tree => instance.with_motion(0.015, 0.0, phase),
+        Model::Mushroom => instance.with_motion(0.0, 0.04, phase),
+        _ => instance,
+    }
+}
+
+/// A phase that is the same for every frame, but differs between nearby positions.
+fn phase(position: Point3<f32>) -> f32 {
+    let hash = (position.x * 12.9898 + position.y * 78.233).sin() * 43758.547;
+    2.0 * PI * hash.fract().abs()
+}
+
+impl super::Game {
+    /// Move the sparkles, spawning new ones around every camera.
+    pub(super) fn update_ambient(&mut self) {
+        if !self.render_options.ambient {
+            self.ambient.sparkles.clear();
+            return;
+        }
+
+        let dt = <Read<TimeStep>>::fetch(&self.world.resources).secs_f32();
+
+        let mut focuses = vec![self.camera.focus];
+        if let Some(second) = &self.second {
+            focuses.push(second.camera.focus);
+        }
+
+        let ambient = &mut self.ambient;
+
+        for sparkle in &mut ambient.sparkles {
+            let flutter = (3.0 * sparkle.age + sparkle.phase).sin();
+            sparkle.position += (WIND + Vector3::new(0.0, 0.0, 0.1 * flutter)) * dt;
+            sparkle.age += dt;
+        }
+
+        ambient
+            .sparkles
+            .retain(|sparkle| sparkle.age < SPARKLE_LIFETIME && sparkle.position.z > 0.0);
+
+        ambient.pending += SPARKLE_RATE * dt;
+        let mut rng = rand::thread_rng();
+        while ambient.pending >= 1.0 {
+            ambient.pending -= 1.0;
+
+            for focus in &focuses {
+                let angle = rng.gen_range(0.0, 2.0 * PI);
+                let distance = SPARKLE_RADIUS * rng.gen_range(0.0f32, 1.0).sqrt();
+                let offset = Vector3::new(
+                    distance * angle.cos(),
+                    distance * angle.sin(),
+                    rng.gen_range(0.5, 3.0),
+                );
+
+                ambient.sparkles.push(Sparkle {
+                    position: *focus + offset,
+                    phase: rng.gen_range(0.0, 2.0 * PI),
+                    age: 0.0,
+                });
+            }
+        }
+    }
+
+    pub(super) fn render_ambient(&self, frame: &mut Frame) {
+        for sparkle in &self.ambient.sparkles {
+            // fade in and out, twinkling in between
+            let life = sparkle.age / SPARKLE_LIFETIME;
+            let fade = (PI * life).sin();
+            let twinkle = 0.75 + 0.25 * (8.0 * sparkle.age + sparkle.phase).sin();
+
+            frame.draw(
+                Model::Cube,
+                Instance::new(sparkle.position)
+                    .with_color([1.0, 1.0, 1.0])
+                    .with_scale([SPARKLE_SIZE * fade * twinkle; 3]),
+            );
+        }
+    }
+}
@@ -1,9 +1,12 @@
-use cgmath::Vector3;
+use cgmath::{prelude::*, Vector3};
 
 use logic::components::Position;
 use logic::legion::prelude::*;
 use logic::resources::TimeStep;
 
+use super::smoothing::Smoothing;
+use crate::renderer::Camera;
+
 use std::f32::consts::PI;
 const TAU: f32 = 2.0 * PI;
 
@@ -17,30 +20,49 @@ pub struct Controller {
     theta_target: f32,
     phi_target: f32,
     distance_target: f32,
+
+    /// How much the camera is currently shaking, in the range 0 to 1.
+    trauma: f32,
 }
 
 impl super::Game {
     pub fn update_camera(&mut self) {
-        let dt = <Read<TimeStep>>::fetch(&self.world.resources);
-        self.controller.apply_velocity(*dt);
-
-        let direction = self.controller.direction();
-        let distance = self.controller.distance;
-
-        if let Some(target) = self.controller.target {
-            if let Some(focus) = self.world.get_component::<Position>(target) {
-                let forward = Vector3::new(direction.x, direction.y, 0.0);
-                let offset = Vector3::new(0.0, 0.0, 0.5) - 0.5 * distance * forward;
-
-                let focus = **focus + offset;
-                let delta = focus - self.camera.focus;
-                let restore = 1.0 - 0.5f32.powf(dt.secs_f32() / 0.05);
-                self.camera.focus += restore * delta;
-            }
+        let world = &self.world;
+        let smoothing = &self.smoothing;
+        follow_target(&mut self.camera, &mut self.controller, world, smoothing);
+
+        if let Some(second) = &mut self.second {
+            follow_target(&mut second.camera, &mut second.controller, world, smoothing);
         }
+    }
+}
 
-        self.camera.position = self.camera.focus - distance * direction;
+/// Move the camera towards the target of the controller.
+fn follow_target(
+    camera: &mut Camera,
+    controller: &mut Controller,
+    world: &World,
+    smoothing: &Smoothing,
+) {
+    let dt = <Read<TimeStep>>::fetch(&world.resources);
+    controller.apply_velocity(*dt);
+
+    let direction = controller.direction();
+    let distance = controller.distance;
+
+    if let Some(target) = controller.target {
+        if let Some(focus) = world.get_component::<Position>(target) {
+            let forward = Vector3::new(direction.x, direction.y, 0.0);
+            let offset = Vector3::new(0.0, 0.0, 0.5) - 0.5 * distance * forward;
+
+            let focus = smoothing.position(target, focus.0) + offset;
+            let delta = focus - camera.focus;
+            let restore = 1.0 - 0.5f32.powf(dt.secs_f32() / 0.05);
+            camera.focus += restore * delta;
+        }
     }
+
+    camera.position = camera.focus - distance * direction + controller.shake();
 }
 
 impl Controller {
@@ -51,6 +73,11 @@ impl Controller {
     const ROTATION_HALF_TIME: f32 = 0.1;
     const DISTANCE_HALF_TIME: f32 = 0.05;
 
+    /// The distance the camera moves when shaking at full intensity.
+    const SHAKE_DISTANCE: f32 = 0.15;
+    /// How much trauma is restored every second.
+    const SHAKE_RECOVERY: f32 = 2.0;
+
     pub fn new() -> Self {
         Controller {
             target: None,
@@ -62,6 +89,8 @@ impl Controller {
             theta_target: (-90f32).to_radians(),
             phi_target: 35f32.to_radians(),
             distance_target: (Self::DISTANCE_CLOSE + Self::DISTANCE_FAR) / 2.0,
+
+            trauma: 0.0,
         }
     }
 
@@ -82,9 +111,16 @@ impl Controller {
             .min(Self::DISTANCE_FAR);
     }
 
+    /// Make the camera shake. An amount of 1 results in the strongest possible shake.
+    pub fn shake_impulse(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+
     pub(self) fn apply_velocity(&mut self, dt: TimeStep) {
         let dt = dt.secs_f32();
 
+        self.trauma = (self.trauma - Self::SHAKE_RECOVERY * dt).max(0.0);
+
         let rotation_falloff = 1.0 - 0.5f32.powf(dt / Self::ROTATION_HALF_TIME);
         self.theta += rotation_falloff * (self.theta_target - self.theta);
         self.phi += rotation_falloff * (self.phi_target - self.phi);
@@ -93,6 +129,18 @@ impl Controller {
         self.distance += distance_falloff * (self.distance_target - self.distance);
     }
 
+    /// Get a random offset to apply to the camera's position while shaking.
+    fn shake(&self) -> Vector3<f32> {
+        if self.trauma <= 0.0 {
+            return Vector3::zero();
+        }
+
+        // Squaring the trauma makes small shakes subtle while large ones remain violent.
+        let magnitude = Self::SHAKE_DISTANCE * self.trauma * self.trauma;
+        let mut random = || magnitude * (2.0 * rand::random::<f32>() - 1.0);
+        Vector3::new(random(), random(), random())
+    }
+
     /// Get the direction in which the camera is facing.
     pub fn direction(&self) -> Vector3<f32> {
         let (sin_theta, cos_theta) = self.theta.sin_cos();
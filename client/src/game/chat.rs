@@ -0,0 +1,127 @@
+//! The chat window. The client can not render text, so chat messages are printed to the console,
+//! where new messages are typed with `say <message>`.
+//!
+//! When the game starts, the most recent messages are requested from the server, so that players
+//! that join late see what was said before they joined. Messages that arrive while waiting for the
+//! history are held back and shown after it.
+
+use protocol::{Action, ActionKind, ChatHistory, ChatLog, ChatMessage};
+
+use std::mem;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::message::{Connection, PollError, ResponseHandle};
+
+/// The number of earlier messages shown when joining the game.
+const HISTORY_LENGTH: u32 = 20;
+
+/// Messages typed into the console that have not been sent yet.
+static OUTBOX: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+pub struct Chat {
+    /// The response to the request for the most recent messages, until it arrives.
+    history: Option<ResponseHandle<ChatLog>>,
+    /// Messages that arrived while waiting for the history.
+    held_back: Vec<ChatMessage>,
+    /// The id of the newest message shown.
+    newest: Option<u32>,
+}
+
+/// Send a message to the chat of the running game.
+pub fn say(text: String) {
+    OUTBOX.lock().unwrap().push(text);
+}
+
+/// Send the messages typed into the console.
+pub fn send_typed(connection: &mut Connection) {
+    for text in OUTBOX.lock().unwrap().drain(..) {
+        connection.send_action(Action {
+            kind: ActionKind::Chat(protocol::Chat { text }),
+            trace: None,
+        });
+    }
+}
+
+impl Chat {
+    /// Request the most recent messages from the server. Messages typed before the game started
+    /// are discarded.
+    pub fn new(connection: &mut Connection) -> Chat {
+        OUTBOX.lock().unwrap().clear();
+
+        Chat {
+            history: Some(connection.request(ChatHistory {
+                limit: HISTORY_LENGTH,
+            })),
+            held_back: Vec::new(),
+            newest: None,
+        }
+    }
+
+    /// A player sent a message.
+    pub fn receive(&mut self, message: ChatMessage) {
+        if self.history.is_some() {
+            self.held_back.push(message);
+        } else {
+            self.show(&message, false);
+        }
+    }
+
+    /// Show the history once it has arrived, followed by the messages that were held back.
+    pub fn poll_history(&mut self) {
+        let history = match &mut self.history {
+            Some(history) => history,
+            None => return,
+        };
+
+        let messages = match history.poll() {
+            Ok(log) => log.messages,
+            Err(PollError::Empty) => return,
+            Err(PollError::Closed) => Vec::new(),
+            Err(PollError::Extract(e)) => {
+                log::warn!("failed to get chat history: {}", e);
+                Vec::new()
+            }
+        };
+        self.history = None;
+
+        for message in &messages {
+            self.show(message, true);
+        }
+        for message in mem::take(&mut self.held_back) {
+            self.show(&message, false);
+        }
+    }
+
+    fn show(&mut self, message: &ChatMessage, from_history: bool) {
+        // messages held back while waiting for the history may also be part of it
+        let is_new = self.newest.map_or(true, |newest| {
+            message.id.wrapping_sub(newest).wrapping_sub(1) < u32::MAX / 2
+        });
+        if !is_new {
+            return;
+        }
+        self.newest = Some(message.id);
+
+        let line = if from_history {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs());
+            let age = now.saturating_sub(message.sent).min(u64::from(u32::MAX));
+            tr!(
+                "chat.earlier_message",
+                nickname = message.nickname,
+                ago = super::summary::spell_duration(age as u32),
+                text = message.text,
+            )
+        } else {
+            tr!(
+                "chat.message",
+                nickname = message.nickname,
+                text = message.text,
+            )
+        };
+
+        println!("{}", line);
+    }
+}
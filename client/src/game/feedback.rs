@@ -0,0 +1,89 @@
+//! Makes hits noticeable: the camera shakes when the local player takes damage and a marker
+//! flashes on targets hit by the player's projectiles.
+
+use cgmath::{Point3, Vector3};
+
+use logic::components::{DebugName, Health, Model};
+use logic::legion::prelude::*;
+use logic::resources::TimeStep;
+
+use protocol::HitConfirmed;
+
+use crate::renderer::{Frame, Instance};
+
+/// For how many seconds a hit marker is visible.
+const MARKER_DURATION: f32 = 0.3;
+
+/// How much the camera shakes for every point of damage taken.
+const SHAKE_PER_DAMAGE: f32 = 0.5;
+
+#[derive(Default)]
+pub struct Feedback {
+    /// The health of the local player during the previous frame.
+    last_health: Option<u32>,
+    markers: Vec<HitMarker>,
+}
+
+struct HitMarker {
+    position: Point3<f32>,
+    /// Seconds since the hit was confirmed.
+    age: f32,
+}
+
+impl super::Game {
+    /// Shake the camera if the local player took damage, and fade out old hit markers.
+    pub(super) fn update_feedback(&mut self) {
+        let dt = <Read<TimeStep>>::fetch(&self.world.resources).secs_f32();
+        let settings = &self.config.feedback;
+
+        let health = self
+            .world
+            .get_component::<Health>(self.player.entity)
+            .map(|health| health.points);
+
+        if let (Some(previous), Some(current)) = (self.feedback.last_health, health) {
+            if current < previous && settings.enabled {
+                let damage = (previous - current) as f32;
+                let amount = settings.screen_shake * SHAKE_PER_DAMAGE * damage;
+                self.controller.shake_impulse(amount);
+            }
+        }
+
+        self.feedback.last_health = health;
+
+        for marker in &mut self.feedback.markers {
+            marker.age += dt;
+        }
+        self.feedback
+            .markers
+            .retain(|marker| marker.age < MARKER_DURATION);
+    }
+
+    /// One of our projectiles hit something.
+    pub(super) fn confirm_hit(&mut self, hit: HitConfirmed) {
+        if let Some(target) = self.snapshots.lookup(hit.target) {
+            let target = DebugName::of(&self.world, target);
+            log::debug!("hit {} for {} damage", target, hit.damage);
+        }
+
+        let settings = &self.config.feedback;
+        if settings.enabled && settings.hit_markers {
+            self.feedback.markers.push(HitMarker {
+                position: hit.position,
+                age: 0.0,
+            });
+        }
+    }
+
+    pub(super) fn render_hit_markers(&self, frame: &mut Frame) {
+        for marker in &self.feedback.markers {
+            let fade = 1.0 - marker.age / MARKER_DURATION;
+            frame.draw(
+                Model::Circle,
+                Instance::new(marker.position + Vector3::new(0.0, 0.0, 0.02))
+                    .with_color([1.0, 0.1, 0.1])
+                    .with_scale([1.5 * fade; 3]),
+            );
+        }
+    }
+}
@@ -0,0 +1,342 @@
+//! Scrolling graphs of recent frame times, tick times and network conditions, drawn in the overlay
+//! in the top left corner of the window. The latency of traced actions is drawn below the graphs.
+//!
+//! Every graph has a fixed scale, so that spikes stand out and values can be compared over time,
+//! and a faint line marking a reference value. Values above the top of the scale are clamped and
+//! drawn in a brighter color.
+
+use protocol::bandwidth::Sample;
+use protocol::{ActionAck, Pong, TraceId};
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::latency::{self, Latency};
+use crate::message::{PollError, ResponseHandle};
+use crate::renderer::OverlayRect;
+
+/// The number of samples kept for every graph.
+const HISTORY_LENGTH: usize = 600;
+
+/// The fewest and most samples that may be shown at once.
+const MIN_SPAN: usize = 30;
+const MAX_SPAN: usize = HISTORY_LENGTH;
+
+/// How often the round trip time is measured while the graphs are visible.
+const PING_INTERVAL: Duration = Duration::from_millis(250);
+
+/// For how long to wait for a reply before counting a ping as lost.
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The number of most recent pings used to estimate the packet loss.
+const LOSS_WINDOW: usize = 20;
+
+const GRAPH_WIDTH: f32 = 300.0;
+const GRAPH_HEIGHT: f32 = 50.0;
+const MARGIN: f32 = 8.0;
+
+const BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+const REFERENCE: [f32; 4] = [1.0, 1.0, 1.0, 0.3];
+
+pub struct Graphs {
+    pub visible: bool,
+    pub paused: bool,
+    /// The number of most recent samples shown.
+    span: usize,
+
+    frame_time: Graph,
+    tick_time: Graph,
+    rtt: Graph,
+    snapshot_size: Graph,
+    packet_loss: Graph,
+
+    latency: Latency,
+
+    last_frame: Instant,
+    /// The bandwidth counters when the snapshot size was last sampled.
+    bandwidth: Option<Sample>,
+
+    /// Pings waiting for a reply.
+    pings: VecDeque<PendingPing>,
+    next_ping: Instant,
+    /// Whether each of the most recent pings was lost.
+    outcomes: VecDeque<bool>,
+}
+
+struct Graph {
+    samples: VecDeque<f32>,
+    /// The value at the top of the graph.
+    max: f32,
+    /// The value marked with a line.
+    reference: f32,
+    color: [f32; 3],
+}
+
+struct PendingPing {
+    sent: Instant,
+    response: ResponseHandle<Pong>,
+}
+
+impl Default for Graphs {
+    fn default() -> Self {
+        let now = Instant::now();
+        Graphs {
+            visible: false,
+            paused: false,
+            span: 200,
+
+            // milliseconds, with a reference at 60 frames per second
+            frame_time: Graph::new(50.0, 1000.0 / 60.0, [0.2, 1.0, 0.2]),
+            // milliseconds
+            tick_time: Graph::new(10.0, 2.0, [1.0, 0.8, 0.2]),
+            // milliseconds
+            rtt: Graph::new(300.0, 100.0, [0.3, 0.6, 1.0]),
+            // bytes
+            snapshot_size: Graph::new(4096.0, 1024.0, [1.0, 0.4, 1.0]),
+            // percent
+            packet_loss: Graph::new(100.0, 10.0, [1.0, 0.3, 0.3]),
+
+            latency: Latency::default(),
+
+            last_frame: now,
+            bandwidth: None,
+
+            pings: VecDeque::new(),
+            next_ping: now,
+            outcomes: VecDeque::new(),
+        }
+    }
+}
+
+impl Graphs {
+    /// Show fewer samples, making every sample wider.
+    pub fn zoom_in(&mut self) {
+        self.span = (self.span / 2).max(MIN_SPAN);
+    }
+
+    /// Show more samples, making every sample narrower.
+    pub fn zoom_out(&mut self) {
+        self.span = (self.span * 2).min(MAX_SPAN);
+    }
+
+    /// Record the time since the previous frame.
+    pub fn record_frame(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(self.last_frame);
+        self.last_frame = now;
+
+        if !self.paused {
+            self.frame_time.push(millis(elapsed));
+        }
+    }
+
+    /// Record the time it took to update the world.
+    pub fn record_tick(&mut self, duration: Duration) {
+        if !self.paused {
+            self.tick_time.push(millis(duration));
+        }
+    }
+
+    /// Record the average size of the snapshots received since the previous call.
+    pub fn record_snapshots(&mut self, bandwidth: Sample) {
+        let previous = match self.bandwidth.replace(bandwidth.clone()) {
+            Some(previous) => previous,
+            None => return,
+        };
+
+        let report = bandwidth.report_since(&previous);
+        let snapshots = report.traffic.iter().find(|(name, _)| *name == "Snapshot");
+        if let Some((_, traffic)) = snapshots {
+            if traffic.received_messages > 0 && !self.paused {
+                let size = traffic.received_bytes as f32 / traffic.received_messages as f32;
+                self.snapshot_size.push(size);
+            }
+        }
+    }
+
+    /// Whether a new ping should be sent. Only pings while the graphs are visible.
+    pub fn should_ping(&self) -> bool {
+        self.visible && Instant::now() >= self.next_ping
+    }
+
+    /// Wait for the reply to a ping that was just sent.
+    pub fn ping_sent(&mut self, response: ResponseHandle<Pong>) {
+        let now = Instant::now();
+        self.next_ping = now + PING_INTERVAL;
+        self.pings.push_back(PendingPing {
+            sent: now,
+            response,
+        });
+    }
+
+    /// Record the round trip time of pings that got a reply, and count pings that timed out as
+    /// lost.
+    pub fn poll_pings(&mut self) {
+        let now = Instant::now();
+        let mut index = 0;
+
+        while index < self.pings.len() {
+            let ping = &mut self.pings[index];
+            let elapsed = now.saturating_duration_since(ping.sent);
+
+            let lost = match ping.response.poll() {
+                Ok(Pong) => false,
+                Err(PollError::Empty) if elapsed < PING_TIMEOUT => {
+                    index += 1;
+                    continue;
+                }
+                Err(_) => true,
+            };
+
+            self.pings.remove(index);
+            if self.paused {
+                continue;
+            }
+
+            if !lost {
+                self.rtt.push(millis(elapsed));
+            }
+
+            self.outcomes.push_back(lost);
+            if self.outcomes.len() > LOSS_WINDOW {
+                self.outcomes.pop_front();
+            }
+
+            let lost = self.outcomes.iter().filter(|lost| **lost).count();
+            self.packet_loss
+                .push(100.0 * lost as f32 / self.outcomes.len() as f32);
+        }
+    }
+
+    /// The player gave input that results in an action.
+    pub fn action_input(&mut self) {
+        self.latency.input();
+    }
+
+    /// The trace id for an action that is about to be sent. Actions are only traced while the
+    /// graphs are visible.
+    pub fn trace_action(&mut self) -> Option<TraceId> {
+        if self.visible && !self.paused {
+            self.latency.trace()
+        } else {
+            self.latency.discard_input();
+            None
+        }
+    }
+
+    /// The server performed a traced action.
+    pub fn action_acknowledged(&mut self, ack: ActionAck) {
+        self.latency.acknowledged(ack);
+    }
+
+    /// A snapshot of the given tick was restored, making the effects of earlier actions visible.
+    pub fn snapshot_restored(&mut self, time: u32) {
+        self.latency.snapshot_restored(time);
+    }
+
+    /// The rectangles that make up the graphs, stacked vertically.
+    pub fn rects(&self) -> Vec<OverlayRect> {
+        let mut rects = Vec::new();
+        if !self.visible {
+            return rects;
+        }
+
+        let graphs = [
+            &self.frame_time,
+            &self.tick_time,
+            &self.rtt,
+            &self.snapshot_size,
+            &self.packet_loss,
+        ];
+
+        for (i, graph) in graphs.iter().enumerate() {
+            let top = MARGIN + i as f32 * (GRAPH_HEIGHT + MARGIN);
+            graph.draw(&mut rects, MARGIN, top, self.span);
+        }
+
+        let top = MARGIN + graphs.len() as f32 * (GRAPH_HEIGHT + MARGIN);
+        self.latency.draw(&mut rects, MARGIN, top, GRAPH_WIDTH);
+
+        if self.paused {
+            // a bar along the left edge shows that the graphs are frozen
+            let height = top + latency::HEIGHT - MARGIN;
+            rects.push(OverlayRect {
+                x: 0.0,
+                y: MARGIN,
+                width: MARGIN / 2.0,
+                height,
+                color: [1.0, 1.0, 1.0, 0.8],
+            });
+        }
+
+        rects
+    }
+}
+
+impl Graph {
+    fn new(max: f32, reference: f32, color: [f32; 3]) -> Graph {
+        Graph {
+            samples: VecDeque::with_capacity(HISTORY_LENGTH),
+            max,
+            reference,
+            color,
+        }
+    }
+
+    fn push(&mut self, value: f32) {
+        if self.samples.len() == HISTORY_LENGTH {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(value);
+    }
+
+    /// Draw the most recent samples as bars, with the newest to the right.
+    fn draw(&self, rects: &mut Vec<OverlayRect>, left: f32, top: f32, span: usize) {
+        let bottom = top + GRAPH_HEIGHT;
+
+        rects.push(OverlayRect {
+            x: left,
+            y: top,
+            width: GRAPH_WIDTH,
+            height: GRAPH_HEIGHT,
+            color: BACKGROUND,
+        });
+
+        let bar_width = GRAPH_WIDTH / span as f32;
+        let shown = self.samples.len().min(span);
+        let skipped = self.samples.len() - shown;
+
+        for (i, &value) in self.samples.iter().skip(skipped).enumerate() {
+            let fraction = (value / self.max).max(0.0);
+            let height = GRAPH_HEIGHT * fraction.min(1.0);
+            let [r, g, b] = self.color;
+            let color = if fraction > 1.0 {
+                [r.max(0.9), g.max(0.9), b.max(0.9), 1.0]
+            } else {
+                [r, g, b, 0.9]
+            };
+
+            let x = left + GRAPH_WIDTH - (shown - i) as f32 * bar_width;
+            rects.push(OverlayRect {
+                x,
+                y: bottom - height,
+                width: bar_width.max(1.0),
+                height,
+                color,
+            });
+        }
+
+        let reference = GRAPH_HEIGHT * (self.reference / self.max).min(1.0);
+        rects.push(OverlayRect {
+            x: left,
+            y: bottom - reference,
+            width: GRAPH_WIDTH,
+            height: 1.0,
+            color: REFERENCE,
+        });
+    }
+}
+
+fn millis(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
@@ -0,0 +1,220 @@
+//! Traces single actions from the player's input until their effects become visible, to guide
+//! tuning of the netcode.
+//!
+//! An action that follows player input is stamped with a trace id. The server acknowledges traced
+//! actions with the tick during which it performed them, and the trace is complete once a snapshot
+//! of that tick has been restored. Every trace is split into three stages:
+//!
+//! - input to send: from the key or button press until the action was sent,
+//! - send to server: from sending the action until the acknowledgement arrived,
+//! - server to visible: from the acknowledgement until a snapshot of that tick was restored.
+//!
+//! The percentiles of recent traces are drawn as stacked bars, one bar per percentile.
+
+use protocol::{ActionAck, TraceId};
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::renderer::OverlayRect;
+
+/// The number of completed traces the percentiles are computed from.
+const HISTORY_LENGTH: usize = 200;
+
+/// For how long to wait for a trace to complete before giving up on it.
+const TRACE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The percentiles that are drawn, from top to bottom.
+const PERCENTILES: [f32; 3] = [50.0, 90.0, 99.0];
+
+/// The latency, in milliseconds, at the right edge of the bars.
+const SCALE: f32 = 500.0;
+/// The latency, in milliseconds, marked with a line.
+const REFERENCE: f32 = 100.0;
+
+const BAR_HEIGHT: f32 = 12.0;
+const PADDING: f32 = 4.0;
+
+/// The height of the drawn percentiles.
+pub const HEIGHT: f32 = PERCENTILES.len() as f32 * (BAR_HEIGHT + PADDING) + PADDING;
+
+const BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+const REFERENCE_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.3];
+const TOTAL_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const STAGE_COLORS: [[f32; 4]; 3] = [
+    [0.2, 1.0, 0.2, 0.9],
+    [0.3, 0.6, 1.0, 0.9],
+    [1.0, 0.8, 0.2, 0.9],
+];
+
+#[derive(Default)]
+pub struct Latency {
+    next_trace: u32,
+    /// When the player gave input that has not been sent yet.
+    input: Option<Instant>,
+    traces: HashMap<TraceId, Trace>,
+    /// The time of the most recently restored snapshot.
+    last_snapshot: Option<u32>,
+    /// The duration of every stage of the most recent completed traces.
+    completed: VecDeque<[Duration; 3]>,
+}
+
+struct Trace {
+    input: Instant,
+    sent: Instant,
+    /// When the acknowledgement arrived and the tick it contained.
+    acknowledged: Option<(Instant, u32)>,
+}
+
+impl Latency {
+    /// The player gave input that results in an action.
+    pub fn input(&mut self) {
+        self.input.get_or_insert_with(Instant::now);
+    }
+
+    /// Start tracing an action that is about to be sent, if it follows new input.
+    pub fn trace(&mut self) -> Option<TraceId> {
+        let input = self.input.take()?;
+
+        let id = TraceId(self.next_trace);
+        self.next_trace = self.next_trace.wrapping_add(1);
+        self.traces.insert(
+            id,
+            Trace {
+                input,
+                sent: Instant::now(),
+                acknowledged: None,
+            },
+        );
+
+        Some(id)
+    }
+
+    /// Forget about any input that has not been sent yet.
+    pub fn discard_input(&mut self) {
+        self.input = None;
+    }
+
+    /// The server performed a traced action.
+    pub fn acknowledged(&mut self, ack: ActionAck) {
+        if let Some(trace) = self.traces.get_mut(&ack.trace) {
+            trace.acknowledged = Some((Instant::now(), ack.tick));
+        }
+
+        // the snapshot may have overtaken the acknowledgement
+        if let Some(time) = self.last_snapshot {
+            self.complete(time);
+        }
+    }
+
+    /// A snapshot of the given tick was restored.
+    pub fn snapshot_restored(&mut self, time: u32) {
+        self.last_snapshot = Some(time);
+        self.complete(time);
+
+        let now = Instant::now();
+        self.traces
+            .retain(|_, trace| now.saturating_duration_since(trace.sent) < TRACE_TIMEOUT);
+    }
+
+    /// Complete every trace whose effects are visible in a snapshot of the given tick.
+    fn complete(&mut self, time: u32) {
+        let now = Instant::now();
+        let completed = &mut self.completed;
+
+        self.traces.retain(|_, trace| match trace.acknowledged {
+            Some((acknowledged, tick)) if is_at_or_after(time, tick) => {
+                if completed.len() == HISTORY_LENGTH {
+                    completed.pop_front();
+                }
+                completed.push_back([
+                    trace.sent.saturating_duration_since(trace.input),
+                    acknowledged.saturating_duration_since(trace.sent),
+                    now.saturating_duration_since(acknowledged),
+                ]);
+                false
+            }
+            _ => true,
+        });
+    }
+
+    /// Draw a bar for every percentile, made up of the percentile of every stage. A mark on each
+    /// bar shows the percentile of the total latency.
+    pub fn draw(&self, rects: &mut Vec<OverlayRect>, left: f32, top: f32, width: f32) {
+        rects.push(OverlayRect {
+            x: left,
+            y: top,
+            width,
+            height: HEIGHT,
+            color: BACKGROUND,
+        });
+
+        let scale = |duration: Duration| width * (millis(duration) / SCALE).min(1.0);
+
+        for (row, &percentile) in PERCENTILES.iter().enumerate() {
+            let y = top + PADDING + row as f32 * (BAR_HEIGHT + PADDING);
+
+            let mut x = left;
+            for (stage, &color) in STAGE_COLORS.iter().enumerate() {
+                let duration = match self.percentile(percentile, |stages| stages[stage]) {
+                    Some(duration) => duration,
+                    None => continue,
+                };
+
+                let end = (x + scale(duration)).min(left + width);
+                rects.push(OverlayRect {
+                    x,
+                    y,
+                    width: end - x,
+                    height: BAR_HEIGHT,
+                    color,
+                });
+                x = end;
+            }
+
+            let total = self.percentile(percentile, |stages| stages.iter().sum());
+            if let Some(total) = total {
+                rects.push(OverlayRect {
+                    x: left + scale(total) - 1.0,
+                    y: y - PADDING / 2.0,
+                    width: 2.0,
+                    height: BAR_HEIGHT + PADDING,
+                    color: TOTAL_COLOR,
+                });
+            }
+        }
+
+        rects.push(OverlayRect {
+            x: left + width * REFERENCE / SCALE,
+            y: top,
+            width: 1.0,
+            height: HEIGHT,
+            color: REFERENCE_COLOR,
+        });
+    }
+
+    /// The given percentile of some duration of the completed traces.
+    fn percentile<F>(&self, percentile: f32, duration: F) -> Option<Duration>
+    where
+        F: Fn(&[Duration; 3]) -> Duration,
+    {
+        if self.completed.is_empty() {
+            return None;
+        }
+
+        let mut durations = self.completed.iter().map(duration).collect::<Vec<_>>();
+        durations.sort();
+
+        let rank = percentile / 100.0 * (durations.len() - 1) as f32;
+        Some(durations[rank.round() as usize])
+    }
+}
+
+/// Is the tick `time` the same as or later than `tick`, taking wrapping into account?
+fn is_at_or_after(time: u32, tick: u32) -> bool {
+    time.wrapping_sub(tick) < u32::MAX / 2
+}
+
+fn millis(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
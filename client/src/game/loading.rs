@@ -0,0 +1,83 @@
+//! Receives the world from the server after connecting, before the game starts.
+//!
+//! The `Connect` response only contains the replicated resources of the world, and the entities
+//! follow in `WorldChunk` events.
+
+use anyhow::Result;
+
+use protocol::{Connect, Entity, Event, EventKind, PlayerId, Snapshot};
+
+use crate::message::Connection;
+
+pub struct WorldLoader {
+    connection: Connection,
+    connect: Connect,
+    /// The chunks received so far, indexed by their position among all chunks.
+    chunks: Vec<Option<Vec<Entity>>>,
+    /// Events that arrived while loading, handled once the game starts.
+    backlog: Vec<Event>,
+}
+
+/// The world as it was when the client connected.
+pub struct InitialWorld {
+    pub player_id: PlayerId,
+    pub snapshot: Snapshot,
+    /// Events that arrived while the world was loading.
+    pub backlog: Vec<Event>,
+}
+
+impl WorldLoader {
+    pub fn new(connection: Connection, connect: Connect) -> WorldLoader {
+        let chunks = vec![None; connect.chunks as usize];
+        WorldLoader {
+            connection,
+            connect,
+            chunks,
+            backlog: Vec::new(),
+        }
+    }
+
+    /// Receive the chunks that have arrived. Returns `true` once every chunk has arrived.
+    pub fn poll(&mut self) -> Result<bool> {
+        while let Some(event) = self.connection.poll_event()? {
+            match event.kind {
+                EventKind::WorldChunk(chunk) => match self.chunks.get_mut(chunk.index as usize) {
+                    Some(slot) => *slot = Some(chunk.entities),
+                    None => log::warn!("received unexpected world chunk {}", chunk.index),
+                },
+                // the world is loaded from the chunks, and a more recent snapshot follows soon
+                EventKind::Snapshot(_) => {}
+                _ => self.backlog.push(event),
+            }
+        }
+
+        Ok(self.is_done())
+    }
+
+    fn is_done(&self) -> bool {
+        self.chunks.iter().all(Option::is_some)
+    }
+
+    /// The number of chunks that have been received, and the total number of chunks.
+    pub fn progress(&self) -> (usize, usize) {
+        let received = self.chunks.iter().filter(|chunk| chunk.is_some()).count();
+        (received, self.chunks.len())
+    }
+
+    /// Assemble the received chunks into the initial world.
+    pub fn finish(self) -> (Connection, InitialWorld) {
+        let entities = self.chunks.into_iter().flatten().flatten().collect();
+
+        let world = InitialWorld {
+            player_id: self.connect.player_id,
+            snapshot: Snapshot {
+                entities,
+                world: self.connect.world,
+                effects: Vec::new(),
+            },
+            backlog: self.backlog,
+        };
+
+        (self.connection, world)
+    }
+}
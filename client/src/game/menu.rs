@@ -0,0 +1,326 @@
+//! The main menu shown before connecting to a server.
+//!
+//! The renderer has no support for text, so the fields of the menu are displayed in the title of
+//! the window while the island slowly rotates in the background. After connecting, the menu stays
+//! up and shows the progress of loading the world until the game can start.
+
+use anyhow::Result;
+
+use cgmath::Point3;
+
+use logic::legion::prelude::*;
+
+use protocol::Init;
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Instant;
+
+use winit::{dpi::PhysicalSize, event::VirtualKeyCode, window::Window};
+
+use super::loading::WorldLoader;
+use super::{render, Event, Game};
+use crate::config::{ServerAddress, Settings};
+use crate::message::Connection;
+use crate::renderer::{Camera, Renderer};
+
+/// How many radians per second the camera orbits the island.
+const ORBIT_SPEED: f32 = 0.1;
+
+pub struct Menu {
+    window: Arc<Window>,
+    renderer: Option<Renderer>,
+    world: World,
+    camera: Camera,
+
+    created: Instant,
+
+    config: Settings,
+
+    nickname: String,
+    address: String,
+    focus: Field,
+    status: Option<String>,
+
+    connect_requested: bool,
+    should_exit: bool,
+
+    /// The worlds being loaded after connecting.
+    loading: Option<Loading>,
+}
+
+struct Loading {
+    first: WorldLoader,
+    second: Option<WorldLoader>,
+}
+
+/// The text field currently receiving input.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Field {
+    Nickname,
+    Address,
+}
+
+impl Menu {
+    pub async fn new(window: Arc<Window>, config: Settings) -> Result<Menu> {
+        let renderer = Game::create_renderer(&window, &config).await?;
+        Ok(Menu::with_renderer(window, renderer, config))
+    }
+
+    /// Create a menu that reuses an existing renderer, such as when returning from a game.
+    pub fn with_renderer(window: Arc<Window>, renderer: Renderer, config: Settings) -> Menu {
+        let world = logic::create_world(logic::WorldKind::Plain);
+
+        let camera = Camera {
+            position: [0.0, -30.0, 15.0].into(),
+            focus: [0.0, 0.0, 0.0].into(),
+            fov: 70.0,
+        };
+
+        let ServerAddress { addr, port } = config.server;
+
+        let menu = Menu {
+            window,
+            renderer: Some(renderer),
+            world,
+            camera,
+
+            created: Instant::now(),
+
+            nickname: config.nickname.clone(),
+            address: format!("{}:{}", addr, port),
+            focus: Field::Nickname,
+            status: None,
+
+            config,
+
+            connect_requested: false,
+            should_exit: false,
+
+            loading: None,
+        };
+
+        menu.update_title();
+
+        menu
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.should_exit
+    }
+
+    pub fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::Resized(PhysicalSize { width, height }) => {
+                if let Some(renderer) = &mut self.renderer {
+                    renderer.set_size(width, height);
+                }
+            }
+            Event::KeyDown { key, .. } => match key {
+                VirtualKeyCode::Tab => {
+                    self.focus = match self.focus {
+                        Field::Nickname => Field::Address,
+                        Field::Address => Field::Nickname,
+                    };
+                }
+                VirtualKeyCode::Back => {
+                    self.focused_text().pop();
+                }
+                VirtualKeyCode::Return => self.connect_requested = true,
+                VirtualKeyCode::Escape => self.should_exit = true,
+                _ => {}
+            },
+            Event::Character(ch) if !ch.is_control() => self.focused_text().push(ch),
+            _ => return,
+        }
+
+        self.update_title();
+    }
+
+    /// Render the menu, and if requested, connect to the server. Returns the game once a
+    /// connection has been established and the world has been loaded.
+    pub fn tick(&mut self) -> Result<Option<Game>> {
+        if self.connect_requested && self.loading.is_none() {
+            self.connect_requested = false;
+            if let Err(e) = self.connect() {
+                self.fail(e);
+            }
+        }
+
+        if self.loading.is_some() {
+            match self.poll_loading() {
+                Ok(Some(game)) => return Ok(Some(game)),
+                Ok(None) => {}
+                Err(e) => {
+                    self.loading = None;
+                    self.fail(e);
+                }
+            }
+        }
+
+        self.render();
+
+        Ok(None)
+    }
+
+    fn fail(&mut self, error: anyhow::Error) {
+        log::error!("failed to connect: {:#}", error);
+        self.status = Some(tr!("menu.error", error = format!("{:#}", error)));
+        self.update_title();
+    }
+
+    /// Receive more of the world, and start the game once all of it has arrived.
+    fn poll_loading(&mut self) -> Result<Option<Game>> {
+        let loading = match &mut self.loading {
+            Some(loading) => loading,
+            None => return Ok(None),
+        };
+
+        let mut done = loading.first.poll()?;
+        if let Some(second) = &mut loading.second {
+            done &= second.poll()?;
+        }
+
+        if !done {
+            let (mut received, mut total) = loading.first.progress();
+            if let Some(second) = &loading.second {
+                let (second_received, second_total) = second.progress();
+                received += second_received;
+                total += second_total;
+            }
+
+            self.status = Some(tr!("menu.loading", received = received, total = total));
+            self.update_title();
+            return Ok(None);
+        }
+
+        let loading = self.loading.take().unwrap();
+        let (connection, initial) = loading.first.finish();
+        let second = loading.second.map(WorldLoader::finish);
+
+        let renderer = self
+            .renderer
+            .take()
+            .ok_or_else(|| anyhow!("renderer was lost"))?;
+
+        let game = Game::new(
+            self.window.clone(),
+            renderer,
+            connection,
+            initial,
+            second,
+            self.config.clone(),
+        )?;
+
+        Ok(Some(game))
+    }
+
+    /// Connect to the server and start loading the world.
+    fn connect(&mut self) -> Result<()> {
+        let nickname = self.nickname.trim().to_owned();
+        if nickname.is_empty() {
+            return Err(anyhow!(tr!("menu.error.nickname")));
+        }
+
+        let addr = parse_address(&self.address, self.config.server.port)?;
+
+        self.status = Some(tr!("menu.connecting"));
+        self.update_title();
+
+        log::info!("Connecting to server on [{}]...", addr);
+        let mut connection = Connection::establish(addr)?;
+        log::info!("Connection established");
+
+        let connect = connection
+            .request(Init {
+                nickname: nickname.clone(),
+                version: protocol::VERSION,
+            })
+            .wait()?;
+        let first = WorldLoader::new(connection, connect);
+
+        let second = if self.config.split_screen.enabled {
+            let nickname = self.config.split_screen.nickname.clone();
+            log::info!("Connecting second player as {:?}...", nickname);
+            let mut connection = Connection::establish(addr)?;
+            let init = Init {
+                nickname,
+                version: protocol::VERSION,
+            };
+            let connect = connection.request(init).wait()?;
+            Some(WorldLoader::new(connection, connect))
+        } else {
+            None
+        };
+
+        self.config.change(|config| {
+            config.nickname = nickname.clone();
+            config.server = ServerAddress {
+                addr: addr.ip(),
+                port: addr.port(),
+            };
+        });
+
+        self.loading = Some(Loading { first, second });
+
+        Ok(())
+    }
+
+    fn focused_text(&mut self) -> &mut String {
+        match self.focus {
+            Field::Nickname => &mut self.nickname,
+            Field::Address => &mut self.address,
+        }
+    }
+
+    fn update_title(&self) {
+        let cursor = |field| if self.focus == field { "_" } else { "" };
+
+        let mut title = format!(
+            "{} | {}",
+            tr!("title"),
+            tr!(
+                "menu.fields",
+                nickname = format!("{}{}", self.nickname, cursor(Field::Nickname)),
+                address = format!("{}{}", self.address, cursor(Field::Address)),
+            ),
+        );
+
+        if let Some(status) = &self.status {
+            title.push_str(" | ");
+            title.push_str(status);
+        }
+
+        self.window.set_title(&title);
+    }
+
+    fn render(&mut self) {
+        let angle = ORBIT_SPEED * self.created.elapsed().as_secs_f32();
+        let (sin, cos) = angle.sin_cos();
+        self.camera.position = Point3::new(30.0 * sin, -30.0 * cos, 15.0);
+
+        if let Some(renderer) = &mut self.renderer {
+            let mut frame = renderer.next_frame(self.camera);
+            render::draw_ground(&mut frame, &self.world);
+            renderer.submit(frame);
+            renderer.cleanup();
+        }
+    }
+}
+
+/// Parse an address of the form `<ip>` or `<ip>:<port>`.
+fn parse_address(text: &str, default_port: u16) -> Result<SocketAddr> {
+    let text = text.trim();
+
+    if let Ok(addr) = text.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+
+    match text.parse::<IpAddr>() {
+        Ok(ip) => Ok(SocketAddr::new(ip, default_port)),
+        Err(_) => Err(anyhow!(tr!(
+            "menu.error.address",
+            address = format!("{:?}", text)
+        ))),
+    }
+}
@@ -1,24 +1,65 @@
 use anyhow::Result;
 use logic::snapshot::RestoreConfig;
-use protocol::{EventKind, GameOver};
+use protocol::{Event, EventKind};
+use std::time::{Duration, Instant};
 
 impl super::Game {
-    pub(super) fn poll_connection(&mut self) -> Result<Option<GameOver>> {
-        while let Some(event) = self.connection.poll_event()? {
-            match event.kind {
-                EventKind::Snapshot(snapshot) => {
-                    let config = RestoreConfig {
-                        active_player: Some(self.player.entity),
-                    };
-                    self.snapshots
-                        .restore_snapshot(&mut self.world, &snapshot, &config);
-                }
-                EventKind::GameOver(game_over) => {
-                    return Ok(Some(game_over));
-                }
-            }
+    pub(super) fn poll_connection(&mut self) -> Result<()> {
+        loop {
+            let event = match self.connection.poll_event() {
+                Ok(Some(event)) => event,
+                Ok(None) => break,
+                // The server closes the connection once the game is over for us.
+                Err(_) if self.game_over.is_some() => break,
+                Err(e) => return Err(e),
+            };
+
+            self.handle_event(event);
         }
 
-        Ok(None)
+        Ok(())
+    }
+
+    pub(super) fn handle_event(&mut self, event: Event) {
+        match event.kind {
+            EventKind::Snapshot(snapshot) => {
+                let config = RestoreConfig {
+                    active_players: self.active_players(),
+                };
+                self.smoothing.record(&self.world);
+                let report = self
+                    .snapshots
+                    .restore_snapshot(&mut self.world, &snapshot, &config);
+                self.smoothing.correct(&self.world);
+                self.resolve_local_players();
+                self.graphs.snapshot_restored(event.time);
+                self.snapshot_effects(&report);
+                self.entity_effects(&snapshot.effects, &report);
+            }
+            EventKind::GameOver(game_over) => {
+                let result = super::summary::result_text(game_over);
+                println!("{}", tr!("game_over.announcement", result = result));
+                self.game_over = Some(game_over);
+                self.update_summary_title();
+            }
+            EventKind::HitConfirmed(hit) => self.confirm_hit(hit),
+            EventKind::MatchSummary(summary) => {
+                super::summary::print_summary(&summary);
+                self.summary = Some(summary);
+                self.update_summary_title();
+            }
+            EventKind::WorldChunk(chunk) => {
+                log::debug!("ignoring world chunk {} after loading", chunk.index);
+            }
+            EventKind::ActionAck(ack) => self.graphs.action_acknowledged(ack),
+            EventKind::Chat(message) => self.chat.receive(message),
+            EventKind::Restart(restart) => {
+                let countdown = super::summary::spell_duration(restart.countdown);
+                println!("{}", tr!("restart.announcement", countdown = countdown));
+                let countdown = Duration::from_secs(restart.countdown.into());
+                self.countdown = Some(Instant::now() + countdown);
+            }
+            EventKind::Unknown => log::debug!("ignoring an event from a newer server"),
+        }
     }
 }
@@ -0,0 +1,64 @@
+//! Keeps track of the entities controlled by the local players.
+//!
+//! The server may replace the entity of a player, such as when they respawn or the world is
+//! resynchronized, so after every snapshot the entity is looked up again by its `Owner` if it no
+//! longer belongs to the player. Input is always routed to `LocalPlayer::entity`, and cameras that
+//! followed the old entity follow the new one.
+
+use logic::components::Owner;
+use logic::legion::prelude::*;
+
+use super::camera::Controller;
+use super::LocalPlayer;
+
+impl LocalPlayer {
+    /// Find the entity owned by the player if the current one was replaced. Returns the previous
+    /// entity if it changed.
+    fn resolve_entity(&mut self, world: &World) -> Option<Entity> {
+        let owned = world
+            .get_component::<Owner>(self.entity)
+            .map_or(false, |owner| owner.0 == self.id);
+        if owned {
+            return None;
+        }
+
+        let (entity, _) = <Read<Owner>>::query()
+            .iter_entities_immutable(world)
+            .find(|(_, owner)| owner.0 == self.id)?;
+
+        log::debug!(
+            "player {} moved from {:?} to {:?}",
+            self.id,
+            self.entity,
+            entity
+        );
+
+        Some(std::mem::replace(&mut self.entity, entity))
+    }
+}
+
+impl super::Game {
+    /// Update the entities of the local players after a snapshot was restored.
+    pub(super) fn resolve_local_players(&mut self) {
+        if let Some(previous) = self.player.resolve_entity(&self.world) {
+            retarget(&mut self.controller, previous, self.player.entity);
+            if self.selected == Some(previous) {
+                self.selected = None;
+            }
+        }
+
+        if let Some(second) = &mut self.second {
+            if let Some(previous) = second.player.resolve_entity(&self.world) {
+                retarget(&mut second.controller, previous, second.player.entity);
+            }
+        }
+    }
+}
+
+/// Follow the new entity if the camera followed the previous one. A camera that was switched to
+/// another entity keeps following it.
+fn retarget(controller: &mut Controller, previous: Entity, entity: Entity) {
+    if controller.target.map_or(true, |target| target == previous) {
+        controller.target = Some(entity);
+    }
+}
@@ -0,0 +1,237 @@
+//! Bursts of particles that mark entities appearing in and disappearing from the world, and the
+//! effects replicated by the server.
+
+use cgmath::{Point3, Vector3};
+use rand::Rng;
+
+use logic::components::{Model, Position};
+use logic::legion::prelude::*;
+use logic::resources::TimeStep;
+use logic::snapshot::{RestoreReport, RestoredKind};
+use logic::tile_map::{TileCoord, TileKind, TileMap};
+
+use protocol::{EffectTag, EntityEffect, ObjectKind};
+
+use crate::renderer::{Frame, Instance};
+
+/// For how many seconds a particle is visible.
+const PARTICLE_LIFETIME: f32 = 0.6;
+
+/// The downwards acceleration of particles.
+const GRAVITY: f32 = 12.0;
+
+#[derive(Default)]
+pub struct Particles {
+    particles: Vec<Particle>,
+}
+
+struct Particle {
+    position: Point3<f32>,
+    velocity: Vector3<f32>,
+    color: [f32; 3],
+    size: f32,
+    /// Seconds since the particle was emitted.
+    age: f32,
+}
+
+/// The particles emitted at once.
+struct Burst {
+    count: usize,
+    speed: f32,
+    size: f32,
+    color: [f32; 3],
+}
+
+const SPAWN: Burst = Burst {
+    count: 10,
+    speed: 3.0,
+    size: 0.15,
+    color: [1.0, 1.0, 1.0],
+};
+
+const PLAYER_DEATH: Burst = Burst {
+    count: 24,
+    speed: 6.0,
+    size: 0.25,
+    color: [0.8, 0.1, 0.1],
+};
+
+const OBJECT_DESTROYED: Burst = Burst {
+    count: 8,
+    speed: 4.0,
+    size: 0.15,
+    color: [0.9, 0.9, 1.0],
+};
+
+const THREW: Burst = Burst {
+    count: 3,
+    speed: 1.5,
+    size: 0.08,
+    color: [1.0, 1.0, 1.0],
+};
+
+const HIT: Burst = Burst {
+    count: 6,
+    speed: 3.0,
+    size: 0.12,
+    color: [1.0, 0.3, 0.3],
+};
+
+const BROKE: Burst = Burst {
+    count: 5,
+    speed: 2.0,
+    size: 0.1,
+    color: [0.6, 0.5, 0.4],
+};
+
+const LANDED: Burst = Burst {
+    count: 6,
+    speed: 2.0,
+    size: 0.12,
+    color: [0.95, 0.95, 1.0],
+};
+
+const FOOTSTEP: Burst = Burst {
+    count: 2,
+    speed: 1.0,
+    size: 0.06,
+    color: [0.9, 0.9, 0.95],
+};
+
+impl Particles {
+    fn emit(&mut self, center: Point3<f32>, burst: &Burst) {
+        let mut rng = rand::thread_rng();
+        for _ in 0..burst.count {
+            let angle = rng.gen_range(0.0, 2.0 * std::f32::consts::PI);
+            let speed = burst.speed * rng.gen_range(0.5, 1.0);
+            let velocity = Vector3::new(
+                speed * angle.cos(),
+                speed * angle.sin(),
+                burst.speed * rng.gen_range(0.5, 1.5),
+            );
+
+            self.particles.push(Particle {
+                position: center + Vector3::new(0.0, 0.0, 0.5),
+                velocity,
+                color: burst.color,
+                size: burst.size,
+                age: 0.0,
+            });
+        }
+    }
+}
+
+/// The footstep of an entity standing on a kind of tile, kicking up some of it.
+fn footstep(kind: Option<TileKind>) -> Burst {
+    let color = match kind {
+        Some(TileKind::Sand) => [0.9, 0.8, 0.5],
+        Some(TileKind::Grass) => [0.3, 0.6, 0.2],
+        Some(TileKind::Water) => [0.5, 0.6, 1.0],
+        Some(TileKind::Ice) => [0.7, 0.9, 1.0],
+        Some(TileKind::Snow) | None => FOOTSTEP.color,
+    };
+
+    Burst { color, ..FOOTSTEP }
+}
+
+impl super::Game {
+    /// Emit particles for the entities that appeared or disappeared when a snapshot was restored.
+    pub(super) fn snapshot_effects(&mut self, report: &RestoreReport) {
+        if !self.config.feedback.enabled {
+            return;
+        }
+
+        for spawned in &report.spawned {
+            if let RestoredKind::Player = spawned.kind {
+                self.particles.emit(spawned.position, &SPAWN);
+            }
+        }
+
+        for despawned in &report.despawned {
+            let burst = match despawned.kind {
+                RestoredKind::Player => PLAYER_DEATH,
+                RestoredKind::Object(ObjectKind::Tree) => Burst {
+                    color: [0.1, 0.5, 0.1],
+                    ..OBJECT_DESTROYED
+                },
+                RestoredKind::Object(ObjectKind::Mushroom) => Burst {
+                    color: [0.8, 0.2, 0.2],
+                    ..OBJECT_DESTROYED
+                },
+                RestoredKind::Field(_) => continue,
+            };
+
+            self.particles.emit(despawned.position, &burst);
+        }
+    }
+
+    /// Emit particles for the effects replicated in a snapshot. Effects may belong to entities that
+    /// were despawned by the same snapshot, such as projectiles that landed.
+    pub(super) fn entity_effects(&mut self, effects: &[EntityEffect], report: &RestoreReport) {
+        if !self.config.feedback.enabled {
+            return;
+        }
+
+        for effect in effects {
+            let alive = self
+                .snapshots
+                .lookup(effect.entity)
+                .and_then(|entity| self.world.get_component::<Position>(entity))
+                .map(|position| position.0);
+            let despawned = || {
+                report
+                    .despawned
+                    .iter()
+                    .find(|despawned| despawned.id == effect.entity)
+                    .map(|despawned| despawned.position)
+            };
+
+            let position = match alive.or_else(despawned) {
+                Some(position) => position,
+                None => continue,
+            };
+
+            let burst = match effect.tag {
+                EffectTag::Threw => THREW,
+                EffectTag::Hit => HIT,
+                EffectTag::Broke => BROKE,
+                EffectTag::Landed => LANDED,
+                EffectTag::Footstep => {
+                    let map = <Read<TileMap>>::fetch(&self.world.resources);
+                    let tile = map.get(TileCoord::from_world(position));
+                    footstep(tile.map(|tile| tile.kind))
+                }
+            };
+
+            self.particles.emit(position, &burst);
+        }
+    }
+
+    /// Move all particles and remove those that faded out.
+    pub(super) fn update_particles(&mut self) {
+        let dt = <Read<TimeStep>>::fetch(&self.world.resources).secs_f32();
+
+        for particle in &mut self.particles.particles {
+            particle.velocity.z -= GRAVITY * dt;
+            particle.position += particle.velocity * dt;
+            particle.position.z = particle.position.z.max(0.0);
+            particle.age += dt;
+        }
+
+        self.particles
+            .particles
+            .retain(|particle| particle.age < PARTICLE_LIFETIME);
+    }
+
+    pub(super) fn render_particles(&self, frame: &mut Frame) {
+        for particle in &self.particles.particles {
+            let fade = 1.0 - particle.age / PARTICLE_LIFETIME;
+            frame.draw(
+                Model::Cube,
+                Instance::new(particle.position)
+                    .with_color(particle.color)
+                    .with_scale([particle.size * fade; 3]),
+            );
+        }
+    }
+}
@@ -1,91 +1,224 @@
 use cgmath::{prelude::*, Point3, Vector3};
 
 use logic::collision::AlignedBox;
-use logic::components::{Breakable, Collision, Health, Model, Position};
+use logic::components::{
+    Breakable, Collision, Field, Health, Model, Movement, Position, Projectile, Stance,
+};
+use logic::effects::{StatusEffect, StatusEffectKind, StatusEffects};
 use logic::legion::prelude::*;
-use logic::tile_map::{TileKind, TileMap};
+use logic::projectiles::ProjectileType;
+use logic::tile_map::{TileCoord, TileKind, TileMap};
 
-use crate::renderer::{Frame, Instance};
+use crate::renderer::{Frame, Instance, OverlayRect, ShaderOptions};
+
+/// The color drawn over the window while it is out of focus.
+const AWAY_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.4];
+
+/// The opacity of a field's decal when the field was just created. It then fades as the field
+/// expires.
+const DECAL_OPACITY: f32 = 0.6;
+
+/// The shield raised by blocking players, placed in front of them.
+const SHIELD_COLOR: [f32; 3] = [0.6, 0.8, 1.0];
+const SHIELD_DISTANCE: f32 = 0.5;
+const SHIELD_HEIGHT: f32 = 0.6;
+const SHIELD_SIZE: [f32; 3] = [0.3, 0.3, 0.5];
 
 pub struct RenderOptions {
     pub render_bounds: bool,
+    /// Animate trees and mushrooms, and draw drifting snow.
+    pub ambient: bool,
+    pub shader_options: ShaderOptions,
 }
 
 impl Default for RenderOptions {
     fn default() -> Self {
         RenderOptions {
             render_bounds: false,
+            ambient: true,
+            shader_options: ShaderOptions::default(),
         }
     }
 }
 
 impl super::Game {
     pub(super) fn render(&mut self) {
-        let mut frame = self.renderer.next_frame(self.camera);
+        if self.renderer.is_minimized() {
+            // there is nothing to draw to, but the renderer still paces the game loop
+            self.renderer.submit_all(Vec::new());
+            return;
+        }
 
-        self.render_ground(&mut frame);
-        self.render_entities(&mut frame);
-        self.render_breaking_progress(&mut frame);
-        self.render_health(&mut frame);
+        let mut cameras = vec![self.camera];
+        if let Some(second) = &self.second {
+            cameras.push(second.camera);
+        }
 
-        if self.render_options.render_bounds {
-            self.render_bounding_boxes(&mut frame);
+        let mut frames = cameras
+            .into_iter()
+            .map(|camera| self.renderer.next_frame(camera))
+            .collect::<Vec<_>>();
+
+        for frame in &mut frames {
+            self.render_scene(frame);
         }
 
-        self.renderer.submit(frame);
+        let mut overlay = Vec::new();
+        if !self.focused && self.config.graphics.away_overlay {
+            overlay.push(OverlayRect {
+                x: 0.0,
+                y: 0.0,
+                width: self.window.size.width as f32,
+                height: self.window.size.height as f32,
+                color: AWAY_COLOR,
+            });
+        }
+        overlay.extend(self.graphs.rects());
+
+        self.renderer.set_overlay(&overlay);
+        self.renderer.submit_all(frames);
         self.renderer.cleanup();
     }
 
+    /// Switch to a different set of shader features, keeping the current ones if the shaders
+    /// could not be compiled.
+    pub(super) fn set_shader_options(&mut self, options: ShaderOptions) {
+        match self.renderer.set_shader_options(options) {
+            Ok(()) => {
+                self.render_options.shader_options = options;
+                self.config.change(|config| {
+                    config.graphics.outlines = options.outlines;
+                    config.graphics.fog = options.fog;
+                });
+            }
+            Err(e) => eprintln!("failed to change shader options: {:#}", e),
+        }
+    }
+
+    fn render_scene(&self, frame: &mut Frame) {
+        self.render_ground(frame);
+        self.render_fields(frame);
+        self.render_entities(frame);
+        self.render_shields(frame);
+        self.render_breaking_progress(frame);
+        self.render_health(frame);
+        self.render_status_effects(frame);
+        self.render_hit_markers(frame);
+        self.render_particles(frame);
+        self.render_ambient(frame);
+
+        if self.render_options.render_bounds {
+            self.render_bounding_boxes(frame);
+        }
+    }
+
     fn render_ground(&self, frame: &mut Frame) {
+        draw_ground(frame, &self.world);
+    }
+
+    fn render_fields(&self, frame: &mut Frame) {
         let map = <Read<TileMap>>::fetch(&self.world.resources);
-        for (position, tile) in map.iter() {
-            let color = match tile.kind {
-                TileKind::Sand => [1.0, 0.8, 0.0],
-                TileKind::Grass => [0.1, 0.8, 0.1],
-                TileKind::Water => [0.0, 0.0, 1.0],
+        let fields = <(Read<Position>, Read<Field>)>::query();
+        for (position, field) in fields.iter_immutable(&self.world) {
+            let field_type = match ProjectileType::of(field.source).field {
+                Some(field_type) => field_type,
+                None => continue,
             };
 
-            let position = [position.x as f32, position.y as f32, 0.0];
-            frame.draw(Model::Rect, Instance::new(position).with_color(color));
+            let ground = map
+                .get(TileCoord::from_world(position.0))
+                .map(|tile| tile_color(tile.kind))
+                .unwrap_or([0.0; 3]);
+            let fade = (field.remaining / field_type.duration).min(1.0);
+
+            draw_decal(
+                frame,
+                position.0,
+                field.radius,
+                ground,
+                field_type.color,
+                DECAL_OPACITY * fade,
+            );
         }
     }
 
     fn render_entities(&self, frame: &mut Frame) {
-        let models = <(Read<Position>, Read<Model>)>::query();
-        for (entity, (position, model)) in models.iter_entities_immutable(&self.world) {
+        let models = <(
+            Read<Position>,
+            Read<Model>,
+            TryRead<Projectile>,
+            TryRead<StatusEffects>,
+        )>::query();
+
+        for (entity, components) in models.iter_entities_immutable(&self.world) {
+            let (position, model, projectile, effects) = components;
+
             let color = if Some(entity) == self.selected {
                 [0.5, 0.5, 0.0]
+            } else if let Some(projectile) = projectile {
+                ProjectileType::of(projectile.kind).color
+            } else if let Some(effects) = effects {
+                effects_tint(&effects)
             } else {
                 [0.0; 3]
             };
 
-            draw_entity(frame, position.0, *model, color);
+            let position = self.smoothing.position(entity, position.0);
+            let ambient = self.render_options.ambient;
+            draw_entity(frame, position, *model, color, ambient);
         }
     }
 
+    fn render_shields(&self, frame: &mut Frame) {
+        <(Read<Position>, Read<Movement>)>::query()
+            .iter_entities_immutable(&self.world)
+            .for_each(|(entity, (position, movement))| {
+                if let Stance::Blocking { facing } = movement.stance {
+                    let position = self.smoothing.position(entity, position.0);
+                    draw_shield(frame, position, facing);
+                }
+            });
+    }
+
     fn render_breaking_progress(&self, frame: &mut Frame) {
         <(Read<Position>, Read<Breakable>)>::query()
-            .iter_immutable(&self.world)
-            .for_each(|(position, breakable)| {
-                draw_indicator(frame, position.0, breakable.durability);
+            .iter_entities_immutable(&self.world)
+            .for_each(|(entity, (position, breakable))| {
+                let position = self.smoothing.position(entity, position.0);
+                draw_indicator(frame, position, breakable.durability);
             });
     }
 
     fn render_health(&self, frame: &mut Frame) {
         <(Read<Position>, Read<Health>, TryRead<Collision>)>::query()
-            .iter_immutable(&self.world)
-            .for_each(|(position, health, collision)| {
+            .iter_entities_immutable(&self.world)
+            .for_each(|(entity, (position, health, collision))| {
                 if health.points < health.max_points {
                     let top = collision.map(|coll| coll.bounds.high.z).unwrap_or(2.0);
+                    let position = self.smoothing.position(entity, position.0);
                     draw_health_bar(
                         frame,
-                        position.0 + Vector3::new(0.0, 0.0, top + 0.4),
+                        position + Vector3::new(0.0, 0.0, top + 0.4),
                         health.points as f32 / health.max_points as f32,
                     );
                 }
             });
     }
 
+    fn render_status_effects(&self, frame: &mut Frame) {
+        <(Read<Position>, Read<StatusEffects>, TryRead<Collision>)>::query()
+            .iter_entities_immutable(&self.world)
+            .for_each(|(entity, (position, effects, collision))| {
+                let top = collision.map(|coll| coll.bounds.high.z).unwrap_or(2.0);
+                let position = self.smoothing.position(entity, position.0);
+                draw_effect_icons(
+                    frame,
+                    position + Vector3::new(0.0, 0.0, top + 0.7),
+                    &effects.active,
+                );
+            });
+    }
+
     fn render_bounding_boxes(&self, frame: &mut Frame) {
         let bounding_boxes = <(Read<Position>, Read<Collision>)>::query();
         for (position, collision) in bounding_boxes.iter_immutable(&self.world) {
@@ -95,16 +228,69 @@ impl super::Game {
     }
 }
 
-fn draw_entity(frame: &mut Frame, position: Point3<f32>, model: Model, color: [f32; 3]) {
+/// Draw the tiles of the world's tile map.
+pub(super) fn draw_ground(frame: &mut Frame, world: &World) {
+    let map = <Read<TileMap>>::fetch(&world.resources);
+    for (position, tile) in map.iter() {
+        let color = tile_color(tile.kind);
+        let position = [position.x as f32, position.y as f32, 0.0];
+        frame.draw(Model::Rect, Instance::new(position).with_color(color));
+    }
+}
+
+fn tile_color(kind: TileKind) -> [f32; 3] {
+    match kind {
+        TileKind::Sand => [1.0, 0.8, 0.0],
+        TileKind::Grass => [0.1, 0.8, 0.1],
+        TileKind::Water => [0.0, 0.0, 1.0],
+        TileKind::Snow => [0.95, 0.95, 1.0],
+        TileKind::Ice => [0.6, 0.85, 1.0],
+    }
+}
+
+fn draw_entity(
+    frame: &mut Frame,
+    position: Point3<f32>,
+    model: Model,
+    color: [f32; 3],
+    ambient: bool,
+) {
     let instance = match model {
         Model::Circle => Instance::new(position).with_scale([0.9; 3]),
 
         _ => Instance::new(position),
     };
 
+    let instance = if ambient {
+        super::ambient::animate(instance, model, position)
+    } else {
+        instance
+    };
+
     frame.draw(model, instance.with_color(color));
 }
 
+/// Draw a flat circle on the ground. The renderer has no support for transparency, so the decal is
+/// blended with the color of the ground beneath its center instead.
+fn draw_decal(
+    frame: &mut Frame,
+    center: Point3<f32>,
+    radius: f32,
+    ground: [f32; 3],
+    color: [f32; 3],
+    opacity: f32,
+) {
+    let blend = |i: usize| ground[i] + opacity * (color[i] - ground[i]);
+    let blended = [blend(0), blend(1), blend(2)];
+
+    frame.draw(
+        Model::Circle,
+        Instance::new(center + Vector3::new(0.0, 0.0, 0.005))
+            .with_color(blended)
+            .with_scale([2.0 * radius, 2.0 * radius, 1.0]),
+    );
+}
+
 fn draw_indicator(frame: &mut Frame, point: Point3<f32>, progress: f32) {
     frame.draw(
         Model::Circle,
@@ -114,6 +300,20 @@ fn draw_indicator(frame: &mut Frame, point: Point3<f32>, progress: f32) {
     );
 }
 
+/// Draw a shield in front of a blocking entity. Models can not be rotated, so the shield looks the
+/// same from every direction and only its position shows where the entity faces.
+fn draw_shield(frame: &mut Frame, position: Point3<f32>, facing: f32) {
+    let front = SHIELD_DISTANCE * Vector3::new(facing.cos(), facing.sin(), 0.0);
+    let center = position + front + Vector3::new(0.0, 0.0, SHIELD_HEIGHT);
+
+    frame.draw(
+        Model::Cube,
+        Instance::new(center)
+            .with_color(SHIELD_COLOR)
+            .with_scale(SHIELD_SIZE),
+    );
+}
+
 fn draw_health_bar(frame: &mut Frame, position: Point3<f32>, amount: f32) {
     let width = 0.75;
     let size = 1.0 / 8.0;
@@ -135,6 +335,46 @@ fn draw_health_bar(frame: &mut Frame, position: Point3<f32>, amount: f32) {
     );
 }
 
+/// The color that represents a kind of status effect.
+fn effect_color(kind: StatusEffectKind) -> [f32; 3] {
+    match kind {
+        StatusEffectKind::Slowed => [0.0, 0.1, 0.4],
+        StatusEffectKind::Stunned => [0.4, 0.4, 0.0],
+        StatusEffectKind::SpawnProtected => [0.3, 0.3, 0.3],
+        StatusEffectKind::SpeedBoost => [0.4, 0.1, 0.0],
+    }
+}
+
+/// The combined tint of all active status effects.
+fn effects_tint(effects: &StatusEffects) -> [f32; 3] {
+    let mut tint = [0.0; 3];
+    for effect in &effects.active {
+        let color = effect_color(effect.kind);
+        tint[0] += color[0];
+        tint[1] += color[1];
+        tint[2] += color[2];
+    }
+    tint
+}
+
+/// Draw a row of icons, one for every active effect. Icons shrink during the last second of
+/// their effect.
+fn draw_effect_icons(frame: &mut Frame, center: Point3<f32>, effects: &[StatusEffect]) {
+    let spacing = 0.25;
+    let left = -0.5 * spacing * (effects.len() as f32 - 1.0);
+
+    for (i, effect) in effects.iter().enumerate() {
+        let offset = Vector3::new(left + spacing * i as f32, 0.0, 0.0);
+        let size = 0.15 * effect.remaining.min(1.0);
+        frame.draw(
+            Model::Cube,
+            Instance::new(center + offset)
+                .with_color(effect_color(effect.kind))
+                .with_scale([size; 3]),
+        );
+    }
+}
+
 fn draw_bounding_box(frame: &mut Frame, bounds: AlignedBox, color: [f32; 3]) {
     let size = bounds.high - bounds.low;
     let center = bounds.low + 0.5 * size;
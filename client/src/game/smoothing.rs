@@ -0,0 +1,87 @@
+//! Hides small disagreements between the predicted and the authoritative world state.
+//!
+//! When a snapshot moves an entity, the distance it moved is kept as an offset that is only
+//! applied when rendering. The offset then decays over a short period of time, so the entity
+//! glides to its corrected position instead of teleporting.
+
+use cgmath::{prelude::*, Point3, Vector3};
+
+use logic::components::Position;
+use logic::legion::prelude::*;
+use logic::resources::TimeStep;
+
+use std::collections::HashMap;
+
+/// After how many seconds half of the remaining error should have been corrected.
+const CORRECTION_HALF_TIME: f32 = 0.025;
+
+/// Corrections larger than this are applied immediately, as the entity most likely teleported.
+const SNAP_DISTANCE: f32 = 2.0;
+
+/// Offsets smaller than this are not noticeable and are dropped.
+const MIN_OFFSET: f32 = 0.001;
+
+#[derive(Default)]
+pub struct Smoothing {
+    /// The positions of all entities before the most recent snapshot was restored.
+    previous: HashMap<Entity, Point3<f32>>,
+    /// The remaining error of every entity that is being corrected.
+    offsets: HashMap<Entity, Vector3<f32>>,
+}
+
+impl Smoothing {
+    /// Remember where every entity is rendered before a snapshot is restored.
+    pub fn record(&mut self, world: &World) {
+        self.previous.clear();
+
+        let offsets = &self.offsets;
+        let positions = <Read<Position>>::query()
+            .iter_entities_immutable(world)
+            .map(|(entity, position)| {
+                let offset = offsets.get(&entity).copied().unwrap_or_else(Vector3::zero);
+                (entity, position.0 + offset)
+            });
+
+        self.previous.extend(positions);
+    }
+
+    /// Turn the difference between the recorded and the restored positions into offsets.
+    pub fn correct(&mut self, world: &World) {
+        self.offsets.clear();
+
+        for (entity, position) in <Read<Position>>::query().iter_entities_immutable(world) {
+            let previous = match self.previous.get(&entity) {
+                Some(previous) => *previous,
+                None => continue,
+            };
+
+            let error = previous - position.0;
+            if error.magnitude() < SNAP_DISTANCE {
+                self.offsets.insert(entity, error);
+            }
+        }
+
+        self.previous.clear();
+    }
+
+    /// Reduce the remaining error of all entities.
+    pub fn decay(&mut self, world: &World) {
+        let dt = <Read<TimeStep>>::fetch(&world.resources).secs_f32();
+        let remaining = 0.5f32.powf(dt / CORRECTION_HALF_TIME);
+
+        for offset in self.offsets.values_mut() {
+            *offset *= remaining;
+        }
+
+        self.offsets
+            .retain(|_, offset| offset.magnitude2() > MIN_OFFSET * MIN_OFFSET);
+    }
+
+    /// The position an entity should be rendered at.
+    pub fn position(&self, entity: Entity, position: Point3<f32>) -> Point3<f32> {
+        match self.offsets.get(&entity) {
+            Some(offset) => position + offset,
+            None => position,
+        }
+    }
+}
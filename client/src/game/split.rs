@@ -0,0 +1,236 @@
+//! A second player sharing the same machine, shown in the right half of the window.
+//!
+//! The second player has a connection of their own and is controlled entirely by the keyboard:
+//! thrown objects are aimed at the closest opponent, blocks face the closest opponent and the
+//! closest breakable object is broken.
+
+use anyhow::Result;
+
+use cgmath::prelude::*;
+use cgmath::Point3;
+
+use logic::components::*;
+use logic::legion::prelude::*;
+use logic::snapshot::RestoreConfig;
+
+use protocol::{Action, ActionKind, Event, EventKind, GameOver, Throw};
+
+use std::f32::consts::PI;
+
+use winit::event::ScanCode;
+
+use super::camera::Controller;
+use super::{summary, LocalPlayer};
+use crate::message::Connection;
+use crate::renderer::Camera;
+
+pub struct SecondPlayer {
+    pub(super) player: LocalPlayer,
+    connection: Connection,
+    pub(super) camera: Camera,
+    pub(super) controller: Controller,
+    game_over: Option<GameOver>,
+}
+
+impl SecondPlayer {
+    pub(super) fn new(player: LocalPlayer, connection: Connection) -> SecondPlayer {
+        let mut controller = Controller::new();
+        controller.target = Some(player.entity);
+
+        SecondPlayer {
+            player,
+            connection,
+            camera: Camera {
+                position: [0.0, -5.0, 2.0].into(),
+                focus: [0.0, 0.0, 0.0].into(),
+                fov: 70.0,
+            },
+            controller,
+            game_over: None,
+        }
+    }
+
+    pub(super) fn is_playing(&self) -> bool {
+        self.game_over.is_none()
+    }
+
+    pub(super) fn close(self) {
+        self.connection.close();
+    }
+}
+
+impl super::Game {
+    /// Handle events sent to the second player.
+    pub(super) fn poll_second_connection(&mut self) -> Result<()> {
+        loop {
+            let second = match &mut self.second {
+                Some(second) => second,
+                None => return Ok(()),
+            };
+
+            let event = match second.connection.poll_event() {
+                Ok(Some(event)) => event,
+                Ok(None) => break,
+                // The server closes the connection once the game is over for the player.
+                Err(_) if second.game_over.is_some() => break,
+                Err(e) => return Err(e),
+            };
+
+            self.handle_second_event(event);
+        }
+
+        Ok(())
+    }
+
+    /// Handle an event sent to the second player. Snapshots are only applied once the first
+    /// player is out of the game, as both connections receive the same world state.
+    pub(super) fn handle_second_event(&mut self, event: Event) {
+        let second = match &mut self.second {
+            Some(second) => second,
+            None => return,
+        };
+
+        match event.kind {
+            EventKind::Snapshot(snapshot) => {
+                if self.game_over.is_some() {
+                    let config = RestoreConfig {
+                        active_players: vec![second.player.entity],
+                    };
+                    self.smoothing.record(&self.world);
+                    let report =
+                        self.snapshots
+                            .restore_snapshot(&mut self.world, &snapshot, &config);
+                    self.smoothing.correct(&self.world);
+                    self.resolve_local_players();
+                    self.snapshot_effects(&report);
+                    self.entity_effects(&snapshot.effects, &report);
+                }
+            }
+            EventKind::GameOver(game_over) => {
+                let result = summary::result_text(game_over);
+                println!("{}", tr!("game_over.second_player", result = result));
+                second.game_over = Some(game_over);
+            }
+            EventKind::MatchSummary(summary) => summary::print_summary(&summary),
+            EventKind::HitConfirmed(_) => {}
+            EventKind::WorldChunk(_) | EventKind::ActionAck(_) | EventKind::Unknown => {}
+            // both players share the chat and the countdown of the first player
+            EventKind::Chat(_) | EventKind::Restart(_) => {}
+        }
+    }
+
+    pub(super) fn second_key_down(&mut self, scancode: ScanCode) {
+        let settings = &self.config.split_screen;
+        let second = match &mut self.second {
+            Some(second) if second.is_playing() => second,
+            _ => return,
+        };
+
+        let entity = second.player.entity;
+        if let Some(direction) = settings.keybindings.direction(scancode) {
+            if let Some(mut movement) = self.world.get_component_mut::<Movement>(entity) {
+                movement.direction.insert(direction);
+            }
+        } else if scancode == settings.keybindings.rotate_left {
+            second.controller.rotation_impulse(PI / 2.0);
+        } else if scancode == settings.keybindings.rotate_right {
+            second.controller.rotation_impulse(-PI / 2.0);
+        } else if scancode == settings.throw {
+            if let Some(target) = closest_opponent(&self.world, entity) {
+                let projectile = second.player.projectile;
+                let _ = logic::events::throw(&mut self.world, entity, target, projectile);
+                second.connection.send_action(Action {
+                    kind: ActionKind::Throw(Throw { target, projectile }),
+                    trace: None,
+                });
+            }
+        } else if scancode == settings.keybindings.block {
+            let facing = facing_opponent(&self.world, entity).unwrap_or(0.0);
+            super::set_stance(&mut self.world, entity, Stance::Blocking { facing });
+        } else if scancode == settings.interact {
+            let breaking = closest_breakable(&self.world, entity);
+            if let Some(mut interaction) = self.world.get_component_mut::<WorldInteraction>(entity)
+            {
+                interaction.breaking = breaking;
+            }
+        }
+    }
+
+    pub(super) fn second_key_up(&mut self, scancode: ScanCode) {
+        let settings = &self.config.split_screen;
+        let second = match &self.second {
+            Some(second) if second.is_playing() => second,
+            _ => return,
+        };
+
+        let entity = second.player.entity;
+        if let Some(direction) = settings.keybindings.direction(scancode) {
+            if let Some(mut movement) = self.world.get_component_mut::<Movement>(entity) {
+                movement.direction.remove(direction);
+            }
+        } else if scancode == settings.keybindings.block {
+            super::set_stance(&mut self.world, entity, Stance::Standing);
+        } else if scancode == settings.interact {
+            if let Some(mut interaction) = self.world.get_component_mut::<WorldInteraction>(entity)
+            {
+                interaction.breaking = None;
+            }
+        }
+    }
+
+    pub(super) fn second_focus_lost(&mut self) {
+        if let Some(second) = &mut self.second {
+            if second.is_playing() {
+                let connection = &mut second.connection;
+                super::stop_player(&mut self.world, second.player.entity, connection);
+            }
+        }
+    }
+
+    pub(super) fn send_second_actions(&mut self) {
+        if let Some(second) = &mut self.second {
+            if second.is_playing() {
+                let entity = second.player.entity;
+                if let Some(facing) = facing_opponent(&self.world, entity) {
+                    super::turn_blocking(&mut self.world, entity, facing);
+                }
+
+                let connection = &mut second.connection;
+                super::send_actions(&self.world, entity, connection, None);
+            }
+        }
+    }
+}
+
+/// Find the position of the player closest to `entity`.
+fn closest_opponent(world: &World, entity: Entity) -> Option<Point3<f32>> {
+    let center = **world.get_component::<Position>(entity)?;
+
+    <(Read<Position>, Read<Owner>)>::query()
+        .iter_entities_immutable(world)
+        .filter(|(other, _)| *other != entity)
+        .map(|(_, (position, _))| position.0)
+        .min_by(|a, b| {
+            a.distance2(center)
+                .partial_cmp(&b.distance2(center))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+}
+
+/// The direction from `entity` to the closest opponent, in radians counter-clockwise from east.
+fn facing_opponent(world: &World, entity: Entity) -> Option<f32> {
+    let center = **world.get_component::<Position>(entity)?;
+    let offset = closest_opponent(world, entity)? - center;
+    Some(offset.y.atan2(offset.x))
+}
+
+/// Find the breakable entity closest to `entity`.
+fn closest_breakable(world: &World, entity: Entity) -> Option<Entity> {
+    let center = **world.get_component::<Position>(entity)?;
+
+    <(Read<Position>, Read<Breakable>)>::query()
+        .iter_entities_immutable(world)
+        .map(|(other, (position, _))| (other, position.distance2(center)))
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(other, _)| other)
+}
@@ -0,0 +1,112 @@
+//! The summary shown once the game is over.
+//!
+//! Like the main menu, the results are displayed in the title of the window since the renderer
+//! has no support for text. They are also printed to stdout so that they remain available after
+//! the window is closed.
+
+use protocol::{GameOver, MatchSummary};
+
+impl super::Game {
+    /// Display the result of the game, and the standings if they have arrived, in the title.
+    pub(super) fn update_summary_title(&self) {
+        let game_over = match self.game_over {
+            Some(game_over) => game_over,
+            None => return,
+        };
+
+        let standings = match &self.summary {
+            None => tr!("summary.waiting"),
+            Some(summary) => format!(
+                "{} | {}",
+                standings(summary),
+                tr!(
+                    "summary.duration",
+                    duration = format_duration(summary.duration)
+                )
+            ),
+        };
+
+        let title = format!(
+            "{} | {} | {} | {}",
+            tr!("title"),
+            result_text(game_over),
+            standings,
+            tr!("summary.controls"),
+        );
+
+        self.window.handle.set_title(&title);
+    }
+}
+
+pub(super) fn result_text(game_over: GameOver) -> String {
+    match game_over {
+        GameOver::Winner => tr!("game_over.winner"),
+        GameOver::Loser => tr!("game_over.loser"),
+    }
+}
+
+/// Print the scoreboard of a finished match.
+pub(super) fn print_summary(summary: &MatchSummary) {
+    println!(
+        "{} | {}",
+        tr!(
+            "summary.duration",
+            duration = spell_duration(summary.duration)
+        ),
+        tr_n!("summary.players", summary.players.len()),
+    );
+
+    for (place, stats) in summary.players.iter().enumerate() {
+        let key = if stats.eliminated {
+            "summary.eliminated"
+        } else {
+            "summary.still_standing"
+        };
+
+        let line = tr!(
+            key,
+            place = place + 1,
+            nickname = stats.nickname,
+            player = stats.player,
+            survived = spell_duration(stats.survived),
+        );
+        println!("  {}", line);
+    }
+}
+
+/// The placement of every player on a single line.
+fn standings(summary: &MatchSummary) -> String {
+    summary
+        .players
+        .iter()
+        .enumerate()
+        .map(|(place, stats)| {
+            tr!(
+                "summary.standing",
+                place = place + 1,
+                nickname = stats.nickname,
+                survived = format_duration(stats.survived),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Format a number of seconds as `m:ss`.
+fn format_duration(seconds: u32) -> String {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Spell out a number of seconds in words, such as `1 minute and 5 seconds`.
+pub(super) fn spell_duration(seconds: u32) -> String {
+    let (minutes, seconds) = (seconds / 60, seconds % 60);
+    match (minutes, seconds) {
+        (0, seconds) => tr_n!("duration.seconds", seconds),
+        (minutes, 0) => tr_n!("duration.minutes", minutes),
+        (minutes, seconds) => tr!(
+            "duration.minutes_seconds",
+            minutes = tr_n!("duration.minutes", minutes),
+            seconds = tr_n!("duration.seconds", seconds),
+        ),
+    }
+}
@@ -0,0 +1,227 @@
+//! Translations of the text shown to the player.
+//!
+//! Every piece of text is looked up by key in a string table, loaded from `assets/lang/<lang>.json`.
+//! English is built into the client and is used for any key missing from the selected language.
+//!
+//! Entries may contain placeholders such as `{name}`, which are replaced by the arguments given
+//! to [`tr!`]. Entries that depend on a count have a form for each plural category, and are looked
+//! up with [`tr_n!`], which also makes the count available as `{count}`.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// The directory that contains the string tables.
+const LANG_DIR: &str = "assets/lang";
+
+/// The language used when no other language is selected, or a key is missing in the selected
+/// language.
+const FALLBACK_LANGUAGE: &str = "en";
+
+/// The string table of the fallback language, so that the client has text even if its assets are
+/// missing.
+const FALLBACK_TABLE: &str = include_str!("../assets/lang/en.json");
+
+static LOCALE: RwLock<Option<Locale>> = RwLock::new(None);
+
+/// Look up the text of a key in the current language, replacing placeholders with the arguments.
+///
+/// ```ignore
+/// tr!("menu.loading", received = 3, total = 8)
+/// ```
+macro_rules! tr {
+    ($key:expr) => {
+        $crate::locale::text($key, &[])
+    };
+    ($key:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::locale::text($key, &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+])
+    };
+}
+
+/// Like `tr!`, but picks the plural form of the entry matching a count.
+///
+/// ```ignore
+/// tr_n!("summary.players", summary.players.len())
+/// ```
+macro_rules! tr_n {
+    ($key:expr, $count:expr) => {
+        $crate::locale::plural($key, $count as u64, &[])
+    };
+    ($key:expr, $count:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::locale::plural(
+            $key,
+            $count as u64,
+            &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+],
+        )
+    };
+}
+
+/// The arguments substituted for placeholders.
+pub type Args<'a> = [(&'a str, &'a dyn Display)];
+
+/// The string tables of a language.
+struct Locale {
+    language: String,
+    strings: HashMap<String, Entry>,
+    fallback: HashMap<String, Entry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Text(String),
+    Plural {
+        /// Used for a count of zero, if present.
+        #[serde(default)]
+        zero: Option<String>,
+        one: String,
+        other: String,
+    },
+}
+
+/// Select the language, or the language of the system if none is given, and load its strings.
+pub fn init(language: Option<&str>) {
+    let language = language
+        .map(str::to_owned)
+        .or_else(system_language)
+        .unwrap_or_else(|| FALLBACK_LANGUAGE.to_owned());
+
+    let locale = Locale::load(&language);
+    log::info!("using language {:?}", locale.language);
+
+    *LOCALE.write().unwrap_or_else(|e| e.into_inner()) = Some(locale);
+}
+
+/// The text of a key in the current language. Falls back to the key itself if no language has
+/// the key.
+pub fn text(key: &str, args: &Args) -> String {
+    lookup(key, |entry| match entry {
+        Entry::Text(text) => substitute(text, args),
+        Entry::Plural { other, .. } => substitute(other, args),
+    })
+}
+
+/// The text of a key in the plural form matching `count`.
+pub fn plural(key: &str, count: u64, args: &Args) -> String {
+    lookup(key, |entry| {
+        let text = match entry {
+            Entry::Text(text) => text,
+            Entry::Plural {
+                zero: Some(zero), ..
+            } if count == 0 => zero,
+            Entry::Plural { one, .. } if count == 1 => one,
+            Entry::Plural { other, .. } => other,
+        };
+
+        let mut args = args.to_vec();
+        args.push(("count", &count));
+        substitute(text, &args)
+    })
+}
+
+fn lookup(key: &str, format: impl Fn(&Entry) -> String) -> String {
+    let locale = LOCALE.read().unwrap_or_else(|e| e.into_inner());
+
+    let entry = match &*locale {
+        Some(locale) => locale.strings.get(key).or_else(|| locale.fallback.get(key)),
+        None => None,
+    };
+
+    match entry.map(format) {
+        Some(text) => text,
+        None => {
+            log::warn!("missing translation for {:?}", key);
+            key.to_owned()
+        }
+    }
+}
+
+/// Replace every `{name}` in the text with the matching argument. Unknown placeholders are left
+/// as they are.
+fn substitute(text: &str, args: &Args) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let name = &rest[1..end];
+        match args.iter().find(|(arg, _)| *arg == name) {
+            Some((_, value)) => result.push_str(&value.to_string()),
+            None => result.push_str(&rest[..=end]),
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Get the language of the system from the environment, such as `sv` from `sv_SE.UTF-8`.
+fn system_language() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| {
+            let language = value.split(&['_', '.', '@'][..]).next()?;
+            match language {
+                "" | "C" | "POSIX" => None,
+                language => Some(language.to_lowercase()),
+            }
+        })
+}
+
+impl Locale {
+    /// Load the strings of a language, falling back to English if they could not be loaded.
+    fn load(language: &str) -> Locale {
+        let fallback: HashMap<String, Entry> =
+            serde_json::from_str(FALLBACK_TABLE).expect("malformed built-in string table");
+
+        let strings = if language == FALLBACK_LANGUAGE {
+            HashMap::new()
+        } else {
+            match Self::read(language) {
+                Ok(strings) => strings,
+                Err(e) => {
+                    log::warn!("failed to load language {:?}: {:#}", language, e);
+                    return Locale {
+                        language: FALLBACK_LANGUAGE.to_owned(),
+                        strings: HashMap::new(),
+                        fallback,
+                    };
+                }
+            }
+        };
+
+        Locale {
+            language: language.to_owned(),
+            strings,
+            fallback,
+        }
+    }
+
+    fn read(language: &str) -> Result<HashMap<String, Entry>> {
+        let path = PathBuf::from(LANG_DIR).join(format!("{}.json", language));
+
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let strings = serde_json::from_str(&text)
+            .with_context(|| format!("malformed string table in {}", path.display()))?;
+
+        Ok(strings)
+    }
+}
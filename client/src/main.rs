@@ -6,20 +6,23 @@
 #[macro_use]
 extern crate anyhow;
 
+#[macro_use]
+mod locale;
+
+mod config;
+mod console;
 mod game;
 mod message;
 mod oneshot;
 mod options;
 mod renderer;
 
-use game::{Event, Game};
-use message::Connection;
+use config::Settings;
+use game::{Event, Menu};
 use options::Options;
 
-use protocol::GameOver;
-
 use anyhow::{Context, Result};
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc};
 use std::thread;
 use structopt::StructOpt;
 
@@ -38,14 +41,22 @@ fn main() -> Result<()> {
 
     setup_logger(options);
 
+    if let Some(path) = &options.pcap_out {
+        socket::capture::start(path).context("failed to start packet capture")?;
+    }
+
+    console::spawn();
+
     let event_loop = EventLoop::new();
     let window = Window::new(&event_loop)?;
     let (mut event_tx, event_rx) = mpsc::channel();
 
-    let connection = connect(options)?;
+    let config = Settings::load(options);
+    renderer::pacing::set_frame_limit(config.graphics.frame_limit);
+    locale::init(config.language.as_deref());
 
     thread::spawn(move || {
-        if let Err(e) = run(window, event_rx, connection).context("game loop exited") {
+        if let Err(e) = run(window, event_rx, config).context("game loop exited") {
             log::error!("{:?}", e);
         }
     });
@@ -73,45 +84,44 @@ fn setup_logger(options: &Options) {
     builder.init();
 }
 
-/// Connect to the server.
-fn connect(options: &Options) -> Result<Connection> {
-    log::info!(
-        "Connecting to server on [{}:{}]...",
-        options.addr,
-        options.port
-    );
-
-    let connection = Connection::establish((options.addr, options.port).into())?;
-    log::info!("Connection established");
-    Ok(connection)
-}
+/// Run the main menu, game logic and graphics frontend.
+fn run(window: Window, events: mpsc::Receiver<Event>, config: Settings) -> Result<()> {
+    let mut menu = futures::executor::block_on(Menu::new(Arc::new(window), config))?;
 
-/// Run the game logic and graphics frontend.
-fn run(window: Window, events: mpsc::Receiver<Event>, connection: Connection) -> Result<()> {
-    let mut game = futures::executor::block_on(Game::new(window, connection))?;
+    loop {
+        let mut game = loop {
+            poll_events(&events, |event| menu.handle_event(event))?;
 
-    while game.is_running() {
-        loop {
-            match events.try_recv() {
-                Err(mpsc::TryRecvError::Empty) => break,
-                Err(mpsc::TryRecvError::Disconnected) => {
-                    return Err(anyhow!("event loop disconnected"))
-                }
-                Ok(event) => game.handle_event(event),
+            if !menu.is_running() {
+                return Ok(());
             }
+
+            if let Some(game) = menu.tick()? {
+                break game;
+            }
+        };
+
+        while game.is_running() {
+            poll_events(&events, |event| game.handle_event(event))?;
+            game.tick()?;
         }
 
-        if let Some(game_over) = game.tick()? {
-            let text = match game_over {
-                GameOver::Winner => "YOU WON! :D",
-                GameOver::Loser => "YOU LOST! :(",
-            };
-            println!("Game over: {}", text);
-            break;
+        match game.into_menu() {
+            Some(next) => menu = next,
+            None => return Ok(()),
         }
     }
+}
 
-    Ok(())
+/// Handle all events that are currently waiting in the channel.
+fn poll_events(events: &mpsc::Receiver<Event>, mut handler: impl FnMut(Event)) -> Result<()> {
+    loop {
+        match events.try_recv() {
+            Err(mpsc::TryRecvError::Empty) => break Ok(()),
+            Err(mpsc::TryRecvError::Disconnected) => break Err(anyhow!("event loop disconnected")),
+            Ok(event) => handler(event),
+        }
+    }
 }
 
 /// Convert a window event to a game input event and send it along the channel.
@@ -137,6 +147,12 @@ fn dispatch_winit_event(
                     y: position.y as f32,
                 })?;
             }
+            WindowEvent::Focused(focused) => {
+                events.send(Event::Focused(focused))?;
+            }
+            WindowEvent::ReceivedCharacter(ch) => {
+                events.send(Event::Character(ch))?;
+            }
             WindowEvent::KeyboardInput { input, .. } => {
                 let KeyboardInput {
                     virtual_keycode,
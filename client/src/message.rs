@@ -1,18 +1,26 @@
 #![allow(dead_code)]
 
 use crate::oneshot;
+use protocol::bandwidth::{self, Bandwidth};
 use protocol::{
-    Action, Channel, ClientMessage, Event, IntoRequest, Request, RequestKind,
+    Action, Channel, ClientMessage, Event, IntoRequest, Limits, Request, RequestKind,
     ResponseKind, ServerMessage,
 };
 use socket::{Connection as Socket, Delivery};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::convert::TryFrom;
 use std::marker::PhantomData;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 use tokio::runtime::{self, Runtime};
 use tokio::sync::mpsc;
+use tokio::time;
+
+/// How often to check for requests that timed out.
+const TIMEOUT_CHECK_INTERVAL: Duration = Duration::from_millis(250);
 
 /// A connection to the game server.
 pub struct Connection {
@@ -23,6 +31,27 @@ pub struct Connection {
 
     packages: mpsc::Sender<Package>,
     events: mpsc::Receiver<Event>,
+
+    bandwidth: Arc<Bandwidth>,
+    requests: Arc<RequestCounts>,
+}
+
+/// Limits on the requests sent to the server at once.
+#[derive(Debug, Copy, Clone)]
+pub struct RequestLimits {
+    /// The most requests waiting for a response at once. Further requests are queued until a
+    /// response arrives.
+    pub max_in_flight: usize,
+    /// For how long to wait for a response before giving up on a request. The handle of a request
+    /// that timed out is closed.
+    pub timeout: Duration,
+}
+
+/// The number of requests in each state, shared with the `Router`.
+#[derive(Debug, Default)]
+struct RequestCounts {
+    in_flight: AtomicUsize,
+    queued: AtomicUsize,
 }
 
 enum Package {
@@ -42,18 +71,44 @@ pub struct ResponseHandle<T> {
 /// A channel through which the response to a request may be sent.
 struct ResponseCallback(oneshot::Sender<ResponseKind>);
 
+/// A request waiting for a response.
+struct InFlight {
+    callback: ResponseCallback,
+    sent: Instant,
+}
+
 /// Routes requests to and from the server.
 struct Router {
     socket: Socket,
     packages: mpsc::Receiver<Package>,
     events: mpsc::Sender<Event>,
     sequence: Channel,
-    callbacks: HashMap<Channel, ResponseCallback>,
+    limits: RequestLimits,
+    /// Requests waiting for a response.
+    in_flight: HashMap<Channel, InFlight>,
+    /// Requests waiting for a free slot in the window of requests in flight.
+    queued: VecDeque<(RequestKind, ResponseCallback)>,
+    /// Channels of requests that timed out, and when they may be reused. Responses that arrive
+    /// late on these channels are dropped instead of being routed to a newer request.
+    retired: HashMap<Channel, Instant>,
+    counts: Arc<RequestCounts>,
+    bandwidth: Arc<Bandwidth>,
+    /// The version of the messages the server knows about, once it has answered `Init`.
+    version: Option<u32>,
 }
 
 impl Connection {
     /// Establish a new connection to the server at address `addr`.
     pub fn establish(addr: SocketAddr) -> anyhow::Result<Connection> {
+        Self::establish_with_limits(addr, RequestLimits::default())
+    }
+
+    /// Establish a new connection to the server at address `addr`, limiting the requests in
+    /// flight at once.
+    pub fn establish_with_limits(
+        addr: SocketAddr,
+        limits: RequestLimits,
+    ) -> anyhow::Result<Connection> {
         let mut runtime = Runtime::new()?;
         let handle = runtime.handle().clone();
 
@@ -62,12 +117,21 @@ impl Connection {
         let (packages_tx, packages_rx) = mpsc::channel(128);
         let (events_tx, events_rx) = mpsc::channel(128);
 
+        let bandwidth = Arc::new(Bandwidth::default());
+        let requests = Arc::new(RequestCounts::default());
+
         let mut responder = Router {
             socket,
             packages: packages_rx,
             events: events_tx,
             sequence: Channel(0),
-            callbacks: HashMap::new(),
+            limits,
+            in_flight: HashMap::new(),
+            queued: VecDeque::new(),
+            retired: HashMap::new(),
+            counts: requests.clone(),
+            bandwidth: bandwidth.clone(),
+            version: None,
         };
 
         let runtime_thread = thread::spawn(move || {
@@ -85,9 +149,26 @@ impl Connection {
             runtime_thread,
             packages: packages_tx,
             events: events_rx,
+            bandwidth,
+            requests,
         })
     }
 
+    /// The number of messages and bytes sent and received of each kind of message.
+    pub fn bandwidth(&self) -> &Bandwidth {
+        &self.bandwidth
+    }
+
+    /// The number of requests waiting for a response.
+    pub fn requests_in_flight(&self) -> usize {
+        self.requests.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// The number of requests waiting to be sent, because too many requests are in flight.
+    pub fn queued_requests(&self) -> usize {
+        self.requests.queued.load(Ordering::Relaxed)
+    }
+
     /// Close the connection
     pub fn close(self) {
         let Connection {
@@ -159,6 +240,8 @@ impl Connection {
 impl Router {
     /// Asynchronously send requests to, and receive messages from, the server.
     async fn run(&mut self) -> anyhow::Result<()> {
+        let mut timeouts = time::interval(TIMEOUT_CHECK_INTERVAL);
+
         loop {
             tokio::select! {
                 bytes = self.socket.recv() => match bytes {
@@ -175,9 +258,8 @@ impl Router {
                             break Ok(());
                         },
                         Some(Package::Request { kind, callback }) => {
-                            let channel = self.setup_callback(callback);
-                            let request = Request { channel, kind };
-                            self.send_message(ClientMessage::Request(request)).await?;
+                            self.queued.push_back((kind, callback));
+                            self.send_queued().await?;
                         }
                         Some(Package::Action(action)) => {
                             self.send_message(ClientMessage::Action(action)).await?;
@@ -185,18 +267,89 @@ impl Router {
                     }
                 },
 
+                _ = timeouts.tick() => {
+                    self.expire_requests();
+                    self.send_queued().await?;
+                },
+
                 else => break Ok(()),
             }
         }
     }
 
+    /// Send queued requests until the window of requests in flight is full.
+    async fn send_queued(&mut self) -> anyhow::Result<()> {
+        while self.in_flight.len() < self.limits.max_in_flight.max(1) {
+            let (kind, callback) = match self.queued.pop_front() {
+                Some(request) => request,
+                None => break,
+            };
+
+            let channel = self.setup_callback(callback);
+            self.update_counts();
+
+            let request = Request { channel, kind };
+            self.send_message(ClientMessage::Request(request)).await?;
+        }
+
+        self.update_counts();
+        Ok(())
+    }
+
+    /// Give up on requests that have waited too long for a response, closing their handles.
+    fn expire_requests(&mut self) {
+        let now = Instant::now();
+        let timeout = self.limits.timeout;
+
+        let expired = self
+            .in_flight
+            .iter()
+            .filter(|(_, request)| now.saturating_duration_since(request.sent) >= timeout)
+            .map(|(channel, _)| *channel)
+            .collect::<Vec<_>>();
+
+        for channel in expired {
+            log::warn!("request on channel {} timed out", channel.0);
+            self.in_flight.remove(&channel);
+            self.retired.insert(channel, now + timeout);
+        }
+
+        self.retired.retain(|_, reusable| *reusable > now);
+        self.update_counts();
+    }
+
+    fn update_counts(&self) {
+        self.counts
+            .in_flight
+            .store(self.in_flight.len(), Ordering::Relaxed);
+        self.counts
+            .queued
+            .store(self.queued.len(), Ordering::Relaxed);
+    }
+
     /// Handle an incoming payload from the server.
     async fn handle_payload(&mut self, bytes: Vec<u8>) -> anyhow::Result<()> {
         log::debug!("received {} bytes...", bytes.len());
 
-        match protocol::from_bytes(&bytes) {
-            Err(e) => log::warn!("malformed message: {:#}", e),
-            Ok(message) => self.dispatch_message(message).await?,
+        let message = match self.version {
+            Some(version) => protocol::from_bytes_strict_versioned::<ServerMessage>(
+                &bytes,
+                version,
+                Limits::UNLIMITED,
+            ),
+            None => protocol::from_bytes_strict::<ServerMessage>(&bytes),
+        };
+
+        match message {
+            Err(e) => {
+                log::warn!("malformed message: {:#}", e);
+                self.bandwidth
+                    .record_received(bandwidth::MALFORMED, bytes.len());
+            }
+            Ok(message) => {
+                self.bandwidth.record_received(message.name(), bytes.len());
+                self.dispatch_message(message).await?
+            }
         }
 
         Ok(())
@@ -206,30 +359,53 @@ impl Router {
     async fn dispatch_message(&mut self, message: ServerMessage) -> anyhow::Result<()> {
         match message {
             ServerMessage::Event(event) => self.events.send(event).await?,
-            ServerMessage::Response(response) => match self.callbacks.remove(&response.channel) {
-                Some(callback) => callback.send(response.kind),
-                None => log::warn!("no callback registered for channel {}", response.channel.0),
-            },
+            ServerMessage::Response(response) => {
+                if let ResponseKind::Connect(connect) = &response.kind {
+                    self.version = Some(connect.version.min(protocol::VERSION));
+                }
+
+                let channel = response.channel;
+                match self.in_flight.remove(&channel) {
+                    Some(request) => request.callback.send(response.kind),
+                    None if self.retired.remove(&channel).is_some() => {
+                        log::debug!("dropped late response on channel {}", channel.0)
+                    }
+                    None => log::warn!("no callback registered for channel {}", channel.0),
+                }
+                self.send_queued().await?;
+            }
         }
 
         Ok(())
     }
 
-    /// Setup a callback for a request on a certain channel.
+    /// Setup a callback for a request on a free channel.
     fn setup_callback(&mut self, callback: ResponseCallback) -> Channel {
-        let channel = self.sequence;
-        self.callbacks.insert(channel, callback);
-
-        while self.callbacks.contains_key(&self.sequence) {
+        while self.in_flight.contains_key(&self.sequence)
+            || self.retired.contains_key(&self.sequence)
+        {
             self.sequence.0 = self.sequence.0.wrapping_add(1);
         }
 
+        let channel = self.sequence;
+        self.sequence.0 = self.sequence.0.wrapping_add(1);
+
+        let request = InFlight {
+            callback,
+            sent: Instant::now(),
+        };
+        self.in_flight.insert(channel, request);
+
         channel
     }
 
     /// Send a request to the server.
     async fn send_message(&mut self, message: ClientMessage) -> anyhow::Result<()> {
-        let bytes = protocol::to_bytes(&message)?;
+        let bytes = match self.version {
+            Some(version) => protocol::to_bytes_versioned(&message, version)?,
+            None => protocol::to_bytes(&message)?,
+        };
+        self.bandwidth.record_sent(message.name(), bytes.len());
 
         let delivery = if message.must_arrive() {
             Delivery::Reliable
@@ -242,6 +418,15 @@ impl Router {
     }
 }
 
+impl Default for RequestLimits {
+    fn default() -> Self {
+        RequestLimits {
+            max_in_flight: 64,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
 pub enum PollError<E> {
     /// The channel has been closed. No value will ever be yielded.
     Closed,
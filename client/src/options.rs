@@ -1,21 +1,49 @@
 use std::net::IpAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 use structopt::StructOpt;
 
+use crate::renderer::FrameLimit;
+
 #[derive(StructOpt)]
 pub struct Options {
-    /// The address of the server to connect to.
-    #[structopt(short, long, default_value = "0.0.0.0")]
-    pub addr: IpAddr,
+    /// The address of the server to connect to. Defaults to the last server connected to.
+    #[structopt(short, long)]
+    pub addr: Option<IpAddr>,
+
+    /// The port of the server to connect to. Defaults to the last server connected to.
+    #[structopt(short, long)]
+    pub port: Option<u16>,
+
+    /// The name other players see.
+    #[structopt(long)]
+    pub nickname: Option<String>,
+
+    /// The language of all text, such as `en` or `sv`. Defaults to the language of the system.
+    #[structopt(long)]
+    pub language: Option<String>,
 
-    /// The port of the server to connect to.
-    #[structopt(short, long, default_value = "8999")]
-    pub port: u16,
+    /// Let a second player join on the same machine, with the window split in two.
+    #[structopt(long)]
+    pub split_screen: bool,
+
+    /// The number of samples to use for multisampling.
+    #[structopt(long)]
+    pub samples: Option<u32>,
+
+    /// How often frames are rendered: `vsync`, `mailbox` or a maximum number of frames per
+    /// second. May be changed at runtime with the `gfx.fps` console command.
+    #[structopt(long)]
+    pub frame_limit: Option<FrameLimit>,
 
     /// The verbosity level of the logger.
     #[structopt(long, default_value = "warn")]
     pub log_level: Vec<LogFilter>,
+
+    /// Write all sent and received datagrams to a pcapng file.
+    #[structopt(long, parse(from_os_str))]
+    pub pcap_out: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone)]
@@ -10,6 +10,7 @@ use std::fs;
 use std::io::Cursor;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use zerocopy::AsBytes;
 
@@ -20,10 +21,21 @@ use winit::window::Window;
 
 mod gbuffer;
 mod models;
+mod overlay;
+pub mod pacing;
+mod permutations;
 mod texture;
 
+pub use pacing::FrameLimit;
+pub use permutations::ShaderOptions;
+
 use gbuffer::GBuffer;
 use models::ModelRegistry;
+use overlay::Overlay;
+use pacing::Pacer;
+use permutations::ShaderPermutations;
+
+pub use overlay::OverlayRect;
 
 /// `cgmath` uses OpenGL's coordinate system while WebGPU uses 
 #[rustfmt::skip]
@@ -39,6 +51,7 @@ pub struct RendererConfig {
     pub width: u32,
     pub height: u32,
     pub samples: u32,
+    pub shader_options: ShaderOptions,
 }
 
 pub struct Renderer {
@@ -46,28 +59,61 @@ pub struct Renderer {
     queue: wgpu::Queue,
     surface: wgpu::Surface,
     swap_chain: wgpu::SwapChain,
-    pipeline: wgpu::RenderPipeline,
+    pacer: Pacer,
+
+    /// The composition pipeline of every shader permutation used so far.
+    pipelines: HashMap<ShaderOptions, wgpu::RenderPipeline>,
+    pipeline_layout: wgpu::PipelineLayout,
+    composition_vertex: wgpu::ShaderModule,
+    composition_fragments: ShaderPermutations,
+    shader_options: ShaderOptions,
 
-    bind_group: wgpu::BindGroup,
     bind_group_layout: wgpu::BindGroupLayout,
 
     framebuffer: wgpu::TextureView,
-    gbuffer: GBuffer,
 
     size: Size,
     samples: u32,
 
-    uniforms: Uniforms,
+    views: Vec<View>,
+    view_count: u32,
 
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
 
+    models: ModelRegistry,
+
+    /// Instance batches of previous frames, reused to avoid allocations.
+    instance_pool: Vec<HashMap<Model, Vec<Instance>>>,
+
+    black_texture: wgpu::TextureView,
+
+    /// Drawn on top of all views. Created the first time anything is drawn in the overlay, and
+    /// `Err` if that failed.
+    overlay: Option<Result<Overlay, ()>>,
+
+    /// When the renderer was created. Ambient motion is animated by the time since.
+    start: Instant,
+}
+
+/// A region of the window rendered from the perspective of a single camera. Every view has its own
+/// G-buffer sized to match the region.
+struct View {
+    rect: Rect,
+    gbuffer: GBuffer,
+
+    uniforms: Uniforms,
     uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
 
-    models: ModelRegistry,
     instances: HashMap<Model, Vec<Instance>>,
+}
 
-    black_texture: wgpu::TextureView,
+#[derive(Copy, Clone)]
+struct Rect {
+    x: u32,
+    y: u32,
+    size: Size,
 }
 
 struct Shaders {
@@ -145,11 +191,18 @@ pub struct Instance {
     scale: [f32; 3],
     #[vertex(format = Float3, location = 6)]
     color: [f32; 3],
+    /// How much the model sways and bobs, and the phase of the motion. See `with_motion`.
+    #[vertex(format = Float3, location = 7)]
+    motion: [f32; 3],
 }
 
 impl Renderer {
     const COLOR_OUTPUT_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8Unorm;
 
+    /// How long to wait instead of rendering a frame while the window is minimized, so that the
+    /// game keeps running without spinning.
+    const MINIMIZED_FRAME_TIME: Duration = Duration::from_millis(50);
+
     pub async fn new(window: &Window, config: RendererConfig) -> Result<Renderer> {
         let surface = wgpu::Surface::create(window);
 
@@ -170,8 +223,10 @@ impl Renderer {
         let device = Arc::new(device);
 
         let vertex_path = "src/shaders/fullscreen.vert.spv";
-        let fragment_path = "src/shaders/composition.frag.spv";
-        let shaders = Shaders::open(&device, vertex_path, fragment_path)?;
+        let composition_vertex = Shaders::open_module(&device, vertex_path)?;
+
+        let composition_fragments = ShaderPermutations::new("src/shaders/composition.frag")
+            .with_precompiled(ShaderOptions::default(), "src/shaders/composition.frag.spv");
 
         // Create bind groups
         let bind_group_layout_desc = Self::bind_group_layout_desc();
@@ -184,11 +239,22 @@ impl Renderer {
         let pipeline_layout = device.create_pipeline_layout(&layout_desc);
 
         // Create render pipeline
-        let render_pipeline_desc = Self::render_pipeline_desc(&pipeline_layout, &shaders, config);
+        let default_options = ShaderOptions::default();
+        let fragment = composition_fragments.fragment(&device, default_options)?;
+        let render_pipeline_desc = Self::render_pipeline_desc(
+            &pipeline_layout,
+            &composition_vertex,
+            &fragment,
+            config.samples,
+        );
         let pipeline = device.create_render_pipeline(&render_pipeline_desc);
 
+        let mut pipelines = HashMap::new();
+        pipelines.insert(default_options, pipeline);
+
         // Setup swap chain
-        let swap_chain_desc = Self::swap_chain_desc(config.width, config.height);
+        let pacer = Pacer::new(pacing::frame_limit());
+        let swap_chain_desc = Self::swap_chain_desc(config.width, config.height, pacer.limit());
         let swap_chain = device.create_swap_chain(&surface, &swap_chain_desc);
 
         // Create multipsampled framebuffer
@@ -197,8 +263,6 @@ impl Renderer {
             .create_texture(&framebuffer_desc)
             .create_default_view();
 
-        let gbuffer = GBuffer::new(device.clone(), size);
-
         // Load models
         let mut encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
@@ -213,75 +277,93 @@ impl Renderer {
         let index_buffer =
             device.create_buffer_with_data(indices.as_bytes(), wgpu::BufferUsage::INDEX);
 
-        // Setup shader uniforms
-        let uniforms = Uniforms::default();
-        let uniform_buffer = device.create_buffer_with_data(
-            uniforms.as_bytes(),
-            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-        );
-
-        let sampler = Self::create_sampler(&device);
-
         let mut black_image = image::RgbaImage::new(1, 1);
         black_image.put_pixel(0, 0, image::Rgba([0, 0, 0, 255]));
         let black_texture = texture::from_image(&black_image, &device, &mut encoder);
 
-        let bindings = Bindings {
-            uniforms: &uniform_buffer,
-            sampler: &sampler,
-            color: gbuffer.color_buffer_view(),
-            normal: gbuffer.normal_buffer_view(),
-            position: gbuffer.position_buffer_view(),
-        };
-
-        let bind_group = Self::create_bind_group(&device, &bind_group_layout, bindings);
+        let views = Self::create_views(&device, &bind_group_layout, size, 1);
 
         queue.submit(&[encoder.finish()]);
 
         // Finilize
-        let renderer = Renderer {
+        let mut renderer = Renderer {
             device,
             queue,
             surface,
             swap_chain,
-            pipeline,
+            pacer,
+
+            pipelines,
+            pipeline_layout,
+            composition_vertex,
+            composition_fragments,
+            shader_options: default_options,
 
-            bind_group,
             bind_group_layout,
 
             framebuffer,
-            gbuffer,
 
-            size: Size {
-                width: config.width,
-                height: config.height,
-            },
+            size,
             samples: config.samples,
 
-            uniforms,
+            views,
+            view_count: 1,
 
             vertex_buffer,
             index_buffer,
 
             models,
-            instances: HashMap::new(),
+            instance_pool: Vec::new(),
 
-            uniform_buffer,
             black_texture,
+
+            overlay: None,
+
+            start: Instant::now(),
         };
 
+        if let Err(e) = renderer.set_shader_options(config.shader_options) {
+            log::error!("failed to enable shader options, using defaults: {:#}", e);
+        }
+
         Ok(renderer)
     }
 
+    /// Switch to the shader permutation with the given features, compiling it if it has not been
+    /// used before. The current permutation is kept if compilation fails.
+    pub fn set_shader_options(&mut self, options: ShaderOptions) -> Result<()> {
+        if !self.pipelines.contains_key(&options) {
+            let fragment = self.composition_fragments.fragment(&self.device, options)?;
+            let desc = Self::render_pipeline_desc(
+                &self.pipeline_layout,
+                &self.composition_vertex,
+                &fragment,
+                self.samples,
+            );
+            let pipeline = self.device.create_render_pipeline(&desc);
+            self.pipelines.insert(options, pipeline);
+        }
+
+        self.shader_options = options;
+        Ok(())
+    }
+
     fn render_pipeline_desc<'a>(
         layout: &'a wgpu::PipelineLayout,
-        shaders: &'a Shaders,
-        config: RendererConfig,
+        vertex: &'a wgpu::ShaderModule,
+        fragment: &'a wgpu::ShaderModule,
+        samples: u32,
     ) -> wgpu::RenderPipelineDescriptor<'a> {
         wgpu::RenderPipelineDescriptor {
             layout,
-            vertex_stage: shaders.vertex_stage(),
-            fragment_stage: Some(shaders.fragment_stage()),
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: vertex,
+                entry_point: "main",
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: fragment,
+                entry_point: "main",
+            }),
             rasterization_state: Some(wgpu::RasterizationStateDescriptor {
                 front_face: wgpu::FrontFace::Ccw,
                 cull_mode: wgpu::CullMode::None,
@@ -299,19 +381,19 @@ impl Renderer {
                 index_format: wgpu::IndexFormat::Uint32,
                 vertex_buffers: &[],
             },
-            sample_count: config.samples,
+            sample_count: samples,
             sample_mask: !0,
             alpha_to_coverage_enabled: false,
         }
     }
 
-    fn swap_chain_desc(width: u32, height: u32) -> wgpu::SwapChainDescriptor {
+    fn swap_chain_desc(width: u32, height: u32, limit: FrameLimit) -> wgpu::SwapChainDescriptor {
         wgpu::SwapChainDescriptor {
             usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
             format: Self::COLOR_OUTPUT_TEXTURE_FORMAT,
             width,
             height,
-            present_mode: wgpu::PresentMode::Mailbox,
+            present_mode: limit.present_mode(),
         }
     }
 
@@ -435,10 +517,91 @@ impl Renderer {
         device.create_bind_group(&bind_group_desc)
     }
 
+    /// Split the window into a number of views placed side by side, one for each frame passed to
+    /// `submit_all`.
+    fn create_views(
+        device: &Arc<wgpu::Device>,
+        layout: &wgpu::BindGroupLayout,
+        size: Size,
+        count: u32,
+    ) -> Vec<View> {
+        let width = u32::max(1, size.width / count);
+
+        (0..count)
+            .map(|i| {
+                let rect = Rect {
+                    x: i * width,
+                    y: 0,
+                    size: Size {
+                        width,
+                        height: size.height,
+                    },
+                };
+
+                let gbuffer = GBuffer::new(device.clone(), rect.size);
+
+                let uniforms = Uniforms::default();
+                let uniform_buffer = device.create_buffer_with_data(
+                    uniforms.as_bytes(),
+                    wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+                );
+
+                let sampler = Self::create_sampler(device);
+
+                let bindings = Bindings {
+                    uniforms: &uniform_buffer,
+                    sampler: &sampler,
+                    color: gbuffer.color_buffer_view(),
+                    normal: gbuffer.normal_buffer_view(),
+                    position: gbuffer.position_buffer_view(),
+                };
+
+                let bind_group = Self::create_bind_group(device, layout, bindings);
+
+                View {
+                    rect,
+                    gbuffer,
+                    uniforms,
+                    uniform_buffer,
+                    bind_group,
+                    instances: HashMap::new(),
+                }
+            })
+            .collect()
+    }
+
+    /// Set the number of views the window is split into.
+    pub fn set_view_count(&mut self, count: u32) {
+        self.view_count = count.max(1);
+        self.views = Self::create_views(
+            &self.device,
+            &self.bind_group_layout,
+            self.size,
+            self.view_count,
+        );
+        self.cleanup();
+    }
+
+    /// The size of a single view.
+    pub fn view_size(&self) -> Size {
+        self.views[0].rect.size
+    }
+
+    /// True if the window has no area to render to, which is the case while it is minimized.
+    pub fn is_minimized(&self) -> bool {
+        self.size.width == 0 || self.size.height == 0
+    }
+
     pub fn set_size(&mut self, width: u32, height: u32) {
         self.size = Size { width, height };
 
-        let swap_chain_desc = Self::swap_chain_desc(width, height);
+        // A swap chain without any area is invalid, so keep the old resources until the window is
+        // restored and we get a proper size again.
+        if self.is_minimized() {
+            return;
+        }
+
+        let swap_chain_desc = Self::swap_chain_desc(width, height, self.pacer.limit());
         self.swap_chain = self
             .device
             .create_swap_chain(&self.surface, &swap_chain_desc);
@@ -449,19 +612,12 @@ impl Renderer {
             .create_texture(&framebuffer_desc)
             .create_default_view();
 
-        self.gbuffer = GBuffer::new(self.device.clone(), self.size);
-
-        let sampler = Self::create_sampler(&self.device);
-
-        let bindings = Bindings {
-            uniforms: &self.uniform_buffer,
-            sampler: &sampler,
-            color: self.gbuffer.color_buffer_view(),
-            normal: self.gbuffer.normal_buffer_view(),
-            position: self.gbuffer.position_buffer_view(),
-        };
-
-        self.bind_group = Self::create_bind_group(&self.device, &self.bind_group_layout, bindings);
+        self.views = Self::create_views(
+            &self.device,
+            &self.bind_group_layout,
+            self.size,
+            self.view_count,
+        );
 
         self.cleanup();
     }
@@ -470,8 +626,27 @@ impl Renderer {
         self.device.poll(wgpu::Maintain::Wait);
     }
 
+    /// Draw rectangles on top of the next submitted frames, until the overlay is replaced.
+    pub fn set_overlay(&mut self, rects: &[OverlayRect]) {
+        if rects.is_empty() && self.overlay.is_none() {
+            return;
+        }
+
+        let device = &self.device;
+        let samples = self.samples;
+        let overlay = self.overlay.get_or_insert_with(|| {
+            Overlay::new(device, samples).map_err(|e| {
+                log::error!("failed to create overlay: {:#}", e);
+            })
+        });
+
+        if let Ok(overlay) = overlay {
+            overlay.set_rects(rects, self.size);
+        }
+    }
+
     pub fn next_frame(&mut self, camera: Camera) -> Frame {
-        let mut instances = std::mem::take(&mut self.instances);
+        let mut instances = self.instance_pool.pop().unwrap_or_default();
         for batch in instances.values_mut() {
             batch.clear();
         }
@@ -479,14 +654,56 @@ impl Renderer {
     }
 
     pub fn submit(&mut self, frame: Frame) {
-        let Frame { instances, camera } = frame;
+        self.submit_all(Some(frame));
+    }
+
+    /// Render a frame in each view, in order from left to right, then wait until the next frame
+    /// may be rendered according to the frame limit.
+    pub fn submit_all(&mut self, frames: impl IntoIterator<Item = Frame>) {
+        self.apply_frame_limit();
 
-        self.instances = instances;
-        self.uniforms.transform = camera.transform(self.size).into();
-        self.uniforms.camera_pos = camera.position.into();
-        self.uniforms.light_pos = camera.focus.into();
+        if self.is_minimized() {
+            self.instance_pool
+                .extend(frames.into_iter().map(|frame| frame.instances));
+            std::thread::sleep(Self::MINIMIZED_FRAME_TIME);
+            return;
+        }
+
+        for (view, frame) in self.views.iter_mut().zip(frames) {
+            let Frame { instances, camera } = frame;
+
+            let previous = std::mem::replace(&mut view.instances, instances);
+            self.instance_pool.push(previous);
+
+            view.uniforms.transform = camera.transform(view.rect.size).into();
+            view.uniforms.camera_pos = camera.position.into();
+            view.uniforms.light_pos = camera.focus.into();
+        }
 
         self.render();
+        self.pacer.wait();
+    }
+
+    /// Switch to the current frame limit if it changed, recreating the swap chain if the present
+    /// mode changes.
+    fn apply_frame_limit(&mut self) {
+        let limit = pacing::frame_limit();
+        if limit == self.pacer.limit() {
+            return;
+        }
+
+        let present_mode = self.pacer.limit().present_mode();
+        self.pacer.set_limit(limit);
+
+        // while minimized the swap chain is recreated with the new limit once the window is restored
+        if limit.present_mode() != present_mode && !self.is_minimized() {
+            let swap_chain_desc = Self::swap_chain_desc(self.size.width, self.size.height, limit);
+            self.swap_chain = self
+                .device
+                .create_swap_chain(&self.surface, &swap_chain_desc);
+        }
+
+        log::info!("frame limit: {}", limit);
     }
 
     fn render(&mut self) {
@@ -494,7 +711,9 @@ impl Renderer {
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
-        self.update_buffers(&mut encoder);
+        for view in &self.views {
+            self.update_buffers(&mut encoder, view);
+        }
 
         let frame = self.swap_chain.get_next_texture().unwrap();
 
@@ -507,14 +726,16 @@ impl Renderer {
         };
 
         // G-buffer
-        {
+        for view in &self.views {
             let uniforms = gbuffer::Uniforms {
-                transform: self.uniforms.transform,
+                transform: view.uniforms.transform,
+                time: self.start.elapsed().as_secs_f32(),
+                _pad: [0.0; 3],
             };
 
-            let instances = self.prepare_instances();
+            let instances = self.prepare_instances(view);
 
-            let mut render_pass = self.gbuffer.begin_render_pass(&mut encoder, uniforms);
+            let mut render_pass = view.gbuffer.begin_render_pass(&mut encoder, uniforms);
             render_pass.set_vertex_buffer(0, &self.vertex_buffer, 0, 0);
             render_pass.set_index_buffer(&self.index_buffer, 0, 0);
 
@@ -525,13 +746,47 @@ impl Renderer {
             }
         }
 
+        let overlay = match &self.overlay {
+            Some(Ok(overlay)) => overlay
+                .vertex_buffer(&self.device)
+                .map(|(buffer, count)| (overlay.pipeline(), buffer, count)),
+            _ => None,
+        };
+
         // Final composit
         {
             let mut render_pass = encoder.begin_render_pass(&render_pass_desc);
-            render_pass.set_pipeline(&self.pipeline);
-            render_pass.set_bind_group(0, &self.bind_group, &[0]);
-            render_pass.draw(0..3, 0..1);
-            render_pass.draw(1..4, 0..1);
+            render_pass.set_pipeline(&self.pipelines[&self.shader_options]);
+
+            for view in &self.views {
+                let Rect { x, y, size } = view.rect;
+                render_pass.set_viewport(
+                    x as f32,
+                    y as f32,
+                    size.width as f32,
+                    size.height as f32,
+                    0.0,
+                    1.0,
+                );
+
+                render_pass.set_bind_group(0, &view.bind_group, &[0]);
+                render_pass.draw(0..3, 0..1);
+                render_pass.draw(1..4, 0..1);
+            }
+
+            if let Some((pipeline, buffer, count)) = &overlay {
+                render_pass.set_viewport(
+                    0.0,
+                    0.0,
+                    self.size.width as f32,
+                    self.size.height as f32,
+                    0.0,
+                    1.0,
+                );
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_vertex_buffer(0, buffer, 0, 0);
+                render_pass.draw(0..*count, 0..1);
+            }
         }
 
         let render_commands = encoder.finish();
@@ -539,8 +794,11 @@ impl Renderer {
         self.queue.submit(&[render_commands]);
     }
 
-    fn prepare_instances(&self) -> Vec<(wgpu::BindGroup, wgpu::Buffer, models::IndexRange, u32)> {
-        self.instances
+    fn prepare_instances(
+        &self,
+        view: &View,
+    ) -> Vec<(wgpu::BindGroup, wgpu::Buffer, models::IndexRange, u32)> {
+        view.instances
             .iter()
             .filter(|(_, instances)| !instances.is_empty())
             .map(|(&model, instances)| {
@@ -555,7 +813,7 @@ impl Renderer {
 
                 let bind_group_desc = wgpu::BindGroupDescriptor {
                     label: None,
-                    layout: self.gbuffer.model_bind_group_layout(),
+                    layout: view.gbuffer.model_bind_group_layout(),
                     bindings: &[
                         wgpu::Binding {
                             binding: 0,
@@ -609,23 +867,33 @@ impl Renderer {
         }
     }
 
-    fn update_buffers(&mut self, encoder: &mut wgpu::CommandEncoder) {
+    fn update_buffers(&self, encoder: &mut wgpu::CommandEncoder, view: &View) {
         let scratch_uniform_buffer = self.device.create_buffer_with_data(
-            self.uniforms.as_bytes(),
+            view.uniforms.as_bytes(),
             wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_SRC,
         );
 
         encoder.copy_buffer_to_buffer(
             &scratch_uniform_buffer,
             0,
-            &self.uniform_buffer,
+            &view.uniform_buffer,
             0,
-            std::mem::size_of_val(&self.uniforms) as u64,
+            std::mem::size_of_val(&view.uniforms) as u64,
         );
     }
 }
 
 impl Shaders {
+    /// Load a single shader module from a SPIR-V file.
+    pub fn open_module(
+        device: &wgpu::Device,
+        path: impl AsRef<Path>,
+    ) -> Result<wgpu::ShaderModule> {
+        let source = fs::read(path)?;
+        let spirv = wgpu::read_spirv(Cursor::new(source))?;
+        Ok(device.create_shader_module(&spirv))
+    }
+
     pub fn open(
         device: &wgpu::Device,
         vertex: impl AsRef<Path>,
@@ -727,6 +995,7 @@ impl Instance {
             position: position.into(),
             scale: [1.0; 3],
             color: [0.0; 3],
+            motion: [0.0; 3],
         }
     }
 
@@ -740,4 +1009,14 @@ impl Instance {
     pub fn with_color(self, color: [f32; 3]) -> Self {
         Instance { color, ..self }
     }
+
+    /// Animate the model in the vertex shader. `sway` bends the top of the model sideways and
+    /// `bob` stretches it vertically, both as fractions of its size. Instances with different
+    /// `phase`s move out of step with each other.
+    pub fn with_motion(self, sway: f32, bob: f32, phase: f32) -> Self {
+        Instance {
+            motion: [sway, bob, phase],
+            ..self
+        }
+    }
 }
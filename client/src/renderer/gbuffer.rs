@@ -34,12 +34,17 @@ struct BufferTexture {
 #[repr(C)]
 pub struct Uniforms {
     pub transform: [[f32; 4]; 4],
+    /// Seconds since the renderer was created, used to animate ambient motion.
+    pub time: f32,
+    pub _pad: [f32; 3],
 }
 
 impl Default for Uniforms {
     fn default() -> Self {
         Uniforms {
             transform: Matrix4::identity().into(),
+            time: 0.0,
+            _pad: [0.0; 3],
         }
     }
 }
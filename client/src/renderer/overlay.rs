@@ -0,0 +1,151 @@
+//! Flat, semi-transparent rectangles drawn on top of every view, such as debug graphs.
+//!
+//! The overlay shaders are not compiled ahead of time, so the pipeline is only created the first
+//! time something is drawn in the overlay.
+
+use super::permutations;
+use super::{Renderer, Size};
+
+use anyhow::Result;
+use glsl_to_spirv::ShaderType;
+use std::path::Path;
+use zerocopy::AsBytes;
+
+use wgpu::VertexFormat::{Float2, Float4};
+use wgpu_shader::VertexLayout;
+
+/// A rectangle in the overlay, in pixels from the top left corner of the window.
+#[derive(Debug, Copy, Clone)]
+pub struct OverlayRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /// The color of the rectangle, with its opacity as the last component.
+    pub color: [f32; 4],
+}
+
+pub(super) struct Overlay {
+    pipeline: wgpu::RenderPipeline,
+    vertices: Vec<OverlayVertex>,
+}
+
+#[derive(Debug, Copy, Clone, AsBytes, VertexLayout)]
+#[repr(C)]
+struct OverlayVertex {
+    #[vertex(format = Float2, location = 0)]
+    position: [f32; 2],
+    #[vertex(format = Float4, location = 1)]
+    color: [f32; 4],
+}
+
+impl Overlay {
+    const VERTEX_BUFFERS: &'static [wgpu::VertexBufferDescriptor<'static>] =
+        &[wgpu::VertexBufferDescriptor {
+            stride: std::mem::size_of::<OverlayVertex>() as u64,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: OverlayVertex::ATTRIBUTES,
+        }];
+
+    const BLEND: wgpu::BlendDescriptor = wgpu::BlendDescriptor {
+        src_factor: wgpu::BlendFactor::SrcAlpha,
+        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+        operation: wgpu::BlendOperation::Add,
+    };
+
+    pub fn new(device: &wgpu::Device, samples: u32) -> Result<Overlay> {
+        let vertex =
+            permutations::compile(Path::new("src/shaders/overlay.vert"), ShaderType::Vertex)?;
+        let fragment =
+            permutations::compile(Path::new("src/shaders/overlay.frag"), ShaderType::Fragment)?;
+        let vertex = device.create_shader_module(&vertex);
+        let fragment = device.create_shader_module(&fragment);
+
+        let layout_desc = wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[],
+        };
+        let layout = device.create_pipeline_layout(&layout_desc);
+
+        let descriptor = wgpu::RenderPipelineDescriptor {
+            layout: &layout,
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: &vertex,
+                entry_point: "main",
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: &fragment,
+                entry_point: "main",
+            }),
+            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                ..Default::default()
+            }),
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            color_states: &[wgpu::ColorStateDescriptor {
+                format: Renderer::COLOR_OUTPUT_TEXTURE_FORMAT,
+                color_blend: Self::BLEND,
+                alpha_blend: Self::BLEND,
+                write_mask: wgpu::ColorWrite::COLOR,
+            }],
+            depth_stencil_state: None,
+            vertex_state: wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint32,
+                vertex_buffers: Self::VERTEX_BUFFERS,
+            },
+            sample_count: samples,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        };
+
+        Ok(Overlay {
+            pipeline: device.create_render_pipeline(&descriptor),
+            vertices: Vec::new(),
+        })
+    }
+
+    pub fn pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipeline
+    }
+
+    /// Replace the rectangles drawn in the overlay.
+    pub fn set_rects(&mut self, rects: &[OverlayRect], window: Size) {
+        self.vertices.clear();
+
+        let width = window.width.max(1) as f32;
+        let height = window.height.max(1) as f32;
+        let to_clip = |x: f32, y: f32| [2.0 * x / width - 1.0, 1.0 - 2.0 * y / height];
+
+        for rect in rects {
+            let top_left = to_clip(rect.x, rect.y);
+            let top_right = to_clip(rect.x + rect.width, rect.y);
+            let bottom_left = to_clip(rect.x, rect.y + rect.height);
+            let bottom_right = to_clip(rect.x + rect.width, rect.y + rect.height);
+
+            let corners = [
+                top_left,
+                bottom_left,
+                bottom_right,
+                top_left,
+                bottom_right,
+                top_right,
+            ];
+            self.vertices
+                .extend(corners.iter().map(|&position| OverlayVertex {
+                    position,
+                    color: rect.color,
+                }));
+        }
+    }
+
+    /// Upload the vertices of the rectangles, returning the buffer and the number of vertices.
+    pub fn vertex_buffer(&self, device: &wgpu::Device) -> Option<(wgpu::Buffer, u32)> {
+        if self.vertices.is_empty() {
+            return None;
+        }
+
+        let buffer =
+            device.create_buffer_with_data(self.vertices.as_bytes(), wgpu::BufferUsage::VERTEX);
+        Some((buffer, self.vertices.len() as u32))
+    }
+}
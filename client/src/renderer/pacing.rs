@@ -0,0 +1,129 @@
+//! Limits how often frames are rendered.
+//!
+//! The limit is global so that it may be changed from the console while the game is running. The
+//! renderer picks up changes before rendering the next frame.
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Sleeping is imprecise, so we yield instead of sleeping during this last part of each frame.
+const YIELD_MARGIN: Duration = Duration::from_millis(1);
+
+static FRAME_LIMIT: Mutex<FrameLimit> = Mutex::new(FrameLimit::Vsync);
+
+/// How often frames are rendered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameLimit {
+    /// Present frames at the refresh rate of the display.
+    Vsync,
+    /// Render as fast as possible, presenting the newest frame at the refresh rate of the display.
+    Mailbox,
+    /// Render at most this many frames per second.
+    Fps(u32),
+}
+
+/// Waits between frames to stay below the frame limit.
+pub(super) struct Pacer {
+    limit: FrameLimit,
+    /// When the next frame may start.
+    deadline: Instant,
+}
+
+/// Get the current frame limit.
+pub fn frame_limit() -> FrameLimit {
+    *FRAME_LIMIT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Change the frame limit, which takes effect on the next frame.
+pub fn set_frame_limit(limit: FrameLimit) {
+    *FRAME_LIMIT.lock().unwrap_or_else(|e| e.into_inner()) = limit;
+}
+
+impl FrameLimit {
+    pub(super) fn present_mode(self) -> wgpu::PresentMode {
+        match self {
+            FrameLimit::Vsync => wgpu::PresentMode::Fifo,
+            FrameLimit::Mailbox | FrameLimit::Fps(_) => wgpu::PresentMode::Mailbox,
+        }
+    }
+}
+
+impl Default for FrameLimit {
+    fn default() -> Self {
+        FrameLimit::Vsync
+    }
+}
+
+impl Display for FrameLimit {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            FrameLimit::Vsync => write!(f, "vsync"),
+            FrameLimit::Mailbox => write!(f, "mailbox"),
+            FrameLimit::Fps(fps) => write!(f, "{} fps", fps),
+        }
+    }
+}
+
+impl FromStr for FrameLimit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vsync" => Ok(FrameLimit::Vsync),
+            "mailbox" => Ok(FrameLimit::Mailbox),
+            _ => match s.parse::<u32>() {
+                Ok(fps) if fps > 0 => Ok(FrameLimit::Fps(fps)),
+                _ => Err(anyhow!(
+                    "expected `vsync`, `mailbox` or a positive number of frames per second"
+                )),
+            },
+        }
+    }
+}
+
+impl Pacer {
+    pub fn new(limit: FrameLimit) -> Pacer {
+        Pacer {
+            limit,
+            deadline: Instant::now(),
+        }
+    }
+
+    pub fn limit(&self) -> FrameLimit {
+        self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: FrameLimit) {
+        self.limit = limit;
+        self.deadline = Instant::now();
+    }
+
+    /// Wait until the next frame may start.
+    pub fn wait(&mut self) {
+        let period = match self.limit {
+            FrameLimit::Fps(fps) if fps > 0 => Duration::from_secs(1) / fps,
+            _ => return,
+        };
+
+        let now = Instant::now();
+        match self.deadline.checked_duration_since(now) {
+            Some(remaining) => {
+                if remaining > YIELD_MARGIN {
+                    thread::sleep(remaining - YIELD_MARGIN);
+                }
+                while Instant::now() < self.deadline {
+                    thread::yield_now();
+                }
+                self.deadline += period;
+            }
+            // we fell behind, so there is no point in rendering the missed frames faster
+            None => self.deadline = now + period,
+        }
+    }
+}
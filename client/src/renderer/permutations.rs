@@ -0,0 +1,123 @@
+//! Variants of a shader with optional features enabled or disabled through preprocessor
+//! definitions.
+//!
+//! Permutations that have been compiled ahead of time are loaded from SPIR-V. Other permutations
+//! are compiled from the GLSL source the first time they are used.
+
+use anyhow::{Context, Result};
+use glsl_to_spirv::ShaderType;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+/// Optional features of the composition pass. Every combination is a separate permutation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ShaderOptions {
+    /// Draw outlines around geometry.
+    pub outlines: bool,
+    /// Fade distant geometry into the sky.
+    pub fog: bool,
+}
+
+/// The permutations of a single fragment shader.
+pub(super) struct ShaderPermutations {
+    /// Path to the GLSL source.
+    source: PathBuf,
+    /// Permutations that were compiled ahead of time.
+    precompiled: HashMap<ShaderOptions, PathBuf>,
+}
+
+impl Default for ShaderOptions {
+    fn default() -> Self {
+        ShaderOptions {
+            outlines: true,
+            fog: true,
+        }
+    }
+}
+
+impl ShaderOptions {
+    /// The preprocessor definitions that enable the features.
+    fn defines(self) -> Vec<&'static str> {
+        let mut defines = Vec::new();
+        if self.outlines {
+            defines.push("OUTLINES");
+        }
+        if self.fog {
+            defines.push("FOG");
+        }
+        defines
+    }
+}
+
+impl ShaderPermutations {
+    pub fn new(source: impl Into<PathBuf>) -> ShaderPermutations {
+        ShaderPermutations {
+            source: source.into(),
+            precompiled: HashMap::new(),
+        }
+    }
+
+    /// Use a SPIR-V binary instead of compiling a permutation from source.
+    pub fn with_precompiled(mut self, options: ShaderOptions, path: impl Into<PathBuf>) -> Self {
+        self.precompiled.insert(options, path.into());
+        self
+    }
+
+    /// Create the shader module of a permutation.
+    pub fn fragment(
+        &self,
+        device: &wgpu::Device,
+        options: ShaderOptions,
+    ) -> Result<wgpu::ShaderModule> {
+        let spirv = match self.precompiled.get(&options) {
+            Some(path) => {
+                let bytes = fs::read(path)
+                    .with_context(|| format!("failed to read shader: {}", path.display()))?;
+                wgpu::read_spirv(Cursor::new(bytes))?
+            }
+            None => {
+                log::info!("compiling shader permutation: {:?}", options);
+                compile_fragment(&self.source, &options.defines())?
+            }
+        };
+
+        Ok(device.create_shader_module(&spirv))
+    }
+}
+
+/// Compile a GLSL fragment shader to SPIR-V with the given preprocessor definitions.
+fn compile_fragment(path: &Path, defines: &[&str]) -> Result<Vec<u32>> {
+    let source = fs::read_to_string(path)
+        .with_context(|| format!("failed to read shader: {}", path.display()))?;
+
+    // definitions have to come after the `#version` directive
+    let (version, body) = match source.find('\n') {
+        Some(end) => source.split_at(end + 1),
+        None => (source.as_str(), ""),
+    };
+
+    let mut permutation = String::from(version);
+    for define in defines {
+        permutation += &format!("#define {}\n", define);
+    }
+    permutation += body;
+
+    compile_source(path, &permutation, ShaderType::Fragment)
+}
+
+/// Compile a GLSL shader that has no ahead of time compiled SPIR-V.
+pub(super) fn compile(path: &Path, kind: ShaderType) -> Result<Vec<u32>> {
+    let source = fs::read_to_string(path)
+        .with_context(|| format!("failed to read shader: {}", path.display()))?;
+    compile_source(path, &source, kind)
+}
+
+fn compile_source(path: &Path, source: &str, kind: ShaderType) -> Result<Vec<u32>> {
+    let spirv = glsl_to_spirv::compile(source, kind)
+        .map_err(|e| anyhow!("failed to compile {}: {}", path.display(), e))?;
+
+    Ok(wgpu::read_spirv(spirv)?)
+}
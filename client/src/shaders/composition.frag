@@ -98,7 +98,13 @@ float phong() {
 void main() {
     init();
 
+#ifdef OUTLINES
     float outline = outline();
+#else
+    float outline = 0.0;
+    close_depth = f_distance;
+#endif
+
     float brightness = phong();
 
     vec4 fog_color = vec4(0.4, 0.7, 0.9, 0.0);
@@ -109,5 +115,9 @@ void main() {
     vec4 diffuse = f_distance > u_camera_far ? fog_color : brightness * f_color;
     vec4 base_color = mix(diffuse, outline_color, (1.0 - 0.8 * pow(distance_factor, 0.5)) * outline);
 
+#ifdef FOG
     out_color = mix(base_color, fog_color, clamp(pow(distance_factor, 2), 0.0, 1.0));
+#else
+    out_color = base_color;
+#endif
 }
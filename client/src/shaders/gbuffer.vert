@@ -17,13 +17,27 @@ layout(location = 2) in vec3 v_normal;
 layout(location = 4) in vec3 i_position;
 layout(location = 5) in vec3 i_scale;
 layout(location = 6) in vec3 i_color;
+// sway, bob, phase
+layout(location = 7) in vec3 i_motion;
 
 layout(binding = 0) uniform Locals {
     mat4 u_transform;
+    float u_time;
 };
 
 void main() {
-    f_position = vec3(i_scale * v_position + i_position);
+    vec3 position = i_scale * v_position;
+
+    // Bend the model sideways, more the higher up the vertex is, so that the base stays in place.
+    float height = max(position.z, 0.0);
+    float sway = i_motion.x * height * height;
+    position.x += sway * sin(1.3 * u_time + i_motion.z);
+    position.y += 0.6 * sway * sin(0.9 * u_time + 1.7 * i_motion.z);
+
+    // Squash and stretch vertically.
+    position.z *= 1.0 + i_motion.y * sin(2.5 * u_time + i_motion.z);
+
+    f_position = position + i_position;
     f_tex_coord = v_tex_coord;
     f_normal = v_normal;
 
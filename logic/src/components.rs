@@ -1,10 +1,15 @@
 use cgmath::{Point3, Vector3};
 use derive_more::{Deref, DerefMut};
 use legion::prelude::*;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display, Formatter};
 use crate::collision;
+use crate::projectiles::{ProjectileKind, ProjectileType};
+use crate::snapshot::Replicate;
+use protocol::snapshot::{ComponentId, EntityId};
+use protocol::ObjectKind;
 
-pub use protocol::Direction;
+pub use protocol::{Direction, Stance};
 
 /// The player that controls the entity.
 #[derive(Debug, Copy, Clone)]
@@ -18,10 +23,38 @@ pub struct Position(pub Point3<f32>);
 #[derive(Debug, Copy, Clone, Deref, DerefMut)]
 pub struct Velocity(pub Vector3<f32>);
 
+impl Replicate for Velocity {
+    const ID: ComponentId = ComponentId(0);
+
+    type State = Vector3<f32>;
+
+    fn pack(&self) -> Self::State {
+        self.0
+    }
+
+    fn unpack(state: Self::State) -> Self {
+        Velocity(state)
+    }
+}
+
 /// The acceleration currently being applied to the inty.
 #[derive(Debug, Copy, Clone, Deref, DerefMut)]
 pub struct Acceleration(pub Vector3<f32>);
 
+impl Replicate for Acceleration {
+    const ID: ComponentId = ComponentId(1);
+
+    type State = Vector3<f32>;
+
+    fn pack(&self) -> Self::State {
+        self.0
+    }
+
+    fn unpack(state: Self::State) -> Self {
+        Acceleration(state)
+    }
+}
+
 /// The model to render the entity with.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Model {
@@ -43,6 +76,23 @@ impl Model {
         Model::Mushroom,
         Model::Cube,
     ];
+
+    /// The kind of object rendered with this model, if any.
+    pub fn object_kind(self) -> Option<ObjectKind> {
+        match self {
+            Model::Tree => Some(ObjectKind::Tree),
+            Model::Mushroom => Some(ObjectKind::Mushroom),
+            _ => None,
+        }
+    }
+
+    /// The model used to render a kind of object.
+    pub fn of_object(kind: ObjectKind) -> Model {
+        match kind {
+            ObjectKind::Tree => Model::Tree,
+            ObjectKind::Mushroom => Model::Mushroom,
+        }
+    }
 }
 
 /// This entity can control its movement within the world.
@@ -52,6 +102,8 @@ pub struct Movement {
     pub direction: Direction,
     /// The maximum speed of the entity.
     pub speed: f32,
+    /// Whether the entity is blocking, which slows it down.
+    pub stance: Stance,
 }
 
 /// This entity can interact with the world.
@@ -108,8 +160,138 @@ impl Health {
 /// This entity is an entity that deals damage.
 #[derive(Debug, Clone)]
 pub struct Projectile {
+    /// The kind of projectile.
+    pub kind: ProjectileKind,
     /// The amount of damage dealt upon impact.
     pub damage: u32,
+    /// The entity that launched the projectile.
+    pub owner: Option<Entity>,
+}
+
+impl Replicate for Projectile {
+    const ID: ComponentId = ComponentId(2);
+
+    type State = ProjectileKind;
+
+    fn pack(&self) -> Self::State {
+        self.kind
+    }
+
+    fn unpack(kind: Self::State) -> Self {
+        Projectile {
+            kind,
+            damage: ProjectileType::of(kind).damage,
+            owner: None,
+        }
+    }
+
+    fn apply(self, world: &mut World, entity: Entity) {
+        // the owner is not replicated, so keep the one we already know about
+        if let Some(mut projectile) = world.get_component_mut::<Projectile>(entity) {
+            projectile.kind = self.kind;
+            return;
+        }
+
+        world.add_component(entity, self);
+    }
+}
+
+/// Entities on the same team are not damaged by each other's projectiles.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Team(pub u32);
+
+/// How a projectile was thrown, kept until it lands so that the throw can be recorded.
+#[derive(Debug, Copy, Clone)]
+pub struct Launch {
+    /// Where the projectile was thrown from.
+    pub origin: Point3<f32>,
+    /// Where the projectile was aimed.
+    pub target: Point3<f32>,
+    /// The initial speed of the projectile.
+    pub speed: f32,
+}
+
+/// The time at which an entity may throw each kind of projectile again.
+#[derive(Debug, Clone, Default)]
+pub struct ThrowCooldowns {
+    /// The `WorldTime` at which each kind of projectile is ready.
+    pub ready: HashMap<ProjectileKind, f32>,
+}
+
+impl ThrowCooldowns {
+    /// The number of seconds until a kind of projectile may be thrown again.
+    pub fn remaining(&self, kind: ProjectileKind, now: f32) -> f32 {
+        match self.ready.get(&kind) {
+            Some(ready) => (ready - now).max(0.0),
+            None => 0.0,
+        }
+    }
+}
+
+/// A human readable name of an entity, such as "tree#42" or "P3:player#7", used in logs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugName(pub String);
+
+impl DebugName {
+    /// Name an entity after what it is and its id.
+    pub fn new(label: &str, id: EntityId) -> DebugName {
+        DebugName(format!("{}#{}", label, id.0))
+    }
+
+    /// Name the entity of a player.
+    pub fn player(owner: protocol::PlayerId, id: EntityId) -> DebugName {
+        DebugName(format!("{}:player#{}", owner, id.0))
+    }
+
+    /// Get the name of an entity, falling back to its id if it has no name.
+    pub fn of(world: &World, entity: Entity) -> String {
+        if let Some(name) = world.get_component::<DebugName>(entity) {
+            return name.0.clone();
+        }
+
+        match world.get_component::<EntityId>(entity) {
+            Some(id) => format!("#{}", id.0),
+            None => format!("{:?}", entity),
+        }
+    }
+}
+
+impl Display for DebugName {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Replicate for DebugName {
+    const ID: ComponentId = ComponentId(3);
+
+    type State = String;
+
+    fn pack(&self) -> Self::State {
+        self.0.clone()
+    }
+
+    fn unpack(name: Self::State) -> Self {
+        DebugName(name)
+    }
+}
+
+/// An area that affects all entities inside it for a limited time.
+#[derive(Debug, Clone)]
+pub struct Field {
+    /// The kind of projectile that created the field, which decides its effect.
+    pub source: ProjectileKind,
+    /// Entities within this distance from the center are affected.
+    pub radius: f32,
+    /// The number of seconds until the field disappears.
+    pub remaining: f32,
+    /// The entity that launched the projectile that created the field.
+    pub owner: Option<Entity>,
+    /// The team of the owner when the field was created. Entities on the same team are not
+    /// damaged by the field.
+    pub team: Option<Team>,
+    /// The number of seconds until the field deals damage again.
+    pub next_damage: f32,
 }
 
 /// This entity can collide with other entities.
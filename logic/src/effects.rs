@@ -0,0 +1,97 @@
+//! Temporary effects on entities, such as being slowed down or protected from damage.
+
+pub use protocol::{StatusEffect, StatusEffectKind};
+
+/// How a new effect is combined with an active effect of the same kind.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stacking {
+    /// Keep the strongest magnitude and the longest remaining duration.
+    Strongest,
+    /// Keep the strongest magnitude, and add the durations together.
+    Extend,
+}
+
+/// Seconds a newly spawned player is protected from damage.
+pub const SPAWN_PROTECTION: f32 = 3.0;
+
+/// The status effects currently affecting an entity.
+#[derive(Debug, Clone, Default)]
+pub struct StatusEffects {
+    /// At most one effect of each kind.
+    pub active: Vec<StatusEffect>,
+}
+
+/// How effects of a kind are combined.
+pub fn stacking(kind: StatusEffectKind) -> Stacking {
+    match kind {
+        StatusEffectKind::Slowed => Stacking::Strongest,
+        StatusEffectKind::Stunned => Stacking::Strongest,
+        StatusEffectKind::SpawnProtected => Stacking::Strongest,
+        StatusEffectKind::SpeedBoost => Stacking::Extend,
+    }
+}
+
+impl StatusEffects {
+    /// Create a set of status effects containing only the given effects.
+    pub fn with(effects: impl IntoIterator<Item = StatusEffect>) -> StatusEffects {
+        let mut status = StatusEffects::default();
+        for effect in effects {
+            status.apply(effect);
+        }
+        status
+    }
+
+    /// Add an effect, combining it with any active effect of the same kind.
+    pub fn apply(&mut self, effect: StatusEffect) {
+        match self
+            .active
+            .iter_mut()
+            .find(|active| active.kind == effect.kind)
+        {
+            None => self.active.push(effect),
+            Some(active) => {
+                active.magnitude = active.magnitude.max(effect.magnitude);
+                active.remaining = match stacking(effect.kind) {
+                    Stacking::Strongest => active.remaining.max(effect.remaining),
+                    Stacking::Extend => active.remaining + effect.remaining,
+                };
+            }
+        }
+    }
+
+    /// Get the active effect of a specific kind.
+    pub fn get(&self, kind: StatusEffectKind) -> Option<&StatusEffect> {
+        self.active.iter().find(|effect| effect.kind == kind)
+    }
+
+    /// Is an effect of a specific kind active?
+    pub fn has(&self, kind: StatusEffectKind) -> bool {
+        self.get(kind).is_some()
+    }
+
+    /// Advance time, removing all effects that wore off.
+    pub fn tick(&mut self, dt: f32) {
+        for effect in &mut self.active {
+            effect.remaining -= dt;
+        }
+        self.active.retain(|effect| effect.remaining > 0.0);
+    }
+
+    /// The factor the speed of the entity is multiplied with.
+    pub fn speed_multiplier(&self) -> f32 {
+        if self.has(StatusEffectKind::Stunned) {
+            return 0.0;
+        }
+
+        let magnitude = |kind| self.get(kind).map(|effect| effect.magnitude).unwrap_or(0.0);
+        let slowed = (1.0 - magnitude(StatusEffectKind::Slowed)).max(0.0);
+        let boosted = 1.0 + magnitude(StatusEffectKind::SpeedBoost);
+
+        slowed * boosted
+    }
+
+    /// Can the entity take damage?
+    pub fn is_vulnerable(&self) -> bool {
+        !self.has(StatusEffectKind::SpawnProtected)
+    }
+}
@@ -1,31 +1,105 @@
 use cgmath::{prelude::*, Point3};
 use legion::prelude::*;
+use protocol::{EffectTag, EntityId};
 
 use crate::components::*;
+use crate::projectiles::{ProjectileKind, ProjectileType};
+use crate::resources::{EntityEffects, GameRules, WorldTime};
 use crate::tags::Static;
 
-/// Attempts to throw the object held by `entity` towards the `target`.
-pub fn throw(world: &mut World, entity: Entity, target: Point3<f32>) {
+/// The reasons an entity may not be able to throw.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ThrowError {
+    /// The entity is not holding anything to throw.
+    NothingHeld,
+    /// The entity has to wait this many seconds before throwing this kind of projectile again.
+    Cooldown(f32),
+}
+
+/// Attempts to throw the object held by `entity` towards the `target` as a specific kind of
+/// projectile.
+pub fn throw(
+    world: &mut World,
+    entity: Entity,
+    target: Point3<f32>,
+    kind: ProjectileKind,
+) -> Result<(), ThrowError> {
+    let now = world
+        .resources
+        .get::<WorldTime>()
+        .map(|time| time.seconds)
+        .unwrap_or(0.0);
+
+    if let Some(cooldowns) = world.get_component::<ThrowCooldowns>(entity) {
+        let remaining = cooldowns.remaining(kind, now);
+        if remaining > 0.0 {
+            return Err(ThrowError::Cooldown(remaining));
+        }
+    }
+
     let held = world
         .get_component_mut::<WorldInteraction>(entity)
         .unwrap()
         .holding
-        .take();
+        .take()
+        .ok_or(ThrowError::NothingHeld)?;
+
+    let properties = ProjectileType::of(kind);
 
-    if let Some(held) = held {
-        let position = *world.get_component::<Position>(held).unwrap();
-        let delta = target - position.0;
+    let object = world
+        .get_component::<Model>(held)
+        .and_then(|model| model.object_kind());
+    let mass = match (object, world.resources.get::<GameRules>()) {
+        (Some(object), Some(rules)) if rules.mass(object) > 0.0 => rules.mass(object),
+        _ => 1.0,
+    };
 
-        let collision_listener = CollisionListener::new();
+    let position = *world.get_component::<Position>(held).unwrap();
+    let delta = target - position.0;
 
-        let acc = Acceleration([0.0, 0.0, -10.0].into());
-        let time = delta.magnitude() / 30.0;
-        let velocity = Velocity(delta / time - 0.5 * acc.0 * time);
+    let collision_listener = CollisionListener::new();
 
-        world.add_component(held, velocity);
-        world.add_component(held, collision_listener);
-        world.add_component(held, Projectile { damage: 1 });
-        world.add_component(held, acc);
-        world.remove_tag::<Static>(held);
+    let acc = Acceleration([0.0, 0.0, -properties.gravity].into());
+    let time = delta.magnitude() * mass / properties.speed;
+    let velocity = Velocity(delta / time - 0.5 * acc.0 * time);
+
+    world.add_component(held, velocity);
+    world.add_component(
+        held,
+        Launch {
+            origin: position.0,
+            target,
+            speed: velocity.0.magnitude(),
+        },
+    );
+    world.add_component(held, collision_listener);
+    world.add_component(
+        held,
+        Projectile {
+            kind,
+            damage: properties.damage,
+            owner: Some(entity),
+        },
+    );
+    world.add_component(held, acc);
+    world.remove_tag::<Static>(held);
+
+    let thrower = world.get_component::<EntityId>(entity).map(|id| *id);
+    if let (Some(thrower), Some(mut effects)) =
+        (thrower, world.resources.get_mut::<EntityEffects>())
+    {
+        effects.push(thrower, EffectTag::Threw);
+    }
+
+    let ready = now + properties.cooldown;
+    if let Some(mut cooldowns) = world.get_component_mut::<ThrowCooldowns>(entity) {
+        cooldowns.ready.insert(kind, ready);
+        return Ok(());
     }
+
+    let mut cooldowns = ThrowCooldowns::default();
+    cooldowns.ready.insert(kind, ready);
+    world.add_component(entity, cooldowns);
+
+    Ok(())
 }
@@ -0,0 +1,157 @@
+//! Remember the state of the world during previous ticks.
+//!
+//! This makes it possible to look back in time, for example to check what a player saw when they
+//! performed an action, or to replay the moments leading up to an elimination.
+
+use cgmath::{prelude::*, Point3};
+use legion::prelude::*;
+use protocol::EntityId;
+
+use crate::collision::AlignedBox;
+use crate::components::{Collision, Position};
+
+use std::collections::{HashMap, VecDeque};
+
+/// The state of the world during a number of previous ticks.
+#[derive(Debug)]
+pub struct WorldHistory {
+    /// The maximum number of ticks to remember.
+    length: usize,
+    /// The recorded ticks, oldest first.
+    frames: VecDeque<Frame>,
+}
+
+#[derive(Debug)]
+struct Frame {
+    tick: u32,
+    entities: HashMap<EntityId, EntityState>,
+}
+
+/// The state of a single entity during a previous tick.
+#[derive(Debug, Copy, Clone)]
+pub struct EntityState {
+    pub position: Point3<f32>,
+    pub collision: Option<Collision>,
+}
+
+/// A view of the world as it was during a previous tick.
+#[derive(Debug, Copy, Clone)]
+pub struct HistoricalView<'a> {
+    frame: &'a Frame,
+}
+
+impl WorldHistory {
+    /// Create a history that remembers at most `length` ticks.
+    pub fn new(length: usize) -> WorldHistory {
+        WorldHistory {
+            length,
+            frames: VecDeque::with_capacity(length),
+        }
+    }
+
+    /// The maximum number of ticks remembered.
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Record the state of the world during a tick. Ticks should be recorded in increasing order.
+    /// Once the history is full, the oldest tick is forgotten.
+    pub fn record(&mut self, tick: u32, world: &World) {
+        if self.length == 0 {
+            return;
+        }
+
+        // reuse the allocation of the oldest frame once the history is full
+        let mut frame = if self.frames.len() >= self.length {
+            self.frames.pop_front().unwrap()
+        } else {
+            Frame {
+                tick,
+                entities: HashMap::new(),
+            }
+        };
+
+        frame.tick = tick;
+        frame.entities.clear();
+
+        let query = <(Read<EntityId>, Read<Position>, TryRead<Collision>)>::query();
+        let entities = query
+            .iter_immutable(world)
+            .map(|(id, position, collision)| {
+                let state = EntityState {
+                    position: position.0,
+                    collision: collision.map(|collision| *collision),
+                };
+                (*id, state)
+            });
+
+        frame.entities.extend(entities);
+        self.frames.push_back(frame);
+    }
+
+    /// Get the world as it was during a tick. If that tick was not recorded, the closest earlier
+    /// tick is used instead. Returns `None` if the tick is older than the oldest recorded tick, or
+    /// newer than the newest.
+    pub fn sample(&self, tick: u32) -> Option<HistoricalView<'_>> {
+        let newest = self.frames.back()?.tick;
+        let age = newest.wrapping_sub(tick);
+
+        // the tick is in the future
+        if age > u32::MAX / 2 {
+            return None;
+        }
+
+        self.frames
+            .iter()
+            .rev()
+            .find(|frame| newest.wrapping_sub(frame.tick) >= age)
+            .map(|frame| HistoricalView { frame })
+    }
+
+    /// The oldest tick that can be sampled.
+    pub fn oldest_tick(&self) -> Option<u32> {
+        self.frames.front().map(|frame| frame.tick)
+    }
+
+    /// The newest tick that can be sampled.
+    pub fn newest_tick(&self) -> Option<u32> {
+        self.frames.back().map(|frame| frame.tick)
+    }
+
+    /// Forget all recorded ticks.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+impl<'a> HistoricalView<'a> {
+    /// The tick the view was recorded during.
+    pub fn tick(&self) -> u32 {
+        self.frame.tick
+    }
+
+    /// Get the state of an entity.
+    pub fn entity(&self, id: EntityId) -> Option<&'a EntityState> {
+        self.frame.entities.get(&id)
+    }
+
+    pub fn position(&self, id: EntityId) -> Option<Point3<f32>> {
+        self.entity(id).map(|state| state.position)
+    }
+
+    pub fn collision(&self, id: EntityId) -> Option<Collision> {
+        self.entity(id).and_then(|state| state.collision)
+    }
+
+    /// The bounding box of an entity in world space, if it can collide.
+    pub fn bounds(&self, id: EntityId) -> Option<AlignedBox> {
+        let state = self.entity(id)?;
+        let collision = state.collision?;
+        Some(collision.bounds.translate(state.position.to_vec()))
+    }
+
+    /// Iterate over all entities that existed during the tick.
+    pub fn entities(&self) -> impl Iterator<Item = (EntityId, &'a EntityState)> + 'a {
+        self.frame.entities.iter().map(|(id, state)| (*id, state))
+    }
+}
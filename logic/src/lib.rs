@@ -3,11 +3,15 @@
 pub extern crate legion;
 
 pub mod components;
+pub mod effects;
 pub mod events;
+pub mod history;
+pub mod projectiles;
 pub mod resources;
 pub mod snapshot;
 pub mod systems;
 pub mod tags;
+pub mod telemetry;
 
 pub mod collision;
 pub mod tile_map;
@@ -15,19 +19,25 @@ pub mod tile_map;
 mod templates;
 
 use legion::entity::Entity;
+use legion::prelude::{IntoQuery, Read};
 use legion::schedule::{Builder as ScheduleBuilder, Schedulable, Schedule};
 use legion::world::World;
 
-use cgmath::Vector3;
+use cgmath::{prelude::*, Point3, Vector3};
 
 use rand::prelude::*;
 
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
 use std::time::{Duration, Instant};
 
-use protocol::PlayerId;
+use protocol::{EntityId, ObjectKind, PlayerId};
 
 use crate::components::{Model, Position};
-use crate::resources::{DeadEntities, EntityAllocator, TimeStep};
+use crate::effects::{StatusEffect, StatusEffectKind, StatusEffects};
+use crate::resources::{
+    DeadEntities, EntityAllocator, EntityEffects, GameRules, Hits, Throws, TimeStep, WorldTime,
+};
 use crate::tags::Player;
 use crate::tile_map::{TileKind, TileMap};
 
@@ -37,14 +47,62 @@ const TREES: usize = 150;
 const MUSHROOMS: usize = 150;
 const SIZE: usize = 30;
 
+/// The health of objects spawned when the world is created.
+const OBJECT_HEALTH: u32 = 3;
+
+/// The downwards acceleration of objects added above the ground.
+const FALL_GRAVITY: f32 = 10.0;
+
 const VOXEL_SIZE: f32 = 1.0 / 16.0;
 
 const TARGET_TICK_RATE: u32 = 120;
 
+/// The least time between two warnings about the executor falling behind.
+const CATCH_UP_WARNING_INTERVAL: Duration = Duration::from_secs(5);
+
 /// An executor that updates the world state using a constistent time step.
 pub struct Executor {
     schedule: Schedule,
     previous_tick: Instant,
+    catch_up: CatchUp,
+    /// Time that has passed but not been simulated yet.
+    backlog: Duration,
+    stats: CatchUpStats,
+    /// The stats when the executor last warned about falling behind.
+    warned: Option<(Instant, CatchUpStats)>,
+}
+
+/// How the executor catches up after falling behind real time, for example after a long frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CatchUp {
+    /// The most ticks simulated during a single call to `Executor::tick`.
+    pub max_ticks: u32,
+    /// What to do with the time that could not be simulated within `max_ticks`.
+    pub policy: CatchUpPolicy,
+}
+
+/// What to do with time that could not be simulated in time.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CatchUpPolicy {
+    /// Skip the time, so that the world jumps ahead to real time.
+    Drop,
+    /// Simulate the time during later ticks, so that the world runs in slow motion until it has
+    /// caught up.
+    SlowMotion,
+    /// Like `SlowMotion`, but skip all time that has not been simulated once it grows beyond a
+    /// limit, so that a world that can never catch up does not fall further and further behind.
+    Limited(Duration),
+}
+
+/// How often, and by how much, the executor has fallen behind since it was created.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct CatchUpStats {
+    /// The number of ticks during which the executor could not simulate all time that passed.
+    pub behind: u64,
+    /// The total time that was skipped.
+    pub dropped: Duration,
+    /// The time that has passed but not been simulated yet.
+    pub backlog: Duration,
 }
 
 /// Different kinds of world presets.
@@ -69,45 +127,209 @@ impl Executor {
         Executor {
             schedule: schedule.build(),
             previous_tick: Instant::now(),
+            catch_up: CatchUp::default(),
+            backlog: Duration::from_secs(0),
+            stats: CatchUpStats::default(),
+            warned: None,
+        }
+    }
+
+    /// Change how the executor catches up after falling behind.
+    pub fn set_catch_up(&mut self, catch_up: CatchUp) {
+        self.catch_up = catch_up;
+    }
+
+    /// How the executor catches up after falling behind.
+    pub fn catch_up(&self) -> CatchUp {
+        self.catch_up
+    }
+
+    /// Let time pass without simulating it, while the world stands still.
+    pub fn pause(&mut self) {
+        self.previous_tick = Instant::now();
+        self.backlog = Duration::from_secs(0);
+    }
+
+    /// How often, and by how much, the executor has fallen behind.
+    pub fn catch_up_stats(&self) -> CatchUpStats {
+        CatchUpStats {
+            backlog: self.backlog,
+            ..self.stats
         }
     }
 
-    /// Update the world state a number of ticks.
+    /// Update the world state a number of ticks. The time that has passed in the world is scaled
+    /// by the time scale of the `GameRules` in effect, so changing the time scale never causes
+    /// time to jump.
     pub fn tick(&mut self, world: &mut World) {
         let now = Instant::now();
         if let Some(elapsed) = now.checked_duration_since(self.previous_tick) {
+            let time_scale = world
+                .resources
+                .get::<GameRules>()
+                .map(|rules| rules.time_scale)
+                .unwrap_or(1.0);
+            let elapsed = elapsed.mul_f32(time_scale.max(0.0));
+
             let target_delay = Duration::from_secs(1) / TARGET_TICK_RATE;
+            let max_ticks = self.catch_up.max_ticks.max(1);
+            let mut remaining = self.backlog + elapsed;
 
             let mut single_tick = |dt| {
                 let time_step = TimeStep::from_duration(dt);
                 world.resources.insert(time_step);
+                if let Some(mut time) = world.resources.get_mut::<WorldTime>() {
+                    time.seconds += time_step.secs_f32();
+                }
                 self.schedule.execute(world);
             };
 
-            let mut remaining = elapsed;
-            while let Some(rest) = remaining.checked_sub(target_delay) {
+            let mut simulated = Duration::from_secs(0);
+            let mut ticks = 0;
+            while ticks < max_ticks {
+                match remaining.checked_sub(target_delay) {
+                    None => break,
+                    Some(rest) => remaining = rest,
+                }
                 single_tick(target_delay);
-                // fast forward if we are too far behind
-                remaining = if rest.as_secs() >= 1 {
-                    Duration::from_secs(0)
-                } else {
-                    rest
+                simulated += target_delay;
+                ticks += 1;
+            }
+
+            if remaining < target_delay {
+                single_tick(remaining);
+                simulated += remaining;
+                self.backlog = Duration::from_secs(0);
+            } else {
+                self.stats.behind += 1;
+
+                let drop = match self.catch_up.policy {
+                    CatchUpPolicy::Drop => true,
+                    CatchUpPolicy::SlowMotion => false,
+                    CatchUpPolicy::Limited(limit) => remaining > limit,
                 };
+
+                if drop {
+                    self.stats.dropped += remaining;
+                    self.backlog = Duration::from_secs(0);
+                } else {
+                    self.backlog = remaining;
+                }
+
+                self.warn_behind(now);
             }
-            single_tick(remaining);
 
-            world.resources.insert(TimeStep::from_duration(elapsed));
+            world.resources.insert(TimeStep::from_duration(simulated));
             self.previous_tick = now;
         }
     }
+
+    /// Log how far behind the executor has fallen since the previous warning, at most once every
+    /// few seconds.
+    fn warn_behind(&mut self, now: Instant) {
+        let stats = self.catch_up_stats();
+        let previous = match self.warned {
+            Some((time, _)) if now.duration_since(time) < CATCH_UP_WARNING_INTERVAL => return,
+            Some((_, previous)) => previous,
+            None => CatchUpStats::default(),
+        };
+
+        log::warn!(
+            "simulation fell behind during {} ticks: skipped {:.1} ms, {:.1} ms left to catch up",
+            stats.behind - previous.behind,
+            1000.0 * (stats.dropped - previous.dropped).as_secs_f64(),
+            1000.0 * stats.backlog.as_secs_f64(),
+        );
+
+        self.warned = Some((now, stats));
+    }
+}
+
+impl Default for CatchUp {
+    fn default() -> Self {
+        CatchUp {
+            max_ticks: TARGET_TICK_RATE,
+            policy: CatchUpPolicy::Limited(Duration::from_secs(1)),
+        }
+    }
+}
+
+impl Display for CatchUpPolicy {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CatchUpPolicy::Drop => write!(f, "drop"),
+            CatchUpPolicy::SlowMotion => write!(f, "slow-motion"),
+            CatchUpPolicy::Limited(limit) => write!(f, "limited:{}", limit.as_secs_f32()),
+        }
+    }
+}
+
+impl FromStr for CatchUpPolicy {
+    type Err = String;
+
+    /// Parse `drop`, `slow-motion` or `limited:<seconds>`.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "drop" => Ok(CatchUpPolicy::Drop),
+            "slow-motion" => Ok(CatchUpPolicy::SlowMotion),
+            _ => {
+                let seconds = text
+                    .strip_prefix("limited:")
+                    .and_then(|seconds| seconds.parse::<f32>().ok())
+                    .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+                    .ok_or_else(|| {
+                        format!(
+                            "expected `drop`, `slow-motion` or `limited:<seconds>`, found `{}`",
+                            text
+                        )
+                    })?;
+                Ok(CatchUpPolicy::Limited(Duration::from_secs_f32(seconds)))
+            }
+        }
+    }
 }
 
 /// Creates all the required resources in the world.
 pub fn create_world(kind: WorldKind) -> World {
+    create_world_with(
+        kind,
+        EntityAllocator::default(),
+        DeadEntities::default(),
+        WorldTime::default(),
+    )
+}
+
+/// Create a world to replace another one. Entity ids continue where the old world left off, and
+/// every entity of the old world is dead in the new one, so that an id never refers to entities
+/// in both worlds. Time also continues, but the rules are reset.
+pub fn create_world_after(old: &World, kind: WorldKind) -> World {
+    let allocator = old.resources.get::<EntityAllocator>().unwrap().clone();
+
+    let mut dead = old.resources.get::<DeadEntities>().unwrap().clone();
+    let query = <Read<EntityId>>::query();
+    dead.entities.extend(query.iter_immutable(old).map(|id| *id));
+
+    let time = *old.resources.get::<WorldTime>().unwrap();
+
+    create_world_with(kind, allocator, dead, time)
+}
+
+fn create_world_with(
+    kind: WorldKind,
+    allocator: EntityAllocator,
+    dead: DeadEntities,
+    time: WorldTime,
+) -> World {
     let mut world = World::new();
 
     world.resources.insert(TimeStep::default());
-    world.resources.insert(DeadEntities::default());
+    world.resources.insert(dead);
+    world.resources.insert(Hits::default());
+    world.resources.insert(Throws::default());
+    world.resources.insert(EntityEffects::default());
+    world.resources.insert(time);
+    world.resources.insert(allocator);
+    world.resources.insert(GameRules::default());
 
     let mut map = TileMap::island(SIZE as i32);
     spawn_invisible_walls(&mut world, &map);
@@ -126,6 +348,7 @@ pub fn create_world(kind: WorldKind) -> World {
 /// Schedule all game logic systems.
 pub fn add_systems(builder: ScheduleBuilder, set: SystemSet) -> ScheduleBuilder {
     let base = builder
+        .add_system(systems::effects::system())
         .add_system(systems::movement::system())
         .add_system(systems::acceleration::system())
         .add_system(systems::tile_interaction::system())
@@ -134,7 +357,9 @@ pub fn add_systems(builder: ScheduleBuilder, set: SystemSet) -> ScheduleBuilder
 
     match set {
         SystemSet::NonDestructive => base,
-        SystemSet::Everything => base.add_system(systems::attack::system()),
+        SystemSet::Everything => base
+            .add_system(systems::attack::system())
+            .add_system(systems::fields::system()),
     }
 }
 
@@ -158,10 +383,16 @@ pub fn add_player(world: &mut World, owner: PlayerId) -> Entity {
         collision: templates::collision(Model::Player),
         health: components::Health::with_max(3),
         owner: components::Owner(owner),
+        effects: StatusEffects::with(Some(StatusEffect {
+            kind: StatusEffectKind::SpawnProtected,
+            remaining: effects::SPAWN_PROTECTION,
+            magnitude: 0.0,
+        })),
     };
 
     let entity = world.insert(tags, Some(()))[0];
     template.insert(world, entity);
+    world.add_component(entity, components::DebugName::player(owner, id));
     entity
 }
 
@@ -176,31 +407,63 @@ fn spawn_objects(world: &mut World, map: &mut TileMap) {
     let mut rng = rand::thread_rng();
     tiles.shuffle(&mut rng);
 
-    let entity_allocator = world
-        .resources
-        .get_or_insert_with(EntityAllocator::default)
-        .unwrap()
-        .clone();
-
     let mut tiles = tiles.into_iter();
-    let mut spawn = |count, model| {
+    let mut spawn = |count, kind| {
         for (coord, _) in tiles.by_ref().take(count) {
-            let entity = world.insert((tags::Static,), Some(()))[0];
             let offset = Vector3::new(rng.gen_range(-0.5, 0.5), rng.gen_range(-0.5, 0.5), 0.0);
-            let template = templates::Object {
-                id: entity_allocator.allocate(),
-                position: Position(coord.to_world() + offset),
-                model,
-                collision: templates::collision(model),
-                health: components::Health::with_max(3),
-                breakable: Some(components::Breakable::default()),
-            };
-            template.insert(world, entity);
+            add_object(world, kind, coord.to_world() + offset, OBJECT_HEALTH);
         }
     };
 
-    spawn(TREES, Model::Tree);
-    spawn(MUSHROOMS, Model::Mushroom);
+    spawn(TREES, ObjectKind::Tree);
+    spawn(MUSHROOMS, ObjectKind::Mushroom);
+}
+
+/// Add an object to the world. Objects added above the ground fall down, and may be picked up once
+/// they land.
+pub fn add_object(
+    world: &mut World,
+    kind: ObjectKind,
+    position: Point3<f32>,
+    health: u32,
+) -> Entity {
+    let id = world
+        .resources
+        .get_or_insert_with(EntityAllocator::default)
+        .unwrap()
+        .allocate();
+
+    let model = Model::of_object(kind);
+    let falling = position.z > 0.0;
+
+    let entity = if falling {
+        world.insert((), Some(()))[0]
+    } else {
+        world.insert((tags::Static,), Some(()))[0]
+    };
+
+    let label = format!("{:?}", model).to_lowercase();
+    world.add_component(entity, components::DebugName::new(&label, id));
+
+    let template = templates::Object {
+        id,
+        position: Position(position),
+        model,
+        collision: templates::collision(model),
+        health: components::Health::with_max(health),
+        breakable: Some(components::Breakable::default()),
+    };
+    template.insert(world, entity);
+
+    if falling {
+        world.add_component(entity, components::Velocity(Vector3::zero()));
+        world.add_component(
+            entity,
+            components::Acceleration([0.0, 0.0, -FALL_GRAVITY].into()),
+        );
+    }
+
+    entity
 }
 
 /// Spawn invisible walls over water tiles.
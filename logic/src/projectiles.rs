@@ -0,0 +1,119 @@
+//! The different kinds of projectiles and their properties.
+
+pub use protocol::ProjectileKind;
+
+/// The properties shared by all projectiles of the same kind.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ProjectileType {
+    /// The amount of damage dealt upon impact.
+    pub damage: u32,
+    /// The horizontal distance travelled per second.
+    pub speed: f32,
+    /// The downwards acceleration during flight.
+    pub gravity: f32,
+    /// The number of seconds before another projectile of this kind may be thrown.
+    pub cooldown: f32,
+    /// The field left behind upon impact.
+    pub field: Option<FieldType>,
+    /// The tint of the thrown entity.
+    pub color: [f32; 3],
+}
+
+/// The properties of a field left behind by a projectile.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FieldType {
+    /// What happens to entities inside the field.
+    pub effect: FieldEffect,
+    /// Entities within this distance from the impact are affected.
+    pub radius: f32,
+    /// The number of seconds the field lasts.
+    pub duration: f32,
+    /// The color of the field's decal.
+    pub color: [f32; 3],
+}
+
+/// What happens to entities inside a field.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FieldEffect {
+    /// Deal damage at a fixed interval.
+    Damage {
+        /// The damage dealt each interval.
+        points: u32,
+        /// The number of seconds between each time damage is dealt.
+        interval: f32,
+    },
+    /// Slow down movement.
+    Slow {
+        /// The fraction of the normal speed affected entities move at.
+        factor: f32,
+    },
+}
+
+const SNOWBALL: ProjectileType = ProjectileType {
+    damage: 1,
+    speed: 30.0,
+    gravity: 10.0,
+    cooldown: 0.25,
+    field: None,
+    color: [0.0; 3],
+};
+
+const ICEBALL: ProjectileType = ProjectileType {
+    damage: 2,
+    speed: 60.0,
+    gravity: 2.0,
+    cooldown: 2.0,
+    field: None,
+    color: [0.0, 0.2, 0.5],
+};
+
+const SLUSHBALL: ProjectileType = ProjectileType {
+    damage: 1,
+    speed: 20.0,
+    gravity: 10.0,
+    cooldown: 3.0,
+    field: Some(FieldType {
+        effect: FieldEffect::Slow { factor: 0.5 },
+        radius: 2.0,
+        duration: 3.0,
+        color: [0.4, 0.5, 0.6],
+    }),
+    color: [0.2, 0.3, 0.1],
+};
+
+const SNOWBOMB: ProjectileType = ProjectileType {
+    damage: 1,
+    speed: 25.0,
+    gravity: 10.0,
+    cooldown: 5.0,
+    field: Some(FieldType {
+        effect: FieldEffect::Damage {
+            points: 1,
+            interval: 0.5,
+        },
+        radius: 1.5,
+        duration: 1.5,
+        color: [0.9, 0.3, 0.1],
+    }),
+    color: [0.4, 0.1, 0.0],
+};
+
+impl ProjectileType {
+    /// All kinds of projectiles, in the order they are selected.
+    pub const KINDS: &'static [ProjectileKind] = &[
+        ProjectileKind::Snowball,
+        ProjectileKind::Iceball,
+        ProjectileKind::Slushball,
+        ProjectileKind::Snowbomb,
+    ];
+
+    /// Get the properties of a kind of projectile.
+    pub fn of(kind: ProjectileKind) -> &'static ProjectileType {
+        match kind {
+            ProjectileKind::Snowball => &SNOWBALL,
+            ProjectileKind::Iceball => &ICEBALL,
+            ProjectileKind::Slushball => &SLUSHBALL,
+            ProjectileKind::Snowbomb => &SNOWBOMB,
+        }
+    }
+}
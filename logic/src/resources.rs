@@ -1,12 +1,49 @@
-use protocol::snapshot::EntityId;
+use cgmath::Point3;
+use legion::entity::Entity;
+use protocol::snapshot::{EffectTag, EntityEffect, EntityId, ResourceId};
+use protocol::ObjectKind;
+use rabbit::{PackBits, UnpackBits};
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::Duration;
 use std::sync::Arc;
 
+use crate::snapshot::ReplicatedResource;
+use crate::telemetry::ThrowRecord;
+use crate::tile_map::TileKind;
+
 /// The amount of time stepped through in this tick.
 #[derive(Debug, Copy, Clone)]
 pub struct TimeStep(f32);
 
+/// The amount of time that has passed since the world was created.
+#[derive(Debug, Copy, Clone, Default, PackBits, UnpackBits)]
+pub struct WorldTime {
+    pub seconds: f32,
+}
+
+/// Rules that affect how the world is simulated, decided by the server.
+#[derive(Debug, Copy, Clone, PartialEq, PackBits, UnpackBits)]
+pub struct GameRules {
+    /// The number of times per second the server updates the world and sends snapshots.
+    pub tick_rate: u32,
+    /// How fast time passes in the world relative to real time.
+    pub time_scale: f32,
+    /// The fraction of the overlap between two players that is resolved every tick, in the range
+    /// 0 to 1. Lower values let players overlap more before being pushed apart.
+    pub player_softness: f32,
+    /// The maximum distance per second a player may be pushed by other players.
+    pub player_push: f32,
+    /// The mass of a tree when held or thrown, relative to a mushroom of mass 1.
+    pub tree_mass: f32,
+    /// The mass of a mushroom when held or thrown.
+    pub mushroom_mass: f32,
+    /// The fraction of their speed entities walk at on sand.
+    pub sand_speed: f32,
+    /// The fraction of the difference between the velocity of an entity on ice and the velocity it
+    /// is walking at that is made up every second. Lower values make entities slide further.
+    pub ice_grip: f32,
+}
+
 /// Manages the creation of new `EntityId`s.
 #[derive(Debug, Clone)]
 pub struct EntityAllocator {
@@ -21,6 +58,38 @@ pub struct DeadEntities {
     pub entities: Vec<EntityId>,
 }
 
+/// A list of all hits dealt by projectiles since the list was last cleared.
+#[derive(Debug, Clone, Default)]
+pub struct Hits {
+    pub hits: Vec<Hit>,
+}
+
+/// A list of all projectiles that landed since the list was last cleared.
+#[derive(Debug, Clone, Default)]
+pub struct Throws {
+    pub throws: Vec<ThrowRecord>,
+}
+
+/// Effects that happened to entities since the last snapshot was made. Sent to clients with the
+/// next snapshot, then cleared.
+#[derive(Debug, Clone, Default)]
+pub struct EntityEffects {
+    pub effects: Vec<EntityEffect>,
+}
+
+/// A projectile hit an entity with health.
+#[derive(Debug, Clone)]
+pub struct Hit {
+    /// The entity that launched the projectile.
+    pub attacker: Option<Entity>,
+    /// The entity that was hit.
+    pub target: EntityId,
+    /// Where the entity was hit.
+    pub position: Point3<f32>,
+    /// The amount of damage that was dealt.
+    pub damage: u32,
+}
+
 impl Default for TimeStep {
     fn default() -> Self {
         TimeStep(0.0)
@@ -39,6 +108,63 @@ impl TimeStep {
     }
 }
 
+impl EntityEffects {
+    /// Record that something happened to an entity.
+    pub fn push(&mut self, entity: EntityId, tag: EffectTag) {
+        self.effects.push(EntityEffect { entity, tag });
+    }
+}
+
+impl ReplicatedResource for WorldTime {
+    const ID: ResourceId = ResourceId(0);
+}
+
+impl ReplicatedResource for GameRules {
+    const ID: ResourceId = ResourceId(1);
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        GameRules {
+            tick_rate: 60,
+            time_scale: 1.0,
+            player_softness: 0.5,
+            player_push: 4.0,
+            tree_mass: 1.5,
+            mushroom_mass: 1.0,
+            sand_speed: 0.8,
+            ice_grip: 2.0,
+        }
+    }
+}
+
+impl GameRules {
+    /// The mass of a kind of object. Heavier objects are thrown slower, along higher arcs.
+    pub fn mass(&self, kind: ObjectKind) -> f32 {
+        match kind {
+            ObjectKind::Tree => self.tree_mass,
+            ObjectKind::Mushroom => self.mushroom_mass,
+        }
+    }
+
+    /// The fraction of their speed entities walk at on a kind of tile.
+    pub fn tile_speed(&self, kind: TileKind) -> f32 {
+        match kind {
+            TileKind::Sand => self.sand_speed,
+            TileKind::Water | TileKind::Grass | TileKind::Snow | TileKind::Ice => 1.0,
+        }
+    }
+
+    /// How fast entities on a kind of tile reach the velocity they are walking at, or `None` if
+    /// they reach it at once, see `ice_grip`.
+    pub fn tile_grip(&self, kind: TileKind) -> Option<f32> {
+        match kind {
+            TileKind::Ice => Some(self.ice_grip),
+            TileKind::Water | TileKind::Grass | TileKind::Sand | TileKind::Snow => None,
+        }
+    }
+}
+
 impl Default for EntityAllocator {
     fn default() -> Self {
         EntityAllocator { next: Arc::new(AtomicU32::new(1)) }
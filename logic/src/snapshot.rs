@@ -1,31 +1,178 @@
+use cgmath::Point3;
 use legion::prelude::*;
+use legion::resource::Resource;
+use legion::storage::Component;
 
 use crate::components::*;
-use crate::resources::DeadEntities;
+use crate::effects::StatusEffects;
+use crate::resources::{DeadEntities, EntityEffects, GameRules, WorldTime};
 use crate::tags;
 use crate::templates;
 
 use std::collections::{hash_map::Entry, HashMap};
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
 
-use protocol::{Entity as PEntity, EntityId, EntityKind, Object, ObjectKind, Player, Snapshot};
+use protocol::{
+    ComponentId, ComponentState, Entity as PEntity, EntityId, EntityKind, Field as PField, Object,
+    ObjectKind, Player, ProjectileKind, ResourceId, ResourceState, Snapshot, WorldState,
+};
+use rabbit::{PackBits, UnpackBits};
+
+/// A replicated resource is sent again after this many snapshots even if it did not change, in
+/// case the snapshot that contained the change was lost.
+const RESOURCE_RESEND_INTERVAL: u32 = 60;
 
 /// Store a mapping from network entities to local entity ids.
-#[derive(Debug, Default)]
 pub struct SnapshotEncoder {
     pub mapping: HashMap<EntityId, Entity>,
+
+    /// Components that are included in snapshots, in addition to those of players and objects.
+    components: Vec<Box<dyn ComponentReplicator>>,
+    /// Resources that are included in snapshots.
+    resources: Vec<Box<dyn ResourceReplicator>>,
+    /// The resources included in the previous delta snapshots.
+    sent_resources: HashMap<ResourceId, SentResource>,
+}
+
+/// A component whose state is included in snapshots.
+pub trait Replicate: Component + Sized {
+    /// Uniquely identifies the component within a snapshot.
+    const ID: ComponentId;
+
+    /// The representation of the component sent over the network.
+    type State: PackBits + UnpackBits;
+
+    /// Convert the component into its network representation.
+    fn pack(&self) -> Self::State;
+
+    /// Convert the network representation back into a component.
+    fn unpack(state: Self::State) -> Self;
+
+    /// Apply a component received in a snapshot to an entity. Replaces the existing component by
+    /// default.
+    fn apply(self, world: &mut World, entity: Entity) {
+        world.add_component(entity, self);
+    }
+}
+
+/// A legion resource whose state is included in snapshots.
+pub trait ReplicatedResource: Resource + PackBits + UnpackBits {
+    /// Uniquely identifies the resource within a snapshot.
+    const ID: ResourceId;
+}
+
+/// Encodes and decodes a specific type of resource.
+trait ResourceReplicator: Send + Sync {
+    fn id(&self) -> ResourceId;
+
+    /// Pack the resource, if it exists.
+    fn encode(&self, resources: &Resources) -> Option<Vec<u8>>;
+
+    /// Replace the resource with the packed data.
+    fn decode(&self, resources: &mut Resources, data: &[u8]) -> Result<(), rabbit::Error>;
+}
+
+/// Encodes and decodes a specific type of component.
+trait ComponentReplicator: Send + Sync {
+    fn id(&self) -> ComponentId;
+
+    /// Pack the component of an entity, if it has one.
+    fn encode(&self, world: &World, entity: Entity) -> Option<Vec<u8>>;
+
+    /// Apply the packed component to an entity.
+    fn decode(&self, world: &mut World, entity: Entity, data: &[u8]) -> Result<(), rabbit::Error>;
+}
+
+struct Replicator<T>(PhantomData<fn() -> T>);
+
+struct SentResource {
+    data: Vec<u8>,
+    /// Number of snapshots since the resource was sent.
+    age: u32,
 }
 
 /// Configuration options when restoring a snapshot.
 pub struct RestoreConfig {
-    /// The player that is currently being controlled by this logic instance.
-    pub active_player: Option<Entity>,
+    /// The players that are currently being controlled by this logic instance.
+    pub active_players: Vec<Entity>,
+}
+
+/// The entities that were affected when a snapshot was restored.
+#[derive(Debug, Clone, Default)]
+pub struct RestoreReport {
+    /// Entities that did not exist before the snapshot.
+    pub spawned: Vec<RestoredEntity>,
+    /// Entities that existed before the snapshot and were updated.
+    pub updated: Vec<RestoredEntity>,
+    /// Entities that were deleted, as they were when they were deleted.
+    pub despawned: Vec<RestoredEntity>,
+}
+
+/// An entity affected by a restored snapshot.
+#[derive(Debug, Copy, Clone)]
+pub struct RestoredEntity {
+    pub id: EntityId,
+    pub entity: Entity,
+    pub kind: RestoredKind,
+    pub position: Point3<f32>,
+}
+
+/// The kind of an entity affected by a restored snapshot.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RestoredKind {
+    Player,
+    Object(ObjectKind),
+    Field(ProjectileKind),
 }
 
 impl SnapshotEncoder {
     pub fn new() -> Self {
-        SnapshotEncoder {
+        let mut encoder = SnapshotEncoder {
             mapping: HashMap::new(),
+            components: Vec::new(),
+            resources: Vec::new(),
+            sent_resources: HashMap::new(),
+        };
+
+        encoder.register_component::<Velocity>();
+        encoder.register_component::<Acceleration>();
+        encoder.register_component::<Projectile>();
+
+        encoder.register_resource::<WorldTime>();
+        encoder.register_resource::<GameRules>();
+
+        encoder
+    }
+
+    /// Include the `DebugName` of entities in all future snapshots, so that both ends of a
+    /// connection refer to entities by the same names in their logs.
+    pub fn replicate_debug_names(&mut self) {
+        self.register_component::<DebugName>();
+    }
+
+    /// Include a component in all future snapshots.
+    pub fn register_component<T: Replicate>(&mut self) {
+        if self
+            .components
+            .iter()
+            .any(|component| component.id() == T::ID)
+        {
+            log::warn!("component with id {} registered twice", T::ID.0);
+            return;
         }
+
+        self.components.push(Box::new(Replicator::<T>(PhantomData)));
+    }
+
+    /// Include a resource in all future snapshots.
+    pub fn register_resource<T: ReplicatedResource>(&mut self) {
+        if self.resources.iter().any(|resource| resource.id() == T::ID) {
+            log::warn!("resource with id {} registered twice", T::ID.0);
+            return;
+        }
+
+        self.resources.push(Box::new(Replicator::<T>(PhantomData)));
     }
 
     /// Update the network -> ECS entity mapping to match the current state.
@@ -37,34 +184,182 @@ impl SnapshotEncoder {
             })
     }
 
-    /// Make a snapshot of the current world state.
+    /// Make a snapshot of the current world state, including every replicated resource.
     pub fn make_snapshot(&self, world: &World) -> Snapshot {
+        let resources = self
+            .resources
+            .iter()
+            .filter_map(|resource| {
+                let data = resource.encode(&world.resources)?;
+                Some(ResourceState {
+                    id: resource.id(),
+                    data,
+                })
+            })
+            .collect();
+
+        Snapshot {
+            entities: self.entities(world),
+            world: WorldState { resources },
+            effects: Vec::new(),
+        }
+    }
+
+    /// Make a snapshot of the current world state, only including the resources that changed since
+    /// the previous delta snapshot, and the effects recorded since the `EntityEffects` were last
+    /// cleared.
+    pub fn make_delta_snapshot(&mut self, world: &World) -> Snapshot {
+        let mut resources = Vec::new();
+
+        for resource in &self.resources {
+            let id = resource.id();
+            let data = match resource.encode(&world.resources) {
+                Some(data) => data,
+                None => continue,
+            };
+
+            match self.sent_resources.get_mut(&id) {
+                Some(sent) if sent.data == data && sent.age < RESOURCE_RESEND_INTERVAL => {
+                    sent.age += 1;
+                }
+                _ => {
+                    let sent = SentResource {
+                        data: data.clone(),
+                        age: 0,
+                    };
+                    self.sent_resources.insert(id, sent);
+                    resources.push(ResourceState { id, data });
+                }
+            }
+        }
+
+        let effects = world
+            .resources
+            .get::<EntityEffects>()
+            .map(|effects| effects.effects.clone())
+            .unwrap_or_default();
+
+        Snapshot {
+            entities: self.entities(world),
+            world: WorldState { resources },
+            effects,
+        }
+    }
+
+    /// Extract all entities in the world.
+    fn entities(&self, world: &World) -> Vec<PEntity> {
         let mut entities = Vec::new();
-        entities.extend(players(world));
-        entities.extend(objects(world));
+
+        let entities_with_components = players(world)
+            .into_iter()
+            .chain(objects(world))
+            .chain(fields(world));
+
+        for (entity, mut data) in entities_with_components {
+            data.components = self.pack_components(world, entity);
+            entities.push(data);
+        }
+
         entities.extend(dead(world));
-        Snapshot { entities }
+        entities
+    }
+
+    /// Pack all replicated components of an entity.
+    fn pack_components(&self, world: &World, entity: Entity) -> Vec<ComponentState> {
+        self.components
+            .iter()
+            .filter_map(|component| {
+                let data = component.encode(world, entity)?;
+                Some(ComponentState {
+                    id: component.id(),
+                    data,
+                })
+            })
+            .collect()
     }
 
-    /// Update the world to match a previous snapshot.
+    /// Update the world to match a previous snapshot, reporting which entities were spawned,
+    /// updated and despawned.
     pub fn restore_snapshot(
         &mut self,
         world: &mut World,
         snapshot: &Snapshot,
         config: &RestoreConfig,
-    ) {
+    ) -> RestoreReport {
+        let mut report = RestoreReport::default();
+
         for entity in &snapshot.entities {
-            match self.mapping.entry(entity.id) {
-                Entry::Occupied(entry) => {
-                    let target = *entry.get();
-                    self.update_entity(world, target, entity, config);
+            if let EntityKind::Dead = entity.kind {
+                if let Some(target) = self.mapping.remove(&entity.id) {
+                    log::debug!("{} despawned", DebugName::of(world, target));
+                    report
+                        .despawned
+                        .extend(restored_entity(world, entity.id, target));
+                    world.delete(target);
                 }
+                continue;
+            }
+
+            let (target, spawned) = match self.mapping.entry(entity.id) {
+                Entry::Occupied(entry) => (*entry.get(), false),
                 Entry::Vacant(entry) => {
                     let target = world.insert((), Some(()))[0];
                     entry.insert(target);
-                    self.update_entity(world, target, entity, config);
+                    (target, true)
                 }
             };
+
+            self.update_entity(world, target, entity, config);
+
+            let restored = restored_entity(world, entity.id, target);
+            if spawned {
+                log::debug!("{} spawned", DebugName::of(world, target));
+                report.spawned.extend(restored);
+            } else {
+                report.updated.extend(restored);
+            }
+        }
+
+        for state in &snapshot.world.resources {
+            self.restore_resource(world, state);
+        }
+
+        report
+    }
+
+    /// Apply the replicated components found in a snapshot to an entity.
+    fn restore_components(&self, world: &mut World, target: Entity, states: &[ComponentState]) {
+        for state in states {
+            let component = self
+                .components
+                .iter()
+                .find(|component| component.id() == state.id);
+
+            match component {
+                None => log::debug!("ignoring unknown component with id {}", state.id.0),
+                Some(component) => {
+                    if let Err(e) = component.decode(world, target, &state.data) {
+                        log::warn!("malformed component with id {}: {}", state.id.0, e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replace a resource with the state found in a snapshot.
+    fn restore_resource(&self, world: &mut World, state: &ResourceState) {
+        let resource = self
+            .resources
+            .iter()
+            .find(|resource| resource.id() == state.id);
+
+        match resource {
+            None => log::debug!("ignoring unknown resource with id {}", state.id.0),
+            Some(resource) => {
+                if let Err(e) = resource.decode(&mut world.resources, &state.data) {
+                    log::warn!("malformed resource with id {}: {}", state.id.0, e);
+                }
+            }
         }
     }
 
@@ -88,10 +383,16 @@ impl SnapshotEncoder {
             EntityKind::Object(object) => {
                 self.update_object(world, target, data.id, object);
             }
+            EntityKind::Field(field) => {
+                self.update_field(world, target, data.id, field);
+            }
             EntityKind::Dead => {
                 world.delete(target);
+                return;
             }
         }
+
+        self.restore_components(world, target, &data.components);
     }
 
     /// Update a player according the what is contained in a snapshot. 
@@ -105,12 +406,13 @@ impl SnapshotEncoder {
     ) {
         let lookup_entity = |entity: EntityId| self.lookup(entity);
 
-        let movement = if Some(target) == config.active_player {
+        let movement = if config.active_players.contains(&target) {
             let movement = world.get_component::<Movement>(target).unwrap();
             (*movement).clone()
         } else {
             Movement {
                 direction: player.movement,
+                stance: player.stance,
                 ..Movement::default()
             }
         };
@@ -131,6 +433,7 @@ impl SnapshotEncoder {
                 max_points: player.max_health,
             },
             owner: Owner(player.owner),
+            effects: StatusEffects::with(player.effects.iter().copied()),
         };
 
         template.insert(world, target);
@@ -139,10 +442,7 @@ impl SnapshotEncoder {
 
     /// Update a specific ojbect according the what is contained in a snapshot. 
     fn update_object(&self, world: &mut World, target: Entity, id: EntityId, object: &Object) {
-        let model = match object.kind {
-            ObjectKind::Tree => Model::Tree,
-            ObjectKind::Mushroom => Model::Mushroom,
-        };
+        let model = Model::of_object(object.kind);
         let breakable = object.durability.map(|durability| Breakable { durability });
         templates::Object {
             id,
@@ -158,6 +458,125 @@ impl SnapshotEncoder {
         .insert(world, target);
         world.add_tag(target, tags::Static);
     }
+
+    /// Update a field according to what is contained in a snapshot.
+    fn update_field(&self, world: &mut World, target: Entity, id: EntityId, field: &PField) {
+        let (owner, team) = world
+            .get_component::<Field>(target)
+            .map_or((None, None), |field| (field.owner, field.team));
+
+        world.add_component(target, id);
+        world.add_component(target, Position(field.position));
+        world.add_component(
+            target,
+            Field {
+                source: field.source,
+                radius: field.radius,
+                remaining: field.remaining,
+                owner,
+                team,
+                next_damage: 0.0,
+            },
+        );
+    }
+}
+
+impl Default for SnapshotEncoder {
+    fn default() -> Self {
+        SnapshotEncoder::new()
+    }
+}
+
+impl Debug for SnapshotEncoder {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let components = self
+            .components
+            .iter()
+            .map(|component| component.id())
+            .collect::<Vec<_>>();
+        let resources = self
+            .resources
+            .iter()
+            .map(|resource| resource.id())
+            .collect::<Vec<_>>();
+
+        f.debug_struct("SnapshotEncoder")
+            .field("mapping", &self.mapping)
+            .field("components", &components)
+            .field("resources", &resources)
+            .finish()
+    }
+}
+
+impl<T: Replicate> ComponentReplicator for Replicator<T> {
+    fn id(&self) -> ComponentId {
+        T::ID
+    }
+
+    // Components are packed for every entity in every snapshot, so they are aligned to bytes to
+    // keep packing fast. Most of them are floats, which makes the padding cheap.
+    fn encode(&self, world: &World, entity: Entity) -> Option<Vec<u8>> {
+        let component = world.get_component::<T>(entity)?;
+        match protocol::to_aligned_bytes(&component.pack()) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                log::error!("failed to pack component with id {}: {}", T::ID.0, e);
+                None
+            }
+        }
+    }
+
+    fn decode(&self, world: &mut World, entity: Entity, data: &[u8]) -> Result<(), rabbit::Error> {
+        let state = protocol::from_aligned_bytes::<T::State>(data)?;
+        T::unpack(state).apply(world, entity);
+        Ok(())
+    }
+}
+
+impl<T: ReplicatedResource> ResourceReplicator for Replicator<T> {
+    fn id(&self) -> ResourceId {
+        T::ID
+    }
+
+    fn encode(&self, resources: &Resources) -> Option<Vec<u8>> {
+        let resource = resources.get::<T>()?;
+        match protocol::to_bytes(&*resource) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                log::error!("failed to pack resource with id {}: {}", T::ID.0, e);
+                None
+            }
+        }
+    }
+
+    fn decode(&self, resources: &mut Resources, data: &[u8]) -> Result<(), rabbit::Error> {
+        let resource = protocol::from_bytes::<T>(data)?;
+        resources.insert(resource);
+        Ok(())
+    }
+}
+
+/// Describe an entity that was affected by a restored snapshot.
+fn restored_entity(world: &World, id: EntityId, entity: Entity) -> Option<RestoredEntity> {
+    let position = world.get_component::<Position>(entity)?.0;
+
+    let kind = if let Some(field) = world.get_component::<Field>(entity) {
+        RestoredKind::Field(field.source)
+    } else {
+        let model = *world.get_component::<Model>(entity)?;
+        match model.object_kind() {
+            Some(kind) => RestoredKind::Object(kind),
+            None if model == Model::Player => RestoredKind::Player,
+            None => return None,
+        }
+    };
+
+    Some(RestoredEntity {
+        id,
+        entity,
+        kind,
+        position,
+    })
 }
 
 /// Attempt to get the network id of an entity.
@@ -172,7 +591,7 @@ fn entity_id<'a>(world: &'a World) -> impl Fn(Entity) -> Option<EntityId> + 'a {
 }
 
 /// Extract all players in the world.
-fn players(world: &World) -> Vec<PEntity> {
+fn players(world: &World) -> Vec<(Entity, PEntity)> {
     <(
         Read<EntityId>,
         Read<Position>,
@@ -181,9 +600,10 @@ fn players(world: &World) -> Vec<PEntity> {
         Read<Health>,
         Read<Owner>,
     )>::query()
-    .iter_immutable(world)
+    .iter_entities_immutable(world)
     .map(
-        move |(id, position, movement, interaction, health, owner)| {
+        move |(entity, (id, position, movement, interaction, health, owner))| {
+            let effects = world.get_component::<StatusEffects>(entity);
             let player = Player {
                 holding: interaction.holding.and_then(entity_id(world)),
                 breaking: interaction.breaking.and_then(entity_id(world)),
@@ -192,18 +612,22 @@ fn players(world: &World) -> Vec<PEntity> {
                 owner: owner.0,
                 health: health.points,
                 max_health: health.max_points,
+                effects: effects.map(|e| e.active.clone()).unwrap_or_default(),
+                stance: movement.stance,
             };
-            PEntity {
+            let data = PEntity {
                 id: *id,
                 kind: EntityKind::Player(player),
-            }
+                components: Vec::new(),
+            };
+            (entity, data)
         },
     )
     .collect()
 }
 
 /// Extract all objects in the world.
-fn objects(world: &World) -> Vec<PEntity> {
+fn objects(world: &World) -> Vec<(Entity, PEntity)> {
     <(
         Read<EntityId>,
         Read<Position>,
@@ -211,13 +635,9 @@ fn objects(world: &World) -> Vec<PEntity> {
         Read<Health>,
         TryRead<Breakable>,
     )>::query()
-    .iter_immutable(world)
-    .filter_map(move |(id, position, model, health, breakable)| {
-        let kind = match *model {
-            Model::Tree => ObjectKind::Tree,
-            Model::Mushroom => ObjectKind::Mushroom,
-            _ => return None,
-        };
+    .iter_entities_immutable(world)
+    .filter_map(move |(entity, (id, position, model, health, breakable))| {
+        let kind = model.object_kind()?;
         let object = Object {
             position: position.0,
             kind,
@@ -225,15 +645,37 @@ fn objects(world: &World) -> Vec<PEntity> {
             health: health.points,
             max_health: health.max_points,
         };
-        let entity = PEntity {
+        let data = PEntity {
             id: *id,
             kind: EntityKind::Object(object),
+            components: Vec::new(),
         };
-        Some(entity)
+        Some((entity, data))
     })
     .collect()
 }
 
+/// Extract all fields in the world.
+fn fields(world: &World) -> Vec<(Entity, PEntity)> {
+    <(Read<EntityId>, Read<Position>, Read<Field>)>::query()
+        .iter_entities_immutable(world)
+        .map(|(entity, (id, position, field))| {
+            let field = PField {
+                position: position.0,
+                source: field.source,
+                radius: field.radius,
+                remaining: field.remaining,
+            };
+            let data = PEntity {
+                id: *id,
+                kind: EntityKind::Field(field),
+                components: Vec::new(),
+            };
+            (entity, data)
+        })
+        .collect()
+}
+
 fn dead(world: &World) -> Vec<PEntity> {
     world
         .resources
@@ -244,6 +686,7 @@ fn dead(world: &World) -> Vec<PEntity> {
         .map(|&id| PEntity {
             id,
             kind: EntityKind::Dead,
+            components: Vec::new(),
         })
         .collect()
 }
@@ -1,5 +1,7 @@
 pub mod acceleration;
 pub mod attack;
 pub mod collision;
+pub mod effects;
+pub mod fields;
 pub mod movement;
 pub mod tile_interaction;
@@ -1,47 +1,292 @@
+use cgmath::{prelude::*, Point3, Vector2, Vector3};
 use legion::prelude::*;
+use legion::system::SubWorld;
+use rand::Rng;
+use std::f32::consts::PI;
 
-use protocol::EntityId;
+use protocol::{EffectTag, EntityId};
 
-use crate::components::{CollisionListener, Projectile, Health};
-use crate::resources::DeadEntities;
+use crate::components::{
+    Acceleration, Breakable, Collision, CollisionListener, DebugName, Field, Health, Launch,
+    Movement, Position, Projectile, Stance, Team, Velocity, WorldInteraction,
+};
+use crate::effects::StatusEffects;
+use crate::projectiles::{FieldType, ProjectileType};
+use crate::resources::{DeadEntities, EntityAllocator, EntityEffects, Hit, Hits, Throws};
+use crate::tags::Static;
+use crate::telemetry::ThrowRecord;
 use crate::System;
 
+/// The upwards speed of an object dropped by an entity that died.
+const DROP_IMPULSE: f32 = 3.0;
+
+/// The greatest horizontal speed of an object dropped by an entity that died.
+const DROP_SPREAD: f32 = 1.5;
+
+/// The downwards acceleration of dropped objects.
+const DROP_GRAVITY: f32 = 10.0;
+
+/// Projectiles that hit a blocking entity at most this many radians from where it faces are
+/// blocked.
+const BLOCK_ANGLE: f32 = PI / 3.0;
+
 /// Apply damage when a projectile hits another entity.
 pub fn system() -> System {
     let query = <(Read<CollisionListener>, Read<Projectile>)>::query();
 
     let mut damage = Vec::new();
+    let mut fields: Vec<(Point3<f32>, &'static FieldType, Projectile)> = Vec::new();
 
     SystemBuilder::new("attack")
         .read_component::<EntityId>()
+        .read_component::<Position>()
+        .read_component::<StatusEffects>()
+        .read_component::<Collision>()
+        .read_component::<WorldInteraction>()
+        .read_component::<Launch>()
+        .read_component::<Team>()
+        .read_component::<Velocity>()
+        .read_component::<Movement>()
         .write_component::<Health>()
         .write_resource::<DeadEntities>()
+        .write_resource::<Hits>()
+        .write_resource::<Throws>()
+        .write_resource::<EntityEffects>()
+        .read_resource::<EntityAllocator>()
         .with_query(query)
-        .build(move |cmd, world, dead, query| {
+        .build(move |cmd, world, resources, query| {
+            let (dead, hits, throws, effects, allocator) = resources;
             let mut deleted = Vec::new();
 
             for (entity, (listener, projectile)) in query.iter_entities_immutable(world) {
+                let field = ProjectileType::of(projectile.kind).field.as_ref();
+                let velocity = world.get_component::<Velocity>(entity).map(|v| v.0);
                 for collision in listener.collisions.iter() {
-                    damage.push((collision.entity, projectile.damage));
-                    cmd.delete(entity);
-                    deleted.push(entity);
+                    damage.push((
+                        collision.entity,
+                        projectile.damage,
+                        projectile.owner,
+                        velocity,
+                    ));
+                }
+
+                if listener.collisions.is_empty() {
+                    continue;
+                }
+
+                // a projectile that hits several entities at once still leaves a single field
+                if let (Some(field), Some(position)) =
+                    (field, world.get_component::<Position>(entity))
+                {
+                    fields.push((position.0, field, (*projectile).clone()));
+                }
+                cmd.delete(entity);
+                deleted.push(entity);
+
+                if let Some(id) = world.get_component::<EntityId>(entity) {
+                    effects.push(*id, EffectTag::Landed);
+                }
+
+                let launch = world.get_component::<Launch>(entity);
+                let impact = world.get_component::<Position>(entity);
+                if let (Some(launch), Some(impact)) = (launch, impact) {
+                    // only players interact with the world
+                    let hit = listener.collisions.iter().any(|collision| {
+                        Some(collision.entity) != projectile.owner
+                            && world
+                                .get_component::<WorldInteraction>(collision.entity)
+                                .is_some()
+                    });
+
+                    throws.throws.push(ThrowRecord {
+                        kind: projectile.kind,
+                        origin: launch.origin,
+                        target: launch.target,
+                        impact: impact.0,
+                        power: launch.speed,
+                        hit,
+                    });
                 }
             }
 
-            for (entity, damage) in damage.drain(..) {
+            for (entity, damage, attacker, velocity) in damage.drain(..) {
+                let target = world.get_component::<EntityId>(entity).map(|id| *id);
+                let position = world.get_component::<Position>(entity).map(|pos| pos.0);
+
+                let vulnerable = world
+                    .get_component::<StatusEffects>(entity)
+                    .is_none_or(|effects| effects.is_vulnerable());
+                if !vulnerable {
+                    continue;
+                }
+
+                let team = world.get_component::<Team>(entity).map(|team| *team);
+                let attacker_team = attacker
+                    .and_then(|attacker| world.get_component::<Team>(attacker))
+                    .map(|team| *team);
+                if team.is_some() && team == attacker_team {
+                    continue;
+                }
+
+                let stance = world
+                    .get_component::<Movement>(entity)
+                    .map_or(Stance::Standing, |movement| movement.stance);
+                let damage = match velocity {
+                    Some(velocity) if blocks(stance, velocity) => damage / 2,
+                    _ => damage,
+                };
+
                 if let Some(mut health) = world.get_component_mut::<Health>(entity) {
                     health.points = health.points.saturating_sub(damage);
+
+                    if let Some(target) = target {
+                        effects.push(target, EffectTag::Hit);
+                    }
+
+                    if let (Some(target), Some(position)) = (target, position) {
+                        hits.hits.push(Hit {
+                            attacker,
+                            target,
+                            position,
+                            damage,
+                        });
+                    }
+
                     if health.points == 0 {
                         cmd.delete(entity);
-                    deleted.push(entity);
+                        deleted.push(entity);
                     }
                 }
             }
 
+            for (impact, field, projectile) in fields.drain(..) {
+                let team = projectile
+                    .owner
+                    .and_then(|owner| world.get_component::<Team>(owner))
+                    .map(|team| *team);
+                let id = allocator.allocate();
+                let label = format!("{:?}-field", projectile.kind).to_lowercase();
+                let components = (
+                    id,
+                    DebugName::new(&label, id),
+                    Position(impact),
+                    Field {
+                        source: projectile.kind,
+                        radius: field.radius,
+                        remaining: field.duration,
+                        owner: projectile.owner,
+                        team,
+                        next_damage: 0.0,
+                    },
+                );
+                cmd.insert((), Some(components));
+            }
+
             for entity in deleted {
+                drop_held(cmd, world, entity);
                 if let Some(id) = world.get_component::<EntityId>(entity) {
                     dead.entities.push(*id);
                 }
             }
         })
 }
+
+/// Does an entity in the given stance block a projectile that hits it with the given velocity?
+fn blocks(stance: Stance, velocity: Vector3<f32>) -> bool {
+    let facing = match stance {
+        Stance::Blocking { facing } => facing,
+        Stance::Standing => return false,
+    };
+
+    // projectiles falling straight down come from no particular direction
+    let incoming = -velocity.truncate();
+    if incoming.is_zero() {
+        return false;
+    }
+
+    let facing = Vector2::new(facing.cos(), facing.sin());
+    facing.dot(incoming.normalize()) >= BLOCK_ANGLE.cos()
+}
+
+/// Drop the object held by an entity that died where it died, so that it may be picked up again.
+pub(crate) fn drop_held(cmd: &mut CommandBuffer, world: &SubWorld, entity: Entity) {
+    let held = match world
+        .get_component::<WorldInteraction>(entity)
+        .and_then(|interaction| interaction.holding)
+    {
+        Some(held) => held,
+        None => return,
+    };
+
+    let mut rng = rand::thread_rng();
+    let impulse = Vector3::new(
+        rng.gen_range(-DROP_SPREAD, DROP_SPREAD),
+        rng.gen_range(-DROP_SPREAD, DROP_SPREAD),
+        DROP_IMPULSE,
+    );
+
+    if let Some(position) = world.get_component::<Position>(entity) {
+        let height = world
+            .get_component::<Collision>(entity)
+            .map(|collision| collision.bounds.high.z)
+            .unwrap_or(1.0);
+        cmd.add_component(held, Position(position.0 + Vector3::new(0.0, 0.0, height)));
+    }
+
+    cmd.add_component(held, Velocity(impulse));
+    cmd.add_component(held, Acceleration([0.0, 0.0, -DROP_GRAVITY].into()));
+    cmd.add_component(held, Breakable::default());
+    cmd.remove_tag::<Static>(held);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::CollisionEvent;
+    use crate::WorldKind;
+    use legion::schedule::Schedule;
+    use protocol::ProjectileKind;
+
+    #[test]
+    fn single_field_for_simultaneous_collisions() {
+        let mut world = crate::create_world(WorldKind::Plain);
+
+        let targets = world
+            .insert(
+                (),
+                vec![
+                    (Position(Point3::new(0.0, 0.0, 0.0)), Health::with_max(10)),
+                    (Position(Point3::new(0.5, 0.0, 0.0)), Health::with_max(10)),
+                ],
+            )
+            .to_vec();
+
+        let mut listener = CollisionListener::new();
+        listener.collisions = targets
+            .iter()
+            .map(|&entity| CollisionEvent { entity })
+            .collect();
+        let projectile = Projectile {
+            kind: ProjectileKind::Snowbomb,
+            damage: 1,
+            owner: None,
+        };
+        let id = world.resources.get::<EntityAllocator>().unwrap().allocate();
+        let position = Position(Point3::new(0.25, 0.0, 0.0));
+        world.insert((), vec![(id, position, projectile, listener)]);
+
+        let mut schedule = Schedule::builder().add_system(system()).build();
+        schedule.execute(&mut world);
+
+        let fields = <Read<Field>>::query().iter_immutable(&world).count();
+        assert_eq!(fields, 1);
+
+        for target in targets {
+            let health = world.get_component::<Health>(target).unwrap();
+            assert_eq!(health.points, 9);
+        }
+
+        let dead = world.resources.get::<DeadEntities>().unwrap();
+        assert_eq!(dead.entities.iter().filter(|dead| **dead == id).count(), 1);
+    }
+}
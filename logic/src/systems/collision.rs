@@ -3,10 +3,13 @@ use legion::prelude::*;
 
 use crate::collision::{Overlap, SweepCollision};
 use crate::components::{Collision, CollisionEvent, CollisionListener, Position, Velocity};
-use crate::resources::TimeStep;
-use crate::tags::Static;
+use crate::resources::{GameRules, TimeStep};
+use crate::tags::{Player, Static};
 use crate::System;
 
+/// Players may overlap each other by at most this distance, regardless of how soft they are.
+const MAX_PLAYER_OVERLAP: f32 = 0.2;
+
 /// Find all collisions of objects that move continously, ie. have a velocity.
 pub fn continuous_system() -> System {
     let colliders = <(Read<Position>, Read<Collision>)>::query();
@@ -52,38 +55,63 @@ pub fn continuous_system() -> System {
         })
 }
 
-/// Move entities that move in discrete steps out collisions.
+/// Move entities that move in discrete steps out collisions. Players push each other apart
+/// gradually, according to the `GameRules`, instead of being moved out of each other at once.
 pub fn discrete_system() -> System {
     let obstacles = <(Read<Position>, Read<Collision>)>::query();
     let dynamic = <(Write<Position>, Read<Collision>)>::query().filter(!tag::<Static>());
+    let players = <Read<Position>>::query().filter(tag::<Player>());
 
     SystemBuilder::new("discrete_collision")
+        .read_resource::<TimeStep>()
+        .read_resource::<GameRules>()
         .with_query(obstacles)
         .with_query(dynamic)
-        .build(move |_, world, (), queries| {
-            let (obstacles, dynamic) = queries;
+        .with_query(players)
+        .build(move |_, world, (dt, rules), queries| {
+            let (obstacles, dynamic, players) = queries;
 
             let collision_boxes = obstacles
                 .iter_entities(world)
                 .map(|(entity, (position, collider))| (entity, bounding_box(*position, *collider)))
                 .collect::<Vec<_>>();
 
+            let players = players
+                .iter_entities(world)
+                .map(|(entity, _)| entity)
+                .collect::<Vec<_>>();
+
             let dynamic = dynamic.iter_entities(world).collect::<Vec<_>>();
             let dynamic_entities = dynamic
                 .iter()
                 .map(|(entity, _)| *entity)
                 .collect::<Vec<_>>();
 
+            let max_push = rules.player_push * dt.secs_f32();
+
             for (entity, (mut position, collider)) in dynamic {
                 let bounds = bounding_box(*position, *collider);
+                let is_player = players.contains(&entity);
+
                 let mut count = 0;
                 let mut sum = Vector3::zero();
+                let mut push = Vector3::zero();
 
                 for (other, overlap) in overlaps(entity, bounds, &collision_boxes) {
-                    count += 1;
-                    if dynamic_entities.contains(&other) {
+                    if is_player && players.contains(&other) {
+                        // both players are pushed, so each resolves half of the overlap
+                        let resolution = 0.5 * overlap.resolution;
+                        let excess = excess_overlap(resolution);
+                        push += rules.player_softness * (resolution - excess);
+                        if excess != Vector3::zero() {
+                            count += 1;
+                            sum += excess;
+                        }
+                    } else if dynamic_entities.contains(&other) {
+                        count += 1;
                         sum += 0.5 * overlap.resolution;
                     } else {
+                        count += 1;
                         sum += overlap.resolution;
                     }
                 }
@@ -92,10 +120,26 @@ pub fn discrete_system() -> System {
                     let average = sum / count as f32;
                     position.0 += average;
                 }
+
+                if push.magnitude() > max_push {
+                    push = max_push * push.normalize();
+                }
+                position.0 += push;
             }
         })
 }
 
+/// The part of an overlap between two players that exceeds the maximum allowed overlap.
+fn excess_overlap(resolution: Vector3<f32>) -> Vector3<f32> {
+    let depth = resolution.magnitude();
+    let allowed = 0.5 * MAX_PLAYER_OVERLAP;
+    if depth > allowed {
+        resolution * (1.0 - allowed / depth)
+    } else {
+        Vector3::zero()
+    }
+}
+
 /// Find the first collisions of an entity.
 fn first_collision(
     entity: Entity,
@@ -0,0 +1,19 @@
+use legion::prelude::*;
+
+use crate::effects::StatusEffects;
+use crate::resources::TimeStep;
+use crate::System;
+
+/// Counts down the remaining time of all status effects.
+pub fn system() -> System {
+    let query = <Write<StatusEffects>>::query();
+
+    SystemBuilder::new("status_effects")
+        .read_resource::<TimeStep>()
+        .with_query(query)
+        .build(move |_, world, dt, query| {
+            for mut effects in query.iter(world) {
+                effects.tick(dt.secs_f32());
+            }
+        })
+}
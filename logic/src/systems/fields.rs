@@ -0,0 +1,127 @@
+use cgmath::{prelude::*, Point3};
+use legion::prelude::*;
+
+use protocol::EntityId;
+
+use crate::components::{Collision, Field, Health, Position, Team, WorldInteraction};
+use crate::effects::{StatusEffect, StatusEffectKind, StatusEffects};
+use crate::projectiles::{FieldEffect, ProjectileType};
+use crate::resources::{DeadEntities, Hit, Hits, TimeStep};
+use crate::System;
+
+use super::attack::drop_held;
+
+/// Entities that leave a slowing field keep being slowed for this many seconds.
+const SLOW_LINGER: f32 = 0.25;
+
+/// Apply the effects of fields to all entities inside them, and remove expired fields.
+pub fn system() -> System {
+    let fields = <(Read<Position>, Write<Field>)>::query();
+    let affected = <(Read<Position>, Write<StatusEffects>)>::query();
+    let targets = <(
+        Read<EntityId>,
+        Read<Position>,
+        Write<Health>,
+        TryRead<StatusEffects>,
+        TryRead<Team>,
+    )>::query();
+
+    let mut effects: Vec<(Point3<f32>, FieldEffect, Field)> = Vec::new();
+
+    SystemBuilder::new("fields")
+        .read_component::<EntityId>()
+        .read_component::<Collision>()
+        .read_component::<WorldInteraction>()
+        .read_resource::<TimeStep>()
+        .write_resource::<DeadEntities>()
+        .write_resource::<Hits>()
+        .with_query(fields)
+        .with_query(affected)
+        .with_query(targets)
+        .build(move |cmd, world, (dt, dead, hits), queries| {
+            let (fields, affected, targets) = queries;
+            let dt = dt.secs_f32();
+
+            let mut expired = Vec::new();
+            let mut killed = Vec::new();
+
+            for (entity, (position, mut field)) in fields.iter_entities(world) {
+                field.remaining -= dt;
+                if field.remaining <= 0.0 {
+                    cmd.delete(entity);
+                    expired.push(entity);
+                    continue;
+                }
+
+                let field_type = match ProjectileType::of(field.source).field {
+                    Some(field_type) => field_type,
+                    None => continue,
+                };
+
+                if let FieldEffect::Damage { interval, .. } = field_type.effect {
+                    field.next_damage -= dt;
+                    if field.next_damage > 0.0 {
+                        continue;
+                    }
+                    field.next_damage += interval;
+                }
+
+                effects.push((position.0, field_type.effect, field.clone()));
+            }
+
+            for entity in expired {
+                if let Some(id) = world.get_component::<EntityId>(entity) {
+                    dead.entities.push(*id);
+                }
+            }
+
+            for (center, effect, field) in effects.drain(..) {
+                match effect {
+                    FieldEffect::Slow { factor } => {
+                        for (position, mut effects) in affected.iter(world) {
+                            if position.0.distance(center) <= field.radius {
+                                effects.apply(StatusEffect {
+                                    kind: StatusEffectKind::Slowed,
+                                    remaining: SLOW_LINGER,
+                                    magnitude: 1.0 - factor,
+                                });
+                            }
+                        }
+                    }
+                    FieldEffect::Damage { points, .. } => {
+                        for (entity, components) in targets.iter_entities(world) {
+                            let (id, position, mut health, effects, team) = components;
+
+                            let in_range = position.0.distance(center) <= field.radius;
+                            let vulnerable = effects.is_none_or(|e| e.is_vulnerable());
+                            let team = team.map(|team| *team);
+                            let teammate = team.is_some() && team == field.team;
+                            let owner = Some(entity) == field.owner;
+                            let affected = in_range && vulnerable && !teammate && !owner;
+                            if !affected || health.points == 0 {
+                                continue;
+                            }
+
+                            health.points = health.points.saturating_sub(points);
+                            hits.hits.push(Hit {
+                                attacker: field.owner,
+                                target: *id,
+                                position: position.0,
+                                damage: points,
+                            });
+
+                            if health.points == 0 {
+                                cmd.delete(entity);
+                                dead.entities.push(*id);
+                                killed.push(entity);
+                            }
+                        }
+                    }
+                }
+            }
+
+            for entity in killed {
+                drop_held(cmd, world, entity);
+            }
+        })
+}
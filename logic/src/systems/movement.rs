@@ -1,19 +1,49 @@
 use cgmath::{prelude::*, Vector3};
 use legion::prelude::*;
+use protocol::{EffectTag, EntityId};
 
-use crate::components::{Direction, Movement, Position};
-use crate::resources::TimeStep;
+use std::collections::HashMap;
+
+use crate::components::{Direction, Movement, Position, Stance};
+use crate::effects::StatusEffects;
+use crate::resources::{EntityEffects, GameRules, TimeStep};
+use crate::tile_map::{TileCoord, TileMap};
 use crate::System;
 
-/// Calculates the new positions for entities that can move.
+/// The distance an entity moves between two footsteps.
+const STRIDE: f32 = 0.8;
+
+/// The fraction of its speed a blocking entity moves at.
+const BLOCKING_SPEED: f32 = 0.4;
+
+/// Below this speed, in units per second, a sliding entity comes to rest.
+const REST_SPEED: f32 = 0.05;
+
+/// Calculates the new positions for entities that can move. How fast entities walk, and whether they
+/// slide, depends on the tile they are standing on.
 pub fn system() -> System {
-    let query = <(Read<Movement>, Write<Position>)>::query();
+    let query = <(
+        Read<Movement>,
+        Write<Position>,
+        TryRead<StatusEffects>,
+        TryRead<EntityId>,
+    )>::query();
+
+    // the distance each moving entity has moved since its last footstep
+    let mut strides = HashMap::<Entity, f32>::new();
+    // the velocity of each entity that is sliding
+    let mut slides = HashMap::<Entity, Vector3<f32>>::new();
 
     SystemBuilder::new("player_direction")
         .read_resource::<TimeStep>()
+        .read_resource::<GameRules>()
+        .read_resource::<TileMap>()
+        .write_resource::<EntityEffects>()
         .with_query(query)
-        .build(move |_, world, dt, query| {
-            for (movement, mut position) in query.iter(world) {
+        .build(move |_, world, (dt, rules, map, footsteps), query| {
+            let dt = dt.secs_f32();
+
+            for (entity, (movement, mut position, effects, id)) in query.iter_entities(world) {
                 let mut direction = Vector3::zero();
 
                 if movement.direction.contains(Direction::NORTH) {
@@ -29,8 +59,54 @@ pub fn system() -> System {
                     direction.x += 1.0;
                 }
 
-                if !direction.is_zero() {
-                    position.0 += 5.0 * dt.secs_f32() * direction.normalize();
+                let multiplier = effects.map(|effects| effects.speed_multiplier());
+                let mut speed = 5.0 * multiplier.unwrap_or(1.0);
+                if let Stance::Blocking { .. } = movement.stance {
+                    speed *= BLOCKING_SPEED;
+                }
+
+                let tile = map
+                    .get(TileCoord::from_world(position.0))
+                    .map(|tile| tile.kind);
+                speed *= tile.map_or(1.0, |kind| rules.tile_speed(kind));
+
+                let walking = if direction.is_zero() {
+                    Vector3::zero()
+                } else {
+                    speed * direction.normalize()
+                };
+
+                let velocity = match tile.and_then(|kind| rules.tile_grip(kind)) {
+                    Some(grip) => {
+                        let velocity = slides.entry(entity).or_insert(walking);
+                        *velocity += (walking - *velocity) * f32::min(grip * dt, 1.0);
+                        if velocity.magnitude() < REST_SPEED {
+                            *velocity = Vector3::zero();
+                        }
+                        *velocity
+                    }
+                    None => {
+                        slides.remove(&entity);
+                        walking
+                    }
+                };
+
+                position.0 += velocity * dt;
+
+                // sliding without walking makes no footsteps
+                if direction.is_zero() {
+                    strides.remove(&entity);
+                    continue;
+                }
+
+                let distance = velocity.magnitude() * dt;
+                let stride = strides.entry(entity).or_insert(0.0);
+                *stride += distance;
+                if *stride >= STRIDE {
+                    *stride %= STRIDE;
+                    if let Some(id) = id {
+                        footsteps.push(*id, EffectTag::Footstep);
+                    }
                 }
             }
         })
@@ -2,9 +2,10 @@ use cgmath::{prelude::*, Vector3};
 
 use legion::prelude::*;
 use legion::system::SubWorld;
+use protocol::{EffectTag, EntityId};
 
 use crate::components::{Breakable, Collision, Position, WorldInteraction};
-use crate::resources::TimeStep;
+use crate::resources::{EntityEffects, TimeStep};
 use crate::System;
 
 /// Allow entities to break other entities.
@@ -13,6 +14,8 @@ pub fn system() -> System {
 
     SystemBuilder::new("tile_interaction")
         .read_resource::<TimeStep>()
+        .write_resource::<EntityEffects>()
+        .read_component::<EntityId>()
         .read_component::<Position>()
         .write_component::<Position>()
         .write_component::<Breakable>()
@@ -21,7 +24,7 @@ pub fn system() -> System {
         .write_component::<WorldInteraction>()
         .with_query(query)
         .build(move |cmd, world, resources, query| {
-            let dt = resources;
+            let (dt, effects) = resources;
             let dt = dt.secs_f32();
 
             for (entity, (mut interaction, position)) in query.iter_entities(world) {
@@ -39,6 +42,9 @@ pub fn system() -> System {
                     if let Some(mut collision) = world.get_component_mut::<Collision>(broken) {
                         collision.ignored = Some(entity);
                     }
+                    if let Some(id) = world.get_component::<EntityId>(broken) {
+                        effects.push(*id, EffectTag::Broke);
+                    }
                 }
             }
         })
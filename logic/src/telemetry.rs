@@ -0,0 +1,112 @@
+//! Anonymized records of thrown projectiles, used to tune the map and physics.
+//!
+//! A throw log starts with `MAGIC`, followed by any number of records. Each record is packed with
+//! `rabbit` and prefixed by its length in bytes as a little endian `u32`, so that a log that was cut
+//! short by a crash can still be read up until the last complete record.
+
+use cgmath::Point3;
+use protocol::ProjectileKind;
+use rabbit::{PackBits, UnpackBits};
+use std::io::{self, Read, Write};
+
+/// The first bytes of every throw log.
+pub const MAGIC: &[u8; 8] = b"SNOWTHR1";
+
+/// The outcome of a single throw. Does not identify the player that threw it.
+#[derive(Debug, Copy, Clone, PackBits, UnpackBits)]
+pub struct ThrowRecord {
+    /// The kind of projectile that was thrown.
+    pub kind: ProjectileKind,
+    /// Where the projectile was thrown from.
+    pub origin: Point3<f32>,
+    /// Where the projectile was aimed.
+    pub target: Point3<f32>,
+    /// Where the projectile landed.
+    pub impact: Point3<f32>,
+    /// The speed the projectile was thrown with.
+    pub power: f32,
+    /// True if the projectile hit a player.
+    pub hit: bool,
+}
+
+/// Appends throw records to a log.
+pub struct ThrowLogWriter<W: Write> {
+    writer: W,
+    /// The last record packed, kept to reuse its memory.
+    buffer: Vec<u8>,
+}
+
+/// Reads the records of a throw log.
+pub struct ThrowLogReader<R: Read> {
+    reader: R,
+}
+
+impl<W: Write> ThrowLogWriter<W> {
+    /// Start a new log by writing the header.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        Ok(ThrowLogWriter {
+            writer,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Append a record to the log.
+    pub fn append(&mut self, record: &ThrowRecord) -> io::Result<()> {
+        rabbit::to_bytes_into(record, &mut self.buffer)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.writer
+            .write_all(&(self.buffer.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&self.buffer)
+    }
+
+    /// Write any buffered records.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<R: Read> ThrowLogReader<R> {
+    /// Open a log, checking that it starts with the header.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a throw log",
+            ));
+        }
+        Ok(ThrowLogReader { reader })
+    }
+
+    /// Read the next record, or `None` at the end of the log. A trailing partial record is treated
+    /// as the end of the log.
+    pub fn next_record(&mut self) -> io::Result<Option<ThrowRecord>> {
+        let mut length = [0; 4];
+        match self.reader.read_exact(&mut length) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let mut bytes = vec![0; u32::from_le_bytes(length) as usize];
+        match self.reader.read_exact(&mut bytes) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        rabbit::from_bytes(&bytes)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl<R: Read> Iterator for ThrowLogReader<R> {
+    type Item = io::Result<ThrowRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
@@ -1,5 +1,6 @@
 use crate::collision::AlignedBox;
 use crate::components::*;
+use crate::effects::StatusEffects;
 use crate::VOXEL_SIZE;
 
 use protocol::snapshot;
@@ -16,6 +17,7 @@ pub struct Player {
     pub collision: Collision,
     pub health: Health,
     pub owner: Owner,
+    pub effects: StatusEffects,
 }
 
 /// The default components of an object.
@@ -40,6 +42,7 @@ impl Player {
             collision,
             health,
             owner,
+            effects,
         } = self;
 
         world.add_component(entity, id);
@@ -50,6 +53,7 @@ impl Player {
         world.add_component(entity, collision);
         world.add_component(entity, health);
         world.add_component(entity, owner);
+        world.add_component(entity, effects);
     }
 }
 
@@ -20,6 +20,9 @@ pub enum TileKind {
     Water,
     Grass,
     Sand,
+    /// Snow packed hard enough to walk on.
+    Snow,
+    Ice,
 }
 
 #[derive(Debug, Clone)]
@@ -1,42 +1,108 @@
 use super::*;
 use cgmath::Point3;
-use snapshot::{Direction, EntityId};
+use snapshot::{Direction, EntityId, ProjectileKind};
 
 /// Sent from the client to the server when an action is performed.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Action {
     pub kind: ActionKind,
+    /// Set on actions whose latency is being traced. The server acknowledges such actions with an
+    /// `ActionAck` once they have been performed.
+    pub trace: Option<TraceId>,
 }
 
+/// Identifies a traced action. Chosen by the client and only unique among its own actions.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TraceId(pub u32);
+
 /// Different kind of actions.
 #[derive(Debug, Clone, PackBits, UnpackBits, From)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ActionKind {
     Break(Break),
     Throw(Throw),
     Move(Move),
+    Chat(Chat),
+    Block(Block),
 }
 
 /// The specified entity is being broken.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Break {
     pub entity: Option<EntityId>,
 }
 
 /// Attempt to throw the currently held entity.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[rabbit(validate = "Throw::validate")]
 pub struct Throw {
-    #[rabbit(with = "packers::point")]
+    #[cfg_attr(feature = "serde", serde(with = "packers::point"))]
     pub target: Point3<f32>,
+    /// The kind of projectile to throw the entity as.
+    pub projectile: ProjectileKind,
 }
 
 /// Attempt to move in the given direction.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Move {
     pub direction: Direction,
 }
 
+/// Start or stop blocking. Sent continuously, like `Move`, so that the facing follows the player's
+/// aim.
+#[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[rabbit(validate = "Block::validate")]
+pub struct Block {
+    /// The direction to block in, in radians counter-clockwise from east, or `None` to stop.
+    pub facing: Option<f32>,
+}
+
+/// Send a message to every player in the game.
+#[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Chat {
+    pub text: String,
+}
+
 impl Action {
     pub fn must_arrive(&self) -> bool {
         true
     }
 }
+
+impl Throw {
+    fn validate(&self) -> Result<(), &'static str> {
+        let Point3 { x, y, z } = self.target;
+        match x.is_finite() && y.is_finite() && z.is_finite() {
+            true => Ok(()),
+            false => Err("the throw target is not finite"),
+        }
+    }
+}
+
+impl Block {
+    fn validate(&self) -> Result<(), &'static str> {
+        match self.facing {
+            Some(facing) if !facing.is_finite() => Err("the blocking facing is not finite"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ActionKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ActionKind::Break(_) => "Break",
+            ActionKind::Throw(_) => "Throw",
+            ActionKind::Move(_) => "Move",
+            ActionKind::Chat(_) => "Chat",
+            ActionKind::Block(_) => "Block",
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Counts the number of messages and bytes of each kind that are sent and received, to find out
+//! which messages use the most bandwidth.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// The name used for payloads that could not be decoded into a message.
+pub const MALFORMED: &str = "Malformed";
+
+/// Traffic counters for every kind of message, which may be shared between connections.
+#[derive(Debug)]
+pub struct Bandwidth {
+    counters: Mutex<HashMap<&'static str, Traffic>>,
+    created: Instant,
+}
+
+/// The number of messages and bytes of a single kind of message.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Traffic {
+    pub sent_messages: u64,
+    pub sent_bytes: u64,
+    pub received_messages: u64,
+    pub received_bytes: u64,
+}
+
+/// The value of all counters at a point in time.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    time: Instant,
+    traffic: HashMap<&'static str, Traffic>,
+}
+
+/// The traffic of each kind of message during an interval of time, with the kinds using the most
+/// bandwidth first.
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// The length of the interval.
+    pub seconds: f64,
+    /// The traffic of all kinds of messages combined.
+    pub total: Traffic,
+    pub traffic: Vec<(&'static str, Traffic)>,
+}
+
+impl Default for Bandwidth {
+    fn default() -> Self {
+        Bandwidth {
+            counters: Mutex::new(HashMap::new()),
+            created: Instant::now(),
+        }
+    }
+}
+
+impl Bandwidth {
+    /// Count a message that was sent.
+    pub fn record_sent(&self, name: &'static str, bytes: usize) {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        let traffic = counters.entry(name).or_default();
+        traffic.sent_messages += 1;
+        traffic.sent_bytes += bytes as u64;
+    }
+
+    /// Count a message that was received.
+    pub fn record_received(&self, name: &'static str, bytes: usize) {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        let traffic = counters.entry(name).or_default();
+        traffic.received_messages += 1;
+        traffic.received_bytes += bytes as u64;
+    }
+
+    /// Get the current value of all counters.
+    pub fn sample(&self) -> Sample {
+        let counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        Sample {
+            time: Instant::now(),
+            traffic: counters.clone(),
+        }
+    }
+
+    /// The traffic since the counters were created.
+    pub fn since_start(&self) -> Report {
+        let start = Sample {
+            time: self.created,
+            traffic: HashMap::new(),
+        };
+        self.sample().report_since(&start)
+    }
+}
+
+impl Traffic {
+    pub fn total_bytes(&self) -> u64 {
+        self.sent_bytes + self.received_bytes
+    }
+
+    fn since(&self, earlier: &Traffic) -> Traffic {
+        Traffic {
+            sent_messages: self.sent_messages.saturating_sub(earlier.sent_messages),
+            sent_bytes: self.sent_bytes.saturating_sub(earlier.sent_bytes),
+            received_messages: self
+                .received_messages
+                .saturating_sub(earlier.received_messages),
+            received_bytes: self.received_bytes.saturating_sub(earlier.received_bytes),
+        }
+    }
+}
+
+impl Sample {
+    /// The traffic between an earlier sample and this one.
+    pub fn report_since(&self, earlier: &Sample) -> Report {
+        let seconds = self
+            .time
+            .saturating_duration_since(earlier.time)
+            .as_secs_f64();
+
+        let mut traffic = self
+            .traffic
+            .iter()
+            .map(|(&name, traffic)| {
+                let earlier = earlier.traffic.get(name).copied().unwrap_or_default();
+                (name, traffic.since(&earlier))
+            })
+            .filter(|(_, traffic)| *traffic != Traffic::default())
+            .collect::<Vec<_>>();
+
+        traffic.sort_by_key(|(name, traffic)| (Reverse(traffic.total_bytes()), *name));
+
+        let mut total = Traffic::default();
+        for (_, traffic) in &traffic {
+            total.sent_messages += traffic.sent_messages;
+            total.sent_bytes += traffic.sent_bytes;
+            total.received_messages += traffic.received_messages;
+            total.received_bytes += traffic.received_bytes;
+        }
+
+        Report {
+            seconds,
+            total,
+            traffic,
+        }
+    }
+}
+
+impl Report {
+    /// Only keep the kinds of messages that used the most bandwidth.
+    pub fn truncate(&mut self, count: usize) {
+        self.traffic.truncate(count);
+    }
+
+    /// Convert a count during the interval to a rate per second.
+    pub fn per_second(&self, count: u64) -> f64 {
+        if self.seconds > 0.0 {
+            count as f64 / self.seconds
+        } else {
+            0.0
+        }
+    }
+
+    fn fmt_traffic(&self, f: &mut Formatter, name: &str, traffic: &Traffic) -> fmt::Result {
+        write!(
+            f,
+            "{}: sent {:.1} kB/s ({:.1} msg/s), received {:.1} kB/s ({:.1} msg/s)",
+            name,
+            self.per_second(traffic.sent_bytes) / 1000.0,
+            self.per_second(traffic.sent_messages),
+            self.per_second(traffic.received_bytes) / 1000.0,
+            self.per_second(traffic.received_messages),
+        )
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.fmt_traffic(f, "total", &self.total)?;
+        for (name, traffic) in &self.traffic {
+            writeln!(f)?;
+            write!(f, "  ")?;
+            self.fmt_traffic(f, name, traffic)?;
+        }
+        Ok(())
+    }
+}
@@ -1,23 +1,38 @@
 use super::*;
-use crate::Snapshot;
+use crate::{Entity, EntityId, PlayerId, Snapshot, TraceId};
+use cgmath::Point3;
 use std::sync::Arc;
 
 /// Sent from the server to the client when an event occurs.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Event {
     pub time: u32,
     pub kind: EventKind,
 }
 
-/// Different kind of events.
+/// Different kind of events. Events added later take the reserved indices, which earlier versions
+/// unpack as `Unknown`, and have to remain the last field of `Event`.
 #[derive(Debug, Clone, PackBits, UnpackBits, From)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[rabbit(reserve = "9..=15")]
 pub enum EventKind {
     Snapshot(Arc<Snapshot>),
     GameOver(GameOver),
+    MatchSummary(MatchSummary),
+    HitConfirmed(HitConfirmed),
+    WorldChunk(WorldChunk),
+    ActionAck(ActionAck),
+    Chat(ChatMessage),
+    Restart(Restart),
+    /// An event added in a later version, which this version does not understand. Never sent.
+    #[rabbit(other)]
+    Unknown,
 }
 
 /// The game session ended.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum GameOver {
     /// The player receiving this lost.
     Loser,
@@ -25,11 +40,112 @@ pub enum GameOver {
     Winner,
 }
 
+/// Statistics of a finished match, sent to a player once the game is over for them.
+#[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MatchSummary {
+    /// How long the match lasted, in seconds.
+    pub duration: u32,
+    /// Every player that took part in the match, ordered by their placement.
+    pub players: Vec<PlayerStats>,
+}
+
+/// How well a single player performed during a match.
+#[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PlayerStats {
+    pub player: PlayerId,
+    pub nickname: String,
+    /// For how long the player stayed alive, in seconds.
+    pub survived: u32,
+    /// The player was knocked out or left before the match ended.
+    pub eliminated: bool,
+}
+
+/// One of the player's projectiles hit another entity.
+#[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HitConfirmed {
+    /// The entity that was hit.
+    pub target: EntityId,
+    /// Where the entity was hit.
+    #[cfg_attr(feature = "serde", serde(with = "packers::point"))]
+    pub position: Point3<f32>,
+    /// The amount of damage that was dealt.
+    pub damage: u32,
+}
+
+/// Part of the world sent to a client after `Connect`. Together the chunks contain every entity in
+/// the world when the client connected.
+#[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WorldChunk {
+    /// The position of this chunk among all chunks, which may arrive in any order.
+    pub index: u32,
+    pub entities: Vec<Entity>,
+}
+
+/// A traced action was performed by the server.
+#[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ActionAck {
+    pub trace: TraceId,
+    /// The tick during which the action was performed. Its effects are visible in the snapshot of
+    /// this tick and any later snapshots.
+    pub tick: u32,
+}
+
+/// A message a player sent to the chat.
+#[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChatMessage {
+    /// Increases by one for every message sent on the server.
+    pub id: u32,
+    pub sender: PlayerId,
+    /// The nickname of the sender when the message was sent.
+    pub nickname: String,
+    /// When the message was sent, in seconds since the UNIX epoch.
+    pub sent: u64,
+    pub text: String,
+}
+
+/// The server restarted the game in a new world without disconnecting anyone. Every entity of the
+/// old world is listed as dead in the snapshots that follow.
+#[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Restart {
+    /// For how many seconds the new world stands still before the game starts.
+    pub countdown: u32,
+}
+
 impl Event {
     pub fn must_arrive(&self) -> bool {
         match self.kind {
             EventKind::Snapshot(_) => false,
             EventKind::GameOver(_) => true,
+            EventKind::MatchSummary(_) => true,
+            EventKind::HitConfirmed(_) => false,
+            EventKind::WorldChunk(_) => true,
+            EventKind::ActionAck(_) => false,
+            EventKind::Chat(_) => true,
+            EventKind::Restart(_) => true,
+            EventKind::Unknown => false,
+        }
+    }
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::Snapshot(_) => "Snapshot",
+            EventKind::GameOver(_) => "GameOver",
+            EventKind::MatchSummary(_) => "MatchSummary",
+            EventKind::HitConfirmed(_) => "HitConfirmed",
+            EventKind::WorldChunk(_) => "WorldChunk",
+            EventKind::ActionAck(_) => "ActionAck",
+            EventKind::Chat(_) => "Chat",
+            EventKind::Restart(_) => "Restart",
+            EventKind::Unknown => "Unknown",
         }
     }
 }
@@ -0,0 +1,20 @@
+//! Convert protocol types to and from JSON, for use by external tools.
+
+use serde::{de::DeserializeOwned, Serialize};
+
+pub use serde_json::Error;
+
+/// Serialize a value as a compact JSON string.
+pub fn to_string<T: Serialize>(value: &T) -> Result<String, Error> {
+    serde_json::to_string(value)
+}
+
+/// Serialize a value as an indented JSON string.
+pub fn to_string_pretty<T: Serialize>(value: &T) -> Result<String, Error> {
+    serde_json::to_string_pretty(value)
+}
+
+/// Deserialize a value from a JSON string.
+pub fn from_str<T: DeserializeOwned>(json: &str) -> Result<T, Error> {
+    serde_json::from_str(json)
+}
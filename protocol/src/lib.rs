@@ -3,9 +3,13 @@
 //!
 //! Contains common data structures for the protocol implementation.
 
-mod packers;
+pub mod packers;
+
+#[cfg(feature = "ext-json")]
+pub mod json;
 
 pub mod action;
+pub mod bandwidth;
 pub mod event;
 pub mod request;
 pub mod response;
@@ -17,18 +21,44 @@ pub use request::*;
 pub use response::*;
 pub use snapshot::*;
 
-pub use rabbit::{from_bytes, to_bytes};
+pub use rabbit::read::Limits;
+pub use rabbit::{
+    from_aligned_bytes, from_bytes, from_bytes_strict, from_bytes_strict_versioned,
+    from_bytes_strict_with_limits, from_bytes_with_limits, to_aligned_bytes, to_bytes,
+    to_bytes_into, to_bytes_versioned,
+};
 
 use derive_more::From;
 use rabbit::{PackBits, UnpackBits};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::fmt::{self, Display, Formatter};
 
+/// Limits on the collections in messages from clients. Clients only ever send short strings, so
+/// anything longer is malformed.
+pub const CLIENT_LIMITS: Limits = Limits {
+    max_len: 1024,
+    max_total_len: 4096,
+};
+
+/// The version of the messages. Fields added to messages are marked `#[rabbit(since = N)]`, with
+/// `N` the next version, and this is bumped to match.
+///
+/// Clients send their version in `Init` and the server answers with its own in `Connect`. From then
+/// on messages are packed for, and unpacked as, the version of the other side, so that older
+/// clients keep working when the server adds fields. `Init` and `Connect` are unpacked before the
+/// version is known, so fields must never be added to them.
+pub const VERSION: u32 = 1;
+
 /// A unique identifier for a player.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[rabbit(transparent)]
 pub struct PlayerId(pub u32);
 
 /// Top-level data that can be sent from the server to the client.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ServerMessage {
     Event(Event),
     Response(Response),
@@ -36,6 +66,7 @@ pub enum ServerMessage {
 
 /// Top-level data that can be sent from the client to the server
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ClientMessage {
     Request(Request),
     Action(Action),
@@ -43,6 +74,8 @@ pub enum ClientMessage {
 
 /// The id of a channel in which requests and responses are sent.
 #[derive(Debug, Copy, Clone, PackBits, UnpackBits, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[rabbit(transparent)]
 pub struct Channel(pub u32);
 
 impl Into<u32> for PlayerId {
@@ -64,6 +97,14 @@ impl ServerMessage {
             ServerMessage::Response(response) => response.must_arrive(),
         }
     }
+
+    /// The name of the kind of event or response.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ServerMessage::Event(event) => event.kind.name(),
+            ServerMessage::Response(response) => response.kind.name(),
+        }
+    }
 }
 
 impl ClientMessage {
@@ -73,4 +114,12 @@ impl ClientMessage {
             ClientMessage::Action(action) => action.must_arrive(),
         }
     }
+
+    /// The name of the kind of request or action.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClientMessage::Request(request) => request.kind.name(),
+            ClientMessage::Action(action) => action.kind.name(),
+        }
+    }
 }
@@ -1,21 +1,22 @@
-use rabbit::{PackBits, ReadBits, UnpackBits, WriteBits};
-
-/// Pack and unpack a point.
+/// Serialize and deserialize a point with serde. Points are serialized as `[x, y, z]`.
+#[cfg(feature = "serde")]
 pub mod point {
-    use super::*;
     use cgmath::Point3;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-    pub fn pack<W: WriteBits, T: PackBits>(point: &Point3<T>, writer: &mut W) -> Result<(), W::Error> {
-        point.x.pack(writer)?;
-        point.y.pack(writer)?;
-        point.z.pack(writer)?;
-        Ok(())
+    pub fn serialize<S: Serializer, T: Serialize>(
+        point: &Point3<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        [&point.x, &point.y, &point.z].serialize(serializer)
     }
 
-    pub fn unpack<R: ReadBits, T: UnpackBits>(reader: &mut R) -> Result<Point3<T>, R::Error> {
-        let x = T::unpack(reader)?;
-        let y = T::unpack(reader)?;
-        let z = T::unpack(reader)?;
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Point3<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>,
+    {
+        let [x, y, z] = <[T; 3]>::deserialize(deserializer)?;
         Ok(Point3 { x, y, z })
     }
 }
@@ -13,6 +13,7 @@ pub trait IntoRequest {
 
 /// Sent from the client to the server.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Request {
     pub channel: Channel,
     pub kind: RequestKind,
@@ -20,24 +21,42 @@ pub struct Request {
 
 /// Different kinds of requests.
 #[derive(Debug, Clone, PackBits, UnpackBits, From)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum RequestKind {
     Ping,
-    Init,
+    Init(Init),
+    ChatHistory(ChatHistory),
 }
 
 /// Ping the server.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Ping;
 
 /// Initialize the game session with the server.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
-pub struct Init;
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Init {
+    /// The name other players see.
+    pub nickname: String,
+    /// The version of the messages the client knows about, see `VERSION`.
+    pub version: u32,
+}
+
+/// Request the most recent messages sent to the chat.
+#[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChatHistory {
+    /// The maximum number of messages to return.
+    pub limit: u32,
+}
 
 impl Request {
     pub fn must_arrive(&self) -> bool {
         match self.kind {
             RequestKind::Ping => false,
-            RequestKind::Init => true,
+            RequestKind::Init(_) => true,
+            RequestKind::ChatHistory(_) => true,
         }
     }
 }
@@ -46,7 +65,8 @@ impl RequestKind {
     pub fn name(&self) -> &'static str {
         match self {
             RequestKind::Ping => "Ping",
-            RequestKind::Init => "Init",
+            RequestKind::Init(_) => "Init",
+            RequestKind::ChatHistory(_) => "ChatHistory",
         }
     }
 }
@@ -54,7 +74,7 @@ impl RequestKind {
 impl IntoRequest for Init {
     type Response = crate::Connect;
     fn into_request(self) -> RequestKind {
-        RequestKind::Init
+        RequestKind::Init(self)
     }
 }
 
@@ -64,3 +84,10 @@ impl IntoRequest for Ping {
         RequestKind::Ping
     }
 }
+
+impl IntoRequest for ChatHistory {
+    type Response = crate::ChatLog;
+    fn into_request(self) -> RequestKind {
+        RequestKind::ChatHistory(self)
+    }
+}
@@ -1,10 +1,11 @@
 use super::*;
-use crate::snapshot::Snapshot;
+use crate::snapshot::WorldState;
 use std::convert::TryFrom;
 use thiserror::Error;
 
 /// Sent from the server to the client in response to a request.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Response {
     pub channel: Channel,
     pub kind: ResponseKind,
@@ -12,10 +13,12 @@ pub struct Response {
 
 /// Different kinds of responses.
 #[derive(Debug, Clone, PackBits, UnpackBits, From)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ResponseKind {
     Error(String),
     Pong(Pong),
     Connect(Connect),
+    ChatLog(ChatLog),
 }
 
 /// An error that may occur when extracting the contents of a Response.
@@ -32,14 +35,30 @@ pub enum FromResponseError {
 
 /// Response to a Ping.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Pong;
 
-/// Establish the connection and initialize the world.
+/// Establish the connection. The entities of the world follow in `WorldChunk` events, so that a
+/// large world does not have to be sent in a single message.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Connect {
     /// The id assigned to the receiving client.
     pub player_id: PlayerId,
-    pub snapshot: Snapshot,
+    /// Every replicated resource, including the rules of the game.
+    pub world: WorldState,
+    /// The number of `WorldChunk`s that follow.
+    pub chunks: u32,
+    /// The version of the messages the server knows about, see `VERSION`.
+    pub version: u32,
+}
+
+/// Response to a `ChatHistory`.
+#[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChatLog {
+    /// The most recent messages, from oldest to newest.
+    pub messages: Vec<ChatMessage>,
 }
 
 impl<R> From<(Channel, R)> for Response
@@ -60,6 +79,7 @@ impl Response {
             ResponseKind::Error(_) => true,
             ResponseKind::Connect(_) => true,
             ResponseKind::Pong(_) => false,
+            ResponseKind::ChatLog(_) => true,
         }
     }
 }
@@ -70,6 +90,7 @@ impl ResponseKind {
             ResponseKind::Error(_) => "Error",
             ResponseKind::Connect(_) => "Connect",
             ResponseKind::Pong(_) => "Pong",
+            ResponseKind::ChatLog(_) => "ChatLog",
         }
     }
 }
@@ -100,3 +121,10 @@ impl TryFrom<ResponseKind> for Pong {
         try_extract!(value, Pong(pong) => Ok(pong))
     }
 }
+
+impl TryFrom<ResponseKind> for ChatLog {
+    type Error = FromResponseError;
+    fn try_from(value: ResponseKind) -> Result<Self, Self::Error> {
+        try_extract!(value, ChatLog(log) => Ok(log))
+    }
+}
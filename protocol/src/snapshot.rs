@@ -1,38 +1,114 @@
 use cgmath::Point3;
 use rabbit::{PackBits, UnpackBits};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-use crate::{packers, PlayerId};
+#[cfg(feature = "serde")]
+use crate::packers;
+use crate::PlayerId;
 
 /// A snapshot of the entities within a world.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Snapshot {
     pub entities: Vec<Entity>,
+    pub world: WorldState,
+    /// Effects that happened since the previous snapshot. Snapshots sent when a client connects
+    /// contain no effects.
+    pub effects: Vec<EntityEffect>,
 }
 
+/// Global state of the world that does not belong to any entity.
+#[derive(Debug, Clone, Default, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WorldState {
+    /// Resources that changed since the previous snapshot. Snapshots sent when a client connects
+    /// contain every resource.
+    pub resources: Vec<ResourceState>,
+}
+
+/// The packed state of a single replicated resource.
+#[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ResourceState {
+    pub id: ResourceId,
+    pub data: Vec<u8>,
+}
+
+/// Identifies a kind of replicated resource.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ResourceId(pub u32);
+
 /// An entity within the world.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Entity {
     pub id: EntityId,
     pub kind: EntityKind,
+    /// Additional replicated components.
+    pub components: Vec<ComponentState>,
+}
+
+/// The packed state of a single replicated component.
+#[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ComponentState {
+    pub id: ComponentId,
+    pub data: Vec<u8>,
+}
+
+/// Identifies a kind of replicated component.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ComponentId(pub u32);
+
+/// Something that happened to an entity, used by clients to trigger sounds and particles.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EntityEffect {
+    pub entity: EntityId,
+    pub tag: EffectTag,
+}
+
+/// Different kinds of effects.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EffectTag {
+    /// The entity threw a projectile.
+    Threw,
+    /// The entity was hit by a projectile and took damage.
+    Hit,
+    /// The entity was broken off and picked up.
+    Broke,
+    /// The entity was a projectile that landed.
+    Landed,
+    /// The entity took a step.
+    Footstep,
 }
 
 /// The unique id of an entity.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[rabbit(transparent)]
 pub struct EntityId(pub u32);
 
 /// The kind of entity.
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum EntityKind {
     Object(Object),
     Player(Player),
     Dead,
+    Field(Field),
 }
 
 /// An object
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Object {
     /// The position within the world
-    #[rabbit(with = "packers::point")]
+    #[cfg_attr(feature = "serde", serde(with = "packers::point"))]
     pub position: Point3<f32>,
     /// The kind of object.
     pub kind: ObjectKind,
@@ -45,16 +121,47 @@ pub struct Object {
 }
 
 /// Different kinds of objcets.
-#[derive(Debug, Clone, PackBits, UnpackBits)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ObjectKind {
     Tree,
     Mushroom,
 }
 
+/// An area that affects all entities inside it for a limited time.
+#[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Field {
+    /// The center of the field.
+    #[cfg_attr(feature = "serde", serde(with = "packers::point"))]
+    pub position: Point3<f32>,
+    /// The kind of projectile that created the field.
+    pub source: ProjectileKind,
+    /// Entities within this distance from the center are affected.
+    pub radius: f32,
+    /// The number of seconds until the field disappears.
+    pub remaining: f32,
+}
+
+/// Different kinds of projectiles, each with their own physics and effects.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ProjectileKind {
+    /// Travels in a high arc.
+    Snowball,
+    /// Travels flat and fast, dealing more damage.
+    Iceball,
+    /// Travels slowly and leaves behind a field that slows down everything inside it.
+    Slushball,
+    /// Explodes on impact, leaving behind a field that damages everything inside it.
+    Snowbomb,
+}
+
 #[derive(Debug, Clone, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Player {
     /// The current position.
-    #[rabbit(with = "packers::point")]
+    #[cfg_attr(feature = "serde", serde(with = "packers::point"))]
     pub position: Point3<f32>,
     /// The direction it is currently moving
     pub movement: Direction,
@@ -68,11 +175,54 @@ pub struct Player {
     pub health: u32,
     /// Maximum health
     pub max_health: u32,
+    /// The status effects currently affecting the player.
+    pub effects: Vec<StatusEffect>,
+    /// Whether the player is blocking.
+    pub stance: Stance,
+}
+
+/// How a player is standing.
+#[derive(Debug, Copy, Clone, Default, PartialEq, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Stance {
+    #[default]
+    Standing,
+    /// Blocking projectiles that come from the front, at the cost of moving slower.
+    Blocking {
+        /// The direction the player faces, in radians counter-clockwise from east.
+        facing: f32,
+    },
+}
+
+/// A temporary effect on an entity.
+#[derive(Debug, Copy, Clone, PartialEq, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StatusEffect {
+    pub kind: StatusEffectKind,
+    /// The number of seconds until the effect wears off.
+    pub remaining: f32,
+    /// How strong the effect is. The meaning depends on the kind of effect.
+    pub magnitude: f32,
+}
+
+/// Different kinds of status effects.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PackBits, UnpackBits)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum StatusEffectKind {
+    /// Moves slower. The magnitude is the fraction of speed lost.
+    Slowed,
+    /// Can not move.
+    Stunned,
+    /// Can not take damage.
+    SpawnProtected,
+    /// Moves faster. The magnitude is the fraction of speed gained.
+    SpeedBoost,
 }
 
 bitflags::bitflags! {
     /// Different directions an entity can move.
-    #[derive(Default, PackBits, UnpackBits)]
+    #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub struct Direction: u8 {
         const NORTH = 1;
         const WEST = 2;
@@ -80,3 +230,5 @@ bitflags::bitflags! {
         const EAST = 8;
     }
 }
+
+rabbit::impl_flags!(Direction);
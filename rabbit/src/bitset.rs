@@ -0,0 +1,238 @@
+//! Sets of small indices, packed as one bit per index.
+//!
+//! A `BitSet` is packed as the number of bits up to and including its largest index, followed by
+//! the bits themselves. Masks of which entities or components changed are mostly small indices, so
+//! they pack into a few bits, unlike a `Vec<bool>` or a list of indices.
+
+use crate::delta::{self, PackDelta, UnpackDelta};
+use crate::{read, PackBits, PackedSize, ReadBits, UnpackBits, WriteBits};
+
+use std::fmt::{self, Debug};
+use std::iter::FromIterator;
+
+const WORD_BITS: usize = 64;
+
+/// A set of indices, stored as one bit per index.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct BitSet {
+    /// The bits of every index, without trailing zero words.
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn new() -> BitSet {
+        BitSet::default()
+    }
+
+    /// Add an index to the set, returning whether it was not in the set already.
+    pub fn insert(&mut self, index: usize) -> bool {
+        let (word, mask) = (index / WORD_BITS, 1 << (index % WORD_BITS));
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let inserted = self.words[word] & mask == 0;
+        self.words[word] |= mask;
+        inserted
+    }
+
+    /// Remove an index from the set, returning whether it was in the set.
+    pub fn remove(&mut self, index: usize) -> bool {
+        if !self.contains(index) {
+            return false;
+        }
+        self.words[index / WORD_BITS] &= !(1 << (index % WORD_BITS));
+        self.trim();
+        true
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        match self.words.get(index / WORD_BITS) {
+            Some(word) => word & (1 << (index % WORD_BITS)) != 0,
+            None => false,
+        }
+    }
+
+    /// The number of indices in the set.
+    pub fn len(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.words.clear();
+    }
+
+    /// Every index in the set, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            (0..WORD_BITS)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| i * WORD_BITS + bit)
+        })
+    }
+
+    /// The number of bits up to and including the largest index.
+    fn width(&self) -> usize {
+        match self.words.last() {
+            Some(last) => self.words.len() * WORD_BITS - last.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    fn trim(&mut self) {
+        while self.words.last() == Some(&0) {
+            self.words.pop();
+        }
+    }
+}
+
+impl FromIterator<usize> for BitSet {
+    fn from_iter<I: IntoIterator<Item = usize>>(indices: I) -> BitSet {
+        let mut set = BitSet::new();
+        set.extend(indices);
+        set
+    }
+}
+
+impl Extend<usize> for BitSet {
+    fn extend<I: IntoIterator<Item = usize>>(&mut self, indices: I) {
+        for index in indices {
+            self.insert(index);
+        }
+    }
+}
+
+impl Debug for BitSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl PackBits for BitSet {
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        let width = self.width();
+        (width as u32).pack(writer)?;
+
+        let mut left = width;
+        for &word in &self.words {
+            for half in [word as u32, (word >> 32) as u32].iter() {
+                let bits = usize::min(left, 32);
+                writer.write(*half, bits as u8)?;
+                left -= bits;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl UnpackBits for BitSet {
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        let width = u32::unpack(reader)? as usize;
+        // check before allocating, in case the width is far larger than the bytes
+        if width > reader.bits_remaining() {
+            let found = format!("{} bits", reader.bits_remaining());
+            return Err(read::Error::unexpected(
+                format_args!("{} bits", width),
+                found,
+            ));
+        }
+
+        let mut words = vec![0; width.div_ceil(WORD_BITS)];
+        let mut left = width;
+        for word in &mut words {
+            for shift in [0, 32].iter() {
+                let bits = usize::min(left, 32);
+                *word |= u64::from(reader.read(bits as u8)?) << shift;
+                left -= bits;
+            }
+        }
+
+        let mut set = BitSet { words };
+        set.trim();
+        Ok(set)
+    }
+}
+
+impl PackedSize for BitSet {
+    fn packed_bits(&self) -> usize {
+        let width = self.width();
+        (width as u32).packed_bits() + width
+    }
+}
+
+impl PackDelta for BitSet {
+    fn pack_delta<W>(&self, baseline: &Self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        delta::pack_changed(self, baseline, writer)
+    }
+}
+
+impl UnpackDelta for BitSet {
+    fn unpack_delta<R>(baseline: &Self, reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        delta::unpack_changed(baseline, reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_operations() {
+        let mut set = BitSet::new();
+        assert!(set.insert(3));
+        assert!(!set.insert(3));
+        assert!(set.insert(130));
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(130));
+        assert!(!set.contains(64));
+
+        assert!(set.remove(130));
+        assert!(!set.remove(130));
+        assert_eq!(set, [3].iter().copied().collect());
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn packed_up_to_largest_index() {
+        let set: BitSet = [0, 2, 9].iter().copied().collect();
+        assert_eq!(set.packed_bits(), (10u32).packed_bits() + 10);
+        let bytes = crate::to_bytes(&set).unwrap();
+        assert_eq!(crate::from_bytes::<BitSet>(&bytes).unwrap(), set);
+
+        let set: BitSet = (0..200).step_by(3).collect();
+        let bytes = crate::to_bytes(&set).unwrap();
+        assert_eq!(crate::from_bytes::<BitSet>(&bytes).unwrap(), set);
+
+        let empty = BitSet::new();
+        assert_eq!(empty.packed_bits(), (0u32).packed_bits());
+        assert_eq!(
+            crate::from_bytes::<BitSet>(&crate::to_bytes(&empty).unwrap()).unwrap(),
+            empty
+        );
+    }
+
+    #[test]
+    fn width_checked_before_allocating() {
+        let bytes = crate::to_bytes(&u32::MAX).unwrap();
+        assert!(crate::from_bytes::<BitSet>(&bytes).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+//! Unpacking values that borrow from the bytes they are unpacked from, without copying.
+//!
+//! Bits are packed without regard for byte boundaries, so only bytes that start on a byte boundary
+//! can be borrowed. Borrowed strings and byte slices are therefore packed as their length, followed
+//! by padding up to the next byte boundary and then the bytes themselves. Owned strings are packed
+//! without padding, which means that a `&str` can only be unpacked from a packed `&str`, not from a
+//! packed `String`.
+//!
+//! The padding depends on where in the packed bytes a value starts, so borrowed values have no
+//! `PackedSize`.
+
+use crate::read::{self, ReadBits};
+use crate::{PackBits, UnpackBits, WriteBits};
+
+/// A reader that can hand out slices of the bytes it reads from.
+pub trait ReadBorrowed<'a>: ReadBits {
+    /// Skip to the next byte boundary and read `len` whole bytes without copying them.
+    fn read_aligned(&mut self, len: usize) -> Result<&'a [u8], Self::Error>;
+}
+
+/// A value that may borrow from the bytes it is unpacked from.
+pub trait UnpackBorrowed<'a>: Sized {
+    fn unpack_borrowed<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBorrowed<'a>;
+}
+
+impl PackBits for &[u8] {
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        (self.len() as u32).pack(writer)?;
+        writer.write_aligned(self)
+    }
+}
+
+impl<'a> UnpackBorrowed<'a> for &'a [u8] {
+    fn unpack_borrowed<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBorrowed<'a>,
+    {
+        let len = read::unpack_len(reader)?;
+        reader.read_aligned(len)
+    }
+}
+
+impl PackBits for &str {
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        // `[u8]` is packed without alignment, so the slice has to be named explicitly
+        <&[u8]>::pack(&self.as_bytes(), writer)
+    }
+}
+
+impl<'a> UnpackBorrowed<'a> for &'a str {
+    fn unpack_borrowed<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBorrowed<'a>,
+    {
+        let bytes = <&'a [u8]>::unpack_borrowed(reader)?;
+        std::str::from_utf8(bytes).map_err(read::Error::custom)
+    }
+}
+
+impl<'a, T> UnpackBorrowed<'a> for Option<T>
+where
+    T: UnpackBorrowed<'a>,
+{
+    fn unpack_borrowed<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBorrowed<'a>,
+    {
+        if reader.read(1)? == 0 {
+            Ok(None)
+        } else {
+            T::unpack_borrowed(reader).map(Some)
+        }
+    }
+}
+
+impl<'a, T> UnpackBorrowed<'a> for Vec<T>
+where
+    T: UnpackBorrowed<'a>,
+{
+    fn unpack_borrowed<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBorrowed<'a>,
+    {
+        let len = read::unpack_len(reader)?;
+        let mut items = Vec::with_capacity(len);
+        for _ in 0..len {
+            items.push(T::unpack_borrowed(reader)?);
+        }
+        Ok(items)
+    }
+}
+
+macro_rules! impl_owned {
+    ($($ty:ty),+) => {
+        $(
+            impl<'a> UnpackBorrowed<'a> for $ty {
+                fn unpack_borrowed<R>(reader: &mut R) -> Result<Self, R::Error>
+                where
+                    R: ReadBorrowed<'a>,
+                {
+                    <$ty>::unpack(reader)
+                }
+            }
+        )+
+    };
+}
+
+impl_owned!(bool, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+impl_owned!(String);
+
+/// Unpack any owned value as part of a borrowed one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Owned<T>(pub T);
+
+impl<T> PackBits for Owned<T>
+where
+    T: PackBits,
+{
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        self.0.pack(writer)
+    }
+}
+
+impl<'a, T> UnpackBorrowed<'a> for Owned<T>
+where
+    T: UnpackBits,
+{
+    fn unpack_borrowed<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBorrowed<'a>,
+    {
+        T::unpack(reader).map(Owned)
+    }
+}
+
+macro_rules! impl_borrowed_tuple {
+    ($($ident:ident),+) => {
+        impl<'a, $($ident: UnpackBorrowed<'a>),*> UnpackBorrowed<'a> for ($($ident,)*) {
+            fn unpack_borrowed<R: ReadBorrowed<'a>>(reader: &mut R) -> Result<Self, R::Error> {
+                Ok(($( $ident::unpack_borrowed(reader)? ,)*))
+            }
+        }
+    };
+}
+
+impl_borrowed_tuple!(A);
+impl_borrowed_tuple!(A, B);
+impl_borrowed_tuple!(A, B, C);
+impl_borrowed_tuple!(A, B, C, D);
+impl_borrowed_tuple!(A, B, C, D, E);
+impl_borrowed_tuple!(A, B, C, D, E, F);
+impl_borrowed_tuple!(A, B, C, D, E, F, G);
+impl_borrowed_tuple!(A, B, C, D, E, F, G, H);
+impl_borrowed_tuple!(A, B, C, D, E, F, G, H, I);
+impl_borrowed_tuple!(A, B, C, D, E, F, G, H, I, J);
+impl_borrowed_tuple!(A, B, C, D, E, F, G, H, I, J, K);
+impl_borrowed_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_are_borrowed() {
+        let value = (true, "snowball", Some(&b"\x00\xff"[..]));
+        let bytes = crate::to_bytes(&value).unwrap();
+
+        let (flag, text, blob): (bool, &str, Option<&[u8]>) =
+            crate::from_bytes_borrowed(&bytes).unwrap();
+        assert!(flag);
+        assert_eq!(text, "snowball");
+        assert_eq!(blob, Some(&[0, 255][..]));
+
+        // the unpacked string points into the packed bytes
+        let range = bytes.as_ptr_range();
+        assert!(range.contains(&text.as_ptr()));
+    }
+
+    #[test]
+    fn aligned_after_unaligned_fields() {
+        let value = (5u8, true, vec!["a", "", "long enough to span words"]);
+        let bytes = crate::to_bytes(&value).unwrap();
+
+        let (number, flag, words): (u8, bool, Vec<&str>) =
+            crate::from_bytes_borrowed(&bytes).unwrap();
+        assert_eq!((number, flag), (5, true));
+        assert_eq!(words, value.2);
+
+        let value = (Owned(Some(300u32)), "slush");
+        let bytes = crate::to_bytes(&value).unwrap();
+        let unpacked: (Owned<Option<u32>>, &str) = crate::from_bytes_borrowed(&bytes).unwrap();
+        assert_eq!(unpacked, value);
+    }
+
+    #[test]
+    fn truncated_input() {
+        let bytes = crate::to_bytes(&"out of snow").unwrap();
+        let result = crate::from_bytes_borrowed::<&str>(&bytes[..bytes.len() - 1]);
+        assert!(matches!(result.unwrap_err().kind(), crate::Error::Eof));
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let bytes = crate::to_bytes(&&[0xffu8, 0xfe][..]).unwrap();
+        assert!(crate::from_bytes_borrowed::<&str>(&bytes).is_err());
+        assert!(crate::from_bytes_borrowed::<&[u8]>(&bytes).is_ok());
+    }
+}
@@ -0,0 +1,235 @@
+//! Integers within a known range, packed in as few bits as the range needs.
+//!
+//! Integers are normally packed as variable-length quantities, which spend extra bits on marking
+//! where the value ends. Values that stay within a small known range, such as tile coordinates or
+//! health points, are better packed as their offset from the start of the range in a fixed number
+//! of bits.
+//!
+//! A `Bounded` keeps its value within the range. Fields of any integer type can be packed the same
+//! way with `#[rabbit(range = "MIN..=MAX")]`, which uses `pack_range` and `unpack_range`.
+
+use crate::delta::{self, PackDelta, UnpackDelta};
+use crate::fixed_width::{check_bits, read_u64, write_u64};
+use crate::{read, write, PackBits, PackedSize, ReadBits, UnpackBits, WriteBits};
+
+use std::any::type_name;
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+
+/// An integer within `MIN..=MAX`, packed in exactly `BITS` bits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Bounded<const MIN: u32, const MAX: u32>(u32);
+
+impl<const MIN: u32, const MAX: u32> Bounded<MIN, MAX> {
+    /// The number of bits every value is packed into.
+    pub const BITS: u8 = {
+        assert!(MIN <= MAX, "the range of a `Bounded` is empty");
+        32 - (MAX - MIN).leading_zeros() as u8
+    };
+
+    pub const MIN: Bounded<MIN, MAX> = Bounded(MIN);
+    pub const MAX: Bounded<MIN, MAX> = Bounded(MAX);
+
+    /// The value, if it is within the range.
+    pub fn new(value: u32) -> Option<Bounded<MIN, MAX>> {
+        if (MIN..=MAX).contains(&value) {
+            Some(Bounded(value))
+        } else {
+            None
+        }
+    }
+
+    /// The value, or the closest end of the range if it is outside.
+    pub fn saturating(value: u32) -> Bounded<MIN, MAX> {
+        Bounded(value.clamp(MIN, MAX))
+    }
+
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl<const MIN: u32, const MAX: u32> Default for Bounded<MIN, MAX> {
+    fn default() -> Self {
+        Bounded::MIN
+    }
+}
+
+impl<const MIN: u32, const MAX: u32> From<Bounded<MIN, MAX>> for u32 {
+    fn from(value: Bounded<MIN, MAX>) -> u32 {
+        value.0
+    }
+}
+
+impl<const MIN: u32, const MAX: u32> Display for Bounded<MIN, MAX> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<const MIN: u32, const MAX: u32> PackBits for Bounded<MIN, MAX> {
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        writer.write(self.0 - MIN, Self::BITS)
+    }
+}
+
+impl<const MIN: u32, const MAX: u32> UnpackBits for Bounded<MIN, MAX> {
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        let offset = reader.read(Self::BITS)?;
+        if offset > MAX - MIN {
+            return Err(read::Error::unexpected(
+                format_args!("a value in {}..={}", MIN, MAX),
+                u64::from(MIN) + u64::from(offset),
+            ));
+        }
+        Ok(Bounded(MIN + offset))
+    }
+}
+
+impl<const MIN: u32, const MAX: u32> PackedSize for Bounded<MIN, MAX> {
+    fn packed_bits(&self) -> usize {
+        usize::from(Self::BITS)
+    }
+
+    fn max_packed_bits() -> Option<usize> {
+        Some(usize::from(Self::BITS))
+    }
+}
+
+impl<const MIN: u32, const MAX: u32> PackDelta for Bounded<MIN, MAX> {
+    fn pack_delta<W>(&self, baseline: &Self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        delta::pack_changed(self, baseline, writer)
+    }
+}
+
+impl<const MIN: u32, const MAX: u32> UnpackDelta for Bounded<MIN, MAX> {
+    fn unpack_delta<R>(baseline: &Self, reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        delta::unpack_changed(baseline, reader)
+    }
+}
+
+/// The number of bits needed for the offset of every value within `min..=max`.
+pub fn range_bits(min: i128, max: i128) -> u8 {
+    128 - ((max - min) as u128).leading_zeros() as u8
+}
+
+/// Pack an integer within `MIN..=MAX` as its offset from `MIN`, in `range_bits(MIN, MAX)` bits.
+pub fn pack_range<T, W, const MIN: i128, const MAX: i128>(
+    value: &T,
+    writer: &mut W,
+) -> Result<(), W::Error>
+where
+    T: Copy + Display,
+    i128: TryFrom<T>,
+    W: WriteBits,
+{
+    let bits = range_bits(MIN, MAX);
+    check_bits(bits, write::Error::custom)?;
+    match i128::try_from(*value) {
+        Ok(wide) if (MIN..=MAX).contains(&wide) => write_u64((wide - MIN) as u64, bits, writer),
+        _ => Err(write::Error::custom(format!(
+            "{} is outside of {}..={}",
+            value, MIN, MAX
+        ))),
+    }
+}
+
+/// Unpack an integer packed by `pack_range`.
+pub fn unpack_range<T, R, const MIN: i128, const MAX: i128>(reader: &mut R) -> Result<T, R::Error>
+where
+    T: TryFrom<i128>,
+    R: ReadBits,
+{
+    let bits = range_bits(MIN, MAX);
+    check_bits(bits, read::Error::custom)?;
+    let value = MIN + i128::from(read_u64(bits, reader)?);
+    if value > MAX {
+        return Err(read::Error::unexpected(
+            format_args!("a value in {}..={}", MIN, MAX),
+            value,
+        ));
+    }
+    T::try_from(value)
+        .map_err(|_| read::Error::unexpected(format_args!("a `{}`", type_name::<T>()), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Health = Bounded<0, 100>;
+    type Tile = Bounded<1000, 1015>;
+
+    #[test]
+    fn minimal_bits() {
+        assert_eq!(Health::BITS, 7);
+        assert_eq!(Tile::BITS, 4);
+        assert_eq!(Bounded::<5, 5>::BITS, 0);
+        assert_eq!(Bounded::<0, { u32::MAX }>::BITS, 32);
+
+        let value = (Tile::new(1015).unwrap(), Tile::MIN);
+        assert_eq!(value.packed_bits(), 8);
+        let bytes = crate::to_bytes(&value).unwrap();
+        assert_eq!(bytes, vec![0b0000_1111]);
+        assert_eq!(crate::from_bytes::<(Tile, Tile)>(&bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn range_checked() {
+        assert_eq!(Health::new(101), None);
+        assert_eq!(Tile::new(999), None);
+        assert_eq!(Tile::saturating(3), Tile::MIN);
+        assert_eq!(Health::saturating(250).get(), 100);
+
+        // seven bits fit values up to 127
+        let bytes = crate::to_bytes(&101u8).unwrap();
+        assert!(crate::from_bytes::<Health>(&bytes).is_err());
+    }
+
+    #[test]
+    fn integer_ranges() {
+        fn packed<T: Copy + Display>(value: T) -> crate::Result<Vec<u8>>
+        where
+            i128: TryFrom<T>,
+        {
+            let mut writer = crate::write::BitWriter::new();
+            pack_range::<_, _, -10, 10>(&value, &mut writer)?;
+            Ok(writer.finish())
+        }
+
+        fn unpacked<T: TryFrom<i128>>(bytes: &[u8]) -> crate::Result<T> {
+            unpack_range::<_, _, -10, 10>(&mut crate::read::BitReader::new(bytes))
+        }
+
+        assert_eq!(range_bits(-10, 10), 5);
+        assert_eq!(range_bits(0, u64::MAX.into()), 64);
+        assert_eq!(packed(-10i8).unwrap(), vec![0]);
+        assert_eq!(unpacked::<i64>(&packed(7i64).unwrap()).unwrap(), 7);
+        assert!(packed(11u32).is_err());
+
+        // -3 is within the range, but does not fit in a `u8`
+        assert_eq!(unpacked::<u8>(&packed(3u8).unwrap()).unwrap(), 3);
+        assert!(unpacked::<u8>(&packed(-3i16).unwrap()).is_err());
+        // 21 fits in five bits, but not in the range
+        assert!(unpacked::<i32>(&[21]).is_err());
+    }
+
+    #[test]
+    fn empty_range() {
+        let value = Bounded::<5, 5>::new(5).unwrap();
+        assert!(crate::to_bytes(&value).unwrap().is_empty());
+        assert_eq!(crate::from_bytes::<Bounded<5, 5>>(&[]).unwrap(), value);
+    }
+}
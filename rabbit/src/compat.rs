@@ -0,0 +1,756 @@
+//! A bridge between serde and the bit streams, so that types implementing `serde::Serialize` and
+//! `serde::Deserialize` may be packed without implementing `PackBits` and `UnpackBits`.
+//!
+//! The encoding is not self-describing: a value must be deserialized as the same type it was
+//! serialized from. Primitives use the same encoding as their `PackBits` implementations.
+//! Sequences and maps are prefixed by their length, and enum variants by their index.
+//!
+//! Wrap a field in [`Serde`] to include a serde type in a message that derives `PackBits`:
+//!
+//! ```ignore
+//! #[derive(PackBits, UnpackBits)]
+//! struct Spawn {
+//!     id: Serde<Uuid>,
+//!     position: Serde<Point3<f32>>,
+//! }
+//! ```
+
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use serde::Deserialize;
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::{read, write, PackBits, ReadBits, UnpackBits, WriteBits};
+
+/// Packs a value through its serde implementations.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Serde<T>(pub T);
+
+/// Serializes values into a bit stream.
+pub struct Serializer<'a, W> {
+    writer: &'a mut W,
+}
+
+/// Deserializes values from a bit stream.
+pub struct Deserializer<'a, R> {
+    reader: &'a mut R,
+}
+
+/// The error of the underlying stream, which is also used for errors raised by serde.
+#[derive(Debug)]
+pub struct Error<E>(pub E);
+
+type Result<T, E> = std::result::Result<T, Error<E>>;
+
+/// Serialize a value to bytes using its serde implementation.
+pub fn to_bytes<T>(value: &T) -> crate::Result<Vec<u8>>
+where
+    T: Serialize + ?Sized,
+{
+    let mut writer = crate::BitWriter::new();
+    value
+        .serialize(&mut Serializer::new(&mut writer))
+        .map_err(|Error(e)| e)?;
+    Ok(writer.finish())
+}
+
+/// Deserialize a value from bytes using its serde implementation.
+pub fn from_bytes<'de, T>(bytes: &[u8]) -> crate::Result<T>
+where
+    T: Deserialize<'de>,
+{
+    let mut reader = crate::BitReader::new(bytes);
+    T::deserialize(&mut Deserializer::new(&mut reader)).map_err(|Error(e)| e)
+}
+
+impl<T> PackBits for Serde<T>
+where
+    T: Serialize,
+{
+    fn pack<W>(&self, writer: &mut W) -> std::result::Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        self.0
+            .serialize(&mut Serializer::new(writer))
+            .map_err(|Error(e)| e)
+    }
+}
+
+impl<T> UnpackBits for Serde<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    fn unpack<R>(reader: &mut R) -> std::result::Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        T::deserialize(&mut Deserializer::new(reader))
+            .map(Serde)
+            .map_err(|Error(e)| e)
+    }
+}
+
+impl<T> From<T> for Serde<T> {
+    fn from(value: T) -> Self {
+        Serde(value)
+    }
+}
+
+impl<E: Display> Display for Error<E> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<E: std::error::Error> std::error::Error for Error<E> {}
+
+impl<E: write::Error> ser::Error for Error<E> {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error(E::custom(msg))
+    }
+}
+
+impl<E: read::Error> de::Error for Error<E> {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error(<E as read::Error>::custom(msg))
+    }
+}
+
+impl<'a, W> Serializer<'a, W>
+where
+    W: WriteBits,
+{
+    pub fn new(writer: &'a mut W) -> Self {
+        Serializer { writer }
+    }
+
+    fn pack<T: PackBits + ?Sized>(&mut self, value: &T) -> Result<(), W::Error> {
+        value.pack(self.writer).map_err(Error)
+    }
+
+    fn pack_len(&mut self, len: Option<usize>) -> Result<(), W::Error> {
+        match len {
+            Some(len) => self.pack(&(len as u32)),
+            None => Err(ser::Error::custom("the length of a sequence must be known")),
+        }
+    }
+}
+
+impl<'a, 'b, W> ser::Serializer for &'b mut Serializer<'a, W>
+where
+    W: WriteBits,
+{
+    type Ok = ();
+    type Error = Error<W::Error>;
+
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<(), W::Error> {
+        self.pack(&v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), W::Error> {
+        self.pack(&(v as u8))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), W::Error> {
+        self.pack(&v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), W::Error> {
+        self.pack(&v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), W::Error> {
+        self.pack(&v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<(), W::Error> {
+        self.pack(&v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), W::Error> {
+        self.pack(&v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), W::Error> {
+        self.pack(&v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), W::Error> {
+        self.pack(&v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), W::Error> {
+        self.pack(&v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<(), W::Error> {
+        self.pack(&v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), W::Error> {
+        self.pack(&v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), W::Error> {
+        self.pack(&v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), W::Error> {
+        self.pack(&(v as u32))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), W::Error> {
+        self.pack(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), W::Error> {
+        self.pack(v)
+    }
+
+    fn serialize_none(self) -> Result<(), W::Error> {
+        self.pack(&false)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), W::Error> {
+        self.pack(&true)?;
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), W::Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), W::Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), W::Error> {
+        self.pack(&variant_index)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), W::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), W::Error> {
+        self.pack(&variant_index)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self, W::Error> {
+        self.pack_len(len)?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self, W::Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self, W::Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, W::Error> {
+        self.pack(&variant_index)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self, W::Error> {
+        self.pack_len(len)?;
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, W::Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, W::Error> {
+        self.pack(&variant_index)?;
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+/// Implements the traits used to serialize compound types, which all serialize their elements
+/// one after the other.
+macro_rules! impl_serialize_compound {
+    ($trait:ident, $method:ident $(, $key:ident)?) => {
+        impl<'a, 'b, W> ser::$trait for &'b mut Serializer<'a, W>
+        where
+            W: WriteBits,
+        {
+            type Ok = ();
+            type Error = Error<W::Error>;
+
+            fn $method<T: Serialize + ?Sized>(
+                &mut self,
+                $($key: &'static str,)?
+                value: &T,
+            ) -> Result<(), W::Error> {
+                value.serialize(&mut **self)
+            }
+
+            fn end(self) -> Result<(), W::Error> {
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_serialize_compound!(SerializeSeq, serialize_element);
+impl_serialize_compound!(SerializeTuple, serialize_element);
+impl_serialize_compound!(SerializeTupleStruct, serialize_field);
+impl_serialize_compound!(SerializeTupleVariant, serialize_field);
+impl_serialize_compound!(SerializeStruct, serialize_field, _key);
+impl_serialize_compound!(SerializeStructVariant, serialize_field, _key);
+
+impl<'a, 'b, W> ser::SerializeMap for &'b mut Serializer<'a, W>
+where
+    W: WriteBits,
+{
+    type Ok = ();
+    type Error = Error<W::Error>;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), W::Error> {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), W::Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), W::Error> {
+        Ok(())
+    }
+}
+
+impl<'a, R> Deserializer<'a, R>
+where
+    R: ReadBits,
+{
+    pub fn new(reader: &'a mut R) -> Self {
+        Deserializer { reader }
+    }
+
+    fn unpack<T: UnpackBits>(&mut self) -> Result<T, R::Error> {
+        T::unpack(self.reader).map_err(Error)
+    }
+
+    fn unpack_len(&mut self) -> Result<usize, R::Error> {
+        read::unpack_len(self.reader).map_err(Error)
+    }
+
+    fn unpack_string(&mut self) -> Result<String, R::Error> {
+        self.unpack::<String>()
+    }
+}
+
+macro_rules! deserialize_primitive {
+    ($method:ident, $visit:ident, $ty:ty) => {
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, R::Error> {
+            visitor.$visit(self.unpack::<$ty>()?)
+        }
+    };
+}
+
+impl<'de, 'a, 'b, R> de::Deserializer<'de> for &'b mut Deserializer<'a, R>
+where
+    R: ReadBits,
+{
+    type Error = Error<R::Error>;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, R::Error> {
+        Err(de::Error::custom(
+            "the encoding is not self-describing, so the type must be known",
+        ))
+    }
+
+    deserialize_primitive!(deserialize_bool, visit_bool, bool);
+    deserialize_primitive!(deserialize_i16, visit_i16, i16);
+    deserialize_primitive!(deserialize_i32, visit_i32, i32);
+    deserialize_primitive!(deserialize_i64, visit_i64, i64);
+    deserialize_primitive!(deserialize_i128, visit_i128, i128);
+    deserialize_primitive!(deserialize_u8, visit_u8, u8);
+    deserialize_primitive!(deserialize_u16, visit_u16, u16);
+    deserialize_primitive!(deserialize_u32, visit_u32, u32);
+    deserialize_primitive!(deserialize_u64, visit_u64, u64);
+    deserialize_primitive!(deserialize_u128, visit_u128, u128);
+    deserialize_primitive!(deserialize_f32, visit_f32, f32);
+    deserialize_primitive!(deserialize_f64, visit_f64, f64);
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, R::Error> {
+        visitor.visit_i8(self.unpack::<u8>()? as i8)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, R::Error> {
+        let code = self.unpack::<u32>()?;
+        match std::char::from_u32(code) {
+            Some(ch) => visitor.visit_char(ch),
+            None => Err(de::Error::custom(format!("invalid char: {:#x}", code))),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, R::Error> {
+        visitor.visit_string(self.unpack_string()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, R::Error> {
+        visitor.visit_string(self.unpack_string()?)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, R::Error> {
+        visitor.visit_byte_buf(self.unpack::<Vec<u8>>()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, R::Error> {
+        visitor.visit_byte_buf(self.unpack::<Vec<u8>>()?)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, R::Error> {
+        if self.unpack::<bool>()? {
+            visitor.visit_some(self)
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, R::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, R::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, R::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, R::Error> {
+        let len = self.unpack_len()?;
+        visitor.visit_seq(Elements {
+            deserializer: self,
+            remaining: len,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, R::Error> {
+        visitor.visit_seq(Elements {
+            deserializer: self,
+            remaining: len,
+        })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, R::Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, R::Error> {
+        let len = self.unpack_len()?;
+        visitor.visit_map(Elements {
+            deserializer: self,
+            remaining: len,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, R::Error> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, R::Error> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, R::Error> {
+        self.deserialize_u32(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, R::Error> {
+        Err(de::Error::custom(
+            "the encoding is not self-describing, so values can not be skipped",
+        ))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+/// The elements of a sequence, tuple or map.
+struct Elements<'b, 'a, R> {
+    deserializer: &'b mut Deserializer<'a, R>,
+    remaining: usize,
+}
+
+impl<'de, 'a, 'b, R> de::SeqAccess<'de> for Elements<'b, 'a, R>
+where
+    R: ReadBits,
+{
+    type Error = Error<R::Error>;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, R::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.deserializer).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'de, 'a, 'b, R> de::MapAccess<'de> for Elements<'b, 'a, R>
+where
+    R: ReadBits,
+{
+    type Error = Error<R::Error>;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, R::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.deserializer).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, R::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.deserializer)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'de, 'a, 'b, R> de::EnumAccess<'de> for &'b mut Deserializer<'a, R>
+where
+    R: ReadBits,
+{
+    type Error = Error<R::Error>;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self), R::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let index = self.unpack::<u32>()?;
+        let index: de::value::U32Deserializer<Self::Error> = index.into_deserializer();
+        let variant = seed.deserialize(index)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a, 'b, R> de::VariantAccess<'de> for &'b mut Deserializer<'a, R>
+where
+    R: ReadBits,
+{
+    type Error = Error<R::Error>;
+
+    fn unit_variant(self) -> Result<(), R::Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, R::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, R::Error>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, R::Error>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Point {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Empty,
+        Dot(Point),
+        Line(Point, Point),
+        Circle { center: Point, radius: f32 },
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Scene {
+        name: String,
+        id: u64,
+        offset: i32,
+        tag: Option<char>,
+        shapes: Vec<Shape>,
+        layers: BTreeMap<String, (u8, i8, bool)>,
+        unit: (),
+    }
+
+    fn assert_lossless<T>(value: T)
+    where
+        T: Serialize + for<'de> Deserialize<'de> + PartialEq + std::fmt::Debug,
+    {
+        let bytes = to_bytes(&value).unwrap();
+        let decoded: T = from_bytes(&bytes).unwrap();
+        assert_eq!(value, decoded);
+    }
+
+    #[test]
+    fn primitives() {
+        assert_lossless(true);
+        assert_lossless(-5i8);
+        assert_lossless(-1234i64);
+        assert_lossless(u128::max_value());
+        assert_lossless(3.5f64);
+        assert_lossless('ö');
+        assert_lossless(String::from("snow"));
+        assert_lossless(Some(3u16));
+        assert_lossless(None::<u16>);
+    }
+
+    #[test]
+    fn compound() {
+        let mut layers = BTreeMap::new();
+        layers.insert("ground".to_owned(), (1, -1, true));
+        layers.insert("sky".to_owned(), (2, 7, false));
+
+        assert_lossless(Scene {
+            name: "island".to_owned(),
+            id: 42,
+            offset: -7,
+            tag: Some('x'),
+            shapes: vec![
+                Shape::Empty,
+                Shape::Dot(Point { x: 1.0, y: 2.0 }),
+                Shape::Line(Point { x: 0.0, y: 0.0 }, Point { x: -1.0, y: 4.5 }),
+                Shape::Circle {
+                    center: Point { x: 3.0, y: 3.0 },
+                    radius: 0.5,
+                },
+            ],
+            layers,
+            unit: (),
+        });
+    }
+
+    #[test]
+    fn matches_pack_bits() {
+        let value = (12u32, -3i32, Some(String::from("abc")), vec![1u8, 2, 3]);
+        assert_eq!(to_bytes(&value).unwrap(), crate::to_bytes(&value).unwrap());
+    }
+
+    #[test]
+    fn serde_field() {
+        let value = (Serde(Point { x: 1.0, y: -2.0 }), 7u16);
+        let bytes = crate::to_bytes(&value).unwrap();
+        let decoded: (Serde<Point>, u16) = crate::from_bytes(&bytes).unwrap();
+        assert_eq!(value, decoded);
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = to_bytes(&Point { x: 1.0, y: 2.0 }).unwrap();
+        assert!(from_bytes::<Point>(&bytes[..5]).is_err());
+    }
+}
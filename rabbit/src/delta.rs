@@ -0,0 +1,179 @@
+//! Packing values relative to a baseline that the receiver already has.
+//!
+//! Every field is preceded by a presence bit: fields equal to the baseline only take that bit,
+//! while changed fields are packed in full after it. Derived implementations recurse into fields
+//! that implement the traits themselves, so only the changed parts of nested structs are sent.
+//! Enums and values such as integers, strings and collections are treated as a whole.
+
+use crate::{PackBits, ReadBits, UnpackBits, WriteBits};
+
+use std::rc::Rc;
+use std::sync::Arc;
+
+pub trait PackDelta: PackBits {
+    /// Pack the differences between `self` and `baseline`.
+    fn pack_delta<W>(&self, baseline: &Self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits;
+}
+
+pub trait UnpackDelta: UnpackBits {
+    /// Unpack a value packed with `PackDelta::pack_delta` against the same `baseline`.
+    fn unpack_delta<R>(baseline: &Self, reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits;
+}
+
+/// Pack a presence bit, followed by all of `value` if it is different from `baseline`.
+pub fn pack_changed<T, W>(value: &T, baseline: &T, writer: &mut W) -> Result<(), W::Error>
+where
+    T: PackBits + PartialEq + ?Sized,
+    W: WriteBits,
+{
+    if value == baseline {
+        writer.write(0, 1)
+    } else {
+        writer.write(1, 1)?;
+        value.pack(writer)
+    }
+}
+
+/// Unpack a value packed with `pack_changed` against the same `baseline`.
+pub fn unpack_changed<T, R>(baseline: &T, reader: &mut R) -> Result<T, R::Error>
+where
+    T: UnpackBits + Clone,
+    R: ReadBits,
+{
+    if reader.read(1)? == 0 {
+        Ok(baseline.clone())
+    } else {
+        T::unpack(reader)
+    }
+}
+
+macro_rules! impl_delta_as_whole {
+    ($($ty:ty),+) => {
+        $(
+            impl PackDelta for $ty {
+                fn pack_delta<W>(&self, baseline: &Self, writer: &mut W) -> Result<(), W::Error>
+                where
+                    W: WriteBits,
+                {
+                    pack_changed(self, baseline, writer)
+                }
+            }
+
+            impl UnpackDelta for $ty {
+                fn unpack_delta<R>(baseline: &Self, reader: &mut R) -> Result<Self, R::Error>
+                where
+                    R: ReadBits,
+                {
+                    unpack_changed(baseline, reader)
+                }
+            }
+        )+
+    };
+}
+
+impl_delta_as_whole!(bool, u8, u16, u32, u64, u128, usize);
+impl_delta_as_whole!(i8, i16, i32, i64, i128, isize);
+impl_delta_as_whole!(f32, f64, String);
+
+macro_rules! impl_delta_as_whole_generic {
+    ($($container:ident),+) => {
+        $(
+            impl<T> PackDelta for $container<T>
+            where
+                T: PackBits + PartialEq,
+            {
+                fn pack_delta<W>(&self, baseline: &Self, writer: &mut W) -> Result<(), W::Error>
+                where
+                    W: WriteBits,
+                {
+                    pack_changed(self, baseline, writer)
+                }
+            }
+
+            impl<T> UnpackDelta for $container<T>
+            where
+                T: UnpackBits + Clone,
+            {
+                fn unpack_delta<R>(baseline: &Self, reader: &mut R) -> Result<Self, R::Error>
+                where
+                    R: ReadBits,
+                {
+                    unpack_changed(baseline, reader)
+                }
+            }
+        )+
+    };
+}
+
+impl_delta_as_whole_generic!(Option, Vec);
+
+macro_rules! impl_delta_wrapper {
+    ($wrapper:ident) => {
+        impl<T> PackDelta for $wrapper<T>
+        where
+            T: PackDelta,
+        {
+            fn pack_delta<W>(&self, baseline: &Self, writer: &mut W) -> Result<(), W::Error>
+            where
+                W: WriteBits,
+            {
+                self.as_ref().pack_delta(baseline.as_ref(), writer)
+            }
+        }
+
+        impl<T> UnpackDelta for $wrapper<T>
+        where
+            T: UnpackDelta,
+        {
+            fn unpack_delta<R>(baseline: &Self, reader: &mut R) -> Result<Self, R::Error>
+            where
+                R: ReadBits,
+            {
+                T::unpack_delta(baseline.as_ref(), reader).map($wrapper::new)
+            }
+        }
+    };
+}
+
+impl_delta_wrapper!(Box);
+impl_delta_wrapper!(Arc);
+impl_delta_wrapper!(Rc);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T>(value: &T, baseline: &T) -> (T, Vec<u8>)
+    where
+        T: PackDelta + UnpackDelta,
+    {
+        let bytes = crate::to_delta_bytes(value, baseline).unwrap();
+        let unpacked = crate::from_delta_bytes(baseline, &bytes).unwrap();
+        (unpacked, bytes)
+    }
+
+    #[test]
+    fn unchanged_is_one_bit() {
+        let name = String::from("a rather long nickname");
+        let (unpacked, bytes) = round_trip(&name, &name);
+        assert_eq!(unpacked, name);
+        assert_eq!(bytes.len(), 1);
+    }
+
+    #[test]
+    fn changed_is_packed_in_full() {
+        let (unpacked, bytes) = round_trip(&Some(1234u32), &None);
+        assert_eq!(unpacked, Some(1234));
+        assert!(bytes.len() > 1);
+
+        let (unpacked, _) = round_trip(&vec![1.5f32, 2.5], &vec![1.5]);
+        assert_eq!(unpacked, vec![1.5, 2.5]);
+
+        let (unpacked, _) = round_trip(&Box::new(-7i64), &Box::new(7));
+        assert_eq!(*unpacked, -7);
+    }
+}
@@ -0,0 +1,267 @@
+//! Fixed-point numbers, for values that have to be exactly the same on every machine.
+//!
+//! A `Fixed<FRAC_BITS>` is an `i32` counting steps of `1 / 2^FRAC_BITS`. Unlike floats, arithmetic
+//! on fixed-point numbers is plain integer arithmetic, so the same operations give the same bits
+//! everywhere, which makes them suitable for values that are simulated on both ends of a
+//! connection. Arithmetic wraps around on overflow, in both debug and release builds.
+//!
+//! Fixed-point numbers are packed as their `i32`, so small values take few bits.
+
+use crate::delta::{self, PackDelta, UnpackDelta};
+use crate::{PackBits, PackedSize, ReadBits, UnpackBits, WriteBits};
+
+use std::fmt::{self, Debug, Display};
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+
+/// A number with `FRAC_BITS` bits after the binary point, and `32 - FRAC_BITS` before it.
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed<const FRAC_BITS: u8>(i32);
+
+impl<const FRAC_BITS: u8> Fixed<FRAC_BITS> {
+    /// The number of steps in one.
+    const SCALE: i64 = {
+        assert!(FRAC_BITS < 32, "a `Fixed` has at most 31 fractional bits");
+        1 << FRAC_BITS
+    };
+
+    pub const ZERO: Fixed<FRAC_BITS> = Fixed(0);
+    pub const ONE: Fixed<FRAC_BITS> = Fixed(Self::SCALE as i32);
+    /// The smallest positive number.
+    pub const EPSILON: Fixed<FRAC_BITS> = Fixed(1);
+    pub const MIN: Fixed<FRAC_BITS> = Fixed(i32::MIN);
+    pub const MAX: Fixed<FRAC_BITS> = Fixed(i32::MAX);
+
+    /// The number with the given raw bits.
+    pub const fn from_bits(bits: i32) -> Fixed<FRAC_BITS> {
+        Fixed(bits)
+    }
+
+    /// The raw bits of the number.
+    pub const fn to_bits(self) -> i32 {
+        self.0
+    }
+
+    /// The integer, wrapping around if it does not fit.
+    pub fn from_int(value: i32) -> Fixed<FRAC_BITS> {
+        Fixed(value.wrapping_shl(u32::from(FRAC_BITS)))
+    }
+
+    /// The number closest to `value`. Values out of range become `MIN` or `MAX`, and NaN becomes
+    /// zero.
+    pub fn from_f32(value: f32) -> Fixed<FRAC_BITS> {
+        Fixed::from_f64(f64::from(value))
+    }
+
+    /// The number closest to `value`. Values out of range become `MIN` or `MAX`, and NaN becomes
+    /// zero.
+    pub fn from_f64(value: f64) -> Fixed<FRAC_BITS> {
+        Fixed((value * Self::SCALE as f64).round() as i32)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.to_f64() as f32
+    }
+
+    pub fn to_f64(self) -> f64 {
+        f64::from(self.0) / Self::SCALE as f64
+    }
+
+    /// The largest integer less than or equal to the number.
+    pub fn floor(self) -> i32 {
+        self.0 >> FRAC_BITS
+    }
+
+    /// The number rounded to the nearest integer, with halves rounded up.
+    pub fn round(self) -> i32 {
+        ((i64::from(self.0) + Self::SCALE / 2) >> FRAC_BITS) as i32
+    }
+
+    pub fn abs(self) -> Fixed<FRAC_BITS> {
+        Fixed(self.0.wrapping_abs())
+    }
+}
+
+impl<const FRAC_BITS: u8> From<i32> for Fixed<FRAC_BITS> {
+    fn from(value: i32) -> Self {
+        Fixed::from_int(value)
+    }
+}
+
+impl<const FRAC_BITS: u8> From<Fixed<FRAC_BITS>> for f32 {
+    fn from(value: Fixed<FRAC_BITS>) -> f32 {
+        value.to_f32()
+    }
+}
+
+impl<const FRAC_BITS: u8> From<Fixed<FRAC_BITS>> for f64 {
+    fn from(value: Fixed<FRAC_BITS>) -> f64 {
+        value.to_f64()
+    }
+}
+
+impl<const FRAC_BITS: u8> Add for Fixed<FRAC_BITS> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Fixed(self.0.wrapping_add(other.0))
+    }
+}
+
+impl<const FRAC_BITS: u8> Sub for Fixed<FRAC_BITS> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Fixed(self.0.wrapping_sub(other.0))
+    }
+}
+
+impl<const FRAC_BITS: u8> Mul for Fixed<FRAC_BITS> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Fixed(((i64::from(self.0) * i64::from(other.0)) >> FRAC_BITS) as i32)
+    }
+}
+
+impl<const FRAC_BITS: u8> Mul<i32> for Fixed<FRAC_BITS> {
+    type Output = Self;
+
+    fn mul(self, other: i32) -> Self {
+        Fixed(self.0.wrapping_mul(other))
+    }
+}
+
+impl<const FRAC_BITS: u8> Div for Fixed<FRAC_BITS> {
+    type Output = Self;
+
+    /// Panics if `other` is zero.
+    fn div(self, other: Self) -> Self {
+        Fixed(((i64::from(self.0) << FRAC_BITS) / i64::from(other.0)) as i32)
+    }
+}
+
+impl<const FRAC_BITS: u8> Neg for Fixed<FRAC_BITS> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Fixed(self.0.wrapping_neg())
+    }
+}
+
+impl<const FRAC_BITS: u8> AddAssign for Fixed<FRAC_BITS> {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl<const FRAC_BITS: u8> SubAssign for Fixed<FRAC_BITS> {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+impl<const FRAC_BITS: u8> Debug for Fixed<FRAC_BITS> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(&self.to_f64(), f)
+    }
+}
+
+impl<const FRAC_BITS: u8> Display for Fixed<FRAC_BITS> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(&self.to_f64(), f)
+    }
+}
+
+impl<const FRAC_BITS: u8> PackBits for Fixed<FRAC_BITS> {
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        self.0.pack(writer)
+    }
+}
+
+impl<const FRAC_BITS: u8> UnpackBits for Fixed<FRAC_BITS> {
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        i32::unpack(reader).map(Fixed)
+    }
+}
+
+impl<const FRAC_BITS: u8> PackedSize for Fixed<FRAC_BITS> {
+    fn packed_bits(&self) -> usize {
+        self.0.packed_bits()
+    }
+
+    fn max_packed_bits() -> Option<usize> {
+        i32::max_packed_bits()
+    }
+}
+
+impl<const FRAC_BITS: u8> PackDelta for Fixed<FRAC_BITS> {
+    fn pack_delta<W>(&self, baseline: &Self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        delta::pack_changed(self, baseline, writer)
+    }
+}
+
+impl<const FRAC_BITS: u8> UnpackDelta for Fixed<FRAC_BITS> {
+    fn unpack_delta<R>(baseline: &Self, reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        delta::unpack_changed(baseline, reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Coord = Fixed<8>;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Coord::ONE.to_bits(), 256);
+        assert_eq!(Coord::from_int(-3).to_f32(), -3.0);
+        assert_eq!(Coord::from_f32(1.5).to_bits(), 384);
+        assert_eq!(Coord::from_f32(0.001), Coord::ZERO);
+        assert_eq!(Coord::from_f32(f32::NAN), Coord::ZERO);
+        assert_eq!(Coord::from_f32(1e12), Coord::MAX);
+        assert_eq!(Coord::from_f32(-1e12), Coord::MIN);
+
+        assert_eq!(Coord::from_f32(-2.25).floor(), -3);
+        assert_eq!(Coord::from_f32(2.5).round(), 3);
+        assert_eq!(Coord::from_f32(-2.75).round(), -3);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = Coord::from_f32(2.5);
+        let b = Coord::from_f32(-0.75);
+        assert_eq!((a + b).to_f32(), 1.75);
+        assert_eq!((a - b).to_f32(), 3.25);
+        assert_eq!((a * b).to_f32(), -1.875);
+        // division rounds towards zero
+        assert_eq!((a / b).to_bits(), -853);
+        assert_eq!((a * 4).to_f32(), 10.0);
+        assert_eq!((-a).abs(), a);
+
+        // overflow wraps around instead of panicking
+        assert_eq!(Coord::MAX + Coord::EPSILON, Coord::MIN);
+    }
+
+    #[test]
+    fn packed_as_integer() {
+        let position = (Coord::from_f32(0.5), Coord::from_f32(-1000.25));
+        let bytes = crate::to_bytes(&position).unwrap();
+        assert_eq!(bytes, crate::to_bytes(&(128i32, -256_064i32)).unwrap());
+        let unpacked: (Coord, Coord) = crate::from_bytes(&bytes).unwrap();
+        assert_eq!(unpacked, position);
+        assert_eq!(crate::size::packed_bytes(&position), bytes.len());
+    }
+}
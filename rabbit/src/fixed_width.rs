@@ -0,0 +1,195 @@
+//! Integers packed in a fixed number of bits, for fields with `#[rabbit(bits = N)]`.
+//!
+//! Integers are normally packed as variable-length quantities. Small values with a known range,
+//! such as a health of 0 to 3, take fewer bits when packed in exactly as many bits as the range
+//! needs. Signed integers are packed in two's complement. Packing fails if the value does not fit
+//! in the bits, and unpacking fails if the bits do not fit in the type.
+
+use crate::read::{self, ReadBits};
+use crate::write::{self, WriteBits};
+
+use std::convert::TryFrom;
+
+/// An integer that can be packed in a fixed number of bits, see `pack` and `unpack`.
+pub trait FixedWidth: Sized {
+    fn pack_fixed<W>(&self, bits: u8, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits;
+
+    fn unpack_fixed<R>(bits: u8, reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits;
+}
+
+/// Pack an integer in exactly `BITS` bits.
+pub fn pack<T, W, const BITS: u8>(value: &T, writer: &mut W) -> Result<(), W::Error>
+where
+    T: FixedWidth,
+    W: WriteBits,
+{
+    value.pack_fixed(BITS, writer)
+}
+
+/// Unpack an integer packed in exactly `BITS` bits.
+pub fn unpack<T, R, const BITS: u8>(reader: &mut R) -> Result<T, R::Error>
+where
+    T: FixedWidth,
+    R: ReadBits,
+{
+    T::unpack_fixed(BITS, reader)
+}
+
+pub(crate) fn write_u64<W: WriteBits>(
+    value: u64,
+    bits: u8,
+    writer: &mut W,
+) -> Result<(), W::Error> {
+    writer.write(value as u32, bits.min(32))?;
+    if bits > 32 {
+        writer.write((value >> 32) as u32, bits - 32)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_u64<R: ReadBits>(bits: u8, reader: &mut R) -> Result<u64, R::Error> {
+    let mut value = u64::from(reader.read(bits.min(32))?);
+    if bits > 32 {
+        value |= u64::from(reader.read(bits - 32)?) << 32;
+    }
+    Ok(value)
+}
+
+/// Fail if `bits` is wider than the widest integer.
+pub(crate) fn check_bits<E: std::fmt::Display>(bits: u8, error: fn(String) -> E) -> Result<(), E> {
+    if bits > 64 {
+        return Err(error(format!("{} bits is wider than 64 bits", bits)));
+    }
+    Ok(())
+}
+
+macro_rules! impl_fixed_width_unsigned {
+    ($($ty:ty),+) => {
+        $(
+            impl FixedWidth for $ty {
+                fn pack_fixed<W>(&self, bits: u8, writer: &mut W) -> Result<(), W::Error>
+                where
+                    W: WriteBits,
+                {
+                    check_bits(bits, write::Error::custom)?;
+                    let value = *self as u64;
+                    if bits < 64 && value >> bits != 0 {
+                        let message = format!("{} does not fit in {} bits", value, bits);
+                        return Err(write::Error::custom(message));
+                    }
+                    write_u64(value, bits, writer)
+                }
+
+                fn unpack_fixed<R>(bits: u8, reader: &mut R) -> Result<Self, R::Error>
+                where
+                    R: ReadBits,
+                {
+                    check_bits(bits, read::Error::custom)?;
+                    let value = read_u64(bits, reader)?;
+                    <$ty>::try_from(value).map_err(|_| {
+                        read::Error::unexpected(concat!("a `", stringify!($ty), "`"), value)
+                    })
+                }
+            }
+        )+
+    };
+}
+
+macro_rules! impl_fixed_width_signed {
+    ($($ty:ty),+) => {
+        $(
+            impl FixedWidth for $ty {
+                fn pack_fixed<W>(&self, bits: u8, writer: &mut W) -> Result<(), W::Error>
+                where
+                    W: WriteBits,
+                {
+                    check_bits(bits, write::Error::custom)?;
+                    let value = *self as i64;
+                    let fits = match bits {
+                        0 => value == 0,
+                        64 => true,
+                        _ => value >> (bits - 1) == 0 || value >> (bits - 1) == -1,
+                    };
+                    if !fits {
+                        let message = format!("{} does not fit in {} bits", value, bits);
+                        return Err(write::Error::custom(message));
+                    }
+                    write_u64(value as u64, bits, writer)
+                }
+
+                fn unpack_fixed<R>(bits: u8, reader: &mut R) -> Result<Self, R::Error>
+                where
+                    R: ReadBits,
+                {
+                    check_bits(bits, read::Error::custom)?;
+                    let value = match bits {
+                        0 => 0,
+                        // move the sign bit to the top to extend it
+                        _ => (read_u64(bits, reader)? << (64 - bits)) as i64 >> (64 - bits),
+                    };
+                    <$ty>::try_from(value).map_err(|_| {
+                        read::Error::unexpected(concat!("a `", stringify!($ty), "`"), value)
+                    })
+                }
+            }
+        )+
+    };
+}
+
+impl_fixed_width_unsigned!(u8, u16, u32, u64, usize);
+impl_fixed_width_signed!(i8, i16, i32, i64, isize);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read::BitReader;
+    use crate::write::BitWriter;
+
+    fn packed<T: FixedWidth>(value: T, bits: u8) -> crate::Result<Vec<u8>> {
+        let mut writer = BitWriter::new();
+        value.pack_fixed(bits, &mut writer)?;
+        Ok(writer.finish())
+    }
+
+    fn unpacked<T: FixedWidth>(bytes: &[u8], bits: u8) -> crate::Result<T> {
+        T::unpack_fixed(bits, &mut BitReader::new(bytes))
+    }
+
+    #[test]
+    fn exact_width() {
+        assert_eq!(packed(3u8, 2).unwrap(), vec![0b11]);
+        assert_eq!(packed(5u64, 3).unwrap(), vec![0b101]);
+        assert_eq!(unpacked::<u16>(&[0b1010_1010], 4).unwrap(), 0b1010);
+        assert!(packed(4u8, 2).is_err());
+        assert!(packed(0u8, 0).unwrap().is_empty());
+
+        let bytes = packed(u64::MAX - 1, 64).unwrap();
+        assert_eq!(unpacked::<u64>(&bytes, 64).unwrap(), u64::MAX - 1);
+    }
+
+    #[test]
+    fn signed() {
+        assert_eq!(packed(-1i8, 3).unwrap(), vec![0b111]);
+        assert_eq!(unpacked::<i32>(&[0b111], 3).unwrap(), -1);
+        assert_eq!(unpacked::<i32>(&[0b011], 3).unwrap(), 3);
+        assert!(packed(4i16, 3).is_err());
+        assert!(packed(-5i16, 3).is_err());
+
+        let bytes = packed(i64::MIN, 64).unwrap();
+        assert_eq!(unpacked::<i64>(&bytes, 64).unwrap(), i64::MIN);
+        let bytes = packed(-(1i64 << 40), 41).unwrap();
+        assert_eq!(unpacked::<i64>(&bytes, 41).unwrap(), -(1i64 << 40));
+    }
+
+    #[test]
+    fn wider_than_type() {
+        let bytes = packed(300u16, 10).unwrap();
+        assert_eq!(unpacked::<u16>(&bytes, 10).unwrap(), 300);
+        assert!(unpacked::<u8>(&bytes, 10).is_err());
+        assert!(packed(1u8, 65).is_err());
+    }
+}
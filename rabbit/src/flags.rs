@@ -0,0 +1,199 @@
+//! Packing `bitflags` types in as few bits as their flags need.
+//!
+//! `impl_flags!` implements the packing traits for types generated by `bitflags!`, using the
+//! `bits`, `all` and `from_bits_truncate` methods it generates. The flags are packed as their raw
+//! bits, up to and including the highest bit of any flag, so four flags take four bits instead of
+//! the eight of a `u8`. Unpacking fails if a bit that does not belong to any flag is set.
+//!
+//! ```ignore
+//! bitflags! {
+//!     pub struct Direction: u8 {
+//!         const NORTH = 1;
+//!         const WEST = 2;
+//!         const SOUTH = 4;
+//!         const EAST = 8;
+//!     }
+//! }
+//!
+//! rabbit::impl_flags!(Direction);
+//! ```
+
+use crate::read::{self, ReadBits};
+use crate::write::WriteBits;
+
+/// The number of bits needed for flags with the raw bits `all` set.
+pub fn width(all: u64) -> u8 {
+    64 - all.leading_zeros() as u8
+}
+
+/// Pack the raw bits of flags, where `all` is the raw bits of every flag.
+pub fn pack<W>(bits: u64, all: u64, writer: &mut W) -> Result<(), W::Error>
+where
+    W: WriteBits,
+{
+    let width = width(all);
+    writer.write(bits as u32, width.min(32))?;
+    if width > 32 {
+        writer.write((bits >> 32) as u32, width - 32)?;
+    }
+    Ok(())
+}
+
+/// Unpack the raw bits of flags packed by `pack` with the same `all`.
+pub fn unpack<R>(all: u64, reader: &mut R) -> Result<u64, R::Error>
+where
+    R: ReadBits,
+{
+    let width = width(all);
+    let mut bits = u64::from(reader.read(width.min(32))?);
+    if width > 32 {
+        bits |= u64::from(reader.read(width - 32)?) << 32;
+    }
+
+    let unknown = bits & !all;
+    if unknown != 0 {
+        let message = format!("unknown flags: {:#b}", unknown);
+        return Err(read::Error::custom(message));
+    }
+
+    Ok(bits)
+}
+
+/// Implement `PackBits`, `UnpackBits`, `PackedSize`, `PackDelta` and `UnpackDelta` for one or more
+/// `bitflags` types, see the `flags` module.
+#[macro_export]
+macro_rules! impl_flags {
+    ($($ty:ty),+ $(,)?) => {
+        $(
+            impl $crate::PackBits for $ty {
+                fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+                where
+                    W: $crate::WriteBits,
+                {
+                    let all = <$ty>::all().bits() as u64;
+                    $crate::flags::pack(self.bits() as u64, all, writer)
+                }
+            }
+
+            impl $crate::UnpackBits for $ty {
+                fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+                where
+                    R: $crate::ReadBits,
+                {
+                    let bits = $crate::flags::unpack(<$ty>::all().bits() as u64, reader)?;
+                    Ok(<$ty>::from_bits_truncate(bits as _))
+                }
+            }
+
+            impl $crate::PackedSize for $ty {
+                fn packed_bits(&self) -> usize {
+                    usize::from($crate::flags::width(<$ty>::all().bits() as u64))
+                }
+
+                fn max_packed_bits() -> Option<usize> {
+                    Some(usize::from($crate::flags::width(<$ty>::all().bits() as u64)))
+                }
+            }
+
+            impl $crate::PackDelta for $ty {
+                fn pack_delta<W>(&self, baseline: &Self, writer: &mut W) -> Result<(), W::Error>
+                where
+                    W: $crate::WriteBits,
+                {
+                    $crate::delta::pack_changed(self, baseline, writer)
+                }
+            }
+
+            impl $crate::UnpackDelta for $ty {
+                fn unpack_delta<R>(baseline: &Self, reader: &mut R) -> Result<Self, R::Error>
+                where
+                    R: $crate::ReadBits,
+                {
+                    $crate::delta::unpack_changed(baseline, reader)
+                }
+            }
+        )+
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::PackedSize;
+
+    /// What `bitflags!` generates, as far as `impl_flags!` is concerned.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Direction {
+        bits: u8,
+    }
+
+    impl Direction {
+        const NORTH: Direction = Direction { bits: 1 };
+        const EAST: Direction = Direction { bits: 8 };
+
+        fn all() -> Direction {
+            Direction { bits: 15 }
+        }
+
+        fn bits(&self) -> u8 {
+            self.bits
+        }
+
+        fn from_bits_truncate(bits: u8) -> Direction {
+            Direction { bits: bits & 15 }
+        }
+    }
+
+    /// Flags spread over more than 32 bits.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Wide {
+        bits: u64,
+    }
+
+    impl Wide {
+        fn all() -> Wide {
+            Wide { bits: 1 | 1 << 40 }
+        }
+
+        fn bits(&self) -> u64 {
+            self.bits
+        }
+
+        fn from_bits_truncate(bits: u64) -> Wide {
+            Wide {
+                bits: bits & Wide::all().bits,
+            }
+        }
+    }
+
+    crate::impl_flags!(Direction, Wide);
+
+    #[test]
+    fn minimal_bits() {
+        let value = Direction::from_bits_truncate(Direction::NORTH.bits | Direction::EAST.bits);
+        assert_eq!(value.packed_bits(), 4);
+        assert_eq!(Direction::max_packed_bits(), Some(4));
+
+        let bytes = crate::to_bytes(&(value, value)).unwrap();
+        assert_eq!(bytes, vec![0b1001_1001]);
+        let unpacked: (Direction, Direction) = crate::from_bytes(&bytes).unwrap();
+        assert_eq!(unpacked, (value, value));
+    }
+
+    #[test]
+    fn wide_flags() {
+        let value = Wide::all();
+        assert_eq!(value.packed_bits(), 41);
+        let bytes = crate::to_bytes(&value).unwrap();
+        assert_eq!(crate::from_bytes::<Wide>(&bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn unknown_flags() {
+        let bytes = [1, 0, 0, 0, 0, 0];
+        assert_eq!(crate::from_bytes::<Wide>(&bytes).unwrap(), Wide { bits: 1 });
+
+        // the second bit belongs to no flag
+        let bytes = [3, 0, 0, 0, 0, 0];
+        assert!(crate::from_bytes::<Wide>(&bytes).is_err());
+    }
+}
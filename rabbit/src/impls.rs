@@ -1,7 +1,22 @@
+mod arrays;
+mod collections;
+mod time;
+#[cfg(feature = "uuid")]
+mod uuid;
+#[cfg(feature = "cgmath")]
+mod vectors;
 mod vlq;
 
-use crate::{read::Error as _, PackBits, ReadBits, UnpackBits, WriteBits};
+pub(crate) use vlq::VariableLengthQuantity;
 
+use crate::{read, read::Error as _, PackBits, ReadBits, UnpackBits, WriteBits};
+
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use std::num::{
+    NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU128,
+    NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize,
+};
 use std::rc::Rc;
 use std::sync::Arc;
 
@@ -43,6 +58,25 @@ impl UnpackBits for u8 {
     }
 }
 
+impl PackBits for i8 {
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        writer.write(*self as u8 as u32, 8)
+    }
+}
+
+impl UnpackBits for i8 {
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        let value = reader.read(8)? as u8 as i8;
+        Ok(value)
+    }
+}
+
 macro_rules! impl_bit_packing_integer {
     ($ty:ty) => {
         impl PackBits for $ty {
@@ -77,6 +111,43 @@ impl_bit_packing_integer!(i64);
 impl_bit_packing_integer!(i128);
 impl_bit_packing_integer!(isize);
 
+macro_rules! impl_bit_packing_non_zero {
+    ($ty:ident, $inner:ty) => {
+        impl PackBits for $ty {
+            fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+            where
+                W: WriteBits,
+            {
+                self.get().pack(writer)
+            }
+        }
+
+        impl UnpackBits for $ty {
+            fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+            where
+                R: ReadBits,
+            {
+                let value = <$inner>::unpack(reader)?;
+                $ty::new(value).ok_or_else(|| R::Error::unexpected("a non-zero integer", 0))
+            }
+        }
+    };
+}
+
+impl_bit_packing_non_zero!(NonZeroU8, u8);
+impl_bit_packing_non_zero!(NonZeroU16, u16);
+impl_bit_packing_non_zero!(NonZeroU32, u32);
+impl_bit_packing_non_zero!(NonZeroU64, u64);
+impl_bit_packing_non_zero!(NonZeroU128, u128);
+impl_bit_packing_non_zero!(NonZeroUsize, usize);
+
+impl_bit_packing_non_zero!(NonZeroI8, i8);
+impl_bit_packing_non_zero!(NonZeroI16, i16);
+impl_bit_packing_non_zero!(NonZeroI32, i32);
+impl_bit_packing_non_zero!(NonZeroI64, i64);
+impl_bit_packing_non_zero!(NonZeroI128, i128);
+impl_bit_packing_non_zero!(NonZeroIsize, isize);
+
 impl PackBits for f32 {
     fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
     where
@@ -142,6 +213,8 @@ impl<T> UnpackBits for Option<T>
 where
     T: UnpackBits,
 {
+    const UNPACKS_REST: bool = T::UNPACKS_REST;
+
     fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
     where
         R: ReadBits,
@@ -149,7 +222,7 @@ where
         if reader.read(1)? == 0 {
             Ok(None)
         } else {
-            T::unpack(reader).map(Some)
+            T::unpack(reader).map(Some).map_err(|e| e.within("Some"))
         }
     }
 }
@@ -178,10 +251,11 @@ where
     where
         R: ReadBits,
     {
-        let len = u32::unpack(reader)?;
-        let mut data = Vec::with_capacity(len as usize);
-        for _ in 0..len {
-            let item = T::unpack(reader)?;
+        const { read::assert_not_trailing::<T>() };
+        let len = read::unpack_len(reader)?;
+        let mut data = Vec::with_capacity(len);
+        for i in 0..len {
+            let item = T::unpack(reader).map_err(|e| e.within(format_args!("[{}]", i)))?;
             data.push(item);
         }
         Ok(data)
@@ -210,7 +284,7 @@ impl PackBits for String {
     where
         W: WriteBits,
     {
-        self.as_bytes().pack(writer)
+        self.as_str().pack(writer)
     }
 }
 
@@ -243,6 +317,8 @@ macro_rules! impl_wrapper {
         where
             T: UnpackBits,
         {
+            const UNPACKS_REST: bool = T::UNPACKS_REST;
+
             fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
             where
                 R: ReadBits,
@@ -257,8 +333,63 @@ impl_wrapper!(Box);
 impl_wrapper!(Arc);
 impl_wrapper!(Rc);
 
+impl PackBits for str {
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        self.as_bytes().pack(writer)
+    }
+}
+
+/// Borrowed and owned values are packed the same way, and always unpacked as owned.
+impl<'a, T> PackBits for Cow<'a, T>
+where
+    T: PackBits + ToOwned + ?Sized,
+{
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        self.as_ref().pack(writer)
+    }
+}
+
+impl<'a, T> UnpackBits for Cow<'a, T>
+where
+    T: ToOwned + ?Sized,
+    T::Owned: UnpackBits,
+{
+    const UNPACKS_REST: bool = T::Owned::UNPACKS_REST;
+
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        T::Owned::unpack(reader).map(Cow::Owned)
+    }
+}
+
+impl<T: ?Sized> PackBits for PhantomData<T> {
+    fn pack<W>(&self, _writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        Ok(())
+    }
+}
+
+impl<T: ?Sized> UnpackBits for PhantomData<T> {
+    fn unpack<R>(_reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        Ok(PhantomData)
+    }
+}
+
 macro_rules! impl_bit_packing_tuple {
-    ($($ident:ident),+) => {
+    ($($index:tt $ident:ident),+) => {
         impl<$($ident: PackBits),*> PackBits for ($($ident,)*) {
             #[allow(non_snake_case)]
             fn pack<W: WriteBits>(&self, writer: &mut W) -> Result<(), W::Error> {
@@ -270,14 +401,93 @@ macro_rules! impl_bit_packing_tuple {
 
         impl<$($ident: UnpackBits),*> UnpackBits for ($($ident,)*) {
             fn unpack<R: ReadBits>(reader: &mut R) -> Result<Self, R::Error> {
-                Ok(($( $ident::unpack(reader)? ,)*))
+                $( const { read::assert_not_trailing::<$ident>() }; )*
+                Ok(($( $ident::unpack(reader).map_err(|e| e.within($index))? ,)*))
             }
         }
     };
 }
 
-impl_bit_packing_tuple!(A);
-impl_bit_packing_tuple!(A, B);
-impl_bit_packing_tuple!(A, B, C);
-impl_bit_packing_tuple!(A, B, C, D);
-impl_bit_packing_tuple!(A, B, C, D, E);
+impl_bit_packing_tuple!(0 A);
+impl_bit_packing_tuple!(0 A, 1 B);
+impl_bit_packing_tuple!(0 A, 1 B, 2 C);
+impl_bit_packing_tuple!(0 A, 1 B, 2 C, 3 D);
+impl_bit_packing_tuple!(0 A, 1 B, 2 C, 3 D, 4 E);
+impl_bit_packing_tuple!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F);
+impl_bit_packing_tuple!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G);
+impl_bit_packing_tuple!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H);
+impl_bit_packing_tuple!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I);
+impl_bit_packing_tuple!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I, 9 J);
+impl_bit_packing_tuple!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I, 9 J, 10 K);
+impl_bit_packing_tuple!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I, 9 J, 10 K, 11 L);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_zero_integers() {
+        let id = NonZeroU32::new(42).unwrap();
+        let bytes = crate::to_bytes(&Some(id)).unwrap();
+        let unpacked = crate::from_bytes::<Option<NonZeroU32>>(&bytes).unwrap();
+        assert_eq!(unpacked, Some(id));
+
+        let offset = NonZeroI8::new(-3).unwrap();
+        let bytes = crate::to_bytes(&offset).unwrap();
+        assert_eq!(crate::from_bytes::<NonZeroI8>(&bytes).unwrap(), offset);
+
+        let bytes = crate::to_bytes(&0u32).unwrap();
+        assert!(crate::from_bytes::<NonZeroU32>(&bytes).is_err());
+    }
+
+    #[test]
+    fn cows_unpack_owned() {
+        let message: Cow<'static, str> = Cow::Borrowed("out of snow");
+        let bytes = crate::to_bytes(&message).unwrap();
+        let owned = String::from("out of snow");
+        assert_eq!(bytes, crate::to_bytes(&owned).unwrap());
+
+        let unpacked = crate::from_bytes::<Cow<'static, str>>(&bytes).unwrap();
+        assert!(matches!(unpacked, Cow::Owned(_)));
+        assert_eq!(unpacked, message);
+
+        let items: Cow<[u16]> = Cow::Borrowed(&[1, 2, 3]);
+        let bytes = crate::to_bytes(&items).unwrap();
+        assert_eq!(crate::from_bytes::<Cow<[u16]>>(&bytes).unwrap(), items);
+    }
+
+    #[test]
+    fn phantom_data_is_empty() {
+        let bytes = crate::to_bytes(&(7u8, PhantomData::<String>)).unwrap();
+        assert_eq!(bytes.len(), 1);
+        let (value, PhantomData) = crate::from_bytes::<(u8, PhantomData<String>)>(&bytes).unwrap();
+        assert_eq!(value, 7);
+    }
+
+    #[test]
+    fn item_errors_located() {
+        let error_path = |error| match error {
+            crate::Error::Context(context) => context.path(),
+            error => panic!("no context: {}", error),
+        };
+
+        let bytes = crate::to_bytes(&[1u8, 0]).unwrap();
+        let error = crate::from_bytes::<[NonZeroU8; 2]>(&bytes).unwrap_err();
+        assert_eq!(error_path(error), "[1]");
+
+        let bytes = crate::to_bytes(&Some((1u8, [1u8, 0]))).unwrap();
+        let error = crate::from_bytes::<Option<(u8, [NonZeroU8; 2])>>(&bytes).unwrap_err();
+        assert_eq!(error_path(error), "Some.1[1]");
+    }
+
+    #[test]
+    fn large_tuples() {
+        let value = (
+            1u8, 2u16, 3u32, 4u64, true, 6i8, 7i16, 8i32, 9f32, 10f64, 11u128, 12u8,
+        );
+        let bytes = crate::to_bytes(&value).unwrap();
+        let unpacked: (u8, u16, u32, u64, bool, i8, i16, i32, f32, f64, u128, u8) =
+            crate::from_bytes(&bytes).unwrap();
+        assert_eq!(unpacked, value);
+    }
+}
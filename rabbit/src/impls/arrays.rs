@@ -0,0 +1,85 @@
+//! Arrays are packed as every item in order. Unlike `Vec` and slices there is no length prefix,
+//! since the length is part of the type.
+
+use crate::{read, read::Error as _, PackBits, PackedSize, ReadBits, UnpackBits, WriteBits};
+
+use std::convert::TryInto;
+
+impl<T, const N: usize> PackBits for [T; N]
+where
+    T: PackBits,
+{
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        for item in self {
+            item.pack(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T, const N: usize> UnpackBits for [T; N]
+where
+    T: UnpackBits,
+{
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        const { read::assert_not_trailing::<T>() };
+        let mut data = Vec::with_capacity(N);
+        for i in 0..N {
+            let item = T::unpack(reader).map_err(|e| e.within(format_args!("[{}]", i)))?;
+            data.push(item);
+        }
+
+        match data.try_into() {
+            Ok(array) => Ok(array),
+            Err(_) => unreachable!("unpacked exactly {} items", N),
+        }
+    }
+}
+
+impl<T, const N: usize> PackedSize for [T; N]
+where
+    T: PackedSize,
+{
+    fn packed_bits(&self) -> usize {
+        self.iter().map(T::packed_bits).sum()
+    }
+
+    fn max_packed_bits() -> Option<usize> {
+        T::max_packed_bits().map(|bits| N * bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn arrays_lossless() {
+        let color: [u8; 3] = [12, 200, 255];
+        let bytes = crate::to_bytes(&color).unwrap();
+        assert_eq!(bytes.len(), 3);
+        assert_eq!(crate::from_bytes::<[u8; 3]>(&bytes).unwrap(), color);
+
+        let rows = [[1.0f32, 2.0], [3.0, 4.0]];
+        let bytes = crate::to_bytes(&rows).unwrap();
+        assert_eq!(crate::from_bytes::<[[f32; 2]; 2]>(&bytes).unwrap(), rows);
+
+        let names = [String::from("snow"), String::from("ice")];
+        let bytes = crate::to_bytes(&names).unwrap();
+        assert_eq!(crate::from_bytes::<[String; 2]>(&bytes).unwrap(), names);
+
+        let empty: [u32; 0] = [];
+        let bytes = crate::to_bytes(&empty).unwrap();
+        assert_eq!(crate::from_bytes::<[u32; 0]>(&bytes).unwrap(), empty);
+    }
+
+    #[test]
+    fn truncated_array_rejected() {
+        let bytes = crate::to_bytes(&[1u8, 2]).unwrap();
+        assert!(crate::from_bytes::<[u8; 3]>(&bytes).is_err());
+    }
+}
@@ -0,0 +1,360 @@
+//! Collections are packed like `Vec`: the number of items followed by every item. Maps pack each
+//! entry as its key followed by its value.
+//!
+//! Errors note the position of the failing item, such as `[3]`. The key of the fourth entry of a
+//! map is noted as `{3}` and its value as `[3]`, since the key is not known.
+
+use crate::size::sequence_bits;
+use crate::{read, read::Error as _, PackBits, PackedSize, ReadBits, UnpackBits, WriteBits};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hash};
+
+impl<T> PackBits for VecDeque<T>
+where
+    T: PackBits,
+{
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        (self.len() as u32).pack(writer)?;
+        for item in self {
+            item.pack(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> UnpackBits for VecDeque<T>
+where
+    T: UnpackBits,
+{
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        const { read::assert_not_trailing::<T>() };
+        let len = read::unpack_len(reader)?;
+        let mut data = VecDeque::with_capacity(len);
+        for i in 0..len {
+            let item = T::unpack(reader).map_err(|e| e.within(format_args!("[{}]", i)))?;
+            data.push_back(item);
+        }
+        Ok(data)
+    }
+}
+
+impl<T, S> PackBits for HashSet<T, S>
+where
+    T: PackBits,
+{
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        (self.len() as u32).pack(writer)?;
+        for item in self {
+            item.pack(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T, S> UnpackBits for HashSet<T, S>
+where
+    T: UnpackBits + Eq + Hash,
+    S: BuildHasher + Default,
+{
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        const { read::assert_not_trailing::<T>() };
+        let len = read::unpack_len(reader)?;
+        let mut set = HashSet::with_capacity_and_hasher(len, S::default());
+        for i in 0..len {
+            let item = T::unpack(reader).map_err(|e| e.within(format_args!("[{}]", i)))?;
+            if !set.insert(item) {
+                let error = R::Error::custom("duplicate item in set");
+                return Err(error.within(format_args!("[{}]", i)));
+            }
+        }
+        Ok(set)
+    }
+}
+
+impl<T> PackBits for BTreeSet<T>
+where
+    T: PackBits,
+{
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        (self.len() as u32).pack(writer)?;
+        for item in self {
+            item.pack(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> UnpackBits for BTreeSet<T>
+where
+    T: UnpackBits + Ord,
+{
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        const { read::assert_not_trailing::<T>() };
+        let len = read::unpack_len(reader)?;
+        let mut set = BTreeSet::new();
+        for i in 0..len {
+            let item = T::unpack(reader).map_err(|e| e.within(format_args!("[{}]", i)))?;
+            if !set.insert(item) {
+                let error = R::Error::custom("duplicate item in set");
+                return Err(error.within(format_args!("[{}]", i)));
+            }
+        }
+        Ok(set)
+    }
+}
+
+impl<K, V, S> PackBits for HashMap<K, V, S>
+where
+    K: PackBits,
+    V: PackBits,
+{
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        (self.len() as u32).pack(writer)?;
+        for (key, value) in self {
+            key.pack(writer)?;
+            value.pack(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K, V, S> UnpackBits for HashMap<K, V, S>
+where
+    K: UnpackBits + Eq + Hash,
+    V: UnpackBits,
+    S: BuildHasher + Default,
+{
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        const { read::assert_not_trailing::<K>() };
+        const { read::assert_not_trailing::<V>() };
+        let len = read::unpack_len(reader)?;
+        let mut map = HashMap::with_capacity_and_hasher(len, S::default());
+        for i in 0..len {
+            let key = K::unpack(reader).map_err(|e| e.within(format_args!("{{{}}}", i)))?;
+            let value = V::unpack(reader).map_err(|e| e.within(format_args!("[{}]", i)))?;
+            if map.insert(key, value).is_some() {
+                let error = R::Error::custom("duplicate key in map");
+                return Err(error.within(format_args!("{{{}}}", i)));
+            }
+        }
+        Ok(map)
+    }
+}
+
+impl<K, V> PackBits for BTreeMap<K, V>
+where
+    K: PackBits,
+    V: PackBits,
+{
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        (self.len() as u32).pack(writer)?;
+        for (key, value) in self {
+            key.pack(writer)?;
+            value.pack(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> UnpackBits for BTreeMap<K, V>
+where
+    K: UnpackBits + Ord,
+    V: UnpackBits,
+{
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        const { read::assert_not_trailing::<K>() };
+        const { read::assert_not_trailing::<V>() };
+        let len = read::unpack_len(reader)?;
+        let mut map = BTreeMap::new();
+        for i in 0..len {
+            let key = K::unpack(reader).map_err(|e| e.within(format_args!("{{{}}}", i)))?;
+            let value = V::unpack(reader).map_err(|e| e.within(format_args!("[{}]", i)))?;
+            if map.insert(key, value).is_some() {
+                let error = R::Error::custom("duplicate key in map");
+                return Err(error.within(format_args!("{{{}}}", i)));
+            }
+        }
+        Ok(map)
+    }
+}
+
+impl<T> PackedSize for VecDeque<T>
+where
+    T: PackedSize,
+{
+    fn packed_bits(&self) -> usize {
+        sequence_bits(self.len(), self.iter().map(T::packed_bits))
+    }
+}
+
+impl<T, S> PackedSize for HashSet<T, S>
+where
+    T: PackedSize,
+{
+    fn packed_bits(&self) -> usize {
+        sequence_bits(self.len(), self.iter().map(T::packed_bits))
+    }
+}
+
+impl<T> PackedSize for BTreeSet<T>
+where
+    T: PackedSize,
+{
+    fn packed_bits(&self) -> usize {
+        sequence_bits(self.len(), self.iter().map(T::packed_bits))
+    }
+}
+
+impl<K, V, S> PackedSize for HashMap<K, V, S>
+where
+    K: PackedSize,
+    V: PackedSize,
+{
+    fn packed_bits(&self) -> usize {
+        let entries = self
+            .iter()
+            .map(|(key, value)| key.packed_bits() + value.packed_bits());
+        sequence_bits(self.len(), entries)
+    }
+}
+
+impl<K, V> PackedSize for BTreeMap<K, V>
+where
+    K: PackedSize,
+    V: PackedSize,
+{
+    fn packed_bits(&self) -> usize {
+        let entries = self
+            .iter()
+            .map(|(key, value)| key.packed_bits() + value.packed_bits());
+        sequence_bits(self.len(), entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T>(value: &T) -> T
+    where
+        T: PackBits + UnpackBits,
+    {
+        let bytes = crate::to_bytes(value).unwrap();
+        crate::from_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    fn sequences_lossless() {
+        let deque: VecDeque<u32> = (0..100).collect();
+        assert_eq!(round_trip(&deque), deque);
+
+        let set: HashSet<String> = vec!["snow".to_owned(), "ice".to_owned()]
+            .into_iter()
+            .collect();
+        assert_eq!(round_trip(&set), set);
+
+        let set: BTreeSet<i16> = (-50..50).collect();
+        assert_eq!(round_trip(&set), set);
+    }
+
+    #[test]
+    fn maps_lossless() {
+        let map: HashMap<u32, String> = (0..20).map(|i| (i, i.to_string())).collect();
+        assert_eq!(round_trip(&map), map);
+
+        let map: BTreeMap<String, Vec<bool>> = (0..20)
+            .map(|i| (i.to_string(), vec![i % 2 == 0; i]))
+            .collect();
+        assert_eq!(round_trip(&map), map);
+    }
+
+    #[test]
+    fn sizes_exact() {
+        let deque: VecDeque<u32> = (0..100).collect();
+        assert_eq!(
+            deque.packed_bits(),
+            crate::size::counted_bits(|w| deque.pack(w))
+        );
+
+        let map: BTreeMap<String, Vec<bool>> = (0..20)
+            .map(|i| (i.to_string(), vec![i % 2 == 0; i]))
+            .collect();
+        assert_eq!(
+            map.packed_bits(),
+            crate::size::counted_bits(|w| map.pack(w))
+        );
+    }
+
+    #[test]
+    fn duplicate_keys_rejected() {
+        let bytes = crate::to_bytes(&vec![(1u8, 2u8), (1u8, 3u8)]).unwrap();
+        assert!(crate::from_bytes::<HashMap<u8, u8>>(&bytes).is_err());
+        assert!(crate::from_bytes::<BTreeMap<u8, u8>>(&bytes).is_err());
+
+        let bytes = crate::to_bytes(&vec![7u8, 7u8]).unwrap();
+        assert!(crate::from_bytes::<HashSet<u8>>(&bytes).is_err());
+        assert!(crate::from_bytes::<BTreeSet<u8>>(&bytes).is_err());
+    }
+
+    fn error_path<T: UnpackBits>(bytes: &[u8]) -> String {
+        match crate::from_bytes::<T>(bytes) {
+            Err(crate::Error::Context(context)) => context.path(),
+            Err(error) => panic!("no context: {}", error),
+            Ok(_) => panic!("unpacked malformed bytes"),
+        }
+    }
+
+    #[test]
+    fn item_errors_located() {
+        use std::num::NonZeroU8;
+
+        let bytes = crate::to_bytes(&vec![1u8, 2, 0]).unwrap();
+        assert_eq!(error_path::<VecDeque<NonZeroU8>>(&bytes), "[2]");
+        assert_eq!(error_path::<HashSet<NonZeroU8>>(&bytes), "[2]");
+        assert_eq!(error_path::<BTreeSet<NonZeroU8>>(&bytes), "[2]");
+
+        let bytes = crate::to_bytes(&vec![1u8, 1]).unwrap();
+        assert_eq!(error_path::<BTreeSet<u8>>(&bytes), "[1]");
+
+        // the key of the second entry is zero
+        let bytes = crate::to_bytes(&vec![(1u8, 1u8), (0, 1)]).unwrap();
+        assert_eq!(error_path::<HashMap<NonZeroU8, NonZeroU8>>(&bytes), "{1}");
+        assert_eq!(error_path::<BTreeMap<NonZeroU8, NonZeroU8>>(&bytes), "{1}");
+
+        // the value of the second entry is zero
+        let bytes = crate::to_bytes(&vec![(1u8, vec![1u8]), (2, vec![1, 0])]).unwrap();
+        assert_eq!(error_path::<HashMap<u8, Vec<NonZeroU8>>>(&bytes), "[1][1]");
+        assert_eq!(error_path::<BTreeMap<u8, Vec<NonZeroU8>>>(&bytes), "[1][1]");
+    }
+}
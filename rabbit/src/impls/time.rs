@@ -0,0 +1,110 @@
+//! A `Duration` is packed as its whole seconds followed by the remaining nanoseconds, both as
+//! variable length integers. A `SystemTime` is packed as the `Duration` since the unix epoch.
+
+use crate::{
+    read::Error as _, write::Error as _, PackBits, PackedSize, ReadBits, UnpackBits, WriteBits,
+};
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+impl PackBits for Duration {
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        self.as_secs().pack(writer)?;
+        self.subsec_nanos().pack(writer)
+    }
+}
+
+impl UnpackBits for Duration {
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        let secs = u64::unpack(reader)?;
+        let nanos = u32::unpack(reader)?;
+        if nanos >= NANOS_PER_SEC {
+            return Err(R::Error::custom("duration nanoseconds out of range"));
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
+impl PackBits for SystemTime {
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        let since_epoch = self
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| W::Error::custom("time is before the unix epoch"))?;
+        since_epoch.pack(writer)
+    }
+}
+
+impl UnpackBits for SystemTime {
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        let since_epoch = Duration::unpack(reader)?;
+        UNIX_EPOCH
+            .checked_add(since_epoch)
+            .ok_or_else(|| R::Error::custom("time out of range"))
+    }
+}
+
+impl PackedSize for Duration {
+    fn packed_bits(&self) -> usize {
+        self.as_secs().packed_bits() + self.subsec_nanos().packed_bits()
+    }
+
+    fn max_packed_bits() -> Option<usize> {
+        Some(u64::max_packed_bits()? + (NANOS_PER_SEC - 1).packed_bits())
+    }
+}
+
+/// A time before the unix epoch can not be packed, and is counted as the epoch itself.
+impl PackedSize for SystemTime {
+    fn packed_bits(&self) -> usize {
+        let since_epoch = self.duration_since(UNIX_EPOCH).unwrap_or_default();
+        since_epoch.packed_bits()
+    }
+
+    fn max_packed_bits() -> Option<usize> {
+        Duration::max_packed_bits()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_lossless() {
+        let durations = [
+            Duration::from_secs(0),
+            Duration::from_millis(16),
+            Duration::new(90, 999_999_999),
+            Duration::from_secs(u64::MAX),
+        ];
+
+        for duration in &durations {
+            let bytes = crate::to_bytes(duration).unwrap();
+            assert_eq!(crate::from_bytes::<Duration>(&bytes).unwrap(), *duration);
+        }
+
+        let now = SystemTime::now();
+        let bytes = crate::to_bytes(&now).unwrap();
+        assert_eq!(crate::from_bytes::<SystemTime>(&bytes).unwrap(), now);
+    }
+
+    #[test]
+    fn invalid_nanos_rejected() {
+        let bytes = crate::to_bytes(&(1u64, NANOS_PER_SEC)).unwrap();
+        assert!(crate::from_bytes::<Duration>(&bytes).is_err());
+    }
+}
@@ -0,0 +1,62 @@
+//! A `Uuid` is packed as its 128 raw bits, most significant first. The bits of an identifier are
+//! evenly distributed, so a variable length encoding would only add overhead.
+
+use crate::{PackBits, PackedSize, ReadBits, UnpackBits, WriteBits};
+
+use uuid::Uuid;
+
+impl PackBits for Uuid {
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        let bits = self.as_u128();
+        for shift in (0..4).rev() {
+            writer.write((bits >> (32 * shift)) as u32, 32)?;
+        }
+        Ok(())
+    }
+}
+
+impl UnpackBits for Uuid {
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        let mut bits = 0u128;
+        for _ in 0..4 {
+            bits = bits << 32 | reader.read(32)? as u128;
+        }
+        Ok(Uuid::from_u128(bits))
+    }
+}
+
+impl PackedSize for Uuid {
+    fn packed_bits(&self) -> usize {
+        128
+    }
+
+    fn max_packed_bits() -> Option<usize> {
+        Some(128)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uuid_lossless() {
+        let ids = [
+            Uuid::nil(),
+            Uuid::from_u128(u128::MAX),
+            Uuid::from_u128(0x936d_a01f_9abd_4d9d_80c7_02af_85c8_22a8),
+        ];
+
+        for id in &ids {
+            let bytes = crate::to_bytes(id).unwrap();
+            assert_eq!(bytes.len(), 16);
+            assert_eq!(crate::from_bytes::<Uuid>(&bytes).unwrap(), *id);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Vectors, points and quaternions from `cgmath` are packed as their components in order, without
+//! any length prefix.
+
+use crate::{PackBits, PackedSize, ReadBits, UnpackBits, WriteBits};
+
+use cgmath::{Point2, Point3, Quaternion, Vector2, Vector3, Vector4};
+
+macro_rules! impl_bit_packing_components {
+    ($ty:ident { $($field:ident),+ }) => {
+        impl<T: PackBits> PackBits for $ty<T> {
+            fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+            where
+                W: WriteBits,
+            {
+                $( self.$field.pack(writer)?; )+
+                Ok(())
+            }
+        }
+
+        impl<T: UnpackBits> UnpackBits for $ty<T> {
+            fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+            where
+                R: ReadBits,
+            {
+                $( let $field = T::unpack(reader)?; )+
+                Ok($ty { $($field),+ })
+            }
+        }
+
+        impl<T: PackedSize> PackedSize for $ty<T> {
+            fn packed_bits(&self) -> usize {
+                0 $( + self.$field.packed_bits() )+
+            }
+
+            fn max_packed_bits() -> Option<usize> {
+                let components = [$( stringify!($field) ),+].len();
+                T::max_packed_bits().map(|bits| components * bits)
+            }
+        }
+    };
+}
+
+impl_bit_packing_components!(Vector2 { x, y });
+impl_bit_packing_components!(Vector3 { x, y, z });
+impl_bit_packing_components!(Vector4 { x, y, z, w });
+impl_bit_packing_components!(Point2 { x, y });
+impl_bit_packing_components!(Point3 { x, y, z });
+
+/// Packed as the scalar part followed by the vector part.
+impl<T: PackBits> PackBits for Quaternion<T> {
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        self.s.pack(writer)?;
+        self.v.pack(writer)
+    }
+}
+
+impl<T: UnpackBits> UnpackBits for Quaternion<T> {
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        let s = T::unpack(reader)?;
+        let v = Vector3::unpack(reader)?;
+        Ok(Quaternion { s, v })
+    }
+}
+
+impl<T: PackedSize> PackedSize for Quaternion<T> {
+    fn packed_bits(&self) -> usize {
+        self.s.packed_bits() + self.v.packed_bits()
+    }
+
+    fn max_packed_bits() -> Option<usize> {
+        Some(T::max_packed_bits()? + Vector3::<T>::max_packed_bits()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vectors_lossless() {
+        let velocity = Vector3::new(1.5f32, -2.0, 0.25);
+        let bytes = crate::to_bytes(&velocity).unwrap();
+        assert_eq!(bytes, crate::to_bytes(&[1.5f32, -2.0, 0.25]).unwrap());
+        assert_eq!(crate::from_bytes::<Vector3<f32>>(&bytes).unwrap(), velocity);
+
+        let color = Vector4::new(1u8, 2, 3, 4);
+        let bytes = crate::to_bytes(&color).unwrap();
+        assert_eq!(crate::from_bytes::<Vector4<u8>>(&bytes).unwrap(), color);
+
+        let cell = Point2::new(-3i32, 7);
+        let bytes = crate::to_bytes(&cell).unwrap();
+        assert_eq!(crate::from_bytes::<Point2<i32>>(&bytes).unwrap(), cell);
+
+        let rotation = Quaternion::new(1.0f64, 0.0, 0.5, -0.5);
+        let bytes = crate::to_bytes(&rotation).unwrap();
+        let unpacked = crate::from_bytes::<Quaternion<f64>>(&bytes).unwrap();
+        assert_eq!(unpacked, rotation);
+    }
+}
@@ -53,6 +53,12 @@ use crate::{read::Error, ReadBits, WriteBits};
 pub(crate) trait VariableLengthQuantity: Default + Sized {
     fn encode<W: WriteBits>(self, writer: &mut W) -> Result<(), W::Error>;
     fn decode<R: ReadBits>(reader: &mut R) -> Result<Self, R::Error>;
+
+    /// The number of bits `encode` writes.
+    fn encoded_bits(self) -> usize;
+
+    /// The number of bits `encode` writes for the largest value.
+    fn max_encoded_bits() -> usize;
 }
 
 macro_rules! index_bits {
@@ -107,6 +113,17 @@ macro_rules! impl_vlq_unsigned {
 
                 Ok(value)
             }
+
+            fn encoded_bits(self) -> usize {
+                const SIZE: $ty = std::mem::size_of::<$ty>() as $ty;
+
+                let additional_bytes = index_bits!(self).saturating_sub(1) / 8;
+                (index_bits!(SIZE - 1) + 8 * (additional_bytes + 1)) as usize
+            }
+
+            fn max_encoded_bits() -> usize {
+                <$ty>::max_value().encoded_bits()
+            }
         }
     };
 }
@@ -144,6 +161,16 @@ macro_rules! impl_vlq_signed {
 
                 Ok(zig_zag)
             }
+
+            fn encoded_bits(self) -> usize {
+                const BITS: usize = 8 * std::mem::size_of::<$signed>();
+                let zig_zag = (self << 1) ^ (self >> (BITS - 1));
+                (zig_zag as $unsigned).encoded_bits()
+            }
+
+            fn max_encoded_bits() -> usize {
+                <$unsigned>::max_encoded_bits()
+            }
         }
     };
 }
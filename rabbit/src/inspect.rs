@@ -0,0 +1,555 @@
+//! Describing packed bytes as a tree of fields, for debugging.
+//!
+//! Types that implement `Inspect` describe the fields they are packed as in a `Schema`. The schema
+//! is used to unpack bytes one field at a time, noting where each field starts and ends and what
+//! value it holds. The unpacked `Node`s are displayed as a tree:
+//!
+//! ```text
+//! Throw @0..72
+//!   power = 7 @0..8
+//!   targets = 2 items @8..72
+//!     [0]: Target @18..29
+//!       id = 3 @18..28
+//!       hit = None @28..29
+//!     [1]: Target @29..72
+//!       id = 4 @29..39
+//!       hit @39..72
+//!         Some: Hit @40..72
+//!           0 = 0.5 @40..72
+//! ```
+//!
+//! Unpacking stops at the first field that fails, which is still part of the tree along with the
+//! error, making it easy to find where malformed bytes went wrong.
+//!
+//! With the `debug-schema` feature, `Inspect` is derived along with `UnpackBits`. Fields of types
+//! that do not implement `Inspect` are shown using their `Debug` implementation if they have one,
+//! and without a value otherwise.
+
+use crate::read::{self, BitReader, Limits, ReadBits};
+use crate::{Error, Result, UnpackBits};
+
+use std::fmt::{self, Debug, Display};
+use std::ops::Range;
+
+/// A type with a known layout of packed fields.
+pub trait Inspect: UnpackBits {
+    fn schema() -> Schema;
+}
+
+/// How a type is packed.
+pub struct Schema {
+    /// The name of the type.
+    pub name: &'static str,
+    pub kind: Kind,
+}
+
+pub enum Kind {
+    /// Fields packed one after another.
+    Struct(Vec<Field>),
+    /// The index of a variant, followed by its fields.
+    Enum {
+        index_bits: u8,
+        variants: Vec<Variant>,
+    },
+    /// A length, followed by that many items.
+    Sequence(fn() -> Schema),
+    /// A single bit, followed by the value if the bit is set.
+    Option(fn() -> Schema),
+    /// A value unpacked as a whole, which can be described by a string.
+    Value(fn(&mut BitReader<'_>) -> Result<Option<String>>),
+}
+
+pub struct Field {
+    pub name: &'static str,
+    /// Schemas are created when they are needed, so that types may contain themselves.
+    pub schema: fn() -> Schema,
+}
+
+pub struct Variant {
+    pub name: &'static str,
+    /// The index the variant is packed as.
+    pub index: u32,
+    pub fields: Vec<Field>,
+}
+
+/// A field unpacked from bytes.
+#[derive(Debug, Clone)]
+pub struct Node {
+    /// The name of the field, or of the type for the outermost value.
+    pub name: String,
+    /// The name of the field's type, for structs and enums.
+    pub ty: Option<&'static str>,
+    /// The bits the field was packed into.
+    pub bits: Range<usize>,
+    /// The unpacked value, if it can be described by a string.
+    pub value: Option<String>,
+    pub children: Vec<Node>,
+    /// The error that stopped unpacking at this field.
+    pub error: Option<Error>,
+}
+
+impl Schema {
+    pub fn structure(name: &'static str, fields: Vec<Field>) -> Schema {
+        Schema {
+            name,
+            kind: Kind::Struct(fields),
+        }
+    }
+
+    pub fn enumeration(name: &'static str, index_bits: u8, variants: Vec<Variant>) -> Schema {
+        Schema {
+            name,
+            kind: Kind::Enum {
+                index_bits,
+                variants,
+            },
+        }
+    }
+
+    /// A value described by its `Debug` implementation.
+    pub fn debug<T>() -> Schema
+    where
+        T: UnpackBits + Debug,
+    {
+        Schema {
+            name: std::any::type_name::<T>(),
+            kind: Kind::Value(|reader| {
+                let value = T::unpack(reader)?;
+                Ok(Some(format!("{:?}", value)))
+            }),
+        }
+    }
+
+    /// A value that can not be described.
+    pub fn opaque<T>() -> Schema
+    where
+        T: UnpackBits,
+    {
+        Schema {
+            name: std::any::type_name::<T>(),
+            kind: Kind::Value(|reader| T::unpack(reader).map(|_| None)),
+        }
+    }
+}
+
+impl Node {
+    fn new(name: String, schema: &Schema, start: usize) -> Node {
+        let ty = match schema.kind {
+            Kind::Struct(_) | Kind::Enum { .. } => Some(schema.name),
+            _ => None,
+        };
+
+        Node {
+            name,
+            ty,
+            bits: start..start,
+            value: None,
+            children: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Whether unpacking failed at this field or any field within it.
+    pub fn failed(&self) -> bool {
+        self.error.is_some() || self.children.last().is_some_and(Node::failed)
+    }
+
+    fn write_tree(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        write!(f, "{:indent$}{}", "", self.name, indent = 2 * depth)?;
+        if let Some(ty) = self.ty {
+            write!(f, ": {}", ty)?;
+        }
+        if let Some(value) = &self.value {
+            write!(f, " = {}", value)?;
+        }
+        writeln!(f, " @{}..{}", self.bits.start, self.bits.end)?;
+
+        if let Some(error) = &self.error {
+            writeln!(f, "{:indent$}error: {}", "", error, indent = 2 * depth + 2)?;
+        }
+
+        for child in &self.children {
+            child.write_tree(f, depth + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_tree(f, 0)
+    }
+}
+
+/// Unpack bytes as a value of type `T`, describing every field.
+pub fn inspect<T: Inspect>(bytes: &[u8]) -> Node {
+    inspect_with_limits::<T>(bytes, Limits::UNLIMITED)
+}
+
+/// Unpack bytes as a value of type `T`, describing every field, failing if its collections exceed
+/// the limits.
+pub fn inspect_with_limits<T: Inspect>(bytes: &[u8], limits: Limits) -> Node {
+    let mut inspector = Inspector {
+        reader: BitReader::with_limits(bytes, limits),
+    };
+
+    let schema = T::schema();
+    let mut node = inspector.node(schema.name.to_owned(), &schema);
+    // the outermost value is already named after its type
+    node.ty = None;
+    node
+}
+
+struct Inspector<'a> {
+    reader: BitReader<'a>,
+}
+
+impl<'a> Inspector<'a> {
+    fn offset(&self) -> usize {
+        self.reader.bit_position()
+    }
+
+    fn node(&mut self, name: String, schema: &Schema) -> Node {
+        let mut node = Node::new(name, schema, self.offset());
+        if let Err(error) = self.unpack(&mut node, &schema.kind) {
+            node.error = Some(error);
+        }
+        node.bits.end = self.offset();
+        node
+    }
+
+    /// Unpack the children of a node, returning the error of the node itself if there is one.
+    /// Stops at the first child that fails.
+    fn unpack(&mut self, node: &mut Node, kind: &Kind) -> Result<()> {
+        match kind {
+            Kind::Struct(fields) => self.fields(node, fields),
+            Kind::Enum {
+                index_bits,
+                variants,
+            } => {
+                let index = self.reader.read(*index_bits)?;
+                let variant = variants
+                    .iter()
+                    .find(|variant| variant.index == index)
+                    .ok_or_else(|| {
+                        let expected = format!("one of {} variant indices", variants.len());
+                        <Error as read::Error>::unexpected(expected, index)
+                    })?;
+                node.value = Some(variant.name.to_owned());
+                self.fields(node, &variant.fields)
+            }
+            Kind::Sequence(item) => {
+                let len = read::unpack_len(&mut self.reader)?;
+                node.value = Some(format!("{} items", len));
+                let item = item();
+                for i in 0..len {
+                    if !self.child(node, format!("[{}]", i), &item) {
+                        break;
+                    }
+                }
+                Ok(())
+            }
+            Kind::Option(inner) => {
+                if bool::unpack(&mut self.reader)? {
+                    self.child(node, "Some".to_owned(), &inner());
+                } else {
+                    node.value = Some("None".to_owned());
+                }
+                Ok(())
+            }
+            Kind::Value(unpack) => {
+                node.value = unpack(&mut self.reader)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn fields(&mut self, node: &mut Node, fields: &[Field]) -> Result<()> {
+        for field in fields {
+            if !self.child(node, field.name.to_owned(), &(field.schema)()) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a child to a node, returning `false` if it failed.
+    fn child(&mut self, node: &mut Node, name: String, schema: &Schema) -> bool {
+        let child = self.node(name, schema);
+        let failed = child.failed();
+        node.children.push(child);
+        !failed
+    }
+}
+
+impl<T> Inspect for Vec<T>
+where
+    T: Inspect,
+{
+    fn schema() -> Schema {
+        Schema {
+            name: "Vec",
+            kind: Kind::Sequence(T::schema),
+        }
+    }
+}
+
+impl<T> Inspect for Option<T>
+where
+    T: Inspect,
+{
+    fn schema() -> Schema {
+        Schema {
+            name: "Option",
+            kind: Kind::Option(T::schema),
+        }
+    }
+}
+
+/// Picks the most descriptive schema for a type, used by the derived `Inspect` for fields of any
+/// type: `Inspect::schema` if the type implements it, followed by `Schema::debug` and
+/// `Schema::opaque`. Call as `(&&&Probe::<T>::new()).schema()` with every trait in scope.
+#[doc(hidden)]
+pub mod probe {
+    use super::{Inspect, Schema};
+    use crate::UnpackBits;
+
+    use std::fmt::Debug;
+    use std::marker::PhantomData;
+
+    pub struct Probe<T>(PhantomData<T>);
+
+    impl<T> Probe<T> {
+        pub fn new() -> Probe<T> {
+            Probe(PhantomData)
+        }
+    }
+
+    impl<T> Default for Probe<T> {
+        fn default() -> Self {
+            Probe::new()
+        }
+    }
+
+    pub trait InspectSchema {
+        fn schema(&self) -> Schema;
+    }
+
+    impl<T: Inspect> InspectSchema for &&Probe<T> {
+        fn schema(&self) -> Schema {
+            T::schema()
+        }
+    }
+
+    pub trait DebugSchema {
+        fn schema(&self) -> Schema;
+    }
+
+    impl<T: UnpackBits + Debug> DebugSchema for &Probe<T> {
+        fn schema(&self) -> Schema {
+            Schema::debug::<T>()
+        }
+    }
+
+    pub trait OpaqueSchema {
+        fn schema(&self) -> Schema;
+    }
+
+    impl<T: UnpackBits> OpaqueSchema for Probe<T> {
+        fn schema(&self) -> Schema {
+            Schema::opaque::<T>()
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::needless_borrow)]
+mod tests {
+    use super::probe::*;
+    use super::*;
+    use crate::{PackBits, WriteBits};
+
+    #[derive(Debug, PartialEq)]
+    struct Throw {
+        power: u8,
+        targets: Vec<Target>,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Target {
+        id: u32,
+        hit: Option<Hit>,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Hit(f32);
+
+    impl PackBits for Throw {
+        fn pack<W: WriteBits>(&self, writer: &mut W) -> Result<(), W::Error> {
+            self.power.pack(writer)?;
+            (self.targets.len() as u32).pack(writer)?;
+            for target in &self.targets {
+                target.id.pack(writer)?;
+                target.hit.as_ref().map(|hit| hit.0).pack(writer)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl UnpackBits for Throw {
+        fn unpack<R: ReadBits>(reader: &mut R) -> Result<Self, R::Error> {
+            Ok(Throw {
+                power: u8::unpack(reader)?,
+                targets: Vec::unpack(reader)?,
+            })
+        }
+    }
+
+    impl UnpackBits for Target {
+        fn unpack<R: ReadBits>(reader: &mut R) -> Result<Self, R::Error> {
+            Ok(Target {
+                id: u32::unpack(reader)?,
+                hit: Option::unpack(reader)?,
+            })
+        }
+    }
+
+    impl UnpackBits for Hit {
+        fn unpack<R: ReadBits>(reader: &mut R) -> Result<Self, R::Error> {
+            f32::unpack(reader).map(Hit)
+        }
+    }
+
+    impl Inspect for Throw {
+        fn schema() -> Schema {
+            Schema::structure(
+                "Throw",
+                vec![
+                    Field {
+                        name: "power",
+                        schema: || (&&&Probe::<u8>::new()).schema(),
+                    },
+                    Field {
+                        name: "targets",
+                        schema: || (&&&Probe::<Vec<Target>>::new()).schema(),
+                    },
+                ],
+            )
+        }
+    }
+
+    impl Inspect for Hit {
+        fn schema() -> Schema {
+            Schema::structure(
+                "Hit",
+                vec![Field {
+                    name: "0",
+                    schema: || (&&&Probe::<f32>::new()).schema(),
+                }],
+            )
+        }
+    }
+
+    impl Inspect for Target {
+        fn schema() -> Schema {
+            Schema::structure(
+                "Target",
+                vec![
+                    Field {
+                        name: "id",
+                        schema: || (&&&Probe::<u32>::new()).schema(),
+                    },
+                    Field {
+                        name: "hit",
+                        schema: || (&&&Probe::<Option<Hit>>::new()).schema(),
+                    },
+                ],
+            )
+        }
+    }
+
+    fn sample() -> Vec<u8> {
+        let throw = Throw {
+            power: 7,
+            targets: vec![
+                Target { id: 3, hit: None },
+                Target {
+                    id: 4,
+                    hit: Some(Hit(0.5)),
+                },
+            ],
+        };
+        crate::to_bytes(&throw).unwrap()
+    }
+
+    #[test]
+    fn describes_fields() {
+        let node = inspect::<Throw>(&sample());
+        assert!(!node.failed());
+
+        let tree = node.to_string();
+        let expected = "\
+Throw @0..72
+  power = 7 @0..8
+  targets = 2 items @8..72
+    [0]: Target @18..29
+      id = 3 @18..28
+      hit = None @28..29
+    [1]: Target @29..72
+      id = 4 @29..39
+      hit @39..72
+        Some: Hit @40..72
+          0 = 0.5 @40..72
+";
+        assert_eq!(tree, expected);
+    }
+
+    #[test]
+    fn stops_at_failure() {
+        let bytes = sample();
+        let node = inspect::<Throw>(&bytes[..5]);
+        assert!(node.failed());
+
+        let targets = &node.children[1];
+        let hit = &targets.children[1].children[1].children[0];
+        assert_eq!(hit.children[0].bits, 40..40);
+        assert!(matches!(hit.children[0].error, Some(Error::Eof)));
+
+        // the error is shown below the field that failed
+        let tree = node.to_string();
+        assert!(tree.ends_with("0 @40..40\n            error: unexpected eof\n"));
+    }
+
+    #[test]
+    fn unknown_variant() {
+        struct Either;
+
+        impl UnpackBits for Either {
+            fn unpack<R: ReadBits>(_: &mut R) -> Result<Self, R::Error> {
+                unreachable!()
+            }
+        }
+
+        impl Inspect for Either {
+            fn schema() -> Schema {
+                let variant = |name, index| Variant {
+                    name,
+                    index,
+                    fields: Vec::new(),
+                };
+                Schema::enumeration("Either", 2, vec![variant("Left", 0), variant("Right", 1)])
+            }
+        }
+
+        let node = inspect::<Either>(&[0b10]);
+        assert!(node.failed());
+        assert_eq!(node.bits, 0..2);
+        assert!(node
+            .to_string()
+            .contains("expected one of 2 variant indices, found 2"));
+
+        let node = inspect::<Either>(&[0b01]);
+        assert_eq!(node.value.as_deref(), Some("Right"));
+    }
+}
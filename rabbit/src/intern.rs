@@ -0,0 +1,351 @@
+//! Packing repeated strings as indices into a dictionary.
+//!
+//! A writer with a `WriterContext` packs every distinct string in full only the first time, and as
+//! its index in the context's dictionary after that. A reader with a `ReaderContext` builds up the
+//! same dictionary while unpacking. Contexts are kept between values, so that strings repeated
+//! across many messages, such as nicknames, are only sent once. The values therefore have to be
+//! unpacked in the same order they were packed, and none of them may be lost.
+//!
+//! Strings are interned by packing them with the functions of this module, for example with
+//! `#[rabbit(with = "rabbit::intern")]`. Readers and writers without a context, such as the
+//! `BitCounter` used for `PackedSize`, pack the strings in full.
+
+use std::collections::HashMap;
+
+use crate::read::{self, ReadBits};
+use crate::write::WriteBits;
+use crate::{PackBits, UnpackBits};
+
+/// The number of strings kept by a context, unless another limit is given.
+pub const DEFAULT_MAX_STRINGS: usize = 1024;
+
+/// The strings interned by a writer.
+#[derive(Debug, Clone)]
+pub struct WriterContext {
+    indices: HashMap<String, u32>,
+    /// Strings packed once the dictionary is full are not added to it.
+    max_strings: usize,
+}
+
+/// The strings interned by the writer of the bytes being read.
+#[derive(Debug, Clone)]
+pub struct ReaderContext {
+    strings: Vec<String>,
+    /// Packed bytes that add more strings are rejected.
+    max_strings: usize,
+}
+
+/// A writer that interns strings into a context.
+pub struct ContextWriter<'a, W> {
+    writer: W,
+    context: &'a mut WriterContext,
+}
+
+/// A reader that looks up interned strings in a context.
+pub struct ContextReader<'a, R> {
+    reader: R,
+    context: &'a mut ReaderContext,
+}
+
+/// How a string is packed.
+enum Entry {
+    /// The string was interned before.
+    Interned(u32),
+    /// The string is packed in full, and is interned if `remember` is set.
+    New { remember: bool },
+}
+
+impl WriterContext {
+    pub fn new() -> WriterContext {
+        WriterContext::with_max_strings(DEFAULT_MAX_STRINGS)
+    }
+
+    /// Keep at most `max_strings` strings.
+    pub fn with_max_strings(max_strings: usize) -> WriterContext {
+        WriterContext {
+            indices: HashMap::new(),
+            max_strings,
+        }
+    }
+
+    /// The number of strings interned so far.
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    fn entry(&mut self, text: &str) -> Entry {
+        if let Some(&index) = self.indices.get(text) {
+            return Entry::Interned(index);
+        }
+
+        let remember = self.indices.len() < self.max_strings;
+        if remember {
+            let index = self.indices.len() as u32;
+            self.indices.insert(text.to_owned(), index);
+        }
+        Entry::New { remember }
+    }
+}
+
+impl Default for WriterContext {
+    fn default() -> Self {
+        WriterContext::new()
+    }
+}
+
+impl ReaderContext {
+    pub fn new() -> ReaderContext {
+        ReaderContext::with_max_strings(DEFAULT_MAX_STRINGS)
+    }
+
+    /// Fail to unpack values that would intern more than `max_strings` strings.
+    pub fn with_max_strings(max_strings: usize) -> ReaderContext {
+        ReaderContext {
+            strings: Vec::new(),
+            max_strings,
+        }
+    }
+
+    /// The number of strings interned so far.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+impl Default for ReaderContext {
+    fn default() -> Self {
+        ReaderContext::new()
+    }
+}
+
+impl<'a, W> ContextWriter<'a, W> {
+    pub fn new(writer: W, context: &'a mut WriterContext) -> ContextWriter<'a, W> {
+        ContextWriter { writer, context }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<'a, W> WriteBits for ContextWriter<'a, W>
+where
+    W: WriteBits,
+{
+    type Error = W::Error;
+
+    fn write(&mut self, bits: u32, count: u8) -> Result<(), Self::Error> {
+        self.writer.write(bits, count)
+    }
+
+    fn align(&mut self) -> Result<(), Self::Error> {
+        self.writer.align()
+    }
+
+    fn write_aligned(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.writer.write_aligned(bytes)
+    }
+
+    fn context(&mut self) -> Option<&mut WriterContext> {
+        Some(self.context)
+    }
+
+    fn version(&self) -> Option<u32> {
+        self.writer.version()
+    }
+}
+
+impl<'a, R> ContextReader<'a, R> {
+    pub fn new(reader: R, context: &'a mut ReaderContext) -> ContextReader<'a, R> {
+        ContextReader { reader, context }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<'a, R> ReadBits for ContextReader<'a, R>
+where
+    R: ReadBits,
+{
+    type Error = R::Error;
+
+    fn read(&mut self, count: u8) -> Result<u32, Self::Error> {
+        self.reader.read(count)
+    }
+
+    fn bits_remaining(&self) -> usize {
+        self.reader.bits_remaining()
+    }
+
+    fn bit_position(&self) -> usize {
+        self.reader.bit_position()
+    }
+
+    fn align_to_byte(&mut self) -> Result<(), Self::Error> {
+        self.reader.align_to_byte()
+    }
+
+    fn skip_bits(&mut self, count: usize) -> Result<(), Self::Error> {
+        self.reader.skip_bits(count)
+    }
+
+    fn reserve(&mut self, len: usize) -> Result<(), Self::Error> {
+        self.reader.reserve(len)
+    }
+
+    fn context(&mut self) -> Option<&mut ReaderContext> {
+        Some(self.context)
+    }
+
+    fn version(&self) -> Option<u32> {
+        self.reader.version()
+    }
+}
+
+/// Pack a string as an index into the writer's dictionary if it was packed before, and in full
+/// otherwise.
+pub fn pack<W>(text: &str, writer: &mut W) -> Result<(), W::Error>
+where
+    W: WriteBits,
+{
+    let entry = match writer.context() {
+        Some(context) => context.entry(text),
+        None => return text.pack(writer),
+    };
+
+    match entry {
+        Entry::Interned(index) => {
+            true.pack(writer)?;
+            index.pack(writer)
+        }
+        Entry::New { remember } => {
+            false.pack(writer)?;
+            remember.pack(writer)?;
+            text.pack(writer)
+        }
+    }
+}
+
+/// Unpack a string packed by `pack`.
+pub fn unpack<R>(reader: &mut R) -> Result<String, R::Error>
+where
+    R: ReadBits,
+{
+    if reader.context().is_none() {
+        return String::unpack(reader);
+    }
+
+    if bool::unpack(reader)? {
+        let index = u32::unpack(reader)?;
+        let context = reader.context().expect("the context was there before");
+        context
+            .strings
+            .get(index as usize)
+            .cloned()
+            .ok_or_else(|| read::Error::custom(format!("no string was interned at {}", index)))
+    } else {
+        let remember = bool::unpack(reader)?;
+        let text = String::unpack(reader)?;
+
+        let context = reader.context().expect("the context was there before");
+        if remember {
+            if context.strings.len() >= context.max_strings {
+                let message = format!("more than {} strings were interned", context.max_strings);
+                return Err(read::Error::custom(message));
+            }
+            context.strings.push(text.clone());
+        }
+
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(texts: &[&str], writer: &mut WriterContext, reader: &mut ReaderContext) -> usize {
+        let value: Vec<String> = texts.iter().map(|text| text.to_string()).collect();
+        let bytes = crate::to_bytes_with_context(&Interned(value.clone()), writer).unwrap();
+        let unpacked: Interned = crate::from_bytes_with_context(&bytes, reader).unwrap();
+        assert_eq!(unpacked.0, value);
+        bytes.len()
+    }
+
+    /// Every string of the list is interned.
+    struct Interned(Vec<String>);
+
+    impl PackBits for Interned {
+        fn pack<W: WriteBits>(&self, writer: &mut W) -> Result<(), W::Error> {
+            (self.0.len() as u32).pack(writer)?;
+            self.0.iter().try_for_each(|text| pack(text, writer))
+        }
+    }
+
+    impl UnpackBits for Interned {
+        fn unpack<R: ReadBits>(reader: &mut R) -> Result<Self, R::Error> {
+            let len = read::unpack_len(reader)?;
+            let texts = (0..len).map(|_| unpack(reader)).collect::<Result<_, _>>()?;
+            Ok(Interned(texts))
+        }
+    }
+
+    #[test]
+    fn repeated_strings_shrink() {
+        let mut writer = WriterContext::new();
+        let mut reader = ReaderContext::new();
+
+        let first = round_trip(&["snowman", "iceberg"], &mut writer, &mut reader);
+        let second = round_trip(&["iceberg", "snowman"], &mut writer, &mut reader);
+        assert!(second < first);
+        assert_eq!((writer.len(), reader.len()), (2, 2));
+
+        // strings repeated within the same value are also interned
+        let repeated = round_trip(&["slush"; 10], &mut writer, &mut reader);
+        assert!(repeated < 10 * "slush".len());
+    }
+
+    #[test]
+    fn full_dictionary() {
+        let mut writer = WriterContext::with_max_strings(1);
+        let mut reader = ReaderContext::with_max_strings(1);
+
+        round_trip(&["a", "b", "b", "a"], &mut writer, &mut reader);
+        assert_eq!((writer.len(), reader.len()), (1, 1));
+
+        // a reader refuses to intern more strings than its limit
+        let mut writer = WriterContext::with_max_strings(2);
+        let mut reader = ReaderContext::with_max_strings(1);
+        let value = Interned(vec!["a".into(), "b".into()]);
+        let bytes = crate::to_bytes_with_context(&value, &mut writer).unwrap();
+        assert!(crate::from_bytes_with_context::<Interned>(&bytes, &mut reader).is_err());
+    }
+
+    #[test]
+    fn without_context() {
+        let value = Interned(vec!["snowball".into(); 2]);
+        let bytes = crate::to_bytes(&value).unwrap();
+        assert_eq!(bytes, crate::to_bytes(&value.0).unwrap());
+    }
+
+    #[test]
+    fn unknown_index() {
+        let mut writer = WriterContext::new();
+        let value = Interned(vec!["snowball".into(); 2]);
+        crate::to_bytes_with_context(&value, &mut writer).unwrap();
+        let bytes = crate::to_bytes_with_context(&value, &mut writer).unwrap();
+
+        let mut reader = ReaderContext::new();
+        assert!(crate::from_bytes_with_context::<Interned>(&bytes, &mut reader).is_err());
+    }
+}
@@ -2,20 +2,44 @@
 
 mod impls;
 
+pub mod bitset;
+pub mod borrowed;
+pub mod bounded;
+#[cfg(feature = "serde")]
+pub mod compat;
+pub mod delta;
+pub mod fixed;
+pub mod fixed_width;
+pub mod flags;
+pub mod inspect;
+pub mod intern;
+pub mod quantized;
 pub mod read;
+pub mod size;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+pub mod time;
 pub mod write;
 
-use std::fmt::Display;
+use std::convert::TryInto;
+use std::fmt::{self, Display};
 use thiserror::Error;
 
-use read::BitReader;
+use intern::{ContextReader, ContextWriter, ReaderContext, WriterContext};
+use read::{BitReader, Limits};
 use write::BitWriter;
 
+pub use bitset::BitSet;
+pub use borrowed::UnpackBorrowed;
+pub use bounded::Bounded;
+pub use delta::{PackDelta, UnpackDelta};
+pub use fixed::Fixed;
 pub use read::ReadBits;
+pub use size::PackedSize;
 pub use write::WriteBits;
 
 #[cfg(feature = "derive")]
-pub use rabbit_derive::{PackBits, UnpackBits};
+pub use rabbit_derive::{PackBits, PackDelta, PackedSize, UnpackBits, UnpackDelta};
 
 #[derive(Debug, Clone, Error)]
 pub enum Error {
@@ -24,19 +48,210 @@ pub enum Error {
 
     #[error("unexpected eof")]
     Eof,
+
+    #[error("a collection of {len} items exceeds the limit of {limit} items")]
+    TooLong { len: usize, limit: usize },
+
+    #[error("the collections exceed the limit of {limit} items in total")]
+    TooManyItems { limit: usize },
+
+    #[error("{bits} bits were left after unpacking")]
+    TrailingBits { bits: usize },
+
+    #[error("the checksum {actual:#010x} does not match the expected {expected:#010x}")]
+    ChecksumMismatch { expected: u32, actual: u32 },
+
+    #[error("expected {expected}, found {found}")]
+    Unexpected { expected: String, found: String },
+
+    /// Another error, with where in the bytes it happened.
+    #[error("{0}")]
+    Context(Box<ErrorContext>),
+}
+
+/// Where unpacking failed, see `Error::Context`.
+#[derive(Debug, Clone)]
+pub struct ErrorContext {
+    /// The fields, variants and items that the failing value was nested in, outermost first.
+    pub path: Vec<String>,
+    /// The number of bits that had been read when unpacking failed, if known.
+    pub bit: Option<usize>,
+    pub error: Error,
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// The number of bytes `to_bytes_checked` appends to the packed value.
+pub const CHECKSUM_BYTES: usize = 4;
+
 pub fn to_bytes<T: PackBits>(value: &T) -> Result<Vec<u8>> {
     let mut writer = BitWriter::new();
     value.pack(&mut writer)?;
     Ok(writer.finish())
 }
 
+/// Pack a value into `bytes`, replacing its contents but reusing its memory. Use when packing many
+/// values in a row, to avoid allocating for every one of them. If packing fails, `bytes` is left
+/// empty.
+pub fn to_bytes_into<T: PackBits>(value: &T, bytes: &mut Vec<u8>) -> Result<()> {
+    let mut writer = BitWriter::with_buffer(std::mem::take(bytes));
+    let result = value.pack(&mut writer);
+    *bytes = writer.finish();
+    if result.is_err() {
+        bytes.clear();
+    }
+    result
+}
+
+/// Pack a value with every field padded to whole bytes, which is faster to pack and unpack but
+/// takes more space. Sizes from `PackedSize` do not apply.
+pub fn to_aligned_bytes<T: PackBits>(value: &T) -> Result<Vec<u8>> {
+    let mut writer = BitWriter::aligned();
+    value.pack(&mut writer)?;
+    Ok(writer.finish())
+}
+
 pub fn from_bytes<T: UnpackBits>(bytes: &[u8]) -> Result<T> {
     let mut reader = BitReader::new(bytes);
-    T::unpack(&mut reader)
+    locate(T::unpack(&mut reader), &reader)
+}
+
+/// Unpack a value, failing if its collections exceed the limits. Use for bytes from untrusted
+/// sources.
+pub fn from_bytes_with_limits<T: UnpackBits>(bytes: &[u8], limits: Limits) -> Result<T> {
+    let mut reader = BitReader::with_limits(bytes, limits);
+    locate(T::unpack(&mut reader), &reader)
+}
+
+/// Unpack a value, failing if anything but the padding of the last byte is left afterwards.
+/// Leftover bits mean that the bytes were packed from a different type, such as a different version
+/// of the same message.
+pub fn from_bytes_strict<T: UnpackBits>(bytes: &[u8]) -> Result<T> {
+    from_bytes_strict_with_limits(bytes, Limits::UNLIMITED)
+}
+
+/// Unpack a value, failing if its collections exceed the limits or if anything but the padding is
+/// left afterwards.
+pub fn from_bytes_strict_with_limits<T: UnpackBits>(bytes: &[u8], limits: Limits) -> Result<T> {
+    let mut reader = BitReader::with_limits(bytes, limits);
+    let value = locate(T::unpack(&mut reader), &reader)?;
+    reader.finish()?;
+    Ok(value)
+}
+
+/// Unpack a value packed by `to_aligned_bytes`.
+pub fn from_aligned_bytes<T: UnpackBits>(bytes: &[u8]) -> Result<T> {
+    from_aligned_bytes_with_limits(bytes, Limits::UNLIMITED)
+}
+
+/// Unpack a value packed by `to_aligned_bytes`, failing if its collections exceed the limits.
+pub fn from_aligned_bytes_with_limits<T: UnpackBits>(bytes: &[u8], limits: Limits) -> Result<T> {
+    let mut reader = BitReader::aligned(bytes, limits);
+    locate(T::unpack(&mut reader), &reader)
+}
+
+/// Pack a value for readers of `version`, leaving out fields added after it, see
+/// `WriteBits::version`.
+pub fn to_bytes_versioned<T: PackBits>(value: &T, version: u32) -> Result<Vec<u8>> {
+    let mut writer = BitWriter::new().with_version(version);
+    value.pack(&mut writer)?;
+    Ok(writer.finish())
+}
+
+/// Unpack a value packed by types of `version`, unpacking fields added after it as their default.
+pub fn from_bytes_versioned<T: UnpackBits>(bytes: &[u8], version: u32) -> Result<T> {
+    let mut reader = BitReader::new(bytes).with_version(version);
+    locate(T::unpack(&mut reader), &reader)
+}
+
+/// Unpack a value packed by types of `version`, failing if its collections exceed the limits or if
+/// anything but the padding is left afterwards.
+pub fn from_bytes_strict_versioned<T: UnpackBits>(
+    bytes: &[u8],
+    version: u32,
+    limits: Limits,
+) -> Result<T> {
+    let mut reader = BitReader::with_limits(bytes, limits).with_version(version);
+    let value = locate(T::unpack(&mut reader), &reader)?;
+    reader.finish()?;
+    Ok(value)
+}
+
+/// Pack a value followed by a CRC32 of the packed bytes, so that bytes corrupted on their way to
+/// the reader are detected before they are unpacked.
+pub fn to_bytes_checked<T: PackBits>(value: &T) -> Result<Vec<u8>> {
+    let mut bytes = to_bytes(value)?;
+    let checksum = crc32fast::hash(&bytes);
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+    Ok(bytes)
+}
+
+/// Unpack a value packed by `to_bytes_checked`, failing with `Error::ChecksumMismatch` if the bytes
+/// were corrupted.
+pub fn from_bytes_checked<T: UnpackBits>(bytes: &[u8]) -> Result<T> {
+    from_bytes(verify_checksum(bytes)?)
+}
+
+/// Verify the checksum appended by `to_bytes_checked`, and return the packed value without it. Use
+/// to unpack checked bytes in other ways than `from_bytes_checked`, such as with limits.
+pub fn verify_checksum(bytes: &[u8]) -> Result<&[u8]> {
+    if bytes.len() < CHECKSUM_BYTES {
+        return Err(Error::Eof);
+    }
+
+    let (payload, checksum) = bytes.split_at(bytes.len() - CHECKSUM_BYTES);
+    let expected = u32::from_le_bytes(checksum.try_into().unwrap());
+    let actual = crc32fast::hash(payload);
+    if actual != expected {
+        return Err(Error::ChecksumMismatch { expected, actual });
+    }
+
+    Ok(payload)
+}
+
+/// Pack a value, interning its strings into `context`, see `intern`.
+pub fn to_bytes_with_context<T>(value: &T, context: &mut WriterContext) -> Result<Vec<u8>>
+where
+    T: PackBits,
+{
+    let mut writer = ContextWriter::new(BitWriter::new(), context);
+    value.pack(&mut writer)?;
+    Ok(writer.into_inner().finish())
+}
+
+/// Unpack a value packed by `to_bytes_with_context`, using a context that has unpacked every value
+/// packed with the writer's context before this one.
+pub fn from_bytes_with_context<T>(bytes: &[u8], context: &mut ReaderContext) -> Result<T>
+where
+    T: UnpackBits,
+{
+    let mut reader = ContextReader::new(BitReader::new(bytes), context);
+    let result = T::unpack(&mut reader);
+    locate(result, &reader.into_inner())
+}
+
+/// Unpack a value that borrows strings and byte slices from `bytes`, see `borrowed`.
+pub fn from_bytes_borrowed<'a, T: UnpackBorrowed<'a>>(bytes: &'a [u8]) -> Result<T> {
+    let mut reader = BitReader::new(bytes);
+    locate(T::unpack_borrowed(&mut reader), &reader)
+}
+
+/// Pack the differences between `value` and `baseline`, see `delta`.
+pub fn to_delta_bytes<T: PackDelta>(value: &T, baseline: &T) -> Result<Vec<u8>> {
+    let mut writer = BitWriter::new();
+    value.pack_delta(baseline, &mut writer)?;
+    Ok(writer.finish())
+}
+
+/// Unpack a value packed with `to_delta_bytes` against the same `baseline`.
+pub fn from_delta_bytes<T: UnpackDelta>(baseline: &T, bytes: &[u8]) -> Result<T> {
+    let mut reader = BitReader::new(bytes);
+    locate(T::unpack_delta(baseline, &mut reader), &reader)
+}
+
+/// Note how far `reader` got if unpacking failed.
+fn locate<T>(result: Result<T>, reader: &BitReader) -> Result<T> {
+    result.map_err(|error| error.at_bit(reader.bit_position()))
 }
 
 pub trait PackBits {
@@ -46,11 +261,71 @@ pub trait PackBits {
 }
 
 pub trait UnpackBits: Sized {
+    /// Whether unpacking may skip every remaining bit, as enums with an `other` variant do for
+    /// variants they do not know. Anything unpacked after such a value would be skipped as well, so
+    /// derived types and collections refuse to compile unless it is unpacked last.
+    const UNPACKS_REST: bool = false;
+
     fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
     where
         R: ReadBits;
 }
 
+impl Error {
+    /// The error without the context of where it happened.
+    pub fn kind(&self) -> &Error {
+        match self {
+            Error::Context(context) => &context.error,
+            error => error,
+        }
+    }
+
+    /// Note that the error happened after reading `bit` bits.
+    pub fn at_bit(self, bit: usize) -> Error {
+        let mut context = self.into_context();
+        context.bit = Some(bit);
+        Error::Context(context)
+    }
+
+    fn into_context(self) -> Box<ErrorContext> {
+        match self {
+            Error::Context(context) => context,
+            error => Box::new(ErrorContext {
+                path: Vec::new(),
+                bit: None,
+                error,
+            }),
+        }
+    }
+}
+
+impl ErrorContext {
+    /// The path of the failing value, such as `players[3].position.x` or `scores{2}`.
+    pub fn path(&self) -> String {
+        let mut path = String::new();
+        for segment in &self.path {
+            if !path.is_empty() && !segment.starts_with('[') && !segment.starts_with('{') {
+                path.push('.');
+            }
+            path.push_str(segment);
+        }
+        path
+    }
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if !self.path.is_empty() {
+            write!(f, " in `{}`", self.path())?;
+        }
+        if let Some(bit) = self.bit {
+            write!(f, " at bit {}", bit)?;
+        }
+        Ok(())
+    }
+}
+
 impl write::Error for Error {
     fn custom<T: Display>(msg: T) -> Error {
         Error::Message(msg.to_string())
@@ -61,4 +336,17 @@ impl read::Error for Error {
     fn custom<T: Display>(msg: T) -> Error {
         Error::Message(msg.to_string())
     }
+
+    fn unexpected<E: Display, F: Display>(expected: E, found: F) -> Error {
+        Error::Unexpected {
+            expected: expected.to_string(),
+            found: found.to_string(),
+        }
+    }
+
+    fn within<S: Display>(self, segment: S) -> Error {
+        let mut context = self.into_context();
+        context.path.insert(0, segment.to_string());
+        Error::Context(context)
+    }
 }
@@ -0,0 +1,155 @@
+//! Floats packed with a fixed number of bits over a known range.
+//!
+//! Many values sent over the network, such as positions within the map or angles, stay within a
+//! known range and need far less precision than a full `f32`. Quantizing maps the range onto the
+//! integers `0..2^bits`, so every value takes exactly `bits` bits and comes back within half a
+//! step of the original.
+//!
+//! The helpers take the range as arguments, so that they can be wrapped in a module for use with
+//! `#[rabbit(with = "...")]`:
+//!
+//! ```ignore
+//! mod angle {
+//!     use rabbit::{quantized, ReadBits, WriteBits};
+//!     use std::f32::consts::PI;
+//!
+//!     pub fn pack<W: WriteBits>(angle: &f32, writer: &mut W) -> Result<(), W::Error> {
+//!         quantized::pack_quantized(*angle, -PI, PI, 10, writer)
+//!     }
+//!
+//!     pub fn unpack<R: ReadBits>(reader: &mut R) -> Result<f32, R::Error> {
+//!         quantized::unpack_quantized(-PI, PI, 10, reader)
+//!     }
+//! }
+//! ```
+
+use crate::{read::Error as _, write::Error as _, ReadBits, WriteBits};
+
+/// The largest number of bits a value may be quantized to.
+pub const MAX_BITS: u8 = 32;
+
+/// The largest integer a value is quantized to.
+fn max_step(bits: u8) -> u64 {
+    (1u64 << bits) - 1
+}
+
+/// Map `value` onto the integers `0..2^bits`. Values outside `min..=max` are clamped to the
+/// closest end of the range, and NaN is mapped to `min`.
+pub fn quantize(value: f32, min: f32, max: f32, bits: u8) -> u32 {
+    let bits = bits.min(MAX_BITS);
+    let fraction = (f64::from(value) - f64::from(min)) / (f64::from(max) - f64::from(min));
+    if fraction.is_nan() || fraction <= 0.0 {
+        return 0;
+    }
+    (fraction.min(1.0) * max_step(bits) as f64).round() as u32
+}
+
+/// The value represented by `quantized`, the inverse of `quantize`.
+pub fn dequantize(quantized: u32, min: f32, max: f32, bits: u8) -> f32 {
+    let bits = bits.min(MAX_BITS);
+    let fraction = f64::from(quantized) / max_step(bits) as f64;
+    (f64::from(min) + fraction * (f64::from(max) - f64::from(min))) as f32
+}
+
+/// The distance between two neighbouring quantized values.
+pub fn step(min: f32, max: f32, bits: u8) -> f32 {
+    ((f64::from(max) - f64::from(min)) / max_step(bits.min(MAX_BITS)) as f64) as f32
+}
+
+/// Pack `value` with `bits` bits over the range `min..=max`.
+pub fn pack_quantized<W>(
+    value: f32,
+    min: f32,
+    max: f32,
+    bits: u8,
+    writer: &mut W,
+) -> Result<(), W::Error>
+where
+    W: WriteBits,
+{
+    if bits == 0 || bits > MAX_BITS {
+        return Err(W::Error::custom(format!(
+            "cannot quantize to {} bits, expected 1 to {}",
+            bits, MAX_BITS
+        )));
+    }
+    writer.write(quantize(value, min, max, bits), bits)
+}
+
+/// Unpack a value packed with `pack_quantized` using the same range and number of bits.
+pub fn unpack_quantized<R>(min: f32, max: f32, bits: u8, reader: &mut R) -> Result<f32, R::Error>
+where
+    R: ReadBits,
+{
+    if bits == 0 || bits > MAX_BITS {
+        return Err(R::Error::custom(format!(
+            "cannot quantize to {} bits, expected 1 to {}",
+            bits, MAX_BITS
+        )));
+    }
+    let quantized = reader.read(bits)?;
+    Ok(dequantize(quantized, min, max, bits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read::BitReader;
+    use crate::write::BitWriter;
+
+    fn round_trip(value: f32, min: f32, max: f32, bits: u8) -> (f32, usize) {
+        let mut writer = BitWriter::new();
+        pack_quantized(value, min, max, bits, &mut writer).unwrap();
+        let bytes = writer.finish();
+
+        let mut reader = BitReader::new(&bytes);
+        let unpacked = unpack_quantized(min, max, bits, &mut reader).unwrap();
+        (unpacked, bytes.len())
+    }
+
+    #[test]
+    fn within_half_a_step() {
+        for &bits in &[4, 10, 16, 24] {
+            let step = step(-50.0, 50.0, bits);
+            for i in 0..=1000 {
+                let value = -50.0 + i as f32 * 0.1;
+                let (unpacked, _) = round_trip(value, -50.0, 50.0, bits);
+                assert!(
+                    (unpacked - value).abs() <= step / 2.0 + 1e-4,
+                    "{} became {} with {} bits",
+                    value,
+                    unpacked,
+                    bits
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ends_of_range_exact() {
+        for &bits in &[1, 8, 32] {
+            assert_eq!(round_trip(-3.5, -3.5, 12.0, bits).0, -3.5);
+            assert_eq!(round_trip(12.0, -3.5, 12.0, bits).0, 12.0);
+        }
+    }
+
+    #[test]
+    fn out_of_range_clamped() {
+        assert_eq!(round_trip(-100.0, 0.0, 1.0, 8).0, 0.0);
+        assert_eq!(round_trip(100.0, 0.0, 1.0, 8).0, 1.0);
+        assert_eq!(round_trip(f32::NAN, 0.0, 1.0, 8).0, 0.0);
+    }
+
+    #[test]
+    fn packed_size() {
+        assert_eq!(round_trip(0.5, 0.0, 1.0, 8).1, 1);
+        assert_eq!(round_trip(0.5, 0.0, 1.0, 12).1, 2);
+    }
+
+    #[test]
+    fn invalid_bits_rejected() {
+        let mut writer = BitWriter::new();
+        assert!(pack_quantized(0.5, 0.0, 1.0, 0, &mut writer).is_err());
+        assert!(pack_quantized(0.5, 0.0, 1.0, 33, &mut writer).is_err());
+    }
+}
@@ -1,3 +1,7 @@
+use crate::borrowed::ReadBorrowed;
+use crate::intern::ReaderContext;
+use crate::UnpackBits;
+
 use std::error::Error as StdError;
 use std::fmt::Display;
 
@@ -5,26 +9,174 @@ pub trait Error: StdError {
     fn custom<T>(msg: T) -> Self
     where
         T: Display;
+
+    /// Unpacked `found` where `expected` was required, such as an unknown variant index.
+    fn unexpected<E, F>(expected: E, found: F) -> Self
+    where
+        E: Display,
+        F: Display,
+        Self: Sized,
+    {
+        Self::custom(format_args!("expected {}, found {}", expected, found))
+    }
+
+    /// Note that the error happened while unpacking `segment` of an enclosing value: a field, a
+    /// variant or an item such as `[3]`. Called from the innermost value outwards. Ignored by
+    /// default.
+    fn within<S>(self, segment: S) -> Self
+    where
+        S: Display,
+        Self: Sized,
+    {
+        let _ = segment;
+        self
+    }
 }
 
 pub trait ReadBits {
     type Error: Error;
 
     fn read(&mut self, count: u8) -> Result<u32, Self::Error>;
+
+    /// The number of bits that have not been read yet, including the padding of the last byte.
+    fn bits_remaining(&self) -> usize;
+
+    /// The number of bits that have been read so far.
+    fn bit_position(&self) -> usize;
+
+    /// Skip to the start of the next byte, unless already there.
+    fn align_to_byte(&mut self) -> Result<(), Self::Error> {
+        match self.bit_position() % 8 {
+            0 => Ok(()),
+            offset => self.skip_bits(8 - offset),
+        }
+    }
+
+    /// Skip the next `count` bits without unpacking them.
+    fn skip_bits(&mut self, count: usize) -> Result<(), Self::Error> {
+        let mut left = count;
+        while left > 0 {
+            let bits = usize::min(left, 32);
+            self.read(bits as u8)?;
+            left -= bits;
+        }
+        Ok(())
+    }
+
+    /// Called with the length of every collection before it is unpacked, so that readers may
+    /// refuse lengths above their limits. Accepts any length by default.
+    fn reserve(&mut self, len: usize) -> Result<(), Self::Error> {
+        let _ = len;
+        Ok(())
+    }
+
+    /// The strings interned by the writer of the bytes, if they were packed with interning, see
+    /// `intern`.
+    fn context(&mut self) -> Option<&mut ReaderContext> {
+        None
+    }
+
+    /// The version of the types that packed the bytes, if known. Fields added in a later version,
+    /// with `#[rabbit(since = N)]`, are unpacked as their default. Unknown by default, in which case
+    /// every field is unpacked, see `unpack_since`.
+    fn version(&self) -> Option<u32> {
+        None
+    }
+}
+
+/// Limits on the collections a `BitReader` unpacks, to guard against packets claiming lengths so
+/// large that allocating room for them would exhaust memory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// The most items in a single collection, or bytes in a single string.
+    pub max_len: usize,
+    /// The most items in all collections unpacked by the same reader.
+    pub max_total_len: usize,
 }
 
 pub struct BitReader<'a> {
+    /// Every byte being read, including those already read.
+    input: &'a [u8],
+    /// The bytes that have not been loaded into the buffer yet.
     bytes: &'a [u8],
     buffer: u64,
     len: u8,
+    limits: Limits,
+    /// The number of items in all collections so far.
+    total_len: usize,
+    /// Every read is padded to whole bytes, see `BitWriter::aligned`.
+    aligned: bool,
+    version: Option<u32>,
+}
+
+impl Limits {
+    /// Collections of any length are accepted.
+    pub const UNLIMITED: Limits = Limits {
+        max_len: usize::MAX,
+        max_total_len: usize::MAX,
+    };
 }
 
 impl<'a> BitReader<'a> {
+    /// Read bytes without any limits.
     pub fn new(bytes: &'a [u8]) -> BitReader<'a> {
+        BitReader::with_limits(bytes, Limits::UNLIMITED)
+    }
+
+    /// Read bytes, failing on collections that exceed the limits.
+    pub fn with_limits(bytes: &'a [u8], limits: Limits) -> BitReader<'a> {
         BitReader {
+            input: bytes,
             bytes,
             buffer: 0,
             len: 0,
+            limits,
+            total_len: 0,
+            aligned: false,
+            version: None,
+        }
+    }
+
+    /// Read bytes packed by types of `version`, see `ReadBits::version`.
+    pub fn with_version(self, version: u32) -> BitReader<'a> {
+        BitReader {
+            version: Some(version),
+            ..self
+        }
+    }
+
+    /// Read bytes packed by an aligned `BitWriter`, failing on collections that exceed the limits.
+    pub fn aligned(bytes: &'a [u8], limits: Limits) -> BitReader<'a> {
+        BitReader {
+            aligned: true,
+            ..BitReader::with_limits(bytes, limits)
+        }
+    }
+
+    /// Read a value padded to whole bytes straight from the bytes, without using the buffer.
+    fn read_padded(&mut self, count: u8) -> Result<u32, crate::Error> {
+        let width = usize::from(count.div_ceil(8));
+        if width > self.bytes.len() {
+            return Err(crate::Error::Eof);
+        }
+
+        let (prefix, rest) = self.bytes.split_at(width);
+        self.bytes = rest;
+
+        let mut bytes = [0; 4];
+        bytes[..width].copy_from_slice(prefix);
+        let mask = u32::MAX.checked_shr(32 - count as u32).unwrap_or(0);
+        Ok(u32::from_le_bytes(bytes) & mask)
+    }
+
+    /// Fail if anything other than the padding of the last byte has not been read. The padding of
+    /// packed values is always zero, so any other padding is also treated as unread data.
+    pub fn finish(self) -> Result<(), crate::Error> {
+        let bits = self.bits_remaining();
+        if bits >= 8 || self.buffer != 0 {
+            Err(crate::Error::TrailingBits { bits })
+        } else {
+            Ok(())
         }
     }
 
@@ -49,6 +201,10 @@ impl<'a> ReadBits for BitReader<'a> {
     fn read(&mut self, count: u8) -> Result<u32, Self::Error> {
         let count = u8::min(count, 32);
 
+        if self.aligned {
+            return self.read_padded(count);
+        }
+
         if count > self.len {
             self.refill_buffer();
         }
@@ -56,11 +212,291 @@ impl<'a> ReadBits for BitReader<'a> {
         if count > self.len {
             Err(crate::Error::Eof)
         } else {
-            let mask = u32::max_value().checked_shr(32 - count as u32).unwrap_or(0);
+            let mask = u32::MAX.checked_shr(32 - count as u32).unwrap_or(0);
             let bits = self.buffer as u32 & mask;
             self.buffer >>= count;
             self.len -= count;
             Ok(bits)
         }
     }
+
+    fn bits_remaining(&self) -> usize {
+        usize::from(self.len) + 8 * self.bytes.len()
+    }
+
+    fn version(&self) -> Option<u32> {
+        self.version
+    }
+
+    fn bit_position(&self) -> usize {
+        8 * self.input.len() - self.bits_remaining()
+    }
+
+    /// Drops skipped bytes without reading them.
+    fn skip_bits(&mut self, count: usize) -> Result<(), Self::Error> {
+        if count > self.bits_remaining() {
+            return Err(crate::Error::Eof);
+        }
+
+        let buffered = usize::min(count, usize::from(self.len)) as u8;
+        self.buffer = self.buffer.checked_shr(u32::from(buffered)).unwrap_or(0);
+        self.len -= buffered;
+
+        // the buffer is empty if anything is left to skip
+        let rest = count - usize::from(buffered);
+        self.bytes = &self.bytes[rest / 8..];
+        self.read((rest % 8) as u8)?;
+        Ok(())
+    }
+
+    fn reserve(&mut self, len: usize) -> Result<(), Self::Error> {
+        if len > self.limits.max_len {
+            return Err(crate::Error::TooLong {
+                len,
+                limit: self.limits.max_len,
+            });
+        }
+
+        self.total_len = self.total_len.saturating_add(len);
+        if self.total_len > self.limits.max_total_len {
+            return Err(crate::Error::TooManyItems {
+                limit: self.limits.max_total_len,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> ReadBorrowed<'a> for BitReader<'a> {
+    fn read_aligned(&mut self, len: usize) -> Result<&'a [u8], Self::Error> {
+        self.read(self.len % 8)?;
+
+        // the buffer only holds whole bytes, which precede the bytes not yet loaded
+        let start = self.input.len() - self.bytes.len() - usize::from(self.len / 8);
+        let end = start.checked_add(len).ok_or(crate::Error::Eof)?;
+        let borrowed = self.input.get(start..end).ok_or(crate::Error::Eof)?;
+
+        self.bytes = &self.input[end..];
+        self.buffer = 0;
+        self.len = 0;
+
+        Ok(borrowed)
+    }
+}
+
+/// Unpack the length prefix of a collection, checking it against the limits of the reader.
+pub fn unpack_len<R>(reader: &mut R) -> Result<usize, R::Error>
+where
+    R: ReadBits,
+{
+    let len = u32::unpack(reader)? as usize;
+    reader.reserve(len)?;
+    Ok(len)
+}
+
+/// Fail to compile if unpacking `T` may skip every remaining bit, for values that are followed by
+/// others, see `UnpackBits::UNPACKS_REST`. Called in a `const` block.
+pub const fn assert_not_trailing<T: UnpackBits>() {
+    assert!(
+        !T::UNPACKS_REST,
+        "only the last value can skip every remaining bit, such as an enum with an `other` variant"
+    );
+}
+
+/// Unpack a field added in version `since`, or `None` if the writer did not pack it.
+///
+/// If the reader knows the version of the writer, the field was packed if that version is `since`
+/// or later. Otherwise the field is always unpacked, just like writers that do not know the version
+/// of the reader pack every field. Bytes from writers of older versions can only be told apart by
+/// their version, since the padding of the last byte may well unpack as a field.
+pub fn unpack_since<R, T, F>(reader: &mut R, since: u32, unpack: F) -> Result<Option<T>, R::Error>
+where
+    R: ReadBits,
+    F: FnOnce(&mut R) -> Result<T, R::Error>,
+{
+    match reader.version() {
+        Some(version) if version < since => Ok(None),
+        _ => unpack(reader).map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const LIMITS: Limits = Limits {
+        max_len: 8,
+        max_total_len: 20,
+    };
+
+    #[test]
+    fn lengths_within_limits() {
+        let items = vec![vec![1u8; 8], vec![2; 8]];
+        let bytes = crate::to_bytes(&items).unwrap();
+        let unpacked: Vec<Vec<u8>> = crate::from_bytes_with_limits(&bytes, LIMITS).unwrap();
+        assert_eq!(unpacked, items);
+    }
+
+    #[test]
+    fn long_collections_rejected() {
+        let bytes = crate::to_bytes(&vec![0u16; 9]).unwrap();
+        let result = crate::from_bytes_with_limits::<Vec<u16>>(&bytes, LIMITS);
+        assert!(matches!(
+            result.unwrap_err().kind(),
+            crate::Error::TooLong { len: 9, limit: 8 }
+        ));
+
+        let bytes = crate::to_bytes(&String::from("a long snowball")).unwrap();
+        assert!(crate::from_bytes_with_limits::<String>(&bytes, LIMITS).is_err());
+
+        let map: HashMap<u8, u8> = (0..9).map(|i| (i, i)).collect();
+        let bytes = crate::to_bytes(&map).unwrap();
+        assert!(crate::from_bytes_with_limits::<HashMap<u8, u8>>(&bytes, LIMITS).is_err());
+    }
+
+    #[test]
+    fn claimed_length_rejected_before_allocating() {
+        // only the length prefix, claiming far more items than there are bytes
+        let bytes = crate::to_bytes(&u32::MAX).unwrap();
+        let result = crate::from_bytes_with_limits::<Vec<u64>>(&bytes, LIMITS);
+        assert!(matches!(
+            result.unwrap_err().kind(),
+            crate::Error::TooLong { .. }
+        ));
+    }
+
+    #[test]
+    fn total_length_limited() {
+        let items = vec![vec![0u8; 8]; 3];
+        let bytes = crate::to_bytes(&items).unwrap();
+        let result = crate::from_bytes_with_limits::<Vec<Vec<u8>>>(&bytes, LIMITS);
+        assert!(matches!(
+            result.unwrap_err().kind(),
+            crate::Error::TooManyItems { limit: 20 }
+        ));
+    }
+
+    #[test]
+    fn strict_accepts_padding() {
+        let value = (true, 3u8);
+        let bytes = crate::to_bytes(&value).unwrap();
+        assert_eq!(bytes.len(), 2);
+        let unpacked: (bool, u8) = crate::from_bytes_strict(&bytes).unwrap();
+        assert_eq!(unpacked, value);
+    }
+
+    #[test]
+    fn strict_rejects_trailing_data() {
+        let bytes = crate::to_bytes(&(7u8, 9u8)).unwrap();
+        assert_eq!(crate::from_bytes::<u8>(&bytes).unwrap(), 7);
+        assert!(matches!(
+            crate::from_bytes_strict::<u8>(&bytes),
+            Err(crate::Error::TrailingBits { bits: 8 })
+        ));
+
+        // a single bit is unpacked, but the rest of the byte is not zero
+        let bytes = crate::to_bytes(&(true, true)).unwrap();
+        assert!(crate::from_bytes_strict::<bool>(&bytes).is_err());
+    }
+
+    #[test]
+    fn strict_with_limits() {
+        let bytes = crate::to_bytes(&vec![1u8; 4]).unwrap();
+        let unpacked = crate::from_bytes_strict_with_limits::<Vec<u8>>(&bytes, LIMITS).unwrap();
+        assert_eq!(unpacked, vec![1; 4]);
+        assert!(crate::from_bytes_strict_with_limits::<u8>(&bytes, LIMITS).is_err());
+    }
+
+    #[test]
+    fn unlimited_by_default() {
+        let bytes = crate::to_bytes(&vec![0u8; 1000]).unwrap();
+        assert_eq!(crate::from_bytes::<Vec<u8>>(&bytes).unwrap().len(), 1000);
+    }
+
+    #[test]
+    fn checksum_round_trip() {
+        let value = (vec![String::from("snowball"); 2], 300u16);
+        let bytes = crate::to_bytes_checked(&value).unwrap();
+        assert_eq!(bytes.len(), crate::to_bytes(&value).unwrap().len() + 4);
+        let unpacked: (Vec<String>, u16) = crate::from_bytes_checked(&bytes).unwrap();
+        assert_eq!(unpacked, value);
+
+        let payload = crate::verify_checksum(&bytes).unwrap();
+        let unpacked = crate::from_bytes_strict_with_limits(payload, LIMITS).unwrap();
+        assert_eq!(value, unpacked);
+    }
+
+    #[test]
+    fn checksum_detects_corruption() {
+        let mut bytes = crate::to_bytes_checked(&(7u8, 1234u32)).unwrap();
+        bytes[1] ^= 0b100;
+        assert!(matches!(
+            crate::from_bytes_checked::<(u8, u32)>(&bytes),
+            Err(crate::Error::ChecksumMismatch { .. })
+        ));
+
+        assert!(matches!(
+            crate::from_bytes_checked::<u8>(&[0; 3]),
+            Err(crate::Error::Eof)
+        ));
+    }
+
+    #[test]
+    fn position_and_skipping() {
+        let bytes = crate::to_bytes(&(true, 0x1234_5678u32, [7u8; 12], 5u8)).unwrap();
+        let mut reader = BitReader::new(&bytes);
+        assert_eq!(reader.bit_position(), 0);
+        assert_eq!(reader.bits_remaining(), 8 * bytes.len());
+
+        assert!(bool::unpack(&mut reader).unwrap());
+        let start = reader.bit_position();
+        u32::unpack(&mut reader).unwrap();
+        let width = reader.bit_position() - start;
+
+        // skip the array, partly from the buffer and partly from bytes not loaded yet
+        reader.skip_bits(12 * 8).unwrap();
+        assert_eq!(reader.bit_position(), start + width + 96);
+        assert_eq!(u8::unpack(&mut reader).unwrap(), 5);
+        assert!(matches!(
+            reader.skip_bits(reader.bits_remaining() + 1),
+            Err(crate::Error::Eof)
+        ));
+
+        let bytes = crate::to_bytes(&(true, 0xabu8)).unwrap();
+        let mut reader = BitReader::new(&bytes);
+        reader.read(3).unwrap();
+        reader.align_to_byte().unwrap();
+        assert_eq!(reader.bit_position(), 8);
+        reader.align_to_byte().unwrap();
+        assert_eq!(reader.bit_position(), 8);
+    }
+
+    #[test]
+    fn aligned_round_trip() {
+        let value = (true, -3i16, Some(3.5f32), String::from("slush"), vec![1u64]);
+        let bytes = crate::to_aligned_bytes(&value).unwrap();
+        let unpacked: (bool, i16, Option<f32>, String, Vec<u64>) =
+            crate::from_aligned_bytes(&bytes).unwrap();
+        assert_eq!(unpacked, value);
+
+        // every field starts on a byte boundary
+        assert!(bytes.len() > crate::to_bytes(&value).unwrap().len());
+        assert_eq!(crate::to_aligned_bytes(&(true, 7u8)).unwrap(), vec![1, 7]);
+    }
+
+    #[test]
+    fn aligned_limits_and_eof() {
+        let bytes = crate::to_aligned_bytes(&vec![0u16; 9]).unwrap();
+        let result = crate::from_aligned_bytes_with_limits::<Vec<u16>>(&bytes, LIMITS);
+        assert!(matches!(
+            result.unwrap_err().kind(),
+            crate::Error::TooLong { .. }
+        ));
+
+        let bytes = crate::to_aligned_bytes(&1.5f64).unwrap();
+        let result = crate::from_aligned_bytes::<f64>(&bytes[..7]);
+        assert!(matches!(result.unwrap_err().kind(), crate::Error::Eof));
+    }
 }
@@ -0,0 +1,369 @@
+//! The number of bits a value is packed into, without packing it.
+//!
+//! Useful to decide how to send a value before packing it, such as whether it fits in a single
+//! packet. The size of a value is exact, and is the number of bits `PackBits::pack` would write.
+//! Some types also have an upper bound for the size of any of their values.
+//!
+//! Fields that are packed with a custom function have no known size, so the derived `PackedSize`
+//! packs them into a `BitCounter`, which only counts the bits written to it.
+//!
+//! Sizes are those of writers without a version, which pack every field. Fields added in a later
+//! version than the reader's are left out, so `packed_bits_versioned` counts them by packing.
+
+use crate::{Error, PackBits, WriteBits};
+
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use std::num::{
+    NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU128,
+    NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize,
+};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::impls::VariableLengthQuantity;
+
+pub trait PackedSize: PackBits {
+    /// The number of bits `pack` writes for this value, with every field included.
+    fn packed_bits(&self) -> usize;
+
+    /// The most bits any value of this type is packed into, or `None` if there is no such limit.
+    fn max_packed_bits() -> Option<usize>
+    where
+        Self: Sized,
+    {
+        None
+    }
+}
+
+/// Counts the bits written to it, without storing them.
+#[derive(Debug, Clone, Default)]
+pub struct BitCounter {
+    bits: usize,
+    version: Option<u32>,
+}
+
+impl BitCounter {
+    pub fn new() -> BitCounter {
+        BitCounter::default()
+    }
+
+    /// Count the bits written for readers of `version`, see `WriteBits::version`.
+    pub fn with_version(self, version: u32) -> BitCounter {
+        BitCounter {
+            version: Some(version),
+            ..self
+        }
+    }
+
+    /// The number of bits written so far.
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+}
+
+impl WriteBits for BitCounter {
+    type Error = Error;
+
+    fn write(&mut self, _bits: u32, count: u8) -> Result<(), Self::Error> {
+        self.bits += usize::from(count.min(32));
+        Ok(())
+    }
+
+    fn align(&mut self) -> Result<(), Self::Error> {
+        self.bits += (8 - self.bits % 8) % 8;
+        Ok(())
+    }
+
+    fn write_aligned(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.align()?;
+        self.bits += 8 * bytes.len();
+        Ok(())
+    }
+
+    fn version(&self) -> Option<u32> {
+        self.version
+    }
+}
+
+/// The number of bits written by a packing function. If packing fails, the bits written up until
+/// the failure are counted.
+pub fn counted_bits<F>(pack: F) -> usize
+where
+    F: FnOnce(&mut BitCounter) -> Result<(), Error>,
+{
+    let mut counter = BitCounter::new();
+    let _ = pack(&mut counter);
+    counter.bits
+}
+
+/// The number of bits a value is packed into for readers of `version`, leaving out fields added
+/// after it.
+pub fn packed_bits_versioned<T>(value: &T, version: u32) -> usize
+where
+    T: PackBits + ?Sized,
+{
+    let mut counter = BitCounter::new().with_version(version);
+    let _ = value.pack(&mut counter);
+    counter.bits
+}
+
+/// The number of bytes `to_bytes` returns for a value.
+pub fn packed_bytes<T>(value: &T) -> usize
+where
+    T: PackedSize + ?Sized,
+{
+    value.packed_bits().div_ceil(8)
+}
+
+macro_rules! impl_fixed_size {
+    ($ty:ty, $bits:expr) => {
+        impl PackedSize for $ty {
+            fn packed_bits(&self) -> usize {
+                $bits
+            }
+
+            fn max_packed_bits() -> Option<usize> {
+                Some($bits)
+            }
+        }
+    };
+}
+
+impl_fixed_size!(bool, 1);
+impl_fixed_size!(u8, 8);
+impl_fixed_size!(i8, 8);
+impl_fixed_size!(f32, 32);
+impl_fixed_size!(f64, 64);
+
+macro_rules! impl_variable_size {
+    ($ty:ty) => {
+        impl PackedSize for $ty {
+            fn packed_bits(&self) -> usize {
+                VariableLengthQuantity::encoded_bits(*self)
+            }
+
+            fn max_packed_bits() -> Option<usize> {
+                Some(<$ty as VariableLengthQuantity>::max_encoded_bits())
+            }
+        }
+    };
+}
+
+impl_variable_size!(u16);
+impl_variable_size!(u32);
+impl_variable_size!(u64);
+impl_variable_size!(u128);
+impl_variable_size!(usize);
+
+impl_variable_size!(i16);
+impl_variable_size!(i32);
+impl_variable_size!(i64);
+impl_variable_size!(i128);
+impl_variable_size!(isize);
+
+macro_rules! impl_non_zero_size {
+    ($ty:ident, $inner:ty) => {
+        impl PackedSize for $ty {
+            fn packed_bits(&self) -> usize {
+                self.get().packed_bits()
+            }
+
+            fn max_packed_bits() -> Option<usize> {
+                <$inner>::max_packed_bits()
+            }
+        }
+    };
+}
+
+impl_non_zero_size!(NonZeroU8, u8);
+impl_non_zero_size!(NonZeroU16, u16);
+impl_non_zero_size!(NonZeroU32, u32);
+impl_non_zero_size!(NonZeroU64, u64);
+impl_non_zero_size!(NonZeroU128, u128);
+impl_non_zero_size!(NonZeroUsize, usize);
+
+impl_non_zero_size!(NonZeroI8, i8);
+impl_non_zero_size!(NonZeroI16, i16);
+impl_non_zero_size!(NonZeroI32, i32);
+impl_non_zero_size!(NonZeroI64, i64);
+impl_non_zero_size!(NonZeroI128, i128);
+impl_non_zero_size!(NonZeroIsize, isize);
+
+impl<T> PackedSize for Option<T>
+where
+    T: PackedSize,
+{
+    fn packed_bits(&self) -> usize {
+        1 + self.as_ref().map_or(0, T::packed_bits)
+    }
+
+    fn max_packed_bits() -> Option<usize> {
+        T::max_packed_bits().map(|bits| 1 + bits)
+    }
+}
+
+/// The number of bits of a length prefix followed by items of the given sizes.
+pub(crate) fn sequence_bits<I>(len: usize, items: I) -> usize
+where
+    I: IntoIterator<Item = usize>,
+{
+    (len as u32).packed_bits() + items.into_iter().sum::<usize>()
+}
+
+impl<T> PackedSize for Vec<T>
+where
+    T: PackedSize,
+{
+    fn packed_bits(&self) -> usize {
+        self.as_slice().packed_bits()
+    }
+}
+
+impl<T> PackedSize for [T]
+where
+    T: PackedSize,
+{
+    fn packed_bits(&self) -> usize {
+        sequence_bits(self.len(), self.iter().map(T::packed_bits))
+    }
+}
+
+impl PackedSize for str {
+    fn packed_bits(&self) -> usize {
+        self.as_bytes().packed_bits()
+    }
+}
+
+impl PackedSize for String {
+    fn packed_bits(&self) -> usize {
+        self.as_str().packed_bits()
+    }
+}
+
+macro_rules! impl_wrapper_size {
+    ($wrapper:ident) => {
+        impl<T> PackedSize for $wrapper<T>
+        where
+            T: PackedSize,
+        {
+            fn packed_bits(&self) -> usize {
+                self.as_ref().packed_bits()
+            }
+
+            fn max_packed_bits() -> Option<usize> {
+                T::max_packed_bits()
+            }
+        }
+    };
+}
+
+impl_wrapper_size!(Box);
+impl_wrapper_size!(Arc);
+impl_wrapper_size!(Rc);
+
+impl<'a, T> PackedSize for Cow<'a, T>
+where
+    T: PackedSize + ToOwned + ?Sized,
+{
+    fn packed_bits(&self) -> usize {
+        self.as_ref().packed_bits()
+    }
+}
+
+impl<T: ?Sized> PackedSize for PhantomData<T> {
+    fn packed_bits(&self) -> usize {
+        0
+    }
+
+    fn max_packed_bits() -> Option<usize> {
+        Some(0)
+    }
+}
+
+macro_rules! impl_tuple_size {
+    ($($ident:ident),+) => {
+        impl<$($ident: PackedSize),*> PackedSize for ($($ident,)*) {
+            #[allow(non_snake_case)]
+            fn packed_bits(&self) -> usize {
+                let ($($ident,)*) = self;
+                0 $( + $ident.packed_bits())*
+            }
+
+            fn max_packed_bits() -> Option<usize> {
+                Some(0 $( + $ident::max_packed_bits()?)*)
+            }
+        }
+    };
+}
+
+impl_tuple_size!(A);
+impl_tuple_size!(A, B);
+impl_tuple_size!(A, B, C);
+impl_tuple_size!(A, B, C, D);
+impl_tuple_size!(A, B, C, D, E);
+impl_tuple_size!(A, B, C, D, E, F);
+impl_tuple_size!(A, B, C, D, E, F, G);
+impl_tuple_size!(A, B, C, D, E, F, G, H);
+impl_tuple_size!(A, B, C, D, E, F, G, H, I);
+impl_tuple_size!(A, B, C, D, E, F, G, H, I, J);
+impl_tuple_size!(A, B, C, D, E, F, G, H, I, J, K);
+impl_tuple_size!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_exact<T>(value: &T)
+    where
+        T: PackedSize + ?Sized,
+    {
+        let mut counter = BitCounter::new();
+        value.pack(&mut counter).unwrap();
+        assert_eq!(value.packed_bits(), counter.bits());
+    }
+
+    #[test]
+    fn integers_exact() {
+        for &value in &[0u32, 1, 255, 256, 65_535, 1 << 24, u32::MAX] {
+            assert_exact(&value);
+        }
+        for &value in &[0i64, -1, 1, i64::MIN, i64::MAX] {
+            assert_exact(&value);
+        }
+        assert_exact(&u128::MAX);
+        assert_exact(&NonZeroU16::new(300).unwrap());
+    }
+
+    #[test]
+    fn containers_exact() {
+        assert_exact(&Some(17u16));
+        assert_exact(&None::<String>);
+        assert_exact(&vec![1u64, 1 << 40, 3]);
+        assert_exact("snowball");
+        assert_exact(&Box::new(String::from("iceball")));
+        assert_exact(&Cow::Borrowed(&[1u8, 2, 3][..]));
+        assert_exact(&(true, 3.5f32, -4i16, PhantomData::<u8>));
+        assert_exact(&(
+            1u8, 2u16, 3u32, 4u64, 5i8, 6i16, 7i32, 8i64, 9f32, true, 11u8, 12u8,
+        ));
+    }
+
+    #[test]
+    fn bytes_match_to_bytes() {
+        let value = (vec![String::from("slush"); 3], Some(1234u32), false);
+        let bytes = crate::to_bytes(&value).unwrap();
+        assert_eq!(packed_bytes(&value), bytes.len());
+    }
+
+    #[test]
+    fn upper_bounds() {
+        assert_eq!(bool::max_packed_bits(), Some(1));
+        assert_eq!(u32::max_packed_bits(), Some(u32::MAX.packed_bits()));
+        assert_eq!(i64::max_packed_bits(), Some(i64::MIN.packed_bits()));
+        assert_eq!(Option::<u8>::max_packed_bits(), Some(9));
+        assert_eq!(<(u8, f64)>::max_packed_bits(), Some(72));
+        assert_eq!(Vec::<u8>::max_packed_bits(), None);
+        assert_eq!(<(u8, String)>::max_packed_bits(), None);
+    }
+}
@@ -0,0 +1,93 @@
+//! Helpers for testing that values survive being packed and unpacked.
+//!
+//! `assert_roundtrip!` packs and unpacks many random values of a type, generated by a `proptest`
+//! strategy, and fails with the smallest value that comes back different:
+//!
+//! ```ignore
+//! use rabbit::test_utils::proptest::prelude::*;
+//!
+//! #[test]
+//! fn directions_roundtrip() {
+//!     rabbit::assert_roundtrip!(Direction, any::<u8>().prop_map(Direction::from_bits_truncate));
+//! }
+//! ```
+//!
+//! Without a strategy, values are generated by the type's `Arbitrary` implementation.
+
+pub use proptest;
+
+use proptest::strategy::Strategy;
+use proptest::test_runner::{TestCaseError, TestRunner};
+
+use crate::{PackBits, UnpackBits};
+
+use std::fmt::Debug;
+
+/// Pack and unpack many values generated by a strategy, panicking with the smallest value that
+/// does not unpack to itself.
+#[macro_export]
+macro_rules! assert_roundtrip {
+    ($ty:ty, $strategy:expr $(,)?) => {
+        $crate::test_utils::assert_roundtrip::<$ty, _>($strategy)
+    };
+    ($ty:ty $(,)?) => {
+        $crate::test_utils::assert_roundtrip::<$ty, _>(
+            $crate::test_utils::proptest::arbitrary::any::<$ty>(),
+        )
+    };
+}
+
+/// Pack and unpack many values generated by `strategy`, panicking with the smallest value that does
+/// not unpack to itself. Usually called through `assert_roundtrip!`.
+pub fn assert_roundtrip<T, S>(strategy: S)
+where
+    T: PackBits + UnpackBits + PartialEq + Debug,
+    S: Strategy<Value = T>,
+{
+    let mut runner = TestRunner::default();
+    let result = runner.run(&strategy, |value| {
+        check_roundtrip(&value).map_err(TestCaseError::fail)
+    });
+
+    if let Err(error) = result {
+        panic!("{}", error);
+    }
+}
+
+/// Pack and unpack a value, describing where it went wrong if it does not unpack to itself.
+pub fn check_roundtrip<T>(value: &T) -> Result<(), String>
+where
+    T: PackBits + UnpackBits + PartialEq + Debug,
+{
+    let bytes = crate::to_bytes(value).map_err(|e| format!("failed to pack: {}", e))?;
+
+    let unpacked: T = crate::from_bytes_strict(&bytes)
+        .map_err(|e| format!("failed to unpack {} bytes: {}", bytes.len(), e))?;
+    if unpacked == *value {
+        return Ok(());
+    }
+
+    let repacked = crate::to_bytes(&unpacked).map_err(|e| format!("failed to repack: {}", e))?;
+    match first_different_bit(&bytes, &repacked) {
+        Some(bit) => Err(format!(
+            "unpacked as {:?}, which packs differently from bit {}",
+            unpacked, bit
+        )),
+        None => Err(format!(
+            "unpacked as {:?}, which packs to the same bits",
+            unpacked
+        )),
+    }
+}
+
+/// The index of the first bit that differs between two packed values, counting from the first bit
+/// packed.
+pub fn first_different_bit(a: &[u8], b: &[u8]) -> Option<usize> {
+    let common = a.iter().zip(b).position(|(x, y)| x != y);
+    match common {
+        // bits are packed starting from the least significant bit of every byte
+        Some(i) => Some(8 * i + (a[i] ^ b[i]).trailing_zeros() as usize),
+        None if a.len() != b.len() => Some(8 * usize::min(a.len(), b.len())),
+        None => None,
+    }
+}
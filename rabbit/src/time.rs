@@ -0,0 +1,86 @@
+//! Compact timestamps for timing data sent over the network.
+
+use crate::{PackBits, PackedSize, ReadBits, UnpackBits, WriteBits};
+
+use std::time::Duration;
+
+/// A point in time counted in whole ticks since an epoch chosen by the application, such as the
+/// start of the server or of a match.
+///
+/// Packed as a variable length integer, so timestamps close to the epoch only take a few bits,
+/// compared to the full width of a `SystemTime` or a `u64` of nanoseconds.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ticks(pub u64);
+
+impl Ticks {
+    /// The number of whole ticks of length `tick` in `elapsed`.
+    pub fn from_duration(elapsed: Duration, tick: Duration) -> Ticks {
+        let ticks = elapsed.as_nanos() / tick.as_nanos().max(1);
+        Ticks(ticks.min(u64::MAX as u128) as u64)
+    }
+
+    /// The time since the epoch, given the length of a tick.
+    pub fn to_duration(self, tick: Duration) -> Duration {
+        let nanos = self.0 as u128 * tick.as_nanos();
+        let secs = nanos / 1_000_000_000;
+        if secs > u64::MAX as u128 {
+            return Duration::from_secs(u64::MAX);
+        }
+        Duration::new(secs as u64, (nanos % 1_000_000_000) as u32)
+    }
+
+    /// The number of ticks since an earlier timestamp, or zero if it is not earlier.
+    pub fn since(self, earlier: Ticks) -> u64 {
+        self.0.saturating_sub(earlier.0)
+    }
+}
+
+impl PackBits for Ticks {
+    fn pack<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where
+        W: WriteBits,
+    {
+        self.0.pack(writer)
+    }
+}
+
+impl UnpackBits for Ticks {
+    fn unpack<R>(reader: &mut R) -> Result<Self, R::Error>
+    where
+        R: ReadBits,
+    {
+        u64::unpack(reader).map(Ticks)
+    }
+}
+
+impl PackedSize for Ticks {
+    fn packed_bits(&self) -> usize {
+        self.0.packed_bits()
+    }
+
+    fn max_packed_bits() -> Option<usize> {
+        u64::max_packed_bits()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_convert_to_durations() {
+        let tick = Duration::from_millis(50);
+        let ticks = Ticks::from_duration(Duration::from_millis(1234), tick);
+        assert_eq!(ticks, Ticks(24));
+        assert_eq!(ticks.to_duration(tick), Duration::from_millis(1200));
+        assert_eq!(ticks.since(Ticks(20)), 4);
+        assert_eq!(Ticks(20).since(ticks), 0);
+    }
+
+    #[test]
+    fn early_ticks_are_small() {
+        let bytes = crate::to_bytes(&Ticks(200)).unwrap();
+        assert_eq!(bytes.len(), 2);
+        assert_eq!(crate::from_bytes::<Ticks>(&bytes).unwrap(), Ticks(200));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::intern::WriterContext;
+
 use std::error::Error as StdError;
 use std::fmt::Display;
 
@@ -12,12 +14,39 @@ pub trait WriteBits {
 
     /// Write `count` bits, starting with the least significant bit (LSB).
     fn write(&mut self, bits: u32, count: u8) -> Result<(), Self::Error>;
+
+    /// Pad with zeros up to the next byte boundary.
+    fn align(&mut self) -> Result<(), Self::Error>;
+
+    /// Pad up to the next byte boundary and write whole bytes, so that readers may borrow them
+    /// without copying.
+    fn write_aligned(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.align()?;
+        for &byte in bytes {
+            self.write(u32::from(byte), 8)?;
+        }
+        Ok(())
+    }
+
+    /// The strings interned by this writer, if it interns strings, see `intern`.
+    fn context(&mut self) -> Option<&mut WriterContext> {
+        None
+    }
+
+    /// The version of the types that will unpack the bytes, if known. Fields added in a later
+    /// version, with `#[rabbit(since = N)]`, are left out. Every field is packed by default.
+    fn version(&self) -> Option<u32> {
+        None
+    }
 }
 
 pub struct BitWriter {
     bytes: Vec<u8>,
     buffer: u64,
     len: u8,
+    /// Pad every write to whole bytes, see `BitWriter::aligned`.
+    aligned: bool,
+    version: Option<u32>,
 }
 
 macro_rules! flush {
@@ -32,20 +61,60 @@ macro_rules! flush {
 
 impl BitWriter {
     pub fn new() -> BitWriter {
+        BitWriter::with_buffer(Vec::new())
+    }
+
+    /// Create a writer that pads every write up to whole bytes instead of packing bits tightly.
+    /// Values take more space, but are packed and unpacked faster. The bytes have to be read by an
+    /// aligned `BitReader`.
+    pub fn aligned() -> BitWriter {
+        BitWriter {
+            aligned: true,
+            ..BitWriter::new()
+        }
+    }
+
+    /// Create a writer that writes into the memory allocated by `bytes`, discarding its contents.
+    pub fn with_buffer(mut bytes: Vec<u8>) -> BitWriter {
+        bytes.clear();
         BitWriter {
-            bytes: Vec::new(),
+            bytes,
             buffer: 0,
             len: 0,
+            aligned: false,
+            version: None,
         }
     }
 
-    pub fn finish(mut self) -> Vec<u8> {
+    /// Leave out the fields that readers of `version` do not know about, see `WriteBits::version`.
+    pub fn with_version(self, version: u32) -> BitWriter {
+        BitWriter {
+            version: Some(version),
+            ..self
+        }
+    }
+
+    /// Discard everything written so far, keeping the allocated memory for the next value.
+    pub fn reset(&mut self) {
+        self.bytes.clear();
+        self.buffer = 0;
+        self.len = 0;
+    }
+
+    /// Pad the last byte with zeros and get every byte written so far. Anything written afterwards
+    /// starts on the next byte boundary.
+    pub fn padded_bytes(&mut self) -> &[u8] {
         self.flush();
 
         while self.len > 0 {
             flush!(self, u8);
         }
 
+        &self.bytes
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.padded_bytes();
         self.bytes
     }
 
@@ -68,6 +137,15 @@ impl WriteBits for BitWriter {
     fn write(&mut self, bits: u32, count: u8) -> Result<(), Self::Error> {
         let count = u8::min(count, 32);
         let mask = u32::max_value().checked_shr(32 - count as u32).unwrap_or(0);
+
+        if self.aligned {
+            // nothing is ever buffered, so the bytes can be written right away
+            let bytes = usize::from(count.div_ceil(8));
+            self.bytes
+                .extend_from_slice(&(bits & mask).to_le_bytes()[..bytes]);
+            return Ok(());
+        }
+
         let masked_bits = (bits & mask) as u64;
         self.buffer |= masked_bits << self.len;
         self.len += count;
@@ -76,4 +154,65 @@ impl WriteBits for BitWriter {
 
         Ok(())
     }
+
+    fn align(&mut self) -> Result<(), Self::Error> {
+        let padding = (8 - self.len % 8) % 8;
+        self.write(0, padding)
+    }
+
+    fn write_aligned(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.align()?;
+        while self.len > 0 {
+            flush!(self, u8);
+        }
+        self.bytes.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn version(&self) -> Option<u32> {
+        self.version
+    }
+}
+
+/// Whether a field added in version `since` is packed by the writer, see `WriteBits::version`.
+pub fn includes<W>(writer: &W, since: u32) -> bool
+where
+    W: WriteBits + ?Sized,
+{
+    match writer.version() {
+        Some(version) => version >= since,
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PackBits;
+
+    #[test]
+    fn buffer_reused() {
+        let mut bytes = Vec::with_capacity(64);
+        let capacity = bytes.capacity();
+
+        crate::to_bytes_into(&vec![7u32; 10], &mut bytes).unwrap();
+        assert_eq!(bytes, crate::to_bytes(&vec![7u32; 10]).unwrap());
+
+        crate::to_bytes_into(&(true, 300u16), &mut bytes).unwrap();
+        assert_eq!(bytes, crate::to_bytes(&(true, 300u16)).unwrap());
+        assert_eq!(bytes.capacity(), capacity);
+    }
+
+    #[test]
+    fn reset_discards_bits() {
+        let mut writer = BitWriter::new();
+        true.pack(&mut writer).unwrap();
+        300u16.pack(&mut writer).unwrap();
+        let expected = crate::to_bytes(&(true, 300u16)).unwrap();
+        assert_eq!(writer.padded_bytes(), &expected[..]);
+
+        writer.reset();
+        5u8.pack(&mut writer).unwrap();
+        assert_eq!(writer.finish(), vec![5]);
+    }
 }
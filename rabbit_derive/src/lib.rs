@@ -3,13 +3,16 @@ extern crate proc_macro;
 #[macro_use]
 mod macros;
 
-use proc_macro2::{Span, TokenStream};
+use proc_macro2::{Span, TokenStream, TokenTree};
 use quote::{quote, ToTokens};
 use syn::{
-    parse::ParseStream, punctuated::Punctuated, spanned::Spanned, Data, DataEnum, DataStruct,
-    DeriveInput, Field, Fields, Ident, Lit, MetaNameValue, Path, Result, Token,
+    parse::ParseStream, parse_quote, punctuated::Punctuated, spanned::Spanned, Attribute, Data,
+    DataEnum, DataStruct, DeriveInput, Field, Fields, Ident, Index, Lifetime, Lit, LitStr, Member,
+    Meta, Path, Result, Token, Variant, WherePredicate,
 };
 
+use std::convert::TryFrom;
+
 struct Errors {
     error: Option<syn::Error>,
 }
@@ -37,6 +40,66 @@ impl Errors {
 struct Attributes {
     pack_fn: Option<Path>,
     unpack_fn: Option<Path>,
+    /// The field is not packed, and is unpacked as its default value.
+    skip: bool,
+    /// The field is an integer packed in exactly this many bits, using the functions in
+    /// `rabbit::fixed_width`, or `rabbit::bounded` for a range, as its packing functions.
+    bits: Option<u8>,
+    /// The field was added in this version, and is left out when packing for earlier versions.
+    since: Option<u32>,
+}
+
+struct ContainerAttributes {
+    /// The whole type is packed with this function instead of field by field.
+    pack_fn: Option<Path>,
+    /// The whole type is unpacked with this function instead of field by field.
+    unpack_fn: Option<Path>,
+    /// Ranges of variant indices kept for future variants, inclusive at both ends.
+    reserved: Vec<(u32, u32)>,
+    /// Bounds on the generic parameters that replace the inferred ones.
+    bound: Option<Vec<WherePredicate>>,
+    /// Checks every unpacked value, failing the unpacking with its error.
+    validate: Option<Path>,
+    /// The type is packed exactly like its only field.
+    transparent: bool,
+}
+
+/// Where unpacked fields belong, which is described in the path of unpacking errors.
+#[derive(Copy, Clone)]
+enum Location<'a> {
+    Struct,
+    Variant(&'a Ident),
+    /// The only field of a transparent type, which is left out of the path.
+    Transparent,
+}
+
+/// Whether a trait packs or unpacks, which decides the custom functions that replace it.
+#[derive(Copy, Clone)]
+enum Direction {
+    Pack,
+    Unpack,
+}
+
+impl Direction {
+    fn custom_fn<'a>(
+        self,
+        pack_fn: &'a Option<Path>,
+        unpack_fn: &'a Option<Path>,
+    ) -> Option<&'a Path> {
+        match self {
+            Direction::Pack => pack_fn.as_ref(),
+            Direction::Unpack => unpack_fn.as_ref(),
+        }
+    }
+}
+
+struct VariantAttributes {
+    /// The index the variant is packed as, instead of the one following the previous variant.
+    index: Option<u32>,
+    /// Unknown and reserved indices are unpacked as this unit variant instead of failing. The
+    /// fields of an unknown variant can not be told apart from what follows it, so every remaining
+    /// bit is skipped, and the enum can only be the last value unpacked.
+    other: bool,
 }
 
 #[proc_macro_derive(Rabbit, attributes(rabbit))]
@@ -57,18 +120,70 @@ pub fn derive_pack_bits(item: proc_macro::TokenStream) -> proc_macro::TokenStrea
     }
 }
 
-#[proc_macro_derive(UnpackBits)]
+#[proc_macro_derive(UnpackBits, attributes(rabbit))]
 pub fn derive_unpack_bits(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = syn::parse_macro_input!(item as DeriveInput);
 
-    match impl_unpack_bits(input) {
+    let output = if cfg!(feature = "debug-schema") {
+        impl_unpack_bits(input.clone()).and_then(|mut output| {
+            output.extend(impl_inspect(input)?);
+            Ok(output)
+        })
+    } else {
+        impl_unpack_bits(input)
+    };
+
+    match output {
+        Ok(output) => output.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+#[proc_macro_derive(UnpackBorrowed, attributes(rabbit))]
+pub fn derive_unpack_borrowed(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(item as DeriveInput);
+
+    match impl_unpack_borrowed(input) {
+        Ok(output) => output.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+#[proc_macro_derive(PackDelta, attributes(rabbit))]
+pub fn derive_pack_delta(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(item as DeriveInput);
+
+    match impl_pack_delta(input) {
         Ok(output) => output.into(),
         Err(e) => e.to_compile_error().into(),
     }
 }
 
-fn impl_pack_bits(input: DeriveInput) -> Result<TokenStream> {
-    let body = item_body(&input.data, pack_struct_body, pack_enum_body)?;
+#[proc_macro_derive(UnpackDelta, attributes(rabbit))]
+pub fn derive_unpack_delta(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(item as DeriveInput);
+
+    match impl_unpack_delta(input) {
+        Ok(output) => output.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+#[proc_macro_derive(PackedSize, attributes(rabbit))]
+pub fn derive_packed_size(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(item as DeriveInput);
+
+    match impl_packed_size(input) {
+        Ok(output) => output.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn impl_pack_bits(mut input: DeriveInput) -> Result<TokenStream> {
+    let body = match extract_container_attributes(&input.attrs)?.pack_fn {
+        Some(pack_fn) => quote! { (#pack_fn)(self, __writer) },
+        None => item_body(&input, pack_struct_body, pack_enum_body)?,
+    };
 
     let rabbit = rabbit!();
     let pack = quote! {
@@ -80,33 +195,396 @@ fn impl_pack_bits(input: DeriveInput) -> Result<TokenStream> {
         }
     };
 
-    impl_trait(&input, quote! { rabbit::PackBits }, pack)
+    let name = quote! { rabbit::PackBits };
+    add_bounds(&mut input, name.clone(), Direction::Pack)?;
+    impl_trait(&input, name, pack)
 }
 
-fn impl_unpack_bits(input: DeriveInput) -> Result<TokenStream> {
-    let body = item_body(&input.data, unpack_struct_body, unpack_enum_body)?;
+fn impl_unpack_bits(mut input: DeriveInput) -> Result<TokenStream> {
+    let body = match extract_container_attributes(&input.attrs)?.unpack_fn {
+        Some(unpack_fn) => quote! { (#unpack_fn)(__reader) },
+        None => item_body(&input, unpack_struct_body, unpack_enum_body)?,
+    };
+    let body = validated(&input.attrs, body)?;
+    let (checks, unpacks_rest) = trailing_values(&input)?;
 
     let rabbit = rabbit!();
     let unpack = quote! {
+        const UNPACKS_REST: bool = #unpacks_rest;
+
         fn unpack<__R>(__reader: &mut __R) -> Result<Self, __R::Error>
         where
             __R: #rabbit::ReadBits,
         {
+            #checks
+            #body
+        }
+    };
+
+    let name = quote! { rabbit::UnpackBits };
+    add_bounds(&mut input, name.clone(), Direction::Unpack)?;
+    impl_trait(&input, name, unpack)
+}
+
+/// Unpack a type that borrows from the packed bytes with the lifetime of the type, or any lifetime
+/// if the type has none. Types can borrow with at most one lifetime.
+fn impl_unpack_borrowed(mut input: DeriveInput) -> Result<TokenStream> {
+    let body = match extract_container_attributes(&input.attrs)?.unpack_fn {
+        Some(unpack_fn) => quote! { (#unpack_fn)(__reader) },
+        None => item_body(
+            &input,
+            unpack_borrowed_struct_body,
+            unpack_borrowed_enum_body,
+        )?,
+    };
+    let body = validated(&input.attrs, body)?;
+
+    let lifetime = {
+        let mut lifetimes = input.generics.lifetimes();
+        match (lifetimes.next(), lifetimes.next()) {
+            (None, _) => Lifetime::new("'__a", Span::call_site()),
+            (Some(def), None) => def.lifetime.clone(),
+            (Some(_), Some(extra)) => {
+                return Err(err!(extra, "borrowed values can only have one lifetime"));
+            }
+        }
+    };
+    let bound = quote! { rabbit::UnpackBorrowed<#lifetime> };
+    add_bounds(&mut input, bound, Direction::Unpack)?;
+
+    let mut generics = input.generics.clone();
+    if generics.lifetimes().next().is_none() {
+        generics.params.insert(0, parse_quote! { #lifetime });
+    }
+    let (impl_generics, _, _) = generics.split_for_impl();
+    let (_, type_generics, where_clause) = input.generics.split_for_impl();
+
+    let rabbit = rabbit!();
+    let ident = &input.ident;
+    let output = quote! {
+        impl #impl_generics rabbit::UnpackBorrowed<#lifetime>
+            for #ident #type_generics
+                #where_clause
+        {
+            fn unpack_borrowed<__R>(__reader: &mut __R) -> Result<Self, __R::Error>
+            where
+                __R: #rabbit::borrowed::ReadBorrowed<#lifetime>,
+            {
+                #body
+            }
+        }
+    };
+
+    Ok(output)
+}
+
+/// Describe the fields of the type, see `rabbit::inspect`. Every type parameter has to implement
+/// `Inspect` as well.
+fn impl_inspect(mut input: DeriveInput) -> Result<TokenStream> {
+    let rabbit = rabbit!();
+    let name = input.ident.to_string();
+    let unpack_fn = extract_container_attributes(&input.attrs)?.unpack_fn;
+    let schema = match (&input.data, unpack_fn) {
+        // shown without a value, like fields with a custom unpacking function
+        (Data::Struct(_), Some(unpack_fn)) | (Data::Enum(_), Some(unpack_fn)) => quote! {
+            #rabbit::inspect::Schema {
+                name: ::std::any::type_name::<Self>(),
+                kind: #rabbit::inspect::Kind::Value(|__reader| {
+                    (#unpack_fn)(__reader).map(|_: Self| None)
+                }),
+            }
+        },
+        (Data::Struct(data), None) if extract_container_attributes(&input.attrs)?.transparent => {
+            let (field, attrs) = transparent_field(data)?;
+            field_schema(field, attrs)
+        }
+        (Data::Struct(data), None) => {
+            let fields = schema_fields(&data.fields)?;
+            quote! { #rabbit::inspect::Schema::structure(#name, #fields) }
+        }
+        (Data::Enum(data), None) => {
+            let index_bits = index_bits(&input.attrs, data)?;
+            let variants = data
+                .variants
+                .iter()
+                .zip(variant_indices(&input.attrs, data)?)
+                .map(|(variant, index)| {
+                    let variant_name = variant.ident.to_string();
+                    let fields = schema_fields(&variant.fields)?;
+                    Ok(quote! {
+                        #rabbit::inspect::Variant {
+                            name: #variant_name,
+                            index: #index,
+                            fields: #fields,
+                        }
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            quote! {
+                #rabbit::inspect::Schema::enumeration(#name, #index_bits, vec![ #( #variants ),* ])
+            }
+        }
+        (Data::Union(data), _) => {
+            return Err(err!(
+                data.union_token,
+                "only available for `struct`s and `enum`s"
+            ))
+        }
+    };
+
+    for param in input.generics.type_params_mut() {
+        let bound = parse_quote! { #rabbit::inspect::Inspect };
+        param.bounds.push(bound);
+    }
+
+    let items = quote! {
+        // fields are probed for the most descriptive schema through auto-referencing
+        #[allow(clippy::needless_borrow)]
+        fn schema() -> #rabbit::inspect::Schema {
+            #schema
+        }
+    };
+
+    impl_trait(&input, quote! { rabbit::inspect::Inspect }, items)
+}
+
+/// The schema of every field, in the order they are packed. Fields with a custom unpacking function
+/// are shown without a value.
+fn schema_fields(fields: &Fields) -> Result<TokenStream> {
+    let rabbit = rabbit!();
+
+    let mut schemas = Vec::new();
+    for (member, field) in field_members(fields).iter().zip(fields) {
+        let name = match member {
+            Member::Named(ident) => ident.to_string(),
+            Member::Unnamed(index) => index.index.to_string(),
+        };
+
+        let attrs = extract_attributes(field)?;
+        if attrs.skip {
+            continue;
+        }
+
+        let schema = field_schema(field, attrs);
+        schemas.push(quote! {
+            #rabbit::inspect::Field {
+                name: #name,
+                schema: || #schema,
+            }
+        });
+    }
+
+    Ok(quote! { vec![ #( #schemas ),* ] })
+}
+
+/// The schema of the values of a field.
+fn field_schema(field: &Field, attrs: Attributes) -> TokenStream {
+    let rabbit = rabbit!();
+    let ty = &field.ty;
+    match attrs.unpack_fn {
+        Some(unpack_fn) if attrs.bits.is_some() => quote! {
+            #rabbit::inspect::Schema {
+                name: ::std::any::type_name::<#ty>(),
+                kind: #rabbit::inspect::Kind::Value(|__reader| {
+                    (#unpack_fn)(__reader).map(|value: #ty| Some(value.to_string()))
+                }),
+            }
+        },
+        Some(unpack_fn) => quote! {
+            #rabbit::inspect::Schema {
+                name: ::std::any::type_name::<#ty>(),
+                kind: #rabbit::inspect::Kind::Value(|__reader| {
+                    (#unpack_fn)(__reader).map(|_: #ty| None)
+                }),
+            }
+        },
+        None => quote! {{
+            use #rabbit::inspect::probe::{DebugSchema, InspectSchema, OpaqueSchema};
+            (&&&#rabbit::inspect::probe::Probe::<#ty>::new()).schema()
+        }},
+    }
+}
+
+fn impl_pack_delta(mut input: DeriveInput) -> Result<TokenStream> {
+    let rabbit = rabbit!();
+    let body = match extract_container_attributes(&input.attrs)?.pack_fn {
+        // packed in full if it changed, like fields with a custom packing function
+        Some(pack_fn) => quote! {
+            let __changed = self != __baseline;
+            #rabbit::WriteBits::write(__writer, __changed as u32, 1)?;
+            if __changed {
+                (#pack_fn)(self, __writer)?;
+            }
+            Ok(())
+        },
+        None => item_body(&input, pack_delta_struct_body, pack_delta_enum_body)?,
+    };
+
+    let pack = quote! {
+        fn pack_delta<__W>(&self, __baseline: &Self, __writer: &mut __W) -> Result<(), __W::Error>
+        where
+            __W: #rabbit::WriteBits,
+        {
+            #body
+        }
+    };
+
+    let name = quote! { rabbit::PackDelta };
+    add_bounds(&mut input, name.clone(), Direction::Pack)?;
+    impl_trait(&input, name, pack)
+}
+
+fn impl_unpack_delta(mut input: DeriveInput) -> Result<TokenStream> {
+    let rabbit = rabbit!();
+    let body = match extract_container_attributes(&input.attrs)?.unpack_fn {
+        Some(unpack_fn) => quote! {
+            if #rabbit::ReadBits::read(__reader, 1)? != 0 {
+                (#unpack_fn)(__reader)
+            } else {
+                Ok(::std::clone::Clone::clone(__baseline))
+            }
+        },
+        None => item_body(&input, unpack_delta_struct_body, unpack_delta_enum_body)?,
+    };
+    let body = validated(&input.attrs, body)?;
+    let (checks, _) = trailing_values(&input)?;
+
+    let unpack = quote! {
+        fn unpack_delta<__R>(__baseline: &Self, __reader: &mut __R) -> Result<Self, __R::Error>
+        where
+            __R: #rabbit::ReadBits,
+        {
+            #checks
             #body
         }
     };
 
-    impl_trait(&input, quote! { rabbit::UnpackBits }, unpack)
+    let name = quote! { rabbit::UnpackDelta };
+    add_bounds(&mut input, name.clone(), Direction::Unpack)?;
+    impl_trait(&input, name, unpack)
+}
+
+fn impl_packed_size(mut input: DeriveInput) -> Result<TokenStream> {
+    let rabbit = rabbit!();
+    let (bits, max_bits) = match extract_container_attributes(&input.attrs)?.pack_fn {
+        Some(pack_fn) => (
+            quote! { #rabbit::size::counted_bits(|__counter| (#pack_fn)(self, __counter)) },
+            quote! { None },
+        ),
+        None => (
+            item_body(&input, packed_bits_struct_body, packed_bits_enum_body)?,
+            item_body(&input, max_bits_struct_body, max_bits_enum_body)?,
+        ),
+    };
+
+    let items = quote! {
+        fn packed_bits(&self) -> usize {
+            #bits
+        }
+
+        fn max_packed_bits() -> Option<usize> {
+            #max_bits
+        }
+    };
+
+    let name = quote! { rabbit::PackedSize };
+    add_bounds(&mut input, name.clone(), Direction::Pack)?;
+    impl_trait(&input, name, items)
+}
+
+/// Check the value unpacked by `body` with the `validate` function of the type, if it has one.
+fn validated(attrs: &[Attribute], body: TokenStream) -> Result<TokenStream> {
+    let rabbit = rabbit!();
+    let output = match extract_container_attributes(attrs)?.validate {
+        Some(validate) => quote! {
+            let __value: Self = (|| -> Result<Self, __R::Error> { #body })()?;
+            match (#validate)(&__value) {
+                Ok(()) => Ok(__value),
+                Err(__error) => Err(<__R::Error as #rabbit::read::Error>::custom(__error)),
+            }
+        },
+        None => body,
+    };
+
+    Ok(output)
+}
+
+/// Checks that only the last unpacked field of every struct and variant may skip every remaining
+/// bit, and whether the type itself may, see `rabbit::UnpackBits::UNPACKS_REST`. Enums with an
+/// `other` variant skip the fields of variants they do not know.
+fn trailing_values(input: &DeriveInput) -> Result<(TokenStream, TokenStream)> {
+    let container = extract_container_attributes(&input.attrs)?;
+    if container.unpack_fn.is_some() {
+        return Ok((quote! {}, quote! { false }));
+    }
+
+    match &input.data {
+        Data::Struct(data) => trailing_fields(&data.fields),
+        Data::Enum(data) => {
+            let mut checks = Vec::new();
+            let mut unpacks_rest = vec![match other_variant(data)? {
+                Some(_) => quote! { true },
+                None => quote! { false },
+            }];
+            for variant in &data.variants {
+                let (check, rest) = trailing_fields(&variant.fields)?;
+                checks.push(check);
+                unpacks_rest.push(rest);
+            }
+            Ok((quote! { #( #checks )* }, quote! { #( #unpacks_rest )||* }))
+        }
+        Data::Union(_) => Ok((quote! {}, quote! { false })),
+    }
+}
+
+fn trailing_fields(fields: &Fields) -> Result<(TokenStream, TokenStream)> {
+    let rabbit = rabbit!();
+
+    // fields with an unpacking function of their own may skip anything, but are trusted not to
+    let mut unpacked = Vec::new();
+    for field in fields {
+        let attrs = extract_attributes(field)?;
+        if !attrs.skip {
+            unpacked.push(attrs.unpack_fn.map_or(Some(&field.ty), |_| None));
+        }
+    }
+
+    let unpacks_rest = match unpacked.pop() {
+        Some(Some(ty)) => quote! { <#ty as #rabbit::UnpackBits>::UNPACKS_REST },
+        _ => quote! { false },
+    };
+    let checks = unpacked.into_iter().flatten().map(|ty| {
+        quote! { const { #rabbit::read::assert_not_trailing::<#ty>() }; }
+    });
+
+    Ok((quote! { #( #checks )* }, unpacks_rest))
 }
 
 fn item_body(
-    data: &Data,
-    struct_body: fn(&DataStruct) -> Result<TokenStream>,
-    enum_body: fn(&DataEnum) -> Result<TokenStream>,
+    input: &DeriveInput,
+    struct_body: fn(&[Attribute], &DataStruct) -> Result<TokenStream>,
+    enum_body: fn(&[Attribute], &DataEnum) -> Result<TokenStream>,
 ) -> Result<TokenStream> {
-    match data {
-        syn::Data::Struct(data) => struct_body(&data),
-        syn::Data::Enum(data) => enum_body(&data),
+    let container = extract_container_attributes(&input.attrs)?;
+    match &input.data {
+        syn::Data::Struct(data) => {
+            if !container.reserved.is_empty() {
+                return Err(err!(
+                    data.struct_token,
+                    "only `enum`s have variant indices to reserve"
+                ));
+            }
+            if container.transparent {
+                transparent_field(data)?;
+            }
+            struct_body(&input.attrs, data)
+        }
+        syn::Data::Enum(data) => {
+            if container.transparent {
+                return Err(err!(data.enum_token, "only `struct`s can be transparent"));
+            }
+            enum_body(&input.attrs, data)
+        }
         syn::Data::Union(data) => Err(err!(
             data.union_token,
             "only available for `struct`s and `enum`s"
@@ -114,36 +592,85 @@ fn item_body(
     }
 }
 
-fn impl_trait(input: &DeriveInput, name: TokenStream, items: TokenStream) -> Result<TokenStream> {
-    let mut errors = Errors::new();
+/// Require every type parameter used by a packed field to implement `bound`. Fields that are
+/// skipped or have a custom function in the `direction` of the trait do not need the bound, and
+/// neither do types with a custom function of their own. A `bound` attribute on the type replaces
+/// the inferred bounds.
+fn add_bounds(input: &mut DeriveInput, bound: TokenStream, direction: Direction) -> Result<()> {
+    let container = extract_container_attributes(&input.attrs)?;
+    let predicates = match container.bound {
+        Some(predicates) => predicates,
+        None if direction
+            .custom_fn(&container.pack_fn, &container.unpack_fn)
+            .is_some() =>
+        {
+            Vec::new()
+        }
+        None => {
+            let fields: Vec<&Field> = match &input.data {
+                Data::Struct(data) => data.fields.iter().collect(),
+                Data::Enum(data) => data.variants.iter().flat_map(|v| &v.fields).collect(),
+                Data::Union(_) => Vec::new(),
+            };
+
+            let mut packed_types = Vec::new();
+            for field in fields {
+                let attrs = extract_attributes(field)?;
+                let custom = direction.custom_fn(&attrs.pack_fn, &attrs.unpack_fn);
+                if !attrs.skip && custom.is_none() {
+                    packed_types.push(field.ty.to_token_stream());
+                }
+            }
 
-    let ident = &input.ident;
-    let lt = &input.generics.lt_token;
-    let gt = &input.generics.gt_token;
-    let where_clause = &input.generics.where_clause;
-    let generic_params = &input.generics.params;
-    let generic_idents = generic_params.iter().filter_map(|param| match param {
-        syn::GenericParam::Type(ty) => Some(ty.ident.clone()),
-        syn::GenericParam::Const(value) => Some(value.ident.clone()),
-        syn::GenericParam::Lifetime(life) => {
-            errors.push(err!(life, "lifetimes are not allowed"));
-            None
+            input
+                .generics
+                .type_params()
+                .filter(|param| {
+                    let ident = &param.ident;
+                    packed_types.iter().any(|ty| mentions(ty.clone(), ident))
+                })
+                .map(|param| {
+                    let ident = &param.ident;
+                    parse_quote! { #ident: #bound }
+                })
+                .collect()
         }
-    });
+    };
+
+    if !predicates.is_empty() {
+        let where_clause = input.generics.make_where_clause();
+        where_clause.predicates.extend(predicates);
+    }
+
+    Ok(())
+}
+
+/// Whether the tokens contain the identifier anywhere, such as `T` in `Vec<Option<T>>`.
+fn mentions(tokens: TokenStream, ident: &Ident) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(other) => other == *ident,
+        TokenTree::Group(group) => mentions(group.stream(), ident),
+        _ => false,
+    })
+}
+
+fn impl_trait(input: &DeriveInput, name: TokenStream, items: TokenStream) -> Result<TokenStream> {
+    let ident = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
 
     let output = quote! {
-        impl #lt #generic_params #gt #name
-            for #ident #lt #(#generic_idents),* #gt
+        impl #impl_generics #name
+            for #ident #type_generics
                 #where_clause
         {
             #items
         }
     };
 
-    errors.finish(output)
+    Ok(output)
 }
 
-fn pack_struct_body(data: &DataStruct) -> Result<TokenStream> {
+fn pack_struct_body(_: &[Attribute], data: &DataStruct) -> Result<TokenStream> {
     let (destructure, idents) = field_destructure(&data.fields);
     let attrs = field_attributes(&data.fields)?;
     let pack_fields = pack_fields(idents.iter().zip(&attrs));
@@ -157,16 +684,14 @@ fn pack_struct_body(data: &DataStruct) -> Result<TokenStream> {
     Ok(output)
 }
 
-fn pack_enum_body(data: &DataEnum) -> Result<TokenStream> {
-    let index_bits = index_bits(data)?;
+fn pack_enum_body(attrs: &[Attribute], data: &DataEnum) -> Result<TokenStream> {
+    let index_bits = index_bits(attrs, data)?;
 
     let variants = data
         .variants
         .iter()
-        .enumerate()
-        .map(|(index, variant)| {
-            let variant_index = index as u32;
-
+        .zip(variant_indices(attrs, data)?)
+        .map(|(variant, variant_index)| {
             let ident = &variant.ident;
             let (destructure, idents) = field_destructure(&variant.fields);
             let attrs = field_attributes(&variant.fields)?;
@@ -193,9 +718,43 @@ fn pack_enum_body(data: &DataEnum) -> Result<TokenStream> {
     Ok(output)
 }
 
-fn unpack_struct_body(data: &DataStruct) -> Result<TokenStream> {
+fn unpack_struct_body(attrs: &[Attribute], data: &DataStruct) -> Result<TokenStream> {
+    let rabbit = rabbit!();
+    unpack_struct(attrs, data, quote! { #rabbit::UnpackBits::unpack })
+}
+
+fn unpack_enum_body(attrs: &[Attribute], data: &DataEnum) -> Result<TokenStream> {
+    let rabbit = rabbit!();
+    unpack_enum(attrs, data, quote! { #rabbit::UnpackBits::unpack })
+}
+
+fn unpack_borrowed_struct_body(attrs: &[Attribute], data: &DataStruct) -> Result<TokenStream> {
+    let rabbit = rabbit!();
+    unpack_struct(
+        attrs,
+        data,
+        quote! { #rabbit::UnpackBorrowed::unpack_borrowed },
+    )
+}
+
+fn unpack_borrowed_enum_body(attrs: &[Attribute], data: &DataEnum) -> Result<TokenStream> {
+    let rabbit = rabbit!();
+    unpack_enum(
+        attrs,
+        data,
+        quote! { #rabbit::UnpackBorrowed::unpack_borrowed },
+    )
+}
+
+/// Unpack every field with the `unpack` function, unless it has an unpacking function of its own.
+fn unpack_struct(
+    attrs: &[Attribute],
+    data: &DataStruct,
+    unpack: TokenStream,
+) -> Result<TokenStream> {
     let (destructure, idents) = field_destructure(&data.fields);
-    let unpack_fields = unpack_fields(idents.iter().zip(&data.fields))?;
+    let location = struct_location(attrs)?;
+    let unpack_fields = unpack_fields(idents.iter().zip(&data.fields), location, &unpack)?;
 
     let output = quote! {
         #unpack_fields
@@ -205,19 +764,20 @@ fn unpack_struct_body(data: &DataStruct) -> Result<TokenStream> {
     Ok(output)
 }
 
-fn unpack_enum_body(data: &DataEnum) -> Result<TokenStream> {
-    let index_bits = index_bits(data)?;
+fn unpack_enum(attrs: &[Attribute], data: &DataEnum, unpack: TokenStream) -> Result<TokenStream> {
+    let index_bits = index_bits(attrs, data)?;
+    let indices = variant_indices(attrs, data)?;
+    let reserved = extract_container_attributes(attrs)?.reserved;
 
     let variants = data
         .variants
         .iter()
-        .enumerate()
-        .map(|(index, variant)| {
-            let variant_index = index as u32;
-
+        .zip(&indices)
+        .map(|(variant, variant_index)| {
             let ident = &variant.ident;
             let (destructure, idents) = field_destructure(&variant.fields);
-            let unpack_fields = unpack_fields(idents.iter().zip(&variant.fields))?;
+            let fields = idents.iter().zip(&variant.fields);
+            let unpack_fields = unpack_fields(fields, Location::Variant(ident), &unpack)?;
 
             Ok(quote! {
                 #variant_index => {
@@ -229,19 +789,239 @@ fn unpack_enum_body(data: &DataEnum) -> Result<TokenStream> {
         .collect::<Result<Vec<_>>>()?;
 
     let rabbit = rabbit!();
+    let output = match other_variant(data)? {
+        Some(other) => quote! {
+            let variant_index = #rabbit::ReadBits::read(__reader, #index_bits)?;
+            match variant_index {
+                #( #variants, )*
+                _ => {
+                    let __remaining = #rabbit::ReadBits::bits_remaining(__reader);
+                    #rabbit::ReadBits::skip_bits(__reader, __remaining)?;
+                    Ok(Self::#other)
+                }
+            }
+        },
+        None => {
+            let reserved = reserved.iter().map(|(first, last)| {
+                quote! {
+                    #first..=#last => Err(<__R::Error as #rabbit::read::Error>::custom(format!(
+                        "variant index {} is reserved for a future version",
+                        variant_index
+                    )))
+                }
+            });
+            let expected = expected_index(&indices);
+            quote! {
+                let variant_index = #rabbit::ReadBits::read(__reader, #index_bits)?;
+                match variant_index {
+                    #( #variants, )*
+                    #( #reserved, )*
+                    _ => Err(<__R::Error as #rabbit::read::Error>::unexpected(#expected, variant_index)),
+                }
+            }
+        }
+    };
+
+    Ok(output)
+}
+
+/// Pack every field relative to the same field of the baseline. Fields with a custom packing
+/// function are packed in full if they changed.
+fn pack_delta_struct_body(_: &[Attribute], data: &DataStruct) -> Result<TokenStream> {
+    let rabbit = rabbit!();
+    let attrs = field_attributes(&data.fields)?;
+
+    let fields = field_members(&data.fields)
+        .into_iter()
+        .zip(&attrs)
+        .filter(|(_, attrs)| !attrs.skip)
+        .map(|(member, attrs)| {
+            let writer = match attrs.pack_fn.as_ref() {
+                Some(pack_fn) => quote! {
+                    let __changed = self.#member != __baseline.#member;
+                    #rabbit::WriteBits::write(__writer, __changed as u32, 1)?;
+                    if __changed {
+                        (#pack_fn)(&self.#member, __writer)?;
+                    }
+                },
+                None => quote! {
+                    #rabbit::PackDelta::pack_delta(&self.#member, &__baseline.#member, __writer)?;
+                },
+            };
+            since_version(attrs, writer)
+        });
+
     let output = quote! {
-        let variant_index = #rabbit::ReadBits::read(__reader, #index_bits)?;
-        match variant_index {
+        #( #fields )*
+        Ok(())
+    };
+
+    Ok(output)
+}
+
+/// Enums are packed in full if they changed.
+fn pack_delta_enum_body(_: &[Attribute], _: &DataEnum) -> Result<TokenStream> {
+    let rabbit = rabbit!();
+    let output = quote! {
+        let __changed = self != __baseline;
+        #rabbit::WriteBits::write(__writer, __changed as u32, 1)?;
+        if __changed {
+            #rabbit::PackBits::pack(self, __writer)?;
+        }
+        Ok(())
+    };
+
+    Ok(output)
+}
+
+fn unpack_delta_struct_body(container: &[Attribute], data: &DataStruct) -> Result<TokenStream> {
+    let rabbit = rabbit!();
+    let (destructure, idents) = field_destructure(&data.fields);
+    let members = field_members(&data.fields);
+    let location = struct_location(container)?;
+
+    let mut readers = Vec::new();
+    for ((ident, member), field) in idents.iter().zip(&members).zip(&data.fields) {
+        let attrs = extract_attributes(field)?;
+        let ty = &field.ty;
+
+        if attrs.skip {
+            readers.push(quote! { let #ident: #ty = ::std::default::Default::default(); });
+            continue;
+        }
+
+        let reader = match attrs.unpack_fn.as_ref() {
+            Some(unpack_fn) => quote! {
+                if #rabbit::ReadBits::read(__reader, 1)? != 0 {
+                    (#unpack_fn)(__reader)
+                } else {
+                    Ok(::std::clone::Clone::clone(&__baseline.#member))
+                }
+            },
+            None => quote! {
+                #rabbit::UnpackDelta::unpack_delta(&__baseline.#member, __reader)
+            },
+        };
+
+        let reader = match attrs.since {
+            Some(since) => quote! {
+                #rabbit::read::unpack_since(__reader, #since, |__reader| #reader).map(|__value| {
+                    __value.unwrap_or_else(|| ::std::clone::Clone::clone(&__baseline.#member))
+                })
+            },
+            None => reader,
+        };
+
+        let reader = within_field(reader, member, location);
+        readers.push(quote! { let #ident: #ty = #reader?; });
+    }
+
+    let output = quote! {
+        #( #readers )*
+        Ok(Self #destructure)
+    };
+
+    Ok(output)
+}
+
+fn unpack_delta_enum_body(_: &[Attribute], _: &DataEnum) -> Result<TokenStream> {
+    let rabbit = rabbit!();
+    let output = quote! {
+        if #rabbit::ReadBits::read(__reader, 1)? != 0 {
+            #rabbit::UnpackBits::unpack(__reader)
+        } else {
+            Ok(::std::clone::Clone::clone(__baseline))
+        }
+    };
+
+    Ok(output)
+}
+
+fn packed_bits_struct_body(_: &[Attribute], data: &DataStruct) -> Result<TokenStream> {
+    let (destructure, idents) = field_destructure(&data.fields);
+    let attrs = field_attributes(&data.fields)?;
+    let bits = packed_bits_fields(idents.iter().zip(&attrs));
+
+    let output = quote! {
+        let Self #destructure = self;
+        #bits
+    };
+
+    Ok(output)
+}
+
+fn packed_bits_enum_body(attrs: &[Attribute], data: &DataEnum) -> Result<TokenStream> {
+    let index_bits = index_bits(attrs, data)? as usize;
+
+    let variants = data
+        .variants
+        .iter()
+        .map(|variant| {
+            let ident = &variant.ident;
+            let (destructure, idents) = field_destructure(&variant.fields);
+            let attrs = field_attributes(&variant.fields)?;
+            let bits = packed_bits_fields(idents.iter().zip(&attrs));
+
+            Ok(quote! {
+                Self::#ident #destructure => #index_bits + #bits
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let output = quote! {
+        match self {
             #( #variants ),*
-            _ => Err(<__R::Error as #rabbit::read::Error>::custom(
-                format!("unknown variant index: {}", variant_index)
-            )),
         }
     };
 
     Ok(output)
 }
 
+/// The sum of the upper bounds of every field. There is no upper bound if any field has a custom
+/// packing function.
+fn max_bits_struct_body(_: &[Attribute], data: &DataStruct) -> Result<TokenStream> {
+    match max_bits_fields(&data.fields)? {
+        Some(bits) => Ok(quote! { Some(#bits) }),
+        None => Ok(quote! { None }),
+    }
+}
+
+/// The largest upper bound of any variant, in addition to the variant index.
+fn max_bits_enum_body(attrs: &[Attribute], data: &DataEnum) -> Result<TokenStream> {
+    let index_bits = index_bits(attrs, data)? as usize;
+
+    let variants = data
+        .variants
+        .iter()
+        .map(|variant| max_bits_fields(&variant.fields))
+        .collect::<Result<Option<Vec<_>>>>()?;
+
+    let variants = match variants {
+        Some(variants) => variants,
+        None => return Ok(quote! { None }),
+    };
+
+    let output = quote! {
+        let mut __max = 0;
+        #( __max = ::std::cmp::max(__max, #variants); )*
+        Some(#index_bits + __max)
+    };
+
+    Ok(output)
+}
+
+/// The expressions used to access each field, such as `self.name` or `self.0`.
+fn field_members(fields: &Fields) -> Vec<Member> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(i)),
+        })
+        .collect()
+}
+
 fn field_destructure(fields: &Fields) -> (TokenStream, Vec<Ident>) {
     let idents = field_idents(fields).collect::<Vec<_>>();
 
@@ -268,58 +1048,312 @@ fn field_attributes(fields: &Fields) -> Result<Vec<Attributes>> {
     fields.iter().map(extract_attributes).collect()
 }
 
+/// The arguments of every `#[rabbit(...)]` attribute.
+fn rabbit_args(attrs: &[Attribute]) -> Result<Vec<Meta>> {
+    let mut args = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("rabbit")) {
+        let parsed = attr.parse_args_with(|stream: ParseStream| {
+            Punctuated::<Meta, Token![,]>::parse_terminated(stream)
+        })?;
+        args.extend(parsed);
+    }
+    Ok(args)
+}
+
+fn unknown_attribute(arg: &Meta) -> syn::Error {
+    err!(
+        arg.path(),
+        format!("unknown attribute: `{}`", arg.to_token_stream())
+    )
+}
+
 fn extract_attributes(field: &Field) -> Result<Attributes> {
     let mut attrs = Attributes::default();
 
-    let raw_attrs = field
-        .attrs
-        .iter()
-        .filter(|attr| attr.path.is_ident("rabbit"));
-
-    for attr in raw_attrs {
-        let args = attr.parse_args_with(|stream: ParseStream| {
-            Punctuated::<MetaNameValue, Token![,]>::parse_terminated(stream)
-        })?;
+    let lit_str = |lit| match lit {
+        Lit::Str(value) => Ok(value),
+        _ => Err(err!(lit, "expected a string literal")),
+    };
 
-        let lit_str = |lit| match lit {
-            Lit::Str(value) => Ok(value),
-            _ => Err(err!(lit, "expected a string literal")),
-        };
+    for arg in rabbit_args(&field.attrs)? {
+        let is = |name| arg.path().is_ident(name);
+        let custom = attrs.pack_fn.is_some() || attrs.unpack_fn.is_some();
+        let fixed = is("bits") || is("range");
+        let packs = is("pack") || is("unpack") || is("with") || fixed;
+        if packs && (attrs.bits.is_some() || fixed && custom) {
+            return Err(err!(
+                arg,
+                "`bits` and `range` can not be combined with other packing functions"
+            ));
+        }
 
-        for arg in args {
-            if arg.path.is_ident("pack") {
+        match arg {
+            Meta::Path(path) if path.is_ident("skip") => attrs.skip = true,
+            Meta::NameValue(arg) if arg.path.is_ident("pack") => {
                 attrs.pack_fn = Some(lit_str(arg.lit)?.parse()?);
-            } else if arg.path.is_ident("unpack") {
+            }
+            Meta::NameValue(arg) if arg.path.is_ident("unpack") => {
                 attrs.unpack_fn = Some(lit_str(arg.lit)?.parse()?);
-            } else if arg.path.is_ident("with") {
-                let value: Path = lit_str(arg.lit)?.parse()?;
-                let member = |ident| {
-                    let mut path = value.clone();
-                    path.segments
-                        .push(Ident::new(ident, Span::call_site()).into());
-                    path
+            }
+            Meta::NameValue(arg) if arg.path.is_ident("bits") => {
+                let bits = match &arg.lit {
+                    Lit::Int(bits) => bits.base10_parse::<u8>().ok().filter(|&bits| bits <= 64),
+                    _ => return Err(err!(arg.lit, "expected an integer literal")),
+                };
+                let bits = bits.ok_or_else(|| err!(arg.lit, "integers have at most 64 bits"))?;
+
+                let rabbit = rabbit!();
+                attrs.pack_fn = Some(parse_quote! { #rabbit::fixed_width::pack::<_, _, #bits> });
+                attrs.unpack_fn =
+                    Some(parse_quote! { #rabbit::fixed_width::unpack::<_, _, #bits> });
+                attrs.bits = Some(bits);
+            }
+            Meta::NameValue(arg) if arg.path.is_ident("range") => {
+                let (min, max) = match &arg.lit {
+                    Lit::Str(range) => parse_range(range)?,
+                    _ => return Err(err!(arg.lit, "expected a string literal")),
+                };
+                if max - min > i128::from(u64::MAX) {
+                    return Err(err!(arg.lit, "ranges span at most 64 bits"));
+                }
+
+                let rabbit = rabbit!();
+                let bounded = quote! { #rabbit::bounded };
+                attrs.pack_fn =
+                    Some(parse_quote! { #bounded::pack_range::<_, _, { #min }, { #max }> });
+                attrs.unpack_fn =
+                    Some(parse_quote! { #bounded::unpack_range::<_, _, { #min }, { #max }> });
+                attrs.bits = Some(128 - ((max - min) as u128).leading_zeros() as u8);
+            }
+            Meta::NameValue(arg) if arg.path.is_ident("since") => {
+                attrs.since = match &arg.lit {
+                    Lit::Int(since) => Some(since.base10_parse()?),
+                    _ => return Err(err!(arg.lit, "expected an integer literal")),
                 };
-                attrs.pack_fn = Some(member("pack"));
-                attrs.unpack_fn = Some(member("unpack"));
-            } else {
-                return Err(err!(
-                    &arg.path,
-                    format!("unknown attribute: `{}`", arg.path.to_token_stream())
-                ));
             }
+            Meta::NameValue(arg) if arg.path.is_ident("with") => {
+                let (pack_fn, unpack_fn) = module_fns(lit_str(arg.lit)?.parse()?);
+                attrs.pack_fn = Some(pack_fn);
+                attrs.unpack_fn = Some(unpack_fn);
+            }
+            arg => return Err(unknown_attribute(&arg)),
         }
     }
 
+    if attrs.skip && (attrs.pack_fn.is_some() || attrs.unpack_fn.is_some()) {
+        return Err(err!(field, "skipped fields can not have packing functions"));
+    }
+    if attrs.skip && attrs.since.is_some() {
+        return Err(err!(field, "skipped fields are not packed in any version"));
+    }
+
     Ok(attrs)
 }
 
-fn index_bits(data: &DataEnum) -> Result<u8> {
-    if data.variants.is_empty() {
-        Err(err!(data.enum_token, "enum must have atleast one variant"))
+/// The `pack` and `unpack` functions of a module given in a `with` attribute.
+fn module_fns(module: Path) -> (Path, Path) {
+    let member = |ident| {
+        let mut path = module.clone();
+        path.segments
+            .push(Ident::new(ident, Span::call_site()).into());
+        path
+    };
+    (member("pack"), member("unpack"))
+}
+
+fn extract_container_attributes(attrs: &[Attribute]) -> Result<ContainerAttributes> {
+    let mut container = ContainerAttributes {
+        pack_fn: None,
+        unpack_fn: None,
+        reserved: Vec::new(),
+        bound: None,
+        validate: None,
+        transparent: false,
+    };
+
+    let lit_str = |lit| match lit {
+        Lit::Str(value) => Ok(value),
+        _ => Err(err!(lit, "expected a string literal")),
+    };
+
+    for arg in rabbit_args(attrs)? {
+        match arg {
+            Meta::NameValue(arg) if arg.path.is_ident("pack") => {
+                container.pack_fn = Some(lit_str(arg.lit)?.parse()?);
+            }
+            Meta::NameValue(arg) if arg.path.is_ident("unpack") => {
+                container.unpack_fn = Some(lit_str(arg.lit)?.parse()?);
+            }
+            Meta::NameValue(arg) if arg.path.is_ident("with") => {
+                let (pack_fn, unpack_fn) = module_fns(lit_str(arg.lit)?.parse()?);
+                container.pack_fn = Some(pack_fn);
+                container.unpack_fn = Some(unpack_fn);
+            }
+            Meta::Path(path) if path.is_ident("transparent") => container.transparent = true,
+            Meta::NameValue(arg) if arg.path.is_ident("validate") => {
+                container.validate = Some(lit_str(arg.lit)?.parse()?);
+            }
+            Meta::NameValue(arg) if arg.path.is_ident("reserve") => match &arg.lit {
+                Lit::Str(range) => container.reserved.push(parse_index_range(range)?),
+                _ => return Err(err!(arg.lit, "expected a string literal")),
+            },
+            Meta::NameValue(arg) if arg.path.is_ident("bound") => match &arg.lit {
+                Lit::Str(bound) => {
+                    let predicates = bound
+                        .parse_with(Punctuated::<WherePredicate, Token![,]>::parse_terminated)?;
+                    container.bound = Some(predicates.into_iter().collect());
+                }
+                _ => return Err(err!(arg.lit, "expected a string literal")),
+            },
+            arg => return Err(unknown_attribute(&arg)),
+        }
+    }
+
+    Ok(container)
+}
+
+/// Parse a variant index or a range of them, such as `"5"`, `"3..8"` or `"3..=7"`, into its first
+/// and last index.
+fn parse_index_range(range: &LitStr) -> Result<(u32, u32)> {
+    let (first, last) = parse_range(range)?;
+    match (u32::try_from(first), u32::try_from(last)) {
+        (Ok(first), Ok(last)) => Ok((first, last)),
+        _ => Err(err!(range, "variant indices are unsigned 32-bit integers")),
+    }
+}
+
+/// Parse an integer or a range of them, such as `"5"`, `"-3..8"` or `"3..=7"`, into its first and
+/// last integer.
+fn parse_range(range: &LitStr) -> Result<(i128, i128)> {
+    let invalid = || err!(range, "expected an integer or a range, such as \"3..=7\"");
+    let integer = |text: &str| text.trim().parse::<i128>().map_err(|_| invalid());
+
+    let value = range.value();
+    let (first, last) = if let Some((start, end)) = value.split_once("..=") {
+        (integer(start)?, integer(end)?)
+    } else if let Some((start, end)) = value.split_once("..") {
+        let end = integer(end)?.checked_sub(1).ok_or_else(invalid)?;
+        (integer(start)?, end)
     } else {
-        let max_index = data.variants.len().saturating_sub(1) as u32;
-        Ok(32 - max_index.leading_zeros() as u8)
+        let integer = integer(&value)?;
+        (integer, integer)
+    };
+
+    if first > last {
+        return Err(err!(range, "the range is empty"));
+    }
+    Ok((first, last))
+}
+
+fn extract_variant_attributes(variant: &Variant) -> Result<VariantAttributes> {
+    let mut attrs = VariantAttributes {
+        index: None,
+        other: false,
+    };
+
+    for arg in rabbit_args(&variant.attrs)? {
+        match arg {
+            Meta::Path(path) if path.is_ident("other") => {
+                if !matches!(variant.fields, Fields::Unit) {
+                    return Err(err!(
+                        path,
+                        "only unit variants can stand in for unknown ones"
+                    ));
+                }
+                attrs.other = true;
+            }
+            Meta::NameValue(arg) if arg.path.is_ident("index") => {
+                attrs.index = match &arg.lit {
+                    Lit::Int(index) => Some(index.base10_parse()?),
+                    _ => return Err(err!(arg.lit, "expected an integer literal")),
+                };
+            }
+            arg => return Err(unknown_attribute(&arg)),
+        }
     }
+
+    Ok(attrs)
+}
+
+/// The variant with an `other` attribute, if any.
+fn other_variant(data: &DataEnum) -> Result<Option<&Ident>> {
+    let mut other: Option<&Ident> = None;
+    for variant in &data.variants {
+        if !extract_variant_attributes(variant)?.other {
+            continue;
+        }
+        if other.is_some() {
+            return Err(err!(
+                variant,
+                "only one variant can stand in for unknown ones"
+            ));
+        }
+        other = Some(&variant.ident);
+    }
+    Ok(other)
+}
+
+/// The index every variant is packed as: the one in its `index` attribute, or one more than the
+/// index of the variant before it, starting from zero. Indices may not be reserved.
+fn variant_indices(attrs: &[Attribute], data: &DataEnum) -> Result<Vec<u32>> {
+    let reserved = extract_container_attributes(attrs)?.reserved;
+    let mut errors = Errors::new();
+    let mut indices = Vec::new();
+    let mut next = Some(0u32);
+
+    for variant in &data.variants {
+        let index = match (extract_variant_attributes(variant)?.index, next) {
+            (Some(index), _) | (None, Some(index)) => index,
+            (None, None) => return Err(err!(variant, "variant index out of range")),
+        };
+        if indices.contains(&index) {
+            errors.push(err!(variant, format!("duplicate variant index: {}", index)));
+        }
+        if reserved
+            .iter()
+            .any(|&(first, last)| (first..=last).contains(&index))
+        {
+            errors.push(err!(
+                variant,
+                format!("variant index {} is reserved", index)
+            ));
+        }
+        indices.push(index);
+        next = index.checked_add(1);
+    }
+
+    errors.finish(indices)
+}
+
+/// The number of bits needed for every variant index, including the reserved ones.
+fn index_bits(attrs: &[Attribute], data: &DataEnum) -> Result<u8> {
+    let max_index = match variant_indices(attrs, data)?.into_iter().max() {
+        None => return Err(err!(data.enum_token, "enum must have atleast one variant")),
+        Some(max_index) => max_index,
+    };
+
+    let reserved = extract_container_attributes(attrs)?.reserved;
+    let max_index = reserved
+        .into_iter()
+        .map(|(_, last)| last)
+        .fold(max_index, u32::max);
+    Ok(32 - max_index.leading_zeros() as u8)
+}
+
+/// What an unknown variant index was expected to be.
+fn expected_index(indices: &[u32]) -> String {
+    let mut sorted = indices.to_vec();
+    sorted.sort_unstable();
+    if sorted
+        .iter()
+        .enumerate()
+        .all(|(i, &index)| i as u32 == index)
+    {
+        return format!("a variant index below {}", sorted.len());
+    }
+
+    let sorted = sorted.iter().map(u32::to_string).collect::<Vec<_>>();
+    format!("one of the variant indices {}", sorted.join(", "))
 }
 
 fn pack_fields<'a>(fields: impl Iterator<Item = (&'a Ident, &'a Attributes)>) -> TokenStream {
@@ -327,43 +1361,198 @@ fn pack_fields<'a>(fields: impl Iterator<Item = (&'a Ident, &'a Attributes)>) ->
 
     let mut extractors = Vec::new();
     for (ident, attrs) in fields {
-        let extractor = if let Some(pack_fn) = attrs.pack_fn.as_ref() {
+        let extractor = if attrs.skip {
+            quote! { let _ = #ident; }
+        } else if let Some(pack_fn) = attrs.pack_fn.as_ref() {
             quote! { (#pack_fn)(#ident, __writer)?; }
         } else {
             quote! { #rabbit::PackBits::pack(#ident, __writer)?; }
         };
 
-        extractors.push(extractor)
+        extractors.push(since_version(attrs, extractor))
     }
 
     quote! { #( #extractors )* }
 }
 
-fn unpack_fields<'a>(fields: impl Iterator<Item = (&'a Ident, &'a Field)>) -> Result<TokenStream> {
+/// Only pack a field added in a later version if the writer packs for that version or later.
+fn since_version(attrs: &Attributes, writer: TokenStream) -> TokenStream {
+    let rabbit = rabbit!();
+    match attrs.since {
+        Some(since) => quote! {
+            if #rabbit::write::includes(__writer, #since) {
+                #writer
+            }
+        },
+        None => writer,
+    }
+}
+
+/// Fields added in later versions are counted as well, like writers without a version pack them,
+/// see `rabbit::size::packed_bits_versioned`.
+fn packed_bits_fields<'a>(
+    fields: impl Iterator<Item = (&'a Ident, &'a Attributes)>,
+) -> TokenStream {
+    let rabbit = rabbit!();
+
+    let mut unused = Vec::new();
+    let mut bits = Vec::new();
+    for (ident, attrs) in fields {
+        if attrs.skip {
+            unused.push(ident);
+        } else if let Some(width) = attrs.bits {
+            // fixed-width fields take the same bits whatever their value
+            let width = usize::from(width);
+            unused.push(ident);
+            bits.push(quote! { #width });
+        } else if let Some(pack_fn) = attrs.pack_fn.as_ref() {
+            bits.push(quote! {
+                #rabbit::size::counted_bits(|__counter| (#pack_fn)(#ident, __counter))
+            });
+        } else {
+            bits.push(quote! { #rabbit::PackedSize::packed_bits(#ident) });
+        }
+    }
+
+    quote! {{
+        #( let _ = #unused; )*
+        0 #( + #bits )*
+    }}
+}
+
+/// Evaluates to the sum of the upper bounds of the fields, returning `None` from the enclosing
+/// function if the upper bound of any field's type is unknown. There is no upper bound at all, and
+/// no expression, if any field has a custom packing function.
+fn max_bits_fields(fields: &Fields) -> Result<Option<TokenStream>> {
+    let rabbit = rabbit!();
+
+    let mut bits = Vec::new();
+    for field in fields {
+        let attrs = extract_attributes(field)?;
+        if attrs.skip {
+            continue;
+        }
+        if let Some(width) = attrs.bits {
+            let width = usize::from(width);
+            bits.push(quote! { #width });
+            continue;
+        }
+        if attrs.pack_fn.is_some() {
+            return Ok(None);
+        }
+
+        let ty = &field.ty;
+        bits.push(quote! { <#ty as #rabbit::PackedSize>::max_packed_bits()? });
+    }
+
+    Ok(Some(quote! { 0 #( + #bits )* }))
+}
+
+/// Unpack every field into a variable, adding the name of the field, and of the variant if there is
+/// one, to the path of errors.
+fn unpack_fields<'a>(
+    fields: impl Iterator<Item = (&'a Ident, &'a Field)>,
+    location: Location,
+    unpack: &TokenStream,
+) -> Result<TokenStream> {
     let rabbit = rabbit!();
 
     let mut readers = Vec::new();
-    for (ident, field) in fields {
+    for (index, (ident, field)) in fields.enumerate() {
         let attrs = extract_attributes(field)?;
+        let ty = &field.ty;
+
+        if attrs.skip {
+            readers.push(quote! { let #ident: #ty = ::std::default::Default::default(); });
+            continue;
+        }
 
         let reader = if let Some(unpack_fn) = attrs.unpack_fn.as_ref() {
-            quote! { (#unpack_fn)(__reader)? }
+            quote! { (#unpack_fn)(__reader) }
         } else {
-            quote! { #rabbit::UnpackBits::unpack(__reader)? }
+            quote! { #unpack(__reader) }
         };
 
-        let ty = &field.ty;
-        readers.push(quote! { let #ident: #ty = #reader; });
+        let reader = match attrs.since {
+            Some(since) => quote! {
+                #rabbit::read::unpack_since(__reader, #since, |__reader| #reader)
+                    .map(::std::option::Option::unwrap_or_default)
+            },
+            None => reader,
+        };
+
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(index)),
+        };
+        let reader = within_field(reader, &member, location);
+        readers.push(quote! { let #ident: #ty = #reader?; });
     }
 
     Ok(quote! { #( #readers )* })
 }
 
+/// Add a field, and the variant it belongs to, to the path of any error from the `reader`.
+fn within_field(reader: TokenStream, member: &Member, location: Location) -> TokenStream {
+    let rabbit = rabbit!();
+    let field = match member {
+        Member::Named(ident) => ident.to_string(),
+        Member::Unnamed(index) => index.index.to_string(),
+    };
+    let variant = match location {
+        Location::Struct => None,
+        Location::Variant(variant) => {
+            let variant = variant.to_string();
+            Some(quote! { let __error = #rabbit::read::Error::within(__error, #variant); })
+        }
+        Location::Transparent => return reader,
+    };
+
+    quote! {
+        #reader.map_err(|__error: __R::Error| {
+            let __error = #rabbit::read::Error::within(__error, #field);
+            #variant
+            __error
+        })
+    }
+}
+
+/// The only field of a transparent `struct` that is not skipped.
+fn transparent_field(data: &DataStruct) -> Result<(&Field, Attributes)> {
+    let mut packed = Vec::new();
+    for field in &data.fields {
+        let attrs = extract_attributes(field)?;
+        if !attrs.skip {
+            packed.push((field, attrs));
+        }
+    }
+
+    match packed.pop() {
+        Some(field) if packed.is_empty() => Ok(field),
+        _ => Err(err!(
+            data.struct_token,
+            "transparent `struct`s have exactly one field that is not skipped"
+        )),
+    }
+}
+
+/// Where the fields of a `struct` belong.
+fn struct_location(attrs: &[Attribute]) -> Result<Location<'static>> {
+    if extract_container_attributes(attrs)?.transparent {
+        Ok(Location::Transparent)
+    } else {
+        Ok(Location::Struct)
+    }
+}
+
 impl Default for Attributes {
     fn default() -> Self {
         Attributes {
             pack_fn: None,
             unpack_fn: None,
+            skip: false,
+            bits: None,
+            since: None,
         }
     }
 }
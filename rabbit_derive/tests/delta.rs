@@ -0,0 +1,95 @@
+use rabbit_derive::*;
+
+fn delta_bytes<T>(value: &T, baseline: &T) -> Vec<u8>
+where
+    T: rabbit::PackDelta + rabbit::UnpackDelta + PartialEq + std::fmt::Debug,
+{
+    let bytes = rabbit::to_delta_bytes(value, baseline).unwrap();
+    let after: T = rabbit::from_delta_bytes(baseline, &bytes).unwrap();
+    assert_eq!(value, &after);
+    bytes
+}
+
+#[derive(Debug, Clone, PartialEq, PackBits, UnpackBits, PackDelta, UnpackDelta)]
+struct Player {
+    name: String,
+    health: u32,
+    position: Position,
+    state: State,
+}
+
+#[derive(Debug, Clone, PartialEq, PackBits, UnpackBits, PackDelta, UnpackDelta)]
+struct Position(f32, f32);
+
+#[derive(Debug, Clone, PartialEq, PackBits, UnpackBits, PackDelta, UnpackDelta)]
+enum State {
+    Idle,
+    Walking { speed: f32 },
+}
+
+fn player() -> Player {
+    Player {
+        name: "snowman".to_owned(),
+        health: 100,
+        position: Position(3.0, -4.0),
+        state: State::Idle,
+    }
+}
+
+#[test]
+fn unchanged_struct() {
+    let baseline = player();
+    let bytes = delta_bytes(&baseline, &baseline);
+    // one bit for each leaf field
+    assert_eq!(bytes.len(), 1);
+}
+
+#[test]
+fn changed_fields() {
+    let baseline = player();
+
+    let mut moved = baseline.clone();
+    moved.position.1 = 2.5;
+    moved.state = State::Walking { speed: 1.5 };
+    let bytes = delta_bytes(&moved, &baseline);
+    assert!(bytes.len() < rabbit::to_bytes(&moved).unwrap().len());
+
+    let mut renamed = baseline.clone();
+    renamed.name = "snowball".to_owned();
+    renamed.health = 0;
+    delta_bytes(&renamed, &baseline);
+}
+
+#[test]
+fn unit_struct() {
+    #[derive(Debug, Clone, PartialEq, PackBits, UnpackBits, PackDelta, UnpackDelta)]
+    struct Empty;
+
+    assert!(delta_bytes(&Empty, &Empty).is_empty());
+}
+
+#[test]
+fn custom_packing_fn() {
+    #[derive(Debug, Clone, PartialEq, PackBits, UnpackBits, PackDelta, UnpackDelta)]
+    struct Line {
+        #[rabbit(with = "byte")]
+        start: u8,
+        end: u8,
+    }
+
+    let baseline = Line { start: 1, end: 2 };
+    assert_eq!(delta_bytes(&baseline, &baseline).len(), 1);
+    delta_bytes(&Line { start: 7, end: 2 }, &baseline);
+}
+
+mod byte {
+    use rabbit::{ReadBits, WriteBits};
+
+    pub fn pack<W: WriteBits>(value: &u8, writer: &mut W) -> Result<(), W::Error> {
+        writer.write(*value as u32, 8)
+    }
+
+    pub fn unpack<R: ReadBits>(reader: &mut R) -> Result<u8, R::Error> {
+        Ok(reader.read(8)? as u8)
+    }
+}
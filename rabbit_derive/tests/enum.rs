@@ -61,3 +61,169 @@ fn data_variants() {
         b: 4711,
     });
 }
+
+#[test]
+fn error_path() {
+    #[derive(Debug, PackBits, UnpackBits)]
+    enum Kind {
+        Snowball,
+        Wall,
+        Tree,
+    }
+
+    #[derive(Debug, PackBits, UnpackBits)]
+    struct Entity {
+        id: u32,
+        kind: Kind,
+    }
+
+    #[derive(Debug, PackBits, UnpackBits)]
+    enum Event {
+        Spawn(Vec<Entity>),
+        Clear,
+    }
+
+    // packs like `Event::Spawn`, but the kind of the second entity has no variant
+    let kinds = rabbit::Bounded::<0, 3>::new;
+    let value = (
+        false,
+        vec![(1u32, kinds(0).unwrap()), (2u32, kinds(3).unwrap())],
+    );
+    let bytes = rabbit::to_bytes(&value).unwrap();
+
+    let error = rabbit::from_bytes::<Event>(&bytes).unwrap_err();
+    let context = match &error {
+        rabbit::Error::Context(context) => context,
+        _ => panic!("no context: {}", error),
+    };
+    assert_eq!(context.path(), "Spawn.0[1].kind");
+    assert_eq!(context.bit, Some(rabbit::PackedSize::packed_bits(&value)));
+    assert!(matches!(
+        error.kind(),
+        rabbit::Error::Unexpected { expected, found }
+            if expected == "a variant index below 3" && found == "3"
+    ));
+    assert!(error
+        .to_string()
+        .starts_with("expected a variant index below 3, found 3 in `Spawn.0[1].kind` at bit "));
+}
+
+#[test]
+fn explicit_indices() {
+    #[derive(Debug, PartialEq, PackBits, UnpackBits, PackedSize)]
+    enum Event {
+        #[rabbit(index = 2)]
+        Join,
+        Leave,
+        #[rabbit(index = 0)]
+        Chat(String),
+        #[rabbit(index = 6)]
+        Kick {
+            reason: String,
+        },
+    }
+
+    assert_lossless(&Event::Join);
+    assert_lossless(&Event::Leave);
+    assert_lossless(&Event::Chat(String::from("brr")));
+    assert_lossless(&Event::Kick {
+        reason: String::from("snowball in the face"),
+    });
+
+    // the largest index takes three bits
+    assert_eq!(rabbit::to_bytes(&Event::Join).unwrap(), vec![2]);
+    assert_eq!(rabbit::to_bytes(&Event::Leave).unwrap(), vec![3]);
+    assert_eq!(<Event as rabbit::PackedSize>::max_packed_bits(), None);
+    assert_eq!(rabbit::PackedSize::packed_bits(&Event::Leave), 3);
+
+    let error = rabbit::from_bytes::<Event>(&[1]).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        rabbit::Error::Unexpected { expected, found }
+            if expected == "one of the variant indices 0, 2, 3, 6" && found == "1"
+    ));
+}
+
+#[test]
+fn reserved_indices() {
+    #[derive(Debug, PartialEq, PackBits, UnpackBits, PackedSize)]
+    #[rabbit(reserve = "3..=7")]
+    enum Tool {
+        Shovel,
+        Bucket,
+        Snowball,
+    }
+
+    assert_lossless(&Tool::Shovel);
+    assert_lossless(&Tool::Snowball);
+
+    // the reserved indices take three bits, so new tools can be added without changing the size
+    assert_eq!(rabbit::PackedSize::packed_bits(&Tool::Bucket), 3);
+    assert_eq!(<Tool as rabbit::PackedSize>::max_packed_bits(), Some(3));
+
+    let error = rabbit::from_bytes::<Tool>(&[5]).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        rabbit::Error::Message(message) if message == "variant index 5 is reserved for a future version"
+    ));
+}
+
+#[test]
+fn unknown_variants() {
+    #[derive(Debug, PartialEq, PackBits)]
+    enum Newer {
+        Hello,
+        Bye,
+        Wave(String),
+    }
+
+    #[derive(Debug, PartialEq, PackBits, UnpackBits)]
+    enum Older {
+        Hello,
+        Bye,
+        #[rabbit(other, index = 3)]
+        Unknown,
+    }
+
+    assert_lossless(&Older::Bye);
+    assert_lossless(&Older::Unknown);
+    assert_eq!(
+        rabbit::to_bytes(&Newer::Hello).unwrap(),
+        rabbit::to_bytes(&Older::Hello).unwrap()
+    );
+
+    // the fields of the unknown variant are skipped
+    let bytes = rabbit::to_bytes(&Newer::Wave(String::from("o/"))).unwrap();
+    assert_eq!(
+        rabbit::from_bytes_strict::<Older>(&bytes).unwrap(),
+        Older::Unknown
+    );
+
+    // and so is anything after them, which is why they can only be unpacked last
+    #[derive(Debug, PartialEq, PackBits)]
+    struct NewerGreeting {
+        count: u8,
+        greeting: Newer,
+    }
+
+    #[derive(Debug, PartialEq, PackBits, UnpackBits)]
+    struct OlderGreeting {
+        count: u8,
+        greeting: Older,
+    }
+
+    let bytes = rabbit::to_bytes(&NewerGreeting {
+        count: 2,
+        greeting: Newer::Bye,
+    })
+    .unwrap();
+    assert_eq!(
+        rabbit::from_bytes_strict::<OlderGreeting>(&bytes).unwrap(),
+        OlderGreeting {
+            count: 2,
+            greeting: Older::Bye
+        }
+    );
+    const { assert!(<Older as rabbit::UnpackBits>::UNPACKS_REST) };
+    const { assert!(<OlderGreeting as rabbit::UnpackBits>::UNPACKS_REST) };
+}
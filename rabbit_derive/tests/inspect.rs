@@ -0,0 +1,142 @@
+use rabbit::inspect::{self, Inspect, Kind};
+use rabbit::{ReadBits, WriteBits};
+use rabbit_derive::*;
+
+#[derive(PackBits, UnpackBits)]
+struct Snapshot {
+    tick: u32,
+    players: Vec<Player>,
+    weather: Weather,
+}
+
+#[derive(Debug, PartialEq, PackBits, UnpackBits)]
+struct Player {
+    name: String,
+    position: (f32, f32),
+    holding: Option<Item>,
+}
+
+#[derive(Debug, PartialEq, PackBits, UnpackBits)]
+enum Item {
+    Snowball,
+    Shovel { durability: u8 },
+}
+
+#[derive(PackBits, UnpackBits)]
+enum Weather {
+    Clear,
+    Snowing(Intensity),
+}
+
+/// Neither `Inspect` nor `Debug`.
+struct Intensity(u8);
+
+#[derive(PackBits, UnpackBits)]
+#[rabbit(transparent)]
+struct Durability(u8);
+
+#[derive(PackBits, UnpackBits)]
+struct Wrapper<T: rabbit::PackBits + rabbit::UnpackBits> {
+    inner: T,
+}
+
+impl rabbit::PackBits for Intensity {
+    fn pack<W: WriteBits>(&self, writer: &mut W) -> Result<(), W::Error> {
+        rabbit::PackBits::pack(&self.0, writer)
+    }
+}
+
+impl rabbit::UnpackBits for Intensity {
+    fn unpack<R: ReadBits>(reader: &mut R) -> Result<Self, R::Error> {
+        rabbit::UnpackBits::unpack(reader).map(Intensity)
+    }
+}
+
+fn sample() -> Snapshot {
+    Snapshot {
+        tick: 3,
+        players: vec![Player {
+            name: "a".to_owned(),
+            position: (1.0, -2.0),
+            holding: Some(Item::Shovel { durability: 9 }),
+        }],
+        weather: Weather::Snowing(Intensity(4)),
+    }
+}
+
+#[test]
+fn derived_schema() {
+    let schema = Player::schema();
+    assert_eq!(schema.name, "Player");
+    match schema.kind {
+        Kind::Struct(fields) => {
+            let names = fields.iter().map(|field| field.name).collect::<Vec<_>>();
+            assert_eq!(names, ["name", "position", "holding"]);
+        }
+        _ => panic!("expected a struct"),
+    }
+
+    assert!(matches!(
+        Item::schema().kind,
+        Kind::Enum { index_bits: 1, .. }
+    ));
+}
+
+#[test]
+fn transparent_schema() {
+    let schema = Durability::schema();
+    assert_eq!(schema.name, "u8");
+    assert!(matches!(schema.kind, Kind::Value(_)));
+}
+
+#[test]
+fn inspect_derived() {
+    let bytes = rabbit::to_bytes(&sample()).unwrap();
+    let node = inspect::inspect::<Snapshot>(&bytes);
+    assert!(!node.failed(), "{}", node);
+
+    let tree = node.to_string();
+    let lines = tree.lines().map(str::trim).collect::<Vec<_>>();
+    assert_eq!(lines[0], format!("Snapshot @0..{}", node.bits.end));
+    assert!(lines.contains(&"tick = 3 @0..10"));
+    assert!(lines.iter().any(|line| line.starts_with("[0]: Player")));
+    assert!(lines.iter().any(|line| line.starts_with("name = \"a\"")));
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("position = (1.0, -2.0)")));
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("Some: Item = Shovel")));
+    assert!(lines.iter().any(|line| line.starts_with("durability = 9")));
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("weather: Weather = Snowing")));
+
+    // without `Debug`, only the bits of the value are shown
+    assert_eq!(lines.last(), Some(&"0 @113..121"));
+
+    assert_eq!(
+        node.bits.end,
+        rabbit::size::counted_bits(|w| rabbit::PackBits::pack(&sample(), w))
+    );
+}
+
+#[test]
+fn inspect_malformed() {
+    let mut bytes = rabbit::to_bytes(&sample()).unwrap();
+    bytes.truncate(bytes.len() - 1);
+
+    let node = inspect::inspect::<Snapshot>(&bytes);
+    assert!(node.failed());
+    assert!(node.to_string().contains("error: unexpected eof"));
+}
+
+#[test]
+fn generic_parameters() {
+    let bytes = rabbit::to_bytes(&Wrapper {
+        inner: Item::Snowball,
+    })
+    .unwrap();
+    let node = inspect::inspect::<Wrapper<Item>>(&bytes);
+    assert_eq!(node.children[0].value.as_deref(), Some("Snowball"));
+}
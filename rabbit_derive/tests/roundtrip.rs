@@ -0,0 +1,81 @@
+use rabbit::test_utils::proptest::prelude::*;
+use rabbit::test_utils::{check_roundtrip, first_different_bit};
+use rabbit_derive::*;
+
+#[derive(Debug, Clone, PartialEq, PackBits, UnpackBits)]
+struct Player {
+    name: String,
+    health: u32,
+    position: (f32, f32),
+    holding: Option<Item>,
+}
+
+#[derive(Debug, Clone, PartialEq, PackBits, UnpackBits)]
+enum Item {
+    Snowball,
+    Shovel { durability: u8 },
+}
+
+fn item() -> impl Strategy<Value = Item> {
+    prop_oneof![
+        Just(Item::Snowball),
+        any::<u8>().prop_map(|durability| Item::Shovel { durability }),
+    ]
+}
+
+fn player() -> impl Strategy<Value = Player> {
+    let position = (-1e3f32..1e3, -1e3f32..1e3);
+    (".*", any::<u32>(), position, prop::option::of(item())).prop_map(
+        |(name, health, position, holding)| Player {
+            name,
+            health,
+            position,
+            holding,
+        },
+    )
+}
+
+/// Packs the `bool` but always unpacks `false`.
+#[derive(Debug, PartialEq)]
+struct Forgetful(u8, bool);
+
+impl rabbit::PackBits for Forgetful {
+    fn pack<W: rabbit::WriteBits>(&self, writer: &mut W) -> Result<(), W::Error> {
+        rabbit::PackBits::pack(&(self.0, self.1), writer)
+    }
+}
+
+impl rabbit::UnpackBits for Forgetful {
+    fn unpack<R: rabbit::ReadBits>(reader: &mut R) -> Result<Self, R::Error> {
+        let (number, _): (u8, bool) = rabbit::UnpackBits::unpack(reader)?;
+        Ok(Forgetful(number, false))
+    }
+}
+
+#[test]
+fn derived_types_roundtrip() {
+    rabbit::assert_roundtrip!(Player, player());
+    rabbit::assert_roundtrip!(Item, item());
+    rabbit::assert_roundtrip!(Vec<Option<i64>>);
+    rabbit::assert_roundtrip!((u8, String, bool));
+}
+
+#[test]
+#[should_panic(expected = "packs differently from bit 8")]
+fn reports_divergence() {
+    rabbit::assert_roundtrip!(
+        Forgetful,
+        (any::<u8>(), any::<bool>()).prop_map(|(a, b)| Forgetful(a, b))
+    );
+}
+
+#[test]
+fn divergent_bits() {
+    assert_eq!(first_different_bit(&[1, 2], &[1, 2]), None);
+    assert_eq!(first_different_bit(&[1, 0b0100], &[1, 0b1100]), Some(11));
+    assert_eq!(first_different_bit(&[1], &[1, 0]), Some(8));
+
+    let error = check_roundtrip(&Forgetful(3, true)).unwrap_err();
+    assert!(error.contains("Forgetful(3, false)"), "{}", error);
+    assert!(check_roundtrip(&Forgetful(3, false)).is_ok());
+}
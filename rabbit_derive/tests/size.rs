@@ -0,0 +1,95 @@
+use rabbit::PackedSize;
+use rabbit_derive::*;
+
+fn assert_exact<T>(value: &T)
+where
+    T: PackedSize,
+{
+    let bytes = rabbit::to_bytes(value).unwrap();
+    let counted = rabbit::size::counted_bits(|writer| value.pack(writer));
+    assert_eq!(value.packed_bits(), counted);
+    assert_eq!(rabbit::size::packed_bytes(value), bytes.len());
+}
+
+#[derive(PackBits, PackedSize)]
+struct Player {
+    name: String,
+    health: u32,
+    position: Position,
+    state: State,
+}
+
+#[derive(PackBits, PackedSize)]
+struct Position(f32, f32);
+
+#[derive(PackBits, PackedSize)]
+struct Marker;
+
+#[derive(PackBits, PackedSize)]
+enum State {
+    Idle,
+    Walking { speed: f32 },
+    Throwing(Option<u8>, bool),
+}
+
+#[test]
+fn struct_size() {
+    assert_exact(&Player {
+        name: "snowman".to_owned(),
+        health: 100,
+        position: Position(3.0, -4.0),
+        state: State::Walking { speed: 2.5 },
+    });
+    assert_exact(&Position(0.0, 1.0));
+    assert_exact(&Marker);
+}
+
+#[test]
+fn enum_size() {
+    assert_exact(&State::Idle);
+    assert_exact(&State::Walking { speed: 1.0 });
+    assert_exact(&State::Throwing(Some(3), true));
+    assert_exact(&State::Throwing(None, false));
+}
+
+#[test]
+fn upper_bounds() {
+    assert_eq!(Position::max_packed_bits(), Some(64));
+    assert_eq!(Marker::max_packed_bits(), Some(0));
+    // two bits for the variant followed by the largest variant
+    assert_eq!(State::max_packed_bits(), Some(2 + 32));
+    // the name has no upper bound
+    assert_eq!(Player::max_packed_bits(), None);
+}
+
+#[test]
+fn custom_packing_fn() {
+    #[derive(PackBits, PackedSize)]
+    struct Line {
+        #[rabbit(with = "point")]
+        start: point::Point,
+        end: u8,
+    }
+
+    let line = Line {
+        start: point::Point { x: 37, y: 1 },
+        end: 4,
+    };
+    assert_exact(&line);
+    assert_eq!(line.packed_bits(), 24);
+    assert_eq!(Line::max_packed_bits(), None);
+}
+
+mod point {
+    use rabbit::{PackBits, WriteBits};
+
+    pub struct Point {
+        pub x: u8,
+        pub y: u8,
+    }
+
+    pub fn pack<W: WriteBits>(point: &Point, writer: &mut W) -> Result<(), W::Error> {
+        point.x.pack(writer)?;
+        point.y.pack(writer)
+    }
+}
@@ -93,6 +93,349 @@ fn custom_packing_fn() {
     });
 }
 
+#[test]
+fn skipped_fields() {
+    #[derive(Debug, PartialEq, PackBits, UnpackBits, PackedSize, PackDelta, UnpackDelta)]
+    struct Sprite {
+        #[rabbit(skip)]
+        texture: Option<u32>,
+        x: i16,
+        y: i16,
+        #[rabbit(skip)]
+        frames: Vec<u8>,
+    }
+
+    let sprite = Sprite {
+        texture: Some(7),
+        x: -3,
+        y: 12,
+        frames: vec![1, 2, 3],
+    };
+
+    let position = (sprite.x, sprite.y);
+    let bytes = rabbit::to_bytes(&sprite).unwrap();
+    assert_eq!(bytes, rabbit::to_bytes(&position).unwrap());
+    assert_eq!(
+        rabbit::PackedSize::packed_bits(&sprite),
+        rabbit::PackedSize::packed_bits(&position)
+    );
+    assert_eq!(
+        <Sprite as rabbit::PackedSize>::max_packed_bits(),
+        <(i16, i16) as rabbit::PackedSize>::max_packed_bits()
+    );
+
+    let unpacked: Sprite = rabbit::from_bytes(&bytes).unwrap();
+    assert_eq!(
+        unpacked,
+        Sprite {
+            texture: None,
+            x: -3,
+            y: 12,
+            frames: Vec::new(),
+        }
+    );
+
+    let bytes = rabbit::to_delta_bytes(&sprite, &unpacked).unwrap();
+    let unpacked: Sprite = rabbit::from_delta_bytes(&unpacked, &bytes).unwrap();
+    assert_eq!((unpacked.x, unpacked.y), position);
+    assert_eq!(unpacked.texture, None);
+}
+
+#[test]
+fn fixed_width_fields() {
+    #[derive(Debug, PartialEq, PackBits, UnpackBits, PackedSize, PackDelta, UnpackDelta)]
+    struct Tile {
+        #[rabbit(bits = 2)]
+        health: u8,
+        #[rabbit(bits = 5)]
+        height: i32,
+        #[rabbit(bits = 3)]
+        kind: u16,
+    }
+
+    let tile = Tile {
+        health: 3,
+        height: -9,
+        kind: 5,
+    };
+    assert_lossless(&tile);
+    assert_eq!(rabbit::to_bytes(&tile).unwrap().len(), 2);
+    assert_eq!(rabbit::PackedSize::packed_bits(&tile), 10);
+    assert_eq!(<Tile as rabbit::PackedSize>::max_packed_bits(), Some(10));
+
+    let baseline = Tile { kind: 1, ..tile };
+    let bytes = rabbit::to_delta_bytes(&tile, &baseline).unwrap();
+    assert_eq!(rabbit::from_delta_bytes(&baseline, &bytes).unwrap(), tile);
+
+    let tile = Tile { health: 4, ..tile };
+    assert!(rabbit::to_bytes(&tile).is_err());
+}
+
+#[test]
+fn ranged_fields() {
+    #[derive(Debug, PartialEq, PackBits, UnpackBits, PackedSize)]
+    struct Stats {
+        #[rabbit(range = "0..=100")]
+        health: u8,
+        #[rabbit(range = "-8..8")]
+        temperature: i32,
+        #[rabbit(range = "1000..=1003")]
+        team: u64,
+    }
+
+    let stats = Stats {
+        health: 100,
+        temperature: -8,
+        team: 1002,
+    };
+    assert_lossless(&stats);
+    // 7 + 4 + 2 bits
+    assert_eq!(rabbit::PackedSize::packed_bits(&stats), 13);
+    assert_eq!(<Stats as rabbit::PackedSize>::max_packed_bits(), Some(13));
+
+    let stats = Stats {
+        temperature: 8,
+        ..stats
+    };
+    assert!(rabbit::to_bytes(&stats).is_err());
+
+    // a health of 127 fits in seven bits, but not in the range
+    let error = rabbit::from_bytes::<Stats>(&[127, 0]).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        rabbit::Error::Unexpected { expected, found }
+            if expected == "a value in 0..=100" && found == "127"
+    ));
+}
+
+#[test]
+fn transparent() {
+    #[derive(Debug, PartialEq, PackBits, UnpackBits, PackedSize)]
+    #[rabbit(transparent)]
+    struct Nickname {
+        text: String,
+        #[rabbit(skip)]
+        shown: bool,
+    }
+
+    #[derive(Debug, PartialEq, PackBits, UnpackBits)]
+    struct Lobby {
+        players: Vec<Nickname>,
+    }
+
+    let nickname = Nickname {
+        text: String::from("frosty"),
+        shown: false,
+    };
+    assert_lossless(&nickname);
+    assert_eq!(
+        rabbit::to_bytes(&nickname).unwrap(),
+        rabbit::to_bytes(&nickname.text).unwrap()
+    );
+
+    // the field of a transparent type is left out of the path
+    let bytes = rabbit::to_bytes(&vec![vec![0xffu8]]).unwrap();
+    match rabbit::from_bytes::<Lobby>(&bytes).unwrap_err() {
+        rabbit::Error::Context(context) => assert_eq!(context.path(), "players[0]"),
+        error => panic!("no context: {}", error),
+    }
+}
+
+#[test]
+fn versioned_fields() {
+    #[derive(Debug, PartialEq, PackBits, UnpackBits, PackedSize)]
+    struct Entity {
+        id: u32,
+        health: u8,
+    }
+
+    #[derive(Debug, PartialEq, PackBits, UnpackBits, PackedSize, PackDelta, UnpackDelta)]
+    struct EntityV2 {
+        id: u32,
+        health: u8,
+        #[rabbit(since = 2)]
+        shield: u8,
+        #[rabbit(since = 2)]
+        name: String,
+    }
+
+    let old = Entity { id: 7, health: 3 };
+    let new = EntityV2 {
+        id: 7,
+        health: 3,
+        shield: 9,
+        name: String::from("yeti"),
+    };
+    assert_lossless(&new);
+
+    // new readers default the fields old writers do not know about, given their version, since
+    // the padding of the last byte would otherwise unpack as the shield
+    let bytes = rabbit::to_bytes(&old).unwrap();
+    let unpacked: EntityV2 = rabbit::from_bytes_versioned(&bytes, 1).unwrap();
+    assert_eq!((unpacked.id, unpacked.health, unpacked.shield), (7, 3, 0));
+    assert!(rabbit::from_bytes_strict::<EntityV2>(&bytes).is_err());
+    let limits = rabbit::read::Limits::UNLIMITED;
+    let unpacked: EntityV2 = rabbit::from_bytes_strict_versioned(&bytes, 1, limits).unwrap();
+    assert_eq!((unpacked.id, unpacked.health, unpacked.shield), (7, 3, 0));
+
+    // old readers can read what new writers pack for them
+    let bytes = rabbit::to_bytes_versioned(&new, 1).unwrap();
+    assert_eq!(bytes, rabbit::to_bytes(&old).unwrap());
+    assert_eq!(
+        rabbit::size::packed_bits_versioned(&new, 1),
+        rabbit::PackedSize::packed_bits(&old)
+    );
+    assert_eq!(
+        rabbit::size::packed_bits_versioned(&new, 2),
+        rabbit::PackedSize::packed_bits(&new)
+    );
+    let bytes = rabbit::to_bytes_versioned(&new, 2).unwrap();
+    assert_eq!(
+        rabbit::from_bytes_versioned::<EntityV2>(&bytes, 2).unwrap(),
+        new
+    );
+
+    let baseline = EntityV2 {
+        shield: 1,
+        ..rabbit::from_bytes_versioned(&rabbit::to_bytes(&old).unwrap(), 1).unwrap()
+    };
+    let mut writer = rabbit::write::BitWriter::new().with_version(1);
+    rabbit::PackDelta::pack_delta(&new, &baseline, &mut writer).unwrap();
+    let bytes = writer.finish();
+    let mut reader = rabbit::read::BitReader::new(&bytes).with_version(1);
+    let unpacked: EntityV2 = rabbit::UnpackDelta::unpack_delta(&baseline, &mut reader).unwrap();
+    assert_eq!(unpacked, baseline);
+}
+
+#[test]
+fn interned_fields() {
+    #[derive(Debug, PartialEq, PackBits, UnpackBits)]
+    struct Throw {
+        #[rabbit(with = "rabbit::intern")]
+        thrower: String,
+        #[rabbit(with = "rabbit::intern")]
+        target: String,
+    }
+
+    let mut writer = rabbit::intern::WriterContext::new();
+    let mut reader = rabbit::intern::ReaderContext::new();
+    let throws = [("alice", "bob"), ("bob", "alice"), ("alice", "bob")];
+
+    let mut sizes = Vec::new();
+    for &(thrower, target) in &throws {
+        let throw = Throw {
+            thrower: thrower.to_owned(),
+            target: target.to_owned(),
+        };
+        let bytes = rabbit::to_bytes_with_context(&throw, &mut writer).unwrap();
+        let unpacked: Throw = rabbit::from_bytes_with_context(&bytes, &mut reader).unwrap();
+        assert_eq!(unpacked, throw);
+        sizes.push(bytes.len());
+    }
+
+    assert!(sizes[1] < sizes[0]);
+    assert_eq!(sizes[1], sizes[2]);
+}
+
+#[test]
+fn borrowed_fields() {
+    #[derive(Debug, PartialEq, PackBits, UnpackBorrowed)]
+    struct ChatRef<'a> {
+        sender: u32,
+        message: &'a str,
+        attachments: Vec<&'a [u8]>,
+    }
+
+    #[derive(Debug, PartialEq, PackBits, UnpackBits, UnpackBorrowed)]
+    struct Owned {
+        sender: u32,
+    }
+
+    let chat = ChatRef {
+        sender: 7,
+        message: "incoming!",
+        attachments: vec![b"snowball", b""],
+    };
+    let bytes = rabbit::to_bytes(&chat).unwrap();
+    let unpacked: ChatRef = rabbit::from_bytes_borrowed(&bytes).unwrap();
+    assert_eq!(unpacked, chat);
+    assert!(bytes.as_ptr_range().contains(&unpacked.message.as_ptr()));
+
+    let owned = Owned { sender: 3 };
+    let bytes = rabbit::to_bytes(&owned).unwrap();
+    assert_eq!(rabbit::from_bytes_borrowed::<Owned>(&bytes).unwrap(), owned);
+}
+
+#[test]
+fn generic_bounds() {
+    #[derive(Debug, PartialEq, PackBits, UnpackBits)]
+    struct Wrapper<T> {
+        inner: Vec<T>,
+    }
+
+    // `PhantomData` packs for any `T`, so the inferred `T: PackBits` is stricter than needed
+    #[derive(Debug, PartialEq, PackBits, UnpackBits)]
+    #[rabbit(bound = "")]
+    struct Id<T> {
+        raw: u32,
+        marker: std::marker::PhantomData<T>,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Player;
+
+    assert_lossless(&Wrapper {
+        inner: vec![3u8, 7],
+    });
+    assert_lossless(&Id::<Player> {
+        raw: 12,
+        marker: std::marker::PhantomData,
+    });
+}
+
+#[derive(Debug, PartialEq, PackBits, UnpackBits, PackedSize)]
+#[rabbit(with = "coarse")]
+pub struct Position {
+    x: f32,
+    y: f32,
+}
+
+#[test]
+fn container_packing_functions() {
+    let position = Position { x: 12.0, y: 200.0 };
+    assert_lossless(&position);
+    assert_eq!(rabbit::to_bytes(&position).unwrap(), vec![12, 200]);
+    assert_eq!(rabbit::PackedSize::packed_bits(&position), 16);
+}
+
+#[test]
+fn validated_after_unpacking() {
+    #[derive(Debug, PartialEq, PackBits, UnpackBits)]
+    #[rabbit(validate = "Facing::check")]
+    struct Facing {
+        radians: f32,
+    }
+
+    impl Facing {
+        fn check(&self) -> Result<(), &'static str> {
+            match self.radians.is_finite() {
+                true => Ok(()),
+                false => Err("the facing is not finite"),
+            }
+        }
+    }
+
+    assert_lossless(&Facing { radians: 1.5 });
+
+    // validation only happens when unpacking
+    let bytes = rabbit::to_bytes(&Facing { radians: f32::NAN }).unwrap();
+    let error = rabbit::from_bytes::<Facing>(&bytes).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        rabbit::Error::Message(message) if message == "the facing is not finite"
+    ));
+}
+
 mod point {
     use rabbit::{PackBits, ReadBits, UnpackBits, WriteBits};
 
@@ -115,3 +458,21 @@ mod point {
         })
     }
 }
+
+mod coarse {
+    use super::Position;
+    use rabbit::{ReadBits, WriteBits};
+
+    // whole units in 8 bits each
+    pub fn pack<W: WriteBits>(position: &Position, writer: &mut W) -> Result<(), W::Error> {
+        writer.write(position.x as u8 as u32, 8)?;
+        writer.write(position.y as u8 as u32, 8)
+    }
+
+    pub fn unpack<R: ReadBits>(reader: &mut R) -> Result<Position, R::Error> {
+        Ok(Position {
+            x: reader.read(8)? as f32,
+            y: reader.read(8)? as f32,
+        })
+    }
+}
@@ -0,0 +1,196 @@
+//! An admin console that reads commands from stdin.
+//!
+//! Supported commands:
+//!
+//! - `rules`: show the current rules of the game.
+//! - `rules [tick_rate <hz>] [time_scale <factor>] [player_softness <fraction>]
+//!   [player_push <distance>]`: change the rules of the game, eg. `rules time_scale 0.25` for
+//!   slow motion. Rules that are not given are kept.
+//! - `restart [countdown <seconds>] [<rule> <value>]...`: restart the game in a new world without
+//!   disconnecting anyone, eg. `restart countdown 5 time_scale 0.5`. The new world stands still
+//!   until the countdown is over. Takes the same rules as `rules`.
+//! - `spawn <tree|mushroom> <x> <y> [height <z>] [health <points>] [team <id>] [count <n>]
+//!   [spread <radius>]`: add entities to the world, eg. `spawn mushroom 0 0 height 20 count 30
+//!   spread 10` for a mushroom rain. Entities spawned above the ground fall down.
+//! - `bandwidth`: show the average bandwidth used by each kind of message since the server
+//!   started.
+
+use anyhow::{Context, Result};
+
+use protocol::bandwidth::Bandwidth;
+
+use cgmath::Point3;
+use protocol::ObjectKind;
+use rand::Rng;
+
+use std::io::{self, BufRead};
+use std::sync::Arc;
+use std::thread;
+
+use server::game::{GameHandle, RulesUpdate, SpawnEntity, WarmRestart};
+
+/// The countdown after a restart, in seconds, unless another one is given.
+const DEFAULT_RESTART_COUNTDOWN: u32 = 3;
+
+/// Start reading commands from stdin in the background.
+pub fn spawn(mut game: GameHandle, bandwidth: Arc<Bandwidth>) {
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    log::error!("failed to read from console: {}", e);
+                    break;
+                }
+            };
+
+            if let Err(e) = execute(&mut game, &bandwidth, &line) {
+                println!("error: {:#}", e);
+            }
+        }
+    });
+}
+
+/// Execute a single command.
+fn execute(game: &mut GameHandle, bandwidth: &Bandwidth, line: &str) -> Result<()> {
+    let mut words = line.split_whitespace();
+
+    match words.next() {
+        None => Ok(()),
+        Some("help") => {
+            println!(
+                "rules [tick_rate <hz>] [time_scale <factor>] \
+                 [player_softness <fraction>] [player_push <distance>]"
+            );
+            println!("restart [countdown <seconds>] [<rule> <value>]...");
+            println!(
+                "spawn <tree|mushroom> <x> <y> [height <z>] [health <points>] [team <id>] \
+                 [count <n>] [spread <radius>]"
+            );
+            println!("bandwidth");
+            Ok(())
+        }
+        Some("rules") => rules(game, words.collect()),
+        Some("restart") => restart(game, words.collect()),
+        Some("spawn") => spawn_entities(game, words.collect()),
+        Some("bandwidth") => {
+            println!("bandwidth {}", bandwidth.since_start());
+            Ok(())
+        }
+        Some(command) => Err(anyhow!("unknown command `{}`, try `help`", command)),
+    }
+}
+
+fn rules(game: &mut GameHandle, args: Vec<&str>) -> Result<()> {
+    let update = parse_rules(&args)?;
+    let rules = futures::executor::block_on(game.update_rules(update))??;
+    println!("rules: {:?}", rules);
+
+    Ok(())
+}
+
+fn restart(game: &mut GameHandle, args: Vec<&str>) -> Result<()> {
+    let mut countdown = DEFAULT_RESTART_COUNTDOWN;
+    let mut rules = Vec::new();
+
+    for pair in args.chunks(2) {
+        match *pair {
+            ["countdown", value] => {
+                countdown = value.parse().context("invalid value for `countdown`")?
+            }
+            _ => rules.extend_from_slice(pair),
+        }
+    }
+
+    let restart = WarmRestart {
+        rules: parse_rules(&rules)?,
+        countdown,
+    };
+    let rules = futures::executor::block_on(game.restart(restart))??;
+    println!("restarted with rules: {:?}", rules);
+
+    Ok(())
+}
+
+/// Parse pairs of rules and their values.
+fn parse_rules(args: &[&str]) -> Result<RulesUpdate> {
+    let mut update = RulesUpdate::default();
+
+    for pair in args.chunks(2) {
+        let (setting, value) = match *pair {
+            [setting, value] => (setting, value),
+            _ => return Err(anyhow!("missing value for `{}`", pair[0])),
+        };
+
+        let invalid = || format!("invalid value for `{}`", setting);
+        match setting {
+            "tick_rate" => update.tick_rate = Some(value.parse().with_context(invalid)?),
+            "time_scale" => update.time_scale = Some(value.parse().with_context(invalid)?),
+            "player_softness" => {
+                update.player_softness = Some(value.parse().with_context(invalid)?)
+            }
+            "player_push" => update.player_push = Some(value.parse().with_context(invalid)?),
+            _ => return Err(anyhow!("unknown setting `{}`", setting)),
+        }
+    }
+
+    Ok(update)
+}
+
+fn spawn_entities(game: &mut GameHandle, args: Vec<&str>) -> Result<()> {
+    let (kind, x, y, options) = match *args.as_slice() {
+        [kind, x, y, ref options @ ..] => (kind, x, y, options),
+        _ => return Err(anyhow!("expected `spawn <kind> <x> <y>`")),
+    };
+
+    let kind = match kind {
+        "tree" => ObjectKind::Tree,
+        "mushroom" => ObjectKind::Mushroom,
+        _ => return Err(anyhow!("unknown kind `{}`", kind)),
+    };
+    let x: f32 = x.parse().context("invalid x coordinate")?;
+    let y: f32 = y.parse().context("invalid y coordinate")?;
+
+    let mut spawn = SpawnEntity {
+        kind,
+        position: Point3::new(x, y, 0.0),
+        health: None,
+        team: None,
+    };
+    let mut count = 1u32;
+    let mut spread = 0.0f32;
+
+    for pair in options.chunks(2) {
+        let (option, value) = match *pair {
+            [option, value] => (option, value),
+            _ => return Err(anyhow!("missing value for `{}`", pair[0])),
+        };
+
+        let invalid = || format!("invalid value for `{}`", option);
+        match option {
+            "height" => spawn.position.z = value.parse().with_context(invalid)?,
+            "health" => spawn.health = Some(value.parse().with_context(invalid)?),
+            "team" => spawn.team = Some(value.parse().with_context(invalid)?),
+            "count" => count = value.parse().with_context(invalid)?,
+            "spread" => spread = value.parse().with_context(invalid)?,
+            _ => return Err(anyhow!("unknown option `{}`", option)),
+        }
+    }
+
+    let mut rng = rand::thread_rng();
+    for _ in 0..count {
+        let mut spawn = spawn;
+        if spread > 0.0 {
+            spawn.position.x += rng.gen_range(-spread, spread);
+            spawn.position.y += rng.gen_range(-spread, spread);
+        }
+
+        match futures::executor::block_on(game.spawn_entity(spawn))? {
+            Ok(id) => println!("spawned {:?} {:?} at {:?}", kind, id, spawn.position),
+            Err(e) => println!("error: {}", e),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,328 @@
+//! Forwards selected game events to an external service, such as a chat bot or a web page.
+//!
+//! Every event is serialized as a JSON object with an `event` field naming its kind. Events are
+//! published from a separate task so that a slow or unreachable sink never stalls the game. If
+//! events arrive faster than the rate limit allows, or faster than the sink accepts them, the
+//! excess events are dropped.
+
+use serde::Serialize;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use protocol::{GameOver, PlayerId};
+
+/// The maximum number of events waiting to be published.
+const QUEUE_SIZE: usize = 256;
+
+/// Where events are published.
+#[derive(Debug, Clone)]
+pub enum Sink {
+    /// Send each event in a separate datagram to `host:port`.
+    Udp(String),
+    /// Write each event on a separate line to a stream connected to `host:port`.
+    Tcp(String),
+    /// POST each event to an HTTP endpoint.
+    Webhook { host: String, path: String },
+}
+
+/// The kinds of events that may be forwarded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EventFilter {
+    Join,
+    Leave,
+    GameOver,
+}
+
+/// An event published to the sink.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ForwardedEvent {
+    /// A player joined the game.
+    Join { player: u32, nickname: String },
+    /// A player disconnected.
+    Leave { player: u32, nickname: String },
+    /// A player won or lost.
+    GameOver {
+        player: u32,
+        nickname: String,
+        won: bool,
+        /// For how many seconds the player stayed in the match.
+        survived: u32,
+    },
+}
+
+/// Queues events for publishing. Created with `Forwarder::spawn`.
+#[derive(Debug)]
+pub struct Forwarder {
+    events: mpsc::Sender<ForwardedEvent>,
+    filter: Vec<EventFilter>,
+    limit: RateLimit,
+    /// The number of events dropped since the last time a drop was logged.
+    dropped: u32,
+}
+
+/// Allows bursts of events, but limits the average number of events per second.
+#[derive(Debug)]
+struct RateLimit {
+    per_second: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Forwarder {
+    /// Start publishing events to a sink. Only events that pass the filter are forwarded, and at
+    /// most `rate` per second on average.
+    pub fn spawn(sink: Sink, filter: Vec<EventFilter>, rate: f64) -> Forwarder {
+        let (events, receiver) = mpsc::channel(QUEUE_SIZE);
+
+        log::info!("forwarding {:?} events to {}", filter, sink);
+        tokio::spawn(publish(sink, receiver));
+
+        Forwarder {
+            events,
+            filter,
+            limit: RateLimit::new(rate),
+            dropped: 0,
+        }
+    }
+
+    /// Queue an event for publishing, unless it is filtered out or the rate limit is exceeded.
+    pub fn forward(&mut self, event: ForwardedEvent) {
+        if !self.filter.contains(&event.filter()) {
+            return;
+        }
+
+        if !self.limit.take() {
+            self.drop_event(&event);
+            return;
+        }
+
+        match self.events.try_send(event) {
+            Ok(()) => self.dropped = 0,
+            Err(TrySendError::Full(event)) => self.drop_event(&event),
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+
+    fn drop_event(&mut self, event: &ForwardedEvent) {
+        if self.dropped == 0 {
+            log::warn!(
+                "dropped forwarded event {:?}: too many events",
+                event.filter()
+            );
+        }
+        self.dropped += 1;
+    }
+}
+
+impl ForwardedEvent {
+    pub fn game_over(
+        player: PlayerId,
+        nickname: String,
+        game_over: GameOver,
+        survived: u32,
+    ) -> Self {
+        ForwardedEvent::GameOver {
+            player: player.0,
+            nickname,
+            won: matches!(game_over, GameOver::Winner),
+            survived,
+        }
+    }
+
+    fn filter(&self) -> EventFilter {
+        match self {
+            ForwardedEvent::Join { .. } => EventFilter::Join,
+            ForwardedEvent::Leave { .. } => EventFilter::Leave,
+            ForwardedEvent::GameOver { .. } => EventFilter::GameOver,
+        }
+    }
+}
+
+impl RateLimit {
+    fn new(per_second: f64) -> RateLimit {
+        RateLimit {
+            per_second,
+            tokens: per_second.max(1.0),
+            updated: Instant::now(),
+        }
+    }
+
+    /// Use up one event of the budget, if there is any left.
+    fn take(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.updated = now;
+
+        let burst = self.per_second.max(1.0);
+        self.tokens = (self.tokens + elapsed * self.per_second).min(burst);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Publish events until the forwarder is dropped.
+async fn publish(sink: Sink, mut events: mpsc::Receiver<ForwardedEvent>) {
+    let mut socket = None;
+    let mut stream = None;
+
+    while let Some(event) = events.recv().await {
+        let json = match serde_json::to_string(&event) {
+            Ok(json) => json,
+            Err(e) => {
+                log::error!("failed to serialize forwarded event: {}", e);
+                continue;
+            }
+        };
+
+        let result = match &sink {
+            Sink::Udp(addr) => send_datagram(&mut socket, addr, &json).await,
+            Sink::Tcp(addr) => send_line(&mut stream, addr, &json).await,
+            Sink::Webhook { host, path } => post(host, path, &json).await,
+        };
+
+        if let Err(e) = result {
+            log::warn!("failed to forward event to {}: {:#}", sink, e);
+        }
+    }
+}
+
+/// Send a datagram from the socket, binding it first if it has not been bound yet.
+async fn send_datagram(
+    socket: &mut Option<UdpSocket>,
+    addr: &str,
+    json: &str,
+) -> crate::Result<()> {
+    let mut bound = match socket.take() {
+        Some(socket) => socket,
+        None => UdpSocket::bind(("0.0.0.0", 0)).await?,
+    };
+
+    bound.send_to(json.as_bytes(), addr).await?;
+    *socket = Some(bound);
+    Ok(())
+}
+
+/// Write a line to the stream, connecting first if there is no open stream. If writing fails, the
+/// stream is closed and a new one is opened for the next line.
+async fn send_line(stream: &mut Option<TcpStream>, addr: &str, json: &str) -> crate::Result<()> {
+    let mut connected = match stream.take() {
+        Some(stream) => stream,
+        None => TcpStream::connect(addr).await?,
+    };
+
+    let mut line = json.to_owned();
+    line.push('\n');
+
+    connected.write_all(line.as_bytes()).await?;
+    *stream = Some(connected);
+    Ok(())
+}
+
+/// Make a plain HTTP/1.1 POST request with the event as the body.
+async fn post(host: &str, path: &str, json: &str) -> crate::Result<()> {
+    let mut stream = TcpStream::connect(host).await?;
+
+    let request = format!(
+        "POST {} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        path,
+        host,
+        json.len(),
+        json
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+
+    let response = String::from_utf8_lossy(&response);
+    let status = response.lines().next().unwrap_or_default();
+    let success = status
+        .split_whitespace()
+        .nth(1)
+        .is_some_and(|code| code.starts_with('2'));
+
+    if success {
+        Ok(())
+    } else {
+        Err(anyhow!("webhook responded with {:?}", status))
+    }
+}
+
+impl FromStr for Sink {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s.find("://").map(|index| (&s[..index], &s[index + 3..]));
+
+        match split {
+            Some(("udp", addr)) => Ok(Sink::Udp(addr.to_owned())),
+            Some(("tcp", addr)) => Ok(Sink::Tcp(addr.to_owned())),
+            Some(("http", rest)) => {
+                let (host, path) = match rest.find('/') {
+                    Some(index) => (&rest[..index], &rest[index..]),
+                    None => (rest, "/"),
+                };
+
+                let host = if host.contains(':') {
+                    host.to_owned()
+                } else {
+                    format!("{}:80", host)
+                };
+
+                Ok(Sink::Webhook {
+                    host,
+                    path: path.to_owned(),
+                })
+            }
+            Some(("https", _)) => Err(anyhow!(
+                "https is not supported, forward to a local relay over http instead"
+            )),
+            _ => Err(anyhow!(
+                "expected a sink of the form `udp://<host>:<port>`, `tcp://<host>:<port>` or \
+                 `http://<host>[:<port>]/<path>`"
+            )),
+        }
+    }
+}
+
+impl Display for Sink {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Sink::Udp(addr) => write!(f, "udp://{}", addr),
+            Sink::Tcp(addr) => write!(f, "tcp://{}", addr),
+            Sink::Webhook { host, path } => write!(f, "http://{}{}", host, path),
+        }
+    }
+}
+
+impl FromStr for EventFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "join" => Ok(EventFilter::Join),
+            "leave" => Ok(EventFilter::Leave),
+            "game_over" => Ok(EventFilter::GameOver),
+            _ => Err(anyhow!(
+                "unknown event {:?}, expected `join`, `leave` or `game_over`",
+                s
+            )),
+        }
+    }
+}
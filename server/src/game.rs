@@ -1,28 +1,60 @@
-use std::collections::BTreeMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, VecDeque};
 use std::fmt::{self, Debug, Formatter};
+use std::fs::File;
+use std::io::BufWriter;
 use std::sync::Arc;
+use thiserror::Error;
 use tokio::sync::{
     mpsc::{self, error::TrySendError},
     oneshot,
 };
 use tokio::time;
 
-use logic::components::{Movement, WorldInteraction};
+use cgmath::Point3;
+use logic::components::{DebugName, Movement, Stance, Team, WorldInteraction};
+use logic::history::WorldHistory;
 use logic::legion::prelude::{Entity, World};
-use logic::resources::DeadEntities;
+use logic::resources::{DeadEntities, EntityEffects, GameRules, Hits, Throws};
 use logic::snapshot::SnapshotEncoder;
+use logic::telemetry::ThrowLogWriter;
+use logic::tile_map::{TileCoord, TileKind, TileMap};
+use logic::CatchUp;
+use socket::shutdown::{self, Shutdown, ShutdownTrigger};
+
+use crate::forward::{ForwardedEvent, Forwarder};
+use crate::storage::{self, MatchPlayer, MatchReport, Profile, Storage};
 
 use protocol::{
-    Action, ActionKind, EntityId, Event, EventKind, GameOver, PlayerId, Request, RequestKind,
-    Response, ResponseKind, Snapshot,
+    Action, ActionAck, ActionKind, ChatLog, ChatMessage, EntityId, Event, EventKind, GameOver,
+    HitConfirmed, MatchSummary, ObjectKind, PlayerId, PlayerStats, Request, RequestKind, Response,
+    ResponseKind, Restart, Snapshot, TraceId,
 };
 
-/// How many times per second to update the game world.
-const TICK_RATE: u32 = 60;
+/// How many seconds of world history to keep around.
+const HISTORY_SECONDS: u32 = 2;
+
+/// The highest tick rate that may be set at runtime.
+pub const MAX_TICK_RATE: u32 = 240;
+
+/// The highest time scale that may be set at runtime.
+pub const MAX_TIME_SCALE: f32 = 4.0;
+
+/// The health of spawned entities, unless another health is given.
+pub const DEFAULT_SPAWN_HEALTH: u32 = 3;
 
 /// The maximum number of events to buffer per player.
 const EVENT_BUFFER_SIZE: usize = 1024;
 
+/// The number of recent chat messages to keep for players that join later.
+const CHAT_HISTORY_LENGTH: usize = 100;
+
+/// The maximum number of characters in a chat message. Longer messages are truncated.
+const MAX_CHAT_LENGTH: usize = 200;
+
+/// The longest countdown before the game starts after a restart, in seconds.
+pub const MAX_RESTART_COUNTDOWN: u32 = 60;
+
 pub struct Game {
     players: BTreeMap<PlayerId, PlayerData>,
     receiver: mpsc::Receiver<Command>,
@@ -30,21 +62,63 @@ pub struct Game {
     world: World,
     executor: logic::Executor,
     snapshots: SnapshotEncoder,
+    history: WorldHistory,
+    /// Include the debug names of entities in snapshots.
+    debug_replication: bool,
+    /// The number of ticks left before the world starts moving after a restart.
+    countdown: u32,
 
     time: u32,
+    /// Seconds the game has been running, regardless of how often it has been updated.
+    uptime: f64,
+    current_match: Match,
+
+    /// Publishes events to an external service, if enabled.
+    forwarder: Option<Forwarder>,
+    /// Records every throw for balancing, if enabled.
+    throw_log: Option<ThrowLogWriter<BufWriter<File>>>,
+    /// Keeps profiles, match reports and events between runs, if enabled.
+    storage: Option<Box<dyn Storage>>,
+    /// The number of events stored so far.
+    stored_events: u64,
+
+    /// The most recent chat messages, from oldest to newest.
+    chat: VecDeque<ChatMessage>,
+    /// The id of the next chat message.
+    next_chat_id: u32,
 }
 
-#[derive(Debug, Clone)]
+/// Keeps track of everyone that took part in the current match.
+#[derive(Debug, Default)]
+struct Match {
+    /// The uptime at which the first player joined.
+    start: Option<f64>,
+    players: BTreeMap<PlayerId, Participant>,
+}
+
+#[derive(Debug)]
+struct Participant {
+    nickname: String,
+    joined: f64,
+    eliminated: Option<f64>,
+}
+
+#[derive(Debug)]
 struct PlayerData {
+    nickname: String,
     entity: Entity,
     network_id: EntityId,
     events: mpsc::Sender<Event>,
+    /// Cancels the player's connection once the player is removed from the game.
+    #[allow(dead_code)]
+    disconnect: ShutdownTrigger,
 }
 
 #[derive(Debug)]
 pub struct PlayerHandle {
     player: PlayerId,
     events: mpsc::Receiver<Event>,
+    shutdown: Shutdown,
 }
 
 #[derive(Debug, Clone)]
@@ -59,16 +133,82 @@ enum Command {
         callback: Callback<Response>,
     },
     RegisterPlayer {
+        nickname: String,
         callback: Callback<PlayerHandle>,
     },
     DisconnectPlayer(PlayerId),
     Snapshot {
-        callback: Callback<Snapshot>,
+        callback: Callback<(u32, Snapshot)>,
     },
     PerformAction {
         action: Action,
         player: PlayerId,
     },
+    UpdateRules {
+        update: RulesUpdate,
+        callback: Callback<Result<GameRules, RulesError>>,
+    },
+    SpawnEntity {
+        spawn: SpawnEntity,
+        callback: Callback<Result<EntityId, SpawnError>>,
+    },
+    Restart {
+        restart: WarmRestart,
+        callback: Callback<Result<GameRules, RulesError>>,
+    },
+}
+
+/// An entity added by an admin or a script, outside of the normal flow of the game.
+#[derive(Debug, Copy, Clone)]
+pub struct SpawnEntity {
+    pub kind: ObjectKind,
+    /// Where to spawn the entity. Entities spawned above the ground fall down.
+    pub position: Point3<f32>,
+    /// The health of the entity. Defaults to `DEFAULT_SPAWN_HEALTH`.
+    pub health: Option<u32>,
+    /// Entities on the same team are not damaged by each other's projectiles.
+    pub team: Option<u32>,
+}
+
+#[derive(Debug, Copy, Clone, Error)]
+pub enum SpawnError {
+    #[error("the health must be at least 1")]
+    InvalidHealth,
+    #[error("there is no land at ({}, {})", .0.x, .0.y)]
+    OutsideIsland(Point3<f32>),
+}
+
+/// Restart the game in a new world, with new objects and possibly new rules, without disconnecting
+/// the players.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct WarmRestart {
+    /// Changes to the rules of the new world.
+    pub rules: RulesUpdate,
+    /// For how many seconds the new world stands still before the game starts.
+    pub countdown: u32,
+}
+
+/// Changes to the rules of the game. Rules that are `None` are kept.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct RulesUpdate {
+    pub tick_rate: Option<u32>,
+    pub time_scale: Option<f32>,
+    pub player_softness: Option<f32>,
+    pub player_push: Option<f32>,
+}
+
+#[derive(Debug, Copy, Clone, Error)]
+pub enum RulesError {
+    #[error("the tick rate must be between 1 and {MAX_TICK_RATE}, found {0}")]
+    InvalidTickRate(u32),
+    #[error("the time scale must be between 0 and {MAX_TIME_SCALE}, found {0}")]
+    InvalidTimeScale(f32),
+    #[error("the player softness must be between 0 and 1, found {0}")]
+    InvalidPlayerSoftness(f32),
+    #[error("the player push must be a non-negative number, found {0}")]
+    InvalidPlayerPush(f32),
+    #[error("the countdown must be at most {MAX_RESTART_COUNTDOWN} seconds, found {0}")]
+    InvalidCountdown(u32),
 }
 
 struct Callback<T> {
@@ -83,21 +223,32 @@ impl<T> Debug for Callback<T> {
 }
 
 impl Game {
-    /// Create a new game alongside a handle to thet game.
-    pub fn new() -> (Game, GameHandle) {
+    /// Create a new game alongside a handle to thet game. If `debug_replication` is set, the debug
+    /// names of entities are included in snapshots.
+    pub fn new(rules: GameRules, debug_replication: bool) -> (Game, GameHandle) {
         let (sender, receiver) = mpsc::channel(1024);
 
-        let world = logic::create_world(logic::WorldKind::WithObjects);
-        let schedule = logic::add_systems(Default::default(), logic::SystemSet::Everything);
-        let executor = logic::Executor::new(schedule);
+        let mut world = logic::create_world(logic::WorldKind::WithObjects);
+        world.resources.insert(rules);
 
         let game = Game {
             players: BTreeMap::new(),
             receiver,
             world,
-            executor,
-            snapshots: SnapshotEncoder::new(),
+            executor: executor(),
+            snapshots: snapshot_encoder(debug_replication),
+            history: WorldHistory::new((HISTORY_SECONDS * rules.tick_rate) as usize),
+            debug_replication,
+            countdown: 0,
             time: 0,
+            uptime: 0.0,
+            current_match: Match::default(),
+            forwarder: None,
+            throw_log: None,
+            storage: None,
+            stored_events: 0,
+            chat: VecDeque::with_capacity(CHAT_HISTORY_LENGTH),
+            next_chat_id: 0,
         };
 
         let handle = GameHandle { sender };
@@ -107,7 +258,8 @@ impl Game {
 
     /// Run the game to completion (either the handle is dropped or a fatal error occurs).
     pub async fn run(&mut self) {
-        let mut timer = time::interval(time::Duration::from_secs(1) / TICK_RATE);
+        let mut tick_rate = self.rules().tick_rate;
+        let mut timer = time::interval(time::Duration::from_secs(1) / tick_rate);
 
         loop {
             tokio::select! {
@@ -125,23 +277,186 @@ impl Game {
                     }
                 }
             };
+
+            if self.rules().tick_rate != tick_rate {
+                tick_rate = self.rules().tick_rate;
+                let period = time::Duration::from_secs(1) / tick_rate;
+                timer = time::interval_at(time::Instant::now() + period, period);
+            }
+        }
+    }
+
+    /// Change how the simulation catches up after the server falls behind.
+    pub fn set_catch_up(&mut self, catch_up: CatchUp) {
+        self.executor.set_catch_up(catch_up);
+    }
+
+    /// Publish events to an external service.
+    pub fn set_forwarder(&mut self, forwarder: Forwarder) {
+        self.forwarder = Some(forwarder);
+    }
+
+    /// Record every throw to a log.
+    pub fn set_throw_log(&mut self, log: ThrowLogWriter<BufWriter<File>>) {
+        self.throw_log = Some(log);
+    }
+
+    /// Keep profiles, match reports and events.
+    pub fn set_storage(&mut self, storage: Box<dyn Storage>) {
+        self.storage = Some(storage);
+    }
+
+    /// Store an event and publish it to the external service, if any.
+    fn forward(&mut self, event: ForwardedEvent) {
+        let sequence = self.stored_events;
+        self.store("event", |blobs| {
+            let key = storage::sequential_key(sequence);
+            storage::save(blobs, storage::EVENTS, &key, &event)
+        });
+        self.stored_events += 1;
+
+        if let Some(forwarder) = &mut self.forwarder {
+            forwarder.forward(event);
         }
     }
 
+    /// Write to the storage, if any. Failures are logged instead of interrupting the game.
+    fn store<F>(&mut self, what: &str, write: F)
+    where
+        F: FnOnce(&mut dyn Storage) -> crate::Result<()>,
+    {
+        if let Some(blobs) = &mut self.storage {
+            if let Err(e) = write(blobs.as_mut()) {
+                log::warn!("failed to store {}: {:#}", what, e);
+            }
+        }
+    }
+
+    /// Apply a change to the stored profile of a player, creating the profile if needed.
+    fn update_profile(&mut self, nickname: &str, update: impl FnOnce(&mut Profile)) {
+        self.store("profile", |blobs| {
+            let mut profile = storage::load::<Profile>(blobs, storage::PROFILES, nickname)?
+                .unwrap_or_else(|| Profile {
+                    nickname: nickname.to_owned(),
+                    ..Profile::default()
+                });
+            update(&mut profile);
+            storage::save(blobs, storage::PROFILES, nickname, &profile)
+        });
+    }
+
+    fn rules(&self) -> GameRules {
+        *self.world.resources.get::<GameRules>().unwrap()
+    }
+
+    /// Change the rules of the game. The new rules are sent to the players with the next snapshot.
+    fn update_rules(&mut self, update: RulesUpdate) -> Result<GameRules, RulesError> {
+        let rules = update.apply(self.rules())?;
+
+        log::info!("changed rules: {:?}", rules);
+        self.world.resources.insert(rules);
+
+        Ok(rules)
+    }
+
+    /// Replace the world with a new one, giving every player a new entity in it. The players are
+    /// sent a full snapshot of the new world, which stands still until the countdown is over. The
+    /// current match is abandoned and a new one started.
+    fn restart(&mut self, restart: WarmRestart) -> Result<GameRules, RulesError> {
+        if restart.countdown > MAX_RESTART_COUNTDOWN {
+            return Err(RulesError::InvalidCountdown(restart.countdown));
+        }
+        let rules = restart.rules.apply(self.rules())?;
+
+        let mut world = logic::create_world_after(&self.world, logic::WorldKind::WithObjects);
+        world.resources.insert(rules);
+        log::info!("restarting the game with {:?}", rules);
+
+        let catch_up = self.executor.catch_up();
+        self.world = world;
+        self.executor = executor();
+        self.executor.set_catch_up(catch_up);
+        self.snapshots = snapshot_encoder(self.debug_replication);
+        self.history = WorldHistory::new((HISTORY_SECONDS * rules.tick_rate) as usize);
+        self.countdown = restart.countdown * rules.tick_rate;
+        self.current_match = Match::default();
+
+        for (&player, data) in &mut self.players {
+            data.entity = logic::add_player(&mut self.world, player);
+            data.network_id = *self.world.get_component::<EntityId>(data.entity).unwrap();
+            self.current_match
+                .join(player, data.nickname.clone(), self.uptime);
+        }
+
+        self.snapshots.update_mapping(&self.world);
+        self.broadcast(Restart {
+            countdown: restart.countdown,
+        });
+        let snapshot = Arc::new(self.snapshot());
+        self.broadcast(snapshot);
+
+        Ok(rules)
+    }
+
+    /// Add an entity to the world. The entity is sent to the players with the next snapshot.
+    fn spawn_entity(&mut self, spawn: SpawnEntity) -> Result<EntityId, SpawnError> {
+        let health = spawn.health.unwrap_or(DEFAULT_SPAWN_HEALTH);
+        if health == 0 {
+            return Err(SpawnError::InvalidHealth);
+        }
+
+        let on_land = self
+            .world
+            .resources
+            .get::<TileMap>()
+            .and_then(|map| {
+                let tile = map.get(TileCoord::from_world(spawn.position))?;
+                Some(!matches!(tile.kind, TileKind::Water))
+            })
+            .unwrap_or(false);
+        if !on_land {
+            return Err(SpawnError::OutsideIsland(spawn.position));
+        }
+
+        let entity = logic::add_object(&mut self.world, spawn.kind, spawn.position, health);
+        if let Some(team) = spawn.team {
+            self.world.add_component(entity, Team(team));
+        }
+
+        log::info!("spawned {}", DebugName::of(&self.world, entity));
+        Ok(*self.world.get_component::<EntityId>(entity).unwrap())
+    }
+
     fn tick(&mut self) {
-        self.executor.tick(&mut self.world);
+        if self.countdown > 0 {
+            self.countdown -= 1;
+            self.executor.pause();
+        } else {
+            self.executor.tick(&mut self.world);
+        }
+
+        self.history.record(self.time, &self.world);
         self.snapshots.update_mapping(&self.world);
+        self.confirm_hits();
+        self.record_throws();
         self.check_win_condition();
 
         let mut events = Vec::<EventKind>::new();
-        let snapshot = Arc::new(self.snapshot());
+        let snapshot = Arc::new(self.snapshots.make_delta_snapshot(&self.world));
         events.push(snapshot.into());
+        self.world
+            .resources
+            .get_mut::<EntityEffects>()
+            .unwrap()
+            .effects
+            .clear();
 
         for event in events {
             self.broadcast(event);
         }
 
         self.time = self.time.wrapping_add(1);
+        self.uptime += 1.0 / f64::from(self.rules().tick_rate);
     }
 
     fn broadcast<T>(&mut self, kind: T)
@@ -172,12 +487,84 @@ impl Game {
         }
 
         for player in dead {
-            self.remove_player(player);
+            self.disconnect_player(player);
+        }
+    }
+
+    /// Append the throws that landed during the last tick to the throw log. Stops recording if the
+    /// log could not be written.
+    fn record_throws(&mut self) {
+        let throws = std::mem::take(&mut self.world.resources.get_mut::<Throws>().unwrap().throws);
+
+        let writer = match &mut self.throw_log {
+            Some(writer) if !throws.is_empty() => writer,
+            _ => return,
+        };
+
+        let result = throws
+            .iter()
+            .try_for_each(|throw| writer.append(throw))
+            .and_then(|()| writer.flush());
+
+        if let Err(e) = result {
+            log::error!("stopped recording throws: {}", e);
+            self.throw_log = None;
+        }
+    }
+
+    /// Notify players about the hits their projectiles made during the last tick.
+    fn confirm_hits(&mut self) {
+        let hits = std::mem::take(&mut self.world.resources.get_mut::<Hits>().unwrap().hits);
+
+        for hit in hits {
+            let attacker = match hit.attacker {
+                Some(attacker) => attacker,
+                None => continue,
+            };
+
+            log::debug!(
+                "{} hit #{} for {} damage",
+                DebugName::of(&self.world, attacker),
+                hit.target.0,
+                hit.damage
+            );
+
+            let player = self
+                .players
+                .values_mut()
+                .find(|data| data.entity == attacker);
+
+            if let Some(player) = player {
+                let event = Event {
+                    time: self.time,
+                    kind: EventKind::HitConfirmed(HitConfirmed {
+                        target: hit.target,
+                        position: hit.position,
+                        damage: hit.damage,
+                    }),
+                };
+
+                // Hit confirmations are purely cosmetic, so it is fine to drop them if the buffer
+                // is full. Any problems with the channel are handled when broadcasting.
+                let _ = player.events.try_send(event);
+            }
+        }
+    }
+
+    /// Remove a player that left the game.
+    fn disconnect_player(&mut self, player: PlayerId) {
+        if let Some(data) = self.remove_player(player) {
+            self.forward(ForwardedEvent::Leave {
+                player: player.0,
+                nickname: data.nickname,
+            });
         }
     }
 
     fn remove_player(&mut self, player: PlayerId) -> Option<PlayerData> {
         let data = self.players.remove(&player)?;
+        log::info!("player {} ({:?}) left the game", player, data.nickname);
+        self.current_match.eliminate(player, self.uptime);
         self.world.delete(data.entity);
         self.world
             .resources
@@ -202,66 +589,132 @@ impl Game {
         drop(dead);
 
         for loser in losers {
-            let mut player = self.players.remove(&loser).unwrap();
-            let event = Event {
-                time: self.time,
-                kind: EventKind::GameOver(GameOver::Loser),
-            };
-            tokio::spawn(async move { player.events.send(event).await });
+            let player = self.players.remove(&loser).unwrap();
+            self.current_match.eliminate(loser, self.uptime);
+            let summary = self.current_match.summary(self.uptime);
+            self.end_game(loser, player, GameOver::Loser, summary);
 
             if self.players.len() == 1 {
                 let winner = *self.players.keys().next().unwrap();
-                let mut player = self.remove_player(winner).unwrap();
-                let event = Event {
-                    time: self.time,
-                    kind: EventKind::GameOver(GameOver::Winner),
-                };
-                tokio::spawn(async move { player.events.send(event).await });
+                let summary = self.current_match.summary(self.uptime);
+                let player = self.remove_player(winner).unwrap();
+                self.end_game(winner, player, GameOver::Winner, summary);
+
+                log::info!("player {} won the match", winner);
+                let report = self.current_match.report(self.uptime);
+                self.store("match report", |blobs| {
+                    let key = storage::sequential_key(0);
+                    storage::save(blobs, storage::MATCHES, &key, &report)
+                });
+                self.current_match = Match::default();
             }
         }
     }
 
+    /// Notify a player that the game is over for them, and send them the current standings.
+    fn end_game(
+        &mut self,
+        id: PlayerId,
+        mut player: PlayerData,
+        game_over: GameOver,
+        summary: MatchSummary,
+    ) {
+        let survived = summary
+            .players
+            .iter()
+            .find(|stats| stats.player == id)
+            .map_or(0, |stats| stats.survived);
+        let won = matches!(game_over, GameOver::Winner);
+        self.update_profile(&player.nickname, |profile| {
+            profile.record_match(won, survived)
+        });
+        self.forward(ForwardedEvent::game_over(
+            id,
+            player.nickname.clone(),
+            game_over.clone(),
+            survived,
+        ));
+
+        let game_over = Event {
+            time: self.time,
+            kind: EventKind::GameOver(game_over),
+        };
+        let summary = Event {
+            time: self.time,
+            kind: EventKind::MatchSummary(summary),
+        };
+
+        tokio::spawn(async move {
+            player.events.send(game_over).await?;
+            player.events.send(summary).await
+        });
+    }
+
     /// Execute a command.
     fn execute_command(&mut self, command: Command) {
         match command {
-            Command::RegisterPlayer { callback } => {
-                callback.send(self.register_player());
-            }
-            Command::DisconnectPlayer(player) => {
-                self.remove_player(player);
+            Command::RegisterPlayer { nickname, callback } => {
+                callback.send(self.register_player(nickname));
             }
+            Command::DisconnectPlayer(player) => self.disconnect_player(player),
             Command::Request { callback, request } => {
                 let message = self.handle_request(request);
                 callback.send(message);
             }
             Command::Snapshot { callback } => {
                 let snapshot = self.snapshot();
-                callback.send(snapshot);
+                callback.send((self.time, snapshot));
             }
             Command::PerformAction { action, player } => self.perform_action(action, player),
+            Command::UpdateRules { update, callback } => {
+                callback.send(self.update_rules(update));
+            }
+            Command::SpawnEntity { spawn, callback } => {
+                callback.send(self.spawn_entity(spawn));
+            }
+            Command::Restart { restart, callback } => {
+                callback.send(self.restart(restart));
+            }
         }
     }
 
     /// Create and register a new player
-    fn register_player(&mut self) -> PlayerHandle {
+    fn register_player(&mut self, nickname: String) -> PlayerHandle {
         let player = self.next_player_id();
+        log::info!("player {} joined as {:?}", player, nickname);
+
         let entity = logic::add_player(&mut self.world, player);
+        log::debug!("spawned {}", DebugName::of(&self.world, entity));
 
         let (sender, receiver) = mpsc::channel(EVENT_BUFFER_SIZE);
 
         let network_id = *self.world.get_component::<EntityId>(entity).unwrap();
 
+        let (disconnect, shutdown) = shutdown::channel();
+
         let data = PlayerData {
+            nickname,
             network_id,
             entity,
             events: sender,
+            disconnect,
         };
 
+        self.current_match
+            .join(player, data.nickname.clone(), self.uptime);
+        self.update_profile(&data.nickname, |profile| {
+            profile.last_seen = storage::unix_time()
+        });
+        self.forward(ForwardedEvent::Join {
+            player: player.0,
+            nickname: data.nickname.clone(),
+        });
         self.players.insert(player, data);
 
         PlayerHandle {
             player,
             events: receiver,
+            shutdown,
         }
     }
 
@@ -284,10 +737,18 @@ impl Game {
     fn handle_request(&mut self, request: Request) -> Response {
         let kind = match request.kind {
             RequestKind::Ping => protocol::Pong.into(),
-            RequestKind::Init => {
+            RequestKind::Init(_) => {
                 let error = "Requested 'Init' on already initialized player";
                 ResponseKind::Error(error.into())
             }
+            RequestKind::ChatHistory(history) => {
+                let count = self.chat.len().min(history.limit as usize);
+                let messages = self.chat.iter().skip(self.chat.len() - count).cloned();
+                ChatLog {
+                    messages: messages.collect(),
+                }
+                .into()
+            }
         };
 
         Response {
@@ -303,6 +764,10 @@ impl Game {
 
     /// Perform an action for a player.
     fn perform_action(&mut self, action: Action, player: PlayerId) {
+        if let Some(trace) = action.trace {
+            self.acknowledge_action(trace, player);
+        }
+
         match action.kind {
             ActionKind::Move(new) => {
                 || -> Option<()> {
@@ -326,17 +791,195 @@ impl Game {
             }
             ActionKind::Throw(throwing) => {
                 if let Some(data) = self.players.get(&player) {
-                    logic::events::throw(&mut self.world, data.entity, throwing.target);
+                    let result = logic::events::throw(
+                        &mut self.world,
+                        data.entity,
+                        throwing.target,
+                        throwing.projectile,
+                    );
+
+                    if let Err(e) = result {
+                        let name = DebugName::of(&self.world, data.entity);
+                        log::debug!("{} failed to throw: {:?}", name, e);
+                    }
                 }
             }
+            ActionKind::Chat(chat) => self.send_chat(player, &chat.text),
+            ActionKind::Block(block) => {
+                || -> Option<()> {
+                    let data = self.players.get(&player)?;
+                    let mut movement = self.world.get_component_mut::<Movement>(data.entity)?;
+                    movement.stance = match block.facing {
+                        Some(facing) => Stance::Blocking { facing },
+                        None => Stance::Standing,
+                    };
+                    Some(())
+                }();
+            }
+        }
+    }
+
+    /// Send a chat message to every player, and keep it in the history.
+    fn send_chat(&mut self, player: PlayerId, text: &str) {
+        let text = text.trim();
+        let nickname = match self.players.get(&player) {
+            Some(data) if !text.is_empty() => data.nickname.clone(),
+            _ => return,
+        };
+
+        let message = ChatMessage {
+            id: self.next_chat_id,
+            sender: player,
+            nickname,
+            sent: storage::unix_time(),
+            text: text.chars().take(MAX_CHAT_LENGTH).collect(),
+        };
+        self.next_chat_id = self.next_chat_id.wrapping_add(1);
+        log::info!("{} [{}]: {}", message.nickname, player, message.text);
+
+        if self.chat.len() == CHAT_HISTORY_LENGTH {
+            self.chat.pop_front();
+        }
+        self.chat.push_back(message.clone());
+
+        self.broadcast(message);
+    }
+
+    /// Tell a player that one of their traced actions was performed during the current tick.
+    fn acknowledge_action(&mut self, trace: TraceId, player: PlayerId) {
+        if let Some(data) = self.players.get_mut(&player) {
+            let event = Event {
+                time: self.time,
+                kind: EventKind::ActionAck(ActionAck {
+                    trace,
+                    tick: self.time,
+                }),
+            };
+
+            // Acknowledgements only feed the latency statistics, so they may be dropped like hit
+            // confirmations.
+            let _ = data.events.try_send(event);
+        }
+    }
+}
+
+/// Run every game logic system.
+fn executor() -> logic::Executor {
+    let schedule = logic::add_systems(Default::default(), logic::SystemSet::Everything);
+    logic::Executor::new(schedule)
+}
+
+fn snapshot_encoder(debug_replication: bool) -> SnapshotEncoder {
+    let mut snapshots = SnapshotEncoder::new();
+    if debug_replication {
+        snapshots.replicate_debug_names();
+    }
+    snapshots
+}
+
+impl RulesUpdate {
+    /// Apply the changes to a set of rules, if they are valid.
+    pub fn apply(self, mut rules: GameRules) -> Result<GameRules, RulesError> {
+        if let Some(tick_rate) = self.tick_rate {
+            if tick_rate == 0 || tick_rate > MAX_TICK_RATE {
+                return Err(RulesError::InvalidTickRate(tick_rate));
+            }
+            rules.tick_rate = tick_rate;
+        }
+
+        if let Some(time_scale) = self.time_scale {
+            if !(0.0..=MAX_TIME_SCALE).contains(&time_scale) {
+                return Err(RulesError::InvalidTimeScale(time_scale));
+            }
+            rules.time_scale = time_scale;
+        }
+
+        if let Some(softness) = self.player_softness {
+            if !(0.0..=1.0).contains(&softness) {
+                return Err(RulesError::InvalidPlayerSoftness(softness));
+            }
+            rules.player_softness = softness;
+        }
+
+        if let Some(push) = self.player_push {
+            if !push.is_finite() || push < 0.0 {
+                return Err(RulesError::InvalidPlayerPush(push));
+            }
+            rules.player_push = push;
+        }
+
+        Ok(rules)
+    }
+}
+
+impl Match {
+    fn join(&mut self, player: PlayerId, nickname: String, time: f64) {
+        self.start.get_or_insert(time);
+        self.players.insert(
+            player,
+            Participant {
+                nickname,
+                joined: time,
+                eliminated: None,
+            },
+        );
+    }
+
+    fn eliminate(&mut self, player: PlayerId, time: f64) {
+        if let Some(participant) = self.players.get_mut(&player) {
+            participant.eliminated.get_or_insert(time);
+        }
+    }
+
+    /// Get the standings of the match at the given time.
+    fn summary(&self, time: f64) -> MatchSummary {
+        let seconds = |duration: f64| duration.max(0.0) as u32;
+
+        let mut players = self
+            .players
+            .iter()
+            .map(|(&player, participant)| {
+                let end = participant.eliminated.unwrap_or(time);
+                PlayerStats {
+                    player,
+                    nickname: participant.nickname.clone(),
+                    survived: seconds(end - participant.joined),
+                    eliminated: participant.eliminated.is_some(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        players.sort_by_key(|stats| (stats.eliminated, Reverse(stats.survived)));
+
+        MatchSummary {
+            duration: seconds(time - self.start.unwrap_or(time)),
+            players,
+        }
+    }
+
+    /// The final standings of the match, to be stored.
+    fn report(&self, time: f64) -> MatchReport {
+        let summary = self.summary(time);
+        MatchReport {
+            ended: storage::unix_time(),
+            duration: summary.duration,
+            players: summary
+                .players
+                .into_iter()
+                .map(|stats| MatchPlayer {
+                    nickname: stats.nickname,
+                    survived: stats.survived,
+                    eliminated: stats.eliminated,
+                })
+                .collect(),
         }
     }
 }
 
 impl GameHandle {
     /// Register a new client and return it's id.
-    pub async fn register_player(&mut self) -> crate::Result<PlayerHandle> {
-        self.send_with(|callback| Command::RegisterPlayer { callback })
+    pub async fn register_player(&mut self, nickname: String) -> crate::Result<PlayerHandle> {
+        self.send_with(|callback| Command::RegisterPlayer { nickname, callback })
             .await
     }
 
@@ -352,8 +995,8 @@ impl GameHandle {
             .await
     }
 
-    /// Get a snapshot of the current game state.
-    pub async fn snapshot(&mut self) -> crate::Result<Snapshot> {
+    /// Get a snapshot of the current game state, and the tick it was taken at.
+    pub async fn snapshot(&mut self) -> crate::Result<(u32, Snapshot)> {
         self.send_with(|callback| Command::Snapshot { callback })
             .await
     }
@@ -366,6 +1009,33 @@ impl GameHandle {
         Ok(())
     }
 
+    /// Restart the game in a new world, returning the rules in effect in it.
+    pub async fn restart(
+        &mut self,
+        restart: WarmRestart,
+    ) -> crate::Result<Result<GameRules, RulesError>> {
+        self.send_with(|callback| Command::Restart { restart, callback })
+            .await
+    }
+
+    /// Change the rules of the game, returning the rules now in effect.
+    pub async fn update_rules(
+        &mut self,
+        update: RulesUpdate,
+    ) -> crate::Result<Result<GameRules, RulesError>> {
+        self.send_with(|callback| Command::UpdateRules { update, callback })
+            .await
+    }
+
+    /// Add an entity to the world.
+    pub async fn spawn_entity(
+        &mut self,
+        spawn: SpawnEntity,
+    ) -> crate::Result<Result<EntityId, SpawnError>> {
+        self.send_with(|callback| Command::SpawnEntity { spawn, callback })
+            .await
+    }
+
     /// Send a command to the game with the specified callback and then return the value passed into
     /// the callback.
     async fn send_with<F, O>(&mut self, to_command: F) -> crate::Result<O>
@@ -388,6 +1058,16 @@ impl PlayerHandle {
     pub async fn poll_event(&mut self) -> Option<Event> {
         self.events.recv().await
     }
+
+    /// Get an event that has already been sent, without waiting.
+    pub fn try_event(&mut self) -> Option<Event> {
+        self.events.try_recv().ok()
+    }
+
+    /// Get a token that is signaled once the player has been removed from the game.
+    pub fn shutdown_token(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
 }
 
 impl<T> Callback<T> {
@@ -0,0 +1,25 @@
+//! Author(s):
+//! - Christofer Nolander (cnol@kth.se)
+//!
+//!
+//! # Architecture
+//!
+//! Clients may connect to the server to reserve a slot. When given a slot, the server registers
+//! them as a receiver and sender of messages. Clients may send evenst to the server at any time,
+//! and the server pushes updates to the clients as soon as possible.
+//!
+//! 60 times a second the server performs a world update with all events that occured since the
+//! previous update. After an update the updated state is sent to the clients.
+
+#[macro_use]
+extern crate anyhow;
+
+pub mod forward;
+pub mod game;
+pub mod message;
+mod server;
+pub mod storage;
+
+pub use server::Server;
+
+pub type Result<T> = anyhow::Result<T>;
@@ -1,33 +1,36 @@
 //! Author(s):
 //! - Christofer Nolander (cnol@kth.se)
-//!
-//!
-//! # Architecture
-//!
-//! Clients may connect to the server to reserve a slot. When given a slot, the server registers
-//! them as a receiver and sender of messages. Clients may send evenst to the server at any time,
-//! and the server pushes updates to the clients as soon as possible.
-//!
-//! 60 times a second the server performs a world update with all events that occured since the
-//! previous update. After an update the updated state is sent to the clients.
 
 #[macro_use]
 extern crate anyhow;
 
-mod game;
-mod message;
+mod console;
 mod options;
 
 use anyhow::Context;
-use protocol::{ClientMessage, RequestKind};
+use logic::resources::GameRules;
+use logic::telemetry::ThrowLogWriter;
+use logic::CatchUp;
+use protocol::bandwidth::Bandwidth;
+use std::fs::File;
+use std::io::BufWriter;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 use structopt::StructOpt;
-use tokio::task;
+use tokio::{task, time};
+
+use server::forward::Forwarder;
+use server::game::{Game, GameHandle, RulesUpdate};
+use server::{Result, Server};
 
-use game::{Game, GameHandle, PlayerHandle};
-use message::{Connection, Listener};
 use options::Options;
 
-type Result<T> = anyhow::Result<T>;
+/// How often to log the bandwidth used by each kind of message.
+const BANDWIDTH_LOG_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The number of kinds of messages included in the bandwidth log.
+const BANDWIDTH_LOG_TOP: usize = 5;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -36,165 +39,92 @@ async fn main() -> Result<()> {
 
     setup_logger(options);
 
-    let (mut game, handle) = Game::new();
+    if let Some(path) = &options.pcap_out {
+        socket::capture::start(path).context("failed to start packet capture")?;
+    }
+
+    let rules = RulesUpdate {
+        tick_rate: Some(options.tick_rate),
+        time_scale: Some(options.time_scale),
+        ..RulesUpdate::default()
+    }
+    .apply(GameRules::default())?;
+    let (mut game, handle) = Game::new(rules, options.debug_replication);
+    game.set_catch_up(CatchUp {
+        max_ticks: options.max_catch_up_ticks,
+        policy: options.catch_up,
+    });
+
+    if let Some(sink) = &options.forward {
+        let filter = options.forward_events.clone();
+        game.set_forwarder(Forwarder::spawn(sink.clone(), filter, options.forward_rate));
+    }
+
+    if let Some(path) = &options.throw_log {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create throw log {}", path.display()))?;
+        game.set_throw_log(ThrowLogWriter::new(BufWriter::new(file))?);
+    }
+
+    if let Some(backend) = &options.storage {
+        let storage = backend
+            .open()
+            .with_context(|| format!("failed to open storage {}", backend))?;
+        log::info!("storing profiles, matches and events in {}", backend);
+        game.set_storage(storage);
+    }
+
+    let bandwidth = Arc::new(Bandwidth::default());
+
+    console::spawn(handle.clone(), bandwidth.clone());
 
     let local = task::LocalSet::new();
     local.spawn_local(async move { game.run().await });
-    local.spawn_local(tokio::spawn(game_server(options, handle)));
+    local.spawn_local(tokio::spawn(log_bandwidth(bandwidth.clone())));
+    local.spawn_local(tokio::spawn(game_server(options, handle, bandwidth)));
     local.await;
     Ok(())
 }
 
-async fn game_server(options: &Options, handle: GameHandle) -> anyhow::Result<()> {
+async fn game_server(
+    options: &Options,
+    handle: GameHandle,
+    bandwidth: Arc<Bandwidth>,
+) -> anyhow::Result<()> {
     loop {
-        let server = Server::new(options, handle.clone()).await?;
+        let addr = SocketAddr::new(options.addr, options.port);
+        let server = Server::new(addr, handle.clone(), bandwidth.clone()).await?;
         let error = server.run().await;
         log::error!("server crashed: {}", error);
     }
 }
 
-/// Setup logging facilities.
-fn setup_logger(options: &Options) {
-    env_logger::Builder::new()
-        .filter_level(options.log_level)
-        .init();
-}
-
-#[derive(Debug)]
-struct Server {
-    listener: Listener,
-    game: GameHandle,
-}
-
-impl Server {
-    pub async fn new(options: &Options, game: GameHandle) -> Result<Server> {
-        let (listener, addr) = Listener::bind((options.addr, options.port)).await?;
+/// Periodically log the kinds of messages that used the most bandwidth.
+async fn log_bandwidth(bandwidth: Arc<Bandwidth>) {
+    let mut previous = bandwidth.sample();
+    let mut timer = time::interval_at(
+        time::Instant::now() + BANDWIDTH_LOG_INTERVAL,
+        BANDWIDTH_LOG_INTERVAL,
+    );
 
-        let addr = addr
-            .map(|a| a.to_string())
-            .unwrap_or_else(|| "<unknown>".into());
-        log::info!("listening for connections on [{}]", addr);
+    loop {
+        timer.tick().await;
 
-        Ok(Server { listener, game })
-    }
+        let current = bandwidth.sample();
+        let mut report = current.report_since(&previous);
+        previous = current;
 
-    /// Handle incoming connections in an endless loop.
-    pub async fn run(mut self) -> anyhow::Error {
-        loop {
-            let conn = match self.listener.accept().await {
-                Ok(conn) => conn,
-                Err(e) => break anyhow!("socket closed: {:#}", e),
-            };
-
-            let peer = conn.peer_addr();
-
-            log::info!("Client connected from [{}]", peer);
-
-            let game = self.game.clone();
-
-            tokio::spawn(async move {
-                let mut conn = conn;
-                match handle_connection(&mut conn, game).await {
-                    Ok(()) => log::info!("Done with the client [{}]", peer),
-                    Err(error) => {
-                        log::error!("An error occured with the client [{}]: {:?}", peer, error);
-                    }
-                }
-
-                if let Err(error) = conn.shutdown().await {
-                    log::error!("failed to shutdown connection to [{}]: {:#}", peer, error);
-                }
-            });
+        if !report.traffic.is_empty() {
+            report.truncate(BANDWIDTH_LOG_TOP);
+            log::info!("bandwidth {}", report);
         }
     }
 }
 
-/// Handle an incoming connection.
-async fn handle_connection(conn: &mut Connection, mut game: GameHandle) -> Result<()> {
-    let mut player = initialize_client(conn, &mut game)
-        .await
-        .context("failed to initialize client")?;
-
-    let result = handle_client(conn, &mut game, &mut player)
-        .await
-        .context("failed to serve client");
-
-    game.disconnect_player(player.id())
-        .await
-        .with_context(|| format!("when disconnecting player {}", player.id()))?;
-
-    result
-}
-
-/// Wait for the client to initialize the connection.
-async fn initialize_client(conn: &mut Connection, game: &mut GameHandle) -> Result<PlayerHandle> {
-    let message = conn
-        .recv()
-        .await
-        .context("failed to receive init request")?
-        .ok_or_else(|| anyhow!("expected a request, found EOF"))?;
-
-    let request = match message {
-        ClientMessage::Request(request) => request,
-        ClientMessage::Action(_) => return Err(anyhow!("expected a request, found an action")),
-    };
-
-    match request.kind {
-        RequestKind::Init => (),
-        _ => {
-            return Err(anyhow!(
-                "exepected an 'Init' request, found '{}'",
-                request.kind.name()
-            ))
-        }
-    };
-
-    let player = game
-        .register_player()
-        .await
-        .context("failed to register player")?;
-
-    let snapshot = game.snapshot().await?;
-
-    let connect = protocol::Connect {
-        player_id: player.id(),
-        snapshot,
-    };
-
-    conn.send_response((request.channel, connect).into())
-        .await
-        .context("failed to send connection response")?;
-
-    Ok(player)
+/// Setup logging facilities.
+fn setup_logger(options: &Options) {
+    env_logger::Builder::new()
+        .filter_level(options.log_level)
+        .init();
 }
 
-/// Handle all messages coming from/to the client.
-async fn handle_client(
-    conn: &mut Connection,
-    game: &mut GameHandle,
-    player: &mut PlayerHandle,
-) -> Result<()> {
-    loop {
-        tokio::select! {
-            request = conn.recv() => match request.context("bad request")? {
-                None => break Ok(()),
-                Some(ClientMessage::Request(request)) => {
-                    let response = game.handle_request(request).await?;
-                    conn.send_response(response).await?;
-                }
-                Some(ClientMessage::Action(action)) => {
-                    game.handle_action(action, player.id()).await?;
-                }
-            },
-
-            event = player.poll_event() => match event {
-                None => break Err(anyhow!("event channel closed")),
-                Some(event) => {
-                    conn.send_event(event).await?;
-                }
-            },
-
-            else => {}
-        };
-    }
-}
@@ -1,17 +1,23 @@
-use protocol::{ClientMessage, Event, Response, ServerMessage};
-use socket::{Connection as Socket, Delivery, Listener as SocketListener};
-use std::net::SocketAddr;
-use tokio::net::ToSocketAddrs;
+use protocol::bandwidth::{self, Bandwidth};
+use protocol::{ClientMessage, Event, RequestKind, Response, ServerMessage};
+use socket::{Connection as Socket, Delivery, Endpoint};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
 
 /// A connection to a single client.
 pub struct Connection {
     socket: Socket,
+    bandwidth: Arc<Bandwidth>,
+    /// The version of the messages the client knows about, once it has sent `Init`.
+    version: Option<u32>,
 }
 
 /// Listens for new client connections.
 #[derive(Debug)]
 pub struct Listener {
-    listener: SocketListener,
+    listener: Endpoint,
+    /// Shared by all connections accepted by the listener.
+    bandwidth: Arc<Bandwidth>,
 }
 
 impl Connection {
@@ -27,7 +33,11 @@ impl Connection {
 
     /// Send a message to the client.
     pub async fn send(&mut self, message: &ServerMessage) -> crate::Result<()> {
-        let bytes = protocol::to_bytes(message)?;
+        let bytes = match self.version {
+            Some(version) => protocol::to_bytes_versioned(message, version)?,
+            None => protocol::to_bytes(message)?,
+        };
+        self.bandwidth.record_sent(message.name(), bytes.len());
 
         let delivery = if message.must_arrive() {
             Delivery::Reliable
@@ -54,7 +64,26 @@ impl Connection {
     /// from the client.
     pub async fn recv(&mut self) -> crate::Result<Option<ClientMessage>> {
         if let Some(bytes) = self.socket.recv().await {
-            let message = protocol::from_bytes(&bytes)?;
+            let limits = protocol::CLIENT_LIMITS;
+            let message = match self.version {
+                Some(version) => {
+                    protocol::from_bytes_strict_versioned::<ClientMessage>(&bytes, version, limits)
+                }
+                None => protocol::from_bytes_strict_with_limits::<ClientMessage>(&bytes, limits),
+            };
+            let name = match &message {
+                Ok(message) => message.name(),
+                Err(_) => bandwidth::MALFORMED,
+            };
+            self.bandwidth.record_received(name, bytes.len());
+
+            let message = message?;
+            if let ClientMessage::Request(request) = &message {
+                if let RequestKind::Init(init) = &request.kind {
+                    self.version = Some(init.version.min(protocol::VERSION));
+                }
+            }
+
             Ok(Some(message))
         } else {
             Ok(None)
@@ -64,14 +93,20 @@ impl Connection {
 
 impl Listener {
     /// Listen for clients on a specific address.
-    pub async fn bind<T>(addr: T) -> crate::Result<(Listener, Option<SocketAddr>)>
+    pub async fn bind<T>(
+        addr: T,
+        bandwidth: Arc<Bandwidth>,
+    ) -> crate::Result<(Listener, Option<SocketAddr>)>
     where
         T: ToSocketAddrs,
     {
-        let listener = SocketListener::bind(addr).await?;
+        let listener = Endpoint::bind(addr).await?;
         let addr = listener.local_addr();
 
-        let listener = Listener { listener };
+        let listener = Listener {
+            listener,
+            bandwidth,
+        };
 
         Ok((listener, addr))
     }
@@ -79,6 +114,10 @@ impl Listener {
     /// Wait for a new client to connect to the socket.
     pub async fn accept(&mut self) -> crate::Result<Connection> {
         let socket = self.listener.accept().await?;
-        Ok(Connection { socket })
+        Ok(Connection {
+            socket,
+            bandwidth: self.bandwidth.clone(),
+            version: None,
+        })
     }
 }
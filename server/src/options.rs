@@ -1,5 +1,10 @@
 use structopt::StructOpt;
 use std::net::IpAddr;
+use std::path::PathBuf;
+
+use logic::CatchUpPolicy;
+use server::forward::{EventFilter, Sink};
+use server::storage::Backend;
 
 // Define some options that can be configured with command line arguments.
 #[derive(StructOpt)]
@@ -15,6 +20,55 @@ pub struct Options {
     /// The verbosity of the logging.
     #[structopt(long, default_value = "info")]
     pub log_level: log::LevelFilter,
+
+    /// How many times per second to update the game world.
+    #[structopt(long, default_value = "60")]
+    pub tick_rate: u32,
+
+    /// How fast time passes in the game world, relative to real time.
+    #[structopt(long, default_value = "1.0")]
+    pub time_scale: f32,
+
+    /// The most simulation steps run at once when the server has fallen behind.
+    #[structopt(long, default_value = "120")]
+    pub max_catch_up_ticks: u32,
+
+    /// What to do with time that could not be simulated within `--max-catch-up-ticks`: `drop` it,
+    /// simulate it later in `slow-motion`, or simulate it later unless more than the given number
+    /// of seconds is behind with `limited:<seconds>`.
+    #[structopt(long, default_value = "limited:1")]
+    pub catch_up: CatchUpPolicy,
+
+    /// Include the debug names of entities in snapshots, so that clients log the same names.
+    #[structopt(long)]
+    pub debug_replication: bool,
+
+    /// Forward game events as JSON to `udp://<host>:<port>`, `tcp://<host>:<port>` or
+    /// `http://<host>[:<port>]/<path>`.
+    #[structopt(long)]
+    pub forward: Option<Sink>,
+
+    /// The kinds of events to forward: `join`, `leave` and `game_over`.
+    #[structopt(long, use_delimiter = true, default_value = "join,leave,game_over")]
+    pub forward_events: Vec<EventFilter>,
+
+    /// The maximum average number of events forwarded per second.
+    #[structopt(long, default_value = "5")]
+    pub forward_rate: f64,
+
+    /// Record the origin, target, power and outcome of every throw to a file, which can be turned
+    /// into a heatmap with the `heatmap` tool.
+    #[structopt(long, parse(from_os_str))]
+    pub throw_log: Option<PathBuf>,
+
+    /// Write all sent and received datagrams to a pcapng file.
+    #[structopt(long, parse(from_os_str))]
+    pub pcap_out: Option<PathBuf>,
+
+    /// Keep player profiles, match reports and game events in `file:<directory>`, or in
+    /// `sqlite:<path>` if the server was built with the `sqlite` feature.
+    #[structopt(long)]
+    pub storage: Option<Backend>,
 }
 
 
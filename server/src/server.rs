@@ -0,0 +1,198 @@
+//! Accepts connections from clients and relays messages between them and the game.
+
+use anyhow::Context;
+use protocol::bandwidth::Bandwidth;
+use protocol::{ClientMessage, Event, EventKind, RequestKind, WorldChunk};
+use socket::shutdown::Shutdown;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::game::{GameHandle, PlayerHandle};
+use crate::message::{Connection, Listener};
+use crate::Result;
+
+/// The greatest number of entities sent in a single `WorldChunk` when a client connects.
+const WORLD_CHUNK_ENTITIES: usize = 64;
+
+/// Listens for clients and connects them to a game.
+#[derive(Debug)]
+pub struct Server {
+    listener: Listener,
+    game: GameHandle,
+    addr: Option<SocketAddr>,
+}
+
+impl Server {
+    /// Start listening for connections on an address.
+    pub async fn new(
+        addr: SocketAddr,
+        game: GameHandle,
+        bandwidth: Arc<Bandwidth>,
+    ) -> Result<Server> {
+        let (listener, addr) = Listener::bind(addr, bandwidth).await?;
+
+        let name = addr
+            .map(|a| a.to_string())
+            .unwrap_or_else(|| "<unknown>".into());
+        log::info!("listening for connections on [{}]", name);
+
+        Ok(Server {
+            listener,
+            game,
+            addr,
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.addr
+    }
+
+    /// Handle incoming connections in an endless loop.
+    pub async fn run(mut self) -> anyhow::Error {
+        loop {
+            let conn = match self.listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => break anyhow!("socket closed: {:#}", e),
+            };
+
+            let peer = conn.peer_addr();
+
+            log::info!("Client connected from [{}]", peer);
+
+            let game = self.game.clone();
+
+            tokio::spawn(async move {
+                let mut conn = conn;
+                match handle_connection(&mut conn, game).await {
+                    Ok(()) => log::info!("Done with the client [{}]", peer),
+                    Err(error) => {
+                        log::error!("An error occured with the client [{}]: {:?}", peer, error);
+                    }
+                }
+
+                if let Err(error) = conn.shutdown().await {
+                    log::error!("failed to shutdown connection to [{}]: {:#}", peer, error);
+                }
+            });
+        }
+    }
+}
+
+/// Handle an incoming connection.
+async fn handle_connection(conn: &mut Connection, mut game: GameHandle) -> Result<()> {
+    let mut player = initialize_client(conn, &mut game)
+        .await
+        .context("failed to initialize client")?;
+
+    let shutdown = player.shutdown_token();
+    let result = handle_client(conn, &mut game, &mut player, shutdown)
+        .await
+        .context("failed to serve client");
+
+    game.disconnect_player(player.id())
+        .await
+        .with_context(|| format!("when disconnecting player {}", player.id()))?;
+
+    result
+}
+
+/// Wait for the client to initialize the connection.
+async fn initialize_client(conn: &mut Connection, game: &mut GameHandle) -> Result<PlayerHandle> {
+    let message = conn
+        .recv()
+        .await
+        .context("failed to receive init request")?
+        .ok_or_else(|| anyhow!("expected a request, found EOF"))?;
+
+    let request = match message {
+        ClientMessage::Request(request) => request,
+        ClientMessage::Action(_) => return Err(anyhow!("expected a request, found an action")),
+    };
+
+    let init = match request.kind {
+        RequestKind::Init(init) => init,
+        kind => {
+            return Err(anyhow!(
+                "exepected an 'Init' request, found '{}'",
+                kind.name()
+            ))
+        }
+    };
+
+    let player = game
+        .register_player(init.nickname)
+        .await
+        .context("failed to register player")?;
+
+    let (time, snapshot) = game.snapshot().await?;
+    let chunks = snapshot.entities.chunks(WORLD_CHUNK_ENTITIES);
+
+    let connect = protocol::Connect {
+        player_id: player.id(),
+        world: snapshot.world.clone(),
+        chunks: chunks.len() as u32,
+        version: protocol::VERSION,
+    };
+
+    conn.send_response((request.channel, connect).into())
+        .await
+        .context("failed to send connection response")?;
+
+    for (index, entities) in chunks.enumerate() {
+        let chunk = WorldChunk {
+            index: index as u32,
+            entities: entities.to_vec(),
+        };
+        let event = Event {
+            time,
+            kind: EventKind::WorldChunk(chunk),
+        };
+        conn.send_event(event)
+            .await
+            .context("failed to send world chunk")?;
+    }
+
+    Ok(player)
+}
+
+/// Handle all messages coming from/to the client.
+async fn handle_client(
+    conn: &mut Connection,
+    game: &mut GameHandle,
+    player: &mut PlayerHandle,
+    mut shutdown: Shutdown,
+) -> Result<()> {
+    loop {
+        tokio::select! {
+            () = shutdown.wait() => {
+                // deliver the events sent right before the player was removed, such as the
+                // results of the match
+                while let Some(event) = player.try_event() {
+                    conn.send_event(event).await?;
+                }
+                break Ok(());
+            },
+
+            request = conn.recv() => match request.context("bad request")? {
+                None => break Ok(()),
+                Some(ClientMessage::Request(request)) => {
+                    let response = game.handle_request(request).await?;
+                    conn.send_response(response).await?;
+                }
+                Some(ClientMessage::Action(action)) => {
+                    game.handle_action(action, player.id()).await?;
+                }
+            },
+
+            event = player.poll_event() => match event {
+                None => break Err(anyhow!("event channel closed")),
+                Some(event) => {
+                    conn.send_event(event).await?;
+                }
+            },
+
+            else => {}
+        };
+    }
+}
@@ -0,0 +1,325 @@
+//! Keeps data between runs of the server: player profiles, reports of finished matches and a log of
+//! game events.
+//!
+//! Data is stored as blobs under a key within a namespace, so that deployments can choose where it
+//! is kept without the rest of the server knowing about it. Everything the server stores is JSON.
+//! By default every blob is a file in a directory per namespace. With the `sqlite` feature, blobs
+//! may instead be rows in a single SQLite database.
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Player profiles, keyed by nickname.
+pub const PROFILES: &str = "profiles";
+/// Reports of finished matches, keyed by when they ended.
+pub const MATCHES: &str = "matches";
+/// Game events, keyed by when they happened.
+pub const EVENTS: &str = "events";
+
+/// A place to keep namespaced blobs.
+pub trait Storage {
+    /// The blob stored under `key`, if any.
+    fn get(&self, namespace: &str, key: &str) -> crate::Result<Option<Vec<u8>>>;
+
+    /// Store a blob under `key`, replacing any previous blob.
+    fn put(&mut self, namespace: &str, key: &str, value: &[u8]) -> crate::Result<()>;
+
+    /// The keys of all blobs in a namespace, in ascending order.
+    fn list(&self, namespace: &str) -> crate::Result<Vec<String>>;
+}
+
+/// Where to store data, as given on the command line.
+#[derive(Debug, Clone)]
+pub enum Backend {
+    /// A file per blob in a directory per namespace.
+    Files(PathBuf),
+    /// A single SQLite database.
+    #[cfg(feature = "sqlite")]
+    Sqlite(PathBuf),
+}
+
+/// Stores every blob as a file at `<root>/<namespace>/<key>`.
+pub struct FileStorage {
+    root: PathBuf,
+}
+
+/// How a player has fared over all matches they took part in.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    pub nickname: String,
+    pub matches: u32,
+    pub wins: u32,
+    /// The longest the player has survived in a match, in seconds.
+    pub longest_survival: u32,
+    /// When the player last joined, in seconds since the UNIX epoch.
+    pub last_seen: u64,
+}
+
+/// A finished match.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchReport {
+    /// When the match ended, in seconds since the UNIX epoch.
+    pub ended: u64,
+    /// How long the match lasted, in seconds.
+    pub duration: u32,
+    /// Every player that took part, ordered by their placement.
+    pub players: Vec<MatchPlayer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchPlayer {
+    pub nickname: String,
+    pub survived: u32,
+    pub eliminated: bool,
+}
+
+impl Backend {
+    /// Open the storage, creating it if it does not exist.
+    pub fn open(&self) -> crate::Result<Box<dyn Storage>> {
+        match self {
+            Backend::Files(root) => Ok(Box::new(FileStorage::open(root.clone())?)),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(path) => Ok(Box::new(sqlite::SqliteStorage::open(path)?)),
+        }
+    }
+}
+
+impl FileStorage {
+    pub fn open(root: PathBuf) -> crate::Result<FileStorage> {
+        fs::create_dir_all(&root)
+            .with_context(|| format!("failed to create storage at {}", root.display()))?;
+        Ok(FileStorage { root })
+    }
+
+    fn path(&self, namespace: &str, key: &str) -> PathBuf {
+        self.root.join(escape(namespace)).join(escape(key))
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&self, namespace: &str, key: &str) -> crate::Result<Option<Vec<u8>>> {
+        match fs::read(self.path(namespace, key)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put(&mut self, namespace: &str, key: &str, value: &[u8]) -> crate::Result<()> {
+        let path = self.path(namespace, key);
+        fs::create_dir_all(self.root.join(escape(namespace)))?;
+
+        // Write to a temporary file first, so that a crash never leaves a partial blob behind.
+        let temporary = path.with_extension("tmp");
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(value)?;
+        file.sync_all()?;
+        fs::rename(&temporary, &path)?;
+
+        Ok(())
+    }
+
+    fn list(&self, namespace: &str) -> crate::Result<Vec<String>> {
+        let entries = match fs::read_dir(self.root.join(escape(namespace))) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut keys = Vec::new();
+        for entry in entries {
+            let name = entry?.file_name();
+            let name = name.to_string_lossy();
+            if !name.ends_with(".tmp") {
+                keys.push(unescape(&name));
+            }
+        }
+
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+/// Turn a key into a file name. Characters that may not be valid in a file name, or that would
+/// make the name ambiguous, are replaced by `%` and their code point in hex.
+fn escape(key: &str) -> String {
+    let mut escaped = String::with_capacity(key.len());
+    for ch in key.chars() {
+        if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' {
+            escaped.push(ch);
+        } else {
+            escaped += &format!("%{:x};", ch as u32);
+        }
+    }
+    escaped
+}
+
+/// The key a file name was escaped from.
+fn unescape(name: &str) -> String {
+    let mut key = String::with_capacity(name.len());
+    let mut rest = name;
+
+    while let Some(start) = rest.find('%') {
+        key += &rest[..start];
+        let escaped = &rest[start + 1..];
+        let end = escaped.find(';').unwrap_or(escaped.len());
+        let ch = u32::from_str_radix(&escaped[..end], 16)
+            .ok()
+            .and_then(std::char::from_u32)
+            .unwrap_or(std::char::REPLACEMENT_CHARACTER);
+        key.push(ch);
+        rest = escaped.get(end + 1..).unwrap_or("");
+    }
+
+    key + rest
+}
+
+/// Seconds since the UNIX epoch.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// A key that sorts in the order the keys were created, for append-only namespaces.
+pub fn sequential_key(sequence: u64) -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis());
+    format!("{:016}-{:08}", millis, sequence)
+}
+
+/// Deserialize the JSON blob under `key`, if any.
+pub fn load<T>(storage: &dyn Storage, namespace: &str, key: &str) -> crate::Result<Option<T>>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    match storage.get(namespace, key)? {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Serialize a value as JSON and store it under `key`.
+pub fn save<T>(
+    storage: &mut dyn Storage,
+    namespace: &str,
+    key: &str,
+    value: &T,
+) -> crate::Result<()>
+where
+    T: Serialize,
+{
+    let bytes = serde_json::to_vec(value)?;
+    storage.put(namespace, key, &bytes)
+}
+
+impl Profile {
+    /// Count a finished match towards the profile.
+    pub fn record_match(&mut self, won: bool, survived: u32) {
+        self.matches += 1;
+        if won {
+            self.wins += 1;
+        }
+        self.longest_survival = self.longest_survival.max(survived);
+    }
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s.find(':').map(|index| (&s[..index], &s[index + 1..]));
+
+        match split {
+            Some(("file", path)) => Ok(Backend::Files(PathBuf::from(path))),
+            #[cfg(feature = "sqlite")]
+            Some(("sqlite", path)) => Ok(Backend::Sqlite(PathBuf::from(path))),
+            #[cfg(not(feature = "sqlite"))]
+            Some(("sqlite", _)) => Err(anyhow!(
+                "the server was built without SQLite support, enable the `sqlite` feature"
+            )),
+            _ => Err(anyhow!(
+                "expected a storage of the form `file:<directory>` or `sqlite:<path>`"
+            )),
+        }
+    }
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Backend::Files(root) => write!(f, "file:{}", root.display()),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(path) => write!(f, "sqlite:{}", path.display()),
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::Storage;
+    use anyhow::Context;
+    use rusqlite::{params, Connection, OptionalExtension};
+    use std::path::Path;
+
+    /// Stores every blob as a row of a single table.
+    pub struct SqliteStorage {
+        connection: Connection,
+    }
+
+    impl SqliteStorage {
+        pub fn open(path: &Path) -> crate::Result<SqliteStorage> {
+            let connection = Connection::open(path)
+                .with_context(|| format!("failed to open database {}", path.display()))?;
+            connection.execute(
+                "CREATE TABLE IF NOT EXISTS blobs (
+                    namespace TEXT NOT NULL,
+                    key TEXT NOT NULL,
+                    value BLOB NOT NULL,
+                    PRIMARY KEY (namespace, key)
+                )",
+                params![],
+            )?;
+            Ok(SqliteStorage { connection })
+        }
+    }
+
+    impl Storage for SqliteStorage {
+        fn get(&self, namespace: &str, key: &str) -> crate::Result<Option<Vec<u8>>> {
+            let value = self
+                .connection
+                .query_row(
+                    "SELECT value FROM blobs WHERE namespace = ?1 AND key = ?2",
+                    params![namespace, key],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(value)
+        }
+
+        fn put(&mut self, namespace: &str, key: &str, value: &[u8]) -> crate::Result<()> {
+            self.connection.execute(
+                "INSERT OR REPLACE INTO blobs (namespace, key, value) VALUES (?1, ?2, ?3)",
+                params![namespace, key, value],
+            )?;
+            Ok(())
+        }
+
+        fn list(&self, namespace: &str) -> crate::Result<Vec<String>> {
+            let mut statement = self
+                .connection
+                .prepare("SELECT key FROM blobs WHERE namespace = ?1 ORDER BY key")?;
+            let keys = statement
+                .query_map(params![namespace], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok(keys)
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Start an endpoint that echoes every payload back to its sender, and send it a few messages.
+//!
+//! Run with `cargo run --example echo`.
+
+use socket::{Connection, Delivery, Endpoint};
+
+#[tokio::main(basic_scheduler)]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut endpoint = Endpoint::bind("127.0.0.1:0").await?;
+    let addr = endpoint.local_addr().expect("endpoint is not bound");
+
+    tokio::spawn(async move {
+        while let Ok(mut conn) = endpoint.accept().await {
+            tokio::spawn(async move {
+                while let Some(payload) = conn.recv().await {
+                    if conn.send(payload, Delivery::Reliable).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    let mut conn = Connection::connect(addr).await?;
+    for message in &["hello", "snow", "fight"] {
+        conn.send(message.as_bytes().to_vec(), Delivery::Reliable)
+            .await?;
+        if let Some(echo) = conn.recv().await {
+            println!("echo: {}", String::from_utf8_lossy(&echo));
+        }
+    }
+
+    conn.shutdown().await?;
+    Ok(())
+}
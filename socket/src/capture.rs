@@ -0,0 +1,259 @@
+//! Dump sent and received datagrams to a pcapng file for inspection in Wireshark.
+//!
+//! See: https://github.com/pcapng/pcapng
+//!
+//! Every datagram is written as an Enhanced Packet Block on a single interface using the
+//! `LINKTYPE_USER0` link-type, so that a custom dissector can be registered for it. The direction
+//! of the datagram is stored in the `epb_flags` option and the address of the peer as a comment.
+//!
+//! Datagrams are written to the file by a separate thread, so that sockets never wait for the disk.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Reserved link-type for private use, see: https://www.tcpdump.org/linktypes.html
+const LINKTYPE_USER0: u16 = 147;
+
+/// The maximum number of bytes captured from each datagram.
+const SNAP_LENGTH: u32 = 1 << 16;
+
+const SECTION_HEADER_BLOCK: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x0000_0001;
+const ENHANCED_PACKET_BLOCK: u32 = 0x0000_0006;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const OPT_END_OF_OPT: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const OPT_EPB_FLAGS: u16 = 2;
+
+/// The number of datagrams that may wait to be written before any more are dropped.
+const QUEUE_CAPACITY: usize = 4096;
+
+/// Is a capture running? Checked before `CAPTURE` is locked, so that sockets do not contend for
+/// the lock while nothing is captured.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// The capture currently running, if any.
+static CAPTURE: Mutex<Option<Capture>> = Mutex::new(None);
+
+/// In which direction a datagram travelled.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    /// Received from the peer.
+    Inbound,
+    /// Sent to the peer.
+    Outbound,
+}
+
+/// A running capture.
+struct Capture {
+    records: SyncSender<Record>,
+    /// The thread writing the records to the file.
+    writer: JoinHandle<io::Result<()>>,
+}
+
+/// A datagram waiting to be written.
+struct Record {
+    direction: Direction,
+    peer: SocketAddr,
+    /// The captured bytes, which may be fewer than the datagram had.
+    bytes: Vec<u8>,
+    /// The size of the datagram, in bytes.
+    length: usize,
+    /// Microseconds since the Unix epoch.
+    timestamp: u64,
+}
+
+struct CaptureWriter {
+    writer: BufWriter<File>,
+}
+
+/// Start writing all datagrams sent and received by any socket to a pcapng file at `path`.
+/// Replaces any previously started capture.
+pub fn start(path: impl AsRef<Path>) -> io::Result<()> {
+    let writer = CaptureWriter::create(path.as_ref())?;
+    let (records, queue) = mpsc::sync_channel(QUEUE_CAPACITY);
+    let writer = thread::Builder::new()
+        .name("packet capture".to_owned())
+        .spawn(move || writer.write_records(queue))?;
+
+    let previous = CAPTURE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .replace(Capture { records, writer });
+    ACTIVE.store(true, Ordering::Relaxed);
+
+    if let Some(previous) = previous {
+        previous.finish()?;
+    }
+    Ok(())
+}
+
+/// Stop the current capture, once every datagram recorded so far has been written to disk.
+pub fn stop() -> io::Result<()> {
+    ACTIVE.store(false, Ordering::Relaxed);
+    let capture = CAPTURE.lock().unwrap_or_else(|e| e.into_inner()).take();
+    match capture {
+        Some(capture) => capture.finish(),
+        None => Ok(()),
+    }
+}
+
+/// Record a datagram in the current capture, if one was started.
+pub(crate) fn record(direction: Direction, peer: SocketAddr, bytes: &[u8]) {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+
+    // timestamps use the default resolution of microseconds
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_micros() as u64)
+        .unwrap_or(0);
+
+    let record = Record {
+        direction,
+        peer,
+        bytes: bytes[..usize::min(bytes.len(), SNAP_LENGTH as usize)].to_vec(),
+        length: bytes.len(),
+        timestamp,
+    };
+
+    let capture = CAPTURE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(capture) = capture.as_ref() {
+        match capture.records.try_send(record) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => log::warn!("packet capture is falling behind"),
+            // the writer reports why it stopped once the capture is stopped
+            Err(TrySendError::Disconnected(_)) => ACTIVE.store(false, Ordering::Relaxed),
+        }
+    }
+}
+
+impl Capture {
+    /// Wait until every record has been written, and the file flushed.
+    fn finish(self) -> io::Result<()> {
+        drop(self.records);
+        self.writer
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("the capture thread panicked")))
+    }
+}
+
+impl CaptureWriter {
+    fn create(path: &Path) -> io::Result<CaptureWriter> {
+        let file = File::create(path)?;
+        let mut capture = CaptureWriter {
+            writer: BufWriter::new(file),
+        };
+
+        capture.write_section_header()?;
+        capture.write_interface_description()?;
+        capture.writer.flush()?;
+
+        Ok(capture)
+    }
+
+    /// Write records until the capture is stopped, flushing whenever there are none waiting.
+    fn write_records(mut self, queue: Receiver<Record>) -> io::Result<()> {
+        loop {
+            let record = match queue.try_recv() {
+                Ok(record) => record,
+                Err(TryRecvError::Disconnected) => break,
+                Err(TryRecvError::Empty) => {
+                    self.writer.flush()?;
+                    match queue.recv() {
+                        Ok(record) => record,
+                        Err(_) => break,
+                    }
+                }
+            };
+
+            if let Err(e) = self.write_packet(record) {
+                log::error!("failed to write packet capture, stopping capture: {:#}", e);
+                return Err(e);
+            }
+        }
+
+        self.writer.flush()
+    }
+
+    fn write_section_header(&mut self) -> io::Result<()> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        // the length of the section is not specified
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        self.write_block(SECTION_HEADER_BLOCK, &body)
+    }
+
+    fn write_interface_description(&mut self) -> io::Result<()> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&LINKTYPE_USER0.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&SNAP_LENGTH.to_le_bytes());
+        self.write_block(INTERFACE_DESCRIPTION_BLOCK, &body)
+    }
+
+    fn write_packet(&mut self, record: Record) -> io::Result<()> {
+        let Record {
+            direction,
+            peer,
+            bytes: captured,
+            length,
+            timestamp,
+        } = record;
+
+        let mut body = Vec::with_capacity(32 + captured.len());
+        body.extend_from_slice(&0u32.to_le_bytes());
+        body.extend_from_slice(&((timestamp >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(timestamp as u32).to_le_bytes());
+        body.extend_from_slice(&(captured.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(length as u32).to_le_bytes());
+        body.extend_from_slice(&captured);
+        pad(&mut body);
+
+        let flags: u32 = match direction {
+            Direction::Inbound => 0b01,
+            Direction::Outbound => 0b10,
+        };
+        write_option(&mut body, OPT_EPB_FLAGS, &flags.to_le_bytes());
+        write_option(&mut body, OPT_COMMENT, peer.to_string().as_bytes());
+        write_option(&mut body, OPT_END_OF_OPT, &[]);
+
+        self.write_block(ENHANCED_PACKET_BLOCK, &body)
+    }
+
+    /// Write a block with the given type, surrounding the body with its total length.
+    fn write_block(&mut self, block_type: u32, body: &[u8]) -> io::Result<()> {
+        let total_length = (12 + body.len()) as u32;
+        self.writer.write_all(&block_type.to_le_bytes())?;
+        self.writer.write_all(&total_length.to_le_bytes())?;
+        self.writer.write_all(body)?;
+        self.writer.write_all(&total_length.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+/// Append an option to a block body.
+fn write_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    pad(body);
+}
+
+/// Pad the bytes to a 32-bit boundary.
+fn pad(bytes: &mut Vec<u8>) {
+    let padding = (4 - bytes.len() % 4) % 4;
+    bytes.resize(bytes.len() + padding, 0);
+}
@@ -0,0 +1,91 @@
+//! Limit the number of unacknowledged bytes, so that a burst of reliable payloads does not flood a
+//! lossy link.
+//!
+//! The congestion window works like TCP Reno: it starts small and doubles every round-trip (slow
+//! start) until it reaches a threshold, after which it grows by about one packet per round-trip.
+//! When a packet has to be retransmitted, the link is assumed to be congested, so the threshold
+//! becomes half the window and the window shrinks to the threshold. Packets that were already in
+//! flight when the window shrank do not shrink it again, so a single burst of losses only counts
+//! once.
+
+use crate::packet::MAX_PACKET_SIZE;
+
+/// The size of the window of a new connection, in bytes.
+const INITIAL_WINDOW: usize = 10 * MAX_PACKET_SIZE;
+
+/// The smallest the window may shrink to, in bytes.
+const MIN_WINDOW: usize = 2 * MAX_PACKET_SIZE;
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct CongestionWindow {
+    /// The number of bytes that may be in flight.
+    window: usize,
+    /// The window size at which slow start ends.
+    threshold: usize,
+    /// The number of bytes sent, but not yet acknowledged or given up on.
+    in_flight: usize,
+    /// Incremented every time the window shrinks.
+    epoch: u64,
+}
+
+impl Default for CongestionWindow {
+    fn default() -> Self {
+        CongestionWindow {
+            window: INITIAL_WINDOW,
+            threshold: usize::MAX,
+            in_flight: 0,
+            epoch: 0,
+        }
+    }
+}
+
+impl CongestionWindow {
+    /// The number of bytes that may be in flight.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// The number of bytes sent, but not yet acknowledged.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// The number of times the window has shrunk. Losses of packets sent during an earlier epoch
+    /// do not shrink the window.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Is there room in the window for a packet of `size` bytes? A packet always fits if nothing
+    /// else is in flight.
+    pub fn has_room(&self, size: usize) -> bool {
+        self.in_flight == 0 || self.in_flight + size <= self.window
+    }
+
+    /// A packet of `size` bytes was sent.
+    pub fn sent(&mut self, size: usize) {
+        self.in_flight += size;
+    }
+
+    /// A packet of `size` bytes was acknowledged.
+    pub fn acknowledged(&mut self, size: usize) {
+        self.in_flight = self.in_flight.saturating_sub(size);
+
+        if self.window < self.threshold {
+            self.window += size;
+        } else {
+            self.window += (MAX_PACKET_SIZE * size / self.window).max(1);
+        }
+    }
+
+    /// A packet sent during `epoch` had to be retransmitted.
+    pub fn lost(&mut self, epoch: u64) {
+        if epoch != self.epoch {
+            return;
+        }
+
+        self.threshold = (self.window / 2).max(MIN_WINDOW);
+        self.window = self.threshold;
+        self.epoch += 1;
+    }
+}
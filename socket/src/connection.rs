@@ -2,84 +2,256 @@
 
 use futures::stream::StreamExt;
 use rand::Rng;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use thiserror::Error;
 use tokio::sync::mpsc;
 use tokio::task;
-use tokio::time::{self, delay_queue::Key, DelayQueue, Duration};
+use tokio::time::{self, delay_queue::Key, DelayQueue, Duration, Instant};
 
+pub use self::serialize::Error as DeserializeError;
 use self::serialize::{FromRawPacket, IntoRawPacket};
-use crate::packet::{self, Flags, Header, PacketId, Sequence};
-
-/// The number of sequences to buffer on in the receive buffer.
-const SEQUENCE_BUFFER_SIZE: usize = 1024;
-
-/// How long to wait before attempting to retransmit a packet.
-const RETRANSMIT_DELAY: Duration = Duration::from_millis(100);
-
-/// How long to wait for a response before closing the connection.
-const CONNECTION_TIMEOUT: Duration = Duration::from_secs(15);
+use crate::congestion::CongestionWindow;
+use crate::crypto::{self, Cipher, KeyExchange};
+use crate::mtu::{self, PathMtu};
+use crate::packet::{self, Flags, Header, PacketId, Refusal, Sequence};
+use crate::rtt::RttEstimator;
+use crate::shutdown::{self, Shutdown, ShutdownTrigger};
+use crate::SimulatedConditions;
+
+/// The maximum number of bytes set aside for incomplete sequences in the receive buffer, and for
+/// ordered payloads waiting for an earlier one. Reliable sequences are acknowledged chunk by chunk,
+/// so they may not be discarded and are counted as the largest payload until they are complete.
+/// When exceeded, the least recently updated incomplete unreliable sequences are discarded, and new
+/// reliable sequences are dropped without being acknowledged, so that the peer retransmits them
+/// once there is room. The sequences that the waiting payloads may wait for are let in until twice
+/// the limit is reached, since the waiting payloads are only passed on once they arrive.
+const MAX_REASSEMBLY_MEMORY: usize = 8 * packet::MAX_PAYLOAD_SIZE;
+
+/// The maximum number of ordered payloads waiting for an earlier one. When reached, new ordered
+/// sequences are dropped without being acknowledged, unless the waiting payloads may wait for them.
+const MAX_REORDERED_PAYLOADS: usize = 256;
+
+/// Compress outgoing payloads of at least this many bytes, if that makes them smaller. Incoming
+/// payloads are decompressed if they are compressed, regardless of this setting.
+const COMPRESSION_THRESHOLD: usize = 128;
+
+/// Append a checksum to every outgoing payload. Incoming payloads are verified if they carry a
+/// checksum, regardless of this setting.
+const CHECKSUM_PAYLOADS: bool = true;
 
 type RawPacket = Vec<u8>;
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// Errors that occur while a connection is running.
 #[derive(Debug, Clone, Error)]
 pub enum Error {
+    /// The connection was closed, either by us or the peer.
     #[error("connection closed")]
     Closed,
 
+    /// The peer did not send anything for too long.
     #[error("the connection timed out")]
     Timeout,
 
+    /// The task driving the connection panicked or was cancelled.
     #[error("an error occured when closing connection")]
     Shutdown,
 
+    /// The payload was too large to be sent.
     #[error("failed to split payload")]
     SplitPayload(#[source] crate::packet::Error),
 
+    /// The peer sent a malformed packet.
     #[error("failed to reconstruct payload")]
     ReconstructPayload(#[source] crate::packet::Error),
 
+    /// A payload did not match its checksum, or could not be decompressed.
+    #[error("received a corrupted payload")]
+    CorruptedPayload(#[source] crate::packet::Error),
+
+    /// A payload could not be decrypted.
+    #[error("failed to decrypt payload")]
+    DecryptPayload(#[source] crate::crypto::Error),
+
+    /// The peer sent a malformed handshake packet.
     #[error("failed to deserialize packet")]
     Deserialize(#[from] self::serialize::Error),
 
+    /// The peer failed the handshake.
     #[error("client did not respond correctly to the challenge")]
     InvalidChallengeResponse,
+
+    /// Encryption was required, but the peer did not agree to encrypt payloads.
+    #[error("the peer did not agree to encrypt payloads")]
+    Unencrypted,
+
+    /// The peer did not acknowledge the reliable payloads in flight, or that the connection was
+    /// closed, before the linger period passed.
+    #[error("the connection was not closed cleanly")]
+    Unacknowledged,
+
+    /// The server refused the connection, since it has too many already.
+    #[error("the server is full")]
+    ServerFull,
+}
+
+/// Options for the connections made over a socket.
+#[derive(Debug, Copy, Clone)]
+pub struct ConnectionConfig {
+    /// How long to wait without hearing from the peer before closing the connection. Endpoints
+    /// also give clients this long to complete the handshake.
+    pub timeout: Duration,
+    /// How long to wait without sending anything before sending a heartbeat, so that the peer does
+    /// not time out an idle connection. Should be well below the peer's `timeout`.
+    pub heartbeat_interval: Duration,
+    /// How long to wait for an acknowledgement before retransmitting a packet, until the
+    /// round-trip time has been measured.
+    pub initial_retransmit_delay: Duration,
+    /// The number of payloads, and packets, that may be queued between a connection and the tasks
+    /// serving it before the sender has to wait.
+    pub channel_capacity: usize,
+    /// How long closing the connection may wait for the reliable payloads in flight to be
+    /// acknowledged, and then for the peer to acknowledge that the connection is closed.
+    pub linger: Duration,
+    /// The number of consecutive sequences buffered while they are reassembled. A sequence this far
+    /// ahead of the oldest buffered one discards it, whether it is complete or not.
+    pub sequence_buffer_size: usize,
+    /// The number of payloads an encrypted connection seals with the same keys before it asks the
+    /// peer for new ones.
+    pub rekey_payloads: u64,
+    /// How long an encrypted connection uses the same keys before it asks the peer for new ones.
+    pub rekey_interval: Duration,
+    /// Only establish connections whose payloads are encrypted. Endpoints always accept encrypted
+    /// connections, but clients only ask for encryption if this is set.
+    pub require_encryption: bool,
+    /// How long packets smaller than the path MTU may be held back, so that several of them can be
+    /// sent in a single datagram. If `None`, every packet is sent right away. Held back packets are
+    /// also sent by `Connection::flush`, so a long window combined with a flush every tick
+    /// coalesces the payloads of each tick.
+    pub coalesce_window: Option<Duration>,
 }
 
 pub(crate) struct ConnectionEnv {
     pub(crate) peer_addr: SocketAddr,
     pub(crate) packet_rx: mpsc::Receiver<RawPacket>,
     pub(crate) packet_tx: mpsc::Sender<RawPacket>,
+    pub(crate) stats: Arc<SharedStats>,
+    /// Stops all tasks serving the connection once the connection is dropped.
+    pub(crate) shutdown: ShutdownTrigger,
+    pub(crate) config: ConnectionConfig,
+    /// The conditions simulated for the connection, on top of those of its socket.
+    pub(crate) conditions: SimulatedConditions,
+    /// The socket forbids fragmentation, so larger packets may be probed for, see `mtu`.
+    pub(crate) probe_mtu: bool,
 }
 
+/// A reliable connection to a peer, over which payloads of up to `MAX_PAYLOAD_SIZE` bytes, less a
+/// few bytes of overhead, may be sent and received.
 pub struct Connection {
     peer_addr: SocketAddr,
     payload_rx: mpsc::Receiver<IncomingPayload>,
-    payload_tx: mpsc::Sender<OutgoingPayload>,
+    payload_tx: mpsc::Sender<Outgoing>,
     driver: task::JoinHandle<Result<()>>,
+    stats: Arc<SharedStats>,
+    encrypted: bool,
+    #[cfg_attr(not(feature = "simulation"), allow(dead_code))]
+    conditions: SimulatedConditions,
+    /// Cancels the driver and the socket tasks when the connection is dropped.
+    #[allow(dead_code)]
+    shutdown: ShutdownTrigger,
+}
+
+/// Statistics about the packets sent over a connection.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Stats {
+    /// Number of received sequences that were discarded because their checksum did not match, or
+    /// because they could not be decrypted or decompressed.
+    pub corrupted_sequences: u64,
+    /// Number of packets that could not be sent by the socket, even after retrying.
+    pub send_failures: u64,
+    /// Number of incomplete unreliable sequences that were discarded to stay within the reassembly
+    /// memory limit.
+    pub evicted_sequences: u64,
+    /// Number of bytes currently buffered for incomplete sequences, and for ordered payloads
+    /// waiting for an earlier one.
+    pub reassembly_bytes: u64,
+    /// Number of bytes of reliable packets that may be unacknowledged at once.
+    pub congestion_window: u64,
+    /// Number of bytes of reliable packets sent, but not yet acknowledged.
+    pub bytes_in_flight: u64,
+    /// Size of the largest packet known to reach the peer, in bytes.
+    pub path_mtu: u64,
+    /// Number of times an encrypted connection has replaced its keys.
+    pub key_exchanges: u64,
+    /// Number of heartbeats sent to keep the connection alive while idle.
+    pub heartbeats_sent: u64,
 }
 
+/// Statistics that are updated by the connection and its socket while they are running.
+#[derive(Debug, Default)]
+pub(crate) struct SharedStats {
+    pub(crate) corrupted_sequences: AtomicU64,
+    pub(crate) send_failures: AtomicU64,
+    pub(crate) evicted_sequences: AtomicU64,
+    pub(crate) reassembly_bytes: AtomicU64,
+    pub(crate) congestion_window: AtomicU64,
+    pub(crate) bytes_in_flight: AtomicU64,
+    pub(crate) path_mtu: AtomicU64,
+    pub(crate) key_exchanges: AtomicU64,
+    pub(crate) heartbeats_sent: AtomicU64,
+    /// The smoothed round-trip time in microseconds, or zero if it has not been measured.
+    pub(crate) rtt_micros: AtomicU64,
+}
+
+/// How a payload is delivered to the peer.
 #[derive(Debug, Copy, Clone)]
 pub enum Delivery {
-    /// Guarantee that the data arrives in the same order as it was sent.
+    /// Guarantee that the data arrives, but not that payloads arrive in the order they were sent.
     Reliable,
 
+    /// Guarantee that the data arrives, and that payloads sent this way arrive in the order they
+    /// were sent. A payload that arrives early is held back until the ones before it have arrived.
+    /// Ordered payloads may be 2 bytes shorter than other payloads.
+    ReliableOrdered,
+
     /// Send the packet once. Use when the payload should arrive as soon as possible, but dropping
     /// it has no consequence.
     BestEffort,
+
+    /// Send the packet once, and drop it if a payload sent this way after it has already arrived.
+    /// Use when only the latest payload matters, such as snapshots of the world. Sequenced payloads
+    /// may be 2 bytes shorter than other payloads.
+    BestEffortSequenced,
+}
+
+impl Delivery {
+    /// The flags set on every chunk of payloads delivered this way.
+    fn flags(self) -> Flags {
+        match self {
+            Delivery::Reliable => Flags::NEEDS_ACK,
+            Delivery::ReliableOrdered => Flags::NEEDS_ACK | Flags::ORDERED,
+            Delivery::BestEffort => Flags::empty(),
+            Delivery::BestEffortSequenced => Flags::SEQUENCED,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
 struct Init {
     salt: u32,
+    /// The public key of the client, if it wants payloads to be encrypted.
+    public_key: Option<[u8; crypto::PUBLIC_KEY_SIZE]>,
 }
 
 #[derive(Debug, Copy, Clone)]
 struct Challenge {
     pepper: u32,
+    /// The public key of the server, if the client asked for payloads to be encrypted.
+    public_key: Option<[u8; crypto::PUBLIC_KEY_SIZE]>,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -89,29 +261,86 @@ struct ChallengeResponse {
 
 pub(crate) struct OutgoingPayload {
     bytes: Vec<u8>,
-    needs_ack: bool,
+    delivery: Delivery,
+}
+
+/// Requests from a connection to the task driving it.
+pub(crate) enum Outgoing {
+    Payload(OutgoingPayload),
+    /// Send the packets held back to be coalesced.
+    Flush,
 }
 
 pub(crate) struct IncomingPayload {
     bytes: Vec<u8>,
+    /// The sequence the payload was sent in.
+    sequence: u16,
+    /// How the bytes were packed by the peer, such as whether they have to be decompressed.
+    flags: Flags,
 }
 
 struct Responder {
     packet_tx: mpsc::Sender<RawPacket>,
     packet_rx: mpsc::Receiver<RawPacket>,
     payload_tx: mpsc::Sender<IncomingPayload>,
-    payload_rx: mpsc::Receiver<OutgoingPayload>,
+    payload_rx: mpsc::Receiver<Outgoing>,
 
     sequences: SequenceBuilder,
+    reorder: ReorderBuffer,
+    /// The position of the newest sequenced payload passed on.
+    newest_sequenced: Option<u16>,
     transmit: TransmitQueue,
+    mtu: PathMtu,
+    /// Encrypts and decrypts payloads, if the peers agreed to do so.
+    cipher: Option<Cipher>,
+    /// When to ask the peer for new keys, if the current ones have not been replaced by then.
+    rekey_timer: time::Delay,
+    /// When to send the next probe for the path MTU.
+    probe_timer: time::Delay,
+    /// When to send a heartbeat, reset every time a packet is sent.
+    heartbeat_timer: time::Delay,
+    config: ConnectionConfig,
+    /// Small packets held back to be sent together in a single batch.
+    batch: Vec<RawPacket>,
+    /// The size of the batch the held back packets would be sent in, in bytes.
+    batch_size: usize,
+    /// When to send the held back packets, set when the first one is held back.
+    batch_timer: time::Delay,
+    closing: Closing,
+    /// When to stop waiting for the peer, set once the connection starts closing.
+    linger_timer: time::Delay,
+    /// When to ask the peer to close the connection again.
+    close_timer: time::Delay,
+    stats: Arc<SharedStats>,
+    shutdown: Shutdown,
+}
+
+/// How far the connection has come in closing.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Closing {
+    /// Payloads are still being sent.
+    Open,
+    /// Every payload has been transmitted, and the reliable ones are waiting to be acknowledged.
+    Lingering,
+    /// The peer has been asked to close the connection this many times, but has not acknowledged
+    /// it yet.
+    Sent(u32),
 }
 
 struct SequenceBuilder {
     /// The sequence contained in each slot.
-    slots: [Slot; SEQUENCE_BUFFER_SIZE],
+    slots: Vec<Slot>,
 
     /// The first sequence that occupies as slot.
     start: u16,
+
+    /// The number of bytes buffered by all incomplete sequences.
+    memory: usize,
+
+    /// Incremented every time a chunk is inserted, used to find the least recently updated slot.
+    clock: u64,
+
+    stats: Arc<SharedStats>,
 }
 
 #[derive(Clone, Default)]
@@ -124,12 +353,51 @@ struct Slot {
 
     /// Is the sequence complete?
     complete: bool,
+
+    /// Have chunks of the sequence been acknowledged? Such sequences are never evicted.
+    reliable: bool,
+
+    /// The value of the builder's clock when a chunk was last inserted.
+    last_used: u64,
+}
+
+/// Ordered payloads that arrived before one sent ahead of them.
+#[derive(Default)]
+struct ReorderBuffer {
+    /// The position of the next ordered payload to pass on.
+    next: u16,
+    /// The payloads waiting for an earlier one, by position.
+    waiting: HashMap<u16, IncomingPayload>,
+    /// The number of bytes in the waiting payloads.
+    memory: usize,
 }
 
 struct TransmitQueue {
     packets: DelayQueue<(PacketId, RawPacket)>,
-    keys: HashMap<PacketId, Key>,
+    in_flight: HashMap<PacketId, InFlight>,
+    /// Reliable packets waiting for room in the congestion window.
+    backlog: VecDeque<(PacketId, RawPacket)>,
     next_sequence: u16,
+    /// The position of the next ordered payload.
+    next_ordered: u16,
+    /// The position of the next sequenced payload.
+    next_sequenced: u16,
+    rtt: RttEstimator,
+    congestion: CongestionWindow,
+}
+
+/// A packet that has been sent, but not yet acknowledged.
+struct InFlight {
+    /// The key of the packet in the retransmission queue.
+    key: Key,
+    /// When the packet was first sent.
+    sent: Instant,
+    /// The number of times the packet has been retransmitted.
+    retransmits: u32,
+    /// The size of the packet in bytes.
+    size: usize,
+    /// The epoch of the congestion window when the packet was sent.
+    epoch: u64,
 }
 
 impl Connection {
@@ -138,58 +406,159 @@ impl Connection {
     pub(crate) async fn accept(mut env: ConnectionEnv) -> Result<Connection> {
         let init = env.recv::<Init>().await?;
 
-        let challenge = Challenge::new();
+        let exchange = init.public_key.map(|_| KeyExchange::new());
+        if exchange.is_none() && env.config.require_encryption {
+            return Err(Error::Unencrypted);
+        }
+
+        let challenge = Challenge::new(exchange.as_ref().map(KeyExchange::public_key));
         env.send(challenge).await?;
 
         let response = env.recv::<ChallengeResponse>().await?;
 
-        if Self::valid_resposne(init, challenge, response) {
-            Ok(Self::spawn(env))
-        } else {
-            Err(Error::InvalidChallengeResponse)
+        if !Self::valid_resposne(init, challenge, response) {
+            return Err(Error::InvalidChallengeResponse);
         }
+
+        let cipher = match (exchange, init.public_key) {
+            (Some(exchange), Some(key)) => {
+                Some(exchange.finish(key, key_salt(init, challenge), false))
+            }
+            _ => None,
+        };
+
+        Ok(Self::spawn(env, cipher))
     }
 
     /// Establish a new connection.
     #[allow(dead_code)]
     pub(crate) async fn establish(mut env: ConnectionEnv) -> Result<Connection> {
-        let init = Init::new();
+        let exchange = if env.config.require_encryption {
+            Some(KeyExchange::new())
+        } else {
+            None
+        };
+
+        let init = Init::new(exchange.as_ref().map(KeyExchange::public_key));
         env.send(init).await?;
 
-        let challenge = env.recv::<Challenge>().await?;
+        let challenge = env.recv_packet().await?;
+        if let Some(Refusal::Full) = packet::parse_refusal(&challenge) {
+            return Err(Error::ServerFull);
+        }
+        let challenge = Challenge::deserialize(&challenge)?;
+
+        let cipher = match (exchange, challenge.public_key) {
+            (Some(exchange), Some(key)) => {
+                Some(exchange.finish(key, key_salt(init, challenge), true))
+            }
+            (Some(_), None) => return Err(Error::Unencrypted),
+            (None, _) => None,
+        };
 
         let response = ChallengeResponse::new(init, challenge);
         env.send(response).await?;
 
-        Ok(Self::spawn(env))
+        Ok(Self::spawn(env, cipher))
     }
 
+    /// The address of the peer.
     pub fn peer_addr(&self) -> SocketAddr {
         self.peer_addr
     }
 
-    /// Send a payload.
-    pub async fn send(&mut self, bytes: Vec<u8>, delivery: Delivery) -> Result<()> {
-        let needs_ack = match delivery {
-            Delivery::Reliable => true,
-            Delivery::BestEffort => false,
+    /// Are payloads encrypted? Encrypted payloads are slightly shorter than unencrypted ones, see
+    /// `max_payload_size`.
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    /// The largest payload that may be sent with `delivery`: `MAX_PAYLOAD_SIZE`, less the bytes
+    /// added to the payload before it is split into packets. Payloads are limited before they are
+    /// compressed, since the peer could not decompress larger ones.
+    pub fn max_payload_size(&self, delivery: Delivery) -> usize {
+        let position = match delivery {
+            Delivery::ReliableOrdered | Delivery::BestEffortSequenced => packet::POSITION_SIZE,
+            Delivery::Reliable | Delivery::BestEffort => 0,
+        };
+        let integrity = if self.encrypted {
+            crypto::OVERHEAD
+        } else if CHECKSUM_PAYLOADS {
+            packet::CHECKSUM_SIZE
+        } else {
+            0
         };
+        packet::MAX_PAYLOAD_SIZE - position - integrity
+    }
+
+    /// Send a payload. Fails without closing the connection if the payload is larger than
+    /// `max_payload_size`.
+    pub async fn send(&mut self, bytes: Vec<u8>, delivery: Delivery) -> Result<()> {
+        if bytes.len() > self.max_payload_size(delivery) {
+            return Err(Error::SplitPayload(packet::Error::PayloadLimitExceeded));
+        }
+
+        let payload = OutgoingPayload { bytes, delivery };
 
-        let payload = OutgoingPayload { bytes, needs_ack };
+        self.payload_tx
+            .send(Outgoing::Payload(payload))
+            .await
+            .map_err(|_| Error::Closed)
+    }
 
+    /// Send the packets held back to be coalesced with others right away, instead of once the
+    /// coalescing window has passed. Flushing once per tick sends the payloads of a tick in as
+    /// few packets as possible.
+    pub async fn flush(&mut self) -> Result<()> {
         self.payload_tx
-            .send(payload)
+            .send(Outgoing::Flush)
             .await
             .map_err(|_| Error::Closed)
     }
 
+    /// Get the statistics of the connection so far.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            corrupted_sequences: self.stats.corrupted_sequences.load(Ordering::Relaxed),
+            send_failures: self.stats.send_failures.load(Ordering::Relaxed),
+            evicted_sequences: self.stats.evicted_sequences.load(Ordering::Relaxed),
+            reassembly_bytes: self.stats.reassembly_bytes.load(Ordering::Relaxed),
+            congestion_window: self.stats.congestion_window.load(Ordering::Relaxed),
+            bytes_in_flight: self.stats.bytes_in_flight.load(Ordering::Relaxed),
+            path_mtu: self.stats.path_mtu.load(Ordering::Relaxed),
+            key_exchanges: self.stats.key_exchanges.load(Ordering::Relaxed),
+            heartbeats_sent: self.stats.heartbeats_sent.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The smoothed round-trip time to the peer, measured from the acknowledgements of reliable
+    /// payloads. `None` until the first acknowledgement has arrived.
+    pub fn rtt(&self) -> Option<Duration> {
+        match self.stats.rtt_micros.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros)),
+        }
+    }
+
+    /// Simulate network conditions for the datagrams received by this connection, on top of those
+    /// of its endpoint. For connections made with `connect`, these are added to the process-wide
+    /// conditions set by `simulation::set_conditions`. If `None`, only the conditions of the
+    /// socket apply.
+    #[cfg(feature = "simulation")]
+    pub fn set_conditions(&self, conditions: Option<crate::simulation::Conditions>) {
+        self.conditions.set(conditions);
+    }
+
     /// Recv a payload
     pub async fn recv(&mut self) -> Option<Vec<u8>> {
         let payload = self.payload_rx.recv().await?;
         Some(payload.bytes)
     }
 
-    /// Close the connection
+    /// Close the connection, after all payloads passed to `send` have been transmitted and the
+    /// reliable ones acknowledged. Fails with `Error::Unacknowledged` if that, or the peer
+    /// acknowledging the close, takes longer than the linger period. Dropping the connection
+    /// instead closes it immediately, discarding any payloads not yet transmitted.
     pub async fn shutdown(self) -> Result<()> {
         drop(self.payload_rx);
         drop(self.payload_tx);
@@ -204,28 +573,60 @@ impl Connection {
         expected.seasoning == response.seasoning
     }
 
-    fn spawn(env: ConnectionEnv) -> Connection {
-        let (outgoing_tx, outgoing_rx) = mpsc::channel(16);
-        let (incoming_tx, incoming_rx) = mpsc::channel(16);
+    fn spawn(env: ConnectionEnv, cipher: Option<Cipher>) -> Connection {
+        let config = env.config;
+        let (outgoing_tx, outgoing_rx) = mpsc::channel(config.channel_capacity);
+        let (incoming_tx, incoming_rx) = mpsc::channel(config.channel_capacity);
+
+        let stats = env.stats;
 
         let sequences = SequenceBuilder {
-            slots: arr![Slot::default(); SEQUENCE_BUFFER_SIZE],
+            slots: vec![Slot::default(); config.sequence_buffer_size.max(1)],
             start: 0,
+            memory: 0,
+            clock: 0,
+            stats: stats.clone(),
         };
 
         let transmit = TransmitQueue {
             packets: DelayQueue::new(),
-            keys: HashMap::new(),
+            in_flight: HashMap::new(),
+            backlog: VecDeque::new(),
             next_sequence: 0,
+            next_ordered: 0,
+            next_sequenced: 0,
+            rtt: RttEstimator::new(config.initial_retransmit_delay),
+            congestion: CongestionWindow::default(),
         };
 
+        let encrypted = cipher.is_some();
         let responder = Responder {
             packet_tx: env.packet_tx,
             packet_rx: env.packet_rx,
             payload_tx: incoming_tx,
             payload_rx: outgoing_rx,
             sequences,
+            reorder: ReorderBuffer::default(),
+            newest_sequenced: None,
             transmit,
+            mtu: if env.probe_mtu {
+                PathMtu::default()
+            } else {
+                PathMtu::fixed()
+            },
+            cipher,
+            rekey_timer: time::delay_for(config.rekey_interval),
+            probe_timer: time::delay_for(Duration::from_millis(0)),
+            heartbeat_timer: time::delay_for(config.heartbeat_interval),
+            config,
+            batch: Vec::new(),
+            batch_size: packet::HEADER_SIZE,
+            batch_timer: time::delay_for(Duration::from_millis(0)),
+            closing: Closing::Open,
+            linger_timer: time::delay_for(config.linger),
+            close_timer: time::delay_for(Duration::from_millis(0)),
+            stats: stats.clone(),
+            shutdown: env.shutdown.token(),
         };
 
         let driver = tokio::spawn(responder.handle_packets());
@@ -235,6 +636,10 @@ impl Connection {
             payload_tx: outgoing_tx,
             payload_rx: incoming_rx,
             driver,
+            stats,
+            encrypted,
+            conditions: env.conditions,
+            shutdown: env.shutdown,
         }
     }
 }
@@ -266,21 +671,36 @@ impl ConnectionEnv {
 }
 
 impl Init {
-    pub fn new() -> Init {
+    pub fn new(public_key: Option<[u8; crypto::PUBLIC_KEY_SIZE]>) -> Init {
         let mut rng = rand::thread_rng();
-        let salt = rng.gen();
-        Init { salt }
+        // the first byte is where packets keep their flags, see `is_handshake`
+        let salt = rng.gen::<u32>() & 0x00ff_ffff;
+        Init { salt, public_key }
     }
 }
 
+/// Could the packet start a handshake? The first byte of an `Init` is where packets keep their
+/// flags, and is always empty, which it never is for a packet of an established connection.
+pub(crate) fn is_handshake(packet: &[u8]) -> bool {
+    packet.first() == Some(&0) && Init::deserialize(packet).is_ok()
+}
+
 impl Challenge {
-    pub fn new() -> Challenge {
+    pub fn new(public_key: Option<[u8; crypto::PUBLIC_KEY_SIZE]>) -> Challenge {
         let mut rng = rand::thread_rng();
         let pepper = rng.gen();
-        Challenge { pepper }
+        Challenge { pepper, public_key }
     }
 }
 
+/// The salt of the keys derived for a connection, which is unique to its handshake.
+fn key_salt(init: Init, challenge: Challenge) -> [u8; 8] {
+    let mut salt = [0; 8];
+    salt[..4].copy_from_slice(&init.salt.to_be_bytes());
+    salt[4..].copy_from_slice(&challenge.pepper.to_be_bytes());
+    salt
+}
+
 impl ChallengeResponse {
     pub fn new(init: Init, challenge: Challenge) -> ChallengeResponse {
         ChallengeResponse {
@@ -293,8 +713,10 @@ mod serialize {
     use super::*;
     use std::convert::TryInto;
 
+    /// Errors that occur when reading a handshake packet.
     #[derive(Debug, Clone, Error)]
     pub enum Error {
+        /// The packet ended before the entire message was read.
         #[error("unexpected end of packet")]
         Eof,
     }
@@ -324,10 +746,27 @@ mod serialize {
         bytes.extend_from_slice(&value.to_be_bytes());
     }
 
+    /// Read a public key, if there is one. Peers that do not want payloads to be encrypted leave
+    /// it out.
+    fn read_public_key(bytes: &[u8]) -> Result<Option<[u8; crypto::PUBLIC_KEY_SIZE]>> {
+        match bytes.len() {
+            0 => Ok(None),
+            crypto::PUBLIC_KEY_SIZE => Ok(Some(bytes.try_into().unwrap())),
+            _ => Err(Error::Eof),
+        }
+    }
+
+    fn write_public_key(bytes: &mut Vec<u8>, key: Option<[u8; crypto::PUBLIC_KEY_SIZE]>) {
+        if let Some(key) = key {
+            bytes.extend_from_slice(&key);
+        }
+    }
+
     impl FromRawPacket for Init {
         fn deserialize(bytes: &[u8]) -> Result<Self> {
-            let (salt, _) = read_u32(bytes)?;
-            Ok(Init { salt })
+            let (salt, bytes) = read_u32(bytes)?;
+            let public_key = read_public_key(bytes)?;
+            Ok(Init { salt, public_key })
         }
     }
 
@@ -335,14 +774,16 @@ mod serialize {
         fn serialize(&self) -> RawPacket {
             let mut bytes = Vec::new();
             write_u32(&mut bytes, self.salt);
+            write_public_key(&mut bytes, self.public_key);
             bytes
         }
     }
 
     impl FromRawPacket for Challenge {
         fn deserialize(bytes: &[u8]) -> Result<Self> {
-            let (pepper, _) = read_u32(bytes)?;
-            Ok(Challenge { pepper })
+            let (pepper, bytes) = read_u32(bytes)?;
+            let public_key = read_public_key(bytes)?;
+            Ok(Challenge { pepper, public_key })
         }
     }
 
@@ -350,6 +791,7 @@ mod serialize {
         fn serialize(&self) -> RawPacket {
             let mut bytes = Vec::new();
             write_u32(&mut bytes, self.pepper);
+            write_public_key(&mut bytes, self.public_key);
             bytes
         }
     }
@@ -370,20 +812,50 @@ mod serialize {
     }
 }
 
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        ConnectionConfig {
+            timeout: Duration::from_secs(15),
+            heartbeat_interval: Duration::from_secs(3),
+            initial_retransmit_delay: Duration::from_millis(100),
+            linger: Duration::from_secs(2),
+            channel_capacity: 16,
+            sequence_buffer_size: 1024,
+            rekey_payloads: 1 << 20,
+            rekey_interval: Duration::from_secs(10 * 60),
+            require_encryption: false,
+            coalesce_window: None,
+        }
+    }
+}
+
 impl ConnectionEnv {
-    pub fn pair(cap: usize, peer_addr: SocketAddr) -> (Self, Self) {
-        let (a_tx, b_rx) = mpsc::channel(cap);
-        let (b_tx, a_rx) = mpsc::channel(cap);
+    pub fn pair(peer_addr: SocketAddr, config: ConnectionConfig, probe_mtu: bool) -> (Self, Self) {
+        let (a_tx, b_rx) = mpsc::channel(config.channel_capacity);
+        let (b_tx, a_rx) = mpsc::channel(config.channel_capacity);
+        let conditions = SimulatedConditions::default();
+
+        let stats = Arc::new(SharedStats::default());
 
         let a = ConnectionEnv {
             peer_addr,
             packet_tx: a_tx,
             packet_rx: a_rx,
+            stats: stats.clone(),
+            shutdown: shutdown::channel().0,
+            config,
+            conditions: conditions.clone(),
+            probe_mtu,
         };
         let b = ConnectionEnv {
             peer_addr,
             packet_tx: b_tx,
             packet_rx: b_rx,
+            stats,
+            shutdown: shutdown::channel().0,
+            config,
+            conditions: conditions.clone(),
+            probe_mtu,
         };
 
         (a, b)
@@ -392,7 +864,7 @@ impl ConnectionEnv {
 
 impl Responder {
     pub async fn handle_packets(mut self) -> Result<()> {
-        let mut timeout = time::delay_for(CONNECTION_TIMEOUT);
+        let mut timeout = time::delay_for(self.config.timeout);
 
         loop {
             tokio::select! {
@@ -402,30 +874,86 @@ impl Responder {
                     break Err(Error::Timeout)
                 },
 
+                () = self.shutdown.wait() => {
+                    log::debug!("connection dropped");
+                    // the socket is shutting down as well, so the peer may never see this
+                    let _ = self.close_connection().await;
+                    break Ok(());
+                },
+
                 Some(packet) = self.packet_rx.recv() => {
                     if let Some((header, body)) = Header::extract(&packet) {
                         if header.is_close() {
-                            break Ok(());
+                            if !header.is_ack() {
+                                // the peer does not wait for anything else once acknowledged
+                                let ack = Header::close_ack();
+                                self.send_unbatched(ack.serialize().to_vec()).await?;
+                                break Ok(());
+                            }
+                            if let Closing::Sent(_) = self.closing {
+                                break Ok(());
+                            }
+                            continue;
                         }
 
-                        timeout = time::delay_for(CONNECTION_TIMEOUT);
+                        timeout = time::delay_for(self.config.timeout);
                         self.handle_packet(header, body).await?;
+                        self.close_when_idle().await?;
                     }
                 },
 
-                payload = self.payload_rx.recv() => {
-                    if let Some(payload) = payload {
-                        self.transmit_payload(&payload).await?;
-                    } else {
-                        self.close_connection().await?;
-                        break Ok(());
+                // stop taking payloads while the congestion window is full
+                outgoing = self.payload_rx.recv(),
+                    if self.transmit.backlog.is_empty() && self.closing == Closing::Open =>
+                {
+                    match outgoing {
+                        Some(Outgoing::Payload(payload)) => self.transmit_payload(payload).await?,
+                        Some(Outgoing::Flush) => self.flush_batch().await?,
+                        None => {
+                            log::debug!("closing connection once reliable payloads are acknowledged");
+                            self.closing = Closing::Lingering;
+                            self.linger_timer = time::delay_for(self.config.linger);
+                            self.close_when_idle().await?;
+                        }
                     }
                 },
 
+                () = &mut self.close_timer, if matches!(self.closing, Closing::Sent(_)) => {
+                    self.send_close().await?;
+                },
+
+                () = &mut self.linger_timer, if self.closing != Closing::Open => {
+                    log::warn!("the peer did not acknowledge that the connection was closed");
+                    self.close_connection().await?;
+                    break Err(Error::Unacknowledged);
+                },
+
                 Some(packet) = &mut self.transmit.packets.next() => {
                     let (chunk, packet) = packet.unwrap().into_inner();
+                    let size = packet.len();
                     self.send_packet(packet.clone()).await?;
-                    self.transmit.enqueue(chunk, packet);
+                    let retransmits = self.transmit.requeue(chunk, packet);
+                    self.mtu.retransmitted(size, retransmits);
+                    self.update_mtu_stats();
+                    self.update_congestion_stats();
+                },
+
+                () = &mut self.probe_timer => {
+                    self.send_probe().await?;
+                },
+
+                () = &mut self.rekey_timer, if self.cipher.is_some() => {
+                    self.request_keys().await?;
+                },
+
+                () = &mut self.heartbeat_timer => {
+                    let heartbeat = Header::heartbeat();
+                    self.send_packet(heartbeat.serialize().to_vec()).await?;
+                    self.stats.heartbeats_sent.fetch_add(1, Ordering::Relaxed);
+                },
+
+                () = &mut self.batch_timer, if !self.batch.is_empty() => {
+                    self.flush_batch().await?;
                 },
 
                 else => {
@@ -437,13 +965,70 @@ impl Responder {
     }
 
     async fn handle_packet(&mut self, header: Header, body: &[u8]) -> Result<()> {
-        self.acknowledge_packet(header).await?;
+        if !header.is_batch() {
+            return self.handle_unbatched(header, body).await;
+        }
+
+        let packets = match packet::split_batch(body) {
+            Ok(packets) => packets,
+            Err(e) => {
+                log::warn!("discarding batch: {}", e);
+                return Ok(());
+            }
+        };
+
+        for packet in packets {
+            match Header::extract(packet) {
+                // batches are never nested, and the peer never batches closing packets
+                Some((header, body)) if !header.is_batch() && !header.is_close() => {
+                    self.handle_unbatched(header, body).await?
+                }
+                _ => log::warn!("discarding invalid packet in batch"),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_unbatched(&mut self, header: Header, body: &[u8]) -> Result<()> {
+        if header.is_probe() {
+            return self.handle_probe(header, body).await;
+        }
+
+        if header.is_rekey() {
+            return self.handle_rekey(header, body).await;
+        }
+
+        // receiving a heartbeat already reset the timeout, and other kinds are unknown to us
+        if header.is_control() {
+            return Ok(());
+        }
 
         if header.is_ack() {
-            let chunk = header.chunk_id();
-            self.transmit.acknowledge(header.chunk_id());
-        } else if let Some(payload) = self.sequences.insert(header, body)? {
-            self.send_payload(payload).await?;
+            if let Some(rtt) = self.transmit.acknowledge(header.chunk_id()) {
+                // zero is reserved for an unmeasured round-trip time
+                let micros = (rtt.as_micros() as u64).max(1);
+                self.stats.rtt_micros.store(micros, Ordering::Relaxed);
+            }
+            self.send_backlog().await?;
+        } else if !self.sequences.admit(header, body.len(), &self.reorder) {
+            // reliable chunks are not acknowledged, so the peer sends them again later
+            log::debug!("out of reassembly memory, dropping sequence {}", header.seq);
+            self.update_memory_stats();
+        } else {
+            self.acknowledge_packet(header).await?;
+            match self.sequences.insert(header, body) {
+                Ok(Some(payload)) => self.receive_payload(payload).await?,
+                Ok(None) => {}
+                Err(Error::CorruptedPayload(e)) => {
+                    log::warn!("discarding sequence {}: {}", header.seq, e);
+                    self.stats
+                        .corrupted_sequences
+                        .fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => return Err(e),
+            }
+            self.update_memory_stats();
         }
 
         Ok(())
@@ -458,44 +1043,312 @@ impl Responder {
         Ok(())
     }
 
+    async fn handle_probe(&mut self, header: Header, body: &[u8]) -> Result<()> {
+        let size = usize::from(header.seq);
+        if header.is_ack() {
+            self.mtu.acknowledged(size);
+            self.send_probe().await?;
+        } else if packet::HEADER_SIZE + body.len() == size {
+            let ack = Header::probe_ack(header.seq);
+            self.send_packet(ack.serialize().to_vec()).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_rekey(&mut self, header: Header, body: &[u8]) -> Result<()> {
+        self.acknowledge_packet(header).await?;
+
+        let cipher = match &mut self.cipher {
+            Some(cipher) => cipher,
+            None => return Ok(()),
+        };
+
+        let ack = if header.is_ack() {
+            cipher.accept_ack(body).map(|_| None)
+        } else {
+            cipher.accept_request(body)
+        };
+
+        let exchanges = cipher.exchanges();
+        self.stats.key_exchanges.store(exchanges, Ordering::Relaxed);
+
+        match ack {
+            Ok(None) => {}
+            Ok(Some(ack)) => {
+                let seq = self.transmit.allocate_sequence();
+                self.send_rekey(Header::rekey_ack(seq), &ack).await?;
+            }
+            Err(e) => log::warn!("discarding key exchange: {}", e),
+        }
+        Ok(())
+    }
+
+    /// Ask the peer for new keys, unless an exchange is already in progress.
+    async fn request_keys(&mut self) -> Result<()> {
+        self.rekey_timer = time::delay_for(self.config.rekey_interval);
+        if let Some(request) = self.cipher.as_mut().and_then(Cipher::request_keys) {
+            log::debug!("asking the peer for new keys");
+            let seq = self.transmit.allocate_sequence();
+            self.send_rekey(Header::rekey(seq), &request).await?;
+        }
+        Ok(())
+    }
+
+    /// Send a request for new keys, or an answer to one, through the reliable transmit queue, so
+    /// that it is retransmitted until the peer acknowledges it.
+    async fn send_rekey(&mut self, header: Header, body: &[u8]) -> Result<()> {
+        let mut packet = header.serialize().to_vec();
+        packet.extend_from_slice(body);
+        self.transmit.backlog.push_back((header.chunk_id(), packet));
+        self.send_backlog().await
+    }
+
+    /// Send the next probe for the path MTU, and schedule the one after it.
+    async fn send_probe(&mut self) -> Result<()> {
+        self.update_mtu_stats();
+
+        match self.mtu.next_probe() {
+            None => {
+                // search again once the interval has passed
+                self.mtu.restart();
+                self.probe_timer = time::delay_for(mtu::SEARCH_INTERVAL);
+            }
+            Some(size) => {
+                let mut probe = Header::probe(size as u16).serialize().to_vec();
+                probe.resize(size, 0);
+                // probes are sent on their own to keep their size, but need not wait for others
+                self.transmit_packet(probe).await?;
+                self.probe_timer = time::delay_for(self.transmit.rtt.retransmit_delay(0));
+            }
+        }
+        Ok(())
+    }
+
+    /// Ask the peer to close the connection once every reliable payload has been acknowledged,
+    /// if the connection is being closed.
+    async fn close_when_idle(&mut self) -> Result<()> {
+        if self.closing == Closing::Lingering && self.transmit.is_idle() {
+            self.send_close().await?;
+        }
+        Ok(())
+    }
+
+    /// Ask the peer to close the connection, and do so again unless it is acknowledged in time.
+    async fn send_close(&mut self) -> Result<()> {
+        let sent = match self.closing {
+            Closing::Sent(sent) => sent,
+            _ => 0,
+        };
+
+        self.close_connection().await?;
+        self.closing = Closing::Sent(sent + 1);
+        self.close_timer = time::delay_for(self.transmit.rtt.retransmit_delay(sent));
+        Ok(())
+    }
+
+    /// Close the connection without waiting for the peer to acknowledge it.
     async fn close_connection(&mut self) -> Result<()> {
         log::debug!("closing connection");
         let close = Header::close();
-        self.send_packet(close.serialize().to_vec()).await?;
+        self.send_unbatched(close.serialize().to_vec()).await?;
         Ok(())
     }
 
-    async fn transmit_payload(&mut self, payload: &OutgoingPayload) -> Result<()> {
-        let sequence = self.transmit.allocate_sequence();
-        let packets = packet::into_chunks(sequence, &payload.bytes).map_err(Error::SplitPayload)?;
+    async fn transmit_payload(&mut self, mut payload: OutgoingPayload) -> Result<()> {
+        let mut flags = payload.delivery.flags();
+        if let Some(position) = self.transmit.allocate_position(payload.delivery) {
+            packet::prepend_position(&mut payload.bytes, position);
+        }
 
-        let mut buffer = Vec::new();
-        for (mut header, body) in packets {
-            if payload.needs_ack {
-                header.flags.insert(Flags::NEEDS_ACK);
+        if payload.bytes.len() >= COMPRESSION_THRESHOLD {
+            if let Some(compressed) = packet::compress(&payload.bytes) {
+                payload.bytes = compressed;
+                flags.insert(Flags::COMPRESSED);
             }
+        }
+
+        if let Some(cipher) = &mut self.cipher {
+            // encrypted payloads are authenticated, which detects corruption as well
+            payload.bytes = cipher.seal(&payload.bytes);
+            if cipher.sealed() >= self.config.rekey_payloads {
+                self.request_keys().await?;
+            }
+        } else if CHECKSUM_PAYLOADS {
+            packet::append_checksum(&mut payload.bytes);
+            flags.insert(Flags::CHECKSUM);
+        }
 
+        let sequence = self.transmit.allocate_sequence();
+        let chunk_size = self.mtu.size() - packet::HEADER_SIZE;
+        let packets = packet::into_chunks(sequence, &payload.bytes, flags, chunk_size)
+            .map_err(Error::SplitPayload)?;
+
+        let needs_ack = flags.contains(Flags::NEEDS_ACK);
+        let mut buffer = Vec::new();
+        for (header, body) in packets {
             buffer.clear();
             buffer.extend_from_slice(&header.serialize());
             buffer.extend_from_slice(body);
 
-            if payload.needs_ack {
-                self.transmit.enqueue(header.chunk_id(), buffer.clone());
+            if needs_ack {
+                self.transmit
+                    .backlog
+                    .push_back((header.chunk_id(), buffer.clone()));
+            } else {
+                self.send_packet(buffer.clone()).await?;
             }
-
-            self.send_packet(buffer.clone()).await?;
         }
 
+        self.send_backlog().await
+    }
+
+    /// Send as many reliable packets as fit in the congestion window.
+    async fn send_backlog(&mut self) -> Result<()> {
+        while let Some(packet) = self.transmit.pop_backlog() {
+            self.send_packet(packet).await?;
+        }
+        self.update_congestion_stats();
         Ok(())
     }
 
+    fn update_memory_stats(&self) {
+        let memory = self.sequences.memory() + self.reorder.memory();
+        self.stats
+            .reassembly_bytes
+            .store(memory as u64, Ordering::Relaxed);
+    }
+
+    fn update_mtu_stats(&self) {
+        self.stats
+            .path_mtu
+            .store(self.mtu.size() as u64, Ordering::Relaxed);
+    }
+
+    fn update_congestion_stats(&self) {
+        let congestion = &self.transmit.congestion;
+        self.stats
+            .congestion_window
+            .store(congestion.window() as u64, Ordering::Relaxed);
+        self.stats
+            .bytes_in_flight
+            .store(congestion.in_flight() as u64, Ordering::Relaxed);
+    }
+
+    /// Send a packet, holding it back to be sent together with others if it is small enough and
+    /// coalescing is enabled.
     async fn send_packet(&mut self, bytes: Vec<u8>) -> Result<()> {
+        let window = match self.config.coalesce_window {
+            Some(window) => window,
+            None => return self.transmit_packet(bytes).await,
+        };
+
+        let size = packet::FRAME_LENGTH_SIZE + bytes.len();
+        if packet::HEADER_SIZE + size > self.mtu.size() {
+            return self.send_unbatched(bytes).await;
+        }
+
+        if self.batch_size + size > self.mtu.size() {
+            self.flush_batch().await?;
+        }
+
+        if self.batch.is_empty() {
+            self.batch_timer = time::delay_for(window);
+        }
+
+        // the packet leaves within the window, so it keeps the connection alive like any other
+        self.heartbeat_timer = time::delay_for(self.config.heartbeat_interval);
+        self.batch.push(bytes);
+        self.batch_size += size;
+        Ok(())
+    }
+
+    /// Send a packet on its own, after the packets held back before it.
+    async fn send_unbatched(&mut self, bytes: Vec<u8>) -> Result<()> {
+        self.flush_batch().await?;
+        self.transmit_packet(bytes).await
+    }
+
+    /// Send the packets held back to be coalesced, if any.
+    async fn flush_batch(&mut self) -> Result<()> {
+        let packet = match self.batch.len() {
+            0 => return Ok(()),
+            1 => self.batch.pop().unwrap(),
+            _ => packet::into_batch(&self.batch),
+        };
+
+        self.batch.clear();
+        self.batch_size = packet::HEADER_SIZE;
+        self.transmit_packet(packet).await
+    }
+
+    async fn transmit_packet(&mut self, bytes: Vec<u8>) -> Result<()> {
+        self.heartbeat_timer = time::delay_for(self.config.heartbeat_interval);
         if self.packet_tx.send(bytes).await.is_err() {
             return Err(Error::Closed);
         }
         Ok(())
     }
 
+    /// Decrypt and decompress a reassembled payload if needed, and pass it on once the ordered
+    /// payloads before it have been. Sequenced payloads older than the newest are dropped.
+    async fn receive_payload(&mut self, payload: IncomingPayload) -> Result<()> {
+        let (position, payload) = match self.unpack_payload(payload) {
+            Ok(unpacked) => unpacked,
+            Err(e) => {
+                log::warn!("discarding payload: {}", e);
+                self.stats
+                    .corrupted_sequences
+                    .fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+        };
+
+        match position {
+            None => self.send_payload(payload).await,
+            Some(position) if payload.flags.contains(Flags::ORDERED) => {
+                for payload in self.reorder.insert(position, payload) {
+                    self.send_payload(payload).await?;
+                }
+                Ok(())
+            }
+            Some(position) => {
+                if let Some(newest) = self.newest_sequenced {
+                    // positions up to half the range behind the newest are considered older
+                    if position.wrapping_sub(newest).wrapping_sub(1) >= 1 << 15 {
+                        log::trace!("dropping stale sequenced payload {}", position);
+                        return Ok(());
+                    }
+                }
+                self.newest_sequenced = Some(position);
+                self.send_payload(payload).await
+            }
+        }
+    }
+
+    /// Undo the packing of a payload. Returns the position of ordered and sequenced payloads.
+    fn unpack_payload(
+        &mut self,
+        mut payload: IncomingPayload,
+    ) -> Result<(Option<u16>, IncomingPayload)> {
+        if let Some(cipher) = &mut self.cipher {
+            payload.bytes = cipher.open(&payload.bytes).map_err(Error::DecryptPayload)?;
+        }
+
+        if payload.flags.contains(Flags::COMPRESSED) {
+            payload.bytes = packet::decompress(&payload.bytes).map_err(Error::CorruptedPayload)?;
+            payload.flags.remove(Flags::COMPRESSED);
+        }
+
+        if payload.flags.intersects(Flags::ORDERED | Flags::SEQUENCED) {
+            let (position, bytes) =
+                packet::split_position(payload.bytes).map_err(Error::CorruptedPayload)?;
+            payload.bytes = bytes;
+            return Ok((Some(position), payload));
+        }
+
+        Ok((None, payload))
+    }
+
     async fn send_payload(&mut self, payload: IncomingPayload) -> Result<()> {
         if self.payload_tx.send(payload).await.is_err() {
             return Err(Error::Closed);
@@ -505,37 +1358,106 @@ impl Responder {
 }
 
 impl SequenceBuilder {
+    /// Make room for a chunk of `size` bytes next to the payloads waiting in `reorder`, evicting
+    /// unreliable sequences if needed. Returns `false` if the chunk has to be dropped, in which case
+    /// it must not be acknowledged.
+    pub fn admit(&mut self, header: Header, size: usize, reorder: &ReorderBuffer) -> bool {
+        self.clear_complete(header.seq);
+
+        let index = self.index(header.seq);
+        let slot = &self.slots[index];
+        let buffered = slot.sequence == Some(header.seq);
+        if buffered && (slot.complete || slot.reliable) {
+            // duplicates are acknowledged again, and the rest of a reliable sequence has room
+            return true;
+        }
+
+        let ordered = header.flags.contains(Flags::ORDERED);
+        let awaited = reorder.awaits(header.seq);
+        if ordered && !buffered && reorder.is_full() && !awaited {
+            return false;
+        }
+
+        let alone = header.flags.contains(Flags::LAST_CHUNK) && header.chunk == 0;
+        let needed = if alone && !ordered {
+            // passed on right away, without being buffered
+            0
+        } else if header.needs_ack() && !alone && !buffered {
+            packet::MAX_PAYLOAD_SIZE
+        } else {
+            size
+        };
+
+        let limit = match awaited {
+            true => 2 * MAX_REASSEMBLY_MEMORY,
+            false => MAX_REASSEMBLY_MEMORY,
+        };
+        if !self.evict_until_room(reorder.memory() + needed, index, limit) {
+            return false;
+        }
+
+        if header.needs_ack() && !alone {
+            self.entry(header.seq).reliable = true;
+        }
+        true
+    }
+
     pub fn insert(&mut self, header: Header, body: &[u8]) -> Result<Option<IncomingPayload>> {
         self.clear_complete(header.seq);
 
+        self.clock += 1;
+        let clock = self.clock;
+
         let slot = self.entry(header.seq);
 
         if slot.complete {
             return Ok(None);
         }
 
+        slot.last_used = clock;
         let sequence = &mut slot.entry;
+        let previous_size = sequence.buffered_size();
 
-        sequence
+        let inserted = sequence
             .insert_chunk(header, body)
-            .map_err(Error::ReconstructPayload)?;
+            .map_err(Error::ReconstructPayload);
+        let size = sequence.buffered_size();
 
-        if sequence.is_complete() {
+        let payload = if inserted.is_ok() && sequence.is_complete() {
             slot.complete = true;
-            let sequence = std::mem::take(sequence);
-            let bytes = sequence.payload();
-            Ok(Some(IncomingPayload { bytes }))
+            Some(std::mem::take(sequence))
         } else {
-            Ok(None)
+            None
+        };
+
+        self.memory += size;
+        self.memory -= previous_size;
+        if payload.is_some() {
+            self.memory -= size;
+        }
+
+        inserted?;
+
+        match payload {
+            None => Ok(None),
+            Some(sequence) => {
+                let flags = sequence.flags();
+                let bytes = sequence.payload().map_err(Error::CorruptedPayload)?;
+                Ok(Some(IncomingPayload {
+                    bytes,
+                    sequence: header.seq,
+                    flags,
+                }))
+            }
         }
     }
 
-    fn index(sequence: u16) -> usize {
-        sequence as usize % SEQUENCE_BUFFER_SIZE
+    fn index(&self, sequence: u16) -> usize {
+        sequence as usize % self.slots.len()
     }
 
     fn entry(&mut self, sequence: u16) -> &mut Slot {
-        let index = Self::index(sequence);
+        let index = self.index(sequence);
         let slot = &mut self.slots[index];
 
         match slot.sequence {
@@ -544,6 +1466,7 @@ impl SequenceBuilder {
 
             // insert new entry
             None | Some(_) => {
+                self.memory -= slot.entry.buffered_size();
                 *slot = Slot::default();
                 slot.sequence = Some(sequence);
                 slot
@@ -552,29 +1475,195 @@ impl SequenceBuilder {
     }
 
     fn clear_complete(&mut self, current: u16) {
-        while current.wrapping_sub(self.start) as usize >= SEQUENCE_BUFFER_SIZE {
-            let index = Self::index(self.start);
+        while current.wrapping_sub(self.start) as usize >= self.slots.len() {
+            let index = self.index(self.start);
+            self.memory -= self.slots[index].entry.buffered_size();
             self.slots[index] = Slot::default();
             self.start = self.start.wrapping_add(1);
         }
     }
+
+    /// The number of bytes set aside for incomplete sequences, counting every reliable one as the
+    /// largest payload.
+    fn reserved(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| !slot.complete)
+            .map(|slot| match slot.reliable {
+                true => packet::MAX_PAYLOAD_SIZE,
+                false => slot.entry.buffered_size(),
+            })
+            .sum()
+    }
+
+    /// The number of bytes buffered by all incomplete sequences.
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    /// Discard the least recently updated incomplete unreliable sequences, other than the one in
+    /// the `keep` slot, until `needed` more bytes fit within `limit`. Returns `false` if they do
+    /// not fit even then.
+    fn evict_until_room(&mut self, needed: usize, keep: usize, limit: usize) -> bool {
+        let mut reserved = self.reserved();
+        while reserved + needed > limit {
+            let oldest = self
+                .slots
+                .iter()
+                .enumerate()
+                .filter(|(index, slot)| *index != keep && !slot.reliable)
+                .filter(|(_, slot)| slot.entry.buffered_size() > 0)
+                .min_by_key(|(_, slot)| slot.last_used)
+                .map(|(index, _)| index);
+
+            let index = match oldest {
+                Some(index) => index,
+                None => return false,
+            };
+
+            let slot = &mut self.slots[index];
+            log::debug!("evicting incomplete sequence {:?}", slot.sequence);
+            reserved -= slot.entry.buffered_size();
+            self.memory -= slot.entry.buffered_size();
+            *slot = Slot::default();
+
+            self.stats.evicted_sequences.fetch_add(1, Ordering::Relaxed);
+        }
+        true
+    }
+}
+
+impl ReorderBuffer {
+    /// Hold back an ordered payload, and take the payloads that can be passed on, in order.
+    pub fn insert(&mut self, position: u16, payload: IncomingPayload) -> Vec<IncomingPayload> {
+        // positions behind the next one have already been passed on
+        if position.wrapping_sub(self.next) >= 1 << 15 {
+            return Vec::new();
+        }
+
+        self.memory += payload.bytes.len();
+        if let Some(duplicate) = self.waiting.insert(position, payload) {
+            self.memory -= duplicate.bytes.len();
+        }
+
+        let mut ready = Vec::new();
+        while let Some(payload) = self.waiting.remove(&self.next) {
+            self.memory -= payload.bytes.len();
+            ready.push(payload);
+            self.next = self.next.wrapping_add(1);
+        }
+        ready
+    }
+
+    /// The number of bytes in the waiting payloads.
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    /// Are there too many waiting payloads to take on more?
+    pub fn is_full(&self) -> bool {
+        self.waiting.len() >= MAX_REORDERED_PAYLOADS
+    }
+
+    /// May the waiting payloads be waiting for the one in `sequence`? Payloads are sent in the
+    /// order of their positions, so the one they wait for was sent in an older sequence than any
+    /// of them.
+    pub fn awaits(&self, sequence: u16) -> bool {
+        // sequences up to half the range behind another are considered older
+        !self.waiting.is_empty()
+            && self
+                .waiting
+                .values()
+                .all(|waiting| waiting.sequence.wrapping_sub(sequence).wrapping_sub(1) < 1 << 15)
+    }
 }
 
 impl TransmitQueue {
+    /// Have all reliable packets been acknowledged?
+    pub fn is_idle(&self) -> bool {
+        self.backlog.is_empty() && self.in_flight.is_empty()
+    }
+
     pub fn allocate_sequence(&mut self) -> u16 {
         let seq = self.next_sequence;
         self.next_sequence = seq.wrapping_add(1);
         seq
     }
 
-    pub fn acknowledge(&mut self, chunk: PacketId) {
-        if let Some(key) = self.keys.remove(&chunk) {
-            self.packets.remove(&key);
+    /// The position of a payload among the others delivered the same way, if the delivery keeps
+    /// track of their order.
+    pub fn allocate_position(&mut self, delivery: Delivery) -> Option<u16> {
+        let next = match delivery {
+            Delivery::ReliableOrdered => &mut self.next_ordered,
+            Delivery::BestEffortSequenced => &mut self.next_sequenced,
+            Delivery::Reliable | Delivery::BestEffort => return None,
+        };
+
+        let position = *next;
+        *next = position.wrapping_add(1);
+        Some(position)
+    }
+
+    /// Stop retransmitting an acknowledged packet. Returns the updated smoothed round-trip time if
+    /// the acknowledgement could be measured.
+    pub fn acknowledge(&mut self, chunk: PacketId) -> Option<Duration> {
+        let in_flight = self.in_flight.remove(&chunk)?;
+        self.packets.remove(&in_flight.key);
+        self.congestion.acknowledged(in_flight.size);
+
+        if in_flight.retransmits > 0 {
+            return None;
         }
+
+        self.rtt.update(in_flight.sent.elapsed());
+        self.rtt.smoothed()
     }
 
-    pub fn enqueue(&mut self, chunk: PacketId, packet: RawPacket) {
-        let key = self.packets.insert((chunk, packet), RETRANSMIT_DELAY);
-        self.keys.insert(chunk, key);
+    /// Take the next packet from the backlog if it fits in the congestion window, and retransmit
+    /// it unless it is acknowledged in time.
+    pub fn pop_backlog(&mut self) -> Option<RawPacket> {
+        let (_, packet) = self.backlog.front()?;
+        if !self.congestion.has_room(packet.len()) {
+            return None;
+        }
+
+        let (chunk, packet) = self.backlog.pop_front()?;
+        self.enqueue(chunk, packet.clone());
+        Some(packet)
+    }
+
+    /// Retransmit a packet unless it is acknowledged in time.
+    fn enqueue(&mut self, chunk: PacketId, packet: RawPacket) {
+        let size = packet.len();
+        let key = self
+            .packets
+            .insert((chunk, packet), self.rtt.retransmit_delay(0));
+        let in_flight = InFlight {
+            key,
+            sent: Instant::now(),
+            retransmits: 0,
+            size,
+            epoch: self.congestion.epoch(),
+        };
+        self.in_flight.insert(chunk, in_flight);
+        self.congestion.sent(size);
+    }
+
+    /// Retransmit a packet that has just been retransmitted again, backing off further. Returns
+    /// the number of times the packet has been retransmitted.
+    pub fn requeue(&mut self, chunk: PacketId, packet: RawPacket) -> u32 {
+        match self.in_flight.get_mut(&chunk) {
+            None => {
+                self.enqueue(chunk, packet);
+                0
+            }
+            Some(in_flight) => {
+                in_flight.retransmits += 1;
+                self.congestion.lost(in_flight.epoch);
+                let delay = self.rtt.retransmit_delay(in_flight.retransmits);
+                in_flight.key = self.packets.insert((chunk, packet), delay);
+                in_flight.retransmits
+            }
+        }
     }
 }
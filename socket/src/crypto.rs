@@ -0,0 +1,420 @@
+//! Encrypt payloads with keys agreed on during the handshake.
+//!
+//! A peer that wants its payloads encrypted sends an ephemeral x25519 public key along with its
+//! `Init` or `Challenge`. Both peers derive the same two keys from the shared secret with
+//! HKDF-SHA256, one for each direction, and seal every payload with ChaCha20-Poly1305. Each sealed
+//! payload starts with the nonce it was sealed with, so payloads may arrive in any order or not at
+//! all, and payloads with a nonce that has already been opened are rejected as replays.
+//!
+//! Keys are replaced after a number of payloads, or a while, by exchanging new ephemeral keys in a
+//! REKEY control packet and its acknowledgement, both sealed with the current keys and sent
+//! reliably, so that they are retransmitted until their packets are acknowledged. The keys of
+//! each exchange belong to an epoch, stored in the most significant byte of every nonce, so that
+//! payloads sealed with the previous keys can still be opened while they are in flight. The peer
+//! that did not ask for new keys only starts sealing with them once it has opened a payload sealed
+//! with them, since until then it can not tell whether the acknowledgement arrived.
+//!
+//! Neither peer has a long-term key, so encryption protects against eavesdropping and tampering,
+//! but not against an attacker that intercepts the handshake.
+
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use std::convert::TryInto;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+/// The size of a public key sent during the handshake, in bytes.
+pub(crate) const PUBLIC_KEY_SIZE: usize = 32;
+
+/// The number of bytes added to every sealed payload: the nonce and the authentication tag.
+pub(crate) const OVERHEAD: usize = NONCE_SIZE + 16;
+
+/// The size of the nonce at the start of every sealed payload, in bytes.
+const NONCE_SIZE: usize = 8;
+
+/// How many nonces older than the newest are tracked to reject replays. Reliable payloads are
+/// retransmitted with the nonce they were first sealed with, and their packets are acknowledged
+/// before they are opened, so a retransmission older than the window is lost for good. At a few
+/// hundred payloads per second, this covers longer than a retransmission takes to arrive.
+const REPLAY_WINDOW: u64 = 4096;
+
+/// The number of bits of a nonce that count the payloads sealed with the same keys. The bits above
+/// them hold the epoch of the keys.
+const COUNTER_BITS: u32 = 56;
+
+/// How long the previous keys are kept once the peer has started sealing with new ones, for
+/// payloads that are still in flight or being retransmitted.
+const PREVIOUS_KEYS_LIFETIME: Duration = Duration::from_secs(10);
+
+/// Errors that occur when opening a sealed payload.
+#[derive(Debug, Copy, Clone, Error)]
+pub enum Error {
+    /// The payload is too short to have been sealed.
+    #[error("the payload is too short to be encrypted")]
+    Truncated,
+
+    /// The payload was not sealed with the key of the peer, or was modified in transit.
+    #[error("the payload could not be authenticated")]
+    Forged,
+
+    /// A payload with the same nonce has already been opened, or the nonce is too old to tell.
+    #[error("the payload has already been received")]
+    Replayed,
+}
+
+/// One half of a key exchange, waiting for the public key of the peer.
+pub(crate) struct KeyExchange {
+    secret: EphemeralSecret,
+    public: PublicKey,
+}
+
+/// Seals outgoing payloads and opens incoming ones.
+pub(crate) struct Cipher {
+    /// The salt of the handshake, which every later exchange derives its keys with as well.
+    salt: [u8; 8],
+    /// Did this peer initiate the connection? If both peers ask for new keys at once, the
+    /// initiator's exchange wins.
+    initiator: bool,
+    sealing: Sealer,
+    /// Keys from an exchange the peer asked for, used once the peer is known to have them.
+    next_sealing: Option<Sealer>,
+    opening: Opener,
+    /// The keys of the previous epoch, and when they are discarded, once known.
+    previous: Option<(Opener, Option<Instant>)>,
+    /// The exchange this peer asked for, until the peer acknowledges it.
+    pending: Option<(u8, KeyExchange)>,
+    /// The number of exchanges completed.
+    exchanges: u64,
+}
+
+/// Seals payloads with the keys of an epoch.
+struct Sealer {
+    epoch: u8,
+    key: ChaCha20Poly1305,
+    next_nonce: u64,
+}
+
+/// Opens payloads sealed with the keys of an epoch.
+struct Opener {
+    epoch: u8,
+    key: ChaCha20Poly1305,
+    replay: ReplayWindow,
+}
+
+/// The nonces opened recently.
+struct ReplayWindow {
+    /// One more than the newest nonce opened.
+    end: u64,
+    /// Bit `nonce % REPLAY_WINDOW` is set if a nonce in the window has been opened.
+    opened: [u64; REPLAY_WINDOW as usize / 64],
+}
+
+impl KeyExchange {
+    pub fn new() -> KeyExchange {
+        let secret = EphemeralSecret::new(OsRng);
+        let public = PublicKey::from(&secret);
+        KeyExchange { secret, public }
+    }
+
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_SIZE] {
+        self.public.to_bytes()
+    }
+
+    /// Derive the keys for a connection from the public key of the peer. `salt` must be the same
+    /// for both peers, and differ between connections.
+    pub fn finish(self, peer: [u8; PUBLIC_KEY_SIZE], salt: [u8; 8], initiator: bool) -> Cipher {
+        let (sealing, opening) = self.derive(peer, &salt, initiator);
+        Cipher {
+            salt,
+            initiator,
+            sealing: Sealer::new(0, sealing),
+            next_sealing: None,
+            opening: Opener::new(0, opening),
+            previous: None,
+            pending: None,
+            exchanges: 0,
+        }
+    }
+
+    /// Derive the key to seal with and the key to open with. `initiator` is set for the peer that
+    /// started the exchange.
+    fn derive(
+        self,
+        peer: [u8; PUBLIC_KEY_SIZE],
+        salt: &[u8],
+        initiator: bool,
+    ) -> (ChaCha20Poly1305, ChaCha20Poly1305) {
+        let shared = self.secret.diffie_hellman(&PublicKey::from(peer));
+
+        let mut keys = [0; 64];
+        Hkdf::<Sha256>::new(Some(salt), shared.as_bytes())
+            .expand(b"snow-fight socket keys", &mut keys)
+            .expect("64 bytes is a valid output length");
+
+        let initiator_key: [u8; 32] = keys[..32].try_into().unwrap();
+        let responder_key: [u8; 32] = keys[32..].try_into().unwrap();
+        let (sealing, opening) = if initiator {
+            (initiator_key, responder_key)
+        } else {
+            (responder_key, initiator_key)
+        };
+
+        (
+            ChaCha20Poly1305::new(&Key::from(sealing)),
+            ChaCha20Poly1305::new(&Key::from(opening)),
+        )
+    }
+}
+
+impl Cipher {
+    /// Encrypt and authenticate a payload.
+    pub fn seal(&mut self, payload: &[u8]) -> Vec<u8> {
+        self.sealing.seal(payload)
+    }
+
+    /// Authenticate and decrypt a payload sealed by the peer.
+    pub fn open(&mut self, sealed: &[u8]) -> Result<Vec<u8>, Error> {
+        if sealed.len() < OVERHEAD {
+            return Err(Error::Truncated);
+        }
+
+        if let Some((_, Some(discard))) = self.previous {
+            if discard <= Instant::now() {
+                self.previous = None;
+            }
+        }
+
+        let nonce = u64::from_be_bytes(sealed[..NONCE_SIZE].try_into().unwrap());
+        let epoch = (nonce >> COUNTER_BITS) as u8;
+        if epoch != self.opening.epoch {
+            return match &mut self.previous {
+                Some((previous, _)) if previous.epoch == epoch => previous.open(sealed),
+                _ => Err(Error::Forged),
+            };
+        }
+
+        let payload = self.opening.open(sealed)?;
+
+        // the peer has the newest keys, so the previous ones are only needed for a while longer
+        if let Some((_, discard @ None)) = &mut self.previous {
+            *discard = Some(Instant::now() + PREVIOUS_KEYS_LIFETIME);
+        }
+        if let Some(sealing) = self.next_sealing.take() {
+            if sealing.epoch == epoch {
+                self.sealing = sealing;
+            } else {
+                self.next_sealing = Some(sealing);
+            }
+        }
+
+        Ok(payload)
+    }
+
+    /// The number of payloads sealed with the current keys.
+    pub fn sealed(&self) -> u64 {
+        self.sealing.next_nonce
+    }
+
+    /// The number of times new keys have been exchanged.
+    pub fn exchanges(&self) -> u64 {
+        self.exchanges
+    }
+
+    /// Ask the peer for new keys. Returns the body of the request, unless an exchange is already in
+    /// progress, or the peer may still be using the previous keys.
+    pub fn request_keys(&mut self) -> Option<Vec<u8>> {
+        if self.pending.is_some() || self.next_sealing.is_some() || self.previous.is_some() {
+            return None;
+        }
+
+        let epoch = self.sealing.epoch.wrapping_add(1);
+        let exchange = KeyExchange::new();
+        let request = self.seal(&exchange_body(epoch, &exchange));
+        self.pending = Some((epoch, exchange));
+        Some(request)
+    }
+
+    /// Answer a request for new keys from the peer. Returns the body of the acknowledgement, if
+    /// there is one to send.
+    pub fn accept_request(&mut self, request: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let (epoch, key) = match self.open(request) {
+            // the packet was retransmitted before it was acknowledged, and has been answered
+            Err(Error::Replayed) => return Ok(None),
+            Err(e) => return Err(e),
+            Ok(body) => parse_exchange_body(&body)?,
+        };
+
+        if self.pending.is_some() {
+            if self.initiator {
+                // the peer answers our request instead
+                return Ok(None);
+            }
+            self.pending = None;
+        }
+
+        if epoch != self.sealing.epoch.wrapping_add(1) || self.next_sealing.is_some() {
+            return Ok(None);
+        }
+
+        let exchange = KeyExchange::new();
+        let ack = self.seal(&exchange_body(epoch, &exchange));
+        let (sealing, opening) = exchange.derive(key, &self.salt, false);
+
+        let previous = std::mem::replace(&mut self.opening, Opener::new(epoch, opening));
+        self.previous = Some((previous, None));
+        self.next_sealing = Some(Sealer::new(epoch, sealing));
+        self.exchanges += 1;
+        Ok(Some(ack))
+    }
+
+    /// Start using the new keys once the peer has acknowledged our request. Returns `true` if the
+    /// acknowledgement completed an exchange.
+    pub fn accept_ack(&mut self, ack: &[u8]) -> Result<bool, Error> {
+        let (epoch, key) = match self.open(ack) {
+            Err(Error::Replayed) => return Ok(false),
+            Err(e) => return Err(e),
+            Ok(body) => parse_exchange_body(&body)?,
+        };
+
+        match self.pending.take() {
+            Some((requested, exchange)) if requested == epoch => {
+                let (sealing, opening) = exchange.derive(key, &self.salt, true);
+                let previous = std::mem::replace(&mut self.opening, Opener::new(epoch, opening));
+                self.previous = Some((previous, None));
+                self.sealing = Sealer::new(epoch, sealing);
+                self.exchanges += 1;
+                Ok(true)
+            }
+            pending => {
+                self.pending = pending;
+                Ok(false)
+            }
+        }
+    }
+}
+
+impl Sealer {
+    fn new(epoch: u8, key: ChaCha20Poly1305) -> Sealer {
+        Sealer {
+            epoch,
+            key,
+            next_nonce: 0,
+        }
+    }
+
+    fn seal(&mut self, payload: &[u8]) -> Vec<u8> {
+        let nonce = u64::from(self.epoch) << COUNTER_BITS | self.next_nonce;
+        self.next_nonce += 1;
+
+        let ciphertext = self
+            .key
+            .encrypt(&expand_nonce(nonce), payload)
+            .expect("payloads are far smaller than the limit of ChaCha20-Poly1305");
+
+        let mut sealed = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+        sealed.extend_from_slice(&nonce.to_be_bytes());
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+}
+
+impl Opener {
+    fn new(epoch: u8, key: ChaCha20Poly1305) -> Opener {
+        Opener {
+            epoch,
+            key,
+            replay: ReplayWindow::default(),
+        }
+    }
+
+    fn open(&mut self, sealed: &[u8]) -> Result<Vec<u8>, Error> {
+        let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+        let nonce = u64::from_be_bytes(nonce.try_into().unwrap());
+        if !self.replay.is_fresh(nonce) {
+            return Err(Error::Replayed);
+        }
+
+        let payload = self
+            .key
+            .decrypt(&expand_nonce(nonce), ciphertext)
+            .map_err(|_| Error::Forged)?;
+        self.replay.insert(nonce);
+        Ok(payload)
+    }
+}
+
+impl Default for ReplayWindow {
+    fn default() -> Self {
+        ReplayWindow {
+            end: 0,
+            opened: [0; REPLAY_WINDOW as usize / 64],
+        }
+    }
+}
+
+impl ReplayWindow {
+    /// Has the nonce not been opened yet?
+    fn is_fresh(&self, nonce: u64) -> bool {
+        if nonce >= self.end {
+            return true;
+        }
+
+        let age = self.end - 1 - nonce;
+        age < REPLAY_WINDOW && !self.is_opened(nonce)
+    }
+
+    fn insert(&mut self, nonce: u64) {
+        if nonce >= self.end {
+            // nonces skipped over may still be opened, and older ones share their bits
+            if nonce - self.end >= REPLAY_WINDOW {
+                self.opened = [0; REPLAY_WINDOW as usize / 64];
+            } else {
+                for skipped in self.end..nonce {
+                    let (word, bit) = Self::position(skipped);
+                    self.opened[word] &= !bit;
+                }
+            }
+            self.end = nonce + 1;
+        }
+
+        let (word, bit) = Self::position(nonce);
+        self.opened[word] |= bit;
+    }
+
+    fn is_opened(&self, nonce: u64) -> bool {
+        let (word, bit) = Self::position(nonce);
+        self.opened[word] & bit != 0
+    }
+
+    /// The word and bit of a nonce in the window.
+    fn position(nonce: u64) -> (usize, u64) {
+        let index = nonce % REPLAY_WINDOW;
+        ((index / 64) as usize, 1 << (index % 64))
+    }
+}
+
+/// The body of a request for new keys, or its acknowledgement, before it is sealed: the epoch of
+/// the keys followed by a public key.
+fn exchange_body(epoch: u8, exchange: &KeyExchange) -> Vec<u8> {
+    let mut body = Vec::with_capacity(1 + PUBLIC_KEY_SIZE);
+    body.push(epoch);
+    body.extend_from_slice(&exchange.public_key());
+    body
+}
+
+fn parse_exchange_body(body: &[u8]) -> Result<(u8, [u8; PUBLIC_KEY_SIZE]), Error> {
+    match body.split_first() {
+        Some((&epoch, key)) if key.len() == PUBLIC_KEY_SIZE => Ok((epoch, key.try_into().unwrap())),
+        _ => Err(Error::Truncated),
+    }
+}
+
+fn expand_nonce(nonce: u64) -> Nonce {
+    let mut bytes = [0; 12];
+    bytes[4..].copy_from_slice(&nonce.to_be_bytes());
+    Nonce::from(bytes)
+}
@@ -1,18 +1,33 @@
+//! Errors returned by endpoints and connections.
+
 use thiserror::Error;
 
+pub use crate::connection::{DeserializeError, Error as ConnectionError};
+pub use crate::packet::Error as PacketError;
+
+/// The result of binding, connecting and accepting.
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// Errors that occur when binding, connecting and accepting.
 #[derive(Debug, Error)]
 pub enum Error {
+    /// The underlying socket failed.
     #[error(transparent)]
     Io(#[from] std::io::Error),
 
+    /// The endpoint no longer accepts connections.
     #[error("connection closed")]
     ConnectionClosed,
 
+    /// A datagram had no destination.
     #[error("no target address specified, but the socket is not connected")]
     NoTarget,
 
+    /// The handshake with the peer failed.
     #[error("failed to establish connection")]
-    Connect(#[source] crate::connection::Error),
+    Connect(#[source] ConnectionError),
+
+    /// An established connection failed.
+    #[error(transparent)]
+    Connection(#[from] ConnectionError),
 }
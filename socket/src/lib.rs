@@ -1,96 +1,262 @@
+//! A connection-oriented protocol on top of UDP, with optionally reliable delivery of payloads
+//! larger than a single datagram.
+//!
+//! A server binds an [`Endpoint`] and accepts [`Connection`]s from it, while a client connects
+//! directly to the server's address:
+//!
+//! ```no_run
+//! use socket::{Connection, Delivery, Endpoint};
+//!
+//! # async fn example() -> socket::error::Result<()> {
+//! let mut endpoint = Endpoint::bind("127.0.0.1:8000").await?;
+//!
+//! let addr = "127.0.0.1:8000".parse().unwrap();
+//! let mut client = Connection::connect(addr).await?;
+//! client.send(b"hello".to_vec(), Delivery::Reliable).await?;
+//!
+//! let mut server = endpoint.accept().await?;
+//! assert_eq!(server.recv().await, Some(b"hello".to_vec()));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Features
+//!
+//! - `simulation` (enabled by default): artificially degrade the network conditions, see the
+//!   [`simulation`] module.
+
+#![warn(missing_docs)]
+
+use socket2::Socket;
 use std::collections::HashMap;
-use std::net::{Ipv4Addr, SocketAddr};
-use tokio::net::{udp, ToSocketAddrs, UdpSocket};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::net::{udp, UdpSocket};
 use tokio::sync::mpsc;
-use tokio::time::{timeout, Duration};
-
-#[macro_use]
-mod util;
+use tokio::time::{self, timeout, Duration};
 
+mod congestion;
 mod connection;
+mod crypto;
+mod mtu;
 mod packet;
+mod rtt;
 
+pub mod capture;
 pub mod error;
+pub mod shutdown;
+#[cfg(feature = "simulation")]
+pub mod simulation;
 
-pub use crate::connection::*;
+pub use crate::connection::{Connection, ConnectionConfig, Delivery, Stats};
+pub use crate::packet::MAX_PAYLOAD_SIZE;
 
+use crate::connection::{ConnectionEnv, SharedStats};
 use crate::error::{Error, Result};
+use crate::packet::{Header, Refusal};
+use crate::shutdown::Shutdown;
+
+/// The size of the socket buffers requested from the OS by default, in bytes.
+const DEFAULT_BUFFER_SIZE: usize = 1 << 20;
 
-/// The percentage of artificial packet loss to add (for testing purposes).
-const PACKET_LOSS: f64 = 0.0;
+/// How many times to retry sending a packet if the socket is temporarily unable to send it.
+const SEND_RETRIES: u32 = 3;
 
-/// The amount of time a client has to establish a connection, measured from the moment the first
-/// packet arrives.
-const CONNECTION_TIMEOUT: Duration = Duration::from_secs(15);
+/// How long to wait before the first retry. The delay doubles with every retry.
+const SEND_RETRY_DELAY: Duration = Duration::from_millis(1);
 
 type RawPacket = Vec<u8>;
 
+/// The network conditions simulated for a socket or connection, see `simulation`.
+#[cfg(feature = "simulation")]
+pub(crate) type SimulatedConditions = simulation::SharedConditions;
+
+/// Without the simulation, there are no conditions to keep track of.
+#[cfg(not(feature = "simulation"))]
+pub(crate) type SimulatedConditions = ();
+
+/// Options for the underlying UDP socket, and the connections made over it.
+#[derive(Debug, Copy, Clone)]
+pub struct SocketConfig {
+    /// The requested size of the send buffer (`SO_SNDBUF`), in bytes. If `None`, the OS default
+    /// is used. The OS may limit the size of the buffer.
+    pub send_buffer_size: Option<usize>,
+    /// The requested size of the receive buffer (`SO_RCVBUF`), in bytes. If `None`, the OS
+    /// default is used. The OS may limit the size of the buffer.
+    pub recv_buffer_size: Option<usize>,
+    /// The maximum number of connections an endpoint keeps at once, including those waiting to be
+    /// accepted. Any more are refused with `ConnectionError::ServerFull`.
+    pub max_connections: usize,
+    /// The maximum number of connections an endpoint keeps waiting to be accepted, either while
+    /// completing the handshake or until returned by `Endpoint::accept`. Any more are refused with
+    /// `ConnectionError::ServerFull`.
+    pub accept_backlog: usize,
+    /// Options for the connections made over the socket.
+    pub connection: ConnectionConfig,
+}
+
+/// A local socket that accepts connections from any number of peers.
 #[derive(Debug)]
-pub struct Listener {
+pub struct Endpoint {
     connections: mpsc::Receiver<Connection>,
+    /// The number of connections waiting to be accepted.
+    pending: Arc<AtomicUsize>,
     addr: Option<SocketAddr>,
+    #[cfg_attr(not(feature = "simulation"), allow(dead_code))]
+    conditions: SimulatedConditions,
 }
 
 struct ConnectionStore {
-    connections: HashMap<SocketAddr, mpsc::Sender<RawPacket>>,
-    listener: mpsc::Sender<Connection>,
-    packets: mpsc::Sender<(RawPacket, SocketAddr)>,
+    connections: HashMap<SocketAddr, Route>,
+    endpoint: mpsc::Sender<Connection>,
+    /// The number of connections waiting to be accepted.
+    pending: Arc<AtomicUsize>,
+    packets: mpsc::Sender<OutgoingPacket>,
+    config: SocketConfig,
+    /// The conditions simulated for the endpoint.
+    conditions: SimulatedConditions,
+    /// The socket forbids fragmentation, see `mtu`.
+    probe_mtu: bool,
+}
+
+/// Where to send the packets received from an address.
+struct Route {
+    packets: mpsc::Sender<RawPacket>,
+    /// Signaled once the connection has been dropped.
+    shutdown: Shutdown,
+    /// The conditions simulated for the connection.
+    conditions: SimulatedConditions,
+}
+
+/// A packet to be sent by an endpoint.
+struct OutgoingPacket {
+    bytes: RawPacket,
+    addr: SocketAddr,
+    /// The statistics of the connection the packet belongs to.
+    stats: Arc<SharedStats>,
+}
+
+impl Default for SocketConfig {
+    fn default() -> Self {
+        SocketConfig {
+            send_buffer_size: Some(DEFAULT_BUFFER_SIZE),
+            recv_buffer_size: Some(DEFAULT_BUFFER_SIZE),
+            max_connections: 1024,
+            accept_backlog: 64,
+            connection: ConnectionConfig::default(),
+        }
+    }
 }
 
 impl Connection {
     /// Connect to a remote address and bind to a random local one.
     pub async fn connect(remote_addr: SocketAddr) -> Result<Connection> {
+        Self::connect_with_config(remote_addr, SocketConfig::default()).await
+    }
+
+    /// Connect to a remote address and bind to a random local one, encrypting all payloads. Fails
+    /// if the peer does not agree to encrypt payloads.
+    pub async fn connect_secure(remote_addr: SocketAddr) -> Result<Connection> {
+        let mut config = SocketConfig::default();
+        config.connection.require_encryption = true;
+        Self::connect_with_config(remote_addr, config).await
+    }
+
+    /// Connect to a remote address, using a socket with a specific configuration.
+    pub async fn connect_with_config(
+        remote_addr: SocketAddr,
+        config: SocketConfig,
+    ) -> Result<Connection> {
         let local_addr = (Ipv4Addr::new(0, 0, 0, 0), 0);
-        let socket = UdpSocket::bind(local_addr).await?;
+        let (socket, probe_mtu) = bind_socket(local_addr, config)?;
         socket.connect(remote_addr).await?;
         let (receiver, sender) = socket.split();
 
-        let (packet_tx, outgoing) = mpsc::channel(16);
-        let (incoming, packet_rx) = mpsc::channel(16);
-
-        tokio::spawn(Self::send_packets(sender, outgoing));
-        tokio::spawn(Self::recv_packets(receiver, incoming));
+        let capacity = config.connection.channel_capacity;
+        let (packet_tx, outgoing) = mpsc::channel(capacity);
+        let (incoming, packet_rx) = mpsc::channel(capacity);
+
+        let stats = Arc::new(SharedStats::default());
+        let (trigger, shutdown) = shutdown::channel();
+        let conditions = SimulatedConditions::default();
+
+        tokio::spawn(Self::send_packets(
+            sender,
+            outgoing,
+            remote_addr,
+            stats.clone(),
+        ));
+        tokio::spawn(Self::recv_packets(
+            receiver,
+            incoming,
+            remote_addr,
+            shutdown,
+            conditions.clone(),
+        ));
 
         let env = ConnectionEnv {
             peer_addr: remote_addr,
             packet_rx,
             packet_tx,
+            stats,
+            shutdown: trigger,
+            config: config.connection,
+            conditions,
+            probe_mtu,
         };
 
         Connection::establish(env).await.map_err(Error::Connect)
     }
 
     /// Receive packets from a channel and send them to the adressee.
-    async fn send_packets(mut socket: udp::SendHalf, mut packets: mpsc::Receiver<RawPacket>) {
+    async fn send_packets(
+        mut socket: udp::SendHalf,
+        mut packets: mpsc::Receiver<RawPacket>,
+        remote_addr: SocketAddr,
+        stats: Arc<SharedStats>,
+    ) {
         while let Some(packet) = packets.recv().await {
             log::trace!("sending {} bytes", packet.len());
-            if let Err(e) = socket.send(&packet).await {
+            capture::record(capture::Direction::Outbound, remote_addr, &packet);
+            if let Err(e) = send_datagram(&mut socket, &packet, None).await {
                 log::error!("failed to send packet: {:#}", e);
+                stats.send_failures.fetch_add(1, Ordering::Relaxed);
             }
         }
     }
 
-    async fn recv_packets(mut socket: udp::RecvHalf, mut packets: mpsc::Sender<RawPacket>) {
+    async fn recv_packets(
+        mut socket: udp::RecvHalf,
+        mut packets: mpsc::Sender<RawPacket>,
+        remote_addr: SocketAddr,
+        mut shutdown: Shutdown,
+        conditions: SimulatedConditions,
+    ) {
+        // the process-wide conditions apply, since the socket has none of its own
+        let socket_conditions = SimulatedConditions::default();
+
         const MAX_UDP_PACKET_SIZE: usize = 1 << 16;
         let mut buffer = vec![0; MAX_UDP_PACKET_SIZE];
 
         loop {
-            match socket.recv(&mut buffer).await {
+            let received = tokio::select! {
+                received = socket.recv(&mut buffer) => received,
+                () = shutdown.wait() => break,
+            };
+
+            match received {
                 Err(e) => {
                     log::error!("failed to receive packet: {:#}", e);
                     break;
                 }
                 Ok(len) => {
                     log::trace!("receiveing {} bytes...", len);
-
-                    use rand::Rng;
-                    if rand::thread_rng().gen_bool(PACKET_LOSS) {
-                        log::warn!("dropping packet");
-                        continue;
-                    }
+                    capture::record(capture::Direction::Inbound, remote_addr, &buffer[..len]);
 
                     let bytes = buffer[..len].to_vec();
-                    if packets.send(bytes).await.is_err() {
+                    if !dispatch(&mut packets, bytes, &socket_conditions, &conditions).await {
                         log::warn!("failed to dispatch packet: channel closed");
                         break;
                     }
@@ -100,31 +266,52 @@ impl Connection {
     }
 }
 
-impl Listener {
+impl Endpoint {
     /// Bind to a local address.
-    pub async fn bind<T>(local_addr: T) -> Result<Listener>
+    pub async fn bind<T>(local_addr: T) -> Result<Endpoint>
     where
         T: ToSocketAddrs,
     {
-        let socket = UdpSocket::bind(local_addr).await?;
+        Self::bind_with_config(local_addr, SocketConfig::default()).await
+    }
+
+    /// Bind to a local address, using a socket with a specific configuration.
+    pub async fn bind_with_config<T>(local_addr: T, config: SocketConfig) -> Result<Endpoint>
+    where
+        T: ToSocketAddrs,
+    {
+        let (socket, probe_mtu) = bind_socket(local_addr, config)?;
         let addr = socket.local_addr().ok();
         let (receiver, sender) = socket.split();
 
-        let (packet_tx, packet_rx) = mpsc::channel::<(Vec<_>, _)>(16);
-        let (connection_tx, connection_rx) = mpsc::channel(16);
+        let capacity = config.connection.channel_capacity;
+        let (packet_tx, packet_rx) = mpsc::channel(capacity);
+        let (connection_tx, connection_rx) = mpsc::channel(capacity);
+
+        let pending = Arc::new(AtomicUsize::new(0));
 
         let connections = ConnectionStore {
             connections: HashMap::new(),
-            listener: connection_tx,
+            endpoint: connection_tx,
+            pending: pending.clone(),
             packets: packet_tx,
+            config,
+            conditions: SimulatedConditions::default(),
+            probe_mtu,
         };
 
+        let (received_tx, received_rx) = mpsc::channel(capacity);
+
         tokio::spawn(Self::send_packets(sender, packet_rx));
-        tokio::spawn(Self::recv_packets(receiver, connections));
+        tokio::spawn(Self::recv_packets(receiver, received_tx));
+        let conditions = connections.conditions.clone();
+        tokio::spawn(Self::dispatch_packets(received_rx, connections));
 
-        Ok(Listener {
+        Ok(Endpoint {
             connections: connection_rx,
+            pending,
             addr,
+            conditions,
         })
     }
 
@@ -135,24 +322,38 @@ impl Listener {
 
     /// Accept an incoming connection.
     pub async fn accept(&mut self) -> Result<Connection> {
-        self.connections.recv().await.ok_or(Error::ConnectionClosed)
+        let connection = self.connections.recv().await;
+        let connection = connection.ok_or(Error::ConnectionClosed)?;
+        self.pending.fetch_sub(1, Ordering::Relaxed);
+        Ok(connection)
+    }
+
+    /// Simulate network conditions for the datagrams received by this endpoint, instead of the
+    /// process-wide ones set by `simulation::set_conditions`. If `None`, the process-wide
+    /// conditions apply again.
+    #[cfg(feature = "simulation")]
+    pub fn set_conditions(&self, conditions: Option<simulation::Conditions>) {
+        self.conditions.set(conditions);
     }
 
     /// Receive packets from a channel and send them to the adressee
-    async fn send_packets(
-        mut socket: udp::SendHalf,
-        mut packets: mpsc::Receiver<(RawPacket, SocketAddr)>,
-    ) {
-        while let Some((packet, addr)) = packets.recv().await {
-            log::trace!("sending {} bytes to [{}]", packet.len(), addr);
-            if let Err(e) = socket.send_to(&packet, &addr).await {
-                log::error!("failed to send packet: {:#}", e);
+    async fn send_packets(mut socket: udp::SendHalf, mut packets: mpsc::Receiver<OutgoingPacket>) {
+        while let Some(packet) = packets.recv().await {
+            let OutgoingPacket { bytes, addr, stats } = packet;
+            log::trace!("sending {} bytes to [{}]", bytes.len(), addr);
+            capture::record(capture::Direction::Outbound, addr, &bytes);
+            if let Err(e) = send_datagram(&mut socket, &bytes, Some(&addr)).await {
+                log::error!("failed to send packet to [{}]: {:#}", addr, e);
+                stats.send_failures.fetch_add(1, Ordering::Relaxed);
             }
         }
     }
 
-    /// Receive packets from a socket and send any new connections to the listener.
-    async fn recv_packets(mut socket: udp::RecvHalf, mut connections: ConnectionStore) {
+    /// Receive packets from a socket and pass them on to be dispatched.
+    async fn recv_packets(
+        mut socket: udp::RecvHalf,
+        mut packets: mpsc::Sender<(RawPacket, SocketAddr)>,
+    ) {
         const MAX_UDP_PACKET_SIZE: usize = 1 << 16;
         let mut buffer = vec![0; MAX_UDP_PACKET_SIZE];
 
@@ -161,66 +362,290 @@ impl Listener {
                 Err(e) => log::error!("failed to receive packet: {:#}", e),
                 Ok((len, addr)) => {
                     log::trace!("receiving {} bytes from [{}]", len, addr);
+                    capture::record(capture::Direction::Inbound, addr, &buffer[..len]);
                     let bytes = buffer[..len].to_vec();
 
-                    use rand::Rng;
-                    if rand::thread_rng().gen_bool(PACKET_LOSS) {
-                        log::warn!("dropping packet");
-                        continue;
+                    if packets.send((bytes, addr)).await.is_err() {
+                        log::warn!("failed to dispatch packet: channel closed");
+                        break;
                     }
-
-                    connections.send(bytes, addr).await;
                 }
             };
         }
     }
+
+    /// Send received packets to their connections and any new connections to the endpoint.
+    async fn dispatch_packets(
+        mut packets: mpsc::Receiver<(RawPacket, SocketAddr)>,
+        mut connections: ConnectionStore,
+    ) {
+        while let Some((bytes, addr)) = packets.recv().await {
+            connections.send(bytes, addr).await;
+        }
+    }
 }
 
 impl ConnectionStore {
     /// Send a packet to a client. If the client does not have an active connection, send a new
-    /// connection to the listener.
+    /// connection to the endpoint.
     pub async fn send(&mut self, packet: RawPacket, addr: SocketAddr) {
         let ConnectionStore {
             ref mut connections,
-            ref mut listener,
+            ref mut endpoint,
+            ref pending,
             ref packets,
-        } = self;
+            config,
+            ref conditions,
+            probe_mtu,
+        } = *self;
+
+        // the connection was dropped, so the peer is attempting to establish a new one
+        if let Some(route) = connections.get_mut(&addr) {
+            if route.shutdown.is_triggered() {
+                connections.remove(&addr);
+            }
+        }
 
-        let conn = connections.entry(addr).or_insert_with(|| {
-            let (a, b) = ConnectionEnv::pair(16, addr);
+        if !connections.contains_key(&addr) {
+            if let Some((header, body)) = Header::extract(&packet) {
+                if header.is_close() {
+                    // the connection is already gone, but the peer did not receive our
+                    // acknowledgement
+                    if !header.is_ack() && body.is_empty() {
+                        let ack = Header::close_ack().serialize().to_vec();
+                        Self::send_unrouted(packets, ack, addr).await;
+                    }
+                    return;
+                }
+            }
 
-            tokio::spawn(Self::accept_connection(b, listener.clone()));
+            // the rest of a connection that is already gone
+            if !connection::is_handshake(&packet) {
+                log::trace!("ignoring packet from [{}] without a connection", addr);
+                return;
+            }
+
+            connections.retain(|_, route| !route.shutdown.is_triggered());
+            let full = connections.len() >= config.max_connections;
+            if full || pending.load(Ordering::Relaxed) >= config.accept_backlog {
+                log::warn!("refusing connection from [{}]: server full", addr);
+                let refusal = packet::into_refusal(Refusal::Full);
+                Self::send_unrouted(packets, refusal, addr).await;
+                return;
+            }
+        }
+
+        let route = connections.entry(addr).or_insert_with(|| {
+            let (a, b) = ConnectionEnv::pair(addr, config.connection, probe_mtu);
+
+            let shutdown = b.shutdown.token();
+            let conditions = b.conditions.clone();
+            pending.fetch_add(1, Ordering::Relaxed);
+            tokio::spawn(Self::accept_connection(
+                b,
+                endpoint.clone(),
+                pending.clone(),
+            ));
 
             let mut packet_rx = a.packet_rx;
             let mut packet_tx = packets.clone();
+            let stats = a.stats;
             tokio::spawn(async move {
-                while let Some(packet) = packet_rx.recv().await {
-                    if packet_tx.send((packet, addr)).await.is_err() {
+                while let Some(bytes) = packet_rx.recv().await {
+                    let packet = OutgoingPacket {
+                        bytes,
+                        addr,
+                        stats: stats.clone(),
+                    };
+                    if packet_tx.send(packet).await.is_err() {
                         break;
                     }
                 }
             });
 
-            a.packet_tx
+            Route {
+                packets: a.packet_tx,
+                shutdown,
+                conditions,
+            }
         });
 
-        if conn.send(packet).await.is_err() {
+        if !dispatch(&mut route.packets, packet, conditions, &route.conditions).await {
             log::warn!("dropping connection to [{}]", addr);
             self.connections.remove(&addr);
         }
     }
 
-    async fn accept_connection(env: ConnectionEnv, mut listener: mpsc::Sender<Connection>) {
-        match timeout(CONNECTION_TIMEOUT, Connection::accept(env)).await {
+    /// Send a packet to an address without a connection.
+    async fn send_unrouted(
+        packets: &mpsc::Sender<OutgoingPacket>,
+        bytes: RawPacket,
+        addr: SocketAddr,
+    ) {
+        let packet = OutgoingPacket {
+            bytes,
+            addr,
+            stats: Arc::default(),
+        };
+        let _ = packets.clone().send(packet).await;
+    }
+
+    async fn accept_connection(
+        env: ConnectionEnv,
+        mut endpoint: mpsc::Sender<Connection>,
+        pending: Arc<AtomicUsize>,
+    ) {
+        // the client has this long to complete the handshake, from the moment its first packet
+        // arrives
+        match timeout(env.config.timeout, Connection::accept(env)).await {
             Err(_) => log::warn!("failed to accept connection: request timed out"),
             Ok(result) => match result {
                 Err(e) => log::error!("failed to accept connection: {:#}", e),
                 Ok(conn) => {
-                    if listener.send(conn).await.is_err() {
-                        log::warn!("failed to accept incoming connection: listener closed");
+                    // no longer pending once the endpoint receives it
+                    if endpoint.send(conn).await.is_ok() {
+                        return;
                     }
+                    log::warn!("failed to accept incoming connection: endpoint closed");
                 }
             },
         }
+
+        pending.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Hand a received datagram to a channel, subject to the simulated network conditions of the
+/// socket and connection it was received by. Returns `false` if the channel has been closed.
+#[cfg(feature = "simulation")]
+async fn dispatch<T>(
+    channel: &mut mpsc::Sender<T>,
+    datagram: T,
+    socket: &SimulatedConditions,
+    connection: &SimulatedConditions,
+) -> bool
+where
+    T: Clone + Send + 'static,
+{
+    simulation::dispatch(channel, datagram, socket, connection).await
+}
+
+/// Hand a received datagram to a channel. Returns `false` if the channel has been closed.
+#[cfg(not(feature = "simulation"))]
+async fn dispatch<T>(
+    channel: &mut mpsc::Sender<T>,
+    datagram: T,
+    _socket: &SimulatedConditions,
+    _connection: &SimulatedConditions,
+) -> bool {
+    channel.send(datagram).await.is_ok()
+}
+
+/// Bind a UDP socket to the first local address that succeeds. Also returns whether the socket
+/// forbids fragmentation.
+fn bind_socket(local_addr: impl ToSocketAddrs, config: SocketConfig) -> Result<(UdpSocket, bool)> {
+    let mut last_error = None;
+
+    for addr in local_addr.to_socket_addrs()? {
+        match configure_socket(addr, config) {
+            Ok(socket) => return Ok(socket),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    let error = last_error
+        .unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no addresses to bind to"));
+
+    Err(error.into())
+}
+
+/// Bind a UDP socket to an address and apply the configuration. Also returns whether the socket
+/// forbids fragmentation.
+fn configure_socket(addr: SocketAddr, config: SocketConfig) -> io::Result<(UdpSocket, bool)> {
+    let socket = Socket::from(std::net::UdpSocket::bind(addr)?);
+
+    let probe_mtu = match mtu::forbid_fragmentation(&socket, addr) {
+        Ok(()) => true,
+        Err(e) => {
+            log::warn!(
+                "failed to forbid fragmentation, sending packets of at most {} bytes: {}",
+                packet::MIN_PACKET_SIZE,
+                e
+            );
+            false
+        }
+    };
+
+    if let Some(size) = config.send_buffer_size {
+        if let Err(e) = socket.set_send_buffer_size(size) {
+            log::warn!(
+                "failed to set the send buffer size to {} bytes: {}",
+                size,
+                e
+            );
+        }
+    }
+
+    if let Some(size) = config.recv_buffer_size {
+        if let Err(e) = socket.set_recv_buffer_size(size) {
+            log::warn!(
+                "failed to set the receive buffer size to {} bytes: {}",
+                size,
+                e
+            );
+        }
     }
+
+    log::debug!(
+        "bound socket to [{}] (send buffer: {:?} bytes, receive buffer: {:?} bytes)",
+        addr,
+        socket.send_buffer_size().ok(),
+        socket.recv_buffer_size().ok(),
+    );
+
+    let socket = socket.into_udp_socket();
+    socket.set_nonblocking(true)?;
+    Ok((UdpSocket::from_std(socket)?, probe_mtu))
+}
+
+/// Send a packet, either to the connected address or to `target`. If the socket is temporarily
+/// unable to send the packet, it is retried a few times with an increasing delay.
+async fn send_datagram(
+    socket: &mut udp::SendHalf,
+    packet: &[u8],
+    target: Option<&SocketAddr>,
+) -> io::Result<()> {
+    let mut retries = 0;
+    let mut delay = SEND_RETRY_DELAY;
+
+    loop {
+        let result = match target {
+            Some(addr) => socket.send_to(packet, addr).await,
+            None => socket.send(packet).await,
+        };
+
+        match result {
+            Ok(_) => return Ok(()),
+            // lost like any other packet that does not fit the path, such as a probe
+            Err(e) if mtu::is_oversized(&e) => {
+                log::debug!("dropping packet of {} bytes: {}", packet.len(), e);
+                return Ok(());
+            }
+            Err(e) if is_transient(&e) && retries < SEND_RETRIES => {
+                log::debug!("failed to send packet, retrying in {:?}: {}", delay, e);
+                time::delay_for(delay).await;
+                retries += 1;
+                delay *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Determines if an error may go away by retrying the operation.
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted | io::ErrorKind::TimedOut
+    )
 }
@@ -0,0 +1,223 @@
+//! Discover the largest packet that reaches the peer, so that payloads are split into as few
+//! packets as possible.
+//!
+//! Every connection starts out sending packets of `MIN_PACKET_SIZE` bytes, which fit on every path.
+//! Meanwhile, it sends probes padded to larger sizes, binary searching between the largest size
+//! known to arrive and the smallest known not to. The peer acknowledges every probe it receives,
+//! and a probe that is not acknowledged in time is retried once before its size is considered too
+//! large. Once the search is narrow enough it stops, and starts over after a while in case the path
+//! has changed.
+//!
+//! Sockets forbid fragmenting their packets where the platform allows it, so that oversized probes
+//! are dropped along the way instead of arriving in fragments. On other platforms a probe could
+//! pass for a size that does not fit the path, so connections never search and keep sending packets
+//! of `MIN_PACKET_SIZE` bytes. If a packet of the discovered size has to be retransmitted
+//! repeatedly, the path is assumed to have shrunk, and the connection falls back to
+//! `MIN_PACKET_SIZE`.
+
+use crate::packet::{MAX_PACKET_SIZE, MIN_PACKET_SIZE};
+use socket2::Socket;
+use std::io;
+use std::net::SocketAddr;
+use tokio::time::Duration;
+
+/// How many times a probe of a given size is sent before the size is considered too large.
+const PROBE_ATTEMPTS: u32 = 2;
+
+/// The search stops once the sizes known to arrive and known not to are this close, in bytes.
+const SEARCH_PRECISION: usize = 16;
+
+/// How long to wait after a search has finished before searching again.
+pub(crate) const SEARCH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// How many times a packet larger than `MIN_PACKET_SIZE` may be retransmitted before falling back
+/// to `MIN_PACKET_SIZE`.
+const FALLBACK_RETRANSMITS: u32 = 3;
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct PathMtu {
+    /// The largest packet size known to arrive.
+    size: usize,
+    /// The smallest packet size known not to arrive.
+    ceiling: usize,
+    /// The size of the probe waiting for an acknowledgement.
+    probe: Option<usize>,
+    /// The number of probes sent of the current size.
+    attempts: u32,
+    /// Never search for larger sizes, see `PathMtu::fixed`.
+    fixed: bool,
+}
+
+impl Default for PathMtu {
+    fn default() -> Self {
+        PathMtu {
+            size: MIN_PACKET_SIZE,
+            ceiling: MAX_PACKET_SIZE + 1,
+            probe: None,
+            attempts: 0,
+            fixed: false,
+        }
+    }
+}
+
+impl PathMtu {
+    /// Stay at `MIN_PACKET_SIZE`, for sockets that can not forbid fragmentation.
+    pub fn fixed() -> Self {
+        PathMtu {
+            ceiling: MIN_PACKET_SIZE,
+            fixed: true,
+            ..PathMtu::default()
+        }
+    }
+
+    /// The largest packet size known to arrive.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The size of the next probe to send, if the search has not finished. Any probe still waiting
+    /// for an acknowledgement is considered lost.
+    pub fn next_probe(&mut self) -> Option<usize> {
+        if let Some(size) = self.probe.take() {
+            if self.attempts < PROBE_ATTEMPTS {
+                self.attempts += 1;
+                self.probe = Some(size);
+                return self.probe;
+            }
+            log::debug!("probes of {} bytes did not arrive", size);
+            self.ceiling = size;
+        }
+
+        if self.ceiling - self.size <= SEARCH_PRECISION {
+            return None;
+        }
+
+        self.attempts = 1;
+        self.probe = Some((self.size + self.ceiling) / 2);
+        self.probe
+    }
+
+    /// A probe of `size` bytes was acknowledged by the peer.
+    pub fn acknowledged(&mut self, size: usize) {
+        if self.probe == Some(size) {
+            self.probe = None;
+        }
+
+        if size > self.size && size < self.ceiling {
+            log::debug!("packets of {} bytes arrive", size);
+            self.size = size;
+        }
+    }
+
+    /// A packet of `size` bytes has been retransmitted `retransmits` times.
+    pub fn retransmitted(&mut self, size: usize, retransmits: u32) {
+        if size > MIN_PACKET_SIZE && retransmits >= FALLBACK_RETRANSMITS && size <= self.size {
+            log::warn!(
+                "packets of {} bytes stopped arriving, falling back to {} bytes",
+                size,
+                MIN_PACKET_SIZE
+            );
+            self.size = MIN_PACKET_SIZE;
+            self.ceiling = size;
+            self.probe = None;
+        }
+    }
+
+    /// Search again, in case larger packets arrive on the current path.
+    pub fn restart(&mut self) {
+        if !self.fixed {
+            self.ceiling = MAX_PACKET_SIZE + 1;
+        }
+    }
+}
+
+/// Set the don't fragment bit on every packet sent by the socket, so that packets larger than the
+/// path MTU are dropped.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn forbid_fragmentation(socket: &Socket, addr: SocketAddr) -> io::Result<()> {
+    // probing ignores the path MTU the kernel has cached, which is what is being discovered
+    match addr {
+        SocketAddr::V4(_) => set_option(
+            socket,
+            libc::IPPROTO_IP,
+            libc::IP_MTU_DISCOVER,
+            libc::IP_PMTUDISC_PROBE,
+        ),
+        SocketAddr::V6(_) => set_option(
+            socket,
+            libc::IPPROTO_IPV6,
+            libc::IPV6_MTU_DISCOVER,
+            libc::IPV6_PMTUDISC_PROBE,
+        ),
+    }
+}
+
+/// Set the don't fragment bit on every packet sent by the socket, so that packets larger than the
+/// path MTU are dropped.
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+pub(crate) fn forbid_fragmentation(socket: &Socket, addr: SocketAddr) -> io::Result<()> {
+    match addr {
+        SocketAddr::V4(_) => set_option(socket, libc::IPPROTO_IP, libc::IP_DONTFRAG, 1),
+        SocketAddr::V6(_) => set_option(socket, libc::IPPROTO_IPV6, libc::IPV6_DONTFRAG, 1),
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+)))]
+pub(crate) fn forbid_fragmentation(_socket: &Socket, _addr: SocketAddr) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "the platform does not support forbidding fragmentation",
+    ))
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+))]
+fn set_option(
+    socket: &Socket,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: the value outlives the call, which only reads `size_of::<c_int>()` bytes of it
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Whether sending failed because the packet does not fit the MTU of the interface, which the
+/// operating system reports instead of fragmenting it.
+pub(crate) fn is_oversized(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    return error.raw_os_error() == Some(libc::EMSGSIZE);
+
+    #[cfg(not(unix))]
+    return {
+        let _ = error;
+        false
+    };
+}
@@ -4,22 +4,61 @@ use thiserror::Error;
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// Errors that occur when splitting payloads into packets and reassembling them.
 #[derive(Debug, Copy, Clone, Error)]
 pub enum Error {
+    /// The payload is larger than `MAX_PAYLOAD_SIZE`.
     #[error("the payload limit of {MAX_PAYLOAD_SIZE} bytes was exceeded")]
     PayloadLimitExceeded,
 
-    #[error("the chunk exceeded it's maximum size: found {actual} expected {MAX_CHUNK_COUNT}")]
-    ChunkSizeExceeded { actual: usize },
-
-    #[error("the chunk did not fill up the packet: found {actual} expected {MAX_CHUNK_SIZE}")]
-    ChunkNotFull { actual: usize },
-
+    /// A chunk is larger than a packet may be.
+    #[error("the chunk exceeded it's maximum size: found {actual} expected {MAX_CHUNK_SIZE}")]
+    ChunkSizeExceeded {
+        /// The size of the chunk in bytes.
+        actual: usize,
+    },
+
+    /// A chunk other than the last one of its sequence differs in size from the others.
+    #[error("the chunk did not fill up the packet: found {actual} expected {expected}")]
+    ChunkNotFull {
+        /// The size of the chunk in bytes.
+        actual: usize,
+        /// The size of the other chunks in the sequence.
+        expected: usize,
+    },
+
+    /// The packet is too short to contain a header.
     #[error("invalid packet size, needs at least {HEADER_SIZE} bytes")]
     MissingHeader,
 
+    /// The last possible chunk of a sequence is not marked as being the last.
     #[error("found the final chunk id {MAX_CHUNK_INDEX} without the LAST_CHUNK flag")]
     MissingLastChunk,
+
+    /// The payload is too short to contain a checksum.
+    #[error("the payload is too short to contain a checksum")]
+    MissingChecksum,
+
+    /// The checksum of the payload does not match its contents.
+    #[error("checksum mismatch: found {actual:#010x} expected {expected:#010x}")]
+    ChecksumMismatch {
+        /// The checksum sent with the payload.
+        expected: u32,
+        /// The checksum of the received payload.
+        actual: u32,
+    },
+
+    /// A compressed payload is malformed, or decompresses to more than `MAX_PAYLOAD_SIZE` bytes.
+    #[error("failed to decompress the payload")]
+    InvalidCompression,
+
+    /// An ordered or sequenced payload is too short to contain its position.
+    #[error("the payload is too short to contain its position")]
+    MissingPosition,
+
+    /// A packet in a batch is longer than the rest of the batch.
+    #[error("a packet in the batch was truncated")]
+    TruncatedBatch,
 }
 
 /// The maximum number of chunks in a sequence.
@@ -28,17 +67,37 @@ pub const MAX_CHUNK_INDEX: u8 = u8::max_value();
 /// The maximum number of chunks in a sequence.
 pub const MAX_CHUNK_COUNT: usize = MAX_CHUNK_INDEX as usize + 1;
 
-/// The maximum size (in bytes) of a chunk's payload.
+/// The size (in bytes) of a packet that fits on every path.
 // The MTU is 576 bytes minimum. Subtract the largest IP header (60 bytes) and UDP header (8 bytes)
 // and you are left with 508 bytes for the packet.
-pub const MAX_CHUNK_SIZE: usize = 508 - HEADER_SIZE;
+pub const MIN_PACKET_SIZE: usize = 508;
+
+/// The largest packet (in bytes) that path MTU discovery will probe for.
+// The MTU of Ethernet is 1500 bytes. Subtract the IPv4 header (20 bytes) and UDP header (8 bytes).
+pub const MAX_PACKET_SIZE: usize = 1472;
+
+/// The size (in bytes) of a chunk's payload in a packet that fits on every path.
+pub const MIN_CHUNK_SIZE: usize = MIN_PACKET_SIZE - HEADER_SIZE;
+
+/// The maximum size (in bytes) of a chunk's payload.
+pub const MAX_CHUNK_SIZE: usize = MAX_PACKET_SIZE - HEADER_SIZE;
 
-/// The maximum size of a payload. A payload with more bytes can not be split into chunks.
-pub const MAX_PAYLOAD_SIZE: usize = MAX_CHUNK_COUNT * MAX_CHUNK_SIZE;
+/// The maximum size of a payload. A payload with more bytes can not be split into chunks, even on
+/// paths that only support the smallest packets.
+pub const MAX_PAYLOAD_SIZE: usize = MAX_CHUNK_COUNT * MIN_CHUNK_SIZE;
 
 /// The size of the packet header, in bytes.
 pub const HEADER_SIZE: usize = 4;
 
+/// The size of the checksum appended to payloads, in bytes.
+pub const CHECKSUM_SIZE: usize = 4;
+
+/// The size of the position at the start of ordered and sequenced payloads, in bytes.
+pub const POSITION_SIZE: usize = 2;
+
+/// The size of the length preceding every packet in a batch, in bytes.
+pub const FRAME_LENGTH_SIZE: usize = 2;
+
 // TODO: replace with an enum with discriminants
 bitflags! {
     pub struct Flags: u8 {
@@ -51,11 +110,51 @@ bitflags! {
         /// This is the last chunk of the message.
         const LAST_CHUNK = 1 << 2;
 
-        /// The connection has been closed.
-        const CLOSE = 1 << 3;
+        /// This packet controls the connection rather than carrying a payload. The chunk is the
+        /// kind of control packet, see `Control`.
+        const CONTROL = 1 << 3;
+
+        /// The payload of the sequence ends with a CRC32 checksum of the preceding bytes.
+        const CHECKSUM = 1 << 4;
+
+        /// The payload of the sequence starts with its position among the ordered payloads, see
+        /// `prepend_position`.
+        const ORDERED = 1 << 5;
+
+        /// The payload of the sequence starts with its position among the sequenced payloads, see
+        /// `prepend_position`.
+        const SEQUENCED = 1 << 6;
+
+        /// The payload of the sequence is compressed with `compress`.
+        const COMPRESSED = 1 << 7;
     }
 }
 
+/// The kinds of packets with the `CONTROL` flag, stored in the chunk of the header.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Control {
+    /// The connection has been closed. Retransmitted until the peer acknowledges it.
+    Close = 0,
+    /// This packet probes whether packets of its size reach the peer. The sequence is the size of
+    /// the probe in bytes.
+    Probe = 1,
+    /// This packet only tells the peer that the connection is still alive.
+    Heartbeat = 2,
+    /// This packet contains several smaller packets, see `into_batch`.
+    Batch = 3,
+    /// This packet asks the peer for new keys, or acknowledges such a request, see `crypto`. The
+    /// sequence is allocated like that of a payload, and the packet is retransmitted until
+    /// acknowledged like the chunks of reliable payloads.
+    Rekey = 4,
+}
+
+/// Why a connection was refused, stored in the body of a CLOSE packet, see `into_refusal`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Refusal {
+    /// The server already has as many connections as it allows, or as many waiting to be accepted.
+    Full = 0,
+}
+
 // TODO: use a separate system for large chunks and "messages"
 /// The header of every packet.
 #[derive(Debug, Copy, Clone)]
@@ -75,18 +174,34 @@ pub(crate) struct PacketId {
 #[derive(Clone)]
 pub(crate) struct Sequence {
     max_chunks: usize,
-    payload: Vec<u8>,
+    /// The chunks received so far, by index. Chunks are only joined once all have arrived, since
+    /// the position of the last chunk depends on the size of the others.
+    chunks: Vec<Vec<u8>>,
+    /// The size of every chunk but the last, once one of them has arrived.
+    chunk_size: Option<usize>,
+    /// The number of bytes in all chunks.
+    buffered: usize,
     received: [bool; MAX_CHUNK_COUNT],
+    /// The flags describing the payload, rather than a single chunk.
+    flags: Flags,
 }
 
-/// Split a payload into a sequence of chunks.
-pub(crate) fn into_chunks(sequence: u16, payload: &[u8]) -> Result<Vec<(Header, &[u8])>> {
+/// Split a payload into a sequence of chunks of `chunk_size` bytes, every one with `flags` set. If
+/// `flags` contains `CHECKSUM`, the payload is expected to end with a checksum appended by
+/// `append_checksum`.
+pub(crate) fn into_chunks(
+    sequence: u16,
+    payload: &[u8],
+    flags: Flags,
+    chunk_size: usize,
+) -> Result<Vec<(Header, &[u8])>> {
     let mut payloads = payload
-        .chunks(MAX_CHUNK_SIZE)
+        .chunks(chunk_size)
         .enumerate()
         .map(|(i, chunk)| -> Result<_> {
             let chunk_id = i.try_into().map_err(|_| Error::PayloadLimitExceeded)?;
-            let header = Header::new(sequence, chunk_id);
+            let mut header = Header::new(sequence, chunk_id);
+            header.flags.insert(flags);
             Ok((header, chunk))
         })
         .collect::<Result<Vec<_>>>()?;
@@ -98,6 +213,125 @@ pub(crate) fn into_chunks(sequence: u16, payload: &[u8]) -> Result<Vec<(Header,
     Ok(payloads)
 }
 
+/// Append a CRC32 checksum of the payload to its end.
+pub(crate) fn append_checksum(payload: &mut Vec<u8>) {
+    let checksum = crc32fast::hash(payload);
+    payload.extend_from_slice(&checksum.to_be_bytes());
+}
+
+/// Compress a payload, unless that does not make it smaller. The compressed payload is the size of
+/// the payload (32-bit big endian) followed by the payload compressed as an LZ4 block.
+pub(crate) fn compress(payload: &[u8]) -> Option<Vec<u8>> {
+    let mut compressed = (payload.len() as u32).to_be_bytes().to_vec();
+    compressed.extend_from_slice(&lz4_flex::compress(payload));
+    if compressed.len() < payload.len() {
+        Some(compressed)
+    } else {
+        None
+    }
+}
+
+/// Decompress a payload compressed by `compress`.
+pub(crate) fn decompress(compressed: &[u8]) -> Result<Vec<u8>> {
+    if compressed.len() < 4 {
+        return Err(Error::InvalidCompression);
+    }
+
+    let (size, block) = compressed.split_at(4);
+    let size = u32::from_be_bytes(size.try_into().unwrap()) as usize;
+    // check before allocating, in case the size is far larger than the payload
+    if size > MAX_PAYLOAD_SIZE {
+        return Err(Error::InvalidCompression);
+    }
+
+    match lz4_flex::decompress(block, size) {
+        Ok(payload) if payload.len() == size => Ok(payload),
+        _ => Err(Error::InvalidCompression),
+    }
+}
+
+/// Prepend the position of an ordered or sequenced payload among the others delivered the same way.
+pub(crate) fn prepend_position(payload: &mut Vec<u8>, position: u16) {
+    payload.splice(0..0, position.to_be_bytes().iter().copied());
+}
+
+/// Remove the position from the start of an ordered or sequenced payload.
+pub(crate) fn split_position(mut payload: Vec<u8>) -> Result<(u16, Vec<u8>)> {
+    if payload.len() < POSITION_SIZE {
+        return Err(Error::MissingPosition);
+    }
+
+    let position = u16::from_be_bytes(payload[..POSITION_SIZE].try_into().unwrap());
+    payload.drain(..POSITION_SIZE);
+    Ok((position, payload))
+}
+
+/// Join several packets into a single one. Every packet is preceded by its length (16-bit big
+/// endian).
+pub(crate) fn into_batch(packets: &[Vec<u8>]) -> Vec<u8> {
+    let mut batch = Header::batch().serialize().to_vec();
+    for packet in packets {
+        batch.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+        batch.extend_from_slice(packet);
+    }
+    batch
+}
+
+/// Split the body of a batch into the packets joined by `into_batch`.
+pub(crate) fn split_batch(mut body: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut packets = Vec::new();
+    while !body.is_empty() {
+        if body.len() < FRAME_LENGTH_SIZE {
+            return Err(Error::TruncatedBatch);
+        }
+
+        let (length, rest) = body.split_at(FRAME_LENGTH_SIZE);
+        let length = u16::from_be_bytes(length.try_into().unwrap()) as usize;
+        if rest.len() < length {
+            return Err(Error::TruncatedBatch);
+        }
+
+        let (packet, rest) = rest.split_at(length);
+        packets.push(packet);
+        body = rest;
+    }
+    Ok(packets)
+}
+
+/// Refuse to establish a connection. No handshake message has the size of the packet, so that a
+/// refused handshake can be told apart from one in progress.
+pub(crate) fn into_refusal(reason: Refusal) -> Vec<u8> {
+    let mut packet = Header::close().serialize().to_vec();
+    packet.push(reason as u8);
+    packet
+}
+
+/// Why a packet refused to establish a connection, if it is one made by `into_refusal`.
+pub(crate) fn parse_refusal(packet: &[u8]) -> Option<Refusal> {
+    match Header::extract(packet)? {
+        (header, [0]) if header.is_close() && !header.is_ack() => Some(Refusal::Full),
+        _ => None,
+    }
+}
+
+/// Verify and remove the checksum at the end of a payload.
+pub(crate) fn verify_checksum(mut payload: Vec<u8>) -> Result<Vec<u8>> {
+    if payload.len() < CHECKSUM_SIZE {
+        return Err(Error::MissingChecksum);
+    }
+
+    let split = payload.len() - CHECKSUM_SIZE;
+    let expected = u32::from_be_bytes(payload[split..].try_into().unwrap());
+    payload.truncate(split);
+
+    let actual = crc32fast::hash(&payload);
+    if actual == expected {
+        Ok(payload)
+    } else {
+        Err(Error::ChecksumMismatch { expected, actual })
+    }
+}
+
 impl Header {
     /// Create a new packet with a specific sequence number and chunk id.
     pub fn new(seq: u16, chunk: u8) -> Self {
@@ -117,15 +351,64 @@ impl Header {
         }
     }
 
-    /// Close the packet stream.
-    pub fn close() -> Self {
+    /// A control packet of some kind.
+    fn control(kind: Control, seq: u16) -> Self {
         Header {
-            flags: Flags::CLOSE | Flags::LAST_CHUNK,
-            seq: 0,
-            chunk: 0,
+            flags: Flags::CONTROL | Flags::LAST_CHUNK,
+            seq,
+            chunk: kind as u8,
         }
     }
 
+    /// Probe whether packets of `size` bytes reach the peer. The probe is padded to its size by the
+    /// sender.
+    pub fn probe(size: u16) -> Self {
+        Header::control(Control::Probe, size)
+    }
+
+    /// Acknowledge that a probe of `size` bytes arrived.
+    pub fn probe_ack(size: u16) -> Self {
+        let mut header = Header::control(Control::Probe, size);
+        header.flags.insert(Flags::ACK);
+        header
+    }
+
+    /// Ask the peer for new keys, in the sequence `seq`.
+    pub fn rekey(seq: u16) -> Self {
+        let mut header = Header::control(Control::Rekey, seq);
+        header.flags.insert(Flags::NEEDS_ACK);
+        header
+    }
+
+    /// Answer a request for new keys, in the sequence `seq`.
+    pub fn rekey_ack(seq: u16) -> Self {
+        let mut header = Header::rekey(seq);
+        header.flags.insert(Flags::ACK);
+        header
+    }
+
+    /// Keep the connection alive while there is nothing else to send.
+    pub fn heartbeat() -> Self {
+        Header::control(Control::Heartbeat, 0)
+    }
+
+    /// Carry several smaller packets in a single one, see `into_batch`.
+    pub fn batch() -> Self {
+        Header::control(Control::Batch, 0)
+    }
+
+    /// Close the packet stream.
+    pub fn close() -> Self {
+        Header::control(Control::Close, 0)
+    }
+
+    /// Acknowledge that the packet stream was closed.
+    pub fn close_ack() -> Self {
+        let mut header = Header::close();
+        header.flags.insert(Flags::ACK);
+        header
+    }
+
     pub fn needs_ack(self) -> bool {
         self.flags.contains(Flags::NEEDS_ACK)
     }
@@ -134,8 +417,40 @@ impl Header {
         self.flags.contains(Flags::ACK)
     }
 
+    /// The kind of control packet, if this is one. Unknown kinds are ignored.
+    pub fn control_kind(self) -> Option<Control> {
+        if !self.flags.contains(Flags::CONTROL) {
+            return None;
+        }
+
+        match self.chunk {
+            0 => Some(Control::Close),
+            1 => Some(Control::Probe),
+            2 => Some(Control::Heartbeat),
+            3 => Some(Control::Batch),
+            4 => Some(Control::Rekey),
+            _ => None,
+        }
+    }
+
+    pub fn is_control(self) -> bool {
+        self.flags.contains(Flags::CONTROL)
+    }
+
     pub fn is_close(self) -> bool {
-        self.flags.contains(Flags::CLOSE)
+        self.control_kind() == Some(Control::Close)
+    }
+
+    pub fn is_probe(self) -> bool {
+        self.control_kind() == Some(Control::Probe)
+    }
+
+    pub fn is_rekey(self) -> bool {
+        self.control_kind() == Some(Control::Rekey)
+    }
+
+    pub fn is_batch(self) -> bool {
+        self.control_kind() == Some(Control::Batch)
     }
 
     pub fn chunk_id(self) -> PacketId {
@@ -183,14 +498,33 @@ impl Sequence {
     pub fn new() -> Self {
         Sequence {
             max_chunks: MAX_CHUNK_COUNT,
-            payload: Vec::new(),
+            chunks: Vec::new(),
+            chunk_size: None,
+            buffered: 0,
             received: [false; MAX_CHUNK_COUNT],
+            flags: Flags::empty(),
         }
     }
 
-    /// Get the current payload.
-    pub fn payload(self) -> Vec<u8> {
-        self.payload
+    /// Get the current payload, verifying its checksum if it has one.
+    pub fn payload(mut self) -> Result<Vec<u8>> {
+        self.chunks.truncate(self.max_chunks);
+        let payload = self.chunks.concat();
+        if self.flags.contains(Flags::CHECKSUM) {
+            verify_checksum(payload)
+        } else {
+            Ok(payload)
+        }
+    }
+
+    /// The flags describing the payload, such as whether it is compressed or ordered.
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    /// The number of bytes buffered for the payload so far.
+    pub fn buffered_size(&self) -> usize {
+        self.buffered
     }
 
     /// Sets index of the last expected chunk. This is used to determine if the sequence is complete
@@ -214,28 +548,40 @@ impl Sequence {
             });
         }
 
+        // the receiver sets aside room for the largest payload, and no more
+        let previous = self.chunks.get(header.chunk as usize).map_or(0, Vec::len);
+        if self.buffered - previous + chunk.len() > MAX_PAYLOAD_SIZE {
+            return Err(Error::PayloadLimitExceeded);
+        }
+
         if header.flags.contains(Flags::LAST_CHUNK) {
             self.set_last_packet(header.chunk);
-        } else if chunk.len() != MAX_CHUNK_SIZE {
-            return Err(Error::ChunkNotFull {
-                actual: chunk.len(),
-            });
         } else if header.chunk == u8::max_value() {
             return Err(Error::MissingLastChunk);
+        } else {
+            let expected = *self.chunk_size.get_or_insert(chunk.len());
+            if chunk.len() != expected {
+                return Err(Error::ChunkNotFull {
+                    actual: chunk.len(),
+                    expected,
+                });
+            }
         }
 
         let chunk_index = header.chunk as usize;
 
         self.received[chunk_index] = true;
+        self.flags |= header.flags
+            & (Flags::CHECKSUM | Flags::COMPRESSED | Flags::ORDERED | Flags::SEQUENCED);
 
-        let insert_start = MAX_CHUNK_SIZE * chunk_index;
-        let required_size = insert_start + chunk.len();
-
-        if self.payload.len() < required_size {
-            self.payload.resize(required_size, 0);
+        if self.chunks.len() <= chunk_index {
+            self.chunks.resize(chunk_index + 1, Vec::new());
         }
 
-        self.payload[insert_start..required_size].copy_from_slice(chunk);
+        let entry = &mut self.chunks[chunk_index];
+        self.buffered = self.buffered - entry.len() + chunk.len();
+        entry.clear();
+        entry.extend_from_slice(chunk);
 
         Ok(())
     }
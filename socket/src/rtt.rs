@@ -0,0 +1,74 @@
+//! Estimate the round-trip time of a connection, and how long to wait for an acknowledgement
+//! before retransmitting a packet.
+//!
+//! The estimate follows RFC 6298: a smoothed round-trip time and its variation are updated from
+//! every acknowledged packet that was only sent once, and the retransmission timeout is the
+//! smoothed round-trip time plus four times its variation. Every retransmission of the same packet
+//! doubles the timeout, so a connection with a congested or lost link backs off instead of flooding
+//! it with copies.
+
+use tokio::time::Duration;
+
+/// The shortest time to wait before retransmitting a packet. Keeps the timeout above the time it
+/// takes the peer to process a packet on very fast links.
+const MIN_RETRANSMIT_DELAY: Duration = Duration::from_millis(20);
+
+/// The longest time to wait before retransmitting a packet, even after backing off.
+const MAX_RETRANSMIT_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct RttEstimator {
+    /// The smoothed round-trip time, if any round-trip has been measured.
+    smoothed: Option<Duration>,
+    /// How much the round-trip time varies around the smoothed value.
+    variation: Duration,
+    /// How long to wait before retransmitting a packet, until the round-trip time has been
+    /// measured.
+    initial_delay: Duration,
+}
+
+impl RttEstimator {
+    pub fn new(initial_delay: Duration) -> Self {
+        RttEstimator {
+            smoothed: None,
+            variation: Duration::from_millis(0),
+            initial_delay,
+        }
+    }
+
+    /// The smoothed round-trip time, if any round-trip has been measured.
+    pub fn smoothed(&self) -> Option<Duration> {
+        self.smoothed
+    }
+
+    /// Update the estimate with a measured round-trip. Only packets that were sent once should be
+    /// measured, since the acknowledgement of a retransmitted packet may belong to any copy.
+    pub fn update(&mut self, sample: Duration) {
+        match self.smoothed {
+            None => {
+                self.smoothed = Some(sample);
+                self.variation = sample / 2;
+            }
+            Some(smoothed) => {
+                let deviation = smoothed.abs_diff(sample);
+                self.variation = (3 * self.variation + deviation) / 4;
+                self.smoothed = Some((7 * smoothed + sample) / 8);
+            }
+        }
+    }
+
+    /// How long to wait for an acknowledgement of a packet that has already been retransmitted
+    /// `retransmits` times.
+    pub fn retransmit_delay(&self, retransmits: u32) -> Duration {
+        let timeout = match self.smoothed {
+            None => self.initial_delay,
+            Some(smoothed) => smoothed + 4 * self.variation,
+        };
+
+        let backoff = 1u32 << retransmits.min(16);
+        let delay = timeout.max(MIN_RETRANSMIT_DELAY).checked_mul(backoff);
+        delay
+            .unwrap_or(MAX_RETRANSMIT_DELAY)
+            .min(MAX_RETRANSMIT_DELAY)
+    }
+}
@@ -0,0 +1,68 @@
+//! Signal a group of tasks to stop what they are doing.
+//!
+//! Every task that should stop holds a `Shutdown` token, and selects on `Shutdown::wait` together
+//! with its regular work. The tokens are signaled once their `ShutdownTrigger` is triggered or
+//! dropped, so tasks never outlive whatever owns the trigger.
+
+use futures::FutureExt;
+use tokio::sync::watch;
+
+/// Signals all associated `Shutdown` tokens when triggered or dropped.
+#[derive(Debug)]
+pub struct ShutdownTrigger {
+    sender: watch::Sender<bool>,
+    token: Shutdown,
+}
+
+/// Notifies a task that it should stop.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    receiver: watch::Receiver<bool>,
+    triggered: bool,
+}
+
+/// Create a new trigger and a token that is signaled by it.
+pub fn channel() -> (ShutdownTrigger, Shutdown) {
+    let (sender, receiver) = watch::channel(false);
+    let token = Shutdown {
+        receiver,
+        triggered: false,
+    };
+
+    let trigger = ShutdownTrigger {
+        sender,
+        token: token.clone(),
+    };
+
+    (trigger, token)
+}
+
+impl ShutdownTrigger {
+    /// Signal all tokens.
+    pub fn trigger(self) {
+        let _ = self.sender.broadcast(true);
+    }
+
+    /// Get another token that is signaled by this trigger.
+    pub fn token(&self) -> Shutdown {
+        self.token.clone()
+    }
+}
+
+impl Shutdown {
+    /// Wait until the trigger is triggered or dropped.
+    pub async fn wait(&mut self) {
+        while !self.triggered {
+            match self.receiver.recv().await {
+                Some(false) => {}
+                Some(true) | None => self.triggered = true,
+            }
+        }
+    }
+
+    /// Has the trigger been triggered or dropped? Does not wait.
+    pub fn is_triggered(&mut self) -> bool {
+        let _ = self.wait().now_or_never();
+        self.triggered
+    }
+}
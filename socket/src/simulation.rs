@@ -0,0 +1,195 @@
+//! Artificially degrade the network conditions of sockets, for testing purposes.
+//!
+//! The conditions are applied to every received datagram: datagrams may be dropped, duplicated or
+//! delayed before they are handed to their connection. Since the delay of each datagram is random
+//! when jitter is enabled, datagrams may also be reordered.
+//!
+//! The conditions set by [`set_conditions`] apply to every socket in the process. They may be
+//! replaced for a single endpoint with `Endpoint::set_conditions`, and every connection may add
+//! conditions of its own on top with `Connection::set_conditions`.
+
+use rand::Rng;
+use std::fmt::{self, Display, Formatter};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::time::{self, Duration};
+
+/// The network conditions currently being simulated.
+static CONDITIONS: Mutex<Conditions> = Mutex::new(Conditions::IDEAL);
+
+/// The shortest time a reordered datagram is held back for, in addition to its latency.
+const MIN_REORDER_DELAY: Duration = Duration::from_millis(10);
+
+/// Network conditions to simulate.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Conditions {
+    /// The probability of a datagram being dropped, in the range 0 to 1.
+    pub loss: f64,
+    /// Time added to the arrival of every datagram.
+    pub latency: Duration,
+    /// The maximum amount of time the latency may randomly vary by, in either direction.
+    pub jitter: Duration,
+    /// The probability of a datagram arriving twice, in the range 0 to 1. Each copy is delayed
+    /// separately.
+    pub duplicate: f64,
+    /// The probability of a datagram being held back until after the ones received shortly after
+    /// it, in the range 0 to 1.
+    pub reorder: f64,
+}
+
+/// Conditions that may be changed while they are being simulated, or left unset.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SharedConditions(Arc<Mutex<Option<Conditions>>>);
+
+impl Conditions {
+    /// No artificial loss or delay.
+    pub const IDEAL: Conditions = Conditions {
+        loss: 0.0,
+        latency: Duration::from_millis(0),
+        jitter: Duration::from_millis(0),
+        duplicate: 0.0,
+        reorder: 0.0,
+    };
+
+    /// Are the conditions free of any artificial loss or delay?
+    pub fn is_ideal(&self) -> bool {
+        *self == Conditions::IDEAL
+    }
+
+    /// The conditions of a datagram that passes through these conditions, and then `other`.
+    pub fn then(self, other: Conditions) -> Conditions {
+        let either = |a: f64, b: f64| 1.0 - (1.0 - a) * (1.0 - b);
+        Conditions {
+            loss: either(self.loss, other.loss),
+            latency: self.latency + other.latency,
+            jitter: self.jitter + other.jitter,
+            duplicate: either(self.duplicate, other.duplicate),
+            reorder: either(self.reorder, other.reorder),
+        }
+    }
+
+    fn should_drop(&self) -> bool {
+        happens(self.loss)
+    }
+
+    fn should_duplicate(&self) -> bool {
+        happens(self.duplicate)
+    }
+
+    /// The amount of time to delay a datagram by.
+    fn delay(&self) -> Duration {
+        let jitter = self.jitter.as_secs_f64();
+        let delay = if jitter == 0.0 {
+            self.latency
+        } else {
+            let offset = rand::thread_rng().gen_range(-jitter, jitter);
+            let delay = self.latency.as_secs_f64() + offset;
+            Duration::from_secs_f64(delay.max(0.0))
+        };
+
+        if happens(self.reorder) {
+            // late enough to arrive after any datagram received while this one is delayed
+            delay + (self.latency + 2 * self.jitter).max(MIN_REORDER_DELAY)
+        } else {
+            delay
+        }
+    }
+}
+
+impl Default for Conditions {
+    fn default() -> Self {
+        Conditions::IDEAL
+    }
+}
+
+impl Display for Conditions {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.0}% loss, {}±{} ms",
+            100.0 * self.loss,
+            self.latency.as_millis(),
+            self.jitter.as_millis()
+        )?;
+
+        if self.duplicate > 0.0 {
+            write!(f, ", {:.0}% duplicated", 100.0 * self.duplicate)?;
+        }
+        if self.reorder > 0.0 {
+            write!(f, ", {:.0}% reordered", 100.0 * self.reorder)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl SharedConditions {
+    pub fn get(&self) -> Option<Conditions> {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set(&self, conditions: Option<Conditions>) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = conditions;
+    }
+}
+
+/// Does an event with a probability in the range 0 to 1 happen?
+fn happens(probability: f64) -> bool {
+    probability > 0.0 && rand::thread_rng().gen_bool(probability.min(1.0))
+}
+
+/// Get the network conditions currently being simulated.
+pub fn conditions() -> Conditions {
+    *CONDITIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Simulate new network conditions, replacing the previous ones.
+pub fn set_conditions(conditions: Conditions) {
+    *CONDITIONS.lock().unwrap_or_else(|e| e.into_inner()) = conditions;
+}
+
+/// Hand a received datagram to a channel, subject to the conditions of the socket, or the
+/// process-wide ones if it has none, and then those of the connection. Returns `false` if the
+/// channel has been closed.
+pub(crate) async fn dispatch<T>(
+    channel: &mut mpsc::Sender<T>,
+    datagram: T,
+    socket: &SharedConditions,
+    connection: &SharedConditions,
+) -> bool
+where
+    T: Clone + Send + 'static,
+{
+    let socket = socket.get().unwrap_or_else(conditions);
+    let conditions = socket.then(connection.get().unwrap_or_default());
+
+    if conditions.should_drop() {
+        log::trace!("dropping packet");
+        return true;
+    }
+
+    if conditions.should_duplicate() {
+        log::trace!("duplicating packet");
+        deliver(channel, datagram.clone(), conditions.delay()).await;
+    }
+
+    deliver(channel, datagram, conditions.delay()).await
+}
+
+/// Hand a datagram to a channel after a delay. Returns `false` if the channel has been closed.
+async fn deliver<T>(channel: &mut mpsc::Sender<T>, datagram: T, delay: Duration) -> bool
+where
+    T: Send + 'static,
+{
+    if delay == Duration::from_millis(0) {
+        return channel.send(datagram).await.is_ok();
+    }
+
+    let mut channel = channel.clone();
+    tokio::spawn(async move {
+        time::delay_for(delay).await;
+        let _ = channel.send(datagram).await;
+    });
+
+    true
+}
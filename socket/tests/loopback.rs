@@ -0,0 +1,444 @@
+use socket::error::{ConnectionError, Error};
+use socket::{Connection, ConnectionConfig, Delivery, Endpoint, SocketConfig, MAX_PAYLOAD_SIZE};
+use std::net::SocketAddr;
+use tokio::time::{self, Duration};
+
+async fn connect() -> (Connection, Connection) {
+    connect_with_config(ConnectionConfig::default()).await
+}
+
+/// Connect a client and server that both use `config` for their connection.
+async fn connect_with_config(config: ConnectionConfig) -> (Connection, Connection) {
+    let config = SocketConfig {
+        connection: config,
+        ..SocketConfig::default()
+    };
+
+    let mut endpoint = Endpoint::bind_with_config("127.0.0.1:0", config)
+        .await
+        .unwrap();
+    let addr = endpoint.local_addr().unwrap();
+
+    let client = Connection::connect_with_config(addr, config).await.unwrap();
+    let server = endpoint.accept().await.unwrap();
+
+    (client, server)
+}
+
+/// Connect a client and server that coalesce their packets over `window`.
+async fn connect_coalesced(window: Duration) -> (Connection, Connection) {
+    connect_with_config(ConnectionConfig {
+        coalesce_window: Some(window),
+        ..ConnectionConfig::default()
+    })
+    .await
+}
+
+/// Bytes that do not compress, so that they are sent in as many packets as their length needs.
+fn incompressible(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_u32;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+/// Bind an endpoint that keeps at most `max_connections`, and `accept_backlog` of them waiting to
+/// be accepted.
+async fn bind_limited(max_connections: usize, accept_backlog: usize) -> (Endpoint, SocketAddr) {
+    let config = SocketConfig {
+        max_connections,
+        accept_backlog,
+        ..SocketConfig::default()
+    };
+
+    let endpoint = Endpoint::bind_with_config("127.0.0.1:0", config)
+        .await
+        .unwrap();
+    let addr = endpoint.local_addr().unwrap();
+    (endpoint, addr)
+}
+
+fn is_server_full<T>(result: Result<T, Error>) -> bool {
+    matches!(result, Err(Error::Connect(ConnectionError::ServerFull)))
+}
+
+#[tokio::test]
+async fn small_payload() {
+    let (mut client, mut server) = connect().await;
+
+    client
+        .send(b"hello".to_vec(), Delivery::Reliable)
+        .await
+        .unwrap();
+    assert_eq!(server.recv().await, Some(b"hello".to_vec()));
+
+    server
+        .send(b"world".to_vec(), Delivery::BestEffort)
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await, Some(b"world".to_vec()));
+}
+
+#[tokio::test]
+async fn payload_spanning_many_packets() {
+    let (mut client, mut server) = connect().await;
+
+    // leave room for the checksum
+    let payload = incompressible(MAX_PAYLOAD_SIZE - 4);
+
+    client
+        .send(payload.clone(), Delivery::Reliable)
+        .await
+        .unwrap();
+    assert_eq!(server.recv().await, Some(payload));
+}
+
+#[tokio::test]
+async fn shutdown_closes_peer() {
+    let (client, mut server) = connect().await;
+
+    client.shutdown().await.unwrap();
+    assert_eq!(server.recv().await, None);
+}
+
+#[tokio::test]
+async fn shutdown_waits_for_reliable_payloads() {
+    let (mut client, mut server) = connect().await;
+
+    for i in 0..8u8 {
+        client
+            .send(incompressible(3000 + i as usize), Delivery::Reliable)
+            .await
+            .unwrap();
+    }
+    let (shutdown, received) = tokio::join!(client.shutdown(), async {
+        let mut received = 0;
+        while server.recv().await.is_some() {
+            received += 1;
+        }
+        received
+    });
+
+    shutdown.unwrap();
+    assert_eq!(received, 8);
+}
+
+#[tokio::test]
+async fn round_trip_time_measured() {
+    let (mut client, mut server) = connect().await;
+    assert_eq!(client.rtt(), None);
+
+    client
+        .send(b"ping".to_vec(), Delivery::Reliable)
+        .await
+        .unwrap();
+    assert_eq!(server.recv().await, Some(b"ping".to_vec()));
+
+    // the acknowledgement of the ping is sent before the pong
+    server
+        .send(b"pong".to_vec(), Delivery::BestEffort)
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await, Some(b"pong".to_vec()));
+    assert!(client.rtt().is_some());
+}
+
+#[tokio::test]
+async fn burst_larger_than_congestion_window() {
+    let (mut client, mut server) = connect().await;
+
+    let payload = incompressible(MAX_PAYLOAD_SIZE - 4);
+    for _ in 0..4 {
+        client
+            .send(payload.clone(), Delivery::Reliable)
+            .await
+            .unwrap();
+    }
+
+    // the payloads are far larger than the initial window, so most packets wait for acks
+    for _ in 0..4 {
+        assert_eq!(server.recv().await.as_ref(), Some(&payload));
+    }
+    assert!(client.stats().congestion_window > 0);
+}
+
+#[tokio::test]
+async fn larger_packets_after_path_mtu_discovery() {
+    let (mut client, mut server) = connect().await;
+
+    // loopback carries the largest packets probed for, so the probes quickly find them
+    time::delay_for(Duration::from_millis(200)).await;
+    assert!(client.stats().path_mtu > 508);
+
+    let payload = incompressible(MAX_PAYLOAD_SIZE - 4);
+    client
+        .send(payload.clone(), Delivery::Reliable)
+        .await
+        .unwrap();
+    assert_eq!(server.recv().await, Some(payload));
+}
+
+#[tokio::test]
+async fn encrypted_payloads() {
+    let mut endpoint = Endpoint::bind("127.0.0.1:0").await.unwrap();
+    let addr = endpoint.local_addr().unwrap();
+
+    let mut client = Connection::connect_secure(addr).await.unwrap();
+    let mut server = endpoint.accept().await.unwrap();
+    assert!(client.is_encrypted());
+    assert!(server.is_encrypted());
+
+    client
+        .send(b"secret".to_vec(), Delivery::Reliable)
+        .await
+        .unwrap();
+    assert_eq!(server.recv().await, Some(b"secret".to_vec()));
+
+    let payload = incompressible(server.max_payload_size(Delivery::BestEffort));
+    assert_eq!(payload.len(), MAX_PAYLOAD_SIZE - 24);
+    server
+        .send(payload.clone(), Delivery::BestEffort)
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await, Some(payload));
+    assert_eq!(client.stats().corrupted_sequences, 0);
+}
+
+#[tokio::test]
+async fn keys_replaced_after_many_payloads() {
+    let (mut client, mut server) = connect_with_config(ConnectionConfig {
+        require_encryption: true,
+        rekey_payloads: 8,
+        ..ConnectionConfig::default()
+    })
+    .await;
+
+    let payloads: Vec<Vec<u8>> = (0..64u8).map(|i| vec![i; 200]).collect();
+
+    tokio::join!(
+        async {
+            for payload in &payloads {
+                client
+                    .send(payload.clone(), Delivery::ReliableOrdered)
+                    .await
+                    .unwrap();
+            }
+        },
+        async {
+            for payload in &payloads {
+                assert_eq!(server.recv().await.as_ref(), Some(payload));
+            }
+        }
+    );
+
+    // the server only seals with the new keys once the client has
+    tokio::join!(
+        async {
+            for payload in &payloads {
+                server
+                    .send(payload.clone(), Delivery::ReliableOrdered)
+                    .await
+                    .unwrap();
+            }
+        },
+        async {
+            for payload in &payloads {
+                assert_eq!(client.recv().await.as_ref(), Some(payload));
+            }
+        }
+    );
+
+    assert!(client.stats().key_exchanges >= 1);
+    assert!(server.stats().key_exchanges >= 1);
+    assert_eq!(client.stats().corrupted_sequences, 0);
+    assert_eq!(server.stats().corrupted_sequences, 0);
+}
+
+#[tokio::test]
+async fn unencrypted_by_default() {
+    let (client, server) = connect().await;
+    assert!(!client.is_encrypted());
+    assert!(!server.is_encrypted());
+}
+
+#[tokio::test]
+async fn oversized_payloads_refused() {
+    let (mut client, mut server) = connect().await;
+
+    let max = client.max_payload_size(Delivery::ReliableOrdered);
+    assert_eq!(max, MAX_PAYLOAD_SIZE - 2 - 4);
+    let refused = client
+        .send(incompressible(max + 1), Delivery::ReliableOrdered)
+        .await;
+    assert!(refused.is_err());
+
+    // the connection is still usable
+    let payload = incompressible(max);
+    client
+        .send(payload.clone(), Delivery::ReliableOrdered)
+        .await
+        .unwrap();
+    assert_eq!(server.recv().await, Some(payload));
+}
+
+#[tokio::test]
+async fn compressed_payloads() {
+    let (mut client, mut server) = connect().await;
+
+    // snapshots are mostly the same few bytes over and over
+    let payload = (0..client.max_payload_size(Delivery::Reliable))
+        .map(|i| (i % 16 / 4) as u8)
+        .collect::<Vec<_>>();
+    client
+        .send(payload.clone(), Delivery::Reliable)
+        .await
+        .unwrap();
+    assert_eq!(server.recv().await, Some(payload));
+
+    let payload = incompressible(1000);
+    server
+        .send(payload.clone(), Delivery::Reliable)
+        .await
+        .unwrap();
+    assert_eq!(client.recv().await, Some(payload));
+}
+
+#[tokio::test]
+async fn coalesced_small_payloads() {
+    let (mut client, mut server) = connect_coalesced(Duration::from_millis(5)).await;
+
+    let payloads: Vec<Vec<u8>> = (0..64u8).map(|i| vec![i; 1 + i as usize]).collect();
+    for (i, payload) in payloads.iter().enumerate() {
+        let delivery = if i % 2 == 0 {
+            Delivery::Reliable
+        } else {
+            Delivery::BestEffort
+        };
+        client.send(payload.clone(), delivery).await.unwrap();
+    }
+
+    // loopback does not reorder packets, and neither does coalescing
+    for payload in payloads {
+        assert_eq!(server.recv().await, Some(payload));
+    }
+    assert_eq!(server.stats().corrupted_sequences, 0);
+}
+
+#[tokio::test]
+async fn flush_sends_held_back_packets() {
+    let (mut client, mut server) = connect_coalesced(Duration::from_secs(60)).await;
+
+    client
+        .send(b"action".to_vec(), Delivery::BestEffort)
+        .await
+        .unwrap();
+    let early = time::timeout(Duration::from_millis(50), server.recv()).await;
+    assert!(
+        early.is_err(),
+        "the payload was sent before the window passed"
+    );
+
+    client.flush().await.unwrap();
+    assert_eq!(server.recv().await, Some(b"action".to_vec()));
+}
+
+#[tokio::test]
+async fn coalesced_heartbeats_paced() {
+    let (client, _server) = connect_with_config(ConnectionConfig {
+        heartbeat_interval: Duration::from_millis(20),
+        coalesce_window: Some(Duration::from_millis(5)),
+        ..ConnectionConfig::default()
+    })
+    .await;
+
+    let before = client.stats().heartbeats_sent;
+    time::delay_for(Duration::from_millis(200)).await;
+    let sent = client.stats().heartbeats_sent - before;
+
+    // at most one heartbeat every 20 ms, rather than one every time the driver is polled
+    assert!(sent >= 2, "only {} heartbeats sent while idle", sent);
+    assert!(sent <= 10, "{} heartbeats sent while idle", sent);
+}
+
+#[tokio::test]
+async fn small_buffers() {
+    let (mut client, mut server) = connect_with_config(ConnectionConfig {
+        channel_capacity: 1,
+        sequence_buffer_size: 8,
+        ..ConnectionConfig::default()
+    })
+    .await;
+
+    let payloads: Vec<Vec<u8>> = (0..32u8)
+        .map(|i| incompressible(100 * i as usize))
+        .collect();
+    // the server holds at most one payload, so it has to be received while the rest are sent
+    let send = async {
+        for payload in &payloads {
+            client
+                .send(payload.clone(), Delivery::Reliable)
+                .await
+                .unwrap();
+        }
+    };
+    let recv = async {
+        for payload in &payloads {
+            assert_eq!(server.recv().await.as_ref(), Some(payload));
+        }
+    };
+    tokio::join!(send, recv);
+}
+
+#[tokio::test]
+async fn connections_beyond_limit_refused() {
+    let (mut endpoint, addr) = bind_limited(1, 1).await;
+
+    let client = Connection::connect(addr).await.unwrap();
+    let server = endpoint.accept().await.unwrap();
+    assert!(is_server_full(Connection::connect(addr).await));
+
+    // the endpoint forgets a connection once it is dropped
+    drop(server);
+    drop(client);
+    Connection::connect(addr).await.unwrap();
+    endpoint.accept().await.unwrap();
+}
+
+#[tokio::test]
+async fn connections_beyond_backlog_refused() {
+    let (mut endpoint, addr) = bind_limited(8, 1).await;
+
+    let _client = Connection::connect(addr).await.unwrap();
+    assert!(is_server_full(Connection::connect(addr).await));
+
+    let _server = endpoint.accept().await.unwrap();
+    let _client = Connection::connect(addr).await.unwrap();
+    endpoint.accept().await.unwrap();
+}
+
+#[tokio::test]
+async fn capture_written_once_stopped() {
+    let path = std::env::temp_dir().join(format!("socket-capture-{}.pcapng", std::process::id()));
+    socket::capture::start(&path).unwrap();
+
+    let (mut client, mut server) = connect().await;
+    client
+        .send(b"captured".to_vec(), Delivery::Reliable)
+        .await
+        .unwrap();
+    assert_eq!(server.recv().await, Some(b"captured".to_vec()));
+
+    socket::capture::stop().unwrap();
+    let capture = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // the section header starts with its block type, followed by at least one packet block
+    assert_eq!(capture[..4], 0x0A0D_0D0Au32.to_le_bytes());
+    let contains = |needle: &[u8]| capture.windows(needle.len()).any(|w| w == needle);
+    assert!(contains(b"captured"));
+}
@@ -0,0 +1,398 @@
+//! Tests under simulated network conditions. The conditions apply to every socket in the process,
+//! so these tests are kept apart from the others.
+
+#![cfg(feature = "simulation")]
+
+use futures::Future;
+use socket::error::ConnectionError;
+use socket::simulation::{self, Conditions};
+use socket::{Connection, ConnectionConfig, Delivery, Endpoint, SocketConfig, MAX_PAYLOAD_SIZE};
+use std::sync::Mutex;
+use tokio::runtime;
+use tokio::time::{self, Duration};
+
+/// Held while a test runs, so that tests don't change the conditions under each other.
+static CONDITIONS: Mutex<()> = Mutex::new(());
+
+async fn connect(config: ConnectionConfig) -> (Connection, Connection) {
+    let config = SocketConfig {
+        connection: config,
+        ..SocketConfig::default()
+    };
+
+    let mut endpoint = Endpoint::bind_with_config("127.0.0.1:0", config)
+        .await
+        .unwrap();
+    let addr = endpoint.local_addr().unwrap();
+
+    let client = Connection::connect_with_config(addr, config).await.unwrap();
+    let server = endpoint.accept().await.unwrap();
+
+    (client, server)
+}
+
+/// Run a test with a connected client and server, with `conditions` simulated for every socket.
+fn simulate<F>(conditions: Conditions, test: impl FnOnce(Connection, Connection) -> F)
+where
+    F: Future<Output = ()>,
+{
+    run(conditions, ConnectionConfig::default(), test)
+}
+
+/// Run a test like `simulate`, over an encrypted connection.
+fn simulate_secure<F>(conditions: Conditions, test: impl FnOnce(Connection, Connection) -> F)
+where
+    F: Future<Output = ()>,
+{
+    let config = ConnectionConfig {
+        require_encryption: true,
+        ..ConnectionConfig::default()
+    };
+    run(conditions, config, test)
+}
+
+/// Run a test like `simulate`, with `config` for both connections.
+fn run<F>(
+    conditions: Conditions,
+    config: ConnectionConfig,
+    test: impl FnOnce(Connection, Connection) -> F,
+) where
+    F: Future<Output = ()>,
+{
+    let _conditions = CONDITIONS.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut runtime = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        let (client, server) = connect(config).await;
+        simulation::set_conditions(conditions);
+        test(client, server).await;
+    });
+
+    simulation::set_conditions(Conditions::IDEAL);
+}
+
+/// Run a test with a connected client and server, over a link that reorders most datagrams sent
+/// close together.
+fn with_jitter<F>(test: impl FnOnce(Connection, Connection) -> F)
+where
+    F: Future<Output = ()>,
+{
+    // jitter as large as the latency reorders most datagrams sent close together
+    let conditions = Conditions {
+        latency: Duration::from_millis(10),
+        jitter: Duration::from_millis(10),
+        ..Conditions::IDEAL
+    };
+    simulate(conditions, test)
+}
+
+#[test]
+fn ordered_payloads_despite_reordering() {
+    with_jitter(|mut client, mut server| async move {
+        // every other payload spans several packets, so that it takes longer to complete
+        let payloads: Vec<Vec<u8>> = (0..32u32)
+            .map(|i| {
+                let len = if i % 2 == 0 { 8 } else { 2000 };
+                (0..len).map(|j| (i * 7 + j) as u8).collect()
+            })
+            .collect();
+
+        for payload in &payloads {
+            client
+                .send(payload.clone(), Delivery::ReliableOrdered)
+                .await
+                .unwrap();
+        }
+
+        for payload in payloads {
+            assert_eq!(server.recv().await, Some(payload));
+        }
+    });
+}
+
+#[test]
+fn stale_sequenced_payloads_dropped() {
+    with_jitter(|mut client, mut server| async move {
+        for i in 0..32u32 {
+            client
+                .send(i.to_be_bytes().to_vec(), Delivery::BestEffortSequenced)
+                .await
+                .unwrap();
+        }
+
+        // nothing is lost, so the last payload is the newest when it arrives
+        let mut received = Vec::new();
+        while received.last() != Some(&31) {
+            let payload = time::timeout(Duration::from_secs(1), server.recv())
+                .await
+                .expect("the last payload did not arrive")
+                .unwrap();
+            received.push(u32::from_be_bytes([
+                payload[0], payload[1], payload[2], payload[3],
+            ]));
+        }
+
+        assert!(received.windows(2).all(|pair| pair[0] < pair[1]));
+    });
+}
+
+#[test]
+fn conditions_of_a_single_connection() {
+    simulate(Conditions::IDEAL, |mut client, mut server| async move {
+        server.set_conditions(Some(Conditions {
+            loss: 1.0,
+            ..Conditions::IDEAL
+        }));
+        client
+            .send(b"lost".to_vec(), Delivery::BestEffort)
+            .await
+            .unwrap();
+        let lost = time::timeout(Duration::from_millis(50), server.recv()).await;
+        assert!(lost.is_err(), "the payload was not dropped");
+
+        server.set_conditions(None);
+        client
+            .send(b"kept".to_vec(), Delivery::BestEffort)
+            .await
+            .unwrap();
+        assert_eq!(server.recv().await, Some(b"kept".to_vec()));
+    });
+}
+
+#[test]
+fn duplicated_payloads_received_once() {
+    simulate(Conditions::IDEAL, |mut client, mut server| async move {
+        client.set_conditions(Some(Conditions {
+            duplicate: 1.0,
+            ..Conditions::IDEAL
+        }));
+
+        for i in 0..16u8 {
+            server.send(vec![i], Delivery::BestEffort).await.unwrap();
+        }
+
+        for i in 0..16u8 {
+            assert_eq!(client.recv().await, Some(vec![i]));
+        }
+        let duplicate = time::timeout(Duration::from_millis(50), client.recv()).await;
+        assert!(duplicate.is_err(), "a duplicated payload was received twice");
+    });
+}
+
+#[test]
+fn close_retransmitted_until_acknowledged() {
+    simulate(Conditions::IDEAL, |client, server| async move {
+        server.set_conditions(Some(Conditions {
+            loss: 1.0,
+            ..Conditions::IDEAL
+        }));
+
+        let (shutdown, ()) = tokio::join!(client.shutdown(), async {
+            time::delay_for(Duration::from_millis(150)).await;
+            server.set_conditions(None);
+        });
+        shutdown.unwrap();
+    });
+}
+
+#[test]
+fn unacknowledged_close_reported() {
+    simulate(Conditions::IDEAL, |client, _server| async move {
+        client.set_conditions(Some(Conditions {
+            loss: 1.0,
+            ..Conditions::IDEAL
+        }));
+
+        let shutdown = client.shutdown().await;
+        assert!(matches!(shutdown, Err(ConnectionError::Unacknowledged)));
+    });
+}
+
+#[test]
+fn encrypted_retransmission_behind_many_newer_payloads() {
+    simulate_secure(Conditions::IDEAL, |mut client, mut server| async move {
+        server.set_conditions(Some(Conditions {
+            loss: 1.0,
+            ..Conditions::IDEAL
+        }));
+        client
+            .send(b"reliable".to_vec(), Delivery::Reliable)
+            .await
+            .unwrap();
+        time::delay_for(Duration::from_millis(20)).await;
+        server.set_conditions(None);
+
+        // the newer payloads are opened before the retransmission arrives
+        for i in 0..200u32 {
+            client
+                .send(i.to_be_bytes().to_vec(), Delivery::BestEffort)
+                .await
+                .unwrap();
+        }
+
+        let mut newer = 0;
+        loop {
+            let payload = time::timeout(Duration::from_secs(2), server.recv())
+                .await
+                .expect("the retransmission was rejected")
+                .unwrap();
+            if payload == b"reliable" {
+                break;
+            }
+            newer += 1;
+        }
+        assert!(newer > 64, "only {} payloads arrived first", newer);
+    });
+}
+
+#[test]
+fn reliable_payloads_kept_under_memory_pressure() {
+    let conditions = Conditions {
+        loss: 0.2,
+        ..Conditions::IDEAL
+    };
+    simulate(conditions, |mut client, mut server| async move {
+        let reliable: Vec<_> = (0..4).map(|i| incompressible(i, 60_000)).collect();
+        for payload in &reliable {
+            client
+                .send(payload.clone(), Delivery::Reliable)
+                .await
+                .unwrap();
+        }
+
+        // unreliable payloads this long rarely arrive whole, so their chunks pile up while the lost
+        // chunks of the reliable ones are retransmitted
+        for i in 4..64 {
+            let payload = incompressible(i, 60_000);
+            client.send(payload, Delivery::BestEffort).await.unwrap();
+        }
+
+        let mut received = Vec::new();
+        while received.len() < reliable.len() {
+            let payload = time::timeout(Duration::from_secs(5), server.recv())
+                .await
+                .expect("a reliable payload was lost")
+                .unwrap();
+            if reliable.contains(&payload) {
+                received.push(payload);
+            }
+        }
+
+        let stats = server.stats();
+        assert!(stats.evicted_sequences > 0);
+        assert!(stats.reassembly_bytes > 0);
+    });
+}
+
+#[test]
+fn keys_replaced_despite_loss() {
+    let conditions = Conditions {
+        loss: 0.3,
+        ..Conditions::IDEAL
+    };
+    let config = ConnectionConfig {
+        require_encryption: true,
+        rekey_payloads: 8,
+        ..ConnectionConfig::default()
+    };
+    run(conditions, config, |mut client, mut server| async move {
+        for round in 0..4u8 {
+            for i in 0..16u8 {
+                let payload = vec![round, i];
+                client
+                    .send(payload.clone(), Delivery::ReliableOrdered)
+                    .await
+                    .unwrap();
+                server
+                    .send(payload.clone(), Delivery::ReliableOrdered)
+                    .await
+                    .unwrap();
+                assert_eq!(server.recv().await, Some(payload.clone()));
+                assert_eq!(client.recv().await, Some(payload));
+            }
+        }
+
+        assert!(client.stats().key_exchanges >= 1);
+        assert!(server.stats().key_exchanges >= 1);
+        assert_eq!(client.stats().corrupted_sequences, 0);
+        assert_eq!(server.stats().corrupted_sequences, 0);
+    });
+}
+
+/// Send an ordered payload that is lost until its retransmissions have backed off, then `later`
+/// ones, and check that the later ones are held back until the first arrives.
+async fn ordered_behind_lost_payload(
+    client: &mut Connection,
+    server: &mut Connection,
+    later: Vec<Vec<u8>>,
+) {
+    server.set_conditions(Some(Conditions {
+        loss: 1.0,
+        ..Conditions::IDEAL
+    }));
+    let first = b"first".to_vec();
+    client
+        .send(first.clone(), Delivery::ReliableOrdered)
+        .await
+        .unwrap();
+    time::delay_for(Duration::from_millis(350)).await;
+    server.set_conditions(None);
+
+    for payload in &later {
+        client
+            .send(payload.clone(), Delivery::ReliableOrdered)
+            .await
+            .unwrap();
+    }
+
+    let early = time::timeout(Duration::from_millis(100), server.recv()).await;
+    assert!(early.is_err(), "a payload was passed on before the first");
+    let held = server.stats().reassembly_bytes;
+    assert!(held > 0, "the held back payloads are not counted");
+    // twice the reassembly memory limit, for the payloads the held back ones wait for
+    let limit = 2 * 8 * MAX_PAYLOAD_SIZE as u64;
+    assert!(held <= limit, "{} bytes held back", held);
+
+    assert_eq!(server.recv().await, Some(first));
+    for payload in later {
+        let received = time::timeout(Duration::from_secs(5), server.recv())
+            .await
+            .expect("an ordered payload was lost");
+        assert_eq!(received, Some(payload));
+    }
+    assert_eq!(server.stats().reassembly_bytes, 0);
+}
+
+#[test]
+fn ordered_payloads_beyond_reorder_limit_kept() {
+    simulate(Conditions::IDEAL, |mut client, mut server| async move {
+        let later = (0..600u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        ordered_behind_lost_payload(&mut client, &mut server, later).await;
+    });
+}
+
+#[test]
+fn ordered_payloads_beyond_memory_limit_kept() {
+    simulate(Conditions::IDEAL, |mut client, mut server| async move {
+        let later = (0..32).map(|i| incompressible(i, 60_000)).collect();
+        ordered_behind_lost_payload(&mut client, &mut server, later).await;
+    });
+}
+
+/// Bytes that do not compress, different for every `seed`.
+fn incompressible(seed: u32, len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491 ^ seed.wrapping_mul(0x9e37_79b9);
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
@@ -0,0 +1,237 @@
+//! Renders a throw log recorded by the server (`--throw-log`) as a heatmap over the tile map.
+//!
+//! Every impact is drawn as a small splat centered where the projectile landed. Areas where most
+//! throws hit a player are red, areas where most throws missed are cyan, and the more throws landed
+//! in an area the more opaque its color is.
+
+use anyhow::{Context, Result};
+use image::{Rgb, RgbImage};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+use logic::telemetry::{ThrowLogReader, ThrowRecord};
+use logic::tile_map::{TileKind, TileMap};
+use logic::WorldKind;
+
+const WATER: [f32; 3] = [40.0, 90.0, 160.0];
+const GRASS: [f32; 3] = [90.0, 150.0, 70.0];
+const SAND: [f32; 3] = [210.0, 195.0, 140.0];
+const SNOW: [f32; 3] = [235.0, 240.0, 245.0];
+const ICE: [f32; 3] = [170.0, 210.0, 235.0];
+
+const HIT: [f32; 3] = [230.0, 30.0, 30.0];
+const MISS: [f32; 3] = [40.0, 220.0, 255.0];
+
+/// The greatest opacity of the heatmap over the tiles.
+const MAX_OPACITY: f32 = 0.85;
+
+#[derive(StructOpt)]
+struct Options {
+    /// The throw log to render.
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+
+    /// Where to write the PNG.
+    #[structopt(short, long, parse(from_os_str), default_value = "heatmap.png")]
+    output: PathBuf,
+
+    /// The width and height of each tile in pixels.
+    #[structopt(long, default_value = "16")]
+    scale: u32,
+
+    /// The radius of each impact in tiles.
+    #[structopt(long, default_value = "1.0")]
+    radius: f32,
+}
+
+/// The accumulated weight of hits and misses at every pixel.
+struct Heat {
+    width: u32,
+    height: u32,
+    hits: Vec<f32>,
+    misses: Vec<f32>,
+}
+
+/// The region of the world covered by the image, in tiles.
+#[derive(Copy, Clone)]
+struct Bounds {
+    min_x: i32,
+    max_y: i32,
+    scale: u32,
+}
+
+fn main() -> Result<()> {
+    let options = Options::from_args();
+
+    let file = File::open(&options.input)
+        .with_context(|| format!("failed to open {}", options.input.display()))?;
+    let records = ThrowLogReader::new(BufReader::new(file))
+        .and_then(|log| log.collect::<Result<Vec<ThrowRecord>, _>>())
+        .with_context(|| format!("failed to read {}", options.input.display()))?;
+
+    let world = logic::create_world(WorldKind::Plain);
+    let map = world.resources.get::<TileMap>().unwrap();
+
+    let (mut image, bounds) = render_map(&map, options.scale);
+
+    let mut heat = Heat::new(image.width(), image.height());
+    let radius = options.radius * options.scale as f32;
+    for record in &records {
+        let (x, y) = bounds.to_pixel(record.impact.x, record.impact.y);
+        heat.splat(x, y, radius, record.hit);
+    }
+    heat.draw(&mut image);
+
+    image
+        .save(&options.output)
+        .with_context(|| format!("failed to write {}", options.output.display()))?;
+
+    let hits = records.iter().filter(|record| record.hit).count();
+    println!(
+        "rendered {} throws ({} hits, {} misses) to {}",
+        records.len(),
+        hits,
+        records.len() - hits,
+        options.output.display()
+    );
+
+    Ok(())
+}
+
+/// Draw every tile of the map as a square of its color.
+fn render_map(map: &TileMap, scale: u32) -> (RgbImage, Bounds) {
+    let coords = map.iter().map(|(coord, _)| coord);
+    let (min_x, max_x, min_y, max_y) = coords.fold(
+        (i32::MAX, i32::MIN, i32::MAX, i32::MIN),
+        |(min_x, max_x, min_y, max_y), coord| {
+            (
+                min_x.min(coord.x),
+                max_x.max(coord.x),
+                min_y.min(coord.y),
+                max_y.max(coord.y),
+            )
+        },
+    );
+
+    let width = (max_x - min_x + 1).max(0) as u32 * scale;
+    let height = (max_y - min_y + 1).max(0) as u32 * scale;
+    let mut image = RgbImage::from_pixel(width, height, color(WATER));
+
+    let bounds = Bounds {
+        min_x,
+        max_y,
+        scale,
+    };
+
+    for (coord, tile) in map.iter() {
+        let fill = match tile.kind {
+            TileKind::Water => WATER,
+            TileKind::Grass => GRASS,
+            TileKind::Sand => SAND,
+            TileKind::Snow => SNOW,
+            TileKind::Ice => ICE,
+        };
+
+        let left = (coord.x - min_x) as u32 * scale;
+        let top = (max_y - coord.y) as u32 * scale;
+        for y in top..top + scale {
+            for x in left..left + scale {
+                image.put_pixel(x, y, color(fill));
+            }
+        }
+    }
+
+    (image, bounds)
+}
+
+impl Bounds {
+    /// The pixel at the given world coordinates. The world y-axis points up in the image.
+    fn to_pixel(self, x: f32, y: f32) -> (f32, f32) {
+        let scale = self.scale as f32;
+        let px = (x - self.min_x as f32 + 0.5) * scale;
+        let py = (self.max_y as f32 + 0.5 - y) * scale;
+        (px, py)
+    }
+}
+
+impl Heat {
+    fn new(width: u32, height: u32) -> Heat {
+        let size = (width * height) as usize;
+        Heat {
+            width,
+            height,
+            hits: vec![0.0; size],
+            misses: vec![0.0; size],
+        }
+    }
+
+    /// Add the weight of an impact to the pixels around it, falling off linearly with distance.
+    fn splat(&mut self, cx: f32, cy: f32, radius: f32, hit: bool) {
+        let radius = radius.max(1.0);
+        let min_x = (cx - radius).floor().max(0.0) as u32;
+        let min_y = (cy - radius).floor().max(0.0) as u32;
+        let max_x = ((cx + radius).ceil().max(0.0) as u32).min(self.width);
+        let max_y = ((cy + radius).ceil().max(0.0) as u32).min(self.height);
+
+        let weights = if hit {
+            &mut self.hits
+        } else {
+            &mut self.misses
+        };
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let dx = x as f32 + 0.5 - cx;
+                let dy = y as f32 + 0.5 - cy;
+                let distance = (dx * dx + dy * dy).sqrt();
+                if distance < radius {
+                    weights[(y * self.width + x) as usize] += 1.0 - distance / radius;
+                }
+            }
+        }
+    }
+
+    /// Blend the heat over the image, relative to the hottest pixel.
+    fn draw(&self, image: &mut RgbImage) {
+        let hottest = self
+            .hits
+            .iter()
+            .zip(&self.misses)
+            .map(|(hits, misses)| hits + misses)
+            .fold(0.0, f32::max);
+
+        if hottest <= 0.0 {
+            return;
+        }
+
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let index = (y * self.width + x) as usize;
+            let (hits, misses) = (self.hits[index], self.misses[index]);
+            let total = hits + misses;
+            if total <= 0.0 {
+                continue;
+            }
+
+            let heat = mix(MISS, HIT, hits / total);
+            let opacity = MAX_OPACITY * (total / hottest).sqrt();
+
+            let base = [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32];
+            *pixel = color(mix(base, heat, opacity));
+        }
+    }
+}
+
+/// Linearly interpolate between two colors.
+fn mix(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+fn color([r, g, b]: [f32; 3]) -> Rgb<u8> {
+    Rgb([r as u8, g as u8, b as u8])
+}
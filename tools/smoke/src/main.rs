@@ -0,0 +1,416 @@
+//! A quick end-to-end sanity check: starts a server in this process, connects two bots to it and
+//! plays a scripted match between them. One bot breaks objects and throws them at the other, which
+//! stands still until it is knocked out.
+//!
+//! Exits with a non-zero status if anything panics, a message from the server can not be decoded,
+//! or the match does not end with the expected winner in time.
+//!
+//! The socket crate has no in-memory transport, so the bots connect to the server over the
+//! loopback interface.
+
+#[macro_use]
+extern crate anyhow;
+
+use anyhow::{Context, Result};
+
+use cgmath::prelude::*;
+use cgmath::{Point3, Vector3};
+
+use logic::resources::GameRules;
+
+use protocol::bandwidth::Bandwidth;
+use protocol::{
+    Action, ActionKind, Break, Channel, ClientMessage, Direction, Entity, EntityId, EntityKind,
+    EventKind, GameOver, Init, Move, Player, PlayerId, ProjectileKind, Request, RequestKind,
+    ResponseKind, ServerMessage, Throw,
+};
+
+use server::game::Game;
+use server::Server;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::panic;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::{task, time};
+
+/// The match fails if it has not ended after this long.
+const MATCH_DURATION: Duration = Duration::from_secs(30);
+
+/// How often the bots decide what to do.
+const BOT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// For how long the target walks around before standing still.
+const TARGET_WALK: Duration = Duration::from_secs(1);
+
+/// The attacker stops walking once it is this close to the object it is breaking.
+const BREAK_DISTANCE: f32 = 1.5;
+
+/// The attacker walks along an axis if the distance along it is greater than this.
+const WALK_THRESHOLD: f32 = 0.3;
+
+/// Set as soon as any thread panics.
+static PANICKED: AtomicBool = AtomicBool::new(false);
+
+/// What a bot does during the match.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Role {
+    /// Break objects and throw them at the other bot.
+    Attacker,
+    /// Walk around for a bit, then stand still.
+    Target,
+}
+
+/// A scripted client.
+struct Bot {
+    name: &'static str,
+    role: Role,
+    socket: socket::Connection,
+    player: PlayerId,
+    /// The latest state of every entity.
+    entities: HashMap<EntityId, Entity>,
+    /// The direction the bot was last told to move in.
+    movement: Direction,
+    started: Instant,
+    snapshots: u32,
+    game_over: Option<GameOver>,
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        PANICKED.store(true, Ordering::SeqCst);
+        default_hook(info);
+    }));
+
+    let result = tokio::runtime::Runtime::new()
+        .context("failed to start runtime")
+        .and_then(|mut runtime| {
+            let local = task::LocalSet::new();
+            local.block_on(&mut runtime, run())
+        });
+
+    let result = result.and_then(|summary| {
+        if PANICKED.load(Ordering::SeqCst) {
+            Err(anyhow!("a thread panicked"))
+        } else {
+            Ok(summary)
+        }
+    });
+
+    match result {
+        Ok(summary) => println!("smoke test passed: {}", summary),
+        Err(e) => {
+            eprintln!("smoke test failed: {:#}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Play a match between two bots, returning a summary of what happened.
+async fn run() -> Result<String> {
+    let (mut game, handle) = Game::new(GameRules::default(), true);
+    task::spawn_local(async move { game.run().await });
+
+    let bandwidth = Arc::new(Bandwidth::default());
+    let server = Server::new(([127, 0, 0, 1], 0).into(), handle, bandwidth.clone()).await?;
+    let addr = server
+        .local_addr()
+        .ok_or_else(|| anyhow!("server is not listening on any address"))?;
+    tokio::spawn(async move {
+        let error = server.run().await;
+        log::error!("server crashed: {:#}", error);
+    });
+
+    let started = Instant::now();
+
+    let target = Bot::connect(addr, "target", Role::Target).await?;
+    let attacker = Bot::connect(addr, "attacker", Role::Attacker).await?;
+
+    let (attacker, target) = time::timeout(MATCH_DURATION, async {
+        tokio::try_join!(attacker.play(), target.play())
+    })
+    .await
+    .map_err(|_| anyhow!("the match did not end within {:?}", MATCH_DURATION))??;
+
+    if !matches!(attacker.game_over, Some(GameOver::Winner)) {
+        return Err(anyhow!(
+            "the attacker did not win: {:?}",
+            attacker.game_over
+        ));
+    }
+    if !matches!(target.game_over, Some(GameOver::Loser)) {
+        return Err(anyhow!("the target did not lose: {:?}", target.game_over));
+    }
+
+    let traffic = bandwidth.since_start().total;
+    Ok(format!(
+        "the attacker won after {:.1} seconds, {} snapshots decoded, {} bytes sent by the server",
+        started.elapsed().as_secs_f32(),
+        attacker.snapshots + target.snapshots,
+        traffic.sent_bytes,
+    ))
+}
+
+impl Bot {
+    /// Connect to the server and load the world.
+    async fn connect(addr: SocketAddr, name: &'static str, role: Role) -> Result<Bot> {
+        let socket = socket::Connection::connect(addr)
+            .await
+            .with_context(|| format!("{} failed to connect", name))?;
+
+        let mut bot = Bot {
+            name,
+            role,
+            socket,
+            player: PlayerId(0),
+            entities: HashMap::new(),
+            movement: Direction::empty(),
+            started: Instant::now(),
+            snapshots: 0,
+            game_over: None,
+        };
+
+        let init = Request {
+            channel: Channel(0),
+            kind: RequestKind::Init(Init {
+                nickname: name.to_owned(),
+                version: protocol::VERSION,
+            }),
+        };
+        bot.send(ClientMessage::Request(init)).await?;
+
+        let connect = loop {
+            match bot.recv().await? {
+                Some(ServerMessage::Response(response)) => match response.kind {
+                    ResponseKind::Connect(connect) => break connect,
+                    kind => {
+                        return Err(anyhow!("{} expected Connect, found {}", name, kind.name()))
+                    }
+                },
+                Some(ServerMessage::Event(_)) => {}
+                None => return Err(anyhow!("{} was disconnected while connecting", name)),
+            }
+        };
+
+        bot.player = connect.player_id;
+
+        let mut chunks = 0;
+        while chunks < connect.chunks {
+            match bot.recv().await? {
+                Some(ServerMessage::Event(event)) => {
+                    if let EventKind::WorldChunk(chunk) = event.kind {
+                        bot.update_entities(chunk.entities);
+                        chunks += 1;
+                    }
+                }
+                Some(ServerMessage::Response(_)) => {}
+                None => return Err(anyhow!("{} was disconnected while loading", name)),
+            }
+        }
+
+        if bot.own_player().is_none() {
+            return Err(anyhow!("{} is missing from the world", name));
+        }
+
+        bot.started = Instant::now();
+        Ok(bot)
+    }
+
+    /// Play until the game is over for this bot.
+    async fn play(mut self) -> Result<Bot> {
+        let mut timer = time::interval(BOT_INTERVAL);
+
+        loop {
+            tokio::select! {
+                message = self.socket.recv() => {
+                    let message = match message {
+                        Some(bytes) => decode(self.name, &bytes)?,
+                        // the server closes the connection once the game is over for us
+                        None if self.game_over.is_some() => break Ok(self),
+                        None => break Err(anyhow!("{} was disconnected", self.name)),
+                    };
+                    self.handle_message(message);
+                }
+
+                _ = timer.tick() => {
+                    if self.game_over.is_some() {
+                        break Ok(self);
+                    }
+
+                    for kind in self.act() {
+                        let action = Action { kind, trace: None };
+                        self.send(ClientMessage::Action(action)).await?;
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle_message(&mut self, message: ServerMessage) {
+        let event = match message {
+            ServerMessage::Event(event) => event,
+            ServerMessage::Response(_) => return,
+        };
+
+        match event.kind {
+            EventKind::Snapshot(snapshot) => {
+                self.snapshots += 1;
+                self.update_entities(snapshot.entities.clone());
+            }
+            EventKind::GameOver(game_over) => {
+                log::info!("{}: game over: {:?}", self.name, game_over);
+                self.game_over = Some(game_over);
+            }
+            EventKind::MatchSummary(_) | EventKind::HitConfirmed(_) => {}
+            EventKind::WorldChunk(_) | EventKind::ActionAck(_) => {}
+            EventKind::Chat(_) | EventKind::Restart(_) => {}
+            EventKind::Unknown => {}
+        }
+    }
+
+    fn update_entities(&mut self, entities: Vec<Entity>) {
+        for entity in entities {
+            if let EntityKind::Dead = entity.kind {
+                self.entities.remove(&entity.id);
+            } else {
+                self.entities.insert(entity.id, entity);
+            }
+        }
+    }
+
+    /// Decide which actions to perform.
+    fn act(&mut self) -> Vec<ActionKind> {
+        let mut actions = Vec::new();
+
+        let movement = match self.role {
+            Role::Target if self.started.elapsed() < TARGET_WALK => Direction::EAST,
+            Role::Target => Direction::empty(),
+            Role::Attacker => self.attack(&mut actions),
+        };
+
+        if movement != self.movement {
+            self.movement = movement;
+            actions.push(ActionKind::Move(Move {
+                direction: movement,
+            }));
+        }
+
+        actions
+    }
+
+    /// Throw the held object at the other bot, or walk to the closest object and break it.
+    /// Returns the direction to move in.
+    fn attack(&self, actions: &mut Vec<ActionKind>) -> Direction {
+        let me = match self.own_player() {
+            Some(me) => me,
+            None => return Direction::empty(),
+        };
+
+        if me.holding.is_some() {
+            if let Some(target) = self.opponent() {
+                actions.push(ActionKind::Throw(Throw {
+                    target: target.position + Vector3::new(0.0, 0.0, 0.5),
+                    projectile: ProjectileKind::Snowball,
+                }));
+            }
+            return Direction::empty();
+        }
+
+        let (object, position) = match self.closest_breakable(me.position) {
+            Some(closest) => closest,
+            None => return Direction::empty(),
+        };
+
+        if me.breaking != Some(object) {
+            actions.push(ActionKind::Break(Break {
+                entity: Some(object),
+            }));
+        }
+
+        let delta = position - me.position;
+        if delta.truncate().magnitude() <= BREAK_DISTANCE {
+            return Direction::empty();
+        }
+
+        let mut direction = Direction::empty();
+        if delta.x > WALK_THRESHOLD {
+            direction |= Direction::EAST;
+        } else if delta.x < -WALK_THRESHOLD {
+            direction |= Direction::WEST;
+        }
+        if delta.y > WALK_THRESHOLD {
+            direction |= Direction::NORTH;
+        } else if delta.y < -WALK_THRESHOLD {
+            direction |= Direction::SOUTH;
+        }
+        direction
+    }
+
+    fn players(&self) -> impl Iterator<Item = &Player> {
+        self.entities
+            .values()
+            .filter_map(|entity| match &entity.kind {
+                EntityKind::Player(player) => Some(player),
+                _ => None,
+            })
+    }
+
+    fn own_player(&self) -> Option<&Player> {
+        self.players().find(|player| player.owner == self.player)
+    }
+
+    fn opponent(&self) -> Option<&Player> {
+        self.players().find(|player| player.owner != self.player)
+    }
+
+    /// The closest object that has not been broken yet.
+    fn closest_breakable(&self, position: Point3<f32>) -> Option<(EntityId, Point3<f32>)> {
+        self.entities
+            .values()
+            .filter_map(|entity| match &entity.kind {
+                EntityKind::Object(object) if object.durability.is_some() => {
+                    Some((entity.id, object.position))
+                }
+                _ => None,
+            })
+            .min_by(|(_, a), (_, b)| {
+                let a = a.distance2(position);
+                let b = b.distance2(position);
+                a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+
+    async fn recv(&mut self) -> Result<Option<ServerMessage>> {
+        match self.socket.recv().await {
+            Some(bytes) => decode(self.name, &bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn send(&mut self, message: ClientMessage) -> Result<()> {
+        let bytes = protocol::to_bytes(&message)?;
+        let delivery = if message.must_arrive() {
+            socket::Delivery::Reliable
+        } else {
+            socket::Delivery::BestEffort
+        };
+
+        self.socket
+            .send(bytes, delivery)
+            .await
+            .with_context(|| format!("{} failed to send {}", self.name, message.name()))
+    }
+}
+
+/// Decode a message from the server. Every message must be well-formed.
+fn decode(name: &str, bytes: &[u8]) -> Result<ServerMessage> {
+    protocol::from_bytes_strict::<ServerMessage>(bytes)
+        .with_context(|| format!("{} received a malformed message", name))
+}